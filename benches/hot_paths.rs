@@ -0,0 +1,138 @@
+// HiveDB Hot Path Benchmarks
+//
+// These benchmarks time the operations every request goes through: adding
+// cells, the compression codecs, evaluating filters, looking keys up in a
+// secondary index and scanning a whole hive. Run them with `cargo bench`
+// and compare against a saved baseline (`--save-baseline` and
+// `--baseline`) to catch regressions.
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use hivedb::core::cell::{Cell, CellDataType};
+use hivedb::core::codec;
+use hivedb::core::compression;
+use hivedb::core::hive::Hive;
+use hivedb::core::index::SecondaryIndex;
+use hivedb::core::query::{self, Query, QueryExecutor, QueryType};
+use hivedb::core::schema::{IndexType, Schema, SchemaIndex};
+use hivedb::core::snapshot::{self, SnapshotCodec};
+use serde_json::{json, Value};
+use tempfile::TempDir;
+
+/// Number of documents in the hives and indexes under test
+const DOCUMENTS: u64 = 1_000;
+
+/// Number of distinct `bucket` values
+const BUCKETS: u64 = 100;
+
+/// A document like those of the benchmark workloads
+fn document(key: u64) -> Value {
+    json!({
+        "key": key,
+        "bucket": key % BUCKETS,
+        "name": format!("worker-{}", key),
+        "address": { "city": ["Riyadh", "Cairo", "Tunis"][key as usize % 3] },
+        "payload": "x".repeat(128),
+    })
+}
+
+/// Create an empty hive in a temporary directory
+fn empty_hive() -> (Hive, TempDir) {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let hive = Hive::builder()
+        .name("bench")
+        .owner("bench")
+        .storage_path(temp_dir.path())
+        .dimensions(64, 64)
+        .build()
+        .unwrap();
+    (hive, temp_dir)
+}
+
+/// Add JSON documents to a hive
+fn add_documents(hive: &mut Hive, keys: std::ops::Range<u64>) {
+    for key in keys {
+        let content = serde_json::to_vec(&document(key)).unwrap();
+        let coordinates = hive.free_coordinates().unwrap();
+        hive.add_cell(Cell::new(format!("doc-{}", key), coordinates, CellDataType::Json, content, true).unwrap()).unwrap();
+    }
+}
+
+fn cell_insert(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cell_insert");
+    group.throughput(Throughput::Elements(DOCUMENTS));
+    group.bench_function("json_documents", |b| {
+        b.iter_batched(empty_hive, |(mut hive, _dir)| add_documents(&mut hive, 0..DOCUMENTS), BatchSize::PerIteration)
+    });
+    group.finish();
+}
+
+fn codecs(c: &mut Criterion) {
+    let content: Vec<u8> = (0..64).flat_map(|key| serde_json::to_vec(&document(key)).unwrap()).collect();
+    let mut group = c.benchmark_group("codecs");
+    group.throughput(Throughput::Bytes(content.len() as u64));
+    
+    let compressed = codec::lz4_compress(&content).unwrap();
+    group.bench_function("lz4_compress", |b| b.iter(|| codec::lz4_compress(black_box(&content)).unwrap()));
+    group.bench_function("lz4_decompress", |b| b.iter(|| codec::lz4_decompress(black_box(&compressed)).unwrap()));
+    group.bench_function("sha256", |b| b.iter(|| codec::sha256(black_box(&content))));
+    
+    let samples: Vec<Vec<u8>> = (0..DOCUMENTS).map(|key| serde_json::to_vec(&document(key)).unwrap()).collect();
+    let dictionary = compression::train(&samples, compression::DEFAULT_DICTIONARY_SIZE).unwrap();
+    group.bench_function("dictionary_compress", |b| b.iter(|| dictionary.compress(black_box(&content)).unwrap()));
+    
+    group.bench_function("snapshot_encode", |b| b.iter(|| snapshot::encode(black_box(&content), SnapshotCodec::Zstd).unwrap()));
+    let encoded = snapshot::encode(&content, SnapshotCodec::Zstd).unwrap();
+    group.bench_function("snapshot_decode", |b| b.iter(|| snapshot::decode(black_box(&encoded)).unwrap()));
+    group.finish();
+}
+
+fn filter_evaluation(c: &mut Criterion) {
+    let documents: Vec<Value> = (0..DOCUMENTS).map(document).collect();
+    let filter = query::and(vec![
+        query::eq("address.city", json!("Cairo")),
+        query::or(vec![query::lt("bucket", json!(10)), query::gte("key", json!(900))]),
+    ]);
+    
+    let mut group = c.benchmark_group("filter_evaluation");
+    group.throughput(Throughput::Elements(DOCUMENTS));
+    group.bench_function("nested", |b| {
+        b.iter(|| documents.iter().filter(|document| filter.evaluate(black_box(document))).count())
+    });
+    group.finish();
+}
+
+fn index_lookup(c: &mut Criterion) {
+    let mut index = SecondaryIndex::new(SchemaIndex::new("by_bucket".to_string(), vec!["bucket".to_string()], IndexType::BTree, false)).unwrap();
+    for key in 0..DOCUMENTS {
+        index.update(&format!("doc-{}", key), Some(&document(key)));
+    }
+    
+    let mut group = c.benchmark_group("index_lookup");
+    let equal = query::eq("bucket", json!(42));
+    group.bench_function("equality", |b| b.iter(|| index.lookup(black_box(&equal)).unwrap()));
+    let range = query::gte("bucket", json!(90));
+    group.bench_function("range", |b| b.iter(|| index.lookup(black_box(&range)).unwrap()));
+    group.finish();
+}
+
+fn full_scan(c: &mut Criterion) {
+    let (mut hive, _dir) = empty_hive();
+    add_documents(&mut hive, 0..DOCUMENTS);
+    
+    let mut group = c.benchmark_group("full_scan");
+    group.throughput(Throughput::Elements(DOCUMENTS));
+    let find = Query::new(QueryType::Find, "bench".to_string()).with_filter(query::eq("address.city", json!("Tunis")));
+    group.bench_function("unindexed_find", |b| b.iter(|| QueryExecutor::execute_read(black_box(&find), &hive).unwrap()));
+    let count = Query::new(QueryType::Count, "bench".to_string());
+    group.bench_function("count", |b| b.iter(|| QueryExecutor::execute_read(black_box(&count), &hive).unwrap()));
+    
+    // The same query once the field is indexed, for comparison
+    let mut schema = Schema::new("bench".to_string(), String::new(), "1".to_string());
+    schema.add_index(SchemaIndex::new("by_city".to_string(), vec!["address.city".to_string()], IndexType::BTree, false));
+    hive.set_schema(schema).unwrap();
+    group.bench_function("indexed_find", |b| b.iter(|| QueryExecutor::execute_read(black_box(&find), &hive).unwrap()));
+    group.finish();
+}
+
+criterion_group!(benches, cell_insert, codecs, filter_evaluation, index_lookup, full_scan);
+criterion_main!(benches);
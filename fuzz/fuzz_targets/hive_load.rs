@@ -0,0 +1,21 @@
+// Fuzz loading a hive from a damaged snapshot file: loading must fail
+// cleanly, and a hive that loads must save and load again to the same cells.
+
+#![no_main]
+
+use hivedb::core::hive::{Hive, SNAPSHOT_FILE};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join(SNAPSHOT_FILE), data).unwrap();
+    
+    let Ok(hive) = Hive::load(dir.path().to_path_buf()) else {
+        return;
+    };
+    
+    hive.save().unwrap();
+    let reloaded = Hive::load(dir.path().to_path_buf()).unwrap();
+    assert_eq!(reloaded.id, hive.id);
+    assert_eq!(reloaded.cell_count(), hive.cell_count());
+});
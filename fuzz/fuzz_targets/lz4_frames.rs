@@ -0,0 +1,14 @@
+// Fuzz LZ4 frames: decompressing arbitrary bytes must fail cleanly, and
+// any content must come back unchanged from a compressed frame.
+
+#![no_main]
+
+use hivedb::core::codec;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = codec::lz4_decompress(data);
+    
+    let compressed = codec::lz4_compress(data).unwrap();
+    assert_eq!(codec::lz4_decompress(&compressed).unwrap(), data);
+});
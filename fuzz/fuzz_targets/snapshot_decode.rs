@@ -0,0 +1,17 @@
+// Fuzz the snapshot framing: decoding arbitrary bytes must fail cleanly,
+// and whatever decodes must survive being written and read again.
+
+#![no_main]
+
+use hivedb::core::snapshot::{self, SnapshotCodec};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok((_, body)) = snapshot::decode(data) else {
+        return;
+    };
+    
+    let encoded = snapshot::encode(&serde_json::to_vec(&body).unwrap(), SnapshotCodec::Json).unwrap();
+    let (_, decoded) = snapshot::decode(&encoded).unwrap();
+    assert_eq!(decoded, body);
+});
@@ -0,0 +1,290 @@
+// HiveDB Async Module
+//
+// This module offers the embedded API to applications built on tokio.
+// The storage engine is synchronous: loading and saving hives, building
+// indexes and running queries block on locks and disk I/O, which would
+// stall the worker threads of a runtime. Here every such operation runs
+// on tokio's blocking thread pool and is awaited instead. Queries are the
+// usual `Query` values or SQL text; dropping the future of a query
+// cancels it at its next checkpoint.
+
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use crate::core::commit::CommitTicket;
+use crate::core::error::HiveError;
+use crate::core::events::EventBus;
+use crate::core::hive::{Hive, HiveBuilder, HiveManager, HiveManagerBuilder};
+use crate::core::query::{Query, QueryExecutor, QueryResult, QueryType};
+use crate::core::schema::Schema;
+use crate::core::session::CancelToken;
+use crate::core::sql;
+use crate::core::verify::VerifyReport;
+
+/// A hive whose blocking operations are awaited; clones share the hive
+#[derive(Clone)]
+pub struct AsyncHive {
+    /// The hive
+    hive: Arc<RwLock<Hive>>,
+    
+    /// Manager the hive belongs to, for subqueries on other hives
+    manager: Option<Arc<RwLock<HiveManager>>>,
+}
+
+impl AsyncHive {
+    /// Wrap a hive
+    pub fn new(hive: Hive) -> Self {
+        Self::from_shared(Arc::new(RwLock::new(hive)))
+    }
+    
+    /// Wrap a hive shared with synchronous code
+    pub fn from_shared(hive: Arc<RwLock<Hive>>) -> Self {
+        Self { hive, manager: None }
+    }
+    
+    /// Load a hive from its storage directory
+    pub async fn open(path: impl Into<PathBuf>) -> Result<Self, HiveError> {
+        let path = path.into();
+        Ok(Self::new(blocking(move || Hive::load(path)).await?))
+    }
+    
+    /// Get the hive, to share it with synchronous code
+    pub fn shared(&self) -> &Arc<RwLock<Hive>> {
+        &self.hive
+    }
+    
+    /// Run a query
+    ///
+    /// Writes are as durable as the hive's durability policy asks by the
+    /// time the result is returned.
+    pub async fn execute(&self, query: Query) -> Result<QueryResult, HiveError> {
+        let cancel = CancelToken::new();
+        let _guard = CancelOnDrop(cancel.clone());
+        let hive = self.hive.clone();
+        let manager = self.manager.clone();
+        
+        blocking(move || {
+            // Subqueries run first, while the other hives can be locked in order
+            let query = match &manager {
+                Some(manager) if query.has_subqueries() => {
+                    let manager = manager.read().map_err(|_| HiveError::LockError)?;
+                    let hive = hive.read().map_err(|_| HiveError::LockError)?;
+                    QueryExecutor::materialize(&query, &hive, Some(&manager), &cancel)?
+                }
+                _ => query,
+            };
+            
+            if matches!(query.query_type, QueryType::Find | QueryType::Count) {
+                let hive = hive.read().map_err(|_| HiveError::LockError)?;
+                return QueryExecutor::execute_read_cancellable(&query, &hive, &cancel);
+            }
+            
+            let (result, ticket) = {
+                let mut hive = hive.write().map_err(|_| HiveError::LockError)?;
+                let result = QueryExecutor::execute_cancellable(&query, &mut hive, &cancel)?;
+                (result, CommitTicket::new(&hive))
+            };
+            ticket.wait(&hive)?;
+            Ok(result)
+        }).await
+    }
+    
+    /// Parse and run a SQL query
+    pub async fn sql(&self, text: &str) -> Result<QueryResult, HiveError> {
+        self.execute(sql::parse(text)?).await
+    }
+    
+    /// Run a function reading the hive
+    pub async fn read<T, F>(&self, work: F) -> Result<T, HiveError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Hive) -> Result<T, HiveError> + Send + 'static,
+    {
+        let hive = self.hive.clone();
+        blocking(move || work(&*hive.read().map_err(|_| HiveError::LockError)?)).await
+    }
+    
+    /// Run a function changing the hive, then commit the changes
+    ///
+    /// Like `execute`, this returns once the changes are as durable as
+    /// the hive's durability policy asks.
+    pub async fn write<T, F>(&self, work: F) -> Result<T, HiveError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Hive) -> Result<T, HiveError> + Send + 'static,
+    {
+        let hive = self.hive.clone();
+        blocking(move || {
+            let (value, ticket) = {
+                let mut hive = hive.write().map_err(|_| HiveError::LockError)?;
+                let value = work(&mut hive)?;
+                (value, CommitTicket::new(&hive))
+            };
+            ticket.wait(&hive)?;
+            Ok(value)
+        }).await
+    }
+    
+    /// Set the schema, rebuilding the indexes it declares
+    pub async fn set_schema(&self, schema: Schema) -> Result<(), HiveError> {
+        self.write(move |hive| hive.set_schema(schema)).await
+    }
+    
+    /// Rebuild an index declared by the schema, returning the number of indexed documents
+    pub async fn rebuild_index(&self, name: &str) -> Result<usize, HiveError> {
+        let name = name.to_string();
+        self.write(move |hive| hive.rebuild_index(&name)).await
+    }
+    
+    /// Save the hive
+    pub async fn save(&self) -> Result<(), HiveError> {
+        self.read(|hive| hive.save()).await
+    }
+    
+    /// Check the hive for inconsistencies, repairing them if asked
+    pub async fn verify(&self, repair: bool) -> Result<VerifyReport, HiveError> {
+        self.write(move |hive| hive.verify(repair)).await
+    }
+}
+
+/// A hive manager whose blocking operations are awaited; clones share the manager
+#[derive(Clone)]
+pub struct AsyncHiveManager {
+    /// The manager
+    manager: Arc<RwLock<HiveManager>>,
+}
+
+impl AsyncHiveManager {
+    /// Build a manager, loading its hives if the builder asks to
+    pub async fn open(builder: HiveManagerBuilder) -> Result<Self, HiveError> {
+        Ok(Self::from_shared(Arc::new(RwLock::new(blocking(move || builder.build()).await?))))
+    }
+    
+    /// Wrap a manager shared with synchronous code
+    pub fn from_shared(manager: Arc<RwLock<HiveManager>>) -> Self {
+        Self { manager }
+    }
+    
+    /// Get the manager, to share it with synchronous code
+    pub fn shared(&self) -> &Arc<RwLock<HiveManager>> {
+        &self.manager
+    }
+    
+    /// Get the bus the manager and its hives publish events on
+    pub async fn events(&self) -> Result<EventBus, HiveError> {
+        self.read(|manager| Ok(manager.events().clone())).await
+    }
+    
+    /// Create a hive; see `HiveManager::create_hive_from`
+    pub async fn create_hive(&self, builder: HiveBuilder) -> Result<AsyncHive, HiveError> {
+        let manager = self.manager.clone();
+        let hive = blocking(move || {
+            let mut manager = manager.write().map_err(|_| HiveError::LockError)?;
+            let id = manager.create_hive_from(builder)?;
+            manager.get_hive(&id).ok_or(HiveError::HiveNotFound)
+        }).await?;
+        
+        Ok(self.attach(hive))
+    }
+    
+    /// Get a hive by ID
+    pub async fn hive(&self, id: &str) -> Result<Option<AsyncHive>, HiveError> {
+        let id = id.to_string();
+        let hive = self.read(move |manager| Ok(manager.get_hive(&id))).await?;
+        Ok(hive.map(|hive| self.attach(hive)))
+    }
+    
+    /// Get a hive by name
+    pub async fn hive_by_name(&self, name: &str) -> Result<Option<AsyncHive>, HiveError> {
+        let name = name.to_string();
+        let hive = self.read(move |manager| Ok(manager.get_hive_by_name(&name))).await?;
+        Ok(hive.map(|hive| self.attach(hive)))
+    }
+    
+    /// List the IDs and names of all hives
+    pub async fn list_hives(&self) -> Result<Vec<(String, String)>, HiveError> {
+        self.read(|manager| Ok(manager.list_hives())).await
+    }
+    
+    /// Delete a hive with its storage
+    pub async fn delete_hive(&self, id: &str) -> Result<(), HiveError> {
+        let id = id.to_string();
+        let manager = self.manager.clone();
+        blocking(move || manager.write().map_err(|_| HiveError::LockError)?.delete_hive(&id)).await
+    }
+    
+    /// Save the hives that changed since they were last saved, returning how many were
+    pub async fn flush(&self) -> Result<usize, HiveError> {
+        self.read(|manager| manager.flush()).await
+    }
+    
+    /// Save all hives
+    pub async fn save_all(&self) -> Result<(), HiveError> {
+        self.read(|manager| manager.save_all()).await
+    }
+    
+    /// Run a function reading the manager on the blocking pool
+    async fn read<T, F>(&self, work: F) -> Result<T, HiveError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&HiveManager) -> Result<T, HiveError> + Send + 'static,
+    {
+        let manager = self.manager.clone();
+        blocking(move || work(&*manager.read().map_err(|_| HiveError::LockError)?)).await
+    }
+    
+    /// Wrap a hive of this manager
+    fn attach(&self, hive: Arc<RwLock<Hive>>) -> AsyncHive {
+        AsyncHive {
+            hive,
+            manager: Some(self.manager.clone()),
+        }
+    }
+}
+
+/// Cancels a query when its future is dropped before it finishes
+struct CancelOnDrop(CancelToken);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+/// Run blocking work on tokio's blocking thread pool and wait for it
+async fn blocking<T, F>(work: F) -> Result<T, HiveError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, HiveError> + Send + 'static,
+{
+    tokio::task::spawn_blocking(work)
+        .await
+        .map_err(|e| HiveError::GenericError(format!("Blocking task failed: {}", e)))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+    
+    #[test]
+    fn test_async_api() {
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let temp_dir = tempdir().unwrap();
+        
+        runtime.block_on(async {
+            let manager = AsyncHiveManager::open(HiveManager::builder().base_path(temp_dir.path())).await.unwrap();
+            let hive = manager.create_hive(Hive::builder().name("tasks").owner("test-user")).await.unwrap();
+            
+            hive.sql("INSERT INTO tasks (title, done) VALUES ('write docs', false), ('ship', true)").await.unwrap();
+            let open = hive.sql("SELECT COUNT(*) FROM tasks WHERE done = false").await.unwrap();
+            assert_eq!(open.count, 1);
+            
+            let found = manager.hive_by_name("tasks").await.unwrap().unwrap();
+            assert_eq!(found.read(|hive| Ok(hive.cell_count())).await.unwrap(), 2);
+            assert!(manager.hive("missing").await.unwrap().is_none());
+            
+            assert_eq!(manager.flush().await.unwrap(), 1);
+            assert_eq!(manager.list_hives().await.unwrap().len(), 1);
+        });
+    }
+}
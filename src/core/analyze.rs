@@ -0,0 +1,460 @@
+// HiveDB Analyze Module
+//
+// This module collects the data distribution of each collection in a
+// hive: for every field, the fraction of documents without a value, an
+// estimate of the number of distinct values, the most common values and
+// a histogram of the rest. The query executor uses these statistics to
+// estimate how many documents a filter selects. Statistics are only
+// refreshed by `ANALYZE`, so they describe the data as it was then.
+
+use std::collections::{BTreeMap, HashMap};
+use std::cmp::Ordering;
+use serde::{Deserialize, Serialize};
+use crate::core::cell::CellDataType;
+use crate::core::error::HiveError;
+use crate::core::hive::Hive;
+use crate::core::query::{
+    compare_values, values_equal, with_id, ComparisonOperator, FilterExpression, ALL_DOCUMENTS,
+};
+use crate::utils::determinism;
+
+/// Default number of documents sampled per collection
+pub const DEFAULT_SAMPLE_SIZE: usize = 30_000;
+
+/// Number of buckets in a field histogram
+pub const HISTOGRAM_BUCKETS: usize = 16;
+
+/// Number of most common values kept per field
+pub const MOST_COMMON_VALUES: usize = 8;
+
+/// Fraction of documents assumed to equal a value when nothing is known
+const DEFAULT_EQ_SELECTIVITY: f64 = 0.005;
+
+/// Fraction of documents assumed to fall in a range when nothing is known
+const DEFAULT_RANGE_SELECTIVITY: f64 = 1.0 / 3.0;
+
+/// Fraction of documents assumed to match a pattern or area
+const DEFAULT_MATCH_SELECTIVITY: f64 = 0.1;
+
+/// A frequent value of a field
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommonValue {
+    /// The value
+    pub value: serde_json::Value,
+    
+    /// Fraction of documents holding it
+    pub frequency: f64,
+}
+
+/// Distribution of the values of a field
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldStats {
+    /// Fraction of documents where the field is missing or null
+    pub null_fraction: f64,
+    
+    /// Estimated number of distinct values
+    pub distinct: f64,
+    
+    /// Most common values, most frequent first
+    pub most_common: Vec<CommonValue>,
+    
+    /// Bounds of equally populated buckets over the other ordered values
+    pub histogram: Vec<serde_json::Value>,
+}
+
+/// Statistics of a collection
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CollectionStats {
+    /// Number of documents in the collection
+    pub documents: usize,
+    
+    /// Number of documents the statistics were computed from
+    pub sampled: usize,
+    
+    /// Statistics of each field seen in the sample
+    pub fields: BTreeMap<String, FieldStats>,
+    
+    /// When the collection was analyzed (seconds since the UNIX epoch)
+    pub analyzed_at: u64,
+}
+
+/// Statistics of the collections of a hive
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HiveStatistics {
+    /// Statistics by collection (`*` covers all documents)
+    pub collections: BTreeMap<String, CollectionStats>,
+}
+
+impl HiveStatistics {
+    /// Get the statistics of a collection, if it has been analyzed
+    pub fn collection(&self, name: &str) -> Option<&CollectionStats> {
+        self.collections.get(name)
+    }
+}
+
+impl FieldStats {
+    /// Fraction of documents with a value not among the most common ones
+    fn other_fraction(&self) -> f64 {
+        let common: f64 = self.most_common.iter().map(|common| common.frequency).sum();
+        (1.0 - self.null_fraction - common).max(0.0)
+    }
+    
+    /// Estimate the fraction of documents where the field equals a value
+    pub fn equal_selectivity(&self, value: &serde_json::Value) -> f64 {
+        if value.is_null() {
+            return self.null_fraction;
+        }
+        if let Some(common) = self.most_common.iter().find(|common| values_equal(&common.value, value)) {
+            return common.frequency;
+        }
+        
+        // The remaining values are assumed to be equally frequent
+        let others = self.distinct - self.most_common.len() as f64;
+        if others < 1.0 {
+            return 0.0;
+        }
+        (self.other_fraction() / others).min(1.0)
+    }
+    
+    /// Estimate the fraction of documents where the field compares to a value as given
+    pub fn range_selectivity(&self, op: &ComparisonOperator, value: &serde_json::Value) -> f64 {
+        let common: f64 = self.most_common.iter()
+            .filter(|common| satisfies(op, &common.value, value))
+            .map(|common| common.frequency)
+            .sum();
+        
+        let below = match histogram_position(&self.histogram, value) {
+            Some(below) => below,
+            None if self.histogram.is_empty() => DEFAULT_RANGE_SELECTIVITY,
+            // Values of another kind never compare
+            None => 0.0,
+        };
+        let within = match op {
+            ComparisonOperator::Lt | ComparisonOperator::Lte => below,
+            _ => 1.0 - below,
+        };
+        
+        (common + self.other_fraction() * within).clamp(0.0, 1.0)
+    }
+}
+
+impl CollectionStats {
+    /// Estimate the fraction of documents that pass a filter
+    ///
+    /// Conditions are assumed to be independent of each other.
+    pub fn selectivity(&self, filter: &FilterExpression) -> f64 {
+        let selectivity = match filter {
+            FilterExpression::Comparison(op, field, value) => match (self.fields.get(field), op) {
+                (Some(stats), ComparisonOperator::Eq) => stats.equal_selectivity(value),
+                (Some(stats), ComparisonOperator::Ne) => {
+                    1.0 - stats.null_fraction - stats.equal_selectivity(value)
+                }
+                (Some(stats), op) => stats.range_selectivity(op, value),
+                (None, ComparisonOperator::Eq) => DEFAULT_EQ_SELECTIVITY,
+                (None, ComparisonOperator::Ne) => 1.0 - DEFAULT_EQ_SELECTIVITY,
+                (None, _) => DEFAULT_RANGE_SELECTIVITY,
+            },
+            FilterExpression::And(filters) => filters.iter().map(|filter| self.selectivity(filter)).product(),
+            FilterExpression::Or(filters) => {
+                1.0 - filters.iter().map(|filter| 1.0 - self.selectivity(filter)).product::<f64>()
+            }
+            FilterExpression::Not(filter) => 1.0 - self.selectivity(filter),
+            FilterExpression::Exists(field, should_exist) => {
+                let present = self.fields.get(field).map_or(0.5, |stats| 1.0 - stats.null_fraction);
+                if *should_exist { present } else { 1.0 - present }
+            }
+            FilterExpression::In(field, values) => match self.fields.get(field) {
+                Some(stats) => values.iter().map(|value| stats.equal_selectivity(value)).sum(),
+                None => DEFAULT_EQ_SELECTIVITY * values.len() as f64,
+            },
+            FilterExpression::Pattern(field, pattern) if !pattern.contains(['%', '_']) => {
+                self.fields.get(field)
+                    .map_or(DEFAULT_EQ_SELECTIVITY, |stats| stats.equal_selectivity(&serde_json::Value::String(pattern.clone())))
+            }
+            FilterExpression::Pattern(..)
+            | FilterExpression::Geo(_)
+            | FilterExpression::Search(_)
+            | FilterExpression::Array(_)
+            | FilterExpression::InQuery(..) => {
+                DEFAULT_MATCH_SELECTIVITY
+            }
+        };
+        
+        selectivity.clamp(0.0, 1.0)
+    }
+    
+    /// Estimate the number of documents that pass a filter
+    pub fn estimate_rows(&self, filter: &FilterExpression) -> f64 {
+        self.documents as f64 * self.selectivity(filter)
+    }
+}
+
+/// Compute the statistics of one collection, or of every collection if none is given
+///
+/// At most `sample_size` documents are read per collection, spread evenly
+/// over the grid.
+pub fn collect(
+    hive: &Hive,
+    collection: Option<&str>,
+    sample_size: usize,
+) -> Result<BTreeMap<String, CollectionStats>, HiveError> {
+    let analyzed_at = determinism::unix_time();
+    
+    let mut cells = hive.cells.all_cells();
+    cells.sort_by_key(|cell_arc| cell_arc.read().map(|cell| (cell.coordinates.1, cell.coordinates.0)).unwrap_or_default());
+    
+    // Group the documents by collection
+    let mut members: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    for (position, cell_arc) in cells.iter().enumerate() {
+        let cell = cell_arc.read().map_err(|_| HiveError::LockError)?;
+        if cell.data.data_type != CellDataType::Json {
+            continue;
+        }
+        
+        let names = std::iter::once(ALL_DOCUMENTS).chain(cell.metadata.tags.iter().map(String::as_str));
+        for name in names {
+            if collection.is_none_or(|collection| collection == name) {
+                members.entry(name.to_string()).or_default().push(position);
+            }
+        }
+    }
+    if let Some(collection) = collection {
+        members.entry(collection.to_string()).or_default();
+    }
+    
+    let mut collections = BTreeMap::new();
+    for (name, positions) in members {
+        let step = positions.len().div_ceil(sample_size.max(1)).max(1);
+        let mut samples = Vec::new();
+        for position in positions.iter().step_by(step) {
+            let cell = cells[*position].read().map_err(|_| HiveError::LockError)?;
+            // Unreadable documents are reported by `verify`, not here
+            if let Ok(document) = serde_json::from_slice::<serde_json::Value>(&cell.get_content()?) {
+                samples.push(with_id(document, &cell.id));
+            }
+        }
+        
+        collections.insert(name, CollectionStats {
+            documents: positions.len(),
+            sampled: samples.len(),
+            fields: field_stats(&samples, positions.len()),
+            analyzed_at,
+        });
+    }
+    
+    Ok(collections)
+}
+
+/// Compute the statistics of every top-level field in a sample of documents
+fn field_stats(samples: &[serde_json::Value], documents: usize) -> BTreeMap<String, FieldStats> {
+    let mut values: BTreeMap<String, Vec<&serde_json::Value>> = BTreeMap::new();
+    for sample in samples {
+        if let Some(object) = sample.as_object() {
+            for (field, value) in object {
+                if !value.is_null() {
+                    values.entry(field.clone()).or_default().push(value);
+                }
+            }
+        }
+    }
+    
+    values.into_iter()
+        .map(|(field, values)| (field, distribution(values, samples.len(), documents)))
+        .collect()
+}
+
+/// Compute the distribution of the non-null values of a field in a sample
+fn distribution(values: Vec<&serde_json::Value>, sampled: usize, documents: usize) -> FieldStats {
+    let mut counts: HashMap<String, (&serde_json::Value, usize)> = HashMap::new();
+    for value in &values {
+        counts.entry(value.to_string()).or_insert((value, 0)).1 += 1;
+    }
+    
+    let seen = counts.len() as f64;
+    let singletons = counts.values().filter(|(_, count)| *count == 1).count() as f64;
+    let sample = values.len() as f64;
+    let population = sample * documents as f64 / sampled.max(1) as f64;
+    
+    let distinct = if sampled >= documents {
+        seen
+    } else if singletons >= sample {
+        // Every sampled value was unique, so the values probably are
+        population
+    } else {
+        // Haas and Stokes' estimator
+        (sample * seen / (sample - singletons + singletons * sample / population)).clamp(seen, population)
+    };
+    
+    // A value is common if it was seen more than once, unless all values fit
+    let mut by_count: Vec<(&serde_json::Value, usize)> = counts.into_values().collect();
+    by_count.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.to_string().cmp(&b.0.to_string())));
+    let keep_all = by_count.len() <= MOST_COMMON_VALUES;
+    let most_common: Vec<CommonValue> = by_count.iter()
+        .take(MOST_COMMON_VALUES)
+        .filter(|(_, count)| keep_all || *count > 1)
+        .map(|(value, count)| CommonValue {
+            value: (*value).clone(),
+            frequency: *count as f64 / sampled as f64,
+        })
+        .collect();
+    
+    // The histogram covers the ordered values that are not common
+    let mut ordered: Vec<&serde_json::Value> = values.into_iter()
+        .filter(|value| value.is_number() || value.is_string())
+        .filter(|value| !most_common.iter().any(|common| values_equal(&common.value, value)))
+        .collect();
+    ordered.sort_by(|a, b| compare_ordered(a, b));
+    
+    let histogram = if ordered.len() < 2 {
+        Vec::new()
+    } else {
+        let buckets = HISTOGRAM_BUCKETS.min(ordered.len() - 1);
+        (0..=buckets)
+            .map(|bucket| ordered[bucket * (ordered.len() - 1) / buckets].clone())
+            .collect()
+    };
+    
+    FieldStats {
+        null_fraction: (sampled - sample as usize) as f64 / sampled as f64,
+        distinct,
+        most_common,
+        histogram,
+    }
+}
+
+/// Order numbers before strings, and values of the same kind by value
+fn compare_ordered(a: &serde_json::Value, b: &serde_json::Value) -> Ordering {
+    compare_values(a, b).unwrap_or_else(|| a.is_string().cmp(&b.is_string()))
+}
+
+/// Fraction of a histogram's values below a value (None if it does not compare)
+fn histogram_position(histogram: &[serde_json::Value], value: &serde_json::Value) -> Option<f64> {
+    let first = histogram.first()?;
+    let last = histogram.last()?;
+    let buckets = (histogram.len() - 1) as f64;
+    
+    if compare_values(value, first)? != Ordering::Greater {
+        return Some(0.0);
+    }
+    if compare_values(value, last)? != Ordering::Less {
+        return Some(1.0);
+    }
+    
+    let bucket = histogram.windows(2)
+        .position(|bounds| compare_values(value, &bounds[1]) == Some(Ordering::Less))?;
+    let (lower, upper) = (&histogram[bucket], &histogram[bucket + 1]);
+    
+    // Interpolate within the bucket where possible
+    let within = match (value.as_f64(), lower.as_f64(), upper.as_f64()) {
+        (Some(v), Some(l), Some(u)) if u > l => (v - l) / (u - l),
+        _ => 0.5,
+    };
+    
+    Some((bucket as f64 + within) / buckets)
+}
+
+/// Check whether a value compares to another as the operator requires
+fn satisfies(op: &ComparisonOperator, actual: &serde_json::Value, expected: &serde_json::Value) -> bool {
+    match (op, compare_values(actual, expected)) {
+        (ComparisonOperator::Gt, Some(ordering)) => ordering == Ordering::Greater,
+        (ComparisonOperator::Gte, Some(ordering)) => ordering != Ordering::Less,
+        (ComparisonOperator::Lt, Some(ordering)) => ordering == Ordering::Less,
+        (ComparisonOperator::Lte, Some(ordering)) => ordering != Ordering::Greater,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::cell::Cell;
+    use serde_json::json;
+    use tempfile::tempdir;
+    
+    fn hive_with_orders(dir: &std::path::Path) -> Hive {
+        let mut hive = Hive::new(
+            "analyze".to_string(),
+            "Analyze test".to_string(),
+            "test-user".to_string(),
+            dir.to_path_buf(),
+            (20, 10),
+        ).unwrap();
+        
+        for i in 0..200 {
+            let status = if i % 10 == 0 { "refunded" } else { "paid" };
+            let document = json!({ "amount": i, "status": status, "coupon": if i < 50 { json!("SPRING") } else { json!(null) } });
+            let mut cell = Cell::new(format!("o{}", i), (i % 20, i / 20), CellDataType::Json, document.to_string().into_bytes(), false).unwrap();
+            cell.add_tag("orders".to_string());
+            hive.add_cell(cell).unwrap();
+        }
+        
+        hive
+    }
+    
+    #[test]
+    fn test_collect_statistics() {
+        let temp_dir = tempdir().unwrap();
+        let hive = hive_with_orders(temp_dir.path());
+        
+        let collections = collect(&hive, None, DEFAULT_SAMPLE_SIZE).unwrap();
+        assert_eq!(collections.keys().collect::<Vec<_>>(), vec!["*", "orders"]);
+        
+        let orders = &collections["orders"];
+        assert_eq!(orders.documents, 200);
+        assert_eq!(orders.sampled, 200);
+        
+        let status = &orders.fields["status"];
+        assert_eq!(status.distinct, 2.0);
+        assert_eq!(status.most_common[0].value, json!("paid"));
+        assert!((status.most_common[0].frequency - 0.9).abs() < 1e-9);
+        
+        let coupon = &orders.fields["coupon"];
+        assert!((coupon.null_fraction - 0.75).abs() < 1e-9);
+        
+        let amount = &orders.fields["amount"];
+        assert_eq!(amount.distinct, 200.0);
+        assert_eq!(amount.histogram.len(), HISTOGRAM_BUCKETS + 1);
+        assert_eq!(amount.histogram[0], json!(0));
+        assert_eq!(amount.histogram[HISTOGRAM_BUCKETS], json!(199));
+        assert_eq!(orders.fields["_id"].distinct, 200.0);
+    }
+    
+    #[test]
+    fn test_selectivity_estimates() {
+        let temp_dir = tempdir().unwrap();
+        let hive = hive_with_orders(temp_dir.path());
+        let collections = collect(&hive, Some("orders"), DEFAULT_SAMPLE_SIZE).unwrap();
+        assert_eq!(collections.len(), 1);
+        let orders = &collections["orders"];
+        
+        let refunded = FilterExpression::Comparison(ComparisonOperator::Eq, "status".to_string(), json!("refunded"));
+        assert!((orders.estimate_rows(&refunded) - 20.0).abs() < 1e-6);
+        
+        let small = FilterExpression::Comparison(ComparisonOperator::Lt, "amount".to_string(), json!(50));
+        assert!((orders.estimate_rows(&small) - 50.0).abs() < 5.0);
+        
+        let one = FilterExpression::Comparison(ComparisonOperator::Eq, "amount".to_string(), json!(7));
+        assert!((orders.estimate_rows(&one) - 1.0).abs() < 1e-6);
+        
+        let both = FilterExpression::And(vec![refunded, small]);
+        assert!((orders.estimate_rows(&both) - 5.0).abs() < 1.0);
+        
+        let coupon = FilterExpression::Exists("coupon".to_string(), true);
+        assert!((orders.selectivity(&coupon) - 0.25).abs() < 1e-9);
+    }
+    
+    #[test]
+    fn test_sampled_statistics_scale_to_collection() {
+        let temp_dir = tempdir().unwrap();
+        let hive = hive_with_orders(temp_dir.path());
+        
+        let collections = collect(&hive, Some("orders"), 50).unwrap();
+        let orders = &collections["orders"];
+        assert_eq!(orders.documents, 200);
+        assert_eq!(orders.sampled, 50);
+        
+        // Every sampled amount is unique, so they are assumed unique overall
+        assert_eq!(orders.fields["amount"].distinct, 200.0);
+        assert_eq!(orders.fields["status"].distinct, 2.0);
+    }
+}
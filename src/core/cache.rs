@@ -0,0 +1,257 @@
+// HiveDB Cache Module
+//
+// This module caches parsed JSON documents so repeated queries over the
+// same cells do not decompress and parse them again. Entries are keyed by
+// the cell checksum, so a changed cell never returns a stale document.
+// The memory held by entries is charged to the memory budget, and the
+// least recently used entries are shed when the budget runs short.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+use serde::{Deserialize, Serialize};
+use crate::core::memory::{self, MemoryBudget, MemoryCategory, Reclaim, Reservation};
+
+/// Default maximum number of cached documents
+pub const DEFAULT_CAPACITY: usize = 10_000;
+
+/// The document cache shared by all queries
+static DOCUMENTS: OnceLock<Arc<DocumentCache>> = OnceLock::new();
+
+/// Snapshot of what a document cache holds and how well it serves lookups
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CacheStats {
+    /// Number of cached documents
+    pub entries: usize,
+    
+    /// Maximum number of cached documents
+    pub capacity: usize,
+    
+    /// Lookups that found their document
+    pub hits: u64,
+    
+    /// Lookups that did not
+    pub misses: u64,
+}
+
+impl CacheStats {
+    /// Fraction of lookups that found their document (0.0 before any lookup)
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            0.0
+        } else {
+            self.hits as f64 / lookups as f64
+        }
+    }
+}
+
+/// A cached document
+struct CacheEntry {
+    /// The parsed document
+    document: Arc<serde_json::Value>,
+    
+    /// Memory charged for the document
+    reservation: Reservation<'static>,
+    
+    /// Tick of the last lookup
+    last_used: u64,
+}
+
+/// Entries and their order of use
+#[derive(Default)]
+struct CacheState {
+    /// Entries by cell checksum
+    entries: HashMap<String, CacheEntry>,
+    
+    /// Checksums by tick of last use, oldest first
+    recency: BTreeMap<u64, String>,
+    
+    /// Counter ordering lookups
+    tick: u64,
+}
+
+/// Least recently used cache of parsed documents
+pub struct DocumentCache {
+    /// Budget the entries are charged to
+    budget: &'static MemoryBudget,
+    
+    /// Maximum number of entries
+    capacity: usize,
+    
+    /// Entries and their order of use
+    state: Mutex<CacheState>,
+    
+    /// Lookups that found their document
+    hits: AtomicU64,
+    
+    /// Lookups that did not
+    misses: AtomicU64,
+}
+
+impl DocumentCache {
+    /// Create a cache and register it with a memory budget
+    pub fn new(budget: &'static MemoryBudget, capacity: usize) -> Arc<Self> {
+        let cache = Arc::new(Self {
+            budget,
+            capacity,
+            state: Mutex::new(CacheState::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        });
+        
+        let reclaim: Arc<dyn Reclaim> = cache.clone();
+        budget.register_cache(Arc::downgrade(&reclaim) as Weak<dyn Reclaim>);
+        cache
+    }
+    
+    /// Look up the document stored in a cell with this checksum
+    ///
+    /// Returns the document with the size it was cached with.
+    pub fn get(&self, checksum: &str) -> Option<(Arc<serde_json::Value>, usize)> {
+        let mut state = self.state.lock().ok()?;
+        state.tick += 1;
+        let tick = state.tick;
+        
+        let Some(entry) = state.entries.get_mut(checksum) else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return None;
+        };
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        let previous = std::mem::replace(&mut entry.last_used, tick);
+        let document = (entry.document.clone(), entry.reservation.bytes());
+        
+        state.recency.remove(&previous);
+        state.recency.insert(tick, checksum.to_string());
+        Some(document)
+    }
+    
+    /// Check whether the document stored in a cell with this checksum is cached
+    ///
+    /// Unlike `get`, this counts neither a hit nor a miss and leaves the
+    /// document's place in line for eviction as it is.
+    pub fn contains(&self, checksum: &str) -> bool {
+        self.state.lock().is_ok_and(|state| state.entries.contains_key(checksum))
+    }
+    
+    /// Cache a document, if its memory can be had without shedding anything
+    pub fn insert(&self, checksum: &str, document: Arc<serde_json::Value>, size: usize) {
+        let Some(reservation) = self.budget.try_reserve(MemoryCategory::Cache, size) else {
+            return;
+        };
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        
+        if state.entries.len() >= self.capacity && !state.entries.contains_key(checksum) {
+            Self::evict_oldest(&mut state);
+        }
+        
+        state.tick += 1;
+        let tick = state.tick;
+        let entry = CacheEntry {
+            document,
+            reservation,
+            last_used: tick,
+        };
+        if let Some(previous) = state.entries.insert(checksum.to_string(), entry) {
+            state.recency.remove(&previous.last_used);
+        }
+        state.recency.insert(tick, checksum.to_string());
+    }
+    
+    /// Get the number of cached documents
+    pub fn len(&self) -> usize {
+        self.state.lock().map(|state| state.entries.len()).unwrap_or(0)
+    }
+    
+    /// Check whether the cache is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    
+    /// Get the size of the cache and how many lookups it served
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            entries: self.len(),
+            capacity: self.capacity,
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+    
+    /// Drop every entry
+    pub fn clear(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.entries.clear();
+            state.recency.clear();
+        }
+    }
+    
+    /// Drop the least recently used entry, and return the bytes it held
+    fn evict_oldest(state: &mut CacheState) -> usize {
+        let Some((_, checksum)) = state.recency.pop_first() else {
+            return 0;
+        };
+        
+        state.entries.remove(&checksum)
+            .map_or(0, |entry| entry.reservation.bytes())
+    }
+}
+
+impl Reclaim for DocumentCache {
+    fn reclaim(&self, bytes: usize) -> usize {
+        let Ok(mut state) = self.state.lock() else {
+            return 0;
+        };
+        
+        let mut freed = 0;
+        while freed < bytes && !state.entries.is_empty() {
+            freed += Self::evict_oldest(&mut state);
+        }
+        freed
+    }
+}
+
+/// Get the document cache shared by all queries
+pub fn documents() -> &'static DocumentCache {
+    DOCUMENTS.get_or_init(|| DocumentCache::new(memory::global(), DEFAULT_CAPACITY))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    
+    #[test]
+    fn test_document_cache_lru_and_shedding() {
+        let budget: &'static MemoryBudget = Box::leak(Box::new(MemoryBudget::new(1000)));
+        let cache = DocumentCache::new(budget, 3);
+        
+        cache.insert("a", Arc::new(json!({"n": 1})), 100);
+        cache.insert("b", Arc::new(json!({"n": 2})), 100);
+        cache.insert("c", Arc::new(json!({"n": 3})), 100);
+        assert!(cache.get("a").is_some());
+        
+        // "b" is the least recently used entry
+        cache.insert("d", Arc::new(json!({"n": 4})), 100);
+        assert_eq!(cache.len(), 3);
+        assert!(cache.get("b").is_none());
+        assert_eq!(budget.usage().cache, 300);
+        
+        // A query needing more than is free sheds the oldest entries
+        let mut query = budget.admit().unwrap();
+        query.grow(900).unwrap();
+        assert_eq!(cache.len(), 1);
+        assert!(cache.get("d").is_some());
+        assert_eq!(budget.usage().cache, 100);
+        
+        // Nothing is cached while the budget is full
+        cache.insert("e", Arc::new(json!({"n": 5})), 200);
+        assert!(cache.get("e").is_none());
+        
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.hits, stats.misses), (1, 2, 2));
+        assert_eq!(stats.hit_rate(), 0.5);
+    }
+}
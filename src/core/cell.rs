@@ -1,15 +1,77 @@
 // HiveDB Cell Module
 //
 // This module defines the hexagonal cell structure that forms
-// the foundation of our database storage system.
+// the foundation of our database storage system. Content larger than the
+// grid's split threshold is spread over continuation cells, which hold
+// the rest of the stored bytes and are hidden from everything but storage.
+// Coordinates are axial; see the coords module for cube and offset forms.
+// Cells nobody reads can have their content moved to the cold tier, in
+// which case they name the object holding it; see the tiering module.
+// Neighbor links can carry edges with a label, a weight and properties,
+// which makes the comb a property graph that traversals can follow.
 
+use bytes::{Bytes, BytesMut};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, BinaryHeap, HashMap, HashSet};
+use std::io::{Read, Write};
+use std::sync::{Arc, RwLock, Weak};
+use crate::core::codec::{self, Checksummer, Integrity, Lz4Encoder};
+use crate::core::compression::{self, CompressionDictionary};
+use crate::core::coords::{Axial, Cube, GridOrigin, Offset};
 use crate::core::error::HiveError;
+use crate::core::offsets::{ContentOffsets, FieldText};
+use crate::core::pheromone::{self, Temperature};
+use crate::core::tiering::{self, AccessTime, ColdTier};
+use crate::utils::determinism;
 use hexgrid::{Coordinate, Direction, HexGrid};
 use log::{debug, info};
 
+/// Stored bytes a cell holds before the rest moves to continuation cells
+pub const DEFAULT_SPLIT_THRESHOLD: usize = 256 * 1024;
+
+/// Get the number of steps between two cells of a grid
+pub fn hex_distance(a: (i32, i32), b: (i32, i32)) -> i32 {
+    Axial::from(a).distance(Axial::from(b))
+}
+
+/// Get the positions exactly `radius` steps from a center, going around it
+fn hex_ring(center: (i32, i32), radius: i32) -> Vec<(i32, i32)> {
+    if radius == 0 {
+        return vec![center];
+    }
+    
+    const DIRECTIONS: [(i32, i32); 6] = [(1, 0), (1, -1), (0, -1), (-1, 0), (-1, 1), (0, 1)];
+    let mut position = (center.0 - radius, center.1 + radius);
+    let mut ring = Vec::with_capacity(6 * radius as usize);
+    for (dq, dr) in DIRECTIONS {
+        for _ in 0..radius {
+            ring.push(position);
+            position = (position.0 + dq, position.1 + dr);
+        }
+    }
+    ring
+}
+
+/// Encode content for storage, returning whether it was compressed and any field offsets
+///
+/// Large JSON documents are compressed in segments so their fields can be
+/// read alone; everything else is compressed as one LZ4 frame.
+fn encode(data_type: &CellDataType, content: Vec<u8>, compress: bool) -> Result<(Bytes, bool, Option<ContentOffsets>), HiveError> {
+    if !compress {
+        return Ok((Bytes::from(content), false, None));
+    }
+    if *data_type == CellDataType::Json {
+        if let Some((stored, offsets)) = ContentOffsets::compress(&content)? {
+            return Ok((Bytes::from(stored), true, Some(offsets)));
+        }
+    }
+    
+    // Compress the data using LZ4
+    Ok((Bytes::from(codec::lz4_compress(&content)?), true, None))
+}
+
 /// Initialize the cell subsystem
 pub fn init() -> Result<(), HiveError> {
     info!("Initializing hexagonal cell subsystem");
@@ -37,6 +99,26 @@ pub struct Cell {
     
     /// Links to neighboring cells
     pub neighbors: HashMap<Direction, String>,
+    
+    /// Edges along neighbor links, by the direction of the link
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub edges: HashMap<Direction, Edge>,
+    
+    /// IDs of the continuation cells holding the rest of the content, in order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub continuations: Vec<String>,
+    
+    /// ID of the cell whose content this continuation cell holds part of
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub continues: Option<String>,
+    
+    /// The continuation cells, once this cell and they are in a grid
+    #[serde(skip)]
+    pieces: Vec<Weak<RwLock<Cell>>>,
+    
+    /// When a client last read this cell, and how often it did
+    #[serde(skip)]
+    last_read: AccessTime,
 }
 
 /// The actual data stored in a cell
@@ -46,11 +128,25 @@ pub struct CellData {
     pub data_type: CellDataType,
     
     /// The actual binary data, possibly compressed
-    pub content: Vec<u8>,
+    ///
+    /// Reads share this buffer rather than copying it.
+    pub content: Bytes,
     
     /// Whether the content is compressed
     pub is_compressed: bool,
     
+    /// ID of the dictionary the content was compressed with, instead of LZ4
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dictionary: Option<u32>,
+    
+    /// Key of the cold tier object holding the stored content, which is then not held here
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote: Option<String>,
+    
+    /// Segments and field offsets of a large JSON document compressed in segments
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offsets: Option<ContentOffsets>,
+    
     /// Checksum for data integrity
     pub checksum: String,
 }
@@ -105,120 +201,246 @@ impl Cell {
         content: Vec<u8>,
         compress: bool,
     ) -> Result<Self, HiveError> {
-        let now = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .map_err(|_| HiveError::SystemTimeError)?
-            .as_secs();
-        
-        let (final_content, is_compressed) = if compress {
-            // Compress the data using LZ4
-            let mut compressed = Vec::new();
-            let mut encoder = lz4::EncoderBuilder::new()
-                .level(6)
-                .build(&mut compressed)
-                .map_err(|e| HiveError::CompressionError(e.to_string()))?;
-            
-            std::io::copy(&mut &content[..], &mut encoder)
-                .map_err(|e| HiveError::CompressionError(e.to_string()))?;
-            
-            let (_, result) = encoder.finish();
-            result.map_err(|e| HiveError::CompressionError(e.to_string()))?;
-            
-            (compressed, true)
-        } else {
-            (content, false)
-        };
+        Self::new_with_integrity(id, coordinates, data_type, content, compress, Integrity::Sha256)
+    }
+    
+    /// Create a new cell with the given data, checksummed with an integrity codec
+    pub fn new_with_integrity(
+        id: String,
+        coordinates: (i32, i32),
+        data_type: CellDataType,
+        content: Vec<u8>,
+        compress: bool,
+        integrity: Integrity,
+    ) -> Result<Self, HiveError> {
+        let (final_content, is_compressed, offsets) = encode(&data_type, content, compress)?;
+        let mut cell = Self::from_stored(id, coordinates, data_type, final_content, is_compressed, integrity)?;
+        cell.data.offsets = offsets;
+        Ok(cell)
+    }
+    
+    /// Create a cell holding content that is already encoded for storage
+    fn from_stored(
+        id: String,
+        coordinates: (i32, i32),
+        data_type: CellDataType,
+        content: Bytes,
+        is_compressed: bool,
+        integrity: Integrity,
+    ) -> Result<Self, HiveError> {
+        let now = determinism::unix_time();
         
         // Calculate checksum
-        let checksum = format!("{:x}", ring::digest::digest(
-            &ring::digest::SHA256,
-            &final_content
-        ));
+        let checksum = integrity.checksum(&content);
         
         Ok(Self {
             id,
             coordinates,
-            data: CellData {
-                data_type,
-                content: final_content,
-                is_compressed,
-                checksum,
-            },
             metadata: CellMetadata {
                 created_at: now,
                 modified_at: now,
-                size_bytes: final_content.len(),
+                size_bytes: content.len(),
                 version: 1,
                 tags: Vec::new(),
             },
+            data: CellData {
+                data_type,
+                content,
+                is_compressed,
+                dictionary: None,
+                remote: None,
+                offsets: None,
+                checksum,
+            },
             neighbors: HashMap::new(),
+            edges: HashMap::new(),
+            continuations: Vec::new(),
+            continues: None,
+            pieces: Vec::new(),
+            last_read: AccessTime::default(),
         })
     }
     
+    /// Check whether this cell holds part of another cell's content
+    pub fn is_continuation(&self) -> bool {
+        self.continues.is_some()
+    }
+    
+    /// Check whether the content of this cell was moved to the cold tier
+    pub fn is_cold(&self) -> bool {
+        self.data.remote.is_some()
+    }
+    
+    /// Note that a client read this cell, which keeps it out of the cold tier
+    pub fn record_read(&self) {
+        self.last_read.touch();
+        tiering::record_read(self.is_cold());
+    }
+    
+    /// Get how many times a client read this cell since it was loaded
+    pub fn read_count(&self) -> u64 {
+        self.last_read.count()
+    }
+    
+    /// Get the pheromone left by the reads of this cell, which evaporates over time
+    pub fn pheromone(&self) -> f64 {
+        self.last_read.pheromone(&pheromone::evaporation(), determinism::unix_time())
+    }
+    
+    /// Classify this cell as hot, warm or cold by its pheromone
+    pub fn temperature(&self) -> Temperature {
+        pheromone::evaporation().classify(self.pheromone())
+    }
+    
+    /// Get when this cell was last read or written, in seconds since the epoch
+    pub fn last_active(&self) -> u64 {
+        self.last_read.get().max(self.metadata.modified_at)
+    }
+    
+    /// Get the stored (possibly compressed) content, joined from any continuation cells
+    ///
+    /// Content held in a single cell is shared, not copied. Cold content is
+    /// fetched from the cold tier.
+    pub fn stored_content(&self) -> Result<Bytes, HiveError> {
+        if let Some(key) = &self.data.remote {
+            return tiering::fetch(key);
+        }
+        if self.continuations.is_empty() {
+            return Ok(self.data.content.clone());
+        }
+        if self.pieces.len() != self.continuations.len() {
+            return Err(HiveError::ReferenceError);
+        }
+        
+        let mut content = BytesMut::from(&self.data.content[..]);
+        for piece in &self.pieces {
+            let piece = piece.upgrade().ok_or(HiveError::ReferenceError)?;
+            let piece = piece.read().map_err(|_| HiveError::LockError)?;
+            content.extend_from_slice(&piece.data.content);
+        }
+        Ok(content.freeze())
+    }
+    
     /// Get the decompressed content of this cell
-    pub fn get_content(&self) -> Result<Vec<u8>, HiveError> {
+    ///
+    /// Uncompressed content held in a single cell is shared, not copied.
+    pub fn get_content(&self) -> Result<Bytes, HiveError> {
+        let content = self.stored_content()?;
         if !self.data.is_compressed {
-            return Ok(self.data.content.clone());
+            return Ok(content);
+        }
+        if let Some(id) = self.data.dictionary {
+            return compression::dictionary(id)?.decompress(&content).map(Bytes::from);
+        }
+        if let Some(offsets) = &self.data.offsets {
+            return offsets.decompress(&content).map(Bytes::from);
         }
         
         // Decompress the data
-        let mut decoder = lz4::Decoder::new(&self.data.content[..])
-            .map_err(|e| HiveError::DecompressionError(e.to_string()))?;
-        
-        let mut decompressed = Vec::new();
-        std::io::copy(&mut decoder, &mut decompressed)
-            .map_err(|e| HiveError::DecompressionError(e.to_string()))?;
-        
-        Ok(decompressed)
+        codec::lz4_decompress(&content).map(Bytes::from)
+    }
+    
+    /// Get the JSON text of some top-level fields, decompressing only the segments holding them
+    ///
+    /// Returns `None` when the content has no field offsets, in which case
+    /// it has to be decompressed whole. Fields the document lacks are left out.
+    pub fn content_fields(&self, names: &BTreeSet<String>) -> Result<Option<Vec<FieldText>>, HiveError> {
+        match &self.data.offsets {
+            Some(offsets) => offsets.fields(&self.stored_content()?, names).map(Some),
+            None => Ok(None),
+        }
     }
     
-    /// Update the content of this cell
+    /// Update the content of this cell, keeping the integrity codec of its checksum
+    ///
+    /// The cell forgets its continuation cells; a grid holding it must
+    /// remove them, which `CellGrid::update_cell` does.
     pub fn update_content(
         &mut self,
         new_content: Vec<u8>,
         compress: bool,
     ) -> Result<(), HiveError> {
-        let now = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .map_err(|_| HiveError::SystemTimeError)?
-            .as_secs();
-        
-        let (final_content, is_compressed) = if compress {
-            // Compress the data using LZ4
-            let mut compressed = Vec::new();
-            let mut encoder = lz4::EncoderBuilder::new()
-                .level(6)
-                .build(&mut compressed)
-                .map_err(|e| HiveError::CompressionError(e.to_string()))?;
-            
-            std::io::copy(&mut &new_content[..], &mut encoder)
-                .map_err(|e| HiveError::CompressionError(e.to_string()))?;
-            
-            let (_, result) = encoder.finish();
-            result.map_err(|e| HiveError::CompressionError(e.to_string()))?;
-            
-            (compressed, true)
-        } else {
-            (new_content, false)
-        };
+        let integrity = Integrity::of_checksum(&self.data.checksum);
+        self.update_content_with_integrity(new_content, compress, integrity)
+    }
+    
+    /// Update the content of this cell, checksummed with an integrity codec
+    pub fn update_content_with_integrity(
+        &mut self,
+        new_content: Vec<u8>,
+        compress: bool,
+        integrity: Integrity,
+    ) -> Result<(), HiveError> {
+        let now = determinism::unix_time();
+        
+        let (final_content, is_compressed, offsets) = encode(&self.data.data_type, new_content, compress)?;
         
         // Calculate new checksum
-        let checksum = format!("{:x}", ring::digest::digest(
-            &ring::digest::SHA256,
-            &final_content
-        ));
+        let checksum = integrity.checksum(&final_content);
         
         // Update the cell
         self.data.content = final_content;
         self.data.is_compressed = is_compressed;
+        self.data.dictionary = None;
+        self.data.remote = None;
+        self.data.offsets = offsets;
         self.data.checksum = checksum;
         self.metadata.modified_at = now;
         self.metadata.size_bytes = self.data.content.len();
         self.metadata.version += 1;
+        self.continuations.clear();
+        self.pieces.clear();
         
         Ok(())
     }
     
+    /// Checksum this cell with an integrity codec, if its checksum was made with another
+    ///
+    /// Cold cells keep their checksums, which would take fetching their
+    /// content. Returns whether the checksum changed.
+    pub fn set_integrity(&mut self, integrity: Integrity) -> Result<bool, HiveError> {
+        if self.is_cold() || Integrity::of_checksum(&self.data.checksum) == integrity {
+            return Ok(false);
+        }
+        
+        self.data.checksum = integrity.checksum(&self.stored_content()?);
+        Ok(true)
+    }
+    
+    /// Get the key of this cell's document in the document cache
+    ///
+    /// SHA-256 checksums tell documents apart on their own. CRC32C ones
+    /// are too short for that, so the cell's ID and version go with them.
+    pub fn document_key(&self) -> Cow<'_, str> {
+        match Integrity::of_checksum(&self.data.checksum) {
+            Integrity::Sha256 => Cow::Borrowed(&self.data.checksum),
+            Integrity::Crc32c => Cow::Owned(format!("{}@{}/{}", self.id, self.metadata.version, self.data.checksum)),
+        }
+    }
+    
+    /// Compress the content of this cell with a dictionary instead of LZ4
+    ///
+    /// Only compressed cells whose content sits in a single cell are
+    /// recompressed, and only when the dictionary makes them smaller.
+    /// Returns whether the cell changed.
+    pub fn compress_with(&mut self, dictionary: &CompressionDictionary) -> Result<bool, HiveError> {
+        if !self.data.is_compressed || self.data.dictionary.is_some() || self.is_cold() || self.is_continuation() || !self.continuations.is_empty() {
+            return Ok(false);
+        }
+        
+        let compressed = dictionary.compress(&self.get_content()?)?;
+        if compressed.len() >= self.data.content.len() {
+            return Ok(false);
+        }
+        
+        self.data.checksum = Integrity::of_checksum(&self.data.checksum).checksum(&compressed);
+        self.data.content = Bytes::from(compressed);
+        self.data.dictionary = Some(dictionary.id);
+        self.data.offsets = None;
+        self.metadata.size_bytes = self.data.content.len();
+        Ok(true)
+    }
+    
     /// Add a tag to this cell
     pub fn add_tag(&mut self, tag: String) {
         if !self.metadata.tags.contains(&tag) {
@@ -232,13 +454,188 @@ impl Cell {
     }
     
     /// Link this cell to a neighbor
+    ///
+    /// An edge along the link is dropped if the link now leads to another cell.
     pub fn link_neighbor(&mut self, direction: Direction, neighbor_id: String) {
-        self.neighbors.insert(direction, neighbor_id);
+        if self.neighbors.insert(direction, neighbor_id).is_some_and(|previous| self.neighbors[&direction] != previous) {
+            self.edges.remove(&direction);
+        }
     }
     
-    /// Unlink a neighbor
+    /// Unlink a neighbor, dropping any edge along the link
     pub fn unlink_neighbor(&mut self, direction: Direction) {
         self.neighbors.remove(&direction);
+        self.edges.remove(&direction);
+    }
+    
+    /// Get the edge from this cell to a neighbor, by the neighbor's ID
+    pub fn edge_to(&self, neighbor_id: &str) -> Option<&Edge> {
+        self.neighbors.iter()
+            .find(|(_, id)| *id == neighbor_id)
+            .and_then(|(direction, _)| self.edges.get(direction))
+    }
+}
+
+/// Sink that cuts stored content into pieces of the split threshold as it is written
+struct PieceWriter {
+    /// Size of each piece
+    threshold: usize,
+    
+    /// Pieces already full
+    pieces: Vec<Bytes>,
+    
+    /// Piece being filled
+    current: BytesMut,
+    
+    /// Running checksum of everything written
+    digest: Checksummer,
+    
+    /// Number of bytes written
+    size: usize,
+}
+
+impl PieceWriter {
+    fn new(threshold: usize, integrity: Integrity) -> Self {
+        Self {
+            threshold,
+            pieces: Vec::new(),
+            current: BytesMut::new(),
+            digest: integrity.start(),
+            size: 0,
+        }
+    }
+    
+    /// Get the pieces, the checksum and the size of everything written
+    fn finish(mut self) -> (Vec<Bytes>, String, usize) {
+        if !self.current.is_empty() {
+            self.pieces.push(self.current.split().freeze());
+        }
+        (self.pieces, self.digest.finish(), self.size)
+    }
+}
+
+impl Write for PieceWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.digest.update(buf);
+        self.size += buf.len();
+        
+        let mut rest = buf;
+        while !rest.is_empty() {
+            let (now, later) = rest.split_at(rest.len().min(self.threshold - self.current.len()));
+            self.current.extend_from_slice(now);
+            if self.current.len() == self.threshold {
+                self.pieces.push(self.current.split().freeze());
+            }
+            rest = later;
+        }
+        Ok(buf.len())
+    }
+    
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A typed, weighted edge along the link from a cell to a neighbor
+///
+/// Edges are directed: an edge from one cell to another says nothing of
+/// the way back, which may have an edge of its own.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Edge {
+    /// Type of the relationship, such as "FRIEND"
+    pub label: String,
+    
+    /// Weight of the edge, such as a distance or strength
+    pub weight: f64,
+    
+    /// Other properties of the edge
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub properties: HashMap<String, serde_json::Value>,
+}
+
+impl Edge {
+    /// Create an edge with a label, a weight of 1.0 and no properties
+    pub fn new(label: &str) -> Self {
+        Self {
+            label: label.to_string(),
+            weight: 1.0,
+            properties: HashMap::new(),
+        }
+    }
+    
+    /// Set the weight of the edge
+    pub fn with_weight(mut self, weight: f64) -> Self {
+        self.weight = weight;
+        self
+    }
+    
+    /// Set a property of the edge
+    pub fn with_property(mut self, name: &str, value: serde_json::Value) -> Self {
+        self.properties.insert(name.to_string(), value);
+        self
+    }
+}
+
+/// A cell reached by a traversal
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TraversalStep {
+    /// ID of the cell
+    pub id: String,
+    
+    /// Coordinates of the cell
+    pub coordinates: (i32, i32),
+    
+    /// Number of edges followed to reach the cell
+    pub depth: usize,
+    
+    /// Coordinates of the cell the last edge left from
+    pub from: (i32, i32),
+    
+    /// The last edge followed
+    pub edge: Edge,
+}
+
+/// The cheapest route found between two cells
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CellPath {
+    /// Coordinates of the cells on the route, both ends included
+    pub coordinates: Vec<(i32, i32)>,
+    
+    /// Sum of the costs of the steps
+    pub cost: f64,
+}
+
+/// A position waiting to be expanded by the path search
+struct Frontier {
+    /// Cost so far plus the distance left, which orders the search
+    estimate: f64,
+    
+    /// Cost of the cheapest route to the position so far
+    cost: f64,
+    
+    /// The position
+    position: (i32, i32),
+}
+
+impl PartialEq for Frontier {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Frontier {}
+
+impl PartialOrd for Frontier {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Frontier {
+    // Reversed, so the heap pops the lowest estimate first
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.estimate.total_cmp(&self.estimate)
+            .then_with(|| other.position.cmp(&self.position))
     }
 }
 
@@ -249,6 +646,24 @@ pub struct CellGrid {
     
     /// Dimensions of the grid
     dimensions: (usize, usize),
+    
+    /// Index of cell IDs to their coordinates
+    ids: HashMap<String, (i32, i32)>,
+    
+    /// Stored bytes a cell holds before the rest moves to continuation cells
+    split_threshold: usize,
+    
+    /// Number of continuation cells in the grid
+    continuation_count: usize,
+    
+    /// Where the coordinates (0, 0) lie
+    origin: GridOrigin,
+    
+    /// Dictionary that new compressed JSON cells are compressed with
+    dictionary: Option<Arc<CompressionDictionary>>,
+    
+    /// Codec that checksums of cells written to the grid are made with
+    integrity: Integrity,
 }
 
 impl CellGrid {
@@ -257,16 +672,342 @@ impl CellGrid {
         Self {
             grid: HexGrid::new(),
             dimensions,
+            ids: HashMap::new(),
+            split_threshold: DEFAULT_SPLIT_THRESHOLD,
+            continuation_count: 0,
+            origin: GridOrigin::Corner,
+            dictionary: None,
+            integrity: Integrity::default(),
+        }
+    }
+    
+    /// Get the codec that checksums of cells written to the grid are made with
+    pub fn integrity(&self) -> Integrity {
+        self.integrity
+    }
+    
+    /// Set the codec that checksums of cells written to the grid are made with
+    ///
+    /// Cells already in the grid keep their checksums until next written.
+    pub fn set_integrity(&mut self, integrity: Integrity) {
+        self.integrity = integrity;
+    }
+    
+    /// Get the dictionary that new compressed JSON cells are compressed with
+    pub fn dictionary(&self) -> Option<&Arc<CompressionDictionary>> {
+        self.dictionary.as_ref()
+    }
+    
+    /// Set the dictionary that new compressed JSON cells are compressed with
+    ///
+    /// Cells already in the grid keep their compression until next written.
+    pub fn set_dictionary(&mut self, dictionary: Option<Arc<CompressionDictionary>>) {
+        self.dictionary = dictionary;
+    }
+    
+    /// Get where the coordinates (0, 0) lie
+    pub fn origin(&self) -> GridOrigin {
+        self.origin
+    }
+    
+    /// Set where the coordinates (0, 0) lie, which only an empty grid can change
+    pub fn set_origin(&mut self, origin: GridOrigin) -> Result<(), HiveError> {
+        if origin != self.origin && !self.grid.is_empty() {
+            return Err(HiveError::GenericError("Cannot change the origin of a grid that holds cells".to_string()));
+        }
+        self.origin = origin;
+        Ok(())
+    }
+    
+    /// Get the lowest and highest coordinates inside the grid
+    pub fn bounds(&self) -> ((i32, i32), (i32, i32)) {
+        let min = self.origin.min(self.dimensions);
+        (min, (min.0 + self.dimensions.0 as i32 - 1, min.1 + self.dimensions.1 as i32 - 1))
+    }
+    
+    /// Get the number of stored bytes a cell holds before the rest moves to continuation cells
+    pub fn split_threshold(&self) -> usize {
+        self.split_threshold
+    }
+    
+    /// Set the number of stored bytes a cell holds before the rest moves to continuation cells
+    ///
+    /// Cells already in the grid are split or joined only when next written.
+    pub fn set_split_threshold(&mut self, bytes: usize) {
+        self.split_threshold = bytes.max(1);
+    }
+    
+    /// Add a cell to the grid, splitting its content if it is too large
+    pub fn add_cell(&mut self, mut cell: Cell) -> Result<(), HiveError> {
+        let coordinates = cell.coordinates;
+        let head = match &cell.continues {
+            Some(head) => head.clone(),
+            None => cell.id.clone(),
+        };
+        
+        if let Some(dictionary) = &self.dictionary {
+            if cell.data.data_type == CellDataType::Json {
+                cell.compress_with(dictionary)?;
+            }
+        }
+        
+        // A large cell must fit with its continuation cells
+        if cell.continuations.is_empty() && !cell.is_continuation() {
+            cell.set_integrity(self.integrity)?;
+            let pieces = self.pieces_needed(cell.data.content.len());
+            if pieces + 1 > self.free_count() {
+                return Err(HiveError::OutOfBoundsError);
+            }
+        }
+        
+        self.insert_cell(cell)?;
+        self.split(coordinates)?;
+        
+        // Cells loaded from storage find their continuations in whatever order they arrive
+        self.link_pieces(&head)
+    }
+    
+    /// Add a cell whose content is read from a stream, compressing and splitting it on the fly
+    ///
+    /// Neither the whole input nor a second copy of the stored content is
+    /// held in memory at once. Returns the number of stored bytes.
+    pub fn add_from_reader(
+        &mut self,
+        id: String,
+        coordinates: (i32, i32),
+        data_type: CellDataType,
+        reader: &mut impl Read,
+        compress: bool,
+    ) -> Result<usize, HiveError> {
+        if !self.in_bounds(coordinates) {
+            return Err(HiveError::OutOfBoundsError);
+        }
+        if self.grid.contains(&Coordinate::new(coordinates.0, coordinates.1)) {
+            return Err(HiveError::CellAlreadyExists);
+        }
+        
+        let mut writer = PieceWriter::new(self.split_threshold, self.integrity);
+        if compress {
+            let mut encoder = Lz4Encoder::new(writer)?;
+            std::io::copy(reader, &mut encoder)
+                .map_err(|e| HiveError::CompressionError(e.to_string()))?;
+            writer = encoder.finish()?;
+        } else {
+            std::io::copy(reader, &mut writer)
+                .map_err(|e| HiveError::IoError(e.to_string()))?;
+        }
+        
+        let (pieces, checksum, size) = writer.finish();
+        if pieces.len() > self.free_count() {
+            return Err(HiveError::OutOfBoundsError);
+        }
+        
+        // The head cell describes the whole stored content, as a split cell does
+        let mut pieces = pieces.into_iter();
+        let mut cell = Cell::from_stored(id.clone(), coordinates, data_type, pieces.next().unwrap_or_default(), compress, self.integrity)?;
+        cell.data.checksum = checksum;
+        cell.metadata.size_bytes = size;
+        self.insert_cell(cell)?;
+        
+        let ids = self.add_continuations(&id, pieces)?;
+        if let Some(cell_arc) = self.get_cell(coordinates) {
+            cell_arc.write().map_err(|_| HiveError::LockError)?.continuations = ids;
+        }
+        self.link_pieces(&id)?;
+        Ok(size)
+    }
+    
+    /// Replace the content of a cell, splitting it again if it is too large
+    pub fn update_cell(&mut self, coordinates: (i32, i32), content: Vec<u8>, compress: bool) -> Result<(), HiveError> {
+        let cell_arc = self.get_cell(coordinates).ok_or(HiveError::CellNotFound)?;
+        let old_pieces = {
+            let mut cell = cell_arc.write().map_err(|_| HiveError::LockError)?;
+            let old_pieces = std::mem::take(&mut cell.continuations);
+            cell.update_content_with_integrity(content, compress, self.integrity)?;
+            if let Some(dictionary) = &self.dictionary {
+                if cell.data.data_type == CellDataType::Json {
+                    cell.compress_with(dictionary)?;
+                }
+            }
+            old_pieces
+        };
+        
+        for id in old_pieces {
+            if let Some(piece) = self.ids.get(&id).copied() {
+                self.remove_cell(piece)?;
+            }
+        }
+        self.split(coordinates)
+    }
+    
+    /// Move the stored content of a cell to the cold tier, returning the bytes moved
+    ///
+    /// The cell keeps its place, checksum and metadata and names the object
+    /// now holding its content. Its continuation cells are removed, since the
+    /// object holds all of the content.
+    pub fn offload(&mut self, coordinates: (i32, i32), key: String, tier: &ColdTier) -> Result<usize, HiveError> {
+        let cell_arc = self.get_cell(coordinates).ok_or(HiveError::CellNotFound)?;
+        let (old_pieces, size) = {
+            let mut cell = cell_arc.write().map_err(|_| HiveError::LockError)?;
+            if cell.is_cold() || cell.is_continuation() {
+                return Ok(0);
+            }
+            
+            let content = cell.stored_content()?;
+            tier.store(&key, &content)?;
+            cell.data.content = Bytes::new();
+            cell.data.remote = Some(key);
+            cell.pieces.clear();
+            (std::mem::take(&mut cell.continuations), content.len())
+        };
+        
+        for id in old_pieces {
+            if let Some(piece) = self.ids.get(&id).copied() {
+                self.remove_cell(piece)?;
+            }
+        }
+        Ok(size)
+    }
+    
+    /// Move a cell to unoccupied coordinates, relinking it with its new neighbors
+    pub fn move_cell(&mut self, from: (i32, i32), to: (i32, i32)) -> Result<(), HiveError> {
+        if !self.in_bounds(to) {
+            return Err(HiveError::OutOfBoundsError);
+        }
+        if self.grid.contains(&Coordinate::new(to.0, to.1)) {
+            return Err(HiveError::CellAlreadyExists);
+        }
+        
+        let mut cell = self.take_cell(from)?;
+        cell.coordinates = to;
+        cell.neighbors.clear();
+        cell.edges.clear();
+        let head = cell.continues.clone();
+        self.insert_cell(cell)?;
+        
+        // A moved continuation cell is a new handle its head must pick up
+        match head {
+            Some(head) => self.link_pieces(&head),
+            None => Ok(()),
+        }
+    }
+    
+    /// Get the coordinates at the middle of the grid
+    pub fn center(&self) -> (i32, i32) {
+        let (min, _) = self.bounds();
+        (min.0 + self.dimensions.0 as i32 / 2, min.1 + self.dimensions.1 as i32 / 2)
+    }
+    
+    /// Plan the moves that pack the cells as close to the center as they fit
+    ///
+    /// Cells already among the closest positions stay where they are; the
+    /// others move to the closest free positions, farthest cells first.
+    pub fn rebalance_plan(&self) -> Vec<((i32, i32), (i32, i32))> {
+        let center = self.center();
+        let rank = |coordinates: (i32, i32)| (hex_distance(coordinates, center), coordinates.1, coordinates.0);
+        
+        let (min, max) = self.bounds();
+        let mut positions: Vec<(i32, i32)> = (min.1..=max.1)
+            .flat_map(|y| (min.0..=max.0).map(move |x| (x, y)))
+            .collect();
+        positions.sort_by_key(|&coordinates| rank(coordinates));
+        positions.truncate(self.grid.len());
+        
+        let targets: HashSet<(i32, i32)> = positions.iter().copied().collect();
+        let mut outside: Vec<(i32, i32)> = self.grid.keys()
+            .map(|coords| (coords.x, coords.y))
+            .filter(|coordinates| !targets.contains(coordinates))
+            .collect();
+        outside.sort_by_key(|&coordinates| std::cmp::Reverse(rank(coordinates)));
+        
+        let free = positions.into_iter().filter(|&(x, y)| !self.grid.contains(&Coordinate::new(x, y)));
+        outside.into_iter().zip(free).collect()
+    }
+    
+    /// Check whether coordinates lie inside the grid
+    fn in_bounds(&self, coordinates: (i32, i32)) -> bool {
+        let (min, max) = self.bounds();
+        (min.0..=max.0).contains(&coordinates.0) && (min.1..=max.1).contains(&coordinates.1)
+    }
+    
+    /// Get the number of continuation cells needed for stored content of some size
+    fn pieces_needed(&self, size: usize) -> usize {
+        size.saturating_sub(1) / self.split_threshold
+    }
+    
+    /// Get the number of coordinates in the grid, or `usize::MAX` if that overflows
+    fn positions(&self) -> usize {
+        self.dimensions.0.saturating_mul(self.dimensions.1)
+    }
+    
+    /// Get the number of unoccupied coordinates
+    fn free_count(&self) -> usize {
+        self.positions().saturating_sub(self.grid.len())
+    }
+    
+    /// Move the content of a cell past the split threshold to new continuation cells
+    fn split(&mut self, coordinates: (i32, i32)) -> Result<(), HiveError> {
+        let cell_arc = self.get_cell(coordinates).ok_or(HiveError::CellNotFound)?;
+        
+        // The cell is unlocked while continuation cells link to their neighbors
+        let (id, rest) = {
+            let mut cell = cell_arc.write().map_err(|_| HiveError::LockError)?;
+            if cell.is_continuation() || !cell.continuations.is_empty() || cell.data.content.len() <= self.split_threshold {
+                return Ok(());
+            }
+            if self.pieces_needed(cell.data.content.len()) > self.free_count() {
+                return Err(HiveError::OutOfBoundsError);
+            }
+            let rest = cell.data.content.split_off(self.split_threshold);
+            (cell.id.clone(), rest)
+        };
+        
+        let threshold = self.split_threshold;
+        let pieces = (0..rest.len())
+            .step_by(threshold)
+            .map(|start| rest.slice(start..rest.len().min(start + threshold)));
+        let ids = self.add_continuations(&id, pieces)?;
+        debug!("Split cell '{}' over {} continuation cells", id, ids.len());
+        
+        cell_arc.write().map_err(|_| HiveError::LockError)?.continuations = ids;
+        self.link_pieces(&id)
+    }
+    
+    /// Add continuation cells holding pieces of a cell's stored content, returning their IDs
+    fn add_continuations(&mut self, id: &str, pieces: impl Iterator<Item = Bytes>) -> Result<Vec<String>, HiveError> {
+        let mut ids = Vec::new();
+        for (number, piece) in pieces.enumerate() {
+            let free = self.first_free_coordinates().ok_or(HiveError::OutOfBoundsError)?;
+            let mut continuation = Cell::from_stored(format!("{}#{}", id, number + 1), free, CellDataType::Binary, piece, false, self.integrity)?;
+            continuation.continues = Some(id.to_string());
+            ids.push(continuation.id.clone());
+            self.insert_cell(continuation)?;
         }
+        Ok(ids)
     }
     
-    /// Add a cell to the grid
-    pub fn add_cell(&mut self, cell: Cell) -> Result<(), HiveError> {
+    /// Point a cell at its continuation cells, if they are all in the grid
+    fn link_pieces(&self, id: &str) -> Result<(), HiveError> {
+        let Some(cell_arc) = self.find_by_id(id) else {
+            return Ok(());
+        };
+        let mut cell = cell_arc.write().map_err(|_| HiveError::LockError)?;
+        
+        let pieces: Option<Vec<_>> = cell.continuations.iter()
+            .map(|id| self.find_by_id(id).map(|piece| Arc::downgrade(&piece)))
+            .collect();
+        if let Some(pieces) = pieces {
+            cell.pieces = pieces;
+        }
+        Ok(())
+    }
+    
+    /// Place a cell in the grid as it is
+    fn insert_cell(&mut self, cell: Cell) -> Result<(), HiveError> {
         let coords = Coordinate::new(cell.coordinates.0, cell.coordinates.1);
         
         // Check if the coordinates are within bounds
-        if cell.coordinates.0 < 0 || cell.coordinates.0 >= self.dimensions.0 as i32 ||
-           cell.coordinates.1 < 0 || cell.coordinates.1 >= self.dimensions.1 as i32 {
+        if !self.in_bounds(cell.coordinates) {
             return Err(HiveError::OutOfBoundsError);
         }
         
@@ -276,6 +1017,10 @@ impl CellGrid {
         }
         
         // Add the cell to the grid
+        if cell.is_continuation() {
+            self.continuation_count += 1;
+        }
+        self.ids.insert(cell.id.clone(), cell.coordinates);
         self.grid.insert(coords, Arc::new(RwLock::new(cell)));
         
         // Update neighbor links
@@ -290,8 +1035,44 @@ impl CellGrid {
         self.grid.get(&coords).cloned()
     }
     
-    /// Remove a cell from the grid
+    /// Get a cell from the grid by axial coordinates
+    pub fn get_cell_axial(&self, axial: Axial) -> Option<Arc<RwLock<Cell>>> {
+        self.get_cell(axial.into())
+    }
+    
+    /// Get a cell from the grid by cube coordinates
+    pub fn get_cell_cube(&self, cube: Cube) -> Option<Arc<RwLock<Cell>>> {
+        self.get_cell_axial(cube.into())
+    }
+    
+    /// Get a cell from the grid by offset coordinates
+    pub fn get_cell_offset(&self, offset: Offset) -> Option<Arc<RwLock<Cell>>> {
+        self.get_cell_axial(offset.into())
+    }
+    
+    /// Remove a cell from the grid, with its continuation cells
+    ///
+    /// The removed cell holds all of its content again, unless the content
+    /// is cold, in which case the cell still names its object.
     pub fn remove_cell(&mut self, coordinates: (i32, i32)) -> Result<Cell, HiveError> {
+        let mut cell = self.take_cell(coordinates)?;
+        
+        if !cell.continuations.is_empty() {
+            let mut content = BytesMut::from(&cell.data.content[..]);
+            for id in std::mem::take(&mut cell.continuations) {
+                if let Some(piece) = self.ids.get(&id).copied() {
+                    content.extend_from_slice(&self.take_cell(piece)?.data.content);
+                }
+            }
+            cell.data.content = content.freeze();
+            cell.pieces.clear();
+        }
+        
+        Ok(cell)
+    }
+    
+    /// Remove a single cell from the grid
+    fn take_cell(&mut self, coordinates: (i32, i32)) -> Result<Cell, HiveError> {
         let coords = Coordinate::new(coordinates.0, coordinates.1);
         
         // Remove the cell
@@ -304,6 +1085,13 @@ impl CellGrid {
             Err(_) => return Err(HiveError::ReferenceError),
         };
         
+        if self.ids.get(&cell.id) == Some(&coordinates) {
+            self.ids.remove(&cell.id);
+        }
+        if cell.is_continuation() {
+            self.continuation_count -= 1;
+        }
+        
         // Update neighbor links for adjacent cells
         for direction in Direction::all() {
             if let Some(neighbor_coords) = coords.neighbor(direction) {
@@ -352,13 +1140,308 @@ impl CellGrid {
         }
     }
     
-    /// Get the number of cells in the grid
+    /// Get the IDs of the cells next to some coordinates, by direction
+    pub fn neighbors_of(&self, coordinates: (i32, i32)) -> Result<HashMap<Direction, String>, HiveError> {
+        let coords = Coordinate::new(coordinates.0, coordinates.1);
+        let mut neighbors = HashMap::new();
+        
+        for direction in Direction::all() {
+            let Some(neighbor_arc) = coords.neighbor(direction).and_then(|neighbor| self.grid.get(&neighbor)) else {
+                continue;
+            };
+            let neighbor = neighbor_arc.read().map_err(|_| HiveError::LockError)?;
+            neighbors.insert(direction, neighbor.id.clone());
+        }
+        
+        Ok(neighbors)
+    }
+    
+    /// Replace the neighbor links of a cell with the cells actually next to it
+    ///
+    /// Edges are kept only along links that still lead to the same cell.
+    pub fn relink(&self, coordinates: (i32, i32)) -> Result<(), HiveError> {
+        let neighbors = self.neighbors_of(coordinates)?;
+        let cell_arc = self.get_cell(coordinates).ok_or(HiveError::CellNotFound)?;
+        let mut cell = cell_arc.write().map_err(|_| HiveError::LockError)?;
+        let previous = std::mem::replace(&mut cell.neighbors, neighbors);
+        let Cell { neighbors, edges, .. } = &mut *cell;
+        edges.retain(|direction, _| neighbors.get(direction).is_some_and(|id| previous.get(direction) == Some(id)));
+        Ok(())
+    }
+    
+    /// Set the edge from a cell to a neighbor, replacing any edge between them
+    pub fn set_edge(&self, from: (i32, i32), to: (i32, i32), edge: Edge) -> Result<(), HiveError> {
+        let direction = self.direction_to(from, to)?;
+        let cell_arc = self.get_cell(from).ok_or(HiveError::CellNotFound)?;
+        let mut cell = cell_arc.write().map_err(|_| HiveError::LockError)?;
+        if cell.is_continuation() || !cell.neighbors.contains_key(&direction) {
+            return Err(HiveError::ReferenceError);
+        }
+        
+        cell.edges.insert(direction, edge);
+        Ok(())
+    }
+    
+    /// Remove the edge from a cell to a neighbor, returning it if there was one
+    pub fn remove_edge(&self, from: (i32, i32), to: (i32, i32)) -> Result<Option<Edge>, HiveError> {
+        let direction = self.direction_to(from, to)?;
+        let cell_arc = self.get_cell(from).ok_or(HiveError::CellNotFound)?;
+        let mut cell = cell_arc.write().map_err(|_| HiveError::LockError)?;
+        Ok(cell.edges.remove(&direction))
+    }
+    
+    /// Get the edges leaving a cell with the coordinates they lead to, ordered by them
+    pub fn edges_of(&self, coordinates: (i32, i32)) -> Result<Vec<((i32, i32), Edge)>, HiveError> {
+        let cell_arc = self.get_cell(coordinates).ok_or(HiveError::CellNotFound)?;
+        let cell = cell_arc.read().map_err(|_| HiveError::LockError)?;
+        let coords = Coordinate::new(coordinates.0, coordinates.1);
+        
+        let mut edges: Vec<((i32, i32), Edge)> = cell.edges.iter()
+            .filter_map(|(direction, edge)| coords.neighbor(*direction).map(|to| ((to.x, to.y), edge.clone())))
+            .collect();
+        edges.sort_by_key(|(to, _)| (to.1, to.0));
+        Ok(edges)
+    }
+    
+    /// Get the edges from neighbors into a cell with the coordinates they leave from, ordered by them
+    pub fn edges_into(&self, coordinates: (i32, i32)) -> Result<Vec<((i32, i32), Edge)>, HiveError> {
+        let coords = Coordinate::new(coordinates.0, coordinates.1);
+        let mut edges = Vec::new();
+        
+        for direction in Direction::all() {
+            let Some(neighbor) = coords.neighbor(direction) else {
+                continue;
+            };
+            let Some(neighbor_arc) = self.grid.get(&neighbor) else {
+                continue;
+            };
+            let cell = neighbor_arc.read().map_err(|_| HiveError::LockError)?;
+            if let Some(edge) = cell.edges.get(&direction.opposite()) {
+                edges.push(((neighbor.x, neighbor.y), edge.clone()));
+            }
+        }
+        
+        edges.sort_by_key(|(from, _)| (from.1, from.0));
+        Ok(edges)
+    }
+    
+    /// Follow edges breadth-first from a cell, up to `max_depth` edges away
+    ///
+    /// Only edges with the given label are followed, or any edge if none is
+    /// given. Each cell reached is listed once, at its smallest depth, with
+    /// the edge it was first reached by; the starting cell is not listed.
+    pub fn traverse(&self, from: (i32, i32), label: Option<&str>, max_depth: usize) -> Result<Vec<TraversalStep>, HiveError> {
+        let mut visited = HashSet::from([from]);
+        let mut frontier = vec![from];
+        let mut steps = Vec::new();
+        
+        for depth in 1..=max_depth {
+            let mut next = Vec::new();
+            for position in frontier {
+                for (to, edge) in self.edges_of(position)? {
+                    if label.is_some_and(|label| edge.label != label) || !visited.insert(to) {
+                        continue;
+                    }
+                    let Some(cell_arc) = self.get_cell(to) else {
+                        continue;
+                    };
+                    let id = cell_arc.read().map_err(|_| HiveError::LockError)?.id.clone();
+                    
+                    steps.push(TraversalStep { id, coordinates: to, depth, from: position, edge });
+                    next.push(to);
+                }
+            }
+            if next.is_empty() {
+                break;
+            }
+            frontier = next;
+        }
+        
+        Ok(steps)
+    }
+    
+    /// Get the direction of the link from a cell to a neighbor
+    fn direction_to(&self, from: (i32, i32), to: (i32, i32)) -> Result<Direction, HiveError> {
+        if self.get_cell(to).is_none() {
+            return Err(HiveError::CellNotFound);
+        }
+        
+        let coords = Coordinate::new(from.0, from.1);
+        Direction::all().into_iter()
+            .find(|direction| coords.neighbor(*direction) == Some(Coordinate::new(to.0, to.1)))
+            .ok_or_else(|| HiveError::GenericError(format!("Cells at {:?} and {:?} are not neighbors", from, to)))
+    }
+    
+    /// Get the number of steps between two positions of the grid
+    pub fn distance(&self, a: (i32, i32), b: (i32, i32)) -> i32 {
+        hex_distance(a, b)
+    }
+    
+    /// Find the `k` cells closest to some coordinates that pass a filter
+    ///
+    /// Cells are returned nearest first, and cells at the same distance
+    /// row by row. A cell at the coordinates themselves counts, at distance
+    /// zero. Continuation cells are skipped.
+    pub fn k_nearest(
+        &self,
+        coordinates: (i32, i32),
+        k: usize,
+        filter: impl Fn(&Cell) -> bool,
+    ) -> Result<Vec<Arc<RwLock<Cell>>>, HiveError> {
+        let mut nearest = Vec::new();
+        if k == 0 {
+            return Ok(nearest);
+        }
+        
+        // Walking rings outwards visits empty positions, so on a sparse
+        // grid it is cheaper to rank every cell
+        let rank = |position: (i32, i32)| (hex_distance(position, coordinates), position.1, position.0);
+        let positions: Box<dyn Iterator<Item = (i32, i32)>> = if self.grid.len() * 4 < self.positions() {
+            let mut positions: Vec<(i32, i32)> = self.grid.keys().map(|coords| (coords.x, coords.y)).collect();
+            positions.sort_by_key(|&position| rank(position));
+            Box::new(positions.into_iter())
+        } else {
+            let (min, max) = self.bounds();
+            let radius = [min, max, (min.0, max.1), (max.0, min.1)].into_iter()
+                .map(|corner| hex_distance(corner, coordinates))
+                .max()
+                .unwrap_or(0);
+            Box::new((0..=radius).flat_map(move |radius| {
+                let mut ring = hex_ring(coordinates, radius);
+                ring.sort_by_key(|&(q, r)| (r, q));
+                ring
+            }))
+        };
+        
+        for position in positions {
+            let Some(cell_arc) = self.get_cell(position) else {
+                continue;
+            };
+            let cell = cell_arc.read().map_err(|_| HiveError::LockError)?;
+            if !cell.is_continuation() && filter(&cell) {
+                drop(cell);
+                nearest.push(cell_arc);
+                if nearest.len() == k {
+                    break;
+                }
+            }
+        }
+        
+        Ok(nearest)
+    }
+    
+    /// Find the cheapest route between two cells, stepping between neighbors
+    ///
+    /// `cost` gives the cost of stepping from a cell to a neighbor, or None
+    /// if the step is not allowed; negative costs are not allowed either.
+    /// Routes only cross occupied positions, and never continuation cells.
+    /// The search (A*) assumes every step costs at least 1.0; with cheaper
+    /// steps the route found may not be the cheapest. Returns None when no
+    /// route exists, and `CellNotFound` if either end holds no cell.
+    pub fn find_path(
+        &self,
+        from: (i32, i32),
+        to: (i32, i32),
+        cost: impl Fn(&Cell, &Cell) -> Option<f64>,
+    ) -> Result<Option<CellPath>, HiveError> {
+        if self.get_cell(from).is_none() || self.get_cell(to).is_none() {
+            return Err(HiveError::CellNotFound);
+        }
+        
+        let mut open = BinaryHeap::from([Frontier { estimate: hex_distance(from, to) as f64, cost: 0.0, position: from }]);
+        let mut best: HashMap<(i32, i32), f64> = HashMap::from([(from, 0.0)]);
+        let mut came_from: HashMap<(i32, i32), (i32, i32)> = HashMap::new();
+        
+        while let Some(Frontier { cost: so_far, position, .. }) = open.pop() {
+            if position == to {
+                let mut coordinates = vec![to];
+                while let Some(&previous) = came_from.get(coordinates.last().unwrap_or(&from)) {
+                    coordinates.push(previous);
+                }
+                coordinates.reverse();
+                return Ok(Some(CellPath { coordinates, cost: so_far }));
+            }
+            if best.get(&position).is_some_and(|&best| so_far > best) {
+                continue;
+            }
+            
+            let Some(cell_arc) = self.get_cell(position) else {
+                continue;
+            };
+            let cell = cell_arc.read().map_err(|_| HiveError::LockError)?;
+            let coords = Coordinate::new(position.0, position.1);
+            for direction in Direction::all() {
+                let Some(next_arc) = coords.neighbor(direction).and_then(|neighbor| self.grid.get(&neighbor)) else {
+                    continue;
+                };
+                let next = next_arc.read().map_err(|_| HiveError::LockError)?;
+                if next.is_continuation() {
+                    continue;
+                }
+                let Some(step) = cost(&cell, &next).filter(|step| *step >= 0.0) else {
+                    continue;
+                };
+                
+                let next_cost = so_far + step;
+                if best.get(&next.coordinates).is_some_and(|&best| next_cost >= best) {
+                    continue;
+                }
+                best.insert(next.coordinates, next_cost);
+                came_from.insert(next.coordinates, position);
+                open.push(Frontier {
+                    estimate: next_cost + hex_distance(next.coordinates, to) as f64,
+                    cost: next_cost,
+                    position: next.coordinates,
+                });
+            }
+        }
+        
+        Ok(None)
+    }
+    
+    /// Get a cell by its ID
+    pub fn find_by_id(&self, id: &str) -> Option<Arc<RwLock<Cell>>> {
+        self.ids.get(id).and_then(|coordinates| self.get_cell(*coordinates))
+    }
+    
+    /// Find the first unoccupied coordinates, scanning row by row
+    pub fn first_free_coordinates(&self) -> Option<(i32, i32)> {
+        let (min, max) = self.bounds();
+        for y in min.1..=max.1 {
+            for x in min.0..=max.0 {
+                if self.grid.get(&Coordinate::new(x, y)).is_none() {
+                    return Some((x, y));
+                }
+            }
+        }
+        
+        None
+    }
+    
+    /// Get the dimensions of the grid
+    pub fn dimensions(&self) -> (usize, usize) {
+        self.dimensions
+    }
+    
+    /// Get the number of cells in the grid, not counting continuation cells
     pub fn cell_count(&self) -> usize {
-        self.grid.len()
+        self.grid.len() - self.continuation_count
     }
     
-    /// Get all cells in the grid
+    /// Get all cells in the grid, except continuation cells
     pub fn all_cells(&self) -> Vec<Arc<RwLock<Cell>>> {
+        if self.continuation_count == 0 {
+            return self.grid.values().cloned().collect();
+        }
+        
+        self.grid.values()
+            .filter(|cell_arc| cell_arc.read().map_or(true, |cell| !cell.is_continuation()))
+            .cloned()
+            .collect()
+    }
+    
+    /// Get every cell in the grid, continuation cells included
+    pub fn stored_cells(&self) -> Vec<Arc<RwLock<Cell>>> {
         self.grid.values().cloned().collect()
     }
     
@@ -434,6 +1517,277 @@ mod tests {
         
         assert_eq!(cell.get_content().unwrap(), new_content);
         assert_eq!(cell.metadata.version, 2);
+        
+        // Uncompressed content is handed out without copying it
+        assert_eq!(cell.get_content().unwrap().as_ptr(), cell.data.content.as_ptr());
+    }
+    
+    #[test]
+    fn test_grid_id_index() {
+        let mut grid = CellGrid::new((2, 2));
+        
+        assert_eq!(grid.first_free_coordinates(), Some((0, 0)));
+        
+        let cell = Cell::new(
+            "test-cell-5".to_string(),
+            (0, 0),
+            CellDataType::KeyValue,
+            b"value".to_vec(),
+            false,
+        ).unwrap();
+        grid.add_cell(cell).unwrap();
+        
+        assert_eq!(grid.first_free_coordinates(), Some((1, 0)));
+        
+        let found = grid.find_by_id("test-cell-5").unwrap();
+        assert_eq!(found.read().unwrap().coordinates, (0, 0));
+        drop(found);
+        
+        grid.remove_cell((0, 0)).unwrap();
+        assert!(grid.find_by_id("test-cell-5").is_none());
+    }
+    
+    #[test]
+    fn test_grid_splits_large_cells() {
+        let mut grid = CellGrid::new((3, 3));
+        grid.set_split_threshold(4);
+        
+        let content = b"0123456789".to_vec();
+        let cell = Cell::new("big".to_string(), (0, 0), CellDataType::Binary, content.clone(), false).unwrap();
+        grid.add_cell(cell).unwrap();
+        
+        // The content continues in two hidden cells
+        assert_eq!(grid.cell_count(), 1);
+        assert_eq!(grid.all_cells().len(), 1);
+        assert_eq!(grid.stored_cells().len(), 3);
+        let big = grid.find_by_id("big").unwrap();
+        assert_eq!(big.read().unwrap().data.content, b"0123".to_vec());
+        assert_eq!(big.read().unwrap().continuations, vec!["big#1".to_string(), "big#2".to_string()]);
+        assert_eq!(big.read().unwrap().get_content().unwrap(), content);
+        drop(big);
+        
+        grid.update_cell((0, 0), b"abcdef".to_vec(), false).unwrap();
+        assert_eq!(grid.stored_cells().len(), 2);
+        assert_eq!(grid.find_by_id("big").unwrap().read().unwrap().get_content().unwrap(), b"abcdef".to_vec());
+        
+        // Content that cannot fit with its continuations is refused
+        let huge = Cell::new("huge".to_string(), (2, 2), CellDataType::Binary, vec![7; 40], false).unwrap();
+        assert!(matches!(grid.add_cell(huge), Err(HiveError::OutOfBoundsError)));
+        assert_eq!(grid.stored_cells().len(), 2);
+        
+        let removed = grid.remove_cell((0, 0)).unwrap();
+        assert_eq!(removed.get_content().unwrap(), b"abcdef".to_vec());
+        assert!(grid.stored_cells().is_empty());
+        assert_eq!(grid.cell_count(), 0);
+    }
+    
+    #[test]
+    fn test_cell_content_fields() {
+        let padding = "x".repeat(crate::core::offsets::OFFSET_THRESHOLD);
+        let content = format!(r#"{{"padding": "{}", "status": "shipped"}}"#, padding).into_bytes();
+        let mut cell = Cell::new("large".to_string(), (0, 0), CellDataType::Json, content.clone(), true).unwrap();
+        
+        // Large documents are compressed in segments
+        assert!(cell.data.offsets.is_some());
+        assert_eq!(cell.get_content().unwrap(), content);
+        
+        let names = ["status".to_string(), "total".to_string()].into_iter().collect();
+        assert_eq!(cell.content_fields(&names).unwrap(), Some(vec![("status".to_string(), br#""shipped""#.to_vec())]));
+        
+        // Small documents are compressed whole
+        cell.update_content(br#"{"status": "shipped"}"#.to_vec(), true).unwrap();
+        assert!(cell.data.offsets.is_none());
+        assert_eq!(cell.content_fields(&names).unwrap(), None);
+    }
+    
+    #[test]
+    fn test_grid_integrity() {
+        let mut grid = CellGrid::new((3, 3));
+        grid.set_split_threshold(4);
+        grid.set_integrity(Integrity::Crc32c);
+        
+        // Cells made elsewhere are checksummed again as they join the grid, pieces too
+        grid.add_cell(Cell::new("big".to_string(), (0, 0), CellDataType::Binary, b"0123456789".to_vec(), false).unwrap()).unwrap();
+        let big = grid.find_by_id("big").unwrap();
+        assert_eq!(big.read().unwrap().data.checksum, Integrity::Crc32c.checksum(b"0123456789"));
+        assert_eq!(big.read().unwrap().document_key(), format!("big@1/{}", big.read().unwrap().data.checksum));
+        let piece = grid.find_by_id("big#1").unwrap();
+        assert_eq!(piece.read().unwrap().data.checksum, Integrity::Crc32c.checksum(b"4567"));
+        drop((big, piece));
+        
+        // Writes use the grid's codec, while a cell alone keeps its own
+        grid.set_integrity(Integrity::Sha256);
+        grid.update_cell((0, 0), b"abc".to_vec(), false).unwrap();
+        let big = grid.find_by_id("big").unwrap();
+        assert_eq!(big.read().unwrap().data.checksum, codec::sha256(b"abc").to_hex());
+        assert_eq!(big.read().unwrap().document_key(), big.read().unwrap().data.checksum);
+        
+        let mut cell = Cell::new_with_integrity("kv".to_string(), (0, 0), CellDataType::KeyValue, b"v1".to_vec(), false, Integrity::Crc32c).unwrap();
+        cell.update_content(b"v2".to_vec(), false).unwrap();
+        assert_eq!(cell.data.checksum, Integrity::Crc32c.checksum(b"v2"));
+        assert!(cell.set_integrity(Integrity::Sha256).unwrap());
+        assert!(!cell.set_integrity(Integrity::Sha256).unwrap());
+        assert_eq!(cell.data.checksum, codec::sha256(b"v2").to_hex());
+    }
+    
+    #[test]
+    fn test_grid_add_from_reader() {
+        let mut grid = CellGrid::new((3, 3));
+        grid.set_split_threshold(4);
+        
+        let content = b"0123456789".to_vec();
+        let size = grid.add_from_reader("stream".to_string(), (1, 1), CellDataType::Binary, &mut &content[..], false).unwrap();
+        assert_eq!(size, 10);
+        assert_eq!(grid.cell_count(), 1);
+        assert_eq!(grid.stored_cells().len(), 3);
+        
+        // The streamed cell is indistinguishable from one that was split
+        let cell = Cell::new("split".to_string(), (0, 0), CellDataType::Binary, content.clone(), false).unwrap();
+        let streamed = grid.find_by_id("stream").unwrap();
+        assert_eq!(streamed.read().unwrap().get_content().unwrap(), content);
+        assert_eq!(streamed.read().unwrap().data.checksum, cell.data.checksum);
+        assert_eq!(streamed.read().unwrap().continuations, vec!["stream#1".to_string(), "stream#2".to_string()]);
+        drop(streamed);
+        
+        let text = "compressible ".repeat(20);
+        grid.set_split_threshold(1024);
+        grid.add_from_reader("text".to_string(), (2, 2), CellDataType::Json, &mut text.as_bytes(), true).unwrap();
+        let cell = grid.find_by_id("text").unwrap();
+        assert!(cell.read().unwrap().data.is_compressed);
+        assert_eq!(cell.read().unwrap().get_content().unwrap(), text.into_bytes());
+        drop(cell);
+        
+        assert!(matches!(
+            grid.add_from_reader("taken".to_string(), (1, 1), CellDataType::Binary, &mut &b"x"[..], false),
+            Err(HiveError::CellAlreadyExists)
+        ));
+    }
+    
+    #[test]
+    fn test_centered_grid() {
+        let mut grid = CellGrid::new((5, 5));
+        grid.set_origin(GridOrigin::Center).unwrap();
+        assert_eq!(grid.bounds(), ((-2, -2), (2, 2)));
+        assert_eq!(grid.center(), (0, 0));
+        assert_eq!(grid.first_free_coordinates(), Some((-2, -2)));
+        
+        let cell = Cell::new("west".to_string(), (-2, 1), CellDataType::KeyValue, b"value".to_vec(), false).unwrap();
+        grid.add_cell(cell).unwrap();
+        let outside = Cell::new("far".to_string(), (3, 0), CellDataType::KeyValue, b"value".to_vec(), false).unwrap();
+        assert!(matches!(grid.add_cell(outside), Err(HiveError::OutOfBoundsError)));
+        assert!(grid.set_origin(GridOrigin::Corner).is_err());
+        
+        // The same cell through every coordinate system
+        assert!(grid.get_cell_axial(Axial::new(-2, 1)).is_some());
+        assert!(grid.get_cell_cube(Cube::new(-2, 1, 1).unwrap()).is_some());
+        assert!(grid.get_cell_offset(Offset::new(-2, 1)).is_some());
+        assert!(grid.get_cell_offset(Offset::new(-3, 1)).is_none());
+    }
+    
+    #[test]
+    fn test_k_nearest() {
+        let mut grid = CellGrid::new((5, 5));
+        for (id, coordinates) in [("a", (2, 2)), ("b", (3, 2)), ("c", (2, 1)), ("d", (0, 4)), ("e", (4, 4))] {
+            let mut cell = Cell::new(id.to_string(), coordinates, CellDataType::KeyValue, b"value".to_vec(), false).unwrap();
+            if id != "b" {
+                cell.add_tag("shop".to_string());
+            }
+            grid.add_cell(cell).unwrap();
+        }
+        assert_eq!(grid.distance((2, 2), (0, 4)), 2);
+        assert_eq!(grid.distance((2, 2), (4, 4)), 4);
+        
+        let ids = |cells: Vec<Arc<RwLock<Cell>>>| cells.iter().map(|cell| cell.read().unwrap().id.clone()).collect::<Vec<_>>();
+        let shops = |cell: &Cell| cell.metadata.tags.contains(&"shop".to_string());
+        assert_eq!(ids(grid.k_nearest((2, 2), 3, shops).unwrap()), ["a", "c", "d"]);
+        assert_eq!(ids(grid.k_nearest((3, 2), 2, |_| true).unwrap()), ["b", "a"]);
+        assert_eq!(grid.k_nearest((2, 2), 10, shops).unwrap().len(), 4);
+        
+        // Filling the grid switches to walking rings, with the same answers
+        for y in 0..5 {
+            for x in 0..5 {
+                if grid.get_cell((x, y)).is_none() {
+                    grid.add_cell(Cell::new(format!("{}-{}", x, y), (x, y), CellDataType::KeyValue, Vec::new(), false).unwrap()).unwrap();
+                }
+            }
+        }
+        assert_eq!(ids(grid.k_nearest((2, 2), 3, shops).unwrap()), ["a", "c", "d"]);
+        assert_eq!(ids(grid.k_nearest((0, 0), 2, |_| true).unwrap()), ["0-0", "1-0"]);
+        assert_eq!(hex_ring((0, 0), 2).len(), 12);
+    }
+    
+    #[test]
+    fn test_find_path() {
+        // A wall down column 2 with a gap at the top, and mud at (1, 1)
+        let mut grid = CellGrid::new((5, 3));
+        for y in 0..3 {
+            for x in 0..5 {
+                let mut cell = Cell::new(format!("{}-{}", x, y), (x, y), CellDataType::KeyValue, Vec::new(), false).unwrap();
+                if x == 2 && y > 0 {
+                    cell.add_tag("wall".to_string());
+                }
+                if (x, y) == (1, 1) {
+                    cell.add_tag("mud".to_string());
+                }
+                grid.add_cell(cell).unwrap();
+            }
+        }
+        let cost = |_: &Cell, next: &Cell| {
+            let tagged = |tag: &str| next.metadata.tags.contains(&tag.to_string());
+            if tagged("wall") {
+                None
+            } else if tagged("mud") {
+                Some(5.0)
+            } else {
+                Some(1.0)
+            }
+        };
+        
+        let path = grid.find_path((0, 2), (4, 2), cost).unwrap().unwrap();
+        assert!(path.coordinates.contains(&(2, 0)));
+        assert!(!path.coordinates.contains(&(1, 1)));
+        assert_eq!(path.coordinates.first(), Some(&(0, 2)));
+        assert_eq!(path.coordinates.last(), Some(&(4, 2)));
+        assert_eq!(path.cost, (path.coordinates.len() - 1) as f64);
+        
+        let path = grid.find_path((3, 1), (3, 1), cost).unwrap().unwrap();
+        assert_eq!((path.coordinates, path.cost), (vec![(3, 1)], 0.0));
+        
+        // Closing the gap cuts the grid in two
+        let closed = |from: &Cell, next: &Cell| if next.coordinates == (2, 0) { None } else { cost(from, next) };
+        assert_eq!(grid.find_path((0, 2), (4, 2), closed).unwrap(), None);
+        grid.remove_cell((4, 2)).unwrap();
+        assert!(matches!(grid.find_path((0, 2), (4, 2), cost), Err(HiveError::CellNotFound)));
+    }
+    
+    #[test]
+    fn test_edges() {
+        let mut grid = CellGrid::new((4, 4));
+        for (id, coordinates) in [("ann", (1, 1)), ("bob", (2, 1)), ("cat", (2, 2)), ("dan", (3, 2)), ("eve", (0, 3))] {
+            grid.add_cell(Cell::new(id.to_string(), coordinates, CellDataType::Json, b"{}".to_vec(), false).unwrap()).unwrap();
+        }
+        grid.set_edge((1, 1), (2, 1), Edge::new("FRIEND").with_property("since", serde_json::json!(2019))).unwrap();
+        grid.set_edge((2, 1), (2, 2), Edge::new("FRIEND").with_weight(0.5)).unwrap();
+        grid.set_edge((2, 2), (3, 2), Edge::new("WORKS_WITH")).unwrap();
+        assert!(matches!(grid.set_edge((1, 1), (3, 2), Edge::new("FRIEND")), Err(HiveError::GenericError(_))));
+        assert!(matches!(grid.set_edge((1, 1), (1, 2), Edge::new("FRIEND")), Err(HiveError::CellNotFound)));
+        
+        let ann = grid.get_cell((1, 1)).unwrap();
+        assert_eq!(ann.read().unwrap().edge_to("bob").unwrap().properties["since"], 2019);
+        assert!(grid.get_cell((2, 1)).unwrap().read().unwrap().edge_to("ann").is_none());
+        
+        let friends = grid.traverse((1, 1), Some("FRIEND"), 5).unwrap();
+        let reached: Vec<(&str, usize)> = friends.iter().map(|step| (step.id.as_str(), step.depth)).collect();
+        assert_eq!(reached, [("bob", 1), ("cat", 2)]);
+        assert_eq!(friends[1].edge.weight, 0.5);
+        assert_eq!(grid.traverse((1, 1), None, 2).unwrap().len(), 2);
+        assert_eq!(grid.traverse((1, 1), None, 3).unwrap().len(), 3);
+        
+        // Removing a cell drops the edges leading to it
+        grid.remove_cell((2, 1)).unwrap();
+        assert!(grid.edges_of((1, 1)).unwrap().is_empty());
+        assert_eq!(grid.remove_edge((2, 2), (3, 2)).unwrap().unwrap().label, "WORKS_WITH");
+        assert!(grid.edges_of((2, 2)).unwrap().is_empty());
     }
     
     #[test]
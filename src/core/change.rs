@@ -0,0 +1,226 @@
+// HiveDB Change Module
+//
+// This module defines the change log that records every mutation applied
+// to a hive, in order, so that downstream consumers can follow it.
+
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use crate::core::error::HiveError;
+use crate::utils::determinism;
+
+/// Default number of change events retained per hive
+pub const DEFAULT_CHANGE_LOG_CAPACITY: usize = 10_000;
+
+/// Kinds of changes that can be applied to a hive
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ChangeKind {
+    /// A cell was added to the hive
+    CellInserted,
+    
+    /// The content of a cell was replaced
+    CellUpdated,
+    
+    /// A cell was removed from the hive
+    CellRemoved,
+    
+    /// A cell was moved to other coordinates, which the event holds
+    CellMoved,
+    
+    /// The schema of the hive was replaced
+    SchemaChanged,
+}
+
+/// A single change recorded in a hive's change log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangeEvent {
+    /// Position of this event in the change log (strictly increasing)
+    pub sequence: u64,
+    
+    /// ID of the hive the change was applied to
+    pub hive_id: String,
+    
+    /// What kind of change this is
+    pub kind: ChangeKind,
+    
+    /// ID of the affected cell, if any
+    pub cell_id: Option<String>,
+    
+    /// Coordinates of the affected cell, if any
+    pub coordinates: Option<(i32, i32)>,
+    
+    /// Uncompressed content after the change (None for removals)
+    pub content: Option<Vec<u8>>,
+    
+    /// When the change was recorded (seconds since the UNIX epoch)
+    pub timestamp: u64,
+}
+
+/// An ordered, bounded log of the changes applied to a hive
+///
+/// Old events are discarded once the capacity is reached; consumers that
+/// fall too far behind get a `ChangeLogTruncated` error and must resync.
+/// Sequence numbers carry on across restarts when the log is resumed from
+/// `next_sequence`, so a saved consumer position stays meaningful.
+#[derive(Debug, Clone)]
+pub struct ChangeLog {
+    /// Retained events, oldest first
+    events: VecDeque<ChangeEvent>,
+    
+    /// Sequence number assigned to the next recorded event
+    next_sequence: u64,
+    
+    /// Maximum number of events to retain
+    capacity: usize,
+}
+
+impl ChangeLog {
+    /// Create a new empty change log
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            events: VecDeque::new(),
+            next_sequence: 1,
+            capacity: capacity.max(1),
+        }
+    }
+    
+    /// Create an empty log that numbers its first event `next_sequence`
+    pub fn resume(capacity: usize, next_sequence: u64) -> Self {
+        Self {
+            next_sequence: next_sequence.max(1),
+            ..Self::new(capacity)
+        }
+    }
+    
+    /// Get the sequence number the next recorded event will get
+    pub fn next_sequence(&self) -> u64 {
+        self.next_sequence
+    }
+    
+    /// Record a change and return its sequence number
+    pub fn record(
+        &mut self,
+        hive_id: &str,
+        kind: ChangeKind,
+        cell_id: Option<String>,
+        coordinates: Option<(i32, i32)>,
+        content: Option<Vec<u8>>,
+    ) -> Result<u64, HiveError> {
+        let now = determinism::unix_time();
+        
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        
+        if self.events.len() == self.capacity {
+            self.events.pop_front();
+        }
+        
+        self.events.push_back(ChangeEvent {
+            sequence,
+            hive_id: hive_id.to_string(),
+            kind,
+            cell_id,
+            coordinates,
+            content,
+            timestamp: now,
+        });
+        
+        Ok(sequence)
+    }
+    
+    /// Get all events recorded after the given sequence number
+    pub fn since(&self, sequence: u64) -> Result<Vec<ChangeEvent>, HiveError> {
+        Ok(self.iter_since(sequence)?.cloned().collect())
+    }
+    
+    /// Iterate over the events recorded after the given sequence number, without copying them
+    ///
+    /// Sequence 0 reads from the oldest retained event. Any other position
+    /// must still be covered by the log: one whose next events were
+    /// discarded, or one past the end of the log (taken from a log that
+    /// did not carry its numbering over), is reported as truncated.
+    pub fn iter_since(&self, sequence: u64) -> Result<impl Iterator<Item = &ChangeEvent> + '_, HiveError> {
+        let oldest = self.events.front().map_or(self.next_sequence, |event| event.sequence);
+        if sequence >= self.next_sequence || (sequence > 0 && sequence + 1 < oldest) {
+            return Err(HiveError::ChangeLogTruncated(sequence));
+        }
+        
+        // Sequence numbers increase, so the first newer event is found by bisection
+        let start = self.events.partition_point(|event| event.sequence <= sequence);
+        Ok(self.events.range(start..))
+    }
+    
+    /// Get the sequence number of the most recent event (0 if none)
+    pub fn last_sequence(&self) -> u64 {
+        self.next_sequence - 1
+    }
+    
+    /// Get the number of retained events
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+    
+    /// Check whether the log holds no events
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+impl Default for ChangeLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_CHANGE_LOG_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_change_log_ordering() {
+        let mut log = ChangeLog::new(10);
+        
+        let first = log.record("hive-1", ChangeKind::CellInserted, Some("a".to_string()), Some((0, 0)), None).unwrap();
+        let second = log.record("hive-1", ChangeKind::CellRemoved, Some("a".to_string()), Some((0, 0)), None).unwrap();
+        
+        assert_eq!(first, 1);
+        assert_eq!(second, 2);
+        assert_eq!(log.last_sequence(), 2);
+        
+        let events = log.since(0).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].kind, ChangeKind::CellInserted);
+        assert_eq!(events[1].kind, ChangeKind::CellRemoved);
+        
+        assert_eq!(log.since(1).unwrap().len(), 1);
+        assert!(log.since(2).unwrap().is_empty());
+    }
+    
+    #[test]
+    fn test_change_log_truncation() {
+        let mut log = ChangeLog::new(2);
+        
+        for i in 0..4 {
+            log.record("hive-1", ChangeKind::CellInserted, Some(i.to_string()), None, None).unwrap();
+        }
+        
+        assert_eq!(log.len(), 2);
+        assert_eq!(log.since(2).unwrap().len(), 2);
+        assert!(matches!(log.since(1), Err(HiveError::ChangeLogTruncated(1))));
+        assert!(matches!(log.since(5), Err(HiveError::ChangeLogTruncated(5))));
+        assert_eq!(log.since(0).unwrap().len(), 2);
+    }
+    
+    #[test]
+    fn test_change_log_resume() {
+        let mut log = ChangeLog::resume(10, 5);
+        
+        // Events from before the restart are gone, so older positions are truncated
+        assert!(log.since(0).unwrap().is_empty());
+        assert!(matches!(log.since(2), Err(HiveError::ChangeLogTruncated(2))));
+        assert!(log.since(4).unwrap().is_empty());
+        
+        assert_eq!(log.record("hive-1", ChangeKind::CellInserted, None, None, None).unwrap(), 5);
+        assert_eq!(log.since(4).unwrap().len(), 1);
+        assert_eq!(log.next_sequence(), 6);
+    }
+}
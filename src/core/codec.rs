@@ -0,0 +1,614 @@
+// HiveDB Codec Module
+//
+// This module provides the checksums, MACs and LZ4 compression used by
+// storage, backups and the network APIs. Native builds use ring and the
+// lz4 C library; builds with the `portable` feature and without the
+// `standard` one, such as wasm and embedded builds, use the pure-Rust
+// sha2, hmac and lz4_flex crates instead. Both produce the same SHA-256
+// digests and LZ4 frames, so files written by one build read in the other.
+// Cell checksums are SHA-256 by default; hives on hot write paths can use
+// CRC32C instead, which x86-64 (SSE4.2) and ARMv8 CPUs compute in hardware
+// and every other build computes from tables.
+
+use std::fmt;
+use std::io::{Read, Write};
+use serde::{Deserialize, Serialize};
+use crate::core::error::HiveError;
+
+#[cfg(not(any(feature = "ring", feature = "portable")))]
+compile_error!("HiveDB needs a checksum provider: enable the `standard` or `portable` feature");
+
+#[cfg(not(any(feature = "lz4", feature = "portable")))]
+compile_error!("HiveDB needs an LZ4 provider: enable the `standard` or `portable` feature");
+
+/// Length of a SHA-256 digest
+pub const DIGEST_LEN: usize = 32;
+
+/// Compression level of LZ4 frames written by the native provider
+#[cfg(feature = "lz4")]
+const LZ4_LEVEL: u32 = 6;
+
+/// Prefix of cell checksums made with CRC32C, which tells them from SHA-256 ones
+const CRC32C_PREFIX: &str = "crc32c:";
+
+/// Reversed CRC32C (Castagnoli) polynomial
+const CRC32C_POLYNOMIAL: u32 = 0x82f6_3b78;
+
+/// Lower-case hex digits of every byte value
+static HEX_PAIRS: [[u8; 2]; 256] = hex_pairs();
+
+/// CRC32C remainders for eight bytes at a time, for CPUs without CRC instructions
+static CRC32C_TABLES: [[u32; 256]; 8] = crc32c_tables();
+
+/// Codec that cell checksums are made with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Integrity {
+    /// SHA-256, which also tells documents apart in the document cache
+    #[default]
+    Sha256,
+    
+    /// CRC32C, much cheaper to compute; it catches corruption but is no digest
+    Crc32c,
+}
+
+/// A SHA-256 digest or HMAC-SHA256 tag
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Digest([u8; DIGEST_LEN]);
+
+impl AsRef<[u8]> for Digest {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl fmt::LowerHex for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
+impl fmt::Debug for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Digest({:x})", self)
+    }
+}
+
+impl Digest {
+    /// Copy a digest out of the bytes of a provider
+    fn from_slice(bytes: &[u8]) -> Self {
+        let mut digest = [0; DIGEST_LEN];
+        digest.copy_from_slice(bytes);
+        Self(digest)
+    }
+    
+    /// Encode this digest as lower-case hex
+    pub fn to_hex(&self) -> String {
+        to_hex(&self.0)
+    }
+}
+
+/// Encode bytes as lower-case hex, two digits at a time from a table
+pub fn to_hex(bytes: &[u8]) -> String {
+    let mut hex = Vec::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        hex.extend_from_slice(&HEX_PAIRS[*byte as usize]);
+    }
+    
+    // The table only holds ASCII digits
+    String::from_utf8(hex).unwrap_or_default()
+}
+
+/// Build the table of the hex digits of every byte value
+const fn hex_pairs() -> [[u8; 2]; 256] {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    let mut pairs = [[0; 2]; 256];
+    let mut byte = 0;
+    while byte < 256 {
+        pairs[byte] = [DIGITS[byte >> 4], DIGITS[byte & 0xf]];
+        byte += 1;
+    }
+    pairs
+}
+
+/// Get the SHA-256 digest of some data
+pub fn sha256(data: &[u8]) -> Digest {
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    hasher.finish()
+}
+
+/// Computes a SHA-256 digest over data given in parts
+pub struct Sha256 {
+    #[cfg(feature = "ring")]
+    context: ring::digest::Context,
+    
+    #[cfg(not(feature = "ring"))]
+    context: sha2::Sha256,
+}
+
+impl Sha256 {
+    /// Start a digest
+    pub fn new() -> Self {
+        Self {
+            #[cfg(feature = "ring")]
+            context: ring::digest::Context::new(&ring::digest::SHA256),
+            
+            #[cfg(not(feature = "ring"))]
+            context: <sha2::Sha256 as sha2::Digest>::new(),
+        }
+    }
+    
+    /// Add data to the digest
+    pub fn update(&mut self, data: &[u8]) {
+        #[cfg(feature = "ring")]
+        self.context.update(data);
+        
+        #[cfg(not(feature = "ring"))]
+        sha2::Digest::update(&mut self.context, data);
+    }
+    
+    /// Get the digest of everything added
+    pub fn finish(self) -> Digest {
+        #[cfg(feature = "ring")]
+        let digest = self.context.finish();
+        
+        #[cfg(not(feature = "ring"))]
+        let digest = sha2::Digest::finalize(self.context);
+        
+        Digest::from_slice(digest.as_ref())
+    }
+}
+
+impl Default for Sha256 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Get the CRC32C of some data
+pub fn crc32c(data: &[u8]) -> u32 {
+    let mut crc = Crc32c::new();
+    crc.update(data);
+    crc.finish()
+}
+
+/// Computes a CRC32C over data given in parts
+#[derive(Debug, Clone, Copy)]
+pub struct Crc32c {
+    /// Running remainder, inverted
+    state: u32,
+}
+
+impl Crc32c {
+    /// Start a CRC
+    pub fn new() -> Self {
+        Self { state: !0 }
+    }
+    
+    /// Add data to the CRC, with the CPU's CRC instructions when it has them
+    pub fn update(&mut self, data: &[u8]) {
+        #[cfg(target_arch = "x86_64")]
+        if std::arch::is_x86_feature_detected!("sse4.2") {
+            // SAFETY: the CPU was just checked for SSE4.2
+            self.state = unsafe { crc32c_sse42(self.state, data) };
+            return;
+        }
+        
+        #[cfg(target_arch = "aarch64")]
+        if std::arch::is_aarch64_feature_detected!("crc") {
+            // SAFETY: the CPU was just checked for the CRC instructions
+            self.state = unsafe { crc32c_arm(self.state, data) };
+            return;
+        }
+        
+        self.state = crc32c_tables_update(self.state, data);
+    }
+    
+    /// Get the CRC of everything added
+    pub fn finish(self) -> u32 {
+        !self.state
+    }
+}
+
+impl Default for Crc32c {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Add data to a CRC32C eight bytes at a time with SSE4.2
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse4.2")]
+fn crc32c_sse42(state: u32, data: &[u8]) -> u32 {
+    use std::arch::x86_64::{_mm_crc32_u64, _mm_crc32_u8};
+    
+    let mut chunks = data.chunks_exact(8);
+    let mut wide = state as u64;
+    for chunk in &mut chunks {
+        wide = _mm_crc32_u64(wide, u64::from_le_bytes(chunk.try_into().unwrap_or_default()));
+    }
+    chunks.remainder().iter().fold(wide as u32, |state, byte| _mm_crc32_u8(state, *byte))
+}
+
+/// Add data to a CRC32C eight bytes at a time with the ARMv8 CRC instructions
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "crc")]
+fn crc32c_arm(state: u32, data: &[u8]) -> u32 {
+    use std::arch::aarch64::{__crc32cb, __crc32cd};
+    
+    let mut chunks = data.chunks_exact(8);
+    let mut state = state;
+    for chunk in &mut chunks {
+        state = __crc32cd(state, u64::from_le_bytes(chunk.try_into().unwrap_or_default()));
+    }
+    chunks.remainder().iter().fold(state, |state, byte| __crc32cb(state, *byte))
+}
+
+/// Add data to a CRC32C eight bytes at a time from tables
+fn crc32c_tables_update(state: u32, data: &[u8]) -> u32 {
+    let tables = &CRC32C_TABLES;
+    let mut chunks = data.chunks_exact(8);
+    let mut state = state;
+    for chunk in &mut chunks {
+        let low = state ^ u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        let high = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+        state = tables[7][(low & 0xff) as usize]
+            ^ tables[6][((low >> 8) & 0xff) as usize]
+            ^ tables[5][((low >> 16) & 0xff) as usize]
+            ^ tables[4][(low >> 24) as usize]
+            ^ tables[3][(high & 0xff) as usize]
+            ^ tables[2][((high >> 8) & 0xff) as usize]
+            ^ tables[1][((high >> 16) & 0xff) as usize]
+            ^ tables[0][(high >> 24) as usize];
+    }
+    chunks.remainder().iter().fold(state, |state, byte| {
+        (state >> 8) ^ tables[0][((state ^ *byte as u32) & 0xff) as usize]
+    })
+}
+
+/// Build the tables of CRC32C remainders, the nth for a byte followed by n zero bytes
+const fn crc32c_tables() -> [[u32; 256]; 8] {
+    let mut tables = [[0; 256]; 8];
+    let mut byte = 0;
+    while byte < 256 {
+        let mut remainder = byte as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            remainder = if remainder & 1 == 1 { (remainder >> 1) ^ CRC32C_POLYNOMIAL } else { remainder >> 1 };
+            bit += 1;
+        }
+        tables[0][byte] = remainder;
+        byte += 1;
+    }
+    
+    let mut table = 1;
+    while table < 8 {
+        let mut byte = 0;
+        while byte < 256 {
+            let previous = tables[table - 1][byte];
+            tables[table][byte] = (previous >> 8) ^ tables[0][(previous & 0xff) as usize];
+            byte += 1;
+        }
+        table += 1;
+    }
+    tables
+}
+
+/// Computes a cell checksum with either integrity codec over data given in parts
+pub enum Checksummer {
+    /// Computing a SHA-256 digest
+    Sha256(Sha256),
+    
+    /// Computing a CRC32C
+    Crc32c(Crc32c),
+}
+
+impl Checksummer {
+    /// Add data to the checksum
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Checksummer::Sha256(hasher) => hasher.update(data),
+            Checksummer::Crc32c(crc) => crc.update(data),
+        }
+    }
+    
+    /// Get the checksum of everything added, as stored in a cell
+    pub fn finish(self) -> String {
+        match self {
+            Checksummer::Sha256(hasher) => hasher.finish().to_hex(),
+            Checksummer::Crc32c(crc) => CRC32C_PREFIX.to_string() + &to_hex(&crc.finish().to_be_bytes()),
+        }
+    }
+}
+
+impl Integrity {
+    /// Get the codec a cell checksum was made with
+    pub fn of_checksum(checksum: &str) -> Self {
+        if checksum.starts_with(CRC32C_PREFIX) {
+            Integrity::Crc32c
+        } else {
+            Integrity::Sha256
+        }
+    }
+    
+    /// Start a checksum over data given in parts
+    pub fn start(self) -> Checksummer {
+        match self {
+            Integrity::Sha256 => Checksummer::Sha256(Sha256::new()),
+            Integrity::Crc32c => Checksummer::Crc32c(Crc32c::new()),
+        }
+    }
+    
+    /// Get the checksum of some data, as stored in a cell
+    pub fn checksum(self, data: &[u8]) -> String {
+        let mut checksummer = self.start();
+        checksummer.update(data);
+        checksummer.finish()
+    }
+}
+
+impl fmt::Display for Integrity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Integrity::Sha256 => write!(f, "sha256"),
+            Integrity::Crc32c => write!(f, "crc32c"),
+        }
+    }
+}
+
+impl std::str::FromStr for Integrity {
+    type Err = HiveError;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "sha256" => Ok(Integrity::Sha256),
+            "crc32c" => Ok(Integrity::Crc32c),
+            _ => Err(HiveError::GenericError(format!("Unknown integrity codec '{}', expected sha256 or crc32c", s))),
+        }
+    }
+}
+
+/// Computes an HMAC-SHA256 tag over data given in parts
+pub struct HmacSha256 {
+    #[cfg(feature = "ring")]
+    context: ring::hmac::Context,
+    
+    #[cfg(not(feature = "ring"))]
+    context: hmac::Hmac<sha2::Sha256>,
+}
+
+impl HmacSha256 {
+    /// Start a tag with a key of any length
+    pub fn new(key: &[u8]) -> Self {
+        Self {
+            #[cfg(feature = "ring")]
+            context: ring::hmac::Context::with_key(&ring::hmac::Key::new(ring::hmac::HMAC_SHA256, key)),
+            
+            #[cfg(not(feature = "ring"))]
+            context: <hmac::Hmac<sha2::Sha256> as hmac::Mac>::new_from_slice(key)
+                .expect("HMAC accepts keys of any length"),
+        }
+    }
+    
+    /// Add data to the tag
+    pub fn update(&mut self, data: &[u8]) {
+        #[cfg(feature = "ring")]
+        self.context.update(data);
+        
+        #[cfg(not(feature = "ring"))]
+        hmac::Mac::update(&mut self.context, data);
+    }
+    
+    /// Get the tag of everything added
+    pub fn finish(self) -> Digest {
+        #[cfg(feature = "ring")]
+        let tag = self.context.sign();
+        
+        #[cfg(not(feature = "ring"))]
+        let tag = hmac::Mac::finalize(self.context).into_bytes();
+        
+        Digest::from_slice(tag.as_ref())
+    }
+}
+
+/// Get the HMAC-SHA256 tag of some data
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> Digest {
+    let mut context = HmacSha256::new(key);
+    context.update(data);
+    context.finish()
+}
+
+/// Check an HMAC-SHA256 tag in constant time
+pub fn verify_hmac_sha256(key: &[u8], data: &[u8], tag: &[u8]) -> bool {
+    constant_time_eq(hmac_sha256(key, data).as_ref(), tag)
+}
+
+/// Compare secrets in a time that depends only on their lengths
+#[cfg(feature = "ring")]
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    ring::constant_time::verify_slices_are_equal(a, b).is_ok()
+}
+
+/// Compare secrets in a time that depends only on their lengths
+#[cfg(not(feature = "ring"))]
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Compresses data written to it into an LZ4 frame
+pub struct Lz4Encoder<W: Write> {
+    #[cfg(feature = "lz4")]
+    encoder: lz4::Encoder<W>,
+    
+    #[cfg(not(feature = "lz4"))]
+    encoder: lz4_flex::frame::FrameEncoder<W>,
+}
+
+impl<W: Write> Lz4Encoder<W> {
+    /// Start a frame written to a writer
+    pub fn new(writer: W) -> Result<Self, HiveError> {
+        #[cfg(feature = "lz4")]
+        let encoder = lz4::EncoderBuilder::new()
+            .level(LZ4_LEVEL)
+            .build(writer)
+            .map_err(|e| HiveError::CompressionError(e.to_string()))?;
+        
+        #[cfg(not(feature = "lz4"))]
+        let encoder = lz4_flex::frame::FrameEncoder::new(writer);
+        
+        Ok(Self { encoder })
+    }
+    
+    /// End the frame and get the writer back
+    pub fn finish(self) -> Result<W, HiveError> {
+        #[cfg(feature = "lz4")]
+        let (writer, result) = self.encoder.finish();
+        #[cfg(feature = "lz4")]
+        let writer = result.map(|()| writer);
+        
+        #[cfg(not(feature = "lz4"))]
+        let writer = self.encoder.finish();
+        
+        writer.map_err(|e| HiveError::CompressionError(e.to_string()))
+    }
+}
+
+impl<W: Write> Write for Lz4Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.encoder.write(buf)
+    }
+    
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.encoder.flush()
+    }
+}
+
+/// Compress data into an LZ4 frame
+pub fn lz4_compress(content: &[u8]) -> Result<Vec<u8>, HiveError> {
+    let mut encoder = Lz4Encoder::new(Vec::new())?;
+    encoder.write_all(content)
+        .map_err(|e| HiveError::CompressionError(e.to_string()))?;
+    encoder.finish()
+}
+
+/// Decompresses an LZ4 frame read from a reader
+pub struct Lz4Decoder<R: Read> {
+    #[cfg(feature = "lz4")]
+    decoder: lz4::Decoder<R>,
+    
+    #[cfg(not(feature = "lz4"))]
+    decoder: lz4_flex::frame::FrameDecoder<R>,
+}
+
+impl<R: Read> Lz4Decoder<R> {
+    /// Start reading a frame from a reader
+    pub fn new(reader: R) -> Result<Self, HiveError> {
+        #[cfg(feature = "lz4")]
+        let decoder = lz4::Decoder::new(reader)
+            .map_err(|e| HiveError::DecompressionError(e.to_string()))?;
+        
+        #[cfg(not(feature = "lz4"))]
+        let decoder = lz4_flex::frame::FrameDecoder::new(reader);
+        
+        Ok(Self { decoder })
+    }
+}
+
+impl<R: Read> Read for Lz4Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.decoder.read(buf)
+    }
+}
+
+/// Decompress an LZ4 frame
+pub fn lz4_decompress(content: &[u8]) -> Result<Vec<u8>, HiveError> {
+    let mut decompressed = Vec::new();
+    Lz4Decoder::new(content)?
+        .read_to_end(&mut decompressed)
+        .map_err(|e| HiveError::DecompressionError(e.to_string()))?;
+    Ok(decompressed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    
+    #[test]
+    fn test_digests() {
+        assert_eq!(
+            format!("{:x}", sha256(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        
+        let mut hasher = Sha256::new();
+        hasher.update(b"a");
+        hasher.update(b"bc");
+        assert_eq!(hasher.finish(), sha256(b"abc"));
+        
+        // RFC 4231, test case 2
+        let tag = hmac_sha256(b"Jefe", b"what do ya want for nothing?");
+        assert_eq!(format!("{:x}", tag), "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+        assert!(verify_hmac_sha256(b"Jefe", b"what do ya want for nothing?", tag.as_ref()));
+        assert!(!verify_hmac_sha256(b"Jefe", b"what do ya want for something?", tag.as_ref()));
+        assert!(!constant_time_eq(b"abc", b"ab"));
+    }
+    
+    #[test]
+    fn test_hex_and_crc32c() {
+        assert_eq!(to_hex(&[0x00, 0x0f, 0xa5, 0xff]), "000fa5ff");
+        assert_eq!(sha256(b"abc").to_hex(), format!("{:x}", sha256(b"abc")));
+        
+        // RFC 3720, appendix B.4
+        assert_eq!(crc32c(b""), 0);
+        assert_eq!(crc32c(b"123456789"), 0xe306_9283);
+        assert_eq!(crc32c(&[0; 32]), 0x8a91_36aa);
+        assert_eq!(crc32c(&[0xff; 32]), 0x62a8_ab43);
+        
+        assert_eq!(Integrity::Crc32c.checksum(b"123456789"), "crc32c:e3069283");
+        assert_eq!(Integrity::Sha256.checksum(b"abc"), sha256(b"abc").to_hex());
+        assert_eq!(Integrity::of_checksum("crc32c:e3069283"), Integrity::Crc32c);
+        assert_eq!(Integrity::of_checksum(&sha256(b"abc").to_hex()), Integrity::Sha256);
+        assert_eq!("CRC32C".parse::<Integrity>().unwrap(), Integrity::Crc32c);
+        assert!("md5".parse::<Integrity>().is_err());
+    }
+    
+    #[test]
+    fn test_lz4_round_trip() {
+        let content = b"hexagonal cells hold documents ".repeat(64);
+        let compressed = lz4_compress(&content).unwrap();
+        assert!(compressed.len() < content.len());
+        assert_eq!(lz4_decompress(&compressed).unwrap(), content);
+        assert!(lz4_decompress(b"not a frame").is_err());
+    }
+    
+    proptest! {
+        #[test]
+        fn prop_lz4_round_trip(content in prop::collection::vec(any::<u8>(), 0..8192)) {
+            let compressed = lz4_compress(&content).unwrap();
+            prop_assert_eq!(lz4_decompress(&compressed).unwrap(), content);
+        }
+        
+        #[test]
+        fn prop_digest_in_parts(content in prop::collection::vec(any::<u8>(), 0..4096), split in any::<prop::sample::Index>()) {
+            let (head, tail) = content.split_at(split.index(content.len() + 1));
+            let mut hasher = Sha256::new();
+            hasher.update(head);
+            hasher.update(tail);
+            prop_assert_eq!(hasher.finish(), sha256(&content));
+        }
+        
+        #[test]
+        fn prop_crc32c_in_parts(content in prop::collection::vec(any::<u8>(), 0..4096), split in any::<prop::sample::Index>()) {
+            let (head, tail) = content.split_at(split.index(content.len() + 1));
+            let mut crc = Crc32c::new();
+            crc.update(head);
+            crc.update(tail);
+            prop_assert_eq!(crc.finish(), crc32c(&content));
+            
+            // The hardware and table paths agree
+            prop_assert_eq!(!crc32c_tables_update(!0, &content), crc32c(&content));
+        }
+    }
+}
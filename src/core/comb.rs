@@ -0,0 +1,246 @@
+// HiveDB Comb Module
+//
+// This module reads and writes honeycomb archives (`.comb` files), which
+// carry a whole hive in one portable file. The archive holds the hive's
+// settings and schema, then its cells one record at a time, so large hives
+// are exported and imported as a stream instead of as one document.
+// Indexes travel as their definitions in the schema and are rebuilt on
+// import. After a short header the records are compressed as a single LZ4
+// frame, and the archive ends with the number of records and a SHA-256
+// digest of them, so a truncated or damaged archive is rejected.
+
+use std::io::{Read, Write};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use crate::core::codec::{self, Lz4Decoder, Lz4Encoder, Sha256, DIGEST_LEN};
+use crate::core::error::HiveError;
+
+/// Bytes every honeycomb archive starts with
+pub const MAGIC: [u8; 4] = *b"COMB";
+
+/// Version of the archive format written by this release
+pub const FORMAT_VERSION: u8 = 1;
+
+/// Length of the header: magic, format version and reserved bytes
+const HEADER_LEN: usize = 8;
+
+/// Length of a record header: kind and body length
+const RECORD_HEADER_LEN: usize = 5;
+
+/// Largest record body read, so a damaged length is not allocated
+const MAX_RECORD_LEN: usize = 1 << 30;
+
+/// What a record holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordKind {
+    /// The hive without its cells
+    Manifest,
+    
+    /// One cell
+    Cell,
+    
+    /// The number of records before it; the digest follows
+    End,
+}
+
+impl RecordKind {
+    fn id(self) -> u8 {
+        match self {
+            RecordKind::Manifest => 1,
+            RecordKind::Cell => 2,
+            RecordKind::End => 0,
+        }
+    }
+    
+    fn from_id(id: u8) -> Result<Self, HiveError> {
+        match id {
+            1 => Ok(RecordKind::Manifest),
+            2 => Ok(RecordKind::Cell),
+            0 => Ok(RecordKind::End),
+            other => Err(HiveError::DeserializationError(format!("Unknown honeycomb record kind {}", other))),
+        }
+    }
+}
+
+/// Writes a honeycomb archive record by record
+pub struct CombWriter<W: Write> {
+    /// Compresses the records into the writer
+    encoder: Lz4Encoder<W>,
+    
+    /// Digest of the records written so far
+    hasher: Sha256,
+    
+    /// Number of records written so far
+    records: u64,
+}
+
+impl<W: Write> CombWriter<W> {
+    /// Start an archive
+    pub fn new(mut writer: W) -> Result<Self, HiveError> {
+        let mut header = [0; HEADER_LEN];
+        header[..4].copy_from_slice(&MAGIC);
+        header[4] = FORMAT_VERSION;
+        writer.write_all(&header)
+            .map_err(|e| HiveError::IoError(e.to_string()))?;
+        
+        Ok(Self {
+            encoder: Lz4Encoder::new(writer)?,
+            hasher: Sha256::new(),
+            records: 0,
+        })
+    }
+    
+    /// Write a record holding a value as JSON
+    pub fn write_record<T: Serialize>(&mut self, kind: RecordKind, value: &T) -> Result<(), HiveError> {
+        let body = serde_json::to_vec(value)
+            .map_err(|e| HiveError::SerializationError(e.to_string()))?;
+        self.write_raw(kind, &body)?;
+        self.records += 1;
+        Ok(())
+    }
+    
+    /// End the archive and get the writer back
+    pub fn finish(mut self) -> Result<W, HiveError> {
+        self.write_raw(RecordKind::End, &self.records.to_le_bytes())?;
+        let digest = self.hasher.finish();
+        self.encoder.write_all(digest.as_ref())
+            .map_err(|e| HiveError::CompressionError(e.to_string()))?;
+        self.encoder.finish()
+    }
+    
+    fn write_raw(&mut self, kind: RecordKind, body: &[u8]) -> Result<(), HiveError> {
+        let len = u32::try_from(body.len())
+            .ok()
+            .filter(|len| *len as usize <= MAX_RECORD_LEN)
+            .ok_or_else(|| HiveError::SerializationError(format!("Honeycomb record of {} bytes is too large", body.len())))?;
+        
+        let mut header = [0; RECORD_HEADER_LEN];
+        header[0] = kind.id();
+        header[1..].copy_from_slice(&len.to_le_bytes());
+        self.hasher.update(&header);
+        self.hasher.update(body);
+        
+        self.encoder.write_all(&header)
+            .and_then(|()| self.encoder.write_all(body))
+            .map_err(|e| HiveError::CompressionError(e.to_string()))
+    }
+}
+
+/// Reads a honeycomb archive record by record
+pub struct CombReader<R: Read> {
+    /// Decompresses the records from the reader
+    decoder: Lz4Decoder<R>,
+    
+    /// Digest of the records read so far (None once the end is checked)
+    hasher: Option<Sha256>,
+    
+    /// Number of records read so far
+    records: u64,
+}
+
+impl<R: Read> CombReader<R> {
+    /// Start reading an archive, checking its header
+    pub fn new(mut reader: R) -> Result<Self, HiveError> {
+        let mut header = [0; HEADER_LEN];
+        reader.read_exact(&mut header)
+            .map_err(|_| HiveError::DeserializationError("Not a honeycomb archive".to_string()))?;
+        if header[..4] != MAGIC {
+            return Err(HiveError::DeserializationError("Not a honeycomb archive".to_string()));
+        }
+        if header[4] > FORMAT_VERSION {
+            return Err(HiveError::DeserializationError(format!(
+                "Honeycomb archive format version {} is newer than the supported version {}",
+                header[4], FORMAT_VERSION
+            )));
+        }
+        
+        Ok(Self {
+            decoder: Lz4Decoder::new(reader)?,
+            hasher: Some(Sha256::new()),
+            records: 0,
+        })
+    }
+    
+    /// Read the next record, or None at the end of a complete archive
+    pub fn next_record(&mut self) -> Result<Option<(RecordKind, Vec<u8>)>, HiveError> {
+        let Some(hasher) = self.hasher.as_mut() else {
+            return Ok(None);
+        };
+        
+        let mut header = [0; RECORD_HEADER_LEN];
+        read_exact(&mut self.decoder, &mut header)?;
+        let kind = RecordKind::from_id(header[0])?;
+        let len = u32::from_le_bytes([header[1], header[2], header[3], header[4]]) as usize;
+        if len > MAX_RECORD_LEN {
+            return Err(HiveError::DeserializationError(format!("Honeycomb record of {} bytes is too large", len)));
+        }
+        
+        let mut body = vec![0; len];
+        read_exact(&mut self.decoder, &mut body)?;
+        hasher.update(&header);
+        hasher.update(&body);
+        if kind != RecordKind::End {
+            self.records += 1;
+            return Ok(Some((kind, body)));
+        }
+        
+        // The end record counts the records, and the digest covers them all
+        let digest = self.hasher.take().map(Sha256::finish);
+        let mut expected = [0; DIGEST_LEN];
+        read_exact(&mut self.decoder, &mut expected)?;
+        let counted = body.try_into().map(u64::from_le_bytes).ok();
+        if counted != Some(self.records) || !digest.is_some_and(|digest| codec::constant_time_eq(digest.as_ref(), &expected)) {
+            return Err(HiveError::DeserializationError("Honeycomb archive is damaged".to_string()));
+        }
+        Ok(None)
+    }
+    
+    /// Read the next record as a value of the given kind, or None at the end
+    pub fn read<T: DeserializeOwned>(&mut self, kind: RecordKind) -> Result<Option<T>, HiveError> {
+        match self.next_record()? {
+            Some((found, body)) if found == kind => serde_json::from_slice(&body)
+                .map(Some)
+                .map_err(|e| HiveError::DeserializationError(e.to_string())),
+            Some((found, _)) => Err(HiveError::DeserializationError(format!(
+                "Expected a {:?} record in the honeycomb archive, found a {:?} record", kind, found
+            ))),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Fill a buffer from the archive, reporting a short read as truncation
+fn read_exact(reader: &mut impl Read, buf: &mut [u8]) -> Result<(), HiveError> {
+    reader.read_exact(buf).map_err(|e| match e.kind() {
+        std::io::ErrorKind::UnexpectedEof => HiveError::DeserializationError("Honeycomb archive is truncated".to_string()),
+        _ => HiveError::DecompressionError(e.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_comb_records() {
+        let mut writer = CombWriter::new(Vec::new()).unwrap();
+        writer.write_record(RecordKind::Manifest, &"hive").unwrap();
+        for n in 0..3 {
+            writer.write_record(RecordKind::Cell, &n).unwrap();
+        }
+        let data = writer.finish().unwrap();
+        assert_eq!(data[..4], MAGIC);
+        
+        let mut reader = CombReader::new(&data[..]).unwrap();
+        assert_eq!(reader.read::<String>(RecordKind::Manifest).unwrap().unwrap(), "hive");
+        let cells: Vec<u32> = std::iter::from_fn(|| reader.read(RecordKind::Cell).transpose()).collect::<Result<_, _>>().unwrap();
+        assert_eq!(cells, vec![0, 1, 2]);
+        assert!(reader.next_record().unwrap().is_none());
+        
+        // Records in the wrong place, a cut archive or another file are refused
+        assert!(CombReader::new(&data[..]).unwrap().read::<u32>(RecordKind::Cell).is_err());
+        let mut truncated = CombReader::new(&data[..data.len() - 4]).unwrap();
+        assert!(std::iter::from_fn(|| truncated.next_record().transpose()).any(|record| record.is_err()));
+        assert!(CombReader::new(&b"HVBK\x02\0\0\0"[..]).is_err());
+    }
+}
@@ -0,0 +1,176 @@
+// HiveDB Commit Module
+//
+// This module batches the saves that make concurrent writes durable (group
+// commit). Under the `Always` durability policy a write is acknowledged only
+// once the hive is saved. Rather than every writer saving and syncing the
+// hive in turn, a writer that finds a save in progress waits for it to end,
+// and the next save covers every write made in the meantime, so many
+// writers share one sync.
+
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use crate::core::durability::Durability;
+use crate::core::error::HiveError;
+use crate::core::hive::Hive;
+
+/// Tracks the saves of one hive and the writers waiting for them
+#[derive(Debug, Default)]
+pub struct GroupCommit {
+    /// Saved version and whether a save is running
+    state: Mutex<CommitState>,
+    
+    /// Signalled when a save ends
+    saved: Condvar,
+}
+
+/// Progress of the saves of a hive
+#[derive(Debug, Default)]
+struct CommitState {
+    /// Highest version of the hive that has been saved
+    saved_version: u64,
+    
+    /// Whether a writer is saving the hive for the others
+    saving: bool,
+    
+    /// Number of saves made by writers
+    saves: u64,
+}
+
+/// A writer's claim on a save covering its writes, taken while it holds the hive
+#[derive(Debug)]
+#[must_use = "a commit ticket does nothing until waited on"]
+pub struct CommitTicket {
+    /// The saves of the hive written to
+    group: Arc<GroupCommit>,
+    
+    /// Version of the hive after the writes
+    version: u64,
+    
+    /// Durability policy of the hive
+    durability: Durability,
+}
+
+impl GroupCommit {
+    /// Track the saves of a hive saved up to some version
+    pub fn new(saved_version: u64) -> Self {
+        Self {
+            state: Mutex::new(CommitState { saved_version, ..CommitState::default() }),
+            saved: Condvar::new(),
+        }
+    }
+    
+    /// Get the highest version of the hive that has been saved
+    pub fn saved_version(&self) -> u64 {
+        self.state.lock().map_or(0, |state| state.saved_version)
+    }
+    
+    /// Get the number of saves writers made, however many writes each covered
+    pub fn saves(&self) -> u64 {
+        self.state.lock().map_or(0, |state| state.saves)
+    }
+    
+    /// Record that the hive was saved at some version
+    pub(crate) fn record_save(&self, version: u64) {
+        if let Ok(mut state) = self.state.lock() {
+            state.saved_version = state.saved_version.max(version);
+        }
+        self.saved.notify_all();
+    }
+    
+    /// Wait until the hive is saved at `version` or later, saving it if no other writer is
+    ///
+    /// The hive must not be locked by the caller.
+    pub fn wait(&self, hive: &RwLock<Hive>, version: u64) -> Result<(), HiveError> {
+        let mut state = self.state.lock().map_err(|_| HiveError::LockError)?;
+        loop {
+            if state.saved_version >= version {
+                return Ok(());
+            }
+            if !state.saving {
+                break;
+            }
+            state = self.saved.wait(state).map_err(|_| HiveError::LockError)?;
+        }
+        
+        // Save for everyone who wrote up to now
+        state.saving = true;
+        state.saves += 1;
+        drop(state);
+        
+        let result = hive.read().map_err(|_| HiveError::LockError).and_then(|hive| hive.save());
+        
+        // The save recorded its version; waiters retry if it failed
+        self.state.lock().map_err(|_| HiveError::LockError)?.saving = false;
+        self.saved.notify_all();
+        result
+    }
+}
+
+impl CommitTicket {
+    /// Claim a save covering a hive's writes so far
+    pub fn new(hive: &Hive) -> Self {
+        Self {
+            group: hive.group_commit(),
+            version: hive.metadata.version,
+            durability: hive.durability,
+        }
+    }
+    
+    /// Return once the writes are as durable as the hive's policy asks
+    ///
+    /// Under `Always` this waits for a save covering the writes; other
+    /// policies leave the save to the periodic flush or to shutdown.
+    pub fn wait(self, hive: &RwLock<Hive>) -> Result<(), HiveError> {
+        match self.durability {
+            Durability::Always => self.group.wait(hive, self.version),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::cell::{Cell, CellDataType};
+    use crate::core::hive::HiveManager;
+    use tempfile::tempdir;
+    
+    #[test]
+    fn test_concurrent_writers_share_saves() {
+        let temp_dir = tempdir().unwrap();
+        let mut manager = HiveManager::new(temp_dir.path().to_path_buf()).unwrap()
+            .with_durability(Durability::Always);
+        let id = manager.create_hive("orders".to_string(), String::new(), "test-user".to_string(), (8, 8)).unwrap();
+        let hive_arc = manager.get_hive(&id).unwrap();
+        
+        let writers: Vec<_> = (0..8)
+            .map(|i| {
+                let hive_arc = hive_arc.clone();
+                std::thread::spawn(move || {
+                    let ticket = {
+                        let mut hive = hive_arc.write().unwrap();
+                        let cell = Cell::new(format!("o{}", i), (i, 0), CellDataType::Json, b"{}".to_vec(), false).unwrap();
+                        hive.add_cell(cell).unwrap();
+                        CommitTicket::new(&hive)
+                    };
+                    ticket.wait(&hive_arc).unwrap();
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        
+        // Every write was saved, by at most one save per writer
+        let hive = hive_arc.read().unwrap();
+        assert!(!hive.is_dirty());
+        let group = hive.group_commit();
+        assert_eq!(group.saved_version(), hive.metadata.version);
+        assert!((1..=8).contains(&group.saves()));
+        drop(hive);
+        drop(manager);
+        
+        let mut reloaded = HiveManager::new(temp_dir.path().to_path_buf()).unwrap();
+        reloaded.load_all().unwrap();
+        assert_eq!(reloaded.get_hive(&id).unwrap().read().unwrap().cell_count(), 8);
+    }
+}
@@ -0,0 +1,142 @@
+// HiveDB Compression Module
+//
+// This module trains zstd dictionaries over samples of a hive's documents.
+// Small JSON documents share most of their field names and structure, which
+// per-cell LZ4 cannot exploit; a dictionary holds that shared material once
+// so each cell only stores what is particular to it. Cells name the
+// dictionary they were compressed with, and every loaded dictionary is kept
+// in a process-wide registry so cells can be read without their hive.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::{Arc, OnceLock, RwLock};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use crate::core::codec;
+use crate::core::error::HiveError;
+
+/// Default maximum size of a trained dictionary
+pub const DEFAULT_DICTIONARY_SIZE: usize = 16 * 1024;
+
+/// Default number of documents sampled to train a dictionary
+pub const DEFAULT_SAMPLE_COUNT: usize = 1_000;
+
+/// Fewest samples zstd can train a useful dictionary from
+pub const MIN_SAMPLE_COUNT: usize = 16;
+
+/// Compression level used with dictionaries
+const LEVEL: i32 = 3;
+
+/// The dictionaries loaded in this process, by ID
+static DICTIONARIES: OnceLock<RwLock<HashMap<u32, Arc<CompressionDictionary>>>> = OnceLock::new();
+
+/// A trained zstd dictionary
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompressionDictionary {
+    /// ID derived from the dictionary content, so it is stable across restarts
+    pub id: u32,
+    
+    /// Number of documents the dictionary was trained on
+    pub samples: usize,
+    
+    /// The dictionary itself
+    pub data: Bytes,
+}
+
+impl CompressionDictionary {
+    /// Wrap trained dictionary content
+    pub fn new(data: Bytes, samples: usize) -> Self {
+        let digest = codec::sha256(&data);
+        let mut id = [0u8; 4];
+        id.copy_from_slice(&digest.as_ref()[..4]);
+        
+        Self {
+            id: u32::from_be_bytes(id),
+            samples,
+            data,
+        }
+    }
+    
+    /// Compress content with this dictionary
+    pub fn compress(&self, content: &[u8]) -> Result<Vec<u8>, HiveError> {
+        zstd::bulk::Compressor::with_dictionary(LEVEL, &self.data)
+            .and_then(|mut compressor| compressor.compress(content))
+            .map_err(|e| HiveError::CompressionError(e.to_string()))
+    }
+    
+    /// Decompress content compressed with this dictionary
+    pub fn decompress(&self, content: &[u8]) -> Result<Vec<u8>, HiveError> {
+        let mut decompressed = Vec::new();
+        zstd::stream::Decoder::with_dictionary(content, &self.data)
+            .and_then(|mut decoder| decoder.read_to_end(&mut decompressed))
+            .map_err(|e| HiveError::DecompressionError(e.to_string()))?;
+        
+        Ok(decompressed)
+    }
+}
+
+/// Train a dictionary of at most `max_size` bytes over sample documents
+pub fn train<S: AsRef<[u8]>>(samples: &[S], max_size: usize) -> Result<CompressionDictionary, HiveError> {
+    if samples.len() < MIN_SAMPLE_COUNT {
+        return Err(HiveError::CompressionError(format!(
+            "Training a dictionary needs at least {} documents, found {}",
+            MIN_SAMPLE_COUNT, samples.len()
+        )));
+    }
+    
+    let data = zstd::dict::from_samples(samples, max_size)
+        .map_err(|e| HiveError::CompressionError(format!("Cannot train a dictionary: {}", e)))?;
+    Ok(CompressionDictionary::new(Bytes::from(data), samples.len()))
+}
+
+/// Make a dictionary available to every cell compressed with it
+pub fn register(dictionary: CompressionDictionary) -> Result<Arc<CompressionDictionary>, HiveError> {
+    let dictionary = Arc::new(dictionary);
+    registry().write().map_err(|_| HiveError::LockError)?
+        .insert(dictionary.id, dictionary.clone());
+    Ok(dictionary)
+}
+
+/// Get a loaded dictionary by ID
+pub fn dictionary(id: u32) -> Result<Arc<CompressionDictionary>, HiveError> {
+    registry().read().map_err(|_| HiveError::LockError)?
+        .get(&id)
+        .cloned()
+        .ok_or_else(|| HiveError::DecompressionError(format!("Dictionary {:08x} is not loaded", id)))
+}
+
+/// Get the registry of loaded dictionaries
+fn registry() -> &'static RwLock<HashMap<u32, Arc<CompressionDictionary>>> {
+    DICTIONARIES.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn samples() -> Vec<Vec<u8>> {
+        (0..200)
+            .map(|i| format!(
+                "{{\"customer\": \"customer-{}\", \"status\": \"{}\", \"items\": [{{\"sku\": \"SKU-{:04}\", \"quantity\": {}}}]}}",
+                i % 37, ["pending", "shipped", "delivered"][i % 3], i * 7 % 1000, i % 5 + 1
+            ).into_bytes())
+            .collect()
+    }
+    
+    #[test]
+    fn test_train_and_round_trip() {
+        let samples = samples();
+        let dictionary = train(&samples, 4 * 1024).unwrap();
+        assert_eq!(dictionary.samples, samples.len());
+        assert_eq!(CompressionDictionary::new(dictionary.data.clone(), 0).id, dictionary.id);
+        
+        let document = br#"{"customer": "customer-99", "status": "shipped", "items": [{"sku": "SKU-0042", "quantity": 2}]}"#;
+        let compressed = dictionary.compress(document).unwrap();
+        assert!(compressed.len() < document.len());
+        assert_eq!(dictionary.decompress(&compressed).unwrap(), document.to_vec());
+        
+        let id = register(dictionary).unwrap().id;
+        assert_eq!(super::dictionary(id).unwrap().decompress(&compressed).unwrap(), document.to_vec());
+        assert!(train(&samples[..3], 1024).is_err());
+    }
+}
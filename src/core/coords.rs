@@ -0,0 +1,205 @@
+// HiveDB Coordinates Module
+//
+// This module converts between the coordinate systems used for hexagonal
+// grids. Cells store axial coordinates `(q, r)`; cube coordinates add the
+// third axis `s = -q - r` so distances and rotations are symmetric, and
+// offset coordinates number the columns and rows of a rectangular map,
+// shifting odd rows half a cell to the right ("odd-r").
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use crate::core::error::HiveError;
+
+/// Axial coordinates, as cells store them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Axial {
+    /// Column axis
+    pub q: i32,
+    
+    /// Row axis
+    pub r: i32,
+}
+
+/// Cube coordinates, whose three axes always sum to zero
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Cube {
+    /// First axis, the same as the axial column
+    pub q: i32,
+    
+    /// Second axis, the same as the axial row
+    pub r: i32,
+    
+    /// Third axis, `-q - r`
+    pub s: i32,
+}
+
+/// Offset coordinates of a rectangular map where odd rows are shifted right
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Offset {
+    /// Column within the row
+    pub col: i32,
+    
+    /// Row
+    pub row: i32,
+}
+
+/// Where a grid puts its coordinates `(0, 0)`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GridOrigin {
+    /// At a corner, so coordinates run from zero up to the dimensions
+    #[default]
+    Corner,
+    
+    /// At the middle, so coordinates run from minus to plus half the dimensions
+    Center,
+}
+
+impl Axial {
+    /// Create axial coordinates
+    pub fn new(q: i32, r: i32) -> Self {
+        Self { q, r }
+    }
+    
+    /// Get the number of steps to other coordinates
+    pub fn distance(self, other: Axial) -> i32 {
+        Cube::from(self).distance(Cube::from(other))
+    }
+}
+
+impl Cube {
+    /// Create cube coordinates, which must sum to zero
+    pub fn new(q: i32, r: i32, s: i32) -> Result<Self, HiveError> {
+        if q + r + s != 0 {
+            return Err(HiveError::GenericError(format!("Cube coordinates ({}, {}, {}) do not sum to zero", q, r, s)));
+        }
+        Ok(Self { q, r, s })
+    }
+    
+    /// Get the number of steps to other coordinates
+    pub fn distance(self, other: Cube) -> i32 {
+        ((self.q - other.q).abs() + (self.r - other.r).abs() + (self.s - other.s).abs()) / 2
+    }
+}
+
+impl Offset {
+    /// Create offset coordinates
+    pub fn new(col: i32, row: i32) -> Self {
+        Self { col, row }
+    }
+}
+
+impl GridOrigin {
+    /// Get the lowest coordinates of a grid with this origin
+    pub fn min(self, dimensions: (usize, usize)) -> (i32, i32) {
+        match self {
+            GridOrigin::Corner => (0, 0),
+            GridOrigin::Center => (-(dimensions.0 as i32 / 2), -(dimensions.1 as i32 / 2)),
+        }
+    }
+}
+
+impl From<(i32, i32)> for Axial {
+    fn from((q, r): (i32, i32)) -> Self {
+        Self { q, r }
+    }
+}
+
+impl From<Axial> for (i32, i32) {
+    fn from(axial: Axial) -> Self {
+        (axial.q, axial.r)
+    }
+}
+
+impl From<Axial> for Cube {
+    fn from(axial: Axial) -> Self {
+        Self { q: axial.q, r: axial.r, s: -axial.q - axial.r }
+    }
+}
+
+impl From<Cube> for Axial {
+    fn from(cube: Cube) -> Self {
+        Self { q: cube.q, r: cube.r }
+    }
+}
+
+impl From<Axial> for Offset {
+    fn from(axial: Axial) -> Self {
+        Self { col: axial.q + (axial.r - (axial.r & 1)) / 2, row: axial.r }
+    }
+}
+
+impl From<Offset> for Axial {
+    fn from(offset: Offset) -> Self {
+        Self { q: offset.col - (offset.row - (offset.row & 1)) / 2, r: offset.row }
+    }
+}
+
+impl From<Cube> for Offset {
+    fn from(cube: Cube) -> Self {
+        Axial::from(cube).into()
+    }
+}
+
+impl From<Offset> for Cube {
+    fn from(offset: Offset) -> Self {
+        Axial::from(offset).into()
+    }
+}
+
+impl fmt::Display for GridOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GridOrigin::Corner => write!(f, "corner"),
+            GridOrigin::Center => write!(f, "center"),
+        }
+    }
+}
+
+impl std::str::FromStr for GridOrigin {
+    type Err = HiveError;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "corner" => Ok(GridOrigin::Corner),
+            "center" => Ok(GridOrigin::Center),
+            _ => Err(HiveError::GenericError(format!("Unknown grid origin '{}', expected corner or center", s))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_conversions() {
+        for r in -3..=3 {
+            for q in -3..=3 {
+                let axial = Axial::new(q, r);
+                let cube = Cube::from(axial);
+                assert_eq!(cube.q + cube.r + cube.s, 0);
+                assert_eq!(Axial::from(cube), axial);
+                assert_eq!(Axial::from(Offset::from(axial)), axial);
+                assert_eq!(Offset::from(Cube::from(Offset::from(axial))), Offset::from(axial));
+            }
+        }
+        
+        // Odd rows shift, so the same column sits half a cell further right
+        assert_eq!(Offset::from(Axial::new(0, 1)), Offset::new(0, 1));
+        assert_eq!(Offset::from(Axial::new(-1, 2)), Offset::new(0, 2));
+        assert_eq!(Offset::from(Axial::new(2, -3)), Offset::new(0, -3));
+        
+        assert_eq!(Axial::new(0, 0).distance(Axial::new(2, -1)), 2);
+        assert_eq!(Cube::new(1, -2, 1).unwrap().distance(Cube::new(-1, 0, 1).unwrap()), 2);
+        assert!(Cube::new(1, 1, 1).is_err());
+    }
+    
+    #[test]
+    fn test_grid_origin() {
+        assert_eq!(GridOrigin::Corner.min((5, 4)), (0, 0));
+        assert_eq!(GridOrigin::Center.min((5, 4)), (-2, -2));
+        assert_eq!("center".parse::<GridOrigin>().unwrap(), GridOrigin::Center);
+        assert!("middle".parse::<GridOrigin>().is_err());
+    }
+}
@@ -0,0 +1,244 @@
+// HiveDB Disk Module
+//
+// This module watches the free space of the volume holding the data
+// directory. Below a warning threshold it logs warnings; below the minimum
+// it refuses client writes with `DiskSpaceLow` until space is freed, so the
+// changes already accepted can still be saved instead of a save running the
+// disk out. Free space is read with `df`, so it is only monitored on Unix.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use serde::{Deserialize, Serialize};
+use crate::core::error::HiveError;
+use log::{error, info, warn};
+
+/// Default free space below which writes are refused (256 MiB)
+pub const DEFAULT_MIN_FREE: u64 = 256 << 20;
+
+/// Free space recorded before the first reading
+const UNKNOWN: u64 = u64::MAX;
+
+/// How much space is left on the volume
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiskState {
+    /// Enough space, or not measured yet
+    Ok,
+    
+    /// Below the warning threshold; writes are still accepted
+    Low,
+    
+    /// Below the minimum; writes are refused
+    Full,
+}
+
+/// Snapshot of what a monitor knows about the volume
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiskStatus {
+    /// Directory whose volume is watched
+    pub path: String,
+    
+    /// Free bytes at the last check (None before the first one)
+    pub free: Option<u64>,
+    
+    /// Free bytes below which writes are refused
+    pub min_free: u64,
+    
+    /// Free bytes below which warnings are logged
+    pub warn_free: u64,
+    
+    /// State at the last check
+    pub state: DiskState,
+    
+    /// Writes refused because the disk was full
+    pub rejected_writes: u64,
+}
+
+/// Watches the free space of the volume holding a directory
+#[derive(Debug)]
+pub struct DiskMonitor {
+    /// Directory whose volume is watched
+    path: PathBuf,
+    
+    /// Free bytes below which writes are refused
+    min_free: u64,
+    
+    /// Free bytes below which warnings are logged
+    warn_free: u64,
+    
+    /// Free bytes at the last check
+    free: AtomicU64,
+    
+    /// State at the last check, encoded as a u8
+    state: AtomicU8,
+    
+    /// Writes refused because the disk was full
+    rejected: AtomicU64,
+}
+
+impl DiskMonitor {
+    /// Watch the volume of a directory, refusing writes below `min_free` bytes
+    ///
+    /// Warnings start at twice the minimum.
+    pub fn new(path: PathBuf, min_free: u64) -> Self {
+        Self {
+            path,
+            min_free,
+            warn_free: min_free.saturating_mul(2),
+            free: AtomicU64::new(UNKNOWN),
+            state: AtomicU8::new(encode(DiskState::Ok)),
+            rejected: AtomicU64::new(0),
+        }
+    }
+    
+    /// Set the free space below which warnings are logged
+    pub fn with_warn_free(mut self, warn_free: u64) -> Self {
+        self.warn_free = warn_free.max(self.min_free);
+        self
+    }
+    
+    /// Measure the free space now and update the state
+    pub fn check(&self) -> Result<DiskState, HiveError> {
+        Ok(self.record(free_space(&self.path)?))
+    }
+    
+    /// Update the state from a measurement of the free space
+    ///
+    /// Changes of state are logged, and every check below the warning
+    /// threshold logs a warning.
+    pub fn record(&self, free: u64) -> DiskState {
+        let state = if free < self.min_free {
+            DiskState::Full
+        } else if free < self.warn_free {
+            DiskState::Low
+        } else {
+            DiskState::Ok
+        };
+        self.free.store(free, Ordering::SeqCst);
+        let previous = decode(self.state.swap(encode(state), Ordering::SeqCst));
+        
+        match state {
+            DiskState::Full if previous != DiskState::Full => error!(
+                "Only {} bytes free for {}; refusing writes until {} bytes are free",
+                free, self.path.display(), self.min_free
+            ),
+            DiskState::Full | DiskState::Low => warn!("Only {} bytes free for {}", free, self.path.display()),
+            DiskState::Ok if previous != DiskState::Ok => info!("{} bytes free for {} again", free, self.path.display()),
+            DiskState::Ok => {}
+        }
+        
+        state
+    }
+    
+    /// Get the state at the last check
+    pub fn state(&self) -> DiskState {
+        decode(self.state.load(Ordering::SeqCst))
+    }
+    
+    /// Check that client writes are allowed by the free space
+    pub fn check_write(&self) -> Result<(), HiveError> {
+        if self.state() != DiskState::Full {
+            return Ok(());
+        }
+        
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+        Err(HiveError::DiskSpaceLow(format!(
+            "{} bytes free, writes resume above {} bytes",
+            self.free.load(Ordering::SeqCst), self.min_free
+        )))
+    }
+    
+    /// Get what this monitor knows about the volume
+    pub fn status(&self) -> DiskStatus {
+        let free = self.free.load(Ordering::SeqCst);
+        DiskStatus {
+            path: self.path.display().to_string(),
+            free: (free != UNKNOWN).then_some(free),
+            min_free: self.min_free,
+            warn_free: self.warn_free,
+            state: self.state(),
+            rejected_writes: self.rejected.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Get the free bytes available to HiveDB on the volume holding a path
+pub fn free_space(path: &Path) -> Result<u64, HiveError> {
+    if !cfg!(unix) {
+        return Err(HiveError::NotImplemented);
+    }
+    
+    let output = Command::new("df")
+        .arg("-Pk")
+        .arg(path)
+        .output()
+        .map_err(|e| HiveError::IoError(format!("Cannot run df: {}", e)))?;
+    if !output.status.success() {
+        return Err(HiveError::IoError(format!(
+            "df failed for {}: {}",
+            path.display(), String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    
+    parse_df(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Read the available space from the output of `df -Pk`
+fn parse_df(output: &str) -> Result<u64, HiveError> {
+    // POSIX output has a header line, then: filesystem, size, used, available, capacity, mount point
+    output.lines()
+        .nth(1)
+        .and_then(|line| line.split_whitespace().nth(3))
+        .and_then(|available| available.parse::<u64>().ok())
+        .map(|kilobytes| kilobytes * 1024)
+        .ok_or_else(|| HiveError::IoError(format!("Unexpected df output: {}", output.trim())))
+}
+
+fn encode(state: DiskState) -> u8 {
+    match state {
+        DiskState::Ok => 0,
+        DiskState::Low => 1,
+        DiskState::Full => 2,
+    }
+}
+
+fn decode(state: u8) -> DiskState {
+    match state {
+        1 => DiskState::Low,
+        2 => DiskState::Full,
+        _ => DiskState::Ok,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_write_protection() {
+        let monitor = DiskMonitor::new(PathBuf::from("data"), 1000);
+        assert_eq!(monitor.status().free, None);
+        assert!(monitor.check_write().is_ok());
+        
+        assert_eq!(monitor.record(1500), DiskState::Low);
+        assert!(monitor.check_write().is_ok());
+        
+        assert_eq!(monitor.record(999), DiskState::Full);
+        assert!(matches!(monitor.check_write(), Err(HiveError::DiskSpaceLow(_))));
+        assert_eq!(monitor.status().rejected_writes, 1);
+        
+        // Writes resume once space is freed
+        assert_eq!(monitor.record(5000), DiskState::Ok);
+        assert!(monitor.check_write().is_ok());
+        assert_eq!(monitor.status().free, Some(5000));
+    }
+    
+    #[test]
+    fn test_parse_df() {
+        let output = "Filesystem     1024-blocks     Used Available Capacity Mounted on\n\
+                      /dev/sda1         41152736 30000000  11152736      73% /\n";
+        assert_eq!(parse_df(output).unwrap(), 11_152_736 * 1024);
+        assert!(parse_df("df: no such file").is_err());
+    }
+}
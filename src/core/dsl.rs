@@ -0,0 +1,216 @@
+// HiveDB DSL Module
+//
+// This module provides a typed builder for query filters and sort
+// criteria, so embedded users write `field("age").gt(30)` instead of
+// assembling `FilterExpression`s by hand. A `Field<T>` only compares
+// against values of its type; the `hive_fields!` macro declares the typed
+// fields of a document type once, so a misspelled field name or a value
+// of the wrong type fails to compile.
+
+use std::marker::PhantomData;
+use serde_json::Value;
+use crate::core::query::{ComparisonOperator, FilterExpression, SortCriteria, SortDirection};
+
+/// A document field whose values are of type `T`
+#[derive(Debug, Clone)]
+pub struct Field<T = Value> {
+    /// Path of the field, such as `address.city`
+    path: String,
+    
+    /// Type of the field's values
+    value_type: PhantomData<fn() -> T>,
+}
+
+/// Refer to a field that may hold values of any type
+pub fn field(path: &str) -> Field<Value> {
+    Field::typed(path)
+}
+
+impl<T: Into<Value>> Field<T> {
+    /// Refer to a field whose values are of type `T`
+    pub fn typed(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            value_type: PhantomData,
+        }
+    }
+    
+    /// Get the path of this field
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+    
+    /// Refer to a field nested in this one
+    pub fn nested<U: Into<Value>>(&self, name: &str) -> Field<U> {
+        Field::typed(&format!("{}.{}", self.path, name))
+    }
+    
+    /// Match documents where this field equals a value
+    pub fn eq(&self, value: impl Into<T>) -> FilterExpression {
+        self.compare(ComparisonOperator::Eq, value)
+    }
+    
+    /// Match documents where this field differs from a value
+    pub fn ne(&self, value: impl Into<T>) -> FilterExpression {
+        self.compare(ComparisonOperator::Ne, value)
+    }
+    
+    /// Match documents where this field is greater than a value
+    pub fn gt(&self, value: impl Into<T>) -> FilterExpression {
+        self.compare(ComparisonOperator::Gt, value)
+    }
+    
+    /// Match documents where this field is greater than or equal to a value
+    pub fn gte(&self, value: impl Into<T>) -> FilterExpression {
+        self.compare(ComparisonOperator::Gte, value)
+    }
+    
+    /// Match documents where this field is less than a value
+    pub fn lt(&self, value: impl Into<T>) -> FilterExpression {
+        self.compare(ComparisonOperator::Lt, value)
+    }
+    
+    /// Match documents where this field is less than or equal to a value
+    pub fn lte(&self, value: impl Into<T>) -> FilterExpression {
+        self.compare(ComparisonOperator::Lte, value)
+    }
+    
+    /// Match documents where this field is between two values, both included
+    pub fn between(&self, low: impl Into<T>, high: impl Into<T>) -> FilterExpression {
+        self.gte(low).and(self.lte(high))
+    }
+    
+    /// Match documents where this field is one of some values
+    pub fn is_in<V: Into<T>>(&self, values: impl IntoIterator<Item = V>) -> FilterExpression {
+        let values = values.into_iter().map(|value| value.into().into()).collect();
+        FilterExpression::In(self.path.clone(), values)
+    }
+    
+    /// Match documents that have this field, or with `false` those that lack it
+    pub fn exists(&self, exists: bool) -> FilterExpression {
+        FilterExpression::Exists(self.path.clone(), exists)
+    }
+    
+    /// Sort by this field in ascending order
+    pub fn ascending(&self) -> SortCriteria {
+        SortCriteria::new(self.path.clone(), SortDirection::Ascending)
+    }
+    
+    /// Sort by this field in descending order
+    pub fn descending(&self) -> SortCriteria {
+        SortCriteria::new(self.path.clone(), SortDirection::Descending)
+    }
+    
+    /// Compare this field to a value
+    fn compare(&self, operator: ComparisonOperator, value: impl Into<T>) -> FilterExpression {
+        FilterExpression::Comparison(operator, self.path.clone(), value.into().into())
+    }
+}
+
+impl Field<String> {
+    /// Match documents where this field matches a LIKE pattern (`%` and `_` wildcards)
+    pub fn like(&self, pattern: &str) -> FilterExpression {
+        FilterExpression::Pattern(self.path.clone(), pattern.to_string())
+    }
+}
+
+impl FilterExpression {
+    /// Match documents that match both this filter and another
+    ///
+    /// Chained calls build one flat AND rather than nesting them.
+    pub fn and(self, other: FilterExpression) -> FilterExpression {
+        match self {
+            FilterExpression::And(mut filters) => {
+                filters.push(other);
+                FilterExpression::And(filters)
+            }
+            filter => FilterExpression::And(vec![filter, other]),
+        }
+    }
+    
+    /// Match documents that match this filter or another
+    ///
+    /// Chained calls build one flat OR rather than nesting them.
+    pub fn or(self, other: FilterExpression) -> FilterExpression {
+        match self {
+            FilterExpression::Or(mut filters) => {
+                filters.push(other);
+                FilterExpression::Or(filters)
+            }
+            filter => FilterExpression::Or(vec![filter, other]),
+        }
+    }
+}
+
+/// Declare the typed fields of a document type
+///
+/// Each field becomes a function returning a `Field` of its type:
+///
+/// ```ignore
+/// hive_fields! {
+///     pub struct UserFields {
+///         age: i64,
+///         status: String,
+///     }
+/// }
+///
+/// let filter = UserFields::age().gt(30).and(UserFields::status().eq("active"));
+/// ```
+#[macro_export]
+macro_rules! hive_fields {
+    ($(#[$meta:meta])* $vis:vis struct $name:ident { $($field:ident : $field_type:ty),* $(,)? }) => {
+        $(#[$meta])*
+        $vis struct $name;
+        
+        impl $name {
+            $(
+                #[allow(dead_code)]
+                pub fn $field() -> $crate::core::dsl::Field<$field_type> {
+                    $crate::core::dsl::Field::typed(stringify!($field))
+                }
+            )*
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::query::{Query, QueryType};
+    
+    crate::hive_fields! {
+        struct OrderFields {
+            total: f64,
+            status: String,
+            address: Value,
+        }
+    }
+    
+    #[test]
+    fn test_typed_filters() {
+        let filter = field("age").gt(30).and(field("status").eq("active")).and(field("tags").exists(true));
+        match &filter {
+            FilterExpression::And(filters) => {
+                assert_eq!(filters.len(), 3);
+                assert!(matches!(&filters[0], FilterExpression::Comparison(ComparisonOperator::Gt, path, value)
+                    if path == "age" && *value == 30));
+            }
+            other => panic!("expected an AND, found {:?}", other),
+        }
+        
+        let filter = OrderFields::total().between(10.0, 99.5)
+            .or(OrderFields::status().is_in(["pending", "shipped"]))
+            .or(OrderFields::status().like("back%"));
+        assert!(matches!(&filter, FilterExpression::Or(filters) if filters.len() == 3));
+        
+        let city: Field<String> = OrderFields::address().nested("city");
+        assert_eq!(city.path(), "address.city");
+        
+        let query = Query::new(QueryType::Find, "orders".to_string())
+            .with_filter(city.eq("Cairo"))
+            .with_sort(vec![OrderFields::total().descending(), city.ascending()]);
+        let sort = query.sort.unwrap();
+        assert_eq!((sort[0].field.as_str(), &sort[0].direction), ("total", &SortDirection::Descending));
+        assert_eq!(sort[1].field, "address.city");
+    }
+}
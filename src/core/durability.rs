@@ -0,0 +1,221 @@
+// HiveDB Durability Module
+//
+// This module defines how hard HiveDB works to keep acknowledged writes
+// through a crash. Hives are persisted as snapshots; the durability policy
+// decides when a changed hive is saved and whether its snapshot is synced
+// to disk, trading safety against write throughput.
+
+use std::fmt;
+use std::fs::File;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+use crate::core::error::HiveError;
+
+/// Interval between saves of changed hives under the default policy
+pub const DEFAULT_SYNC_INTERVAL: Duration = Duration::from_secs(1);
+
+/// When changed hives are saved and synced to disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Durability {
+    /// Save and sync a hive before acknowledging each write to it
+    Always,
+    
+    /// Save and sync changed hives every interval, so writes since the last save can be lost
+    Interval(Duration),
+    
+    /// Save hives only when asked to or at shutdown, leaving snapshots in the OS page cache
+    Buffered,
+}
+
+impl Durability {
+    /// Check whether snapshots are synced to disk when written
+    pub fn syncs(&self) -> bool {
+        !matches!(self, Durability::Buffered)
+    }
+}
+
+impl Default for Durability {
+    fn default() -> Self {
+        Durability::Interval(DEFAULT_SYNC_INTERVAL)
+    }
+}
+
+impl fmt::Display for Durability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Durability::Always => write!(f, "always"),
+            Durability::Interval(interval) => write!(f, "{}ms", interval.as_millis()),
+            Durability::Buffered => write!(f, "buffered"),
+        }
+    }
+}
+
+impl FromStr for Durability {
+    type Err = HiveError;
+    
+    /// Parse `always`, `buffered`, or an interval such as `200ms` or `2s`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_ascii_lowercase();
+        let interval = |number: &str, unit: u64| number.parse::<u64>().ok()
+            .filter(|number| *number > 0)
+            .map(|number| Durability::Interval(Duration::from_millis(number * unit)));
+        
+        let durability = match s.as_str() {
+            "always" => Some(Durability::Always),
+            "buffered" => Some(Durability::Buffered),
+            _ => match s.strip_suffix("ms") {
+                Some(millis) => interval(millis, 1),
+                None => s.strip_suffix('s').and_then(|seconds| interval(seconds, 1000)),
+            },
+        };
+        
+        durability.ok_or_else(|| HiveError::GenericError(format!(
+            "Invalid durability '{}', expected always, buffered or an interval such as 200ms", s
+        )))
+    }
+}
+
+/// Sync the content of a written file to disk
+pub fn sync_file(path: &Path) -> Result<(), HiveError> {
+    File::open(path)
+        .and_then(|file| file.sync_all())
+        .map_err(|e| HiveError::IoError(e.to_string()))
+}
+
+/// Sync the directory holding a file, so a rename to that file survives a crash
+pub fn sync_parent(path: &Path) -> Result<(), HiveError> {
+    // Directories cannot be opened for syncing on every platform
+    if let Some(Ok(dir)) = path.parent().map(File::open) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+/// Replace a file so that a crash leaves either its old or its new content
+///
+/// The content is written to a temporary file next to the target, which
+/// is then renamed over it. A crash can leave the temporary file behind.
+pub fn replace_file(path: &Path, data: &[u8], sync: bool) -> Result<(), HiveError> {
+    let temp_path = path.with_extension("tmp");
+    
+    #[cfg(test)]
+    if let Some(written) = crash::torn_write() {
+        let _ = std::fs::write(&temp_path, &data[..written.min(data.len())]);
+        return Err(crash::crashed());
+    }
+    
+    std::fs::write(&temp_path, data)
+        .map_err(|e| HiveError::IoError(e.to_string()))?;
+    if sync {
+        sync_file(&temp_path)?;
+    }
+    
+    #[cfg(test)]
+    crash::reach(crash::CrashPoint::BeforeRename)?;
+    
+    std::fs::rename(&temp_path, path)
+        .map_err(|e| HiveError::IoError(e.to_string()))?;
+    
+    #[cfg(test)]
+    crash::reach(crash::CrashPoint::AfterRename)?;
+    
+    if sync {
+        sync_parent(path)?;
+    }
+    Ok(())
+}
+
+/// Crashes injected into file replacements, for recovery tests
+///
+/// A planned crash stops the next replacement on the same thread at its
+/// point, leaving the files as a killed process would, and fails it.
+#[cfg(test)]
+pub(crate) mod crash {
+    use std::cell::Cell;
+    use crate::core::error::HiveError;
+    
+    /// Where a file replacement is interrupted
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub(crate) enum CrashPoint {
+        /// While writing the temporary file, once this many bytes are written
+        Writing(usize),
+        
+        /// After the temporary file is written, before it is renamed
+        BeforeRename,
+        
+        /// After the rename, before the directory is synced
+        AfterRename,
+    }
+    
+    thread_local! {
+        /// Crash planned on this thread
+        static PLANNED: Cell<Option<CrashPoint>> = const { Cell::new(None) };
+    }
+    
+    /// Interrupt the next file replacement on this thread
+    pub(crate) fn plan(point: CrashPoint) {
+        PLANNED.with(|planned| planned.set(Some(point)));
+    }
+    
+    /// Get the number of bytes written before a planned crash while writing
+    pub(super) fn torn_write() -> Option<usize> {
+        match PLANNED.with(Cell::get) {
+            Some(CrashPoint::Writing(written)) => {
+                PLANNED.with(|planned| planned.set(None));
+                Some(written)
+            }
+            _ => None,
+        }
+    }
+    
+    /// Fail if a crash is planned at this point
+    pub(super) fn reach(point: CrashPoint) -> Result<(), HiveError> {
+        if PLANNED.with(Cell::get) != Some(point) {
+            return Ok(());
+        }
+        PLANNED.with(|planned| planned.set(None));
+        Err(crashed())
+    }
+    
+    /// The error of an interrupted replacement
+    pub(super) fn crashed() -> HiveError {
+        HiveError::IoError("Injected crash".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_parse_durability() {
+        assert_eq!("always".parse::<Durability>().unwrap(), Durability::Always);
+        assert_eq!("Buffered".parse::<Durability>().unwrap(), Durability::Buffered);
+        assert_eq!("250ms".parse::<Durability>().unwrap(), Durability::Interval(Duration::from_millis(250)));
+        assert_eq!("2s".parse::<Durability>().unwrap(), Durability::Interval(Duration::from_secs(2)));
+        assert!("0ms".parse::<Durability>().is_err());
+        assert!("sometimes".parse::<Durability>().is_err());
+        
+        for durability in [Durability::Always, Durability::Buffered, Durability::default()] {
+            assert_eq!(durability.to_string().parse::<Durability>().unwrap(), durability);
+        }
+        assert!(!Durability::Buffered.syncs());
+    }
+    
+    #[test]
+    fn test_replace_file() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("state.json");
+        replace_file(&path, b"old", true).unwrap();
+        
+        crash::plan(crash::CrashPoint::Writing(2));
+        assert!(replace_file(&path, b"new", true).is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), b"old");
+        assert_eq!(std::fs::read(path.with_extension("tmp")).unwrap(), b"ne");
+        
+        replace_file(&path, b"new", false).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"new");
+        assert!(!path.with_extension("tmp").exists());
+    }
+}
@@ -43,6 +43,10 @@ pub enum HiveError {
     #[error("Decompression error: {0}")]
     DecompressionError(String),
     
+    /// Error encrypting or decrypting data
+    #[error("Encryption error: {0}")]
+    EncryptionError(String),
+    
     /// I/O error
     #[error("I/O error: {0}")]
     IoError(String),
@@ -63,6 +67,38 @@ pub enum HiveError {
     #[error("Authorization error: {0}")]
     AuthorizationError(String),
     
+    /// No user has the specified name
+    #[error("User not found: {0}")]
+    UserNotFound(String),
+    
+    /// A user with the specified name already exists
+    #[error("User already exists: {0}")]
+    UserAlreadyExists(String),
+    
+    /// A password does not satisfy the password policy
+    #[error("Password policy violation: {0}")]
+    PasswordPolicyViolation(String),
+    
+    /// The user's password has expired and must be changed
+    #[error("Password of user '{0}' has expired and must be changed")]
+    PasswordExpired(String),
+    
+    /// The account is locked after too many failed logins
+    #[error("Account '{0}' is locked")]
+    AccountLocked(String),
+    
+    /// No API key has the specified ID
+    #[error("API key not found: {0}")]
+    ApiKeyNotFound(String),
+    
+    /// The hive has no index with the specified name
+    #[error("Index not found: {0}")]
+    IndexNotFound(String),
+    
+    /// No webhook has the specified ID
+    #[error("Webhook not found: {0}")]
+    WebhookNotFound(String),
+    
     /// Schema validation error
     #[error("Schema validation error: {0}")]
     SchemaValidationError(String),
@@ -71,6 +107,46 @@ pub enum HiveError {
     #[error("Query error: {0}")]
     QueryError(String),
     
+    /// The query was cancelled before it completed
+    #[error("Query was cancelled")]
+    QueryCancelled,
+    
+    /// A tenant's query waited too long for a scheduler slot
+    #[error("Too many queries running for '{0}'; try again later")]
+    TenantBusy(String),
+    
+    /// No running query has the specified ID
+    #[error("No running query with ID {0}")]
+    QueryNotFound(u64),
+    
+    /// The server is in read-only mode and rejects writes
+    #[error("Server is in read-only mode")]
+    ReadOnlyMode,
+    
+    /// The data directory is almost out of space and writes are refused
+    #[error("Not enough free disk space: {0}")]
+    DiskSpaceLow(String),
+    
+    /// The server is in maintenance mode and only serves admin requests
+    #[error("Server is in maintenance mode")]
+    MaintenanceMode,
+    
+    /// The change log no longer holds events after the requested sequence
+    #[error("Change log no longer contains events after sequence {0}")]
+    ChangeLogTruncated(u64),
+    
+    /// Another process has the data directory open
+    #[error("Data directory {0} is in use by another process (is a HiveDB server running?)")]
+    DirectoryLocked(String),
+    
+    /// Another server already runs on the data directory
+    #[error("HiveDB is already running with PID {0}")]
+    AlreadyRunning(u32),
+    
+    /// The memory budget cannot accommodate a query
+    #[error("Memory budget exceeded: {0}")]
+    MemoryBudgetExceeded(String),
+    
     /// Network error
     #[error("Network error: {0}")]
     NetworkError(String),
@@ -0,0 +1,253 @@
+// HiveDB Events Module
+//
+// This module carries events about hives as a whole, such as a hive being
+// created or its schema changing, on a bus owned by the hive manager.
+// Unlike the change log of each hive, which records every cell mutation,
+// the bus is meant for operators: library users subscribe to it, the
+// server writes it to the audit log, and webhooks can receive it. Events
+// are kept in a bounded history so late consumers can catch up by
+// sequence number.
+
+use std::collections::{HashSet, VecDeque};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use serde::{Deserialize, Serialize};
+use crate::core::error::HiveError;
+use crate::utils::determinism;
+use log::{info, warn};
+
+/// Number of events kept for consumers that poll the bus
+pub const DEFAULT_EVENT_HISTORY: usize = 1_000;
+
+/// Log target of the audit log
+pub const AUDIT_TARGET: &str = "hivedb::audit";
+
+/// Whether snapshots reach the snapshot store
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplicationState {
+    /// Snapshots are written through to the store
+    InSync,
+    
+    /// The store could not be reached on the last attempt
+    Failing,
+}
+
+/// What happened, with the details of each kind of event
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EventKind {
+    /// A hive was created
+    HiveCreated { name: String },
+    
+    /// A hive was loaded from storage
+    HiveLoaded { name: String },
+    
+    /// A hive was deleted with its storage
+    HiveDeleted { name: String },
+    
+    /// The schema of a hive was replaced or its indexes changed
+    SchemaChanged { version: u64 },
+    
+    /// Cold cells of a hive were moved to the cold tier
+    CellsOffloaded { cells: usize, bytes: usize },
+    
+    /// Cells of a hive were archived by its retention rules and removed
+    CellsArchived { cells: usize, bytes: usize, target: String },
+    
+    /// Snapshots started or stopped reaching the snapshot store
+    ReplicationChanged { state: ReplicationState, error: Option<String> },
+}
+
+/// An event published on the bus
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManagerEvent {
+    /// Position of the event on the bus (strictly increasing)
+    pub sequence: u64,
+    
+    /// When the event was published (seconds since the UNIX epoch)
+    pub timestamp: u64,
+    
+    /// ID of the hive the event is about (None for the whole manager)
+    pub hive_id: Option<String>,
+    
+    /// What happened
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+/// Publishes events to subscribers; clones share the same bus
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    /// Subscribers and history, shared by the clones
+    state: Arc<Mutex<BusState>>,
+}
+
+/// Subscribers and history of a bus
+#[derive(Debug)]
+struct BusState {
+    /// Channels of the subscribers
+    subscribers: Vec<Sender<ManagerEvent>>,
+    
+    /// Most recent events, oldest first
+    history: VecDeque<ManagerEvent>,
+    
+    /// Maximum number of events in the history
+    capacity: usize,
+    
+    /// Sequence number of the last event published
+    last_sequence: u64,
+    
+    /// Hives whose snapshots failed to reach the store (None for the manager)
+    failing: HashSet<Option<String>>,
+}
+
+impl EventBus {
+    /// Create a bus keeping the given number of events
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(BusState {
+                subscribers: Vec::new(),
+                history: VecDeque::new(),
+                capacity: capacity.max(1),
+                last_sequence: 0,
+                failing: HashSet::new(),
+            })),
+        }
+    }
+    
+    /// Publish an event to every subscriber
+    ///
+    /// Publishing never fails: subscribers that went away are dropped.
+    pub fn publish(&self, hive_id: Option<&str>, kind: EventKind) {
+        let Ok(mut state) = self.state.lock() else {
+            warn!("Event bus lock poisoned, dropping {:?}", kind);
+            return;
+        };
+        
+        state.last_sequence += 1;
+        let event = ManagerEvent {
+            sequence: state.last_sequence,
+            timestamp: determinism::unix_time(),
+            hive_id: hive_id.map(str::to_string),
+            kind,
+        };
+        
+        state.subscribers.retain(|subscriber| subscriber.send(event.clone()).is_ok());
+        if state.history.len() == state.capacity {
+            state.history.pop_front();
+        }
+        state.history.push_back(event);
+    }
+    
+    /// Receive every event published from now on
+    ///
+    /// Events queue up until they are received, so a subscriber should
+    /// keep up with the bus or drop the receiver.
+    pub fn subscribe(&self) -> Result<Receiver<ManagerEvent>, HiveError> {
+        let (sender, receiver) = mpsc::channel();
+        self.state.lock().map_err(|_| HiveError::LockError)?.subscribers.push(sender);
+        Ok(receiver)
+    }
+    
+    /// Get the retained events published after the given sequence number
+    pub fn since(&self, sequence: u64) -> Result<Vec<ManagerEvent>, HiveError> {
+        let state = self.state.lock().map_err(|_| HiveError::LockError)?;
+        Ok(state.history.iter()
+            .filter(|event| event.sequence > sequence)
+            .cloned()
+            .collect())
+    }
+    
+    /// Get the sequence number of the last event published (0 if none)
+    pub fn last_sequence(&self) -> u64 {
+        self.state.lock().map_or(0, |state| state.last_sequence)
+    }
+    
+    /// Record whether snapshots reached the snapshot store
+    ///
+    /// An event is published only when the outcome differs from the last
+    /// one for the same hive; the first failure of a hive counts as a change.
+    pub fn report_replication(&self, hive_id: Option<&str>, outcome: Result<(), &HiveError>) {
+        let changed = match self.state.lock() {
+            Ok(mut state) => {
+                let key = hive_id.map(str::to_string);
+                if outcome.is_ok() { state.failing.remove(&key) } else { state.failing.insert(key) }
+            }
+            Err(_) => false,
+        };
+        
+        if changed {
+            let (state, error) = match outcome {
+                Ok(()) => (ReplicationState::InSync, None),
+                Err(e) => (ReplicationState::Failing, Some(e.to_string())),
+            };
+            self.publish(hive_id, EventKind::ReplicationChanged { state, error });
+        }
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(DEFAULT_EVENT_HISTORY)
+    }
+}
+
+/// Write events to the audit log until the bus is dropped
+pub fn audit_log(events: Receiver<ManagerEvent>) {
+    for event in events {
+        match serde_json::to_string(&event) {
+            Ok(line) => info!(target: AUDIT_TARGET, "{}", line),
+            Err(e) => warn!(target: AUDIT_TARGET, "Could not encode event {}: {}", event.sequence, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_publish_and_subscribe() {
+        let bus = EventBus::new(2);
+        let events = bus.subscribe().unwrap();
+        let dropped = bus.subscribe().unwrap();
+        drop(dropped);
+        
+        bus.publish(Some("hive-1"), EventKind::HiveCreated { name: "a".to_string() });
+        bus.publish(Some("hive-1"), EventKind::SchemaChanged { version: 2 });
+        bus.publish(None, EventKind::HiveDeleted { name: "b".to_string() });
+        
+        let received: Vec<ManagerEvent> = events.try_iter().collect();
+        assert_eq!(received.len(), 3);
+        assert_eq!(received[1].kind, EventKind::SchemaChanged { version: 2 });
+        assert_eq!(bus.state.lock().unwrap().subscribers.len(), 1);
+        
+        // Only the last two events are kept
+        let history = bus.since(0).unwrap();
+        assert_eq!(history.iter().map(|event| event.sequence).collect::<Vec<_>>(), vec![2, 3]);
+        assert!(bus.since(3).unwrap().is_empty());
+        assert_eq!(bus.last_sequence(), 3);
+    }
+    
+    #[test]
+    fn test_replication_changes() {
+        let bus = EventBus::default();
+        let error = HiveError::NetworkError("unreachable".to_string());
+        
+        bus.report_replication(Some("hive-1"), Ok(()));
+        bus.report_replication(Some("hive-1"), Err(&error));
+        bus.report_replication(Some("hive-1"), Err(&error));
+        bus.report_replication(None, Err(&error));
+        bus.report_replication(Some("hive-1"), Ok(()));
+        
+        let states: Vec<(Option<String>, EventKind)> = bus.since(0).unwrap()
+            .into_iter()
+            .map(|event| (event.hive_id, event.kind))
+            .collect();
+        assert_eq!(states.len(), 3);
+        assert!(matches!(&states[0].1, EventKind::ReplicationChanged { state: ReplicationState::Failing, error: Some(_) }));
+        assert_eq!(states[1].0, None);
+        assert_eq!(states[2].1, EventKind::ReplicationChanged { state: ReplicationState::InSync, error: None });
+    }
+}
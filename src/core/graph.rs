@@ -0,0 +1,388 @@
+// HiveDB Graph Module
+//
+// This module matches graph patterns against the edges between cells, so
+// social-graph style questions need no hand-written traversal. A pattern
+// is a chain of nodes joined by edges, as written in SQL:
+//
+//   MATCH (a:users)-[:FRIEND]->(b)<-[r:FOLLOWS]-(c) WHERE a.name = 'Sara'
+//       [RETURN a, c] [LIMIT n]
+//
+// A node may name a variable and a collection its cell must belong to; an
+// edge may name a variable and the label it must carry, and points either
+// way. Matching binds the first node to every cell in turn and follows the
+// edges of the chain from there. An edge is used at most once per match,
+// and nodes sharing a variable must bind the same cell.
+
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use crate::core::cell::{CellDataType, Edge};
+use crate::core::error::HiveError;
+use crate::core::hive::Hive;
+use crate::core::query::{with_id, FilterExpression, QueryExecutor};
+use crate::core::session::CancelToken;
+
+/// Which way an edge of a pattern points
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EdgeDirection {
+    /// From the node before the edge to the node after it, `-[]->`
+    Outgoing,
+    
+    /// From the node after the edge to the node before it, `<-[]-`
+    Incoming,
+}
+
+/// A node of a pattern, written `(variable:collection)`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodePattern {
+    /// Name the matched cell is bound to (None for an anonymous node)
+    pub variable: Option<String>,
+    
+    /// Collection the matched cell must belong to (None for any cell)
+    pub collection: Option<String>,
+}
+
+/// An edge of a pattern, written `-[variable:LABEL]->` or `<-[variable:LABEL]-`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EdgePattern {
+    /// Name the matched edge is bound to (None for an anonymous edge)
+    pub variable: Option<String>,
+    
+    /// Label the matched edge must carry (None for any label)
+    pub label: Option<String>,
+    
+    /// Which way the matched edge points
+    pub direction: EdgeDirection,
+}
+
+/// A graph pattern with the condition and shape of its results
+#[derive(Debug, Clone)]
+pub struct GraphQuery {
+    /// First node of the chain
+    pub start: NodePattern,
+    
+    /// Edges of the chain, each with the node it leads to
+    pub steps: Vec<(EdgePattern, NodePattern)>,
+    
+    /// Condition on the bound variables, read as `variable.field`
+    pub filter: Option<FilterExpression>,
+    
+    /// Variables to return (None for every named variable)
+    pub returns: Option<Vec<String>>,
+    
+    /// Maximum number of matches
+    pub limit: Option<usize>,
+}
+
+impl GraphQuery {
+    /// Create a pattern of a single node
+    pub fn new(start: NodePattern) -> Self {
+        Self {
+            start,
+            steps: Vec::new(),
+            filter: None,
+            returns: None,
+            limit: None,
+        }
+    }
+    
+    /// Extend the chain by an edge and the node it leads to
+    pub fn with_step(mut self, edge: EdgePattern, node: NodePattern) -> Self {
+        self.steps.push((edge, node));
+        self
+    }
+    
+    /// Set the condition on the bound variables
+    pub fn with_filter(mut self, filter: FilterExpression) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+    
+    /// Set the variables to return
+    pub fn with_returns(mut self, returns: Vec<String>) -> Self {
+        self.returns = Some(returns);
+        self
+    }
+    
+    /// Set the maximum number of matches
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+    
+    /// Get the named variables of the pattern, in order of first appearance
+    pub fn variables(&self) -> Vec<String> {
+        let mut variables: Vec<String> = Vec::new();
+        let names = std::iter::once(&self.start.variable)
+            .chain(self.steps.iter().flat_map(|(edge, node)| [&edge.variable, &node.variable]));
+        for name in names.flatten() {
+            if !variables.contains(name) {
+                variables.push(name.clone());
+            }
+        }
+        variables
+    }
+    
+    /// Find the matches of the pattern in a hive
+    ///
+    /// Each match is an object from variable name to the bound value: a
+    /// cell's document with its `_id` (just the `_id` for cells that do not
+    /// hold JSON), or an edge's label, weight and properties. Matches are
+    /// ordered by the position of the first node's cell, row by row.
+    pub fn execute(&self, hive: &Hive, cancel: &CancelToken) -> Result<Vec<serde_json::Value>, HiveError> {
+        if let Some(unknown) = self.returns.iter().flatten().find(|name| !self.variables().contains(name)) {
+            return Err(HiveError::QueryError(format!("RETURN of unknown variable '{}'", unknown)));
+        }
+        
+        let mut starts: Vec<(i32, i32)> = Vec::new();
+        for cell_arc in hive.cells.all_cells() {
+            starts.push(cell_arc.read().map_err(|_| HiveError::LockError)?.coordinates);
+        }
+        starts.sort_by_key(|position| (position.1, position.0));
+        
+        let mut matcher = Matcher {
+            query: self,
+            hive,
+            cancel,
+            documents: HashMap::new(),
+            nodes: Vec::new(),
+            edges: Vec::new(),
+            rows: Vec::new(),
+        };
+        for start in starts {
+            cancel.check()?;
+            if matcher.bind(&self.start, start)? && matcher.extend()? {
+                break;
+            }
+            matcher.nodes.clear();
+        }
+        
+        Ok(matcher.rows)
+    }
+}
+
+/// State of a running match
+struct Matcher<'a> {
+    /// Pattern being matched
+    query: &'a GraphQuery,
+    
+    /// Hive being searched
+    hive: &'a Hive,
+    
+    /// Token the search checks for cancellation
+    cancel: &'a CancelToken,
+    
+    /// Bound values of the cells seen so far, by position
+    documents: HashMap<(i32, i32), serde_json::Value>,
+    
+    /// Positions bound to the nodes matched so far
+    nodes: Vec<(i32, i32)>,
+    
+    /// Edges bound so far, with the positions they leave from and lead to
+    edges: Vec<((i32, i32), (i32, i32), Edge)>,
+    
+    /// Matches found
+    rows: Vec<serde_json::Value>,
+}
+
+impl Matcher<'_> {
+    /// Bind the next node of the pattern to a position, if its cell fits
+    fn bind(&mut self, pattern: &NodePattern, position: (i32, i32)) -> Result<bool, HiveError> {
+        let Some(cell_arc) = self.hive.cells.get_cell(position) else {
+            return Ok(false);
+        };
+        let cell = cell_arc.read().map_err(|_| HiveError::LockError)?;
+        if cell.is_continuation() || pattern.collection.as_ref().is_some_and(|tag| !cell.metadata.tags.contains(tag)) {
+            return Ok(false);
+        }
+        
+        // A variable seen before must bind the same cell again
+        let earlier = std::iter::once(&self.query.start).chain(self.query.steps.iter().map(|(_, node)| node));
+        for (node, bound) in earlier.zip(&self.nodes) {
+            if pattern.variable.is_some() && node.variable == pattern.variable && *bound != position {
+                return Ok(false);
+            }
+        }
+        
+        self.nodes.push(position);
+        Ok(true)
+    }
+    
+    /// Match the rest of the chain from the last bound node
+    ///
+    /// Returns true once the limit of matches is reached.
+    fn extend(&mut self) -> Result<bool, HiveError> {
+        let query = self.query;
+        let Some((edge_pattern, node_pattern)) = query.steps.get(self.nodes.len() - 1) else {
+            return self.emit();
+        };
+        
+        let from = self.nodes[self.nodes.len() - 1];
+        let candidates = match edge_pattern.direction {
+            EdgeDirection::Outgoing => self.hive.cells.edges_of(from)?
+                .into_iter()
+                .map(|(to, edge)| (to, (from, to), edge))
+                .collect::<Vec<_>>(),
+            EdgeDirection::Incoming => self.hive.cells.edges_into(from)?
+                .into_iter()
+                .map(|(to, edge)| (to, (to, from), edge))
+                .collect(),
+        };
+        
+        for (next, ends, edge) in candidates {
+            self.cancel.check()?;
+            if edge_pattern.label.as_ref().is_some_and(|label| edge.label != *label)
+                || self.edges.iter().any(|(source, target, _)| (*source, *target) == ends)
+            {
+                continue;
+            }
+            if !self.bind(node_pattern, next)? {
+                continue;
+            }
+            
+            self.edges.push((ends.0, ends.1, edge));
+            let done = self.extend()?;
+            self.edges.pop();
+            self.nodes.pop();
+            if done {
+                return Ok(true);
+            }
+        }
+        
+        Ok(false)
+    }
+    
+    /// Record the current bindings as a match if they pass the condition
+    ///
+    /// Returns true once the limit of matches is reached.
+    fn emit(&mut self) -> Result<bool, HiveError> {
+        let query = self.query;
+        let mut row = serde_json::Map::new();
+        
+        let nodes = std::iter::once(&query.start).chain(query.steps.iter().map(|(_, node)| node));
+        for (node, position) in nodes.zip(self.nodes.clone()) {
+            if let Some(variable) = &node.variable {
+                let document = self.document(position)?;
+                row.insert(variable.clone(), document);
+            }
+        }
+        for ((edge, _), (_, _, bound)) in query.steps.iter().zip(&self.edges) {
+            if let Some(variable) = &edge.variable {
+                let mut value = serde_json::Map::new();
+                value.extend(bound.properties.iter().map(|(name, value)| (name.clone(), value.clone())));
+                value.insert("label".to_string(), serde_json::Value::String(bound.label.clone()));
+                value.insert("weight".to_string(), serde_json::Value::from(bound.weight));
+                row.insert(variable.clone(), serde_json::Value::Object(value));
+            }
+        }
+        
+        let row = serde_json::Value::Object(row);
+        if query.filter.as_ref().is_some_and(|filter| !filter.evaluate(&row)) {
+            return Ok(false);
+        }
+        
+        let row = match (&query.returns, row) {
+            (Some(returns), serde_json::Value::Object(mut bindings)) => serde_json::Value::Object(
+                returns.iter()
+                    .filter_map(|name| bindings.remove_entry(name))
+                    .collect(),
+            ),
+            (_, row) => row,
+        };
+        self.rows.push(row);
+        
+        Ok(query.limit.is_some_and(|limit| self.rows.len() >= limit))
+    }
+    
+    /// Get the bound value of the cell at a position, loading it once
+    fn document(&mut self, position: (i32, i32)) -> Result<serde_json::Value, HiveError> {
+        if let Some(document) = self.documents.get(&position) {
+            return Ok(document.clone());
+        }
+        
+        let cell_arc = self.hive.cells.get_cell(position).ok_or(HiveError::CellNotFound)?;
+        let cell = cell_arc.read().map_err(|_| HiveError::LockError)?;
+        let document = if cell.data.data_type == CellDataType::Json {
+            with_id((*QueryExecutor::load_document(&cell)?.0).clone(), &cell.id)
+        } else {
+            with_id(serde_json::Value::Object(serde_json::Map::new()), &cell.id)
+        };
+        
+        self.documents.insert(position, document.clone());
+        Ok(document)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::cell::Cell;
+    use crate::core::sql::{self, Statement};
+    use tempfile::tempdir;
+    
+    fn person(hive: &mut Hive, id: &str, position: (i32, i32), name: &str) {
+        let content = format!(r#"{{"name": "{}"}}"#, name).into_bytes();
+        let mut cell = Cell::new(id.to_string(), position, CellDataType::Json, content, false).unwrap();
+        cell.add_tag("users".to_string());
+        hive.add_cell(cell).unwrap();
+    }
+    
+    fn pattern(text: &str) -> GraphQuery {
+        match sql::parse_statement(text).unwrap() {
+            Statement::Match(query) => query,
+            other => panic!("Expected MATCH, got {:?}", other),
+        }
+    }
+    
+    fn ids(rows: &[serde_json::Value], variable: &str) -> Vec<String> {
+        rows.iter()
+            .map(|row| row[variable]["_id"].as_str().unwrap_or_default().to_string())
+            .collect()
+    }
+    
+    #[test]
+    fn test_match_patterns() {
+        let temp_dir = tempdir().unwrap();
+        let mut hive = Hive::new(
+            "social".to_string(),
+            String::new(),
+            "test-user".to_string(),
+            temp_dir.path().to_path_buf(),
+            (4, 4),
+        ).unwrap();
+        person(&mut hive, "sara", (1, 1), "Sara");
+        person(&mut hive, "omar", (2, 1), "Omar");
+        person(&mut hive, "laila", (1, 2), "Laila");
+        hive.add_cell(Cell::new("post".to_string(), (2, 0), CellDataType::Binary, b"hi".to_vec(), false).unwrap()).unwrap();
+        
+        hive.set_edge((1, 1), (2, 1), Edge::new("FRIEND")).unwrap();
+        hive.set_edge((2, 1), (1, 1), Edge::new("FRIEND")).unwrap();
+        hive.set_edge((2, 1), (1, 2), Edge::new("FRIEND").with_weight(3.0)).unwrap();
+        hive.set_edge((1, 1), (2, 0), Edge::new("WROTE")).unwrap();
+        let cancel = CancelToken::new();
+        
+        let friends = pattern("MATCH (a:users)-[:FRIEND]->(b) RETURN a, b").execute(&hive, &cancel).unwrap();
+        assert_eq!(ids(&friends, "a"), vec!["sara", "omar", "omar"]);
+        assert_eq!(ids(&friends, "b"), vec!["omar", "sara", "laila"]);
+        
+        // Friends of friends, without walking the same edge back
+        let second = pattern("MATCH (a)-[:FRIEND]->()-[r:FRIEND]->(c) WHERE a.name = 'Sara' AND r.weight > 2")
+            .execute(&hive, &cancel)
+            .unwrap();
+        assert_eq!(ids(&second, "c"), vec!["laila"]);
+        assert_eq!(second[0]["r"]["label"], serde_json::json!("FRIEND"));
+        
+        let incoming = pattern("MATCH (p)<-[:WROTE]-(author:users) RETURN author LIMIT 5").execute(&hive, &cancel).unwrap();
+        assert_eq!(ids(&incoming, "author"), vec!["sara"]);
+        assert!(incoming[0].get("p").is_none());
+        
+        let cycles = pattern("MATCH (a)-->(b)-->(a)").execute(&hive, &cancel).unwrap();
+        assert_eq!(ids(&cycles, "a"), vec!["sara", "omar"]);
+        
+        let limited = pattern("MATCH (a)-->(b) LIMIT 1").execute(&hive, &cancel).unwrap();
+        assert_eq!(limited.len(), 1);
+        
+        let unknown = GraphQuery::new(NodePattern { variable: Some("a".to_string()), collection: None })
+            .with_returns(vec!["b".to_string()]);
+        assert!(unknown.execute(&hive, &cancel).is_err());
+    }
+}
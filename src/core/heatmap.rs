@@ -0,0 +1,230 @@
+// HiveDB Heat Map Module
+//
+// This module exports which positions of a hive's grid hold cells and how
+// often each cell was read, as JSON for other tools or as an SVG picture
+// of the comb. Reads are counted in memory since the hive was loaded, so
+// a fresh server starts with a cold map.
+
+use std::collections::HashSet;
+use std::fmt::Write;
+use serde::{Deserialize, Serialize};
+use crate::core::error::HiveError;
+use crate::core::hive::Hive;
+use crate::core::pheromone::Temperature;
+
+/// Distance from the center of a drawn hexagon to its corners, in pixels
+pub const HEX_SIZE: f64 = 10.0;
+
+/// Largest grid whose empty positions are drawn too
+pub const MAX_DRAWN_POSITIONS: usize = 16_384;
+
+/// Fill of empty positions
+const EMPTY_COLOR: &str = "#f2f2f2";
+
+/// Fill of cells nobody read, shading towards `HOT_COLOR` as reads grow
+const COLD_COLOR: (u8, u8, u8) = (255, 224, 138);
+
+/// Fill of the most read cells
+const HOT_COLOR: (u8, u8, u8) = (192, 57, 43);
+
+/// A cell on the heat map
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HeatCell {
+    /// ID of the cell
+    pub id: String,
+    
+    /// Axial coordinates of the cell
+    pub coordinates: (i32, i32),
+    
+    /// Reads of the cell since the hive was loaded
+    pub reads: u64,
+    
+    /// Pheromone left by the reads, which evaporates over time
+    pub pheromone: f64,
+    
+    /// Whether the cell is hot, warm or cold by its pheromone
+    pub temperature: Temperature,
+    
+    /// Size of the cell's content as stored
+    pub stored_bytes: usize,
+    
+    /// Whether the cell holds part of another cell's content
+    pub continuation: bool,
+}
+
+/// Occupancy and read frequency of every position of a grid
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HeatMap {
+    /// Name of the hive
+    pub hive: String,
+    
+    /// Grid dimensions (width, height)
+    pub dimensions: (usize, usize),
+    
+    /// Lowest coordinates inside the grid
+    pub min: (i32, i32),
+    
+    /// Reads of the most read cell
+    pub max_reads: u64,
+    
+    /// Cells of the grid, ordered by row and then column
+    pub cells: Vec<HeatCell>,
+}
+
+impl HeatMap {
+    /// Collect the heat map of a hive
+    pub fn collect(hive: &Hive) -> Result<Self, HiveError> {
+        let mut cells = Vec::with_capacity(hive.cells.cell_count());
+        for cell_arc in hive.cells.stored_cells() {
+            let cell = cell_arc.read().map_err(|_| HiveError::LockError)?;
+            cells.push(HeatCell {
+                id: cell.id.clone(),
+                coordinates: cell.coordinates,
+                reads: cell.read_count(),
+                pheromone: cell.pheromone(),
+                temperature: cell.temperature(),
+                stored_bytes: cell.data.content.len(),
+                continuation: cell.is_continuation(),
+            });
+        }
+        cells.sort_by_key(|cell| (cell.coordinates.1, cell.coordinates.0));
+        
+        Ok(Self {
+            hive: hive.name.clone(),
+            dimensions: hive.cells.dimensions(),
+            min: hive.cells.bounds().0,
+            max_reads: cells.iter().map(|cell| cell.reads).max().unwrap_or(0),
+            cells,
+        })
+    }
+    
+    /// Fraction of the grid's positions holding a cell
+    pub fn occupancy(&self) -> f64 {
+        let capacity = self.dimensions.0 * self.dimensions.1;
+        if capacity == 0 {
+            0.0
+        } else {
+            self.cells.len() as f64 / capacity as f64
+        }
+    }
+    
+    /// Draw the heat map as an SVG document
+    ///
+    /// Cells are pointy-top hexagons laid out by their axial coordinates,
+    /// so the grid shows as a rhombus. Their fill goes from pale honey for
+    /// unread cells to red for the most read ones; hovering a cell shows
+    /// its ID and reads. Empty positions are drawn only on grids of up to
+    /// `MAX_DRAWN_POSITIONS` positions.
+    pub fn to_svg(&self) -> String {
+        let (width, height) = self.dimensions;
+        let step = 3f64.sqrt() * HEX_SIZE;
+        let span = (width.max(1) - 1) as f64 + (height.max(1) - 1) as f64 / 2.0;
+        let svg_width = step * (span + 1.0);
+        let svg_height = 1.5 * HEX_SIZE * (height.max(1) - 1) as f64 + 2.0 * HEX_SIZE;
+        let center = |(q, r): (i32, i32)| {
+            let (q, r) = ((q - self.min.0) as f64, (r - self.min.1) as f64);
+            (step * (q + r / 2.0) + step / 2.0, 1.5 * HEX_SIZE * r + HEX_SIZE)
+        };
+        
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{:.0}" height="{:.0}" viewBox="0 0 {:.1} {:.1}">"#,
+            svg_width.ceil(), svg_height.ceil(), svg_width, svg_height
+        );
+        let _ = writeln!(svg, "<title>{}</title>", escape(&self.hive));
+        
+        if width * height <= MAX_DRAWN_POSITIONS {
+            let occupied: HashSet<(i32, i32)> = self.cells.iter().map(|cell| cell.coordinates).collect();
+            for r in self.min.1..self.min.1 + height as i32 {
+                for q in self.min.0..self.min.0 + width as i32 {
+                    if !occupied.contains(&(q, r)) {
+                        let _ = writeln!(svg, r##"<polygon points="{}" fill="{}" stroke="#dddddd"/>"##, hexagon(center((q, r))), EMPTY_COLOR);
+                    }
+                }
+            }
+        }
+        
+        for cell in &self.cells {
+            let heat = if self.max_reads == 0 { 0.0 } else { cell.reads as f64 / self.max_reads as f64 };
+            let _ = writeln!(
+                svg,
+                r##"<polygon points="{}" fill="{}" stroke="#8a6d1d"><title>{}: {} reads</title></polygon>"##,
+                hexagon(center(cell.coordinates)), color(heat), escape(&cell.id), cell.reads
+            );
+        }
+        
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+/// List the corners of a pointy-top hexagon around a center, as SVG points
+fn hexagon((x, y): (f64, f64)) -> String {
+    (0..6)
+        .map(|corner| {
+            let angle = (60.0 * corner as f64 - 30.0).to_radians();
+            format!("{:.1},{:.1}", x + HEX_SIZE * angle.cos(), y + HEX_SIZE * angle.sin())
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Get the fill of a cell whose reads are `heat` times those of the most read cell
+fn color(heat: f64) -> String {
+    let mix = |cold: u8, hot: u8| (cold as f64 + (hot as f64 - cold as f64) * heat.clamp(0.0, 1.0)).round() as u8;
+    format!(
+        "#{:02x}{:02x}{:02x}",
+        mix(COLD_COLOR.0, HOT_COLOR.0), mix(COLD_COLOR.1, HOT_COLOR.1), mix(COLD_COLOR.2, HOT_COLOR.2)
+    )
+}
+
+/// Escape text for SVG content
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::cell::{Cell, CellDataType};
+    use tempfile::tempdir;
+    
+    #[test]
+    fn test_heat_map() {
+        let temp_dir = tempdir().unwrap();
+        let mut hive = Hive::new(
+            "heat".to_string(),
+            String::new(),
+            "test-user".to_string(),
+            temp_dir.path().to_path_buf(),
+            (4, 3),
+        ).unwrap();
+        hive.add_cell(Cell::new("a".to_string(), (0, 0), CellDataType::Json, b"{}".to_vec(), false).unwrap()).unwrap();
+        hive.add_cell(Cell::new("b<1>".to_string(), (2, 1), CellDataType::Json, b"{}".to_vec(), false).unwrap()).unwrap();
+        for _ in 0..3 {
+            hive.get_cell((2, 1)).unwrap().read().unwrap().record_read();
+        }
+        
+        let map = HeatMap::collect(&hive).unwrap();
+        assert_eq!(map.max_reads, 3);
+        assert_eq!(map.cells[0].id, "a");
+        assert_eq!(map.cells[1].reads, 3);
+        assert_eq!(map.occupancy(), 2.0 / 12.0);
+        
+        let svg = map.to_svg();
+        assert_eq!(svg.matches("<polygon").count(), 12);
+        assert!(svg.contains("<title>b&lt;1&gt;: 3 reads</title>"));
+        assert!(svg.contains(&format!(r#"fill="{}""#, color(1.0))));
+    }
+    
+    #[test]
+    fn test_color_scale() {
+        assert_eq!(color(0.0), "#ffe08a");
+        assert_eq!(color(1.0), "#c0392b");
+        assert_eq!(color(7.0), color(1.0));
+    }
+}
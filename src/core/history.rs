@@ -0,0 +1,261 @@
+// HiveDB History Module
+//
+// This module keeps a history of hive statistics, so growth can be
+// followed over days and weeks. The server samples every hive at an
+// interval and stores each sample as a JSON cell of an internal hive;
+// samples older than the retention period, or the oldest ones once that
+// hive is full, make room for new ones.
+
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use crate::core::cell::{Cell, CellDataType};
+use crate::core::error::HiveError;
+use crate::core::hive::{Hive, HiveManager};
+use crate::utils::determinism;
+
+/// Name of the hive holding the samples
+pub const HISTORY_HIVE: &str = "_stats_history";
+
+/// Default time between two samples of every hive
+pub const DEFAULT_SAMPLE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Default age after which samples are dropped (90 days)
+pub const DEFAULT_RETENTION: Duration = Duration::from_secs(90 * 24 * 60 * 60);
+
+/// Dimensions of the history hive, which bound the samples it keeps
+const HISTORY_DIMENSIONS: (usize, usize) = (128, 128);
+
+/// Statistics of a hive at one point in time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatsSample {
+    /// ID of the hive
+    pub hive_id: String,
+    
+    /// Name of the hive
+    pub hive: String,
+    
+    /// When the sample was taken (seconds since the UNIX epoch)
+    pub timestamp: u64,
+    
+    /// Version of the hive, which grows by one with every write
+    pub version: u64,
+    
+    /// Number of cells
+    pub cell_count: usize,
+    
+    /// Size of the cell contents as stored
+    pub stored_bytes: u64,
+    
+    /// Size of the cell contents after decompression
+    pub raw_bytes: u64,
+    
+    /// Total size of the files in the hive's storage directory
+    pub bytes_on_disk: u64,
+    
+    /// Reads of the hive's cells since the hive was loaded
+    pub reads: u64,
+}
+
+impl StatsSample {
+    /// Take a sample of a hive
+    pub fn collect(hive: &Hive, timestamp: u64) -> Result<Self, HiveError> {
+        let stats = hive.stats()?;
+        let mut reads = 0;
+        for cell_arc in hive.cells.stored_cells() {
+            reads += cell_arc.read().map_err(|_| HiveError::LockError)?.read_count();
+        }
+        
+        Ok(Self {
+            hive_id: stats.id,
+            hive: stats.name,
+            timestamp,
+            version: stats.version,
+            cell_count: stats.cell_count,
+            stored_bytes: stats.stored_bytes,
+            raw_bytes: stats.raw_bytes,
+            bytes_on_disk: stats.bytes_on_disk,
+            reads,
+        })
+    }
+    
+    /// Get the ID of the cell holding this sample
+    fn cell_id(&self) -> String {
+        format!("{}@{}", self.hive_id, self.timestamp)
+    }
+}
+
+/// Change of a hive between two samples
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Trend {
+    /// Hours between the samples
+    pub hours: f64,
+    
+    /// Cells added, or removed if negative
+    pub cells: i64,
+    
+    /// Stored bytes added, or removed if negative
+    pub stored_bytes: i64,
+    
+    /// Bytes on disk added, or removed if negative
+    pub bytes_on_disk: i64,
+    
+    /// Writes to the hive
+    pub writes: u64,
+    
+    /// Reads of the hive's cells
+    pub reads: u64,
+}
+
+impl Trend {
+    /// Get the change from an earlier sample to a later one
+    ///
+    /// Read counts start over when the server restarts, so when they went
+    /// down, the reads of the later sample are all counted.
+    pub fn between(earlier: &StatsSample, later: &StatsSample) -> Self {
+        let delta = |from: u64, to: u64| to as i64 - from as i64;
+        
+        Self {
+            hours: later.timestamp.saturating_sub(earlier.timestamp) as f64 / 3600.0,
+            cells: delta(earlier.cell_count as u64, later.cell_count as u64),
+            stored_bytes: delta(earlier.stored_bytes, later.stored_bytes),
+            bytes_on_disk: delta(earlier.bytes_on_disk, later.bytes_on_disk),
+            writes: later.version.saturating_sub(earlier.version),
+            reads: later.reads.checked_sub(earlier.reads).unwrap_or(later.reads),
+        }
+    }
+    
+    /// Get a change per hour, or zero for samples taken at the same time
+    pub fn per_hour(&self, change: f64) -> f64 {
+        if self.hours > 0.0 { change / self.hours } else { 0.0 }
+    }
+}
+
+/// Get the history hive of a manager, creating it if needed
+pub fn open(manager: &mut HiveManager) -> Result<Arc<RwLock<Hive>>, HiveError> {
+    if let Some(history) = manager.get_hive_by_name(HISTORY_HIVE) {
+        return Ok(history);
+    }
+    
+    let id = manager.create_hive(
+        HISTORY_HIVE.to_string(),
+        "Statistics history of the other hives".to_string(),
+        "hivedb".to_string(),
+        HISTORY_DIMENSIONS,
+    )?;
+    manager.get_hive(&id).ok_or(HiveError::HiveNotFound)
+}
+
+/// Sample every hive of a manager into the history hive
+///
+/// Samples older than the retention period are dropped first. Returns
+/// the number of samples recorded.
+pub fn record(manager: &HiveManager, history: &RwLock<Hive>, retention: Duration) -> Result<usize, HiveError> {
+    let timestamp = determinism::unix_time();
+    let history_id = history.read().map_err(|_| HiveError::LockError)?.id.clone();
+    
+    let mut samples = Vec::new();
+    for (id, _) in manager.list_hives() {
+        if id == history_id {
+            continue;
+        }
+        if let Some(hive_arc) = manager.get_hive(&id) {
+            let hive = hive_arc.read().map_err(|_| HiveError::LockError)?;
+            samples.push(StatsSample::collect(&hive, timestamp)?);
+        }
+    }
+    samples.sort_by(|a, b| a.hive.cmp(&b.hive));
+    
+    let mut history = history.write().map_err(|_| HiveError::LockError)?;
+    let mut stored: Vec<(u64, (i32, i32))> = Vec::with_capacity(history.cell_count());
+    for cell_arc in history.cells.all_cells() {
+        let cell = cell_arc.read().map_err(|_| HiveError::LockError)?;
+        let taken_at = cell.id.rsplit_once('@').and_then(|(_, timestamp)| timestamp.parse().ok());
+        stored.push((taken_at.unwrap_or(0), cell.coordinates));
+    }
+    stored.sort();
+    
+    let cutoff = timestamp.saturating_sub(retention.as_secs());
+    let mut oldest = stored.into_iter().peekable();
+    while let Some((_, coordinates)) = oldest.next_if(|(taken_at, _)| *taken_at < cutoff) {
+        history.remove_cell(coordinates)?;
+    }
+    
+    let mut recorded = 0;
+    for sample in samples {
+        let id = sample.cell_id();
+        if history.find_cell_by_id(&id).is_some() {
+            continue;
+        }
+        let coordinates = match history.free_coordinates() {
+            Some(coordinates) => coordinates,
+            None => {
+                let (_, coordinates) = oldest.next().ok_or(HiveError::OutOfBoundsError)?;
+                history.remove_cell(coordinates)?;
+                coordinates
+            }
+        };
+        let content = serde_json::to_vec(&sample)
+            .map_err(|e| HiveError::SerializationError(e.to_string()))?;
+        history.add_cell(Cell::new(id, coordinates, CellDataType::Json, content, false)?)?;
+        recorded += 1;
+    }
+    
+    history.commit()?;
+    Ok(recorded)
+}
+
+/// Get the samples of a hive, given by name or ID, oldest first
+pub fn samples(history: &Hive, hive: &str) -> Result<Vec<StatsSample>, HiveError> {
+    let mut samples = Vec::new();
+    for cell_arc in history.cells.all_cells() {
+        let content = cell_arc.read().map_err(|_| HiveError::LockError)?.get_content()?;
+        let sample: StatsSample = serde_json::from_slice(&content)
+            .map_err(|e| HiveError::DeserializationError(e.to_string()))?;
+        if sample.hive == hive || sample.hive_id == hive {
+            samples.push(sample);
+        }
+    }
+    samples.sort_by_key(|sample| sample.timestamp);
+    Ok(samples)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+    
+    #[test]
+    fn test_record_history() {
+        let (_guard, clock) = determinism::deterministic(7);
+        let dir = tempdir().unwrap();
+        let mut manager = HiveManager::new(dir.path().to_path_buf()).unwrap();
+        let id = manager.create_hive("orders".to_string(), String::new(), "test".to_string(), (8, 8)).unwrap();
+        let history = open(&mut manager).unwrap();
+        
+        assert_eq!(record(&manager, &history, DEFAULT_RETENTION).unwrap(), 1);
+        clock.advance(Duration::from_secs(2 * 60 * 60));
+        {
+            let hive_arc = manager.get_hive(&id).unwrap();
+            let mut hive = hive_arc.write().unwrap();
+            hive.add_cell(Cell::new("a".to_string(), (0, 0), CellDataType::Binary, vec![1; 16], false).unwrap()).unwrap();
+            hive.get_cell((0, 0)).unwrap().read().unwrap().record_read();
+        }
+        assert_eq!(record(&manager, &history, DEFAULT_RETENTION).unwrap(), 1);
+        
+        let samples = samples(&history.read().unwrap(), "orders").unwrap();
+        assert_eq!(samples.len(), 2);
+        let trend = Trend::between(&samples[0], &samples[1]);
+        assert_eq!(trend.hours, 2.0);
+        assert_eq!(trend.cells, 1);
+        assert_eq!(trend.stored_bytes, 16);
+        assert_eq!((trend.writes, trend.reads), (1, 1));
+        assert_eq!(trend.per_hour(trend.reads as f64), 0.5);
+        
+        // Samples past the retention period make room for new ones
+        clock.advance(Duration::from_secs(60 * 60));
+        assert_eq!(record(&manager, &history, Duration::from_secs(90 * 60)).unwrap(), 1);
+        assert_eq!(history.read().unwrap().cell_count(), 2);
+        assert!(Arc::ptr_eq(&open(&mut manager).unwrap(), &history));
+    }
+}
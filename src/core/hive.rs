@@ -4,14 +4,42 @@
 // for data in the HiveDB system, similar to a database in traditional systems.
 
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, RwLock};
 use serde::{Deserialize, Serialize};
-use crate::core::cell::{Cell, CellDataType, CellGrid};
+use crate::core::analyze::{self, HiveStatistics};
+use crate::core::cell::{Cell, CellDataType, CellGrid, Edge, DEFAULT_SPLIT_THRESHOLD};
+use crate::core::change::{ChangeKind, ChangeLog, DEFAULT_CHANGE_LOG_CAPACITY};
+use crate::core::codec::Integrity;
+use crate::core::comb::{CombReader, CombWriter, RecordKind};
+use crate::core::commit::GroupCommit;
+use crate::core::compression::{self, CompressionDictionary, DEFAULT_DICTIONARY_SIZE};
+use crate::core::coords::GridOrigin;
+use crate::core::durability::{self, Durability};
 use crate::core::error::HiveError;
+use crate::core::events::{EventBus, EventKind};
+use crate::core::heatmap::HeatMap;
+use crate::core::index::{IndexSet, SecondaryIndex};
+use crate::core::remote::SnapshotStore;
 use crate::core::schema::Schema;
+use crate::core::snapshot::{self, SnapshotCodec, FORMAT_VERSION};
+use crate::core::stats::{HiveStats, ManagerStats};
+use crate::core::pheromone::Temperature;
+use crate::core::tiering::{ColdTier, OffloadReport, TieringPolicy};
+use crate::core::worker;
+use crate::core::verify::{self, VerifyReport};
+use crate::core::Config;
+use crate::utils::determinism;
+use crate::utils::telemetry;
 use log::{debug, info, warn};
-use rand::Rng;
+
+/// Name of the snapshot file in a hive's storage directory
+pub const SNAPSHOT_FILE: &str = "hive.json";
+
+/// Name of the lock file in a hive manager's base directory
+pub const LOCK_FILE: &str = "LOCK";
 
 /// Initialize the hive subsystem
 pub fn init() -> Result<(), HiveError> {
@@ -50,6 +78,81 @@ pub struct Hive {
     
     /// Additional metadata for this hive
     pub metadata: HiveMetadata,
+    
+    /// Ordered log of the changes applied to this hive
+    pub changes: ChangeLog,
+    
+    /// Data distribution of the collections, as of the last `ANALYZE`
+    pub statistics: HiveStatistics,
+    
+    /// Secondary indexes declared by the schema
+    pub indexes: IndexSet,
+    
+    /// When changes to this hive are saved and synced to disk
+    pub durability: Durability,
+    
+    /// Saves of this hive, shared with the writers waiting on them
+    group: Arc<GroupCommit>,
+    
+    /// Object store every snapshot is also written to, if any
+    pub remote: Option<SnapshotStore>,
+    
+    /// Bus of the manager this hive belongs to, if any
+    pub events: Option<EventBus>,
+}
+
+/// On-disk snapshot of a hive
+#[derive(Serialize, Deserialize)]
+struct HiveSnapshot {
+    id: String,
+    name: String,
+    description: String,
+    created_at: u64,
+    modified_at: u64,
+    schema: Option<Schema>,
+    dimensions: (usize, usize),
+    metadata: HiveMetadata,
+    cells: Vec<Cell>,
+    #[serde(default)]
+    statistics: HiveStatistics,
+    #[serde(default = "default_split_threshold")]
+    split_threshold: usize,
+    #[serde(default)]
+    origin: GridOrigin,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    dictionary: Option<CompressionDictionary>,
+    #[serde(default)]
+    integrity: Integrity,
+    #[serde(default)]
+    next_change: u64,
+}
+
+/// A copy of the state of a hive, to be encoded as a snapshot file
+pub struct SnapshotCopy {
+    /// The state of the hive
+    snapshot: HiveSnapshot,
+    
+    /// Name of the hive's storage directory
+    directory: String,
+}
+
+impl SnapshotCopy {
+    /// Get the version of the hive this copy was taken at
+    pub fn version(&self) -> u64 {
+        self.snapshot.metadata.version
+    }
+    
+    /// Get the name of the hive's storage directory
+    pub fn directory(&self) -> &str {
+        &self.directory
+    }
+    
+    /// Encode this copy as a snapshot file
+    pub fn encode(&self) -> Result<Vec<u8>, HiveError> {
+        let body = serde_json::to_vec(&self.snapshot)
+            .map_err(|e| HiveError::SerializationError(e.to_string()))?;
+        snapshot::encode(&body, SnapshotCodec::default())
+    }
 }
 
 /// Metadata for a Hive
@@ -69,6 +172,11 @@ pub struct HiveMetadata {
 }
 
 impl Hive {
+    /// Start building a hive, naming each setting instead of passing them in order
+    pub fn builder() -> HiveBuilder {
+        HiveBuilder::default()
+    }
+    
     /// Create a new hive with the given name
     pub fn new(
         name: String,
@@ -77,10 +185,7 @@ impl Hive {
         storage_path: PathBuf,
         dimensions: (usize, usize),
     ) -> Result<Self, HiveError> {
-        let now = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .map_err(|_| HiveError::SystemTimeError)?
-            .as_secs();
+        let now = determinism::unix_time();
         
         // Generate a unique ID for this hive
         let id = generate_hive_id();
@@ -100,35 +205,315 @@ impl Hive {
                 tags: Vec::new(),
                 properties: HashMap::new(),
             },
+            changes: ChangeLog::default(),
+            statistics: HiveStatistics::default(),
+            indexes: IndexSet::default(),
+            durability: Durability::default(),
+            group: Arc::new(GroupCommit::new(0)),
+            remote: None,
+            events: None,
         })
     }
     
     /// Add a cell to this hive
     pub fn add_cell(&mut self, cell: Cell) -> Result<(), HiveError> {
+        let cell_id = cell.id.clone();
+        let coordinates = cell.coordinates;
+        let data_type = cell.data.data_type.clone();
+        let content = cell.get_content()?;
+        
         self.cells.add_cell(cell)?;
+        self.indexes.index_cell(&cell_id, data_type, &content);
         self.metadata.version += 1;
         self.update_modified_time()?;
+        
+        self.changes.record(
+            &self.id,
+            ChangeKind::CellInserted,
+            Some(cell_id),
+            Some(coordinates),
+            Some(content.to_vec()),
+        )?;
         Ok(())
     }
     
+    /// Add a cell whose content is read from a stream, at the first free coordinates
+    ///
+    /// The content is compressed and split over continuation cells as it
+    /// is read, so large payloads never sit in memory twice. Only JSON
+    /// documents are read back whole, to index them and to carry them in
+    /// the change log. Returns the coordinates of the new cell.
+    pub fn insert_from_reader(
+        &mut self,
+        id: String,
+        data_type: CellDataType,
+        mut reader: impl std::io::Read,
+        compress: bool,
+    ) -> Result<(i32, i32), HiveError> {
+        let coordinates = self.free_coordinates().ok_or(HiveError::OutOfBoundsError)?;
+        let size = self.cells.add_from_reader(id.clone(), coordinates, data_type.clone(), &mut reader, compress)?;
+        
+        let content = match data_type {
+            CellDataType::Json => {
+                let cell_arc = self.cells.get_cell(coordinates).ok_or(HiveError::CellNotFound)?;
+                let content = cell_arc.read().map_err(|_| HiveError::LockError)?.get_content()?;
+                Some(content)
+            }
+            _ => None,
+        };
+        self.indexes.index_cell(&id, data_type, content.as_deref().unwrap_or_default());
+        self.metadata.version += 1;
+        self.update_modified_time()?;
+        
+        self.changes.record(
+            &self.id,
+            ChangeKind::CellInserted,
+            Some(id.clone()),
+            Some(coordinates),
+            content.map(|content| content.to_vec()),
+        )?;
+        debug!("Streamed {} stored bytes into cell '{}' of hive '{}'", size, id, self.name);
+        Ok(coordinates)
+    }
+    
     /// Get a cell from this hive
     pub fn get_cell(&self, coordinates: (i32, i32)) -> Option<Arc<RwLock<Cell>>> {
         self.cells.get_cell(coordinates)
     }
     
+    /// Get a cell from this hive by its ID
+    pub fn find_cell_by_id(&self, id: &str) -> Option<Arc<RwLock<Cell>>> {
+        self.cells.find_by_id(id)
+    }
+    
+    /// Get the first unoccupied coordinates in this hive, if any
+    pub fn free_coordinates(&self) -> Option<(i32, i32)> {
+        self.cells.first_free_coordinates()
+    }
+    
     /// Remove a cell from this hive
     pub fn remove_cell(&mut self, coordinates: (i32, i32)) -> Result<Cell, HiveError> {
         let cell = self.cells.remove_cell(coordinates)?;
+        self.indexes.remove_cell(&cell.id);
         self.metadata.version += 1;
         self.update_modified_time()?;
+        
+        self.changes.record(
+            &self.id,
+            ChangeKind::CellRemoved,
+            Some(cell.id.clone()),
+            Some(coordinates),
+            None,
+        )?;
         Ok(cell)
     }
     
+    /// Replace the content of a cell in this hive
+    pub fn update_cell(
+        &mut self,
+        coordinates: (i32, i32),
+        new_content: Vec<u8>,
+        compress: bool,
+    ) -> Result<(), HiveError> {
+        let cell_arc = self.cells.get_cell(coordinates)
+            .ok_or(HiveError::CellNotFound)?;
+        
+        let (cell_id, data_type) = {
+            let cell = cell_arc.read().map_err(|_| HiveError::LockError)?;
+            (cell.id.clone(), cell.data.data_type.clone())
+        };
+        self.cells.update_cell(coordinates, new_content.clone(), compress)?;
+        
+        self.indexes.index_cell(&cell_id, data_type, &new_content);
+        self.metadata.version += 1;
+        self.update_modified_time()?;
+        
+        self.changes.record(
+            &self.id,
+            ChangeKind::CellUpdated,
+            Some(cell_id),
+            Some(coordinates),
+            Some(new_content),
+        )?;
+        Ok(())
+    }
+    
+    /// Set the edge from a cell of this hive to a neighbor
+    pub fn set_edge(&mut self, from: (i32, i32), to: (i32, i32), edge: Edge) -> Result<(), HiveError> {
+        self.cells.set_edge(from, to, edge)?;
+        self.metadata.version += 1;
+        self.update_modified_time()
+    }
+    
+    /// Remove the edge from a cell of this hive to a neighbor, returning it if there was one
+    pub fn remove_edge(&mut self, from: (i32, i32), to: (i32, i32)) -> Result<Option<Edge>, HiveError> {
+        let edge = self.cells.remove_edge(from, to)?;
+        if edge.is_some() {
+            self.metadata.version += 1;
+            self.update_modified_time()?;
+        }
+        Ok(edge)
+    }
+    
+    /// Pack the cells of this hive toward the center of its grid
+    ///
+    /// After many deletes the cells are scattered; moving them together
+    /// shortens scans. Cells keep their IDs, so indexes stay valid, and
+    /// neighbor links follow the moves. `progress` is called with the
+    /// number of cells moved so far and the number to move. Returns the
+    /// number of cells moved.
+    pub fn rebalance(&mut self, mut progress: impl FnMut(usize, usize)) -> Result<usize, HiveError> {
+        let plan = self.cells.rebalance_plan();
+        let total = plan.len();
+        
+        for (moved, (from, to)) in plan.into_iter().enumerate() {
+            self.cells.move_cell(from, to)?;
+            
+            let cell_id = self.cells.get_cell(to)
+                .map(|cell| cell.read().map(|cell| cell.id.clone()).map_err(|_| HiveError::LockError))
+                .transpose()?;
+            self.changes.record(&self.id, ChangeKind::CellMoved, cell_id, Some(to), None)?;
+            progress(moved + 1, total);
+        }
+        
+        if total > 0 {
+            self.metadata.version += 1;
+            self.update_modified_time()?;
+            info!("Rebalanced hive '{}': moved {} cells", self.name, total);
+        }
+        Ok(total)
+    }
+    
+    /// Train a compression dictionary over up to `samples` JSON documents of this hive
+    ///
+    /// Compressed JSON cells written afterwards use the dictionary; cells
+    /// already stored keep their compression until they are next written.
+    pub fn train_dictionary(&mut self, samples: usize) -> Result<Arc<CompressionDictionary>, HiveError> {
+        let mut documents = Vec::new();
+        for cell_arc in self.cells.all_cells() {
+            if documents.len() >= samples {
+                break;
+            }
+            let cell = cell_arc.read().map_err(|_| HiveError::LockError)?;
+            if cell.data.data_type == CellDataType::Json {
+                documents.push(cell.get_content()?);
+            }
+        }
+        
+        let dictionary = compression::register(compression::train(&documents, DEFAULT_DICTIONARY_SIZE)?)?;
+        self.cells.set_dictionary(Some(dictionary.clone()));
+        self.metadata.version += 1;
+        self.update_modified_time()?;
+        
+        info!("Trained a {} byte dictionary over {} documents of hive '{}'", dictionary.data.len(), documents.len(), self.name);
+        Ok(dictionary)
+    }
+    
+    /// Move the content of cells nobody read or wrote lately to the cold tier
+    ///
+    /// Hot cells stay on local disk however long ago they were last read,
+    /// since their pheromone shows steady use. Objects are keyed by hive,
+    /// cell and checksum, so rewritten content never reuses the object of
+    /// an older version.
+    pub fn offload_cold(&mut self, policy: &TieringPolicy, tier: &ColdTier) -> Result<OffloadReport, HiveError> {
+        let now = determinism::unix_time();
+        
+        let mut cold = Vec::new();
+        for cell_arc in self.cells.all_cells() {
+            let cell = cell_arc.read().map_err(|_| HiveError::LockError)?;
+            if !cell.is_cold() && cell.temperature() != Temperature::Hot
+                && policy.is_cold(cell.metadata.size_bytes, cell.last_active(), now) {
+                let key = format!("{}/{}/{}", self.id, hex::encode(cell.id.as_bytes()), cell.data.checksum);
+                cold.push((cell.coordinates, key));
+            }
+        }
+        
+        let mut report = OffloadReport::default();
+        for (coordinates, key) in cold {
+            report.bytes += self.cells.offload(coordinates, key, tier)?;
+            report.cells += 1;
+        }
+        
+        // The content did not change, but the snapshot must now name the objects
+        if report.cells > 0 {
+            self.metadata.version += 1;
+            info!("Moved {} cells ({} bytes) of hive '{}' to the cold tier", report.cells, report.bytes, self.name);
+            self.publish(EventKind::CellsOffloaded { cells: report.cells, bytes: report.bytes });
+        }
+        Ok(report)
+    }
+    
     /// Set the schema for this hive
+    ///
+    /// The indexes the schema declares are rebuilt over all cells.
     pub fn set_schema(&mut self, schema: Schema) -> Result<(), HiveError> {
+        let content = serde_json::to_vec(&schema)
+            .map_err(|e| HiveError::SerializationError(e.to_string()))?;
+        
+        self.indexes.rebuild(Some(&schema), &self.cells)?;
         self.schema = Some(schema);
         self.metadata.version += 1;
         self.update_modified_time()?;
+        
+        self.changes.record(&self.id, ChangeKind::SchemaChanged, None, None, Some(content))?;
+        self.publish(EventKind::SchemaChanged { version: self.metadata.version });
+        Ok(())
+    }
+    
+    /// Add a built index to this hive and declare it in the schema
+    ///
+    /// An index of the same name is replaced. The hive must have a schema.
+    pub fn install_index(&mut self, index: SecondaryIndex) -> Result<(), HiveError> {
+        let schema = self.schema.as_mut()
+            .ok_or_else(|| HiveError::SchemaValidationError("Hive has no schema".to_string()))?;
+        schema.indexes.retain(|existing| existing.name != index.definition().name);
+        schema.add_index(index.definition().clone());
+        let content = serde_json::to_vec(&schema)
+            .map_err(|e| HiveError::SerializationError(e.to_string()))?;
+        
+        self.indexes.insert(index);
+        self.metadata.version += 1;
+        self.update_modified_time()?;
+        
+        self.changes.record(&self.id, ChangeKind::SchemaChanged, None, None, Some(content))?;
+        self.publish(EventKind::SchemaChanged { version: self.metadata.version });
+        Ok(())
+    }
+    
+    /// Rebuild an index declared by the schema from the current cells
+    ///
+    /// Returns the number of indexed documents.
+    pub fn rebuild_index(&mut self, name: &str) -> Result<usize, HiveError> {
+        let definition = self.schema.as_ref()
+            .and_then(|schema| schema.indexes.iter().find(|index| index.name == name))
+            .cloned()
+            .ok_or_else(|| HiveError::IndexNotFound(name.to_string()))?;
+        
+        let entries = self.indexes.rebuild_index(&definition, &self.cells)?;
+        info!("Rebuilt index '{}' of hive '{}' ({} documents)", name, self.name, entries);
+        Ok(entries)
+    }
+    
+    /// Drop an index and remove it from the schema
+    ///
+    /// A build of the index still running in the background is cancelled.
+    pub fn drop_index(&mut self, name: &str) -> Result<(), HiveError> {
+        let removed = self.indexes.remove(name);
+        let declared = self.schema.as_mut()
+            .filter(|schema| schema.indexes.iter().any(|index| index.name == name));
+        let Some(schema) = declared else {
+            return if removed { Ok(()) } else { Err(HiveError::IndexNotFound(name.to_string())) };
+        };
+        
+        schema.indexes.retain(|index| index.name != name);
+        let content = serde_json::to_vec(&schema)
+            .map_err(|e| HiveError::SerializationError(e.to_string()))?;
+        self.metadata.version += 1;
+        self.update_modified_time()?;
+        
+        self.changes.record(&self.id, ChangeKind::SchemaChanged, None, None, Some(content))?;
+        self.publish(EventKind::SchemaChanged { version: self.metadata.version });
+        info!("Dropped index '{}' of hive '{}'", name, self.name);
         Ok(())
     }
     
@@ -189,27 +574,344 @@ impl Hive {
     
     /// Update the modified time for this hive
     fn update_modified_time(&mut self) -> Result<(), HiveError> {
-        let now = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .map_err(|_| HiveError::SystemTimeError)?
-            .as_secs();
+        let now = determinism::unix_time();
         
         self.modified_at = now;
         Ok(())
     }
     
+    /// Collect statistics about this hive
+    pub fn stats(&self) -> Result<HiveStats, HiveError> {
+        HiveStats::collect(self)
+    }
+    
+    /// Collect which positions hold cells and how often each cell was read
+    pub fn heat_map(&self) -> Result<HeatMap, HiveError> {
+        HeatMap::collect(self)
+    }
+    
+    /// Refresh the statistics of one collection, or of all collections if none is given
+    ///
+    /// Returns the number of collections analyzed.
+    pub fn analyze(&mut self, collection: Option<&str>) -> Result<usize, HiveError> {
+        let collections = analyze::collect(self, collection, analyze::DEFAULT_SAMPLE_SIZE)?;
+        let count = collections.len();
+        
+        if collection.is_none() {
+            self.statistics.collections.clear();
+        }
+        self.statistics.collections.extend(collections);
+        info!("Analyzed {} collection(s) of hive '{}'", count, self.name);
+        
+        Ok(count)
+    }
+    
+    /// Check the integrity of this hive, repairing what can be repaired if asked to
+    pub fn verify(&mut self, repair: bool) -> Result<VerifyReport, HiveError> {
+        verify::verify_hive(self, repair)
+    }
+    
     /// Save this hive to storage
+    ///
+    /// The whole hive is written as a snapshot in its storage directory,
+    /// and to the snapshot store if the hive has one.
     pub fn save(&self) -> Result<(), HiveError> {
-        // TODO: Implement actual storage logic
         info!("Saving hive '{}' to {}", self.name, self.storage_path.display());
+        
+        let mut span = telemetry::span("hive.save");
+        span.set_attribute("hivedb.hive", &self.name);
+        span.record(self.write_snapshot())
+    }
+    
+    /// Copy the state of this hive for a snapshot
+    ///
+    /// Cell contents are shared rather than copied, so this is quick. The
+    /// copy is consistent as long as the hive is locked while it is taken,
+    /// and can be encoded after the lock is released, so writers only wait
+    /// for the copy and not for serialization or I/O.
+    pub fn copy_snapshot(&self) -> Result<SnapshotCopy, HiveError> {
+        let mut cells = Vec::with_capacity(self.cell_count());
+        for cell_arc in self.cells.stored_cells() {
+            cells.push(cell_arc.read().map_err(|_| HiveError::LockError)?.clone());
+        }
+        cells.sort_by_key(|cell| (cell.coordinates.1, cell.coordinates.0));
+        
+        let snapshot = HiveSnapshot {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            description: self.description.clone(),
+            created_at: self.created_at,
+            modified_at: self.modified_at,
+            schema: self.schema.clone(),
+            dimensions: self.cells.dimensions(),
+            metadata: self.metadata.clone(),
+            cells,
+            statistics: self.statistics.clone(),
+            split_threshold: self.cells.split_threshold(),
+            origin: self.cells.origin(),
+            dictionary: self.cells.dictionary().map(|dictionary| (**dictionary).clone()),
+            integrity: self.cells.integrity(),
+            next_change: self.changes.next_sequence(),
+        };
+        
+        Ok(SnapshotCopy {
+            snapshot,
+            directory: self.storage_path.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        })
+    }
+    
+    /// Write the snapshot file of this hive
+    fn write_snapshot(&self) -> Result<(), HiveError> {
+        let data = self.copy_snapshot()?.encode()?;
+        
+        std::fs::create_dir_all(&self.storage_path)
+            .map_err(|e| HiveError::IoError(e.to_string()))?;
+        
+        // A crash never leaves a partial snapshot
+        durability::replace_file(&self.storage_path.join(SNAPSHOT_FILE), &data, self.durability.syncs())?;
+        
+        // The save only counts once the object store has it too
+        if let Some(remote) = &self.remote {
+            let uploaded = remote.upload(&self.storage_path, &data);
+            if let Some(events) = &self.events {
+                events.report_replication(Some(&self.id), uploaded.as_ref().map(|_| ()));
+            }
+            uploaded?;
+        }
+        
+        self.group.record_save(self.metadata.version);
         Ok(())
     }
     
+    /// Publish an event about this hive on its manager's bus, if any
+    fn publish(&self, kind: EventKind) {
+        if let Some(events) = &self.events {
+            events.publish(Some(&self.id), kind);
+        }
+    }
+    
+    /// Check whether this hive changed since it was last saved
+    pub fn is_dirty(&self) -> bool {
+        self.group.saved_version() != self.metadata.version
+    }
+    
+    /// Get the saves of this hive, to wait on once the hive is unlocked
+    pub fn group_commit(&self) -> Arc<GroupCommit> {
+        self.group.clone()
+    }
+    
+    /// Make the changes acknowledged so far as durable as the durability policy asks
+    ///
+    /// Under `Always` the hive is saved now; other policies leave the save
+    /// to the periodic flush or to shutdown. Concurrent writers should take a
+    /// `CommitTicket` instead, so they can share a save once the hive is unlocked.
+    pub fn commit(&self) -> Result<(), HiveError> {
+        match self.durability {
+            Durability::Always if self.is_dirty() => self.save(),
+            _ => Ok(()),
+        }
+    }
+    
     /// Load a hive from storage
     pub fn load(path: PathBuf) -> Result<Self, HiveError> {
-        // TODO: Implement actual loading logic
         info!("Loading hive from {}", path.display());
-        Err(HiveError::NotImplemented)
+        
+        let mut span = telemetry::span("hive.load");
+        span.set_attribute("hivedb.path", path.display());
+        span.record(Self::read_snapshot(path))
+    }
+    
+    /// Read a hive from the snapshot file in a directory
+    fn read_snapshot(path: PathBuf) -> Result<Self, HiveError> {
+        let data = std::fs::read(path.join(SNAPSHOT_FILE))
+            .map_err(|e| HiveError::IoError(e.to_string()))?;
+        let (header, body) = snapshot::decode(&data)?;
+        let mut snapshot: HiveSnapshot = serde_json::from_value(body)
+            .map_err(|e| HiveError::DeserializationError(e.to_string()))?;
+        
+        // Snapshots in an older format count as unsaved, so the next save rewrites them
+        let version = match header.version {
+            FORMAT_VERSION => snapshot.metadata.version,
+            old => {
+                info!("Hive '{}' has a format version {} snapshot, upgrading it on the next save", snapshot.name, old);
+                0
+            }
+        };
+        
+        let cells = std::mem::take(&mut snapshot.cells);
+        Self::from_snapshot(snapshot, cells.into_iter().map(Ok), path, version)
+    }
+    
+    /// Export this hive to a honeycomb archive file; see `write_comb`
+    pub fn export_comb(&self, path: &Path) -> Result<(), HiveError> {
+        let file = File::create(path)
+            .map_err(|e| HiveError::IoError(e.to_string()))?;
+        self.write_comb(BufWriter::new(file))?
+            .flush()
+            .map_err(|e| HiveError::IoError(e.to_string()))
+    }
+    
+    /// Write this hive as a honeycomb archive, returning the writer
+    ///
+    /// The archive is written from a copy taken as for a snapshot, one
+    /// cell at a time, so the hive is only locked while the copy is taken.
+    pub fn write_comb<W: Write>(&self, writer: W) -> Result<W, HiveError> {
+        let mut manifest = self.copy_snapshot()?.snapshot;
+        let cells = std::mem::take(&mut manifest.cells);
+        
+        let mut comb = CombWriter::new(writer)?;
+        comb.write_record(RecordKind::Manifest, &manifest)?;
+        for cell in &cells {
+            comb.write_record(RecordKind::Cell, cell)?;
+        }
+        comb.finish()
+    }
+    
+    /// Import a hive from a honeycomb archive file; see `read_comb`
+    pub fn import_comb(path: &Path, storage_path: PathBuf) -> Result<Self, HiveError> {
+        let file = File::open(path)
+            .map_err(|e| HiveError::IoError(e.to_string()))?;
+        Self::read_comb(BufReader::new(file), storage_path)
+    }
+    
+    /// Read a hive from a honeycomb archive, to be stored in a directory
+    ///
+    /// The hive keeps its ID and counts as unsaved until it is saved to
+    /// its directory. Cells are added as they are read, and indexes are
+    /// rebuilt from the schema once the archive is checked to be complete.
+    pub fn read_comb<R: Read>(reader: R, storage_path: PathBuf) -> Result<Self, HiveError> {
+        let mut comb = CombReader::new(reader)?;
+        let mut manifest: HiveSnapshot = comb.read(RecordKind::Manifest)?
+            .ok_or_else(|| HiveError::DeserializationError("Honeycomb archive holds no hive".to_string()))?;
+        
+        let inline = std::mem::take(&mut manifest.cells);
+        let cells = inline.into_iter().map(Ok).chain(std::iter::from_fn(|| comb.read(RecordKind::Cell).transpose()));
+        Self::from_snapshot(manifest, cells, storage_path, 0)
+    }
+    
+    /// Build a hive from a snapshot and its cells
+    ///
+    /// `saved_version` is the version of the hive last saved to `path`.
+    fn from_snapshot(
+        snapshot: HiveSnapshot,
+        stored: impl IntoIterator<Item = Result<Cell, HiveError>>,
+        path: PathBuf,
+        saved_version: u64,
+    ) -> Result<Self, HiveError> {
+        // Stored cells go back as they were, without splitting them again
+        let mut cells = CellGrid::new(snapshot.dimensions);
+        cells.set_split_threshold(usize::MAX);
+        cells.set_origin(snapshot.origin)?;
+        cells.set_integrity(snapshot.integrity);
+        for cell in stored {
+            cells.add_cell(cell?)?;
+        }
+        cells.set_split_threshold(snapshot.split_threshold);
+        if let Some(dictionary) = snapshot.dictionary {
+            cells.set_dictionary(Some(compression::register(dictionary)?));
+        }
+        let indexes = IndexSet::build(snapshot.schema.as_ref(), &cells)?;
+        
+        Ok(Self {
+            id: snapshot.id,
+            name: snapshot.name,
+            description: snapshot.description,
+            created_at: snapshot.created_at,
+            modified_at: snapshot.modified_at,
+            schema: snapshot.schema,
+            cells,
+            storage_path: path,
+            metadata: snapshot.metadata,
+            changes: ChangeLog::resume(DEFAULT_CHANGE_LOG_CAPACITY, snapshot.next_change),
+            statistics: snapshot.statistics,
+            indexes,
+            durability: Durability::default(),
+            group: Arc::new(GroupCommit::new(saved_version)),
+            remote: None,
+            events: None,
+        })
+    }
+}
+
+/// Builds a hive; see `Hive::builder`
+///
+/// A name, an owner and a storage path are required, unless the hive is
+/// created through `HiveManager::create_hive_from`, which picks the path.
+/// Dimensions default to those of `Config::default`.
+#[derive(Debug, Clone, Default)]
+pub struct HiveBuilder {
+    name: Option<String>,
+    description: String,
+    owner: Option<String>,
+    storage_path: Option<PathBuf>,
+    dimensions: Option<(usize, usize)>,
+    schema: Option<Schema>,
+    durability: Option<Durability>,
+}
+
+impl HiveBuilder {
+    /// Set the name of the hive
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+    
+    /// Set the description of the hive
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = description.into();
+        self
+    }
+    
+    /// Set the owner of the hive
+    pub fn owner(mut self, owner: impl Into<String>) -> Self {
+        self.owner = Some(owner.into());
+        self
+    }
+    
+    /// Set the directory the hive is saved in
+    pub fn storage_path(mut self, storage_path: impl Into<PathBuf>) -> Self {
+        self.storage_path = Some(storage_path.into());
+        self
+    }
+    
+    /// Set the grid dimensions (width, height)
+    pub fn dimensions(mut self, width: usize, height: usize) -> Self {
+        self.dimensions = Some((width, height));
+        self
+    }
+    
+    /// Set the schema, building the indexes it declares
+    pub fn schema(mut self, schema: Schema) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+    
+    /// Set when changes to the hive are saved and synced to disk
+    pub fn durability(mut self, durability: Durability) -> Self {
+        self.durability = Some(durability);
+        self
+    }
+    
+    /// Create the hive
+    pub fn build(self) -> Result<Hive, HiveError> {
+        let missing = |setting: &str| HiveError::GenericError(format!("A hive needs a {}", setting));
+        let name = self.name.ok_or_else(|| missing("name"))?;
+        let owner = self.owner.ok_or_else(|| missing("owner"))?;
+        let storage_path = self.storage_path.ok_or_else(|| missing("storage path"))?;
+        let dimensions = self.dimensions.unwrap_or(Config::default().grid_dimensions);
+        
+        let mut hive = Hive::new(name, self.description, owner, storage_path, dimensions)?;
+        if let Some(schema) = self.schema {
+            hive.indexes = IndexSet::build(Some(&schema), &hive.cells)?;
+            hive.schema = Some(schema);
+        }
+        if let Some(durability) = self.durability {
+            hive.durability = durability;
+        }
+        
+        Ok(hive)
     }
 }
 
@@ -220,15 +922,101 @@ pub struct HiveManager {
     
     /// Base storage path for all hives
     base_path: PathBuf,
+    
+    /// When changes to the hives are saved and synced to disk
+    durability: Durability,
+    
+    /// Object store the snapshots are kept in, if any
+    remote: Option<SnapshotStore>,
+    
+    /// Bus the manager and its hives publish events on
+    events: EventBus,
+    
+    /// Dimensions of hives created without their own
+    default_dimensions: (usize, usize),
+    
+    /// Lock file holding the exclusive lock on the base path
+    _lock: File,
 }
 
 impl HiveManager {
+    /// Start building a hive manager, naming each setting
+    pub fn builder() -> HiveManagerBuilder {
+        HiveManagerBuilder::default()
+    }
+    
     /// Create a new hive manager
-    pub fn new(base_path: PathBuf) -> Self {
-        Self {
+    ///
+    /// The manager takes an exclusive advisory lock on the base path,
+    /// held until it is dropped, so that two processes never work on the
+    /// same hives. Fails with `DirectoryLocked` if another manager holds it.
+    pub fn new(base_path: PathBuf) -> Result<Self, HiveError> {
+        std::fs::create_dir_all(&base_path)
+            .map_err(|e| HiveError::IoError(e.to_string()))?;
+        
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(base_path.join(LOCK_FILE))
+            .map_err(|e| HiveError::IoError(e.to_string()))?;
+        match lock.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                return Err(HiveError::DirectoryLocked(base_path.display().to_string()));
+            }
+            Err(TryLockError::Error(e)) => return Err(HiveError::IoError(e.to_string())),
+        }
+        
+        Ok(Self {
             hives: HashMap::new(),
             base_path,
+            durability: Durability::default(),
+            remote: None,
+            events: EventBus::default(),
+            default_dimensions: Config::default().grid_dimensions,
+            _lock: lock,
+        })
+    }
+    
+    /// Set when changes to the hives are saved and synced to disk
+    ///
+    /// The policy applies to the hives already open and to those created or loaded later.
+    pub fn with_durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        for hive_arc in self.hives.values() {
+            if let Ok(mut hive) = hive_arc.write() {
+                hive.durability = durability;
+            }
+        }
+        self
+    }
+    
+    /// Get when changes to the hives are saved and synced to disk
+    pub fn durability(&self) -> Durability {
+        self.durability
+    }
+    
+    /// Keep the snapshots of the hives in an object store
+    ///
+    /// The base path then only caches the snapshots: `load_all` fetches
+    /// them from the store, and every save is written through to it.
+    pub fn with_snapshot_store(mut self, store: SnapshotStore) -> Self {
+        for hive_arc in self.hives.values() {
+            if let Ok(mut hive) = hive_arc.write() {
+                hive.remote = Some(store.clone());
+            }
         }
+        self.remote = Some(store);
+        self
+    }
+    
+    /// Get the bus the manager and its hives publish events on
+    ///
+    /// Subscribe to it to follow hives being created, loaded and deleted,
+    /// schema changes, cold tier offloads and snapshot store outages.
+    pub fn events(&self) -> &EventBus {
+        &self.events
     }
     
     /// Create a new hive
@@ -239,19 +1027,40 @@ impl HiveManager {
         owner: String,
         dimensions: (usize, usize),
     ) -> Result<String, HiveError> {
+        self.create_hive_from(
+            Hive::builder()
+                .name(name)
+                .description(description)
+                .owner(owner)
+                .dimensions(dimensions.0, dimensions.1),
+        )
+    }
+    
+    /// Create a new hive from a builder
+    ///
+    /// The hive is stored under the base path, whatever storage path the
+    /// builder names. Settings the builder leaves out come from the manager:
+    /// its durability and the dimensions of its configuration.
+    pub fn create_hive_from(&mut self, builder: HiveBuilder) -> Result<String, HiveError> {
         // Create a storage path for this hive
-        let hive_path = self.base_path.join(sanitize_name(&name));
+        let hive_path = self.base_path.join(sanitize_name(builder.name.as_deref().unwrap_or_default()));
+        let (width, height) = builder.dimensions.unwrap_or(self.default_dimensions);
+        let durability = builder.durability.unwrap_or(self.durability);
         
         // Create the hive
-        let hive = Hive::new(
-            name,
-            description,
-            owner,
-            hive_path.clone(),
-            dimensions,
-        )?;
+        let mut hive = builder
+            .storage_path(hive_path.clone())
+            .dimensions(width, height)
+            .durability(durability)
+            .build()?;
+        hive.remote = self.remote.clone();
+        hive.events = Some(self.events.clone());
+        if let Some(remote) = &self.remote {
+            remote.add_hives(&[&hive_path])?;
+        }
         
         let hive_id = hive.id.clone();
+        let name = hive.name.clone();
         
         // Add the hive to our map
         self.hives.insert(hive_id.clone(), Arc::new(RwLock::new(hive)));
@@ -263,6 +1072,7 @@ impl HiveManager {
         }
         
         info!("Created new hive '{}' with ID {}", name, hive_id);
+        self.events.publish(Some(&hive_id), EventKind::HiveCreated { name });
         
         Ok(hive_id)
     }
@@ -302,8 +1112,12 @@ impl HiveManager {
             std::fs::remove_dir_all(&hive.storage_path)
                 .map_err(|e| HiveError::IoError(e.to_string()))?;
         }
+        if let Some(remote) = &self.remote {
+            remote.remove_hive(&hive.storage_path)?;
+        }
         
         info!("Deleted hive '{}' with ID {}", hive.name, id);
+        self.events.publish(Some(id), EventKind::HiveDeleted { name: hive.name });
         
         Ok(())
     }
@@ -321,6 +1135,18 @@ impl HiveManager {
             .collect()
     }
     
+    /// Collect statistics about every hive and the caches they share
+    ///
+    /// Like `Hive::stats`, this reads every cell of every hive.
+    pub fn stats(&self) -> Result<ManagerStats, HiveError> {
+        let mut hives = Vec::with_capacity(self.hives.len());
+        for hive_arc in self.hives.values() {
+            hives.push(hive_arc.read().map_err(|_| HiveError::LockError)?.stats()?);
+        }
+        
+        Ok(ManagerStats::collect(hives))
+    }
+    
     /// Save all hives
     pub fn save_all(&self) -> Result<(), HiveError> {
         for (id, hive_arc) in &self.hives {
@@ -334,24 +1160,178 @@ impl HiveManager {
         Ok(())
     }
     
+    /// Save the hives that changed since they were last saved
+    ///
+    /// Returns the number of hives saved.
+    pub fn flush(&self) -> Result<usize, HiveError> {
+        let mut saved = 0;
+        for hive_arc in self.hives.values() {
+            let hive = hive_arc.read().map_err(|_| HiveError::LockError)?;
+            if hive.is_dirty() {
+                hive.save()?;
+                saved += 1;
+            }
+        }
+        
+        Ok(saved)
+    }
+    
+    /// Move the content of cold cells of every hive to the cold tier
+    ///
+    /// Between hives, the move pauses to stay under the background rate limit.
+    pub fn offload_cold(&self, policy: &TieringPolicy, tier: &ColdTier) -> Result<OffloadReport, HiveError> {
+        let mut report = OffloadReport::default();
+        for hive_arc in self.hives.values() {
+            let offloaded = {
+                let mut hive = hive_arc.write().map_err(|_| HiveError::LockError)?;
+                let offloaded = hive.offload_cold(policy, tier)?;
+                hive.commit()?;
+                offloaded
+            };
+            worker::throttle(offloaded.bytes as u64);
+            report.cells += offloaded.cells;
+            report.bytes += offloaded.bytes;
+        }
+        
+        Ok(report)
+    }
+    
     /// Load all hives from the base path
+    ///
+    /// Every directory holding a hive snapshot is loaded; other entries
+    /// are ignored. With a snapshot store, the snapshots are first brought
+    /// up to date with it; if it cannot be reached, the cached ones load.
     pub fn load_all(&mut self) -> Result<(), HiveError> {
-        // TODO: Implement actual loading logic
         info!("Loading all hives from {}", self.base_path.display());
+        
+        if let Some(remote) = &self.remote {
+            let pulled = remote.pull(&self.base_path);
+            match &pulled {
+                Ok(downloaded) => info!("Fetched {} snapshot(s) from the snapshot store", downloaded),
+                Err(e) => warn!("Could not fetch snapshots from the snapshot store, loading cached ones: {}", e),
+            }
+            self.events.report_replication(None, pulled.as_ref().map(|_| ()));
+        }
+        
+        if !self.base_path.exists() {
+            return Ok(());
+        }
+        
+        let entries = std::fs::read_dir(&self.base_path)
+            .map_err(|e| HiveError::IoError(e.to_string()))?;
+        
+        let mut loaded = Vec::new();
+        for entry in entries {
+            let path = entry.map_err(|e| HiveError::IoError(e.to_string()))?.path();
+            if !path.join(SNAPSHOT_FILE).is_file() {
+                continue;
+            }
+            
+            let mut hive = Hive::load(path)?;
+            hive.durability = self.durability;
+            hive.remote = self.remote.clone();
+            hive.events = Some(self.events.clone());
+            loaded.push(hive.storage_path.clone());
+            debug!("Loaded hive '{}' with {} cells", hive.name, hive.cell_count());
+            self.events.publish(Some(&hive.id), EventKind::HiveLoaded { name: hive.name.clone() });
+            self.hives.insert(hive.id.clone(), Arc::new(RwLock::new(hive)));
+        }
+        
+        // Hives kept only locally until now join the store on their next save
+        if let Some(remote) = &self.remote {
+            let loaded: Vec<_> = loaded.iter().map(PathBuf::as_path).collect();
+            if let Err(e) = remote.add_hives(&loaded) {
+                warn!("Could not list the hives in the snapshot store: {}", e);
+            }
+        }
+        
         Ok(())
     }
 }
 
+/// Builds a hive manager; see `HiveManager::builder`
+///
+/// Only the base path is required. The configuration's durability and
+/// grid dimensions apply to every hive the manager opens or creates.
+#[derive(Debug, Default)]
+pub struct HiveManagerBuilder {
+    base_path: Option<PathBuf>,
+    config: Config,
+    snapshot_store: Option<SnapshotStore>,
+    event_history: Option<usize>,
+    load: bool,
+}
+
+impl HiveManagerBuilder {
+    /// Set the directory the hives are stored in
+    pub fn base_path(mut self, base_path: impl Into<PathBuf>) -> Self {
+        self.base_path = Some(base_path.into());
+        self
+    }
+    
+    /// Set the configuration of the hives
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+    
+    /// Set when changes to the hives are saved and synced to disk
+    pub fn durability(mut self, durability: Durability) -> Self {
+        self.config.durability = durability;
+        self
+    }
+    
+    /// Keep the snapshots of the hives in an object store
+    pub fn snapshot_store(mut self, store: SnapshotStore) -> Self {
+        self.snapshot_store = Some(store);
+        self
+    }
+    
+    /// Set the number of events the event bus keeps for pollers
+    pub fn event_history(mut self, capacity: usize) -> Self {
+        self.event_history = Some(capacity);
+        self
+    }
+    
+    /// Load the hives already in the base path when the manager is built
+    pub fn load_existing(mut self, load: bool) -> Self {
+        self.load = load;
+        self
+    }
+    
+    /// Create the manager, taking the lock on its base path
+    pub fn build(self) -> Result<HiveManager, HiveError> {
+        let base_path = self.base_path
+            .ok_or_else(|| HiveError::GenericError("A hive manager needs a base path".to_string()))?;
+        
+        let mut manager = HiveManager::new(base_path)?.with_durability(self.config.durability);
+        manager.default_dimensions = self.config.grid_dimensions;
+        if let Some(capacity) = self.event_history {
+            manager.events = EventBus::new(capacity);
+        }
+        if let Some(store) = self.snapshot_store {
+            manager = manager.with_snapshot_store(store);
+        }
+        if self.load {
+            manager.load_all()?;
+        }
+        
+        Ok(manager)
+    }
+}
+
+/// Split threshold of hives saved before cells could be split
+fn default_split_threshold() -> usize {
+    DEFAULT_SPLIT_THRESHOLD
+}
+
 /// Generate a unique ID for a hive
 fn generate_hive_id() -> String {
-    let mut rng = rand::thread_rng();
-    let random_bytes: Vec<u8> = (0..16).map(|_| rng.gen::<u8>()).collect();
-    
-    format!("hive-{}", hex::encode(random_bytes))
+    format!("hive-{}", hex::encode(determinism::random_bytes(16)))
 }
 
 /// Sanitize a name for use in a file path
-fn sanitize_name(name: &str) -> String {
+pub(crate) fn sanitize_name(name: &str) -> String {
     name.chars()
         .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
         .collect()
@@ -360,6 +1340,7 @@ fn sanitize_name(name: &str) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
     use tempfile::tempdir;
     
     #[test]
@@ -419,10 +1400,523 @@ mod tests {
         assert_eq!(hive.get_property("category"), Some(&"test".to_string()));
     }
     
+    #[test]
+    fn test_hive_deterministic() {
+        let temp_dir = tempdir().unwrap();
+        let build = || {
+            let (_guard, clock) = determinism::deterministic(7);
+            let mut hive = Hive::new(
+                "test-hive".to_string(),
+                String::new(),
+                "test-user".to_string(),
+                temp_dir.path().to_path_buf(),
+                (8, 8),
+            ).unwrap();
+            
+            clock.advance(std::time::Duration::from_secs(60));
+            hive.set_property("stage".to_string(), "seeded".to_string()).unwrap();
+            (hive.id.clone(), hive.created_at, hive.modified_at)
+        };
+        
+        let (id, created_at, modified_at) = build();
+        assert_eq!(created_at, determinism::DETERMINISTIC_START.as_secs());
+        assert_eq!(modified_at, created_at + 60);
+        assert_eq!(build(), (id, created_at, modified_at));
+    }
+    
+    #[test]
+    fn test_hive_save_and_load() {
+        let temp_dir = tempdir().unwrap();
+        let mut manager = HiveManager::new(temp_dir.path().to_path_buf()).unwrap();
+        let id = manager.create_hive(
+            "orders".to_string(),
+            "Customer orders".to_string(),
+            "test-user".to_string(),
+            (16, 16),
+        ).unwrap();
+        
+        {
+            let hive_arc = manager.get_hive(&id).unwrap();
+            let mut hive = hive_arc.write().unwrap();
+            hive.set_property("region".to_string(), "eu".to_string()).unwrap();
+            hive.cells.set_origin(GridOrigin::Center).unwrap();
+            for i in 0..3 {
+                let cell = Cell::new(
+                    format!("cell-{}", i),
+                    (i, 0),
+                    CellDataType::Json,
+                    format!("{{\"n\": {}}}", i).into_bytes(),
+                    true,
+                ).unwrap();
+                hive.add_cell(cell).unwrap();
+            }
+            
+            hive.cells.set_split_threshold(8);
+            hive.cells.set_integrity(Integrity::Crc32c);
+            let big = Cell::new(
+                "big".to_string(),
+                hive.free_coordinates().unwrap(),
+                CellDataType::Json,
+                b"{\"text\": \"larger than one cell\"}".to_vec(),
+                false,
+            ).unwrap();
+            hive.add_cell(big).unwrap();
+        }
+        manager.save_all().unwrap();
+        drop(manager);
+        
+        let mut reloaded = HiveManager::new(temp_dir.path().to_path_buf()).unwrap();
+        reloaded.load_all().unwrap();
+        
+        let hive_arc = reloaded.get_hive_by_name("orders").unwrap();
+        let hive = hive_arc.read().unwrap();
+        assert_eq!(hive.id, id);
+        assert_eq!(hive.cell_count(), 4);
+        assert_eq!(hive.cells.dimensions(), (16, 16));
+        assert_eq!(hive.cells.split_threshold(), 8);
+        assert_eq!(hive.cells.integrity(), Integrity::Crc32c);
+        assert_eq!(hive.cells.bounds(), ((-8, -8), (7, 7)));
+        assert_eq!(hive.get_property("region"), Some(&"eu".to_string()));
+        
+        let cell = hive.find_cell_by_id("cell-2").unwrap();
+        assert_eq!(cell.read().unwrap().get_content().unwrap(), b"{\"n\": 2}".to_vec());
+        
+        let big = hive.find_cell_by_id("big").unwrap();
+        assert_eq!(big.read().unwrap().continuations.len(), 3);
+        assert_eq!(big.read().unwrap().get_content().unwrap(), b"{\"text\": \"larger than one cell\"}".to_vec());
+        assert!(big.read().unwrap().data.checksum.starts_with("crc32c:"));
+    }
+    
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+        
+        #[test]
+    fn test_change_sequence_survives_reload() {
+        let temp_dir = tempdir().unwrap();
+        let mut hive = Hive::new(
+            "orders".to_string(),
+            String::new(),
+            "test-user".to_string(),
+            temp_dir.path().to_path_buf(),
+            (8, 8),
+        ).unwrap();
+        for i in 0..3 {
+            hive.add_cell(Cell::new(format!("cell-{}", i), (i, 0), CellDataType::Json, b"{}".to_vec(), false).unwrap()).unwrap();
+        }
+        hive.save().unwrap();
+        
+        // A consumer that read up to 2 before the restart is told it missed event 3
+        let mut reloaded = Hive::load(temp_dir.path().to_path_buf()).unwrap();
+        assert_eq!(reloaded.changes.next_sequence(), 4);
+        assert!(matches!(reloaded.changes.since(2), Err(HiveError::ChangeLogTruncated(2))));
+        
+        // One that read everything carries on from the next event
+        reloaded.add_cell(Cell::new("cell-3".to_string(), (3, 0), CellDataType::Json, b"{}".to_vec(), false).unwrap()).unwrap();
+        let events = reloaded.changes.since(3).unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].sequence, 4);
+    }
+    
+    #[test]
+        fn prop_hive_save_and_load(
+            cells in prop::collection::btree_map(
+                (0i32..16, 0i32..16),
+                (prop::collection::vec(any::<u8>(), 0..256), any::<bool>()),
+                0..8,
+            ),
+        ) {
+            let temp_dir = tempdir().unwrap();
+            let mut hive = Hive::new(
+                "test-hive".to_string(),
+                String::new(),
+                "test-user".to_string(),
+                temp_dir.path().to_path_buf(),
+                (16, 16),
+            ).unwrap();
+            hive.cells.set_split_threshold(64);
+            
+            // Continuations of large cells may already hold some coordinates
+            let mut added = Vec::new();
+            for (i, (coordinates, (content, compress))) in cells.into_iter().enumerate() {
+                if hive.get_cell(coordinates).is_some() {
+                    continue;
+                }
+                let id = format!("cell-{}", i);
+                hive.add_cell(Cell::new(id.clone(), coordinates, CellDataType::Binary, content.clone(), compress).unwrap()).unwrap();
+                added.push((id, content));
+            }
+            hive.save().unwrap();
+            
+            let reloaded = Hive::load(temp_dir.path().to_path_buf()).unwrap();
+            prop_assert_eq!(reloaded.cell_count(), hive.cell_count());
+            for (id, content) in added {
+                let original = hive.find_cell_by_id(&id).unwrap();
+                let cell = reloaded.find_cell_by_id(&id).unwrap();
+                let cell = cell.read().unwrap();
+                prop_assert_eq!(cell.get_content().unwrap().to_vec(), content);
+                prop_assert_eq!(&cell.data.checksum, &original.read().unwrap().data.checksum);
+            }
+        }
+    }
+    
+    #[test]
+    fn test_crash_recovery() {
+        use crate::core::durability::crash::{self, CrashPoint};
+        
+        let add = |hive: &mut Hive, from: i32, to: i32| {
+            for i in from..to {
+                let content = format!("cell {}", i).repeat(i as usize + 1).into_bytes();
+                hive.add_cell(Cell::new(format!("cell-{}", i), (i, i), CellDataType::Binary, content, i % 2 == 0).unwrap()).unwrap();
+            }
+        };
+        
+        // Save three cells, then crash while saving two more
+        let crash_and_recover = |point: CrashPoint| {
+            let temp_dir = tempdir().unwrap();
+            let mut hive = Hive::new(
+                "test-hive".to_string(),
+                String::new(),
+                "test-user".to_string(),
+                temp_dir.path().to_path_buf(),
+                (16, 16),
+            ).unwrap();
+            add(&mut hive, 0, 3);
+            hive.save().unwrap();
+            add(&mut hive, 3, 5);
+            let size = hive.copy_snapshot().unwrap().encode().unwrap().len();
+            
+            crash::plan(point);
+            assert!(hive.save().is_err());
+            drop(hive);
+            
+            let mut recovered = Hive::load(temp_dir.path().to_path_buf()).unwrap();
+            assert!(recovered.verify(true).unwrap().is_healthy());
+            assert!(!temp_dir.path().join(SNAPSHOT_FILE).with_extension("tmp").exists());
+            (recovered, size, temp_dir)
+        };
+        
+        let (_, size, _) = crash_and_recover(CrashPoint::BeforeRename);
+        let mut points: Vec<CrashPoint> = (0..=8).map(|i| CrashPoint::Writing(size * i / 8)).collect();
+        points.extend([CrashPoint::Writing(size - 1), CrashPoint::BeforeRename, CrashPoint::AfterRename]);
+        
+        for point in points {
+            let (mut recovered, _, temp_dir) = crash_and_recover(point);
+            
+            // The hive is as it was saved last, before or after the crash
+            let saved = if point == CrashPoint::AfterRename { 5 } else { 3 };
+            assert_eq!(recovered.cell_count(), saved, "{:?}", point);
+            for i in 0..saved {
+                let cell = recovered.find_cell_by_id(&format!("cell-{}", i)).unwrap();
+                let content = format!("cell {}", i).repeat(i + 1).into_bytes();
+                assert_eq!(cell.read().unwrap().get_content().unwrap(), content);
+            }
+            
+            // And saves again
+            add(&mut recovered, saved as i32, 6);
+            recovered.save().unwrap();
+            assert_eq!(Hive::load(temp_dir.path().to_path_buf()).unwrap().cell_count(), 6);
+        }
+    }
+    
+    #[test]
+    fn test_comb_export_and_import() {
+        use crate::core::schema::{IndexType, SchemaIndex};
+        
+        let temp_dir = tempdir().unwrap();
+        let mut hive = Hive::new(
+            "orders".to_string(),
+            "Customer orders".to_string(),
+            "test-user".to_string(),
+            temp_dir.path().join("orders"),
+            (16, 16),
+        ).unwrap();
+        let mut schema = Schema::new("orders".to_string(), String::new(), "1".to_string());
+        schema.add_index(SchemaIndex::new("by_sku".to_string(), vec!["sku".to_string()], IndexType::BTree, false));
+        hive.set_schema(schema).unwrap();
+        hive.cells.set_split_threshold(16);
+        for i in 0..4 {
+            let content = format!("{{\"sku\": \"sku-{}\", \"note\": \"{}\"}}", i, "x".repeat(i * 10)).into_bytes();
+            hive.add_cell(Cell::new(format!("order-{}", i), (i as i32, 0), CellDataType::Json, content, i % 2 == 0).unwrap()).unwrap();
+        }
+        
+        let archive = temp_dir.path().join("orders.comb");
+        hive.export_comb(&archive).unwrap();
+        let imported = Hive::import_comb(&archive, temp_dir.path().join("copy")).unwrap();
+        
+        assert_eq!(imported.id, hive.id);
+        assert_eq!(imported.name, "orders");
+        assert_eq!(imported.cell_count(), hive.cell_count());
+        assert_eq!(imported.cells.split_threshold(), 16);
+        assert_eq!(imported.indexes.get("by_sku").unwrap().len(), 4);
+        assert!(imported.is_dirty());
+        for i in 0..4 {
+            let id = format!("order-{}", i);
+            let original = hive.find_cell_by_id(&id).unwrap().read().unwrap().get_content().unwrap();
+            assert_eq!(imported.find_cell_by_id(&id).unwrap().read().unwrap().get_content().unwrap(), original);
+        }
+        
+        // A damaged archive is refused
+        let mut data = std::fs::read(&archive).unwrap();
+        let middle = data.len() / 2;
+        data[middle] ^= 0xff;
+        assert!(Hive::read_comb(&data[..], temp_dir.path().join("damaged")).is_err());
+    }
+    
+    #[test]
+    fn test_hive_change_log() {
+        let temp_dir = tempdir().unwrap();
+        let mut hive = Hive::new(
+            "test-hive".to_string(),
+            "A test hive".to_string(),
+            "test-user".to_string(),
+            temp_dir.path().to_path_buf(),
+            (64, 64),
+        ).unwrap();
+        
+        let cell = Cell::new(
+            "cell-1".to_string(),
+            (1, 1),
+            CellDataType::Json,
+            b"{\"n\": 1}".to_vec(),
+            true,
+        ).unwrap();
+        
+        hive.add_cell(cell).unwrap();
+        hive.update_cell((1, 1), b"{\"n\": 2}".to_vec(), true).unwrap();
+        hive.remove_cell((1, 1)).unwrap();
+        
+        let events = hive.changes.since(0).unwrap();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0].kind, ChangeKind::CellInserted);
+        assert_eq!(events[0].content, Some(b"{\"n\": 1}".to_vec()));
+        assert_eq!(events[1].kind, ChangeKind::CellUpdated);
+        assert_eq!(events[1].content, Some(b"{\"n\": 2}".to_vec()));
+        assert_eq!(events[2].kind, ChangeKind::CellRemoved);
+        assert_eq!(events[2].cell_id, Some("cell-1".to_string()));
+    }
+    
+    #[test]
+    fn test_hive_rebuild_and_drop_index() {
+        use crate::core::schema::{IndexType, SchemaIndex};
+        
+        let temp_dir = tempdir().unwrap();
+        let mut hive = Hive::new(
+            "test-hive".to_string(),
+            "A test hive".to_string(),
+            "test-user".to_string(),
+            temp_dir.path().to_path_buf(),
+            (64, 64),
+        ).unwrap();
+        
+        let mut schema = Schema::new("item".to_string(), String::new(), "1".to_string());
+        schema.add_index(SchemaIndex::new("by_n".to_string(), vec!["n".to_string()], IndexType::BTree, false));
+        hive.set_schema(schema).unwrap();
+        for i in 0..3 {
+            let cell = Cell::new(
+                format!("cell-{}", i),
+                (i, 0),
+                CellDataType::Json,
+                format!("{{\"n\": {}}}", i).into_bytes(),
+                true,
+            ).unwrap();
+            hive.add_cell(cell).unwrap();
+        }
+        
+        assert_eq!(hive.rebuild_index("by_n").unwrap(), 3);
+        assert!(matches!(hive.rebuild_index("missing"), Err(HiveError::IndexNotFound(_))));
+        
+        let version = hive.metadata.version;
+        hive.drop_index("by_n").unwrap();
+        assert!(hive.indexes.get("by_n").is_none());
+        assert!(hive.schema.as_ref().unwrap().indexes.is_empty());
+        assert_eq!(hive.metadata.version, version + 1);
+        assert!(matches!(hive.drop_index("by_n"), Err(HiveError::IndexNotFound(_))));
+    }
+    
+    #[test]
+    fn test_hive_insert_from_reader() {
+        let temp_dir = tempdir().unwrap();
+        let mut hive = Hive::new(
+            "streams".to_string(),
+            String::new(),
+            "test-user".to_string(),
+            temp_dir.path().to_path_buf(),
+            (8, 8),
+        ).unwrap();
+        hive.cells.set_split_threshold(16);
+        
+        let payload = vec![42u8; 100];
+        let coordinates = hive.insert_from_reader("blob".to_string(), CellDataType::Binary, &payload[..], false).unwrap();
+        assert_eq!(hive.cell_count(), 1);
+        assert_eq!(hive.get_cell(coordinates).unwrap().read().unwrap().get_content().unwrap(), payload);
+        
+        let document = br#"{"name": "streamed", "size": 2}"#;
+        hive.insert_from_reader("doc".to_string(), CellDataType::Json, &document[..], true).unwrap();
+        
+        let events = hive.changes.since(0).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].content, None);
+        assert_eq!(events[1].content.as_deref(), Some(&document[..]));
+    }
+    
+    #[test]
+    fn test_hive_dictionary_compression() {
+        let temp_dir = tempdir().unwrap();
+        let mut manager = HiveManager::new(temp_dir.path().to_path_buf()).unwrap();
+        let id = manager.create_hive("events".to_string(), String::new(), "test-user".to_string(), (32, 32)).unwrap();
+        let document = |i: usize| format!(
+            "{{\"event\": \"page_view\", \"user\": \"user-{}\", \"path\": \"/products/{}\", \"device\": \"mobile\"}}",
+            i % 23, i % 50
+        ).into_bytes();
+        
+        {
+            let hive_arc = manager.get_hive(&id).unwrap();
+            let mut hive = hive_arc.write().unwrap();
+            for i in 0..200 {
+                let cell = Cell::new(format!("e{}", i), hive.free_coordinates().unwrap(), CellDataType::Json, document(i), true).unwrap();
+                hive.add_cell(cell).unwrap();
+            }
+            
+            let dictionary = hive.train_dictionary(100).unwrap();
+            assert_eq!(dictionary.samples, 100);
+            
+            let cell = Cell::new("new".to_string(), hive.free_coordinates().unwrap(), CellDataType::Json, document(7), true).unwrap();
+            let lz4_size = cell.data.content.len();
+            hive.add_cell(cell).unwrap();
+            
+            let cell = hive.find_cell_by_id("new").unwrap();
+            assert_eq!(cell.read().unwrap().data.dictionary, Some(dictionary.id));
+            assert!(cell.read().unwrap().data.content.len() < lz4_size);
+            assert_eq!(hive.find_cell_by_id("e1").unwrap().read().unwrap().data.dictionary, None);
+        }
+        manager.save_all().unwrap();
+        drop(manager);
+        
+        let mut reloaded = HiveManager::new(temp_dir.path().to_path_buf()).unwrap();
+        reloaded.load_all().unwrap();
+        let hive_arc = reloaded.get_hive_by_name("events").unwrap();
+        let hive = hive_arc.read().unwrap();
+        assert!(hive.cells.dictionary().is_some());
+        assert_eq!(hive.find_cell_by_id("new").unwrap().read().unwrap().get_content().unwrap(), document(7));
+    }
+    
+    #[test]
+    fn test_hive_durability() {
+        let temp_dir = tempdir().unwrap();
+        let mut manager = HiveManager::new(temp_dir.path().to_path_buf()).unwrap()
+            .with_durability(Durability::Always);
+        let id = manager.create_hive("orders".to_string(), String::new(), "test-user".to_string(), (8, 8)).unwrap();
+        let hive_arc = manager.get_hive(&id).unwrap();
+        let snapshot = temp_dir.path().join("orders").join(SNAPSHOT_FILE);
+        
+        // Every commit saves the hive
+        {
+            let mut hive = hive_arc.write().unwrap();
+            assert!(hive.is_dirty());
+            hive.commit().unwrap();
+            assert!(!hive.is_dirty());
+            assert!(snapshot.exists());
+            
+            hive.add_cell(Cell::new("a".to_string(), (0, 0), CellDataType::Json, b"{}".to_vec(), false).unwrap()).unwrap();
+            assert!(hive.is_dirty());
+            hive.commit().unwrap();
+            assert!(!hive.is_dirty());
+        }
+        
+        // Buffered writes wait for a flush
+        let manager = manager.with_durability(Durability::Buffered);
+        {
+            let mut hive = hive_arc.write().unwrap();
+            assert_eq!(hive.durability, Durability::Buffered);
+            hive.add_cell(Cell::new("b".to_string(), (1, 0), CellDataType::Json, b"{}".to_vec(), false).unwrap()).unwrap();
+            hive.commit().unwrap();
+            assert!(hive.is_dirty());
+        }
+        assert_eq!(manager.flush().unwrap(), 1);
+        assert_eq!(manager.flush().unwrap(), 0);
+        assert!(!hive_arc.read().unwrap().is_dirty());
+    }
+    
+    #[test]
+    fn test_hive_snapshot_upgrade() {
+        let temp_dir = tempdir().unwrap();
+        let mut manager = HiveManager::new(temp_dir.path().to_path_buf()).unwrap();
+        let id = manager.create_hive("orders".to_string(), String::new(), "test-user".to_string(), (8, 8)).unwrap();
+        {
+            let hive_arc = manager.get_hive(&id).unwrap();
+            let mut hive = hive_arc.write().unwrap();
+            hive.add_cell(Cell::new("a".to_string(), (0, 0), CellDataType::Json, b"{}".to_vec(), false).unwrap()).unwrap();
+        }
+        manager.save_all().unwrap();
+        drop(manager);
+        
+        // Rewrite the snapshot as a bare JSON body, as releases before the header did
+        let path = temp_dir.path().join("orders").join(SNAPSHOT_FILE);
+        let data = std::fs::read(&path).unwrap();
+        assert!(data.starts_with(&snapshot::MAGIC));
+        let (_, body) = snapshot::decode(&data).unwrap();
+        std::fs::write(&path, serde_json::to_vec(&body).unwrap()).unwrap();
+        
+        let mut reloaded = HiveManager::new(temp_dir.path().to_path_buf()).unwrap();
+        reloaded.load_all().unwrap();
+        let hive_arc = reloaded.get_hive(&id).unwrap();
+        assert_eq!(hive_arc.read().unwrap().cell_count(), 1);
+        assert!(hive_arc.read().unwrap().is_dirty());
+        
+        assert_eq!(reloaded.flush().unwrap(), 1);
+        let (header, _) = snapshot::decode(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(header.version, FORMAT_VERSION);
+    }
+    
+    #[test]
+    fn test_hive_rebalance() {
+        let temp_dir = tempdir().unwrap();
+        let mut hive = Hive::new(
+            "sparse".to_string(),
+            String::new(),
+            "test-user".to_string(),
+            temp_dir.path().to_path_buf(),
+            (8, 8),
+        ).unwrap();
+        hive.cells.set_split_threshold(8);
+        
+        for (i, coordinates) in [(0, 0), (7, 7), (0, 7), (4, 4)].into_iter().enumerate() {
+            let content = format!("{{\"n\": {}, \"pad\": \"{}\"}}", i, "x".repeat(i * 4)).into_bytes();
+            let cell = Cell::new(format!("cell-{}", i), coordinates, CellDataType::Json, content, false).unwrap();
+            hive.add_cell(cell).unwrap();
+        }
+        let stored = hive.cells.stored_cells().len();
+        
+        let mut reports = Vec::new();
+        let moved = hive.rebalance(|moved, total| reports.push((moved, total))).unwrap();
+        assert!(moved > 0);
+        assert_eq!(reports.last(), Some(&(moved, moved)));
+        let moves = hive.changes.since(0).unwrap().into_iter()
+            .filter(|event| event.kind == ChangeKind::CellMoved)
+            .count();
+        assert_eq!(moves, moved);
+        
+        // Every stored cell now sits within the smallest ring around the center that holds them all
+        let center = hive.cells.center();
+        let mut distances: Vec<i32> = hive.cells.stored_cells().iter()
+            .map(|cell| crate::core::cell::hex_distance(cell.read().unwrap().coordinates, center))
+            .collect();
+        distances.sort();
+        assert_eq!(distances.len(), stored);
+        assert!(*distances.last().unwrap() <= 2);
+        
+        for i in 0..4 {
+            let cell = hive.find_cell_by_id(&format!("cell-{}", i)).unwrap();
+            let document: serde_json::Value = serde_json::from_slice(&cell.read().unwrap().get_content().unwrap()).unwrap();
+            assert_eq!(document["n"], i);
+        }
+        assert_eq!(hive.rebalance(|_, _| {}).unwrap(), 0);
+    }
+    
     #[test]
     fn test_hive_manager() {
         let temp_dir = tempdir().unwrap();
-        let mut manager = HiveManager::new(temp_dir.path().to_path_buf());
+        let mut manager = HiveManager::new(temp_dir.path().to_path_buf()).unwrap();
         
         // Create a hive
         let hive_id = manager.create_hive(
@@ -452,4 +1946,93 @@ mod tests {
         assert_eq!(hives[0].0, hive_id);
         assert_eq!(hives[0].1, "test-hive");
     }
+    
+    #[test]
+    fn test_hive_manager_directory_lock() {
+        let temp_dir = tempdir().unwrap();
+        let manager = HiveManager::new(temp_dir.path().to_path_buf()).unwrap();
+        
+        assert!(matches!(
+            HiveManager::new(temp_dir.path().to_path_buf()),
+            Err(HiveError::DirectoryLocked(_))
+        ));
+        
+        // The lock is released with the manager
+        drop(manager);
+        assert!(HiveManager::new(temp_dir.path().to_path_buf()).is_ok());
+    }
+    
+    #[test]
+    fn test_hive_builder() {
+        use crate::core::schema::{IndexType, SchemaIndex};
+        
+        let temp_dir = tempdir().unwrap();
+        let mut schema = Schema::new("item".to_string(), String::new(), "1".to_string());
+        schema.add_index(SchemaIndex::new("by_sku".to_string(), vec!["sku".to_string()], IndexType::BTree, false));
+        
+        let hive = Hive::builder()
+            .name("built")
+            .owner("test-user")
+            .storage_path(temp_dir.path().join("built"))
+            .dimensions(16, 8)
+            .schema(schema)
+            .durability(Durability::Always)
+            .build()
+            .unwrap();
+        assert_eq!(hive.cells.dimensions(), (16, 8));
+        assert_eq!(hive.metadata.version, 1);
+        assert!(hive.indexes.get("by_sku").is_some());
+        assert_eq!(hive.durability, Durability::Always);
+        
+        assert!(matches!(Hive::builder().owner("test-user").storage_path(temp_dir.path()).build(), Err(HiveError::GenericError(_))));
+        assert!(Hive::builder().name("no-owner").storage_path(temp_dir.path()).build().is_err());
+    }
+    
+    #[test]
+    fn test_hive_manager_builder() {
+        let temp_dir = tempdir().unwrap();
+        let config = Config {
+            grid_dimensions: (12, 12),
+            durability: Durability::Always,
+            ..Config::default()
+        };
+        let mut manager = HiveManager::builder()
+            .base_path(temp_dir.path())
+            .config(config)
+            .load_existing(true)
+            .build()
+            .unwrap();
+        assert_eq!(manager.durability(), Durability::Always);
+        
+        // The manager picks the path and fills in the dimensions
+        let hive_id = manager.create_hive_from(Hive::builder().name("a b").owner("test-user").storage_path("/elsewhere")).unwrap();
+        let hive_arc = manager.get_hive(&hive_id).unwrap();
+        let hive = hive_arc.read().unwrap();
+        assert_eq!(hive.storage_path, temp_dir.path().join("a_b"));
+        assert_eq!(hive.cells.dimensions(), (12, 12));
+        assert_eq!(hive.durability, Durability::Always);
+        
+        assert!(HiveManager::builder().build().is_err());
+    }
+    
+    #[test]
+    fn test_manager_events() {
+        let temp_dir = tempdir().unwrap();
+        let mut manager = HiveManager::new(temp_dir.path().to_path_buf()).unwrap();
+        let events = manager.events().subscribe().unwrap();
+        
+        let hive_id = manager.create_hive("events".to_string(), String::new(), "test-user".to_string(), (8, 8)).unwrap();
+        manager.get_hive(&hive_id).unwrap().write().unwrap()
+            .set_schema(Schema::new("item".to_string(), String::new(), "1".to_string()))
+            .unwrap();
+        manager.delete_hive(&hive_id).unwrap();
+        
+        let kinds: Vec<EventKind> = events.try_iter().map(|event| event.kind).collect();
+        assert_eq!(kinds, vec![
+            EventKind::HiveCreated { name: "events".to_string() },
+            EventKind::SchemaChanged { version: 2 },
+            EventKind::HiveDeleted { name: "events".to_string() },
+        ]);
+        assert_eq!(manager.events().since(0).unwrap()[1].hive_id, Some(hive_id));
+    }
 }
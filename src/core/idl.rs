@@ -0,0 +1,689 @@
+// HiveDB IDL Module
+//
+// This module builds hive schemas from the interface definition languages
+// other systems already describe their data in: Protocol Buffers `.proto`
+// files and Avro schemas. Message and record fields become schema fields,
+// with their scalar types mapped to `FieldType`s, nested messages to
+// objects and enums to strings limited to their symbols. Only the parts of
+// a `.proto` file that describe data are read; services, options and
+// extensions are skipped.
+
+use std::collections::HashMap;
+use serde_json::Value;
+use crate::core::error::HiveError;
+use crate::core::schema::{FieldType, Schema, SchemaField, ValidationRule};
+
+/// Version given to schemas built from an IDL
+const IMPORTED_VERSION: &str = "1";
+
+/// A word, string or symbol of a `.proto` file, with its line
+#[derive(Debug, Clone, PartialEq)]
+struct Token {
+    kind: TokenKind,
+    line: usize,
+}
+
+/// What a token is
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    /// An identifier, keyword or number
+    Word(String),
+    
+    /// A quoted string, without its quotes
+    Str(String),
+    
+    /// Punctuation such as `{` or `=`
+    Symbol(char),
+}
+
+/// How often a field of a message occurs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Label {
+    Singular,
+    Optional,
+    Required,
+    Repeated,
+}
+
+/// A field of a message
+#[derive(Debug, Clone)]
+struct ProtoField {
+    name: String,
+    label: Label,
+    type_name: String,
+    
+    /// Key and value types of a map field
+    map: Option<(String, String)>,
+}
+
+/// The messages and enums of a `.proto` file, by fully qualified name
+#[derive(Debug, Default)]
+struct ProtoFile {
+    package: Option<String>,
+    messages: HashMap<String, Vec<ProtoField>>,
+    enums: HashMap<String, Vec<String>>,
+}
+
+/// Reads the tokens of a `.proto` file
+struct ProtoParser {
+    tokens: Vec<Token>,
+    position: usize,
+    file: ProtoFile,
+}
+
+impl Schema {
+    /// Build a schema from a message of a `.proto` file
+    ///
+    /// The message is named as in the file, or qualified with its package
+    /// or enclosing messages. Proto2 `required` fields are required;
+    /// proto3 fields never are.
+    pub fn from_proto(source: &str, message: &str) -> Result<Self, HiveError> {
+        let file = ProtoParser::new(source)?.parse()?;
+        let name = file.resolve(message, "")
+            .filter(|name| file.messages.contains_key(name))
+            .ok_or_else(|| HiveError::SchemaValidationError(format!("Message '{}' is not defined", message)))?;
+        
+        let short_name = name.rsplit('.').next().unwrap_or(&name).to_string();
+        let mut schema = Schema::new(short_name, format!("Imported from protobuf message {}", name), IMPORTED_VERSION.to_string());
+        schema.fields = file.fields(&name, &mut vec![name.clone()])?;
+        schema.set_metadata("source".to_string(), "protobuf".to_string());
+        if let Some(package) = &file.package {
+            schema.set_metadata("package".to_string(), package.clone());
+        }
+        Ok(schema)
+    }
+    
+    /// Build a schema from an Avro record schema, given as JSON
+    ///
+    /// Fields whose type is a union with `null` are not required.
+    pub fn from_avro(source: &str) -> Result<Self, HiveError> {
+        let record: Value = serde_json::from_str(source)
+            .map_err(|e| HiveError::DeserializationError(e.to_string()))?;
+        Self::from_avro_value(&record)
+    }
+    
+    /// Build a schema from an Avro record schema already parsed from JSON
+    pub fn from_avro_value(record: &Value) -> Result<Self, HiveError> {
+        if record.get("type").and_then(Value::as_str) != Some("record") {
+            return Err(HiveError::SchemaValidationError("An Avro schema must be a record".to_string()));
+        }
+        
+        let mut named = HashMap::new();
+        let (name, fields) = avro_record(record, None, &mut named)?;
+        let short_name = name.rsplit('.').next().unwrap_or(&name).to_string();
+        let description = record.get("doc").and_then(Value::as_str)
+            .map_or_else(|| format!("Imported from Avro record {}", name), str::to_string);
+        
+        let mut schema = Schema::new(short_name, description, IMPORTED_VERSION.to_string());
+        schema.fields = fields;
+        schema.set_metadata("source".to_string(), "avro".to_string());
+        if let Some(namespace) = record.get("namespace").and_then(Value::as_str) {
+            schema.set_metadata("namespace".to_string(), namespace.to_string());
+        }
+        Ok(schema)
+    }
+}
+
+/// Map a protobuf scalar type to a field type
+fn proto_scalar(type_name: &str) -> Option<FieldType> {
+    match type_name {
+        "double" | "float" => Some(FieldType::Float),
+        "int32" | "int64" | "uint32" | "uint64" | "sint32" | "sint64"
+        | "fixed32" | "fixed64" | "sfixed32" | "sfixed64" => Some(FieldType::Integer),
+        "bool" => Some(FieldType::Boolean),
+        "string" => Some(FieldType::String),
+        "bytes" => Some(FieldType::Binary),
+        "google.protobuf.Timestamp" | ".google.protobuf.Timestamp" => Some(FieldType::DateTime),
+        _ => None,
+    }
+}
+
+impl ProtoFile {
+    /// Find the fully qualified name of a type referenced from a scope
+    ///
+    /// As in protoc, the innermost scope defining the name wins.
+    fn resolve(&self, type_name: &str, scope: &str) -> Option<String> {
+        if let Some(absolute) = type_name.strip_prefix('.') {
+            return self.defines(absolute).then(|| absolute.to_string());
+        }
+        
+        let mut scope = scope.to_string();
+        loop {
+            let candidate = if scope.is_empty() { type_name.to_string() } else { format!("{}.{}", scope, type_name) };
+            if self.defines(&candidate) {
+                return Some(candidate);
+            }
+            if scope.is_empty() {
+                break;
+            }
+            scope = scope.rsplit_once('.').map_or(String::new(), |(outer, _)| outer.to_string());
+        }
+        
+        // Names may also be given without the package
+        let package = self.package.as_deref()?;
+        let qualified = format!("{}.{}", package, type_name);
+        self.defines(&qualified).then_some(qualified)
+    }
+    
+    /// Check whether a message or enum has a fully qualified name
+    fn defines(&self, name: &str) -> bool {
+        self.messages.contains_key(name) || self.enums.contains_key(name)
+    }
+    
+    /// Get the schema fields of a message
+    ///
+    /// `visiting` holds the messages being expanded, so a message that
+    /// contains itself becomes a custom type instead of recursing forever.
+    fn fields(&self, message: &str, visiting: &mut Vec<String>) -> Result<Vec<SchemaField>, HiveError> {
+        let mut fields = Vec::new();
+        for field in &self.messages[message] {
+            let (mut field_type, symbols) = match &field.map {
+                // A map is an object with any keys; its value type must still exist
+                Some((_, value)) => {
+                    self.field_type(value, message, visiting)?;
+                    (FieldType::Object(Vec::new()), None)
+                }
+                None => self.field_type(&field.type_name, message, visiting)?,
+            };
+            if field.label == Label::Repeated {
+                field_type = FieldType::Array(Box::new(field_type));
+            }
+            
+            let mut schema_field = SchemaField::new(field.name.clone(), String::new(), field_type, field.label == Label::Required);
+            if let Some(symbols) = symbols {
+                schema_field = schema_field.with_validation(ValidationRule::Enum(symbols));
+            }
+            fields.push(schema_field);
+        }
+        Ok(fields)
+    }
+    
+    /// Get the field type of a type referenced from a message, with the symbols of an enum
+    fn field_type(&self, type_name: &str, scope: &str, visiting: &mut Vec<String>) -> Result<(FieldType, Option<Vec<String>>), HiveError> {
+        if let Some(scalar) = proto_scalar(type_name) {
+            return Ok((scalar, None));
+        }
+        
+        let name = self.resolve(type_name, scope)
+            .ok_or_else(|| HiveError::SchemaValidationError(format!("Type '{}' used in message {} is not defined", type_name, scope)))?;
+        if let Some(symbols) = self.enums.get(&name) {
+            return Ok((FieldType::String, Some(symbols.clone())));
+        }
+        if visiting.contains(&name) {
+            return Ok((FieldType::Custom(name), None));
+        }
+        
+        visiting.push(name.clone());
+        let fields = self.fields(&name, visiting)?;
+        visiting.pop();
+        Ok((FieldType::Object(fields), None))
+    }
+}
+
+impl ProtoParser {
+    /// Split a `.proto` file into tokens, dropping comments
+    fn new(source: &str) -> Result<Self, HiveError> {
+        let mut tokens = Vec::new();
+        let mut chars = source.chars().peekable();
+        let mut line = 1;
+        
+        while let Some(c) = chars.next() {
+            match c {
+                '\n' => line += 1,
+                c if c.is_whitespace() => {}
+                '/' if chars.peek() == Some(&'/') => {
+                    while chars.next_if(|&c| c != '\n').is_some() {}
+                }
+                '/' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    let mut previous = ' ';
+                    loop {
+                        match chars.next() {
+                            Some('/') if previous == '*' => break,
+                            Some(c) => {
+                                line += usize::from(c == '\n');
+                                previous = c;
+                            }
+                            None => return Err(HiveError::DeserializationError(format!("line {}: unterminated comment", line))),
+                        }
+                    }
+                }
+                '"' | '\'' => {
+                    let mut value = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('\\') => value.extend(chars.next()),
+                            Some(end) if end == c => break,
+                            Some('\n') | None => return Err(HiveError::DeserializationError(format!("line {}: unterminated string", line))),
+                            Some(c) => value.push(c),
+                        }
+                    }
+                    tokens.push(Token { kind: TokenKind::Str(value), line });
+                }
+                c if c.is_alphanumeric() || matches!(c, '_' | '.' | '-' | '+') => {
+                    let mut word = c.to_string();
+                    while let Some(c) = chars.next_if(|&c| c.is_alphanumeric() || matches!(c, '_' | '.' | '-' | '+')) {
+                        word.push(c);
+                    }
+                    tokens.push(Token { kind: TokenKind::Word(word), line });
+                }
+                c => tokens.push(Token { kind: TokenKind::Symbol(c), line }),
+            }
+        }
+        
+        Ok(Self { tokens, position: 0, file: ProtoFile::default() })
+    }
+    
+    /// Read the messages and enums of the file
+    fn parse(mut self) -> Result<ProtoFile, HiveError> {
+        while let Some(token) = self.next() {
+            match token.kind {
+                TokenKind::Word(word) if word == "message" => self.message("")?,
+                TokenKind::Word(word) if word == "enum" => self.enumeration("")?,
+                TokenKind::Word(word) if word == "package" => {
+                    self.file.package = Some(self.word()?);
+                    self.expect(';')?;
+                }
+                TokenKind::Word(word) if word == "service" || word == "extend" => self.skip_block()?,
+                TokenKind::Symbol(';') => {}
+                _ => self.skip_statement()?,
+            }
+        }
+        Ok(self.file)
+    }
+    
+    /// Read a message, after its `message` keyword
+    fn message(&mut self, scope: &str) -> Result<(), HiveError> {
+        let name = self.word()?;
+        let scope = self.qualify(scope, &name);
+        self.expect('{')?;
+        
+        let mut fields = Vec::new();
+        let mut oneof = false;
+        loop {
+            let token = self.next().ok_or_else(|| self.error("expected '}'"))?;
+            let word = match token.kind {
+                TokenKind::Symbol('}') if oneof => {
+                    oneof = false;
+                    continue;
+                }
+                TokenKind::Symbol('}') => break,
+                TokenKind::Symbol(';') => continue,
+                TokenKind::Word(word) => word,
+                _ => return Err(self.error("expected a field")),
+            };
+            
+            match word.as_str() {
+                "message" => self.message(&scope)?,
+                "enum" => self.enumeration(&scope)?,
+                "option" | "reserved" | "extensions" => self.skip_statement()?,
+                "extend" => self.skip_block()?,
+                "oneof" => {
+                    self.word()?;
+                    self.expect('{')?;
+                    oneof = true;
+                }
+                "map" => {
+                    self.expect('<')?;
+                    let key = self.word()?;
+                    self.expect(',')?;
+                    let value = self.word()?;
+                    self.expect('>')?;
+                    let name = self.field_rest()?;
+                    fields.push(ProtoField { name, label: Label::Singular, type_name: String::new(), map: Some((key, value)) });
+                }
+                "repeated" | "optional" | "required" if !oneof => {
+                    let label = match word.as_str() {
+                        "repeated" => Label::Repeated,
+                        "optional" => Label::Optional,
+                        _ => Label::Required,
+                    };
+                    let type_name = self.word()?;
+                    let name = self.field_rest()?;
+                    fields.push(ProtoField { name, label, type_name, map: None });
+                }
+                _ => {
+                    let name = self.field_rest()?;
+                    let label = if oneof { Label::Optional } else { Label::Singular };
+                    fields.push(ProtoField { name, label, type_name: word, map: None });
+                }
+            }
+        }
+        
+        self.file.messages.insert(scope, fields);
+        Ok(())
+    }
+    
+    /// Read an enum, after its `enum` keyword
+    fn enumeration(&mut self, scope: &str) -> Result<(), HiveError> {
+        let name = self.word()?;
+        let name = self.qualify(scope, &name);
+        self.expect('{')?;
+        
+        let mut symbols = Vec::new();
+        loop {
+            let token = self.next().ok_or_else(|| self.error("expected '}'"))?;
+            match token.kind {
+                TokenKind::Symbol('}') => break,
+                TokenKind::Symbol(';') => {}
+                TokenKind::Word(word) if word == "option" || word == "reserved" => self.skip_statement()?,
+                TokenKind::Word(word) => {
+                    self.expect('=')?;
+                    self.skip_statement()?;
+                    symbols.push(word);
+                }
+                _ => return Err(self.error("expected an enum value")),
+            }
+        }
+        
+        self.file.enums.insert(name, symbols);
+        Ok(())
+    }
+    
+    /// Get the fully qualified name of a message or enum defined in a scope
+    ///
+    /// Types outside any message are in the package of the file.
+    fn qualify(&self, scope: &str, name: &str) -> String {
+        match (scope, &self.file.package) {
+            ("", Some(package)) => format!("{}.{}", package, name),
+            ("", None) => name.to_string(),
+            _ => format!("{}.{}", scope, name),
+        }
+    }
+    
+    /// Read the name, number and options of a field, returning its name
+    fn field_rest(&mut self) -> Result<String, HiveError> {
+        let name = self.word()?;
+        self.expect('=')?;
+        self.skip_statement()?;
+        Ok(name)
+    }
+    
+    /// Get the next token
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+    
+    /// Read a word
+    fn word(&mut self) -> Result<String, HiveError> {
+        match self.next().map(|token| token.kind) {
+            Some(TokenKind::Word(word)) => Ok(word),
+            _ => Err(self.error("expected a name")),
+        }
+    }
+    
+    /// Read a symbol
+    fn expect(&mut self, symbol: char) -> Result<(), HiveError> {
+        match self.next().map(|token| token.kind) {
+            Some(TokenKind::Symbol(c)) if c == symbol => Ok(()),
+            _ => Err(self.error(&format!("expected '{}'", symbol))),
+        }
+    }
+    
+    /// Skip to the end of a statement, past its `;`, and over any `[...]` options
+    fn skip_statement(&mut self) -> Result<(), HiveError> {
+        let mut depth = 0;
+        loop {
+            match self.next().map(|token| token.kind) {
+                Some(TokenKind::Symbol('[' | '{' | '(')) => depth += 1,
+                Some(TokenKind::Symbol(']' | '}' | ')')) => depth -= 1,
+                Some(TokenKind::Symbol(';')) if depth == 0 => return Ok(()),
+                Some(_) => {}
+                None => return Err(self.error("expected ';'")),
+            }
+        }
+    }
+    
+    /// Skip a named block such as a service, with everything it holds
+    fn skip_block(&mut self) -> Result<(), HiveError> {
+        let mut depth = 0;
+        loop {
+            match self.next().map(|token| token.kind) {
+                Some(TokenKind::Symbol('{')) => depth += 1,
+                Some(TokenKind::Symbol('}')) if depth == 1 => return Ok(()),
+                Some(TokenKind::Symbol('}')) => depth -= 1,
+                Some(_) => {}
+                None => return Err(self.error("expected '}'")),
+            }
+        }
+    }
+    
+    /// Describe a syntax error at the last token read
+    fn error(&self, message: &str) -> HiveError {
+        let line = self.tokens.get(self.position.saturating_sub(1))
+            .or(self.tokens.last())
+            .map_or(1, |token| token.line);
+        HiveError::DeserializationError(format!("line {}: {}", line, message))
+    }
+}
+
+/// Read an Avro record, returning its full name and fields
+///
+/// Records and enums are remembered by name, so later fields can refer to them.
+fn avro_record(record: &Value, namespace: Option<&str>, named: &mut HashMap<String, FieldType>) -> Result<(String, Vec<SchemaField>), HiveError> {
+    let name = avro_name(record, namespace)?;
+    let namespace = name.rsplit_once('.').map(|(namespace, _)| namespace.to_string());
+    named.insert(name.clone(), FieldType::Custom(name.clone()));
+    
+    let mut fields = Vec::new();
+    for field in record.get("fields").and_then(Value::as_array).into_iter().flatten() {
+        let field_name = field.get("name").and_then(Value::as_str)
+            .ok_or_else(|| HiveError::SchemaValidationError(format!("A field of record {} has no name", name)))?;
+        let (field_type, nullable, symbols) = avro_type(&field["type"], namespace.as_deref(), named)?;
+        
+        let description = field.get("doc").and_then(Value::as_str).unwrap_or_default().to_string();
+        let mut schema_field = SchemaField::new(field_name.to_string(), description, field_type, !nullable);
+        if let Some(default) = field.get("default").filter(|default| !default.is_null()) {
+            schema_field = schema_field.with_default(default.as_str().map_or_else(|| default.to_string(), str::to_string));
+        }
+        if let Some(symbols) = symbols {
+            schema_field = schema_field.with_validation(ValidationRule::Enum(symbols));
+        }
+        fields.push(schema_field);
+    }
+    
+    named.insert(name.clone(), FieldType::Object(fields.clone()));
+    Ok((name, fields))
+}
+
+/// Get the full name of an Avro record, enum or fixed type
+fn avro_name(schema: &Value, namespace: Option<&str>) -> Result<String, HiveError> {
+    let name = schema.get("name").and_then(Value::as_str)
+        .ok_or_else(|| HiveError::SchemaValidationError("A named Avro type has no name".to_string()))?;
+    let namespace = schema.get("namespace").and_then(Value::as_str).or(namespace);
+    Ok(match namespace {
+        Some(namespace) if !name.contains('.') && !namespace.is_empty() => format!("{}.{}", namespace, name),
+        _ => name.to_string(),
+    })
+}
+
+/// Map an Avro type to a field type, with whether it allows null and the symbols of an enum
+fn avro_type(
+    schema: &Value,
+    namespace: Option<&str>,
+    named: &mut HashMap<String, FieldType>,
+) -> Result<(FieldType, bool, Option<Vec<String>>), HiveError> {
+    let unknown = |name: &str| HiveError::SchemaValidationError(format!("Avro type '{}' is not defined", name));
+    
+    match schema {
+        Value::String(name) => {
+            let field_type = match name.as_str() {
+                "null" => return Ok((FieldType::Custom("null".to_string()), true, None)),
+                "boolean" => FieldType::Boolean,
+                "int" | "long" => FieldType::Integer,
+                "float" | "double" => FieldType::Float,
+                "bytes" => FieldType::Binary,
+                "string" => FieldType::String,
+                name => {
+                    let qualified = match namespace {
+                        Some(namespace) if !name.contains('.') => format!("{}.{}", namespace, name),
+                        _ => name.to_string(),
+                    };
+                    named.get(&qualified).or_else(|| named.get(name)).cloned().ok_or_else(|| unknown(name))?
+                }
+            };
+            Ok((field_type, false, None))
+        }
+        Value::Array(branches) => {
+            let nullable = branches.iter().any(|branch| branch.as_str() == Some("null"));
+            let mut types = Vec::new();
+            for branch in branches.iter().filter(|branch| branch.as_str() != Some("null")) {
+                types.push(avro_type(branch, namespace, named)?);
+            }
+            match types.len() {
+                1 => {
+                    let (field_type, _, symbols) = types.remove(0);
+                    Ok((field_type, nullable, symbols))
+                }
+                _ => {
+                    let names: Vec<String> = types.iter().map(|(field_type, _, _)| format!("{:?}", field_type)).collect();
+                    Ok((FieldType::Custom(format!("union of {}", names.join(", "))), nullable, None))
+                }
+            }
+        }
+        Value::Object(_) => {
+            let logical = schema.get("logicalType").and_then(Value::as_str);
+            match (schema.get("type").and_then(Value::as_str), logical) {
+                (_, Some("timestamp-millis" | "timestamp-micros" | "local-timestamp-millis" | "local-timestamp-micros" | "date")) => {
+                    Ok((FieldType::DateTime, false, None))
+                }
+                (_, Some("decimal")) => Ok((FieldType::Float, false, None)),
+                (Some("record"), _) => {
+                    let (_, fields) = avro_record(schema, namespace, named)?;
+                    Ok((FieldType::Object(fields), false, None))
+                }
+                (Some("enum"), _) => {
+                    let symbols: Vec<String> = schema.get("symbols").and_then(Value::as_array).into_iter().flatten()
+                        .filter_map(Value::as_str)
+                        .map(str::to_string)
+                        .collect();
+                    named.insert(avro_name(schema, namespace)?, FieldType::String);
+                    Ok((FieldType::String, false, Some(symbols)))
+                }
+                (Some("fixed"), _) => {
+                    named.insert(avro_name(schema, namespace)?, FieldType::Binary);
+                    Ok((FieldType::Binary, false, None))
+                }
+                (Some("array"), _) => {
+                    let (items, _, _) = avro_type(&schema["items"], namespace, named)?;
+                    Ok((FieldType::Array(Box::new(items)), false, None))
+                }
+                (Some("map"), _) => Ok((FieldType::Object(Vec::new()), false, None)),
+                (Some(_), _) => avro_type(&schema["type"], namespace, named),
+                (None, _) => Err(HiveError::SchemaValidationError("An Avro type has no type".to_string())),
+            }
+        }
+        other => Err(unknown(&other.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    const ORDERS_PROTO: &str = r#"
+        syntax = "proto3";
+        package shop.v1;
+        
+        import "google/protobuf/timestamp.proto";
+        
+        /* An order placed in the shop */
+        message Order {
+            string id = 1;
+            repeated Item items = 2 [packed = true];
+            Status status = 3;
+            google.protobuf.Timestamp placed_at = 4;
+            map<string, string> labels = 5;
+            Order replaces = 6;
+            oneof payment {
+                string card = 7;
+                bytes voucher = 8;
+            }
+            reserved 9, 10;
+            
+            message Item {
+                string sku = 1;
+                uint32 quantity = 2;
+                double price = 3;
+            }
+        }
+        
+        enum Status {
+            STATUS_UNSPECIFIED = 0;
+            PLACED = 1;
+            SHIPPED = 2 [deprecated = true];
+        }
+        
+        service Orders {
+            rpc Place(Order) returns (Order) { option idempotency_level = IDEMPOTENT; }
+        }
+    "#;
+    
+    #[test]
+    fn test_schema_from_proto() {
+        let schema = Schema::from_proto(ORDERS_PROTO, "Order").unwrap();
+        assert_eq!(schema.name, "Order");
+        assert_eq!(schema.metadata.get("package"), Some(&"shop.v1".to_string()));
+        let names: Vec<&str> = schema.fields.iter().map(|field| field.name.as_str()).collect();
+        assert_eq!(names, vec!["id", "items", "status", "placed_at", "labels", "replaces", "card", "voucher"]);
+        
+        let items = &schema.get_field("items").unwrap().field_type;
+        let FieldType::Array(item) = items else {
+            panic!("items should be an array, not {:?}", items);
+        };
+        let FieldType::Object(item_fields) = item.as_ref() else {
+            panic!("an item should be an object, not {:?}", item);
+        };
+        assert_eq!(item_fields[1].field_type, FieldType::Integer);
+        assert_eq!(item_fields[2].field_type, FieldType::Float);
+        
+        let status = schema.get_field("status").unwrap();
+        assert_eq!(status.field_type, FieldType::String);
+        assert!(matches!(&status.validation[..], [ValidationRule::Enum(symbols)] if symbols.len() == 3));
+        assert_eq!(schema.get_field("placed_at").unwrap().field_type, FieldType::DateTime);
+        assert_eq!(schema.get_field("labels").unwrap().field_type, FieldType::Object(Vec::new()));
+        assert_eq!(schema.get_field("replaces").unwrap().field_type, FieldType::Custom("shop.v1.Order".to_string()));
+        assert_eq!(schema.get_field("voucher").unwrap().field_type, FieldType::Binary);
+        
+        assert!(Schema::from_proto(ORDERS_PROTO, "shop.v1.Order.Item").is_ok());
+        assert!(Schema::from_proto(ORDERS_PROTO, "Customer").is_err());
+        assert!(Schema::from_proto("message Broken { string id = 1; Missing other = 2; }", "Broken").is_err());
+        assert!(Schema::from_proto("message Broken { string id = 1;", "Broken").is_err());
+    }
+    
+    #[test]
+    fn test_schema_from_avro() {
+        let schema = Schema::from_avro(r#"{
+            "type": "record",
+            "name": "User",
+            "namespace": "com.example",
+            "doc": "A registered user",
+            "fields": [
+                {"name": "id", "type": "string"},
+                {"name": "age", "type": ["null", "int"], "default": null},
+                {"name": "role", "type": {"type": "enum", "name": "Role", "symbols": ["ADMIN", "MEMBER"]}, "default": "MEMBER"},
+                {"name": "created", "type": {"type": "long", "logicalType": "timestamp-millis"}},
+                {"name": "address", "type": {"type": "record", "name": "Address", "fields": [{"name": "city", "type": "string"}]}},
+                {"name": "previous", "type": {"type": "array", "items": "Address"}},
+                {"name": "manager", "type": ["null", "User"]}
+            ]
+        }"#).unwrap();
+        
+        assert_eq!(schema.name, "User");
+        assert_eq!(schema.description, "A registered user");
+        assert!(schema.get_field("id").unwrap().required);
+        assert!(!schema.get_field("age").unwrap().required);
+        assert_eq!(schema.get_field("age").unwrap().field_type, FieldType::Integer);
+        let role = schema.get_field("role").unwrap();
+        assert_eq!(role.default_value.as_deref(), Some("MEMBER"));
+        assert!(matches!(&role.validation[..], [ValidationRule::Enum(symbols)] if symbols.len() == 2));
+        assert_eq!(schema.get_field("created").unwrap().field_type, FieldType::DateTime);
+        assert!(matches!(&schema.get_field("previous").unwrap().field_type, FieldType::Array(item) if matches!(item.as_ref(), FieldType::Object(fields) if fields.len() == 1)));
+        assert_eq!(schema.get_field("manager").unwrap().field_type, FieldType::Custom("com.example.User".to_string()));
+        
+        assert!(Schema::from_avro(r#"{"type": "enum", "name": "Role", "symbols": []}"#).is_err());
+        assert!(Schema::from_avro(r#"{"type": "record", "name": "R", "fields": [{"name": "x", "type": "Unknown"}]}"#).is_err());
+    }
+}
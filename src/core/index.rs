@@ -0,0 +1,1113 @@
+// HiveDB Index Module
+//
+// This module maintains the secondary indexes declared by a hive's
+// schema. An index maps the key computed from each document (field values
+// or expressions over them, such as `lower(email)`) to the IDs of the
+// cells holding it. A partial index only covers documents that meet its
+// predicate, and a covering index also stores the values of extra fields
+// so queries reading only those can be answered without touching the
+// cells. Indexes live in memory: they are built when a schema is set
+// or a hive is loaded, and updated as cells are added, changed or removed.
+// Full-text indexes keep an inverted list of the terms in a text field
+// instead of keys, and answer text searches; n-gram indexes keep the
+// trigrams of a string value and answer LIKE patterns. Ordinary indexes
+// answer comparisons and patterns with a literal prefix.
+// A new index can also be built in the background while the hive stays
+// available; writes made during the build are caught up before the index
+// is swapped in.
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::{Arc, Mutex, RwLock};
+use crate::core::analyze::CollectionStats;
+use crate::core::cell::{CellDataType, CellGrid};
+use crate::core::error::HiveError;
+use crate::core::hive::Hive;
+use crate::core::query::{
+    compare_values, field_value, values_equal, with_id, ComparisonOperator, FilterExpression, ID_FIELD,
+};
+use crate::core::ngram::NgramIndex;
+use crate::core::schema::{IndexType, Schema, SchemaIndex};
+use crate::core::text::TextIndex;
+use crate::core::sql;
+use crate::core::worker::{self, Priority, Task};
+use log::{info, warn};
+
+/// Selectivity assumed for an indexed equality when the collection has no statistics
+const DEFAULT_EQ_SELECTIVITY: f64 = 0.005;
+
+/// Selectivity assumed for an indexed range when the collection has no statistics
+const DEFAULT_RANGE_SELECTIVITY: f64 = 1.0 / 3.0;
+
+/// Cost of a document read from an index relative to one read from its cell
+const INDEX_ONLY_COST: f64 = 0.1;
+
+/// A value indexed for each document: a field, or a function of one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IndexExpression {
+    /// The value of a field
+    Field(String),
+    
+    /// A string field in lower case
+    Lower(String),
+    
+    /// A string field in upper case
+    Upper(String),
+    
+    /// A string field without surrounding whitespace
+    Trim(String),
+    
+    /// The length of a string or array field
+    Length(String),
+}
+
+impl IndexExpression {
+    /// Parse a field name or a function call such as `lower(email)`
+    pub fn parse(text: &str) -> Result<Self, HiveError> {
+        let text = text.trim();
+        let Some((function, rest)) = text.split_once('(') else {
+            return Ok(IndexExpression::Field(text.to_string()));
+        };
+        
+        let field = rest.strip_suffix(')')
+            .map(str::trim)
+            .filter(|field| !field.is_empty() && !field.contains(['(', ')']))
+            .ok_or_else(|| HiveError::QueryError(format!("Invalid expression '{}'", text)))?
+            .to_string();
+        
+        match function.trim().to_ascii_lowercase().as_str() {
+            "lower" => Ok(IndexExpression::Lower(field)),
+            "upper" => Ok(IndexExpression::Upper(field)),
+            "trim" => Ok(IndexExpression::Trim(field)),
+            "length" => Ok(IndexExpression::Length(field)),
+            other => Err(HiveError::QueryError(format!("Unknown function '{}'", other))),
+        }
+    }
+    
+    /// Get the field the expression reads
+    pub fn field(&self) -> &str {
+        match self {
+            IndexExpression::Field(field)
+            | IndexExpression::Lower(field)
+            | IndexExpression::Upper(field)
+            | IndexExpression::Trim(field)
+            | IndexExpression::Length(field) => field,
+        }
+    }
+    
+    /// Compute the value of the expression for a document
+    pub fn evaluate(&self, document: &serde_json::Value) -> Option<serde_json::Value> {
+        let value = field_value(document, self.field())?;
+        
+        match self {
+            IndexExpression::Field(_) => Some(value.clone()),
+            IndexExpression::Lower(_) => value.as_str().map(|text| text.to_lowercase().into()),
+            IndexExpression::Upper(_) => value.as_str().map(|text| text.to_uppercase().into()),
+            IndexExpression::Trim(_) => value.as_str().map(|text| text.trim().into()),
+            IndexExpression::Length(_) => match value {
+                serde_json::Value::String(text) => Some(text.chars().count().into()),
+                serde_json::Value::Array(items) => Some(items.len().into()),
+                _ => None,
+            },
+        }
+    }
+}
+
+impl fmt::Display for IndexExpression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IndexExpression::Field(field) => write!(f, "{}", field),
+            IndexExpression::Lower(field) => write!(f, "lower({})", field),
+            IndexExpression::Upper(field) => write!(f, "upper({})", field),
+            IndexExpression::Trim(field) => write!(f, "trim({})", field),
+            IndexExpression::Length(field) => write!(f, "length({})", field),
+        }
+    }
+}
+
+/// The key of a document in an index, one value per indexed expression
+#[derive(Debug, Clone)]
+pub struct IndexKey(pub Vec<serde_json::Value>);
+
+impl PartialEq for IndexKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for IndexKey {}
+
+impl PartialOrd for IndexKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for IndexKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.iter()
+            .zip(&other.0)
+            .map(|(a, b)| compare_key_values(a, b))
+            .find(|ordering| *ordering != Ordering::Equal)
+            .unwrap_or_else(|| self.0.len().cmp(&other.0.len()))
+    }
+}
+
+/// What an index holds for one document
+#[derive(Debug, Clone)]
+struct IndexRow {
+    /// Key of the document
+    key: IndexKey,
+    
+    /// Values of the included fields (None where the document lacks one)
+    included: Vec<Option<serde_json::Value>>,
+}
+
+/// An index over the documents of a hive
+#[derive(Debug, Clone)]
+pub struct SecondaryIndex {
+    /// Definition from the schema
+    definition: SchemaIndex,
+    
+    /// Parsed indexed expressions
+    expressions: Vec<IndexExpression>,
+    
+    /// Parsed predicate of a partial index
+    predicate: Option<FilterExpression>,
+    
+    /// IDs of the cells holding each key
+    entries: BTreeMap<IndexKey, BTreeSet<String>>,
+    
+    /// Row of each indexed cell
+    rows: HashMap<String, IndexRow>,
+    
+    /// Terms of the indexed field, for a full-text index
+    text: Option<TextIndex>,
+    
+    /// Trigrams of the indexed value, for an n-gram index
+    grams: Option<NgramIndex>,
+}
+
+impl SecondaryIndex {
+    /// Create an empty index from its definition
+    pub fn new(definition: SchemaIndex) -> Result<Self, HiveError> {
+        if definition.fields.is_empty() {
+            return Err(HiveError::SchemaValidationError(format!("Index '{}' has no fields", definition.name)));
+        }
+        
+        let expressions = definition.fields.iter()
+            .map(|field| IndexExpression::parse(field))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| HiveError::SchemaValidationError(format!("Index '{}': {}", definition.name, e)))?;
+        let predicate = definition.predicate.as_deref()
+            .map(sql::parse_condition)
+            .transpose()
+            .map_err(|e| HiveError::SchemaValidationError(format!("Index '{}': {}", definition.name, e)))?;
+        
+        let (text, grams) = match (&definition.index_type, expressions.as_slice()) {
+            (IndexType::FullText, [IndexExpression::Field(_)]) if definition.include.is_empty() => {
+                (Some(TextIndex::new()), None)
+            }
+            (IndexType::Ngram, [_]) if definition.include.is_empty() => (None, Some(NgramIndex::new())),
+            (IndexType::FullText, _) => return Err(HiveError::SchemaValidationError(format!(
+                "Full-text index '{}' must cover exactly one field and include no others", definition.name
+            ))),
+            (IndexType::Ngram, _) => return Err(HiveError::SchemaValidationError(format!(
+                "N-gram index '{}' must cover exactly one expression and include no others", definition.name
+            ))),
+            _ => (None, None),
+        };
+        
+        Ok(Self {
+            definition,
+            expressions,
+            predicate,
+            entries: BTreeMap::new(),
+            rows: HashMap::new(),
+            text,
+            grams,
+        })
+    }
+    
+    /// Get the definition of this index
+    pub fn definition(&self) -> &SchemaIndex {
+        &self.definition
+    }
+    
+    /// Get the indexed expressions
+    pub fn expressions(&self) -> &[IndexExpression] {
+        &self.expressions
+    }
+    
+    /// Get the terms of a full-text index (None for other indexes)
+    pub fn text(&self) -> Option<&TextIndex> {
+        self.text.as_ref()
+    }
+    
+    /// Get the number of indexed documents
+    pub fn len(&self) -> usize {
+        match (&self.text, &self.grams) {
+            (Some(text), _) => text.len(),
+            (_, Some(grams)) => grams.len(),
+            _ => self.rows.len(),
+        }
+    }
+    
+    /// Check whether the index holds keys, rather than terms or grams
+    fn keyed(&self) -> bool {
+        self.text.is_none() && self.grams.is_none()
+    }
+    
+    /// Check whether no document is indexed
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    
+    /// Check whether the index stores the value of a field
+    ///
+    /// Plain indexed fields and included fields are stored; expressions
+    /// over a field, and fields under a full-text or n-gram index, are not.
+    pub fn stores(&self, field: &str) -> bool {
+        self.keyed() && self.definition.include.iter().any(|included| included == field)
+            || self.expressions.iter().any(|expression| *expression == IndexExpression::Field(field.to_string()))
+    }
+    
+    /// Rebuild the stored part of an indexed document, without its ID
+    pub fn document(&self, cell_id: &str) -> Option<serde_json::Value> {
+        let row = self.rows.get(cell_id)?;
+        let mut document = serde_json::Map::new();
+        
+        for (expression, value) in self.expressions.iter().zip(&row.key.0) {
+            if let IndexExpression::Field(field) = expression {
+                document.insert(field.clone(), value.clone());
+            }
+        }
+        for (field, value) in self.definition.include.iter().zip(&row.included) {
+            if let Some(value) = value {
+                document.insert(field.clone(), value.clone());
+            }
+        }
+        document.remove(ID_FIELD);
+        
+        Some(serde_json::Value::Object(document))
+    }
+    
+    /// Compute the key of a document (None if the index does not cover it)
+    ///
+    /// A document is covered if it meets the predicate and every
+    /// expression has a non-null value.
+    pub fn key_for(&self, document: &serde_json::Value) -> Option<IndexKey> {
+        if let Some(predicate) = &self.predicate {
+            if !predicate.evaluate(document) {
+                return None;
+            }
+        }
+        
+        self.expressions.iter()
+            .map(|expression| expression.evaluate(document).filter(|value| !value.is_null()))
+            .collect::<Option<Vec<_>>>()
+            .map(IndexKey)
+    }
+    
+    /// Index the document now held by a cell (None if it no longer holds one)
+    pub fn update(&mut self, cell_id: &str, document: Option<&serde_json::Value>) {
+        self.remove(cell_id);
+        
+        let Some(document) = document else {
+            return;
+        };
+        if let Some(text) = &mut self.text {
+            let covered = self.predicate.as_ref().is_none_or(|predicate| predicate.evaluate(document));
+            let value = field_value(document, self.expressions[0].field()).and_then(serde_json::Value::as_str);
+            if let (true, Some(value)) = (covered, value) {
+                text.insert(cell_id, value);
+            }
+            return;
+        }
+        if self.grams.is_some() {
+            let key = self.key_for(document);
+            let value = key.as_ref().and_then(|key| key.0[0].as_str());
+            if let (Some(grams), Some(value)) = (&mut self.grams, value) {
+                grams.insert(cell_id, value);
+            }
+            return;
+        }
+        if let Some(key) = self.key_for(document) {
+            let included = self.definition.include.iter()
+                .map(|field| field_value(document, field).cloned())
+                .collect();
+            self.entries.entry(key.clone()).or_default().insert(cell_id.to_string());
+            self.rows.insert(cell_id.to_string(), IndexRow { key, included });
+        }
+    }
+    
+    /// Remove a cell from the index
+    pub fn remove(&mut self, cell_id: &str) {
+        if let Some(text) = &mut self.text {
+            text.remove(cell_id);
+        }
+        if let Some(grams) = &mut self.grams {
+            grams.remove(cell_id);
+        }
+        if let Some(row) = self.rows.remove(cell_id) {
+            if let Some(ids) = self.entries.get_mut(&row.key) {
+                ids.remove(cell_id);
+                if ids.is_empty() {
+                    self.entries.remove(&row.key);
+                }
+            }
+        }
+    }
+    
+    /// Check whether every document passing a filter is covered by the predicate
+    ///
+    /// This holds when each condition of the predicate is also a top-level
+    /// condition of the filter.
+    pub fn covers(&self, filter: &FilterExpression) -> bool {
+        let Some(predicate) = &self.predicate else {
+            return true;
+        };
+        
+        let required = conjuncts(predicate);
+        let present: Vec<serde_json::Value> = conjuncts(filter).into_iter()
+            .filter_map(|condition| serde_json::to_value(condition).ok())
+            .collect();
+        required.into_iter().all(|condition| {
+            serde_json::to_value(condition).is_ok_and(|condition| present.contains(&condition))
+        })
+    }
+    
+    /// Find the cells whose first key value satisfies a condition
+    ///
+    /// Returns None for conditions the index cannot answer.
+    pub fn lookup(&self, condition: &FilterExpression) -> Option<BTreeSet<String>> {
+        let first = self.expressions[0].to_string();
+        if let Some(text) = &self.text {
+            return match condition {
+                FilterExpression::Search(search) if search.field == first => Some(text.lookup(search)),
+                _ => None,
+            };
+        }
+        if let Some(grams) = &self.grams {
+            return match condition {
+                FilterExpression::Pattern(field, pattern) if *field == first => grams.lookup(pattern),
+                _ => None,
+            };
+        }
+        
+        match condition {
+            FilterExpression::Comparison(op, field, value) if *field == first && *op != ComparisonOperator::Ne => {
+                Some(self.lookup_comparison(op, value))
+            }
+            FilterExpression::In(field, values) if *field == first => Some(
+                values.iter()
+                    .flat_map(|value| self.lookup_comparison(&ComparisonOperator::Eq, value))
+                    .collect()
+            ),
+            FilterExpression::Pattern(field, pattern) if *field == first => {
+                like_prefix(pattern).map(|prefix| self.lookup_prefix(&prefix))
+            }
+            _ => None,
+        }
+    }
+    
+    /// Find the cells whose first key value is a string starting with a prefix
+    fn lookup_prefix(&self, prefix: &str) -> BTreeSet<String> {
+        let start = IndexKey(vec![serde_json::Value::String(prefix.to_string())]);
+        self.entries.range(start..)
+            .take_while(|(key, _)| key.0[0].as_str().is_some_and(|value| value.starts_with(prefix)))
+            .flat_map(|(_, ids)| ids.iter().cloned())
+            .collect()
+    }
+    
+    /// Find the cells whose first key value compares to a value as given
+    fn lookup_comparison(&self, op: &ComparisonOperator, value: &serde_json::Value) -> BTreeSet<String> {
+        let start = IndexKey(vec![value.clone()]);
+        let matches = |key: &IndexKey| match compare_values(&key.0[0], value) {
+            Some(ordering) => match op {
+                ComparisonOperator::Eq => ordering == Ordering::Equal,
+                ComparisonOperator::Gt => ordering == Ordering::Greater,
+                ComparisonOperator::Gte => ordering != Ordering::Less,
+                ComparisonOperator::Lt => ordering == Ordering::Less,
+                ComparisonOperator::Lte => ordering != Ordering::Greater,
+                ComparisonOperator::Ne => false,
+            },
+            None => values_equal(&key.0[0], value) && *op == ComparisonOperator::Eq,
+        };
+        
+        let entries: Box<dyn Iterator<Item = (&IndexKey, &BTreeSet<String>)>> = match op {
+            // Keys are ordered, so the matches are the keys from the value on
+            ComparisonOperator::Eq | ComparisonOperator::Gt | ComparisonOperator::Gte => {
+                Box::new(self.entries.range(start..).take_while(|(key, _)| same_kind(&key.0[0], value)))
+            }
+            _ => Box::new(self.entries.iter().take_while(|(key, _)| {
+                compare_key_values(&key.0[0], value) != Ordering::Greater || matches(key)
+            })),
+        };
+        
+        entries.filter(|(key, _)| matches(key))
+            .flat_map(|(_, ids)| ids.iter().cloned())
+            .collect()
+    }
+}
+
+/// The indexes of a hive
+#[derive(Debug, Clone, Default)]
+pub struct IndexSet {
+    /// Indexes by name
+    indexes: BTreeMap<String, SecondaryIndex>,
+    
+    /// Indexes being built in the background, by name
+    building: BTreeMap<String, Arc<BuildProgress>>,
+}
+
+/// State shared between a background index build and the hive
+#[derive(Debug, Default)]
+pub struct BuildProgress {
+    /// IDs of the cells written since the build started
+    touched: Mutex<HashSet<String>>,
+    
+    /// Number of cells scanned so far
+    scanned: AtomicUsize,
+    
+    /// Number of cells to scan
+    total: AtomicUsize,
+    
+    /// Set to stop the build
+    cancelled: AtomicBool,
+}
+
+impl BuildProgress {
+    /// Get the number of cells scanned so far
+    pub fn scanned(&self) -> usize {
+        self.scanned.load(AtomicOrdering::Relaxed)
+    }
+    
+    /// Get the number of cells to scan
+    pub fn total(&self) -> usize {
+        self.total.load(AtomicOrdering::Relaxed)
+    }
+    
+    /// Record that a cell was written during the build
+    fn touch(&self, cell_id: &str) {
+        if let Ok(mut touched) = self.touched.lock() {
+            touched.insert(cell_id.to_string());
+        }
+    }
+}
+
+/// An index being built in the background
+pub struct IndexBuild {
+    /// Name of the index
+    name: String,
+    
+    /// State shared with the build
+    progress: Arc<BuildProgress>,
+    
+    /// The build, running on a worker bee
+    task: Task<Result<(), HiveError>>,
+}
+
+impl IndexBuild {
+    /// Get the name of the index
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    
+    /// Get the state of the build
+    pub fn progress(&self) -> &BuildProgress {
+        &self.progress
+    }
+    
+    /// Check whether the build has finished, successfully or not
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+    
+    /// Stop the build; the index is not added
+    pub fn cancel(&self) {
+        self.progress.cancelled.store(true, AtomicOrdering::Relaxed);
+    }
+    
+    /// Wait for the build to finish
+    pub fn wait(self) -> Result<(), HiveError> {
+        self.task.wait()
+            .map_err(|e| HiveError::GenericError(format!("Build of index '{}' failed: {}", self.name, e)))?
+    }
+}
+
+/// A plan to read the documents passing a filter through an index
+#[derive(Debug, Clone)]
+pub struct IndexScan<'a> {
+    /// The index to read
+    pub index: &'a SecondaryIndex,
+    
+    /// The condition the index answers
+    pub condition: &'a FilterExpression,
+    
+    /// Estimated fraction of the documents the condition selects
+    pub selectivity: f64,
+    
+    /// Whether the index stores every field the query reads
+    pub index_only: bool,
+}
+
+impl IndexScan<'_> {
+    /// Get the IDs of the cells that may pass the filter
+    pub fn cell_ids(&self) -> BTreeSet<String> {
+        self.index.lookup(self.condition).unwrap_or_default()
+    }
+}
+
+impl IndexSet {
+    /// Build the indexes declared by a schema over the cells of a grid
+    pub fn build(schema: Option<&Schema>, cells: &CellGrid) -> Result<Self, HiveError> {
+        let mut indexes = BTreeMap::new();
+        for definition in schema.map(|schema| schema.indexes.as_slice()).unwrap_or_default() {
+            indexes.insert(definition.name.clone(), SecondaryIndex::new(definition.clone())?);
+        }
+        
+        let mut set = Self {
+            indexes,
+            building: BTreeMap::new(),
+        };
+        if set.indexes.is_empty() {
+            return Ok(set);
+        }
+        
+        for cell_arc in cells.all_cells() {
+            let cell = cell_arc.read().map_err(|_| HiveError::LockError)?;
+            set.index_cell(&cell.id, cell.data.data_type.clone(), &cell.get_content()?);
+        }
+        
+        Ok(set)
+    }
+    
+    /// Rebuild the indexes for a new schema, keeping track of background builds
+    pub fn rebuild(&mut self, schema: Option<&Schema>, cells: &CellGrid) -> Result<(), HiveError> {
+        let rebuilt = Self::build(schema, cells)?;
+        self.indexes = rebuilt.indexes;
+        Ok(())
+    }
+    
+    /// Get an index by name
+    pub fn get(&self, name: &str) -> Option<&SecondaryIndex> {
+        self.indexes.get(name)
+    }
+    
+    /// Iterate over the indexes
+    pub fn iter(&self) -> impl Iterator<Item = &SecondaryIndex> {
+        self.indexes.values()
+    }
+    
+    /// Get the number of indexes
+    pub fn len(&self) -> usize {
+        self.indexes.len()
+    }
+    
+    /// Check whether there are no indexes
+    pub fn is_empty(&self) -> bool {
+        self.indexes.is_empty()
+    }
+    
+    /// Update every index for the new content of a cell
+    pub fn index_cell(&mut self, cell_id: &str, data_type: CellDataType, content: &[u8]) {
+        for progress in self.building.values() {
+            progress.touch(cell_id);
+        }
+        if self.indexes.is_empty() {
+            return;
+        }
+        
+        let document = document_of(cell_id, &data_type, content);
+        for index in self.indexes.values_mut() {
+            index.update(cell_id, document.as_ref());
+        }
+    }
+    
+    /// Remove a cell from every index
+    pub fn remove_cell(&mut self, cell_id: &str) {
+        for progress in self.building.values() {
+            progress.touch(cell_id);
+        }
+        for index in self.indexes.values_mut() {
+            index.remove(cell_id);
+        }
+    }
+    
+    /// Add a built index, replacing any index of the same name
+    pub fn insert(&mut self, index: SecondaryIndex) {
+        self.indexes.insert(index.definition.name.clone(), index);
+    }
+    
+    /// Build one index afresh over the cells of a grid, replacing any index of the same name
+    ///
+    /// Returns the number of indexed documents.
+    pub fn rebuild_index(&mut self, definition: &SchemaIndex, cells: &CellGrid) -> Result<usize, HiveError> {
+        let mut index = SecondaryIndex::new(definition.clone())?;
+        for cell_arc in cells.all_cells() {
+            let cell = cell_arc.read().map_err(|_| HiveError::LockError)?;
+            let document = document_of(&cell.id, &cell.data.data_type, &cell.get_content()?);
+            index.update(&cell.id, document.as_ref());
+        }
+        
+        let entries = index.len();
+        self.insert(index);
+        Ok(entries)
+    }
+    
+    /// Remove an index, cancelling its build if it is being built in the background
+    ///
+    /// Returns whether there was such an index.
+    pub fn remove(&mut self, name: &str) -> bool {
+        let building = self.building.remove(name)
+            .inspect(|progress| progress.cancelled.store(true, AtomicOrdering::Relaxed))
+            .is_some();
+        self.indexes.remove(name).is_some() || building
+    }
+    
+    /// Get the full-text index on a field, if there is one covering every document
+    pub fn text_index(&self, field: &str) -> Option<&TextIndex> {
+        self.indexes.values()
+            .filter(|index| index.definition.predicate.is_none() && index.expressions[0].field() == field)
+            .find_map(SecondaryIndex::text)
+    }
+    
+    /// Get the state of the indexes being built in the background, by name
+    pub fn building(&self) -> impl Iterator<Item = (&str, &BuildProgress)> {
+        self.building.iter().map(|(name, progress)| (name.as_str(), progress.as_ref()))
+    }
+    
+    /// Choose the cheapest index to read the documents passing a filter, if any applies
+    ///
+    /// Each top-level condition of the filter that an index covering the
+    /// filter can answer is a candidate; the collection statistics, when
+    /// available, estimate how many documents each selects. If `columns`
+    /// lists every field the query reads, indexes storing all of them can
+    /// serve the query on their own, which is cheaper.
+    pub fn plan<'a>(
+        &'a self,
+        filter: &'a FilterExpression,
+        stats: Option<&CollectionStats>,
+        columns: Option<&BTreeSet<String>>,
+    ) -> Option<IndexScan<'a>> {
+        let conditions = conjuncts(filter);
+        let mut best: Option<(f64, IndexScan<'a>)> = None;
+        
+        for index in self.indexes.values().filter(|index| index.covers(filter)) {
+            let first = index.expressions[0].to_string();
+            let index_only = columns.is_some_and(|columns| {
+                columns.iter().all(|column| column == ID_FIELD || index.stores(column))
+            });
+            for condition in &conditions {
+                let selectivity = match condition {
+                    FilterExpression::Comparison(op, field, _)
+                        if *field == first && *op != ComparisonOperator::Ne && index.keyed() => {
+                        match op {
+                            ComparisonOperator::Eq => DEFAULT_EQ_SELECTIVITY,
+                            _ => DEFAULT_RANGE_SELECTIVITY,
+                        }
+                    }
+                    FilterExpression::In(field, values) if *field == first && index.keyed() => {
+                        DEFAULT_EQ_SELECTIVITY * values.len() as f64
+                    }
+                    FilterExpression::Pattern(field, pattern) if *field == first => match &index.grams {
+                        // The index knows how many values hold the rarest gram
+                        Some(grams) => match grams.estimate(pattern) {
+                            Some(rows) => rows as f64 / grams.len().max(1) as f64,
+                            None => continue,
+                        },
+                        None if index.keyed() && like_prefix(pattern).is_some() => DEFAULT_RANGE_SELECTIVITY,
+                        None => continue,
+                    },
+                    // The index knows how many documents hold the terms
+                    FilterExpression::Search(search) if search.field == first => match &index.text {
+                        Some(text) => text.estimate(search) as f64 / text.len().max(1) as f64,
+                        None => continue,
+                    },
+                    _ => continue,
+                };
+                let selectivity = match (condition, stats) {
+                    (FilterExpression::Search(_), _) | (_, None) => selectivity,
+                    (FilterExpression::Pattern(..), _) if index.grams.is_some() => selectivity,
+                    (_, Some(stats)) => stats.selectivity(condition),
+                };
+                let cost = if index_only { selectivity * INDEX_ONLY_COST } else { selectivity };
+                
+                if best.as_ref().is_none_or(|(best, _)| cost < *best) {
+                    best = Some((cost, IndexScan {
+                        index,
+                        condition,
+                        selectivity,
+                        index_only,
+                    }));
+                }
+            }
+        }
+        
+        best.map(|(_, scan)| scan)
+    }
+}
+
+/// Build an index over a shared hive in the background
+///
+/// The hive is only locked briefly at the start and while the finished
+/// index is swapped in: cells are scanned one at a time, and the cells
+/// written meanwhile are indexed again from their latest content before
+/// the swap. The index then joins the hive's schema. The build runs on a
+/// worker bee at index maintenance priority.
+pub fn build_in_background(hive: &Arc<RwLock<Hive>>, definition: SchemaIndex) -> Result<IndexBuild, HiveError> {
+    let mut index = SecondaryIndex::new(definition)?;
+    let name = index.definition.name.clone();
+    let progress = Arc::new(BuildProgress::default());
+    
+    let cells = {
+        let mut hive = hive.write().map_err(|_| HiveError::LockError)?;
+        if hive.indexes.building.contains_key(&name) {
+            return Err(HiveError::SchemaValidationError(format!("Index '{}' is already being built", name)));
+        }
+        hive.indexes.building.insert(name.clone(), progress.clone());
+        hive.cells.all_cells()
+    };
+    progress.total.store(cells.len(), AtomicOrdering::Relaxed);
+    info!("Building index '{}' over {} cells in the background", name, cells.len());
+    
+    let shared = hive.clone();
+    let state = progress.clone();
+    let index_name = name.clone();
+    let task = worker::pool().submit(Priority::IndexMaintenance, move || {
+        let result = scan_cells(&mut index, &cells, &state)
+            .and_then(|_| swap_in(&shared, index, &state));
+        
+        if let Err(e) = &result {
+            warn!("Build of index '{}' failed: {}", index_name, e);
+            if let Ok(mut hive) = shared.write() {
+                hive.indexes.building.remove(&index_name);
+            }
+        }
+        result
+    })?;
+    
+    Ok(IndexBuild {
+        name,
+        progress,
+        task,
+    })
+}
+
+/// Index each cell in turn
+fn scan_cells(
+    index: &mut SecondaryIndex,
+    cells: &[Arc<RwLock<crate::core::cell::Cell>>],
+    progress: &BuildProgress,
+) -> Result<(), HiveError> {
+    for cell_arc in cells {
+        if progress.cancelled.load(AtomicOrdering::Relaxed) {
+            return Err(HiveError::GenericError(format!("Build of index '{}' was cancelled", index.definition.name)));
+        }
+        
+        let bytes = {
+            let cell = cell_arc.read().map_err(|_| HiveError::LockError)?;
+            let content = cell.get_content()?;
+            let document = document_of(&cell.id, &cell.data.data_type, &content);
+            index.update(&cell.id, document.as_ref());
+            content.len()
+        };
+        progress.scanned.fetch_add(1, AtomicOrdering::Relaxed);
+        worker::throttle(bytes as u64);
+    }
+    
+    Ok(())
+}
+
+/// Catch up with the writes made during the build and add the index to the hive
+fn swap_in(hive: &Arc<RwLock<Hive>>, mut index: SecondaryIndex, progress: &BuildProgress) -> Result<(), HiveError> {
+    let mut hive = hive.write().map_err(|_| HiveError::LockError)?;
+    if progress.cancelled.load(AtomicOrdering::Relaxed) {
+        return Err(HiveError::GenericError(format!("Build of index '{}' was cancelled", index.definition.name)));
+    }
+    
+    let touched = std::mem::take(&mut *progress.touched.lock().map_err(|_| HiveError::LockError)?);
+    for cell_id in &touched {
+        match hive.find_cell_by_id(cell_id) {
+            Some(cell_arc) => {
+                let cell = cell_arc.read().map_err(|_| HiveError::LockError)?;
+                let document = document_of(&cell.id, &cell.data.data_type, &cell.get_content()?);
+                index.update(cell_id, document.as_ref());
+            }
+            None => index.remove(cell_id),
+        }
+    }
+    
+    let name = index.definition.name.clone();
+    hive.indexes.building.remove(&name);
+    hive.install_index(index)?;
+    info!("Index '{}' is ready ({} cells written during the build)", name, touched.len());
+    
+    Ok(())
+}
+
+/// Parse the content of a JSON cell into the document an index sees
+fn document_of(cell_id: &str, data_type: &CellDataType, content: &[u8]) -> Option<serde_json::Value> {
+    match data_type {
+        CellDataType::Json => serde_json::from_slice(content).ok().map(|body| with_id(body, cell_id)),
+        _ => None,
+    }
+}
+
+/// Get the literal text a LIKE pattern starts with, if any
+fn like_prefix(pattern: &str) -> Option<String> {
+    let prefix: String = pattern.chars().take_while(|c| *c != '%' && *c != '_').collect();
+    Some(prefix).filter(|prefix| !prefix.is_empty())
+}
+
+/// Split a filter into its top-level AND conditions
+fn conjuncts(filter: &FilterExpression) -> Vec<&FilterExpression> {
+    match filter {
+        FilterExpression::And(filters) => filters.iter().flat_map(conjuncts).collect(),
+        other => vec![other],
+    }
+}
+
+/// Rank of a JSON value's kind in index order
+fn kind_rank(value: &serde_json::Value) -> u8 {
+    match value {
+        serde_json::Value::Null => 0,
+        serde_json::Value::Bool(_) => 1,
+        serde_json::Value::Number(_) => 2,
+        serde_json::Value::String(_) => 3,
+        serde_json::Value::Array(_) => 4,
+        serde_json::Value::Object(_) => 5,
+    }
+}
+
+/// Check whether two values are of the same kind
+fn same_kind(a: &serde_json::Value, b: &serde_json::Value) -> bool {
+    kind_rank(a) == kind_rank(b)
+}
+
+/// Order any two JSON values: by kind first, then by value
+pub(crate) fn compare_key_values(a: &serde_json::Value, b: &serde_json::Value) -> Ordering {
+    kind_rank(a).cmp(&kind_rank(b))
+        .then_with(|| match (a.as_f64(), b.as_f64()) {
+            (Some(x), Some(y)) => x.total_cmp(&y),
+            _ => compare_values(a, b).unwrap_or_else(|| a.to_string().cmp(&b.to_string())),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    
+    fn index(fields: &[&str], predicate: Option<&str>) -> SecondaryIndex {
+        let mut definition = SchemaIndex::new(
+            "test".to_string(),
+            fields.iter().map(|field| field.to_string()).collect(),
+            IndexType::BTree,
+            false,
+        );
+        if let Some(predicate) = predicate {
+            definition = definition.with_predicate(predicate.to_string());
+        }
+        SecondaryIndex::new(definition).unwrap()
+    }
+    
+    fn condition(sql: &str) -> FilterExpression {
+        sql::parse_condition(sql).unwrap()
+    }
+    
+    #[test]
+    fn test_expression_parsing() {
+        assert_eq!(IndexExpression::parse("email").unwrap(), IndexExpression::Field("email".to_string()));
+        assert_eq!(IndexExpression::parse("LOWER( email )").unwrap(), IndexExpression::Lower("email".to_string()));
+        assert_eq!(IndexExpression::parse("lower(email)").unwrap().to_string(), "lower(email)");
+        assert!(IndexExpression::parse("reverse(email)").is_err());
+        assert!(IndexExpression::parse("lower(email").is_err());
+        
+        let document = json!({ "email": "Ada@Example.com", "tags": ["a", "b"] });
+        assert_eq!(IndexExpression::parse("lower(email)").unwrap().evaluate(&document), Some(json!("ada@example.com")));
+        assert_eq!(IndexExpression::parse("length(tags)").unwrap().evaluate(&document), Some(json!(2)));
+    }
+    
+    #[test]
+    fn test_expression_index_maintenance() {
+        let mut index = index(&["lower(email)"], None);
+        index.update("c1", Some(&json!({ "email": "Ada@Example.com" })));
+        index.update("c2", Some(&json!({ "email": "bob@example.com" })));
+        index.update("c3", Some(&json!({ "name": "no email" })));
+        assert_eq!(index.len(), 2);
+        
+        let lookup = condition("lower(email) = 'ada@example.com'");
+        assert_eq!(index.lookup(&lookup).unwrap(), BTreeSet::from(["c1".to_string()]));
+        
+        // Changing the document moves it to its new key
+        index.update("c1", Some(&json!({ "email": "ADA@new.org" })));
+        assert!(index.lookup(&lookup).unwrap().is_empty());
+        assert_eq!(index.lookup(&condition("lower(email) = 'ada@new.org'")).unwrap().len(), 1);
+        
+        index.remove("c2");
+        assert_eq!(index.len(), 1);
+        assert!(index.lookup(&condition("email = 'x'")).is_none());
+    }
+    
+    #[test]
+    fn test_partial_index() {
+        let mut index = index(&["age"], Some("active = true"));
+        for (id, age, active) in [("c1", 30, true), ("c2", 40, false), ("c3", 50, true), ("c4", 20, true)] {
+            index.update(id, Some(&json!({ "age": age, "active": active })));
+        }
+        assert_eq!(index.len(), 3);
+        
+        let filter = condition("active = true AND age >= 30");
+        assert!(index.covers(&filter));
+        assert!(!index.covers(&condition("age >= 30")));
+        
+        let ids = index.lookup(&condition("age >= 30")).unwrap();
+        assert_eq!(ids, BTreeSet::from(["c1".to_string(), "c3".to_string()]));
+        assert_eq!(index.lookup(&condition("age < 30")).unwrap(), BTreeSet::from(["c4".to_string()]));
+        assert_eq!(index.lookup(&condition("age IN (20, 50)")).unwrap().len(), 2);
+    }
+    
+    #[test]
+    fn test_background_build_catches_up_with_writes() {
+        use crate::core::query::{Query, QueryType};
+        use crate::core::schema::Schema;
+        
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut hive = Hive::new(
+            "orders".to_string(),
+            String::new(),
+            "test-user".to_string(),
+            temp_dir.path().to_path_buf(),
+            (16, 16),
+        ).unwrap();
+        hive.set_schema(Schema::new("order".to_string(), String::new(), "1".to_string())).unwrap();
+        Query::new(QueryType::Insert, "orders".to_string())
+            .with_data(json!([
+                { "_id": "o1", "item": "apple" },
+                { "_id": "o2", "item": "pear" },
+            ]))
+            .execute(&mut hive)
+            .unwrap();
+        let hive = Arc::new(RwLock::new(hive));
+        
+        // Drive the build by hand so the writes land between scan and swap
+        let mut index = SecondaryIndex::new(SchemaIndex::new(
+            "by_item".to_string(), vec!["item".to_string()], IndexType::Hash, false,
+        )).unwrap();
+        let progress = Arc::new(BuildProgress::default());
+        let cells = {
+            let mut hive = hive.write().unwrap();
+            hive.indexes.building.insert("by_item".to_string(), progress.clone());
+            hive.cells.all_cells()
+        };
+        scan_cells(&mut index, &cells, &progress).unwrap();
+        assert_eq!(progress.scanned(), 2);
+        
+        {
+            let mut hive = hive.write().unwrap();
+            Query::new(QueryType::Insert, "orders".to_string())
+                .with_data(json!({ "_id": "o3", "item": "apple" }))
+                .execute(&mut hive)
+                .unwrap();
+            Query::new(QueryType::Delete, "orders".to_string())
+                .with_filter(condition("_id = 'o1'"))
+                .execute(&mut hive)
+                .unwrap();
+        }
+        swap_in(&hive, index, &progress).unwrap();
+        
+        let hive_ref = hive.read().unwrap();
+        assert_eq!(hive_ref.indexes.building().count(), 0);
+        assert_eq!(hive_ref.schema.as_ref().unwrap().indexes.len(), 1);
+        let apples = hive_ref.indexes.get("by_item").unwrap().lookup(&condition("item = 'apple'")).unwrap();
+        assert_eq!(apples, BTreeSet::from(["o3".to_string()]));
+        drop(hive_ref);
+        
+        // The same through a build thread, replacing the index
+        let build = build_in_background(&hive, SchemaIndex::new(
+            "by_item".to_string(), vec!["upper(item)".to_string()], IndexType::BTree, false,
+        )).unwrap();
+        build.wait().unwrap();
+        
+        let hive = hive.read().unwrap();
+        let index = hive.indexes.get("by_item").unwrap();
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.lookup(&condition("upper(item) = 'PEAR'")).unwrap().len(), 1);
+    }
+    
+    #[test]
+    fn test_pattern_lookups() {
+        let mut by_name = index(&["name"], None);
+        let mut grams = SecondaryIndex::new(SchemaIndex::new(
+            "name_grams".to_string(), vec!["lower(name)".to_string()], IndexType::Ngram, false,
+        )).unwrap();
+        for (id, name) in [("c1", "Honeycomb"), ("c2", "Honey"), ("c3", "Beeswax"), ("c4", "Wax")] {
+            by_name.update(id, Some(&json!({ "name": name })));
+            grams.update(id, Some(&json!({ "name": name })));
+        }
+        
+        // Patterns with a literal prefix range over an ordinary index
+        assert_eq!(by_name.lookup(&condition("name LIKE 'Hon%'")).unwrap().len(), 2);
+        assert!(by_name.lookup(&condition("name LIKE '%wax'")).is_none());
+        
+        // An n-gram index narrows patterns matching anywhere in the value
+        assert_eq!(grams.lookup(&condition("lower(name) LIKE '%wax%'")).unwrap(), BTreeSet::from(["c3".to_string(), "c4".to_string()]));
+        assert!(grams.lookup(&condition("lower(name) LIKE '%x%'")).is_none());
+        assert!(grams.lookup(&condition("name LIKE '%wax%'")).is_none());
+        assert!(!grams.stores("name"));
+        
+        let mut set = IndexSet::default();
+        set.insert(by_name);
+        set.insert(grams);
+        let filter = condition("lower(name) LIKE '%comb%'");
+        assert_eq!(set.plan(&filter, None, None).unwrap().index.definition().name, "name_grams");
+        
+        assert!(SecondaryIndex::new(SchemaIndex::new(
+            "bad".to_string(), vec!["a".to_string(), "b".to_string()], IndexType::Ngram, false,
+        )).is_err());
+    }
+    
+    #[test]
+    fn test_plan_prefers_selective_condition() {
+        let mut set = IndexSet::default();
+        set.indexes.insert("by_age".to_string(), index(&["age"], None));
+        let mut by_email = index(&["lower(email)"], None);
+        by_email.definition.name = "by_email".to_string();
+        set.indexes.insert("by_email".to_string(), by_email);
+        
+        let filter = condition("age > 20 AND lower(email) = 'ada@example.com' AND name LIKE 'A%'");
+        let scan = set.plan(&filter, None, None).unwrap();
+        assert_eq!(scan.index.definition().name, "by_email");
+        assert!(!scan.index_only);
+        
+        assert!(set.plan(&condition("name = 'Ada' OR age > 3"), None, None).is_none());
+    }
+    
+    #[test]
+    fn test_covering_index() {
+        let definition = SchemaIndex::new("by_age".to_string(), vec!["age".to_string()], IndexType::BTree, false)
+            .with_include(vec!["name".to_string()]);
+        let mut index = SecondaryIndex::new(definition).unwrap();
+        index.update("c1", Some(&json!({ "_id": "c1", "age": 30, "name": "Ada", "bio": "long text" })));
+        index.update("c2", Some(&json!({ "_id": "c2", "age": 40 })));
+        
+        assert!(index.stores("age") && index.stores("name"));
+        assert!(!index.stores("bio"));
+        assert_eq!(index.document("c1"), Some(json!({ "age": 30, "name": "Ada" })));
+        assert_eq!(index.document("c2"), Some(json!({ "age": 40 })));
+        
+        let mut set = IndexSet::default();
+        set.indexes.insert("by_age".to_string(), index);
+        let filter = condition("age > 20");
+        
+        let columns = BTreeSet::from(["age".to_string(), "name".to_string()]);
+        assert!(set.plan(&filter, None, Some(&columns)).unwrap().index_only);
+        
+        let columns = BTreeSet::from(["bio".to_string()]);
+        assert!(!set.plan(&filter, None, Some(&columns)).unwrap().index_only);
+    }
+}
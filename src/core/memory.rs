@@ -0,0 +1,419 @@
+// HiveDB Memory Module
+//
+// This module keeps an approximate account of the memory used by caches,
+// in-flight queries and decompression buffers against a configurable
+// budget. When the budget is exceeded, registered caches are asked to
+// shed entries first; queries that still do not fit fail with
+// `MemoryBudgetExceeded`, and new queries wait for memory to be released
+// before they start. The default budget is unlimited.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use crate::core::error::HiveError;
+use log::{debug, warn};
+
+/// Default time a new query waits for memory before it is rejected
+pub const DEFAULT_ADMISSION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The budget shared by the whole process
+static GLOBAL: MemoryBudget = MemoryBudget::unlimited();
+
+/// What memory is used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MemoryCategory {
+    /// Entries of caches that can be shed
+    Cache,
+    
+    /// Documents held by running queries
+    Queries,
+    
+    /// Buffers holding decompressed cell content
+    Decompression,
+}
+
+impl MemoryCategory {
+    /// Index of the category's usage counter
+    fn index(self) -> usize {
+        match self {
+            MemoryCategory::Cache => 0,
+            MemoryCategory::Queries => 1,
+            MemoryCategory::Decompression => 2,
+        }
+    }
+}
+
+/// A cache whose entries can be dropped to free memory
+pub trait Reclaim: Send + Sync {
+    /// Drop entries until about `bytes` are freed, and return the amount freed
+    fn reclaim(&self, bytes: usize) -> usize;
+}
+
+/// Snapshot of the memory accounted against a budget
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MemoryUsage {
+    /// Budget in bytes (None if unlimited)
+    pub limit: Option<usize>,
+    
+    /// Bytes used by caches
+    pub cache: usize,
+    
+    /// Bytes used by running queries
+    pub queries: usize,
+    
+    /// Bytes used by decompression buffers
+    pub decompression: usize,
+    
+    /// Number of queries rejected because memory ran out
+    pub rejected_queries: usize,
+}
+
+impl MemoryUsage {
+    /// Get the total number of bytes in use
+    pub fn total(&self) -> usize {
+        self.cache + self.queries + self.decompression
+    }
+}
+
+/// Memory accounting against a limit
+pub struct MemoryBudget {
+    /// Limit in bytes (0 means unlimited)
+    limit: AtomicUsize,
+    
+    /// Bytes in use, by category
+    used: [AtomicUsize; 3],
+    
+    /// Queries rejected because memory ran out
+    rejected: AtomicUsize,
+    
+    /// How long new queries wait for memory, in milliseconds
+    admission_timeout_ms: AtomicUsize,
+    
+    /// Caches that can shed entries
+    caches: Mutex<Vec<Weak<dyn Reclaim>>>,
+    
+    /// Signalled whenever memory is released
+    released: Condvar,
+    
+    /// Lock paired with `released`
+    waiters: Mutex<()>,
+}
+
+impl MemoryBudget {
+    /// Create a budget without a limit
+    pub const fn unlimited() -> Self {
+        Self {
+            limit: AtomicUsize::new(0),
+            used: [AtomicUsize::new(0), AtomicUsize::new(0), AtomicUsize::new(0)],
+            rejected: AtomicUsize::new(0),
+            admission_timeout_ms: AtomicUsize::new(DEFAULT_ADMISSION_TIMEOUT.as_millis() as usize),
+            caches: Mutex::new(Vec::new()),
+            released: Condvar::new(),
+            waiters: Mutex::new(()),
+        }
+    }
+    
+    /// Create a budget with a limit in bytes
+    pub fn new(limit: usize) -> Self {
+        let budget = Self::unlimited();
+        budget.set_limit(Some(limit));
+        budget
+    }
+    
+    /// Change the limit (None for unlimited)
+    pub fn set_limit(&self, limit: Option<usize>) {
+        self.limit.store(limit.unwrap_or(0), Ordering::Relaxed);
+        self.released.notify_all();
+    }
+    
+    /// Get the limit (None if unlimited)
+    pub fn limit(&self) -> Option<usize> {
+        match self.limit.load(Ordering::Relaxed) {
+            0 => None,
+            limit => Some(limit),
+        }
+    }
+    
+    /// Change how long new queries wait for memory before they are rejected
+    pub fn set_admission_timeout(&self, timeout: Duration) {
+        self.admission_timeout_ms.store(timeout.as_millis() as usize, Ordering::Relaxed);
+    }
+    
+    /// Register a cache that can shed entries when memory runs short
+    pub fn register_cache(&self, cache: Weak<dyn Reclaim>) {
+        if let Ok(mut caches) = self.caches.lock() {
+            caches.retain(|cache| cache.strong_count() > 0);
+            caches.push(cache);
+        }
+    }
+    
+    /// Get the memory currently accounted
+    pub fn usage(&self) -> MemoryUsage {
+        let used = |category: MemoryCategory| self.used[category.index()].load(Ordering::Relaxed);
+        
+        MemoryUsage {
+            limit: self.limit(),
+            cache: used(MemoryCategory::Cache),
+            queries: used(MemoryCategory::Queries),
+            decompression: used(MemoryCategory::Decompression),
+            rejected_queries: self.rejected.load(Ordering::Relaxed),
+        }
+    }
+    
+    /// Admit a new query, waiting while the budget is exhausted
+    ///
+    /// The returned reservation starts empty; the query grows it as it
+    /// loads documents.
+    pub fn admit(&self) -> Result<Reservation<'_>, HiveError> {
+        let timeout = Duration::from_millis(self.admission_timeout_ms.load(Ordering::Relaxed) as u64);
+        let deadline = Instant::now() + timeout;
+        
+        let mut guard = self.waiters.lock().map_err(|_| HiveError::LockError)?;
+        while self.over_limit(0) {
+            let now = Instant::now();
+            if now >= deadline {
+                self.rejected.fetch_add(1, Ordering::Relaxed);
+                return Err(HiveError::MemoryBudgetExceeded(format!(
+                    "no memory became available within {} ms ({} of {} bytes in use)",
+                    timeout.as_millis(),
+                    self.usage().total(),
+                    self.limit().unwrap_or(0)
+                )));
+            }
+            
+            debug!("Query waiting for memory to be released");
+            guard = self.released.wait_timeout(guard, deadline - now)
+                .map_err(|_| HiveError::LockError)?
+                .0;
+        }
+        
+        Ok(Reservation {
+            budget: self,
+            category: MemoryCategory::Queries,
+            bytes: 0,
+        })
+    }
+    
+    /// Reserve memory, shedding cache entries if needed
+    ///
+    /// Fails with `MemoryBudgetExceeded` if the memory cannot be found.
+    pub fn reserve(&self, category: MemoryCategory, bytes: usize) -> Result<Reservation<'_>, HiveError> {
+        let mut reservation = Reservation {
+            budget: self,
+            category,
+            bytes: 0,
+        };
+        reservation.grow(bytes)?;
+        
+        Ok(reservation)
+    }
+    
+    /// Reserve memory only if it is available without shedding anything
+    pub fn try_reserve(&self, category: MemoryCategory, bytes: usize) -> Option<Reservation<'_>> {
+        if self.over_limit(bytes) {
+            return None;
+        }
+        
+        self.used[category.index()].fetch_add(bytes, Ordering::Relaxed);
+        Some(Reservation {
+            budget: self,
+            category,
+            bytes,
+        })
+    }
+    
+    /// Check whether using `extra` more bytes would exceed the limit
+    fn over_limit(&self, extra: usize) -> bool {
+        match self.limit() {
+            Some(limit) => self.usage().total() + extra > limit,
+            None => false,
+        }
+    }
+    
+    /// Ask the registered caches to free `bytes`, and return the amount freed
+    fn reclaim(&self, bytes: usize) -> usize {
+        let caches: Vec<Arc<dyn Reclaim>> = match self.caches.lock() {
+            Ok(caches) => caches.iter().filter_map(Weak::upgrade).collect(),
+            Err(_) => return 0,
+        };
+        
+        let mut freed = 0;
+        for cache in caches {
+            if freed >= bytes {
+                break;
+            }
+            freed += cache.reclaim(bytes - freed);
+        }
+        
+        if freed > 0 {
+            debug!("Shed {} bytes of cache entries", freed);
+        }
+        freed
+    }
+    
+    /// Return memory to the budget
+    fn release(&self, category: MemoryCategory, bytes: usize) {
+        if bytes == 0 {
+            return;
+        }
+        
+        self.used[category.index()].fetch_sub(bytes, Ordering::Relaxed);
+        let _guard = self.waiters.lock();
+        self.released.notify_all();
+    }
+}
+
+/// Memory accounted against a budget, released when dropped
+pub struct Reservation<'a> {
+    /// Budget the memory is accounted against
+    budget: &'a MemoryBudget,
+    
+    /// What the memory is used for
+    category: MemoryCategory,
+    
+    /// Bytes reserved
+    bytes: usize,
+}
+
+impl Reservation<'_> {
+    /// Reserve more memory, shedding cache entries if needed
+    pub fn grow(&mut self, bytes: usize) -> Result<(), HiveError> {
+        if self.budget.over_limit(bytes) {
+            let limit = self.budget.limit().unwrap_or(0);
+            let needed = (self.budget.usage().total() + bytes).saturating_sub(limit);
+            self.budget.reclaim(needed);
+            
+            if self.budget.over_limit(bytes) {
+                if self.category == MemoryCategory::Queries {
+                    self.budget.rejected.fetch_add(1, Ordering::Relaxed);
+                }
+                warn!("Memory budget of {} bytes exceeded by a {:?} reservation", limit, self.category);
+                return Err(HiveError::MemoryBudgetExceeded(format!(
+                    "{} more bytes needed for {:?} but {} of {} bytes are in use",
+                    bytes,
+                    self.category,
+                    self.budget.usage().total(),
+                    limit
+                )));
+            }
+        }
+        
+        self.budget.used[self.category.index()].fetch_add(bytes, Ordering::Relaxed);
+        self.bytes += bytes;
+        Ok(())
+    }
+    
+    /// Return part of the memory to the budget
+    pub fn shrink(&mut self, bytes: usize) {
+        let bytes = bytes.min(self.bytes);
+        self.bytes -= bytes;
+        self.budget.release(self.category, bytes);
+    }
+    
+    /// Get the number of bytes reserved
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        self.budget.release(self.category, self.bytes);
+    }
+}
+
+/// Get the budget shared by the whole process
+pub fn global() -> &'static MemoryBudget {
+    &GLOBAL
+}
+
+/// Parse a size such as `512M` or `2G` into bytes
+pub fn parse_size(value: &str) -> Result<usize, HiveError> {
+    let value = value.trim();
+    let (number, multiplier) = match value.char_indices().last() {
+        Some((index, 'K' | 'k')) => (&value[..index], 1 << 10),
+        Some((index, 'M' | 'm')) => (&value[..index], 1 << 20),
+        Some((index, 'G' | 'g')) => (&value[..index], 1 << 30),
+        _ => (value, 1),
+    };
+    
+    number.trim().parse::<usize>()
+        .map(|number| number * multiplier)
+        .map_err(|_| HiveError::GenericError(format!("Invalid size '{}'", value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// A cache holding a fixed amount of memory that it gives up on request
+    struct TestCache<'a> {
+        reservation: Mutex<Option<Reservation<'a>>>,
+    }
+    
+    impl Reclaim for TestCache<'static> {
+        fn reclaim(&self, _bytes: usize) -> usize {
+            self.reservation.lock().unwrap().take().map_or(0, |reservation| reservation.bytes())
+        }
+    }
+    
+    #[test]
+    fn test_reservations_and_release() {
+        let budget = MemoryBudget::new(1000);
+        
+        let mut query = budget.admit().unwrap();
+        query.grow(600).unwrap();
+        let buffer = budget.reserve(MemoryCategory::Decompression, 300).unwrap();
+        assert_eq!(budget.usage().total(), 900);
+        
+        assert!(budget.try_reserve(MemoryCategory::Cache, 200).is_none());
+        assert!(matches!(query.grow(200), Err(HiveError::MemoryBudgetExceeded(_))));
+        assert_eq!(budget.usage().rejected_queries, 1);
+        
+        drop(buffer);
+        query.grow(200).unwrap();
+        drop(query);
+        assert_eq!(budget.usage().total(), 0);
+    }
+    
+    #[test]
+    fn test_caches_are_shed() {
+        let budget: &'static MemoryBudget = Box::leak(Box::new(MemoryBudget::new(1000)));
+        let cache = Arc::new(TestCache {
+            reservation: Mutex::new(budget.try_reserve(MemoryCategory::Cache, 800)),
+        });
+        let weak: Weak<dyn Reclaim> = Arc::downgrade(&(cache.clone() as Arc<dyn Reclaim>));
+        budget.register_cache(weak);
+        
+        let mut query = budget.admit().unwrap();
+        query.grow(500).unwrap();
+        assert_eq!(budget.usage().cache, 0);
+        assert_eq!(budget.usage().queries, 500);
+    }
+    
+    #[test]
+    fn test_admission_waits_for_memory() {
+        let budget: &'static MemoryBudget = Box::leak(Box::new(MemoryBudget::new(100)));
+        budget.set_admission_timeout(Duration::from_millis(50));
+        
+        let held = budget.reserve(MemoryCategory::Queries, 100).unwrap();
+        assert!(matches!(budget.admit(), Err(HiveError::MemoryBudgetExceeded(_))));
+        
+        budget.set_admission_timeout(Duration::from_secs(5));
+        let releaser = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            drop(held);
+        });
+        assert!(budget.admit().is_ok());
+        releaser.join().unwrap();
+    }
+    
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("64K").unwrap(), 64 * 1024);
+        assert_eq!(parse_size("2g").unwrap(), 2 << 30);
+        assert!(parse_size("lots").is_err());
+    }
+}
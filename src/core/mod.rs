@@ -3,14 +3,49 @@
 // This module contains the core components of the HiveDB system,
 // including the hexagonal data structure and basic operations.
 
+pub mod analyze;
+pub mod cache;
 pub mod cell;
+pub mod change;
+pub mod codec;
+pub mod comb;
+pub mod commit;
+pub mod compression;
+pub mod coords;
+pub mod disk;
+pub mod dsl;
+pub mod durability;
+pub mod events;
+pub mod graph;
+pub mod heatmap;
+pub mod history;
 pub mod hive;
+pub mod idl;
+pub mod index;
+pub mod memory;
+pub mod mode;
+pub mod ngram;
+pub mod offsets;
+pub mod patch;
+pub mod pheromone;
 pub mod query;
+pub mod remote;
+pub mod retention;
 pub mod schema;
+pub mod scheduler;
+pub mod session;
+pub mod snapshot;
+pub mod sql;
+pub mod stats;
+pub mod text;
+pub mod tiering;
+pub mod verify;
+pub mod worker;
 pub mod error;
 
 // Re-export important types
 pub use cell::Cell;
+pub use change::{ChangeEvent, ChangeLog};
 pub use hive::Hive;
 pub use query::Query;
 pub use schema::Schema;
@@ -46,6 +81,12 @@ pub struct Config {
     
     /// Hexagonal grid dimensions
     pub grid_dimensions: (usize, usize),
+    
+    /// When changed hives are saved and synced to disk
+    pub durability: durability::Durability,
+    
+    /// Limits on index builds, compaction and other background work
+    pub background: worker::BackgroundLimits,
 }
 
 impl Default for Config {
@@ -55,6 +96,8 @@ impl Default for Config {
             compression_level: 6,
             enable_swarm_optimization: true,
             grid_dimensions: (64, 64),
+            durability: durability::Durability::default(),
+            background: worker::BackgroundLimits::default(),
         }
     }
 }
@@ -73,5 +116,7 @@ pub fn new_config(
         compression_level: compression.unwrap_or(default.compression_level),
         enable_swarm_optimization: swarm_opt.unwrap_or(default.enable_swarm_optimization),
         grid_dimensions: dimensions.unwrap_or(default.grid_dimensions),
+        durability: default.durability,
+        background: default.background,
     }
 }
@@ -0,0 +1,171 @@
+// HiveDB Mode Module
+//
+// This module defines the operating modes of a HiveDB server. A server
+// can be switched at runtime into a read-only mode (replicas, freezes
+// before an upgrade) or a maintenance mode in which only the admin API
+// is served. Writes are also refused while the disk monitor, if any,
+// finds the data directory almost out of space.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, Ordering};
+use crate::core::disk::DiskMonitor;
+use crate::core::error::HiveError;
+
+/// Operating mode of a server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServerMode {
+    /// All traffic is accepted
+    Normal,
+    
+    /// Reads are accepted, writes are rejected
+    ReadOnly,
+    
+    /// Only admin traffic is accepted
+    Maintenance,
+}
+
+impl ServerMode {
+    /// Get the name of the mode as used by the admin API
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ServerMode::Normal => "normal",
+            ServerMode::ReadOnly => "read_only",
+            ServerMode::Maintenance => "maintenance",
+        }
+    }
+    
+    /// Parse a mode name as used by the admin API
+    pub fn parse(name: &str) -> Result<Self, HiveError> {
+        match name.to_ascii_lowercase().replace('-', "_").as_str() {
+            "normal" => Ok(ServerMode::Normal),
+            "read_only" | "readonly" => Ok(ServerMode::ReadOnly),
+            "maintenance" => Ok(ServerMode::Maintenance),
+            _ => Err(HiveError::DeserializationError(format!("Unknown server mode '{}'", name))),
+        }
+    }
+}
+
+/// The current mode of a server, shared by its listeners
+#[derive(Debug)]
+pub struct ModeControl {
+    /// Current mode, encoded as a u8
+    mode: AtomicU8,
+    
+    /// Free space of the data directory, if it is watched
+    disk: Option<Arc<DiskMonitor>>,
+}
+
+impl ModeControl {
+    /// Create a control in the given mode
+    pub fn new(mode: ServerMode) -> Self {
+        Self {
+            mode: AtomicU8::new(encode(mode)),
+            disk: None,
+        }
+    }
+    
+    /// Refuse writes while a disk monitor finds too little free space
+    pub fn with_disk_monitor(mut self, disk: Arc<DiskMonitor>) -> Self {
+        self.disk = Some(disk);
+        self
+    }
+    
+    /// Get the disk monitor, if any
+    pub fn disk(&self) -> Option<&Arc<DiskMonitor>> {
+        self.disk.as_ref()
+    }
+    
+    /// Get the current mode
+    pub fn get(&self) -> ServerMode {
+        match self.mode.load(Ordering::SeqCst) {
+            1 => ServerMode::ReadOnly,
+            2 => ServerMode::Maintenance,
+            _ => ServerMode::Normal,
+        }
+    }
+    
+    /// Switch to another mode and return the previous one
+    pub fn set(&self, mode: ServerMode) -> ServerMode {
+        match self.mode.swap(encode(mode), Ordering::SeqCst) {
+            1 => ServerMode::ReadOnly,
+            2 => ServerMode::Maintenance,
+            _ => ServerMode::Normal,
+        }
+    }
+    
+    /// Check that client reads are currently allowed
+    pub fn check_read(&self) -> Result<(), HiveError> {
+        match self.get() {
+            ServerMode::Maintenance => Err(HiveError::MaintenanceMode),
+            _ => Ok(()),
+        }
+    }
+    
+    /// Check that client writes are currently allowed
+    pub fn check_write(&self) -> Result<(), HiveError> {
+        match self.get() {
+            ServerMode::Normal => {}
+            ServerMode::ReadOnly => return Err(HiveError::ReadOnlyMode),
+            ServerMode::Maintenance => return Err(HiveError::MaintenanceMode),
+        }
+        
+        match &self.disk {
+            Some(disk) => disk.check_write(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Default for ModeControl {
+    fn default() -> Self {
+        Self::new(ServerMode::Normal)
+    }
+}
+
+fn encode(mode: ServerMode) -> u8 {
+    match mode {
+        ServerMode::Normal => 0,
+        ServerMode::ReadOnly => 1,
+        ServerMode::Maintenance => 2,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_mode_checks() {
+        let control = ModeControl::default();
+        assert!(control.check_read().is_ok());
+        assert!(control.check_write().is_ok());
+        
+        assert_eq!(control.set(ServerMode::ReadOnly), ServerMode::Normal);
+        assert!(control.check_read().is_ok());
+        assert!(matches!(control.check_write(), Err(HiveError::ReadOnlyMode)));
+        
+        control.set(ServerMode::Maintenance);
+        assert!(matches!(control.check_read(), Err(HiveError::MaintenanceMode)));
+        assert!(matches!(control.check_write(), Err(HiveError::MaintenanceMode)));
+    }
+    
+    #[test]
+    fn test_disk_full_refuses_writes() {
+        let disk = Arc::new(DiskMonitor::new("data".into(), 1000));
+        let control = ModeControl::default().with_disk_monitor(disk.clone());
+        assert!(control.check_write().is_ok());
+        
+        disk.record(10);
+        assert!(control.check_read().is_ok());
+        assert!(matches!(control.check_write(), Err(HiveError::DiskSpaceLow(_))));
+    }
+    
+    #[test]
+    fn test_mode_names() {
+        for mode in [ServerMode::Normal, ServerMode::ReadOnly, ServerMode::Maintenance] {
+            assert_eq!(ServerMode::parse(mode.as_str()).unwrap(), mode);
+        }
+        assert_eq!(ServerMode::parse("read-only").unwrap(), ServerMode::ReadOnly);
+        assert!(ServerMode::parse("frozen").is_err());
+    }
+}
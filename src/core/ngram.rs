@@ -0,0 +1,148 @@
+// HiveDB N-gram Module
+//
+// This module indexes string values by their trigrams, the runs of three
+// consecutive characters they contain. A LIKE pattern can only match a
+// value holding every trigram of the literal text in the pattern, so the
+// index narrows `%abc%`-style searches to a few candidates, which are then
+// checked against the pattern itself.
+
+use std::collections::{BTreeSet, HashMap};
+
+/// Number of characters in a gram
+pub const GRAM_LENGTH: usize = 3;
+
+/// Trigram index of a string value per cell
+#[derive(Debug, Clone, Default)]
+pub struct NgramIndex {
+    /// Cells whose value holds each gram
+    grams: HashMap<String, BTreeSet<String>>,
+    
+    /// Distinct grams of each cell's value
+    values: HashMap<String, Vec<String>>,
+}
+
+impl NgramIndex {
+    /// Create an empty index
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Index the value held by a cell, replacing what was indexed for it
+    pub fn insert(&mut self, cell_id: &str, value: &str) {
+        self.remove(cell_id);
+        
+        let grams = grams(value);
+        for gram in &grams {
+            self.grams.entry(gram.clone()).or_default().insert(cell_id.to_string());
+        }
+        self.values.insert(cell_id.to_string(), grams.into_iter().collect());
+    }
+    
+    /// Remove a cell from the index
+    pub fn remove(&mut self, cell_id: &str) {
+        let Some(grams) = self.values.remove(cell_id) else {
+            return;
+        };
+        
+        for gram in grams {
+            if let Some(cells) = self.grams.get_mut(&gram) {
+                cells.remove(cell_id);
+                if cells.is_empty() {
+                    self.grams.remove(&gram);
+                }
+            }
+        }
+    }
+    
+    /// Get the number of indexed values
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+    
+    /// Check whether no value is indexed
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+    
+    /// Find the cells whose value may match a LIKE pattern
+    ///
+    /// Every value matching the pattern is among the candidates, but not
+    /// every candidate matches. Returns None if the pattern has no run of
+    /// literal text long enough to hold a gram.
+    pub fn lookup(&self, pattern: &str) -> Option<BTreeSet<String>> {
+        let mut required = pattern_grams(pattern);
+        if required.is_empty() {
+            return None;
+        }
+        
+        // Intersect the rarest grams first to keep the sets small
+        required.sort_by_key(|gram| self.grams.get(gram).map_or(0, BTreeSet::len));
+        let mut candidates: Option<BTreeSet<String>> = None;
+        for gram in &required {
+            let Some(cells) = self.grams.get(gram) else {
+                return Some(BTreeSet::new());
+            };
+            candidates = Some(match candidates {
+                Some(candidates) => candidates.intersection(cells).cloned().collect(),
+                None => cells.clone(),
+            });
+            if candidates.as_ref().is_some_and(BTreeSet::is_empty) {
+                break;
+            }
+        }
+        
+        candidates
+    }
+    
+    /// Estimate the number of cells a LIKE pattern selects, if the index can answer it
+    pub fn estimate(&self, pattern: &str) -> Option<usize> {
+        pattern_grams(pattern).iter()
+            .map(|gram| self.grams.get(gram).map_or(0, BTreeSet::len))
+            .min()
+    }
+}
+
+/// Get the distinct grams of a value
+fn grams(value: &str) -> BTreeSet<String> {
+    let chars: Vec<char> = value.chars().collect();
+    chars.windows(GRAM_LENGTH)
+        .map(|window| window.iter().collect())
+        .collect()
+}
+
+/// Get the grams every value matching a LIKE pattern must hold
+fn pattern_grams(pattern: &str) -> Vec<String> {
+    let required: BTreeSet<String> = pattern.split(['%', '_'])
+        .flat_map(grams)
+        .collect();
+    required.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_ngram_lookup() {
+        let mut index = NgramIndex::new();
+        index.insert("c1", "honeycomb");
+        index.insert("c2", "honey");
+        index.insert("c3", "comb");
+        index.insert("c4", "ok");
+        
+        let ids = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<BTreeSet<_>>();
+        assert_eq!(index.lookup("%comb%").unwrap(), ids(&["c1", "c3"]));
+        assert_eq!(index.lookup("hon%").unwrap(), ids(&["c1", "c2"]));
+        assert_eq!(index.lookup("%hon_%comb").unwrap(), ids(&["c1"]));
+        assert!(index.lookup("%xyz%").unwrap().is_empty());
+        
+        // Runs shorter than a gram cannot narrow the search
+        assert!(index.lookup("%ok%").is_none());
+        assert!(index.lookup("%").is_none());
+        assert_eq!(index.estimate("%comb%"), Some(2));
+        
+        index.remove("c1");
+        assert_eq!(index.lookup("%comb%").unwrap(), ids(&["c3"]));
+        assert_eq!(index.len(), 3);
+    }
+}
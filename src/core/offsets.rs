@@ -0,0 +1,283 @@
+// HiveDB Offsets Module
+//
+// This module lets a filter read a few fields of a large JSON document
+// without decompressing all of it. Documents of at least
+// `OFFSET_THRESHOLD` bytes are compressed as independent LZ4 segments
+// rather than one frame, and the cell keeps a sidecar naming where each
+// segment ends and which bytes of the document hold each top-level
+// field's value. Reading a field then decodes only the segments holding
+// it. Documents whose keys use escapes, or that are not JSON objects,
+// are compressed whole as before.
+
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use serde::{Deserialize, Serialize};
+use crate::core::codec;
+use crate::core::error::HiveError;
+
+/// Smallest document compressed in segments with field offsets
+pub const OFFSET_THRESHOLD: usize = 64 * 1024;
+
+/// Decompressed size of each segment
+pub const SEGMENT_SIZE: usize = 16 * 1024;
+
+/// A top-level field's name, with the JSON text of its value
+pub type FieldText = (String, Vec<u8>);
+
+/// Where the segments of a document end, and where its fields lie
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ContentOffsets {
+    /// Segments in order
+    segments: Vec<Segment>,
+    
+    /// Range of the decompressed document holding each top-level field's value
+    fields: BTreeMap<String, (usize, usize)>,
+}
+
+/// One independently compressed segment of a document
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Segment {
+    /// End of the segment in the stored content
+    pub stored_end: usize,
+    
+    /// End of the segment in the decompressed document
+    pub content_end: usize,
+}
+
+impl ContentOffsets {
+    /// Compress a JSON document in segments, if it is large enough and its fields can be located
+    ///
+    /// Returns the stored content with its offsets, or `None` when the
+    /// document should be compressed whole.
+    pub fn compress(content: &[u8]) -> Result<Option<(Vec<u8>, Self)>, HiveError> {
+        if content.len() < OFFSET_THRESHOLD {
+            return Ok(None);
+        }
+        let Some(fields) = field_spans(content) else {
+            return Ok(None);
+        };
+        
+        let mut stored = Vec::new();
+        let mut segments = Vec::new();
+        let mut content_end = 0;
+        for chunk in content.chunks(SEGMENT_SIZE) {
+            stored.extend_from_slice(&codec::lz4_compress(chunk)?);
+            content_end += chunk.len();
+            segments.push(Segment { stored_end: stored.len(), content_end });
+        }
+        
+        Ok(Some((stored, Self { segments, fields })))
+    }
+    
+    /// Get the segments of the document
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+    
+    /// Check whether the document has a top-level field
+    pub fn has_field(&self, name: &str) -> bool {
+        self.fields.contains_key(name)
+    }
+    
+    /// Decompress the whole document
+    pub fn decompress(&self, stored: &[u8]) -> Result<Vec<u8>, HiveError> {
+        let mut content = Vec::with_capacity(self.segments.last().map_or(0, |segment| segment.content_end));
+        for index in 0..self.segments.len() {
+            content.extend_from_slice(&self.decompress_segment(stored, index)?);
+        }
+        Ok(content)
+    }
+    
+    /// Get the JSON text of some top-level fields, decompressing only the segments holding them
+    ///
+    /// Fields the document lacks are left out. Each segment is decompressed
+    /// at most once.
+    pub fn fields(&self, stored: &[u8], names: &BTreeSet<String>) -> Result<Vec<FieldText>, HiveError> {
+        let spans: Vec<(&String, (usize, usize))> = names.iter()
+            .filter_map(|name| self.fields.get(name).map(|span| (name, *span)))
+            .collect();
+        
+        let mut decompressed = HashMap::new();
+        let mut fields = Vec::with_capacity(spans.len());
+        for (name, (start, end)) in spans {
+            let mut text = Vec::with_capacity(end - start);
+            for index in self.covering(start, end) {
+                if let Entry::Vacant(entry) = decompressed.entry(index) {
+                    entry.insert(self.decompress_segment(stored, index)?);
+                }
+                
+                let segment_start = self.content_start(index);
+                let from = start.max(segment_start) - segment_start;
+                let to = end.min(self.segments[index].content_end) - segment_start;
+                text.extend_from_slice(&decompressed[&index][from..to]);
+            }
+            fields.push((name.clone(), text));
+        }
+        
+        Ok(fields)
+    }
+    
+    /// Get the indexes of the segments overlapping a range of the document
+    fn covering(&self, start: usize, end: usize) -> impl Iterator<Item = usize> + '_ {
+        let first = self.segments.partition_point(|segment| segment.content_end <= start);
+        (first..self.segments.len())
+            .take_while(move |index| self.content_start(*index) < end)
+    }
+    
+    /// Get where a segment starts in the decompressed document
+    fn content_start(&self, index: usize) -> usize {
+        index.checked_sub(1).map_or(0, |previous| self.segments[previous].content_end)
+    }
+    
+    /// Decompress one segment, checking it has the size the offsets give
+    fn decompress_segment(&self, stored: &[u8], index: usize) -> Result<Vec<u8>, HiveError> {
+        let stored_start = index.checked_sub(1).map_or(0, |previous| self.segments[previous].stored_end);
+        let segment = self.segments[index];
+        let bytes = stored.get(stored_start..segment.stored_end)
+            .ok_or_else(|| HiveError::DecompressionError(format!("Segment {} lies past the stored content", index)))?;
+        
+        let content = codec::lz4_decompress(bytes)?;
+        if content.len() != segment.content_end - self.content_start(index) {
+            return Err(HiveError::DecompressionError(format!("Segment {} has the wrong size", index)));
+        }
+        Ok(content)
+    }
+}
+
+/// Locate the value of each top-level field of a JSON object
+///
+/// Returns `None` for anything but a well-formed object, or when a key
+/// uses escapes. As when parsing, the last of repeated keys wins.
+fn field_spans(content: &[u8]) -> Option<BTreeMap<String, (usize, usize)>> {
+    let mut fields = BTreeMap::new();
+    let mut at = skip_whitespace(content, 0);
+    if content.get(at) != Some(&b'{') {
+        return None;
+    }
+    at = skip_whitespace(content, at + 1);
+    if content.get(at) == Some(&b'}') {
+        return (skip_whitespace(content, at + 1) == content.len()).then_some(fields);
+    }
+    
+    loop {
+        if content.get(at) != Some(&b'"') {
+            return None;
+        }
+        let key_end = skip_string(content, at)?;
+        let key = &content[at + 1..key_end - 1];
+        if key.contains(&b'\\') {
+            return None;
+        }
+        let key = String::from_utf8(key.to_vec()).ok()?;
+        
+        at = skip_whitespace(content, key_end);
+        if content.get(at) != Some(&b':') {
+            return None;
+        }
+        let start = skip_whitespace(content, at + 1);
+        let end = skip_value(content, start)?;
+        fields.insert(key, (start, end));
+        
+        at = skip_whitespace(content, end);
+        match content.get(at) {
+            Some(b',') => at = skip_whitespace(content, at + 1),
+            Some(b'}') => return (skip_whitespace(content, at + 1) == content.len()).then_some(fields),
+            _ => return None,
+        }
+    }
+}
+
+/// Get the position after any whitespace at a position
+fn skip_whitespace(content: &[u8], mut at: usize) -> usize {
+    while content.get(at).is_some_and(|byte| byte.is_ascii_whitespace()) {
+        at += 1;
+    }
+    at
+}
+
+/// Get the position after the string starting at a position
+fn skip_string(content: &[u8], start: usize) -> Option<usize> {
+    let mut at = start + 1;
+    loop {
+        match content.get(at)? {
+            b'\\' => at += 2,
+            b'"' => return Some(at + 1),
+            _ => at += 1,
+        }
+    }
+}
+
+/// Get the position after the value starting at a position
+fn skip_value(content: &[u8], start: usize) -> Option<usize> {
+    match content.get(start)? {
+        b'"' => skip_string(content, start),
+        b'{' | b'[' => {
+            let mut depth = 0usize;
+            let mut at = start;
+            loop {
+                match content.get(at)? {
+                    b'"' => {
+                        at = skip_string(content, at)?;
+                        continue;
+                    }
+                    b'{' | b'[' => depth += 1,
+                    b'}' | b']' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            return Some(at + 1);
+                        }
+                    }
+                    _ => {}
+                }
+                at += 1;
+            }
+        }
+        _ => {
+            let end = content[start..].iter()
+                .position(|byte| matches!(byte, b',' | b'}' | b']') || byte.is_ascii_whitespace())
+                .map_or(content.len(), |length| start + length);
+            (end > start).then_some(end)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_segmented_fields() {
+        let spans = field_spans(br#" { "a" : 1, "b": {"c": "}\"]"}, "d": [1, [2]], "a": true } "#).unwrap();
+        assert_eq!(spans.len(), 3);
+        assert_eq!(spans["a"], (52, 56));
+        assert_eq!(spans["b"], (17, 30));
+        assert!(field_spans(br#"{"a\"b": 1}"#).is_none());
+        assert!(field_spans(b"[1, 2]").is_none());
+        assert!(field_spans(br#"{"a": 1"#).is_none());
+        
+        // Small documents are compressed whole
+        assert!(ContentOffsets::compress(br#"{"a": 1}"#).unwrap().is_none());
+        
+        let padding = "x".repeat(OFFSET_THRESHOLD);
+        let document = format!(r#"{{"id": 7, "padding": "{}", "status": "shipped", "tags": ["a", "b"]}}"#, padding);
+        let (stored, offsets) = ContentOffsets::compress(document.as_bytes()).unwrap().unwrap();
+        assert_eq!(offsets.segments().len(), document.len().div_ceil(SEGMENT_SIZE));
+        assert!(offsets.has_field("status") && !offsets.has_field("total"));
+        assert_eq!(offsets.decompress(&stored).unwrap(), document.as_bytes());
+        
+        let names = ["id", "status", "tags", "total"].iter().map(|name| name.to_string()).collect();
+        let fields = offsets.fields(&stored, &names).unwrap();
+        assert_eq!(fields, vec![
+            ("id".to_string(), b"7".to_vec()),
+            ("status".to_string(), br#""shipped""#.to_vec()),
+            ("tags".to_string(), br#"["a", "b"]"#.to_vec()),
+        ]);
+        
+        // A field spanning segments is joined from all of them
+        let names = ["padding".to_string()].into_iter().collect();
+        assert_eq!(offsets.fields(&stored, &names).unwrap()[0].1, format!("\"{}\"", padding).into_bytes());
+        
+        // Offsets that do not fit the stored content are caught
+        assert!(offsets.decompress(&stored[..stored.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,238 @@
+// HiveDB Patch Module
+//
+// This module applies JSON Patch documents (RFC 6902) to stored documents.
+// Operations address values with JSON Pointers (RFC 6901) such as
+// `/address/city` or `/items/0`, where `~1` stands for `/` and `~0` for `~`
+// inside a member name, and `-` names the end of an array.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::core::error::HiveError;
+use crate::core::query::{values_equal, ID_FIELD};
+
+/// One operation of a JSON Patch
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOperation {
+    /// Add a member or insert an array element, replacing a member of the same name
+    Add { path: String, value: Value },
+    
+    /// Remove a value
+    Remove { path: String },
+    
+    /// Replace an existing value
+    Replace { path: String, value: Value },
+    
+    /// Remove a value and add it elsewhere
+    Move { from: String, path: String },
+    
+    /// Add a copy of a value elsewhere
+    Copy { from: String, path: String },
+    
+    /// Check that a value is present and equal to the given one
+    Test { path: String, value: Value },
+}
+
+impl PatchOperation {
+    /// Get the pointers the operation reads or writes
+    pub fn pointers(&self) -> Vec<&str> {
+        match self {
+            PatchOperation::Add { path, .. }
+            | PatchOperation::Remove { path }
+            | PatchOperation::Replace { path, .. }
+            | PatchOperation::Test { path, .. } => vec![path],
+            PatchOperation::Move { from, path } | PatchOperation::Copy { from, path } => vec![from, path],
+        }
+    }
+}
+
+/// Read a JSON Patch document: an array of operations
+///
+/// Operations may not touch the document ID.
+pub fn parse_patch(patch: &Value) -> Result<Vec<PatchOperation>, HiveError> {
+    let operations: Vec<PatchOperation> = serde_json::from_value(patch.clone())
+        .map_err(|e| HiveError::QueryError(format!("Invalid JSON Patch: {}", e)))?;
+    
+    for operation in &operations {
+        for pointer in operation.pointers() {
+            if pointer_tokens(pointer)?.first().is_some_and(|token| token == ID_FIELD) {
+                return Err(HiveError::QueryError(format!("JSON Patch cannot change {}", ID_FIELD)));
+            }
+        }
+    }
+    
+    Ok(operations)
+}
+
+/// Apply a JSON Patch to a document
+///
+/// Either every operation applies or the document is left unchanged.
+pub fn apply_patch(document: &mut Value, operations: &[PatchOperation]) -> Result<(), HiveError> {
+    let mut patched = document.clone();
+    for operation in operations {
+        apply_operation(&mut patched, operation)?;
+    }
+    
+    *document = patched;
+    Ok(())
+}
+
+/// Split a JSON Pointer into its unescaped reference tokens
+pub fn pointer_tokens(pointer: &str) -> Result<Vec<String>, HiveError> {
+    if pointer.is_empty() {
+        return Ok(Vec::new());
+    }
+    let Some(rest) = pointer.strip_prefix('/') else {
+        return Err(HiveError::QueryError(format!("Invalid JSON Pointer '{}'", pointer)));
+    };
+    
+    Ok(rest.split('/').map(|token| token.replace("~1", "/").replace("~0", "~")).collect())
+}
+
+/// Apply one operation, which may leave the document half-changed if it fails
+fn apply_operation(document: &mut Value, operation: &PatchOperation) -> Result<(), HiveError> {
+    match operation {
+        PatchOperation::Add { path, value } => add(document, path, value.clone()),
+        PatchOperation::Remove { path } => remove(document, path).map(drop),
+        PatchOperation::Replace { path, value } => {
+            let target = resolve(document, &pointer_tokens(path)?)
+                .ok_or_else(|| missing(path))?;
+            *target = value.clone();
+            Ok(())
+        }
+        PatchOperation::Move { from, path } => {
+            if path.starts_with(&format!("{}/", from)) {
+                return Err(HiveError::QueryError(format!("Cannot move '{}' into itself", from)));
+            }
+            let value = remove(document, from)?;
+            add(document, path, value)
+        }
+        PatchOperation::Copy { from, path } => {
+            let value = resolve(document, &pointer_tokens(from)?)
+                .ok_or_else(|| missing(from))?
+                .clone();
+            add(document, path, value)
+        }
+        PatchOperation::Test { path, value } => {
+            let actual = resolve(document, &pointer_tokens(path)?).ok_or_else(|| missing(path))?;
+            if !values_equal(actual, value) {
+                return Err(HiveError::QueryError(format!("Test failed: '{}' is not {}", path, value)));
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Add a value at a pointer, whose parent must exist
+fn add(document: &mut Value, path: &str, value: Value) -> Result<(), HiveError> {
+    let tokens = pointer_tokens(path)?;
+    let Some((last, parents)) = tokens.split_last() else {
+        *document = value;
+        return Ok(());
+    };
+    
+    match resolve(document, parents).ok_or_else(|| missing(path))? {
+        Value::Object(object) => {
+            object.insert(last.clone(), value);
+        }
+        Value::Array(items) if last == "-" => items.push(value),
+        Value::Array(items) => match array_index(last) {
+            Some(index) if index <= items.len() => items.insert(index, value),
+            _ => return Err(missing(path)),
+        },
+        _ => return Err(missing(path)),
+    }
+    
+    Ok(())
+}
+
+/// Remove the value at a pointer, returning it
+fn remove(document: &mut Value, path: &str) -> Result<Value, HiveError> {
+    let tokens = pointer_tokens(path)?;
+    let Some((last, parents)) = tokens.split_last() else {
+        return Err(HiveError::QueryError("Cannot remove the whole document".to_string()));
+    };
+    
+    match resolve(document, parents) {
+        Some(Value::Object(object)) => object.remove(last).ok_or_else(|| missing(path)),
+        Some(Value::Array(items)) => match array_index(last) {
+            Some(index) if index < items.len() => Ok(items.remove(index)),
+            _ => Err(missing(path)),
+        },
+        _ => Err(missing(path)),
+    }
+}
+
+/// Find the value that reference tokens point to
+fn resolve<'a>(document: &'a mut Value, tokens: &[String]) -> Option<&'a mut Value> {
+    tokens.iter().try_fold(document, |value, token| match value {
+        Value::Object(object) => object.get_mut(token),
+        Value::Array(items) => items.get_mut(array_index(token)?),
+        _ => None,
+    })
+}
+
+/// Read an array index token, which has no sign or leading zeros
+fn array_index(token: &str) -> Option<usize> {
+    if token.is_empty() || !token.bytes().all(|b| b.is_ascii_digit()) || (token.len() > 1 && token.starts_with('0')) {
+        return None;
+    }
+    token.parse().ok()
+}
+
+/// Error for a pointer that names no value
+fn missing(path: &str) -> HiveError {
+    HiveError::QueryError(format!("No value at '{}'", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    
+    #[test]
+    fn test_apply_patch() {
+        let mut document = json!({
+            "name": "Ahmed",
+            "address": { "city": "Cairo" },
+            "tags": ["a", "b"],
+            "a/b": 1,
+        });
+        
+        let patch = parse_patch(&json!([
+            { "op": "test", "path": "/address/city", "value": "Cairo" },
+            { "op": "replace", "path": "/address/city", "value": "Giza" },
+            { "op": "add", "path": "/tags/1", "value": "x" },
+            { "op": "add", "path": "/tags/-", "value": "z" },
+            { "op": "remove", "path": "/a~1b" },
+            { "op": "copy", "from": "/name", "path": "/address/owner" },
+            { "op": "move", "from": "/name", "path": "/full_name" },
+        ])).unwrap();
+        apply_patch(&mut document, &patch).unwrap();
+        
+        assert_eq!(document, json!({
+            "full_name": "Ahmed",
+            "address": { "city": "Giza", "owner": "Ahmed" },
+            "tags": ["a", "x", "b", "z"],
+        }));
+        
+        // A failing operation leaves the document as it was
+        let failing = parse_patch(&json!([
+            { "op": "remove", "path": "/full_name" },
+            { "op": "test", "path": "/tags/0", "value": "b" },
+        ])).unwrap();
+        assert!(apply_patch(&mut document, &failing).is_err());
+        assert_eq!(document["full_name"], "Ahmed");
+        
+        let invalid = |operation: Value| {
+            let patch = parse_patch(&json!([operation]))?;
+            apply_patch(&mut document.clone(), &patch)
+        };
+        assert!(invalid(json!({ "op": "add", "path": "/missing/x", "value": 1 })).is_err());
+        assert!(invalid(json!({ "op": "add", "path": "/tags/01", "value": 1 })).is_err());
+        assert!(invalid(json!({ "op": "replace", "path": "/_id", "value": "x" })).is_err());
+        assert!(invalid(json!({ "op": "move", "from": "/address", "path": "/address/inner" })).is_err());
+        assert!(invalid(json!({ "op": "bogus", "path": "/tags" })).is_err());
+        assert!(invalid(json!({ "op": "add", "path": "tags", "value": 1 })).is_err());
+    }
+}
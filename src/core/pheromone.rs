@@ -0,0 +1,193 @@
+// HiveDB Pheromone Module
+//
+// This module classifies cells as hot, warm or cold by how often and how
+// lately they were read. Every read deposits a unit of pheromone on the
+// cell's trail, and the trail evaporates with a half-life, so a cell read
+// a hundred times last month ends up as cold as one never read, while one
+// read steadily stays hot. The document cache only admits cells that are
+// not cold, so a one-off scan does not flush it, and the tiering policy
+// never moves hot cells to the cold tier. The evaporation model is
+// process-wide, like the cold tier.
+
+use std::sync::{Mutex, OnceLock, RwLock};
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use crate::core::error::HiveError;
+
+/// Default time for a trail to lose half its pheromone
+pub const DEFAULT_HALF_LIFE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Default lowest level of a hot cell
+pub const DEFAULT_HOT_LEVEL: f64 = 8.0;
+
+/// Default level under which a cell is cold
+pub const DEFAULT_COLD_LEVEL: f64 = 0.5;
+
+/// The evaporation model of this process
+static EVAPORATION: OnceLock<RwLock<Evaporation>> = OnceLock::new();
+
+/// How often and how lately a cell was read
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Temperature {
+    /// Read often and lately
+    Hot,
+    
+    /// Read now and then
+    Warm,
+    
+    /// Read rarely, or not for a long time
+    Cold,
+}
+
+/// How fast trails evaporate, and the levels separating the temperatures
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Evaporation {
+    /// Time for a trail to lose half its pheromone
+    pub half_life: Duration,
+    
+    /// Lowest level of a hot cell
+    pub hot_level: f64,
+    
+    /// Level under which a cell is cold
+    pub cold_level: f64,
+}
+
+/// The pheromone a cell's reads left, as of the last read
+#[derive(Debug, Default)]
+pub struct Trail {
+    /// Level at the last read, and the time of that read
+    deposit: Mutex<(f64, u64)>,
+}
+
+/// Number of cells at each temperature
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TemperatureCounts {
+    /// Cells read often and lately
+    pub hot: usize,
+    
+    /// Cells read now and then
+    pub warm: usize,
+    
+    /// Cells read rarely, or not for a long time
+    pub cold: usize,
+}
+
+impl Evaporation {
+    /// Get what is left of a level some seconds later
+    pub fn evaporate(&self, level: f64, elapsed: u64) -> f64 {
+        let half_life = self.half_life.as_secs_f64();
+        if half_life <= 0.0 {
+            return 0.0;
+        }
+        level * 0.5f64.powf(elapsed as f64 / half_life)
+    }
+    
+    /// Classify a level
+    pub fn classify(&self, level: f64) -> Temperature {
+        if level >= self.hot_level {
+            Temperature::Hot
+        } else if level < self.cold_level {
+            Temperature::Cold
+        } else {
+            Temperature::Warm
+        }
+    }
+}
+
+impl Default for Evaporation {
+    fn default() -> Self {
+        Self {
+            half_life: DEFAULT_HALF_LIFE,
+            hot_level: DEFAULT_HOT_LEVEL,
+            cold_level: DEFAULT_COLD_LEVEL,
+        }
+    }
+}
+
+impl Trail {
+    /// Deposit the pheromone of a read at a time
+    pub fn deposit(&self, model: &Evaporation, now: u64) {
+        if let Ok(mut deposit) = self.deposit.lock() {
+            let (level, at) = *deposit;
+            *deposit = (model.evaporate(level, now.saturating_sub(at)) + 1.0, now);
+        }
+    }
+    
+    /// Get the level of this trail at a time
+    pub fn level(&self, model: &Evaporation, now: u64) -> f64 {
+        self.deposit.lock()
+            .map(|deposit| model.evaporate(deposit.0, now.saturating_sub(deposit.1)))
+            .unwrap_or(0.0)
+    }
+}
+
+impl Clone for Trail {
+    fn clone(&self) -> Self {
+        let deposit = self.deposit.lock().map(|deposit| *deposit).unwrap_or_default();
+        Self {
+            deposit: Mutex::new(deposit),
+        }
+    }
+}
+
+impl TemperatureCounts {
+    /// Count a cell at a temperature
+    pub fn add(&mut self, temperature: Temperature) {
+        match temperature {
+            Temperature::Hot => self.hot += 1,
+            Temperature::Warm => self.warm += 1,
+            Temperature::Cold => self.cold += 1,
+        }
+    }
+}
+
+/// Make a model the evaporation model of this process
+pub fn set_evaporation(model: Evaporation) -> Result<(), HiveError> {
+    *slot().write().map_err(|_| HiveError::LockError)? = model;
+    Ok(())
+}
+
+/// Get the evaporation model of this process
+pub fn evaporation() -> Evaporation {
+    slot().read().map(|model| *model).unwrap_or_default()
+}
+
+/// Get the slot holding the evaporation model
+fn slot() -> &'static RwLock<Evaporation> {
+    EVAPORATION.get_or_init(|| RwLock::new(Evaporation::default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_trail_evaporation() {
+        let model = Evaporation { half_life: Duration::from_secs(100), hot_level: 4.0, cold_level: 0.5 };
+        let trail = Trail::default();
+        assert_eq!(model.classify(trail.level(&model, 1_000)), Temperature::Cold);
+        
+        // Reads in quick succession pile up
+        for _ in 0..4 {
+            trail.deposit(&model, 1_000);
+        }
+        assert_eq!(trail.level(&model, 1_000), 4.0);
+        assert_eq!(model.classify(trail.level(&model, 1_000)), Temperature::Hot);
+        
+        // Each half-life halves what is left
+        assert_eq!(trail.level(&model, 1_100), 2.0);
+        assert_eq!(model.classify(trail.level(&model, 1_100)), Temperature::Warm);
+        assert_eq!(model.classify(trail.level(&model, 1_400)), Temperature::Cold);
+        
+        // A read deposits on top of what is left
+        trail.deposit(&model, 1_200);
+        assert_eq!(trail.level(&model, 1_200), 2.0);
+        assert_eq!(trail.clone().level(&model, 1_300), 1.0);
+        
+        let mut counts = TemperatureCounts::default();
+        counts.add(Temperature::Hot);
+        counts.add(Temperature::Cold);
+        counts.add(Temperature::Cold);
+        assert_eq!(counts, TemperatureCounts { hot: 1, warm: 0, cold: 2 });
+    }
+}
@@ -1,12 +1,54 @@
 // HiveDB Query Module
 //
 // This module defines the query system for HiveDB, which allows
-// for data retrieval and manipulation.
+// for data retrieval and manipulation. Fields may be named by paths into
+// nested documents, such as `address.city` or `items[0].sku`, or by JSON
+// Pointers such as `/address/city`. Updates either set fields or apply a
+// JSON Patch.
 
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, BinaryHeap, HashMap, HashSet};
+use std::fmt;
+use std::io::Write;
+use std::sync::Arc;
+use std::time::Instant;
 use crate::core::error::HiveError;
 use crate::core::cell::{Cell, CellDataType};
+use crate::core::analyze::CollectionStats;
+use crate::core::cache;
+use crate::core::pheromone::Temperature;
+use crate::core::hive::{Hive, HiveManager};
+use crate::core::index::{compare_key_values, IndexExpression, IndexKey, IndexScan};
+use crate::core::memory::{self, MemoryCategory, Reservation};
+use crate::core::patch;
+use crate::core::session::CancelToken;
+use crate::core::text::TextSearch;
+use crate::utils::determinism;
+use crate::utils::format::{DocumentWriter, OutputFormat};
+use crate::utils::telemetry;
+use log::debug;
+
+/// Target that addresses every JSON document in a hive
+pub const ALL_DOCUMENTS: &str = "*";
+
+/// Field holding the cell ID in query results
+pub const ID_FIELD: &str = "_id";
+
+/// Field holding the relevance of a document to the text searches of a query
+pub const SCORE_FIELD: &str = "_score";
+
+/// Field holding the highlighted snippets of a document, by searched field
+pub const HIGHLIGHT_FIELD: &str = "_highlight";
+
+/// Field naming an array element that is not an object in an element filter
+pub const ELEMENT_FIELD: &str = "_element";
+
+/// Most top-level fields a filter may read to be checked before documents are decompressed whole
+const MAX_PREFILTER_FIELDS: usize = 4;
 
 /// Represents a query in the HiveDB system
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -37,6 +79,18 @@ pub struct Query {
     
     /// Additional options
     pub options: HashMap<String, String>,
+    
+    /// Whether to highlight the terms matched by text searches
+    #[serde(default)]
+    pub highlight: bool,
+    
+    /// Fields whose distinct combinations of values are returned instead of documents
+    #[serde(default)]
+    pub distinct: Option<Vec<String>>,
+    
+    /// Continuation token of a previous page, whose results this query starts after
+    #[serde(default)]
+    pub cursor: Option<String>,
 }
 
 /// Types of queries
@@ -87,6 +141,31 @@ pub enum FilterExpression {
     
     /// Geospatial query
     Geo(GeoFilter),
+    
+    /// Full-text search
+    Search(TextSearch),
+    
+    /// Condition on an array-valued field
+    Array(ArrayFilter),
+    
+    /// Check if a field is among the values another query returns
+    InQuery(String, Box<Subquery>),
+}
+
+/// A query whose results supply the values of an `InQuery` filter
+///
+/// The subquery runs once, before the query that holds it, and the
+/// values of `field` in its results become an ordinary `In` list.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Subquery {
+    /// Hive to run the query in, by name or ID (the same hive if None)
+    pub hive: Option<String>,
+    
+    /// Field of the results that supplies the values
+    pub field: String,
+    
+    /// The query, which must be a Find
+    pub query: Query,
 }
 
 /// Comparison operators for filter expressions
@@ -129,14 +208,41 @@ pub enum GeoFilter {
     },
 }
 
-/// Sorting criteria
+/// Array filter
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ArrayFilter {
+    /// Some element satisfies a filter
+    ElemMatch {
+        field: String,
+        filter: Box<FilterExpression>,
+    },
+    
+    /// The array holds every one of the values
+    ContainsAll {
+        field: String,
+        values: Vec<serde_json::Value>,
+    },
+    
+    /// The array has exactly this many elements
+    Size {
+        field: String,
+        size: usize,
+    },
+}
+
+/// Sorting criteria
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SortCriteria {
     /// Field to sort by
     pub field: String,
     
     /// Sort direction
     pub direction: SortDirection,
+    
+    /// Where documents without a value go; by default nulls sort after
+    /// every value, so last ascending and first descending
+    #[serde(default)]
+    pub nulls: Option<NullsOrder>,
 }
 
 /// Sort directions
@@ -149,6 +255,54 @@ pub enum SortDirection {
     Descending,
 }
 
+/// Placement of null and missing values in a sort
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum NullsOrder {
+    /// Before every value
+    First,
+    
+    /// After every value
+    Last,
+}
+
+/// How a query reads its documents
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum AccessPath {
+    /// Every cell is read
+    FullScan,
+    
+    /// An index selects the cells to read
+    IndexScan,
+    
+    /// An index provides the documents without reading cells
+    IndexOnlyScan,
+}
+
+/// Plan of a query, as shown by EXPLAIN
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryPlan {
+    /// Type of the query
+    pub query_type: QueryType,
+    
+    /// Collection the query reads
+    pub target: String,
+    
+    /// How documents are read
+    pub access: AccessPath,
+    
+    /// Index read, if any
+    pub index: Option<String>,
+    
+    /// Condition the index answers, if any
+    pub index_condition: Option<String>,
+    
+    /// Filter applied to each document read, in evaluation order
+    pub filter: Option<String>,
+    
+    /// Estimated number of matching documents (None without statistics)
+    pub estimated_rows: Option<f64>,
+}
+
 /// Result of a query
 #[derive(Debug, Clone)]
 pub struct QueryResult {
@@ -164,6 +318,9 @@ pub struct QueryResult {
     /// Whether there are more results
     pub has_more: bool,
     
+    /// Continuation token for the next page, when there are more results
+    pub next_cursor: Option<String>,
+    
     /// Execution time in milliseconds
     pub execution_time_ms: u64,
 }
@@ -181,6 +338,9 @@ impl Query {
             skip: None,
             data: None,
             options: HashMap::new(),
+            highlight: false,
+            distinct: None,
+            cursor: None,
         }
     }
     
@@ -214,173 +374,2178 @@ impl Query {
         self
     }
     
+    /// Start after the results of a previous page, given its `next_cursor`
+    ///
+    /// Unlike a skip, the documents of earlier pages are passed over while
+    /// scanning rather than sorted and dropped. The query must keep the
+    /// filter and sort of the one that returned the cursor.
+    pub fn with_cursor(mut self, cursor: String) -> Self {
+        self.cursor = Some(cursor);
+        self
+    }
+    
     /// Add data to this query
     pub fn with_data(mut self, data: serde_json::Value) -> Self {
         self.data = Some(data);
         self
     }
     
+    /// Return snippets of the searched fields with the matched terms highlighted
+    pub fn with_highlight(mut self) -> Self {
+        self.highlight = true;
+        self
+    }
+    
+    /// Return the distinct combinations of values of some fields instead of documents
+    pub fn with_distinct(mut self, fields: Vec<String>) -> Self {
+        self.distinct = Some(fields);
+        self
+    }
+    
+    /// Check whether the filter holds subqueries, which run before the query
+    pub fn has_subqueries(&self) -> bool {
+        self.filter.as_ref().is_some_and(has_subqueries)
+    }
+    
     /// Add an option to this query
     pub fn with_option(mut self, key: String, value: String) -> Self {
         self.options.insert(key, value);
         self
     }
     
-    /// Execute this query
-    pub fn execute(&self) -> Result<QueryResult, HiveError> {
-        // TODO: Implement query execution
-        
-        Err(HiveError::NotImplemented)
+    /// Execute this query against a hive
+    pub fn execute(&self, hive: &mut Hive) -> Result<QueryResult, HiveError> {
+        QueryExecutor::execute(self, hive)
+    }
+    
+    /// Execute this Find query, writing the results to a sink in a format
+    ///
+    /// Returns the number of documents written. See
+    /// `QueryExecutor::execute_into` for which queries are streamed.
+    pub fn execute_into<W: Write>(&self, hive: &Hive, sink: W, format: OutputFormat) -> Result<usize, HiveError> {
+        QueryExecutor::execute_into(self, hive, sink, format)
     }
 }
 
 /// Hive Query Language (HQL) parser
+///
+/// HQL is the SQL dialect described in `core::sql`.
 pub struct HqlParser;
 
 impl HqlParser {
     /// Parse an HQL query string into a Query object
     pub fn parse(hql: &str) -> Result<Query, HiveError> {
-        // TODO: Implement HQL parsing
-        
-        Err(HiveError::NotImplemented)
+        crate::core::sql::parse(hql)
     }
 }
 
 /// Query executor
+///
+/// Documents are the JSON cells of a hive. A query's target names a
+/// collection, which is the set of cells carrying that tag; the target
+/// `*` addresses every JSON cell.
 pub struct QueryExecutor;
 
-impl QueryExecutor {
-    /// Execute a query
-    pub fn execute(query: &Query) -> Result<QueryResult, HiveError> {
-        // TODO: Implement query execution
-        
-        Err(HiveError::NotImplemented)
-    }
-}
-
-/// Create a simple equality filter
-pub fn eq(field: &str, value: serde_json::Value) -> FilterExpression {
-    FilterExpression::Comparison(ComparisonOperator::Eq, field.to_string(), value)
+/// A document loaded from a hive
+struct Document {
+    /// ID of the cell holding the document
+    id: String,
+    
+    /// Coordinates of the cell holding the document
+    coordinates: (i32, i32),
+    
+    /// Parsed document body
+    body: serde_json::Value,
 }
 
-/// Create a simple inequality filter
-pub fn ne(field: &str, value: serde_json::Value) -> FilterExpression {
-    FilterExpression::Comparison(ComparisonOperator::Ne, field.to_string(), value)
+/// A document ordered by sort criteria, then grid position
+struct Ranked<'a> {
+    /// The document
+    document: Document,
+    
+    /// Approximate size of the document in memory
+    size: usize,
+    
+    /// Criteria to order by
+    criteria: &'a [SortCriteria],
 }
 
-/// Create a greater than filter
-pub fn gt(field: &str, value: serde_json::Value) -> FilterExpression {
-    FilterExpression::Comparison(ComparisonOperator::Gt, field.to_string(), value)
+impl PartialEq for Ranked<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
 }
 
-/// Create a greater than or equal filter
-pub fn gte(field: &str, value: serde_json::Value) -> FilterExpression {
-    FilterExpression::Comparison(ComparisonOperator::Gte, field.to_string(), value)
-}
+impl Eq for Ranked<'_> {}
 
-/// Create a less than filter
-pub fn lt(field: &str, value: serde_json::Value) -> FilterExpression {
-    FilterExpression::Comparison(ComparisonOperator::Lt, field.to_string(), value)
+impl PartialOrd for Ranked<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
 }
 
-/// Create a less than or equal filter
-pub fn lte(field: &str, value: serde_json::Value) -> FilterExpression {
-    FilterExpression::Comparison(ComparisonOperator::Lte, field.to_string(), value)
+impl Ord for Ranked<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare_documents(&self.document.body, &other.document.body, self.criteria)
+            .then_with(|| self.document.position().cmp(&other.document.position()))
+    }
 }
 
-/// Create an AND filter
-pub fn and(expressions: Vec<FilterExpression>) -> FilterExpression {
-    FilterExpression::And(expressions)
+impl Document {
+    /// Get the grid position of the document, which orders documents that sort alike
+    fn position(&self) -> (i32, i32) {
+        (self.coordinates.1, self.coordinates.0)
+    }
 }
 
-/// Create an OR filter
-pub fn or(expressions: Vec<FilterExpression>) -> FilterExpression {
-    FilterExpression::Or(expressions)
+/// Where a page of results ended, which the next page starts after
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Cursor {
+    /// Sort criteria of the query
+    sort: Vec<SortCriteria>,
+    
+    /// Values of the sort fields in the last document of the page (null if missing)
+    keys: Vec<serde_json::Value>,
+    
+    /// Grid position of the last document of the page
+    position: (i32, i32),
 }
 
-/// Create a NOT filter
-pub fn not(expression: FilterExpression) -> FilterExpression {
-    FilterExpression::Not(Box::new(expression))
+impl Cursor {
+    /// Make the cursor after a document
+    fn after(document: &Document, criteria: &[SortCriteria]) -> Self {
+        Self {
+            sort: criteria.to_vec(),
+            keys: criteria.iter()
+                .map(|criterion| sort_value(&document.body, &criterion.field).cloned().unwrap_or(serde_json::Value::Null))
+                .collect(),
+            position: document.position(),
+        }
+    }
+    
+    /// Check whether a document comes after this cursor
+    fn precedes(&self, document: &Document) -> bool {
+        let ordering = self.sort.iter().zip(&self.keys)
+            .map(|(criterion, key)| {
+                let key = Some(key).filter(|key| !matches!(key, serde_json::Value::Null));
+                compare_sort_values(key, sort_value(&document.body, &criterion.field), criterion)
+            })
+            .find(|ordering| *ordering != Ordering::Equal)
+            .unwrap_or(Ordering::Equal);
+        ordering.then_with(|| self.position.cmp(&document.position())) == Ordering::Less
+    }
+    
+    /// Encode this cursor as an opaque token
+    fn encode(&self) -> Result<String, HiveError> {
+        let json = serde_json::to_vec(self).map_err(|e| HiveError::SerializationError(e.to_string()))?;
+        Ok(URL_SAFE_NO_PAD.encode(json))
+    }
+    
+    /// Decode a token, checking it was made for the same sort criteria
+    fn decode(token: &str, criteria: &[SortCriteria]) -> Result<Self, HiveError> {
+        let invalid = || HiveError::QueryError("Invalid cursor".to_string());
+        let json = URL_SAFE_NO_PAD.decode(token).map_err(|_| invalid())?;
+        let cursor: Self = serde_json::from_slice(&json).map_err(|_| invalid())?;
+        if cursor.sort != criteria || cursor.keys.len() != criteria.len() {
+            return Err(HiveError::QueryError("Cursor was made for a query with another sort".to_string()));
+        }
+        Ok(cursor)
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+impl QueryExecutor {
+    /// Execute a query, which may modify the hive
+    pub fn execute(query: &Query, hive: &mut Hive) -> Result<QueryResult, HiveError> {
+        Self::execute_cancellable(query, hive, &CancelToken::new())
+    }
     
-    #[test]
-    fn test_query_builder() {
-        let query = Query::new(QueryType::Find, "users".to_string())
-            .with_filter(eq("age", serde_json::json!(30)))
-            .with_projection(vec!["name".to_string(), "email".to_string()])
-            .with_sort(vec![
-                SortCriteria {
-                    field: "name".to_string(),
-                    direction: SortDirection::Ascending,
-                }
-            ])
-            .with_limit(10)
-            .with_skip(0);
+    /// Execute a read-only query (Find or Count)
+    pub fn execute_read(query: &Query, hive: &Hive) -> Result<QueryResult, HiveError> {
+        Self::execute_read_cancellable(query, hive, &CancelToken::new())
+    }
+    
+    /// Execute a query, stopping with `QueryCancelled` once the token is cancelled
+    ///
+    /// Subqueries may only read the same hive; see `materialize` for
+    /// subqueries on other hives.
+    pub fn execute_cancellable(query: &Query, hive: &mut Hive, cancel: &CancelToken) -> Result<QueryResult, HiveError> {
+        let materialized;
+        let query = if query.has_subqueries() {
+            materialized = Self::materialize(query, hive, None, cancel)?;
+            &materialized
+        } else {
+            query
+        };
         
-        assert_eq!(query.query_type, QueryType::Find);
-        assert_eq!(query.target, "users");
+        if matches!(query.query_type, QueryType::Find | QueryType::Count) {
+            return Self::execute_read_cancellable(query, hive, cancel);
+        }
         
-        if let Some(FilterExpression::Comparison(op, field, value)) = &query.filter {
-            assert_eq!(*op, ComparisonOperator::Eq);
-            assert_eq!(field, "age");
-            assert_eq!(value, &serde_json::json!(30));
+        let mut span = execute_span(query, hive);
+        span.record(match query.query_type {
+            QueryType::Insert => Self::insert(query, hive, cancel),
+            QueryType::Update => Self::update(query, hive, cancel),
+            QueryType::Delete => Self::delete(query, hive, cancel),
+            _ => Err(HiveError::NotImplemented),
+        })
+    }
+    
+    /// Execute a read-only query, stopping with `QueryCancelled` once the token is cancelled
+    pub fn execute_read_cancellable(query: &Query, hive: &Hive, cancel: &CancelToken) -> Result<QueryResult, HiveError> {
+        let materialized;
+        let query = if query.has_subqueries() {
+            materialized = Self::materialize(query, hive, None, cancel)?;
+            &materialized
         } else {
-            panic!("Expected Comparison filter");
+            query
+        };
+        
+        let mut span = execute_span(query, hive);
+        span.record(Self::read(query, hive, cancel))
+    }
+    
+    /// Execute a Find query, writing the results to a sink in a format
+    ///
+    /// Exports, which are queries without a sort, limit, skip, DISTINCT,
+    /// text search, subqueries or cursor, are streamed: each document is
+    /// written as it is read, in no particular order, so the results are
+    /// never held together. Other queries are run as usual and their
+    /// results written once complete. Returns the number of documents written.
+    pub fn execute_into<W: Write>(query: &Query, hive: &Hive, sink: W, format: OutputFormat) -> Result<usize, HiveError> {
+        if query.query_type != QueryType::Find {
+            return Err(HiveError::QueryError("Only Find queries can write their results to a sink".to_string()));
+        }
+        let mut writer = DocumentWriter::new(sink, format)?;
+        if let Some(fields) = &query.projection {
+            let mut columns = vec![ID_FIELD.to_string()];
+            columns.extend(fields.iter().filter(|field| *field != ID_FIELD).cloned());
+            writer = writer.with_columns(columns);
         }
         
-        assert_eq!(query.projection, Some(vec!["name".to_string(), "email".to_string()]));
-        assert_eq!(query.limit, Some(10));
-        assert_eq!(query.skip, Some(0));
+        let export = query.sort.is_none()
+            && query.limit.is_none()
+            && query.skip.is_none()
+            && query.distinct.is_none()
+            && query.cursor.is_none()
+            && !query.has_subqueries()
+            && query.filter.as_ref().is_none_or(|filter| text_searches(filter).is_empty());
+        if export {
+            let mut span = execute_span(query, hive);
+            span.record(Self::scan_documents(query, hive, &CancelToken::new(), |document, _| {
+                let mut result = with_id(document.body, &document.id);
+                if let Some(fields) = &query.projection {
+                    result = project(&result, fields);
+                }
+                writer.write(&result)
+            }))?;
+        } else {
+            for result in Self::execute_read(query, hive)?.results {
+                writer.write(&result)?;
+            }
+        }
+        
+        let count = writer.count();
+        writer.finish()?;
+        Ok(count)
     }
     
-    #[test]
-    fn test_complex_filter() {
-        let filter = and(vec![
-            eq("status", serde_json::json!("active")),
-            or(vec![
-                gt("age", serde_json::json!(18)),
-                eq("verified", serde_json::json!(true)),
-            ]),
-        ]);
+    /// Run a Find or Count query
+    fn read(query: &Query, hive: &Hive, cancel: &CancelToken) -> Result<QueryResult, HiveError> {
+        let started = Instant::now();
         
-        if let FilterExpression::And(conditions) = &filter {
-            assert_eq!(conditions.len(), 2);
-            
-            if let FilterExpression::Comparison(op, field, value) = &conditions[0] {
-                assert_eq!(*op, ComparisonOperator::Eq);
-                assert_eq!(field, "status");
-                assert_eq!(value, &serde_json::json!("active"));
-            } else {
-                panic!("Expected Comparison filter");
+        let mut memory = memory::global().admit()?;
+        
+        if query.query_type == QueryType::Count {
+            let mut count = 0;
+            Self::scan_documents(query, hive, cancel, |_, _| {
+                count += 1;
+                Ok(())
+            })?;
+            return Ok(QueryResult {
+                query_type: QueryType::Count,
+                results: vec![serde_json::json!({ "count": count })],
+                count,
+                has_more: false,
+                next_cursor: None,
+                execution_time_ms: started.elapsed().as_millis() as u64,
+            });
+        }
+        
+        if query.query_type != QueryType::Find {
+            return Err(HiveError::QueryError(format!(
+                "{:?} queries modify the hive and cannot run read-only",
+                query.query_type
+            )));
+        }
+        
+        let skip = query.skip.unwrap_or(0);
+        let limit = query.limit.unwrap_or(usize::MAX);
+        let criteria = query.sort.as_deref().unwrap_or_default();
+        let searches = query.filter.as_ref().map(text_searches).unwrap_or_default();
+        
+        // Pages can only continue in a deterministic order, which ranked text searches lack
+        let paged = query.distinct.is_none() && (query.sort.is_some() || searches.is_empty());
+        let cursor = match &query.cursor {
+            Some(_) if !paged => {
+                return Err(HiveError::QueryError(
+                    "Cursors need a sorted query without DISTINCT; ranked text searches must be sorted".to_string()
+                ));
+            }
+            Some(token) => Some(Cursor::decode(token, criteria)?),
+            None => None,
+        };
+        
+        if let Some(fields) = &query.distinct {
+            let rows = Self::distinct_rows(query, hive, cancel, &mut memory, fields)?;
+            let total = rows.len();
+            let results: Vec<serde_json::Value> = rows.into_iter().skip(skip).take(limit).collect();
+            return Ok(QueryResult {
+                query_type: QueryType::Find,
+                count: results.len(),
+                has_more: skip + results.len() < total,
+                next_cursor: None,
+                results,
+                execution_time_ms: started.elapsed().as_millis() as u64,
+            });
+        }
+        
+        // A sorted query with a limit only keeps the documents it may return,
+        // and one continuing from a cursor only those after it
+        let (mut documents, total) = match (&query.sort, query.limit) {
+            _ if cursor.is_some() => {
+                Self::top_documents(query, hive, cancel, &mut memory, criteria, cursor.as_ref(), skip.saturating_add(limit))?
+            }
+            (Some(criteria), Some(limit)) if searches.is_empty() => {
+                Self::top_documents(query, hive, cancel, &mut memory, criteria, None, skip.saturating_add(limit))?
+            }
+            _ => {
+                let documents = Self::matching_documents(query, hive, cancel, &mut memory)?;
+                let total = documents.len();
+                (documents, total)
+            }
+        };
+        
+        // Text searches rank the documents unless the query sorts them
+        let scores: HashMap<String, f64> = documents.iter()
+            .filter(|_| !searches.is_empty())
+            .map(|document| (document.id.clone(), Self::score(&searches, hive, document)))
+            .collect();
+        if let Some(criteria) = &query.sort {
+            documents.sort_by(|a, b| compare_documents(&a.body, &b.body, criteria));
+        } else if !searches.is_empty() {
+            documents.sort_by(|a, b| scores[&b.id].total_cmp(&scores[&a.id]));
+        }
+        
+        let selected = |field: &str| query.projection.as_ref().is_none_or(|fields| fields.iter().any(|f| f == field));
+        let highlight = !searches.is_empty()
+            && (query.highlight || query.projection.as_ref().is_some_and(|fields| fields.iter().any(|f| f == HIGHLIGHT_FIELD)));
+        
+        let mut results = Vec::new();
+        let mut last = None;
+        for document in documents.into_iter().skip(skip).take(limit) {
+            if paged {
+                last = Some(Cursor::after(&document, criteria));
             }
+            let highlights: serde_json::Map<String, serde_json::Value> = searches.iter()
+                .filter(|_| highlight)
+                .filter_map(|search| Some((search.field.clone(), search.highlight(&document.body)?.into())))
+                .collect();
             
-            if let FilterExpression::Or(or_conditions) = &conditions[1] {
-                assert_eq!(or_conditions.len(), 2);
-                
-                if let FilterExpression::Comparison(op, field, value) = &or_conditions[0] {
-                    assert_eq!(*op, ComparisonOperator::Gt);
-                    assert_eq!(field, "age");
-                    assert_eq!(value, &serde_json::json!(18));
-                } else {
-                    panic!("Expected Comparison filter");
+            let mut result = with_id(document.body, &document.id);
+            if let Some(fields) = &query.projection {
+                result = project(&result, fields);
+            }
+            if let Some(object) = result.as_object_mut() {
+                if !searches.is_empty() && selected(SCORE_FIELD) {
+                    object.insert(SCORE_FIELD.to_string(), scores[&document.id].into());
                 }
-                
-                if let FilterExpression::Comparison(op, field, value) = &or_conditions[1] {
-                    assert_eq!(*op, ComparisonOperator::Eq);
-                    assert_eq!(field, "verified");
-                    assert_eq!(value, &serde_json::json!(true));
-                } else {
-                    panic!("Expected Comparison filter");
+                if highlight {
+                    object.insert(HIGHLIGHT_FIELD.to_string(), serde_json::Value::Object(highlights));
                 }
-            } else {
-                panic!("Expected Or filter");
             }
-        } else {
-            panic!("Expected And filter");
+            results.push(result);
+        }
+        
+        let count = results.len();
+        let has_more = skip + count < total;
+        Ok(QueryResult {
+            query_type: QueryType::Find,
+            results,
+            count,
+            has_more,
+            next_cursor: last.filter(|_| has_more).map(|cursor| cursor.encode()).transpose()?,
+            execution_time_ms: started.elapsed().as_millis() as u64,
+        })
+    }
+    
+    /// Insert the document (or array of documents) in the query data
+    fn insert(query: &Query, hive: &mut Hive, cancel: &CancelToken) -> Result<QueryResult, HiveError> {
+        let started = Instant::now();
+        
+        let documents = match &query.data {
+            Some(serde_json::Value::Array(items)) => items.clone(),
+            Some(item) => vec![item.clone()],
+            None => return Err(HiveError::QueryError("Insert requires data".to_string())),
+        };
+        
+        let mut results = Vec::new();
+        for mut body in documents {
+            cancel.check()?;
+            
+            let object = body.as_object_mut()
+                .ok_or_else(|| HiveError::QueryError("Inserted documents must be JSON objects".to_string()))?;
+            
+            let id = match object.remove(ID_FIELD) {
+                Some(serde_json::Value::String(id)) => id,
+                Some(_) => return Err(HiveError::QueryError(format!("{} must be a string", ID_FIELD))),
+                None => generate_document_id(),
+            };
+            
+            if hive.find_cell_by_id(&id).is_some() {
+                return Err(HiveError::QueryError(format!("Document '{}' already exists", id)));
+            }
+            
+            let coordinates = hive.free_coordinates()
+                .ok_or_else(|| HiveError::QueryError("Hive is full".to_string()))?;
+            let content = serde_json::to_vec(&body)
+                .map_err(|e| HiveError::SerializationError(e.to_string()))?;
+            
+            let mut cell = Cell::new_with_integrity(id.clone(), coordinates, CellDataType::Json, content, true, hive.cells.integrity())?;
+            if query.target != ALL_DOCUMENTS {
+                cell.add_tag(query.target.clone());
+            }
+            hive.add_cell(cell)?;
+            
+            results.push(with_id(body, &id));
+        }
+        
+        let count = results.len();
+        Ok(QueryResult {
+            query_type: QueryType::Insert,
+            results,
+            count,
+            has_more: false,
+            next_cursor: None,
+            execution_time_ms: started.elapsed().as_millis() as u64,
+        })
+    }
+    
+    /// Update every matching document
+    ///
+    /// The query data is either an object of fields to set or a JSON Patch
+    /// (an array of operations). A patch that fails on any document
+    /// leaves every document unchanged.
+    fn update(query: &Query, hive: &mut Hive, cancel: &CancelToken) -> Result<QueryResult, HiveError> {
+        let started = Instant::now();
+        
+        let patch = match &query.data {
+            Some(patch @ serde_json::Value::Array(_)) => Some(patch::parse_patch(patch)?),
+            Some(serde_json::Value::Object(_)) => None,
+            _ => return Err(HiveError::QueryError("Update requires an object of fields to set or a JSON Patch".to_string())),
+        };
+        
+        let mut memory = memory::global().admit()?;
+        let mut documents = Self::matching_documents(query, hive, cancel, &mut memory)?;
+        let count = documents.len();
+        
+        for document in &mut documents {
+            match (&patch, query.data.as_ref().and_then(|data| data.as_object())) {
+                (Some(operations), _) => patch::apply_patch(&mut document.body, operations).map_err(|e| match e {
+                    HiveError::QueryError(message) => HiveError::QueryError(format!("Document '{}': {}", document.id, message)),
+                    other => other,
+                })?,
+                (None, Some(changes)) => if let Some(object) = document.body.as_object_mut() {
+                    for (field, value) in changes {
+                        object.insert(field.clone(), value.clone());
+                    }
+                },
+                (None, None) => {}
+            }
+        }
+        
+        for document in documents {
+            cancel.check()?;
+            
+            let content = serde_json::to_vec(&document.body)
+                .map_err(|e| HiveError::SerializationError(e.to_string()))?;
+            let compress = match hive.get_cell(document.coordinates) {
+                Some(cell) => cell.read().map_err(|_| HiveError::LockError)?.data.is_compressed,
+                None => continue,
+            };
+            hive.update_cell(document.coordinates, content, compress)?;
+        }
+        
+        Ok(QueryResult {
+            query_type: QueryType::Update,
+            results: Vec::new(),
+            count,
+            has_more: false,
+            next_cursor: None,
+            execution_time_ms: started.elapsed().as_millis() as u64,
+        })
+    }
+    
+    /// Remove every matching document
+    fn delete(query: &Query, hive: &mut Hive, cancel: &CancelToken) -> Result<QueryResult, HiveError> {
+        let started = Instant::now();
+        
+        let mut memory = memory::global().admit()?;
+        let documents = Self::matching_documents(query, hive, cancel, &mut memory)?;
+        let count = documents.len();
+        
+        for document in documents {
+            cancel.check()?;
+            hive.remove_cell(document.coordinates)?;
         }
+        
+        Ok(QueryResult {
+            query_type: QueryType::Delete,
+            results: Vec::new(),
+            count,
+            has_more: false,
+            next_cursor: None,
+            execution_time_ms: started.elapsed().as_millis() as u64,
+        })
+    }
+    
+    /// Load the documents in the query target that pass its filter
+    ///
+    /// Documents are returned in grid order (row by row), and the memory
+    /// they hold is charged to the query's reservation.
+    fn matching_documents(
+        query: &Query,
+        hive: &Hive,
+        cancel: &CancelToken,
+        memory: &mut Reservation,
+    ) -> Result<Vec<Document>, HiveError> {
+        let mut documents = Vec::new();
+        Self::scan_documents(query, hive, cancel, |document, size| {
+            memory.grow(size)?;
+            documents.push(document);
+            Ok(())
+        })?;
+        
+        documents.sort_by_key(Document::position);
+        Ok(documents)
+    }
+    
+    /// Pass each document in the query target that passes its filter to `visit`, in no particular order
+    ///
+    /// `visit` also receives the approximate size of the document in memory.
+    fn scan_documents(
+        query: &Query,
+        hive: &Hive,
+        cancel: &CancelToken,
+        mut visit: impl FnMut(Document, usize) -> Result<(), HiveError>,
+    ) -> Result<(), HiveError> {
+        let stats = hive.statistics.collection(&query.target);
+        let filter = query.filter.as_ref().map(|filter| match stats {
+            Some(stats) => plan_filter(filter, stats),
+            None => filter.clone(),
+        });
+        let prefilter = filter.as_ref().and_then(prefilter_fields);
+        
+        // Read only the cells an index selects, when one applies
+        let scan = Self::choose_scan(query, hive);
+        let cells = match &scan {
+            Some(scan) => {
+                debug!("Reading '{}' through index '{}'", query.target, scan.index.definition().name);
+                scan.cell_ids().iter().filter_map(|id| hive.find_cell_by_id(id)).collect()
+            }
+            None => hive.cells.all_cells(),
+        };
+        
+        for cell_arc in cells {
+            cancel.check()?;
+            
+            let cell = cell_arc.read().map_err(|_| HiveError::LockError)?;
+            
+            if cell.data.data_type != CellDataType::Json {
+                continue;
+            }
+            if query.target != ALL_DOCUMENTS && !cell.metadata.tags.contains(&query.target) {
+                continue;
+            }
+            
+            // An index storing every field the query reads saves loading the content
+            let (body, size) = match scan.as_ref().filter(|scan| scan.index_only) {
+                Some(scan) => match scan.index.document(&cell.id) {
+                    Some(body) => {
+                        let size = body.to_string().len() * 2;
+                        (Arc::new(body), size)
+                    }
+                    None => continue,
+                },
+                None => {
+                    // Large documents can be ruled out on the few fields the filter reads
+                    if let (Some(filter), Some(fields)) = (&filter, &prefilter) {
+                        if !Self::may_match(&cell, filter, fields)? {
+                            cell.record_read();
+                            continue;
+                        }
+                    }
+                    Self::load_document(&cell)?
+                }
+            };
+            
+            if let Some(filter) = &filter {
+                if !filter.evaluate(&with_id((*body).clone(), &cell.id)) {
+                    continue;
+                }
+            }
+            
+            visit(Document {
+                id: cell.id.clone(),
+                coordinates: cell.coordinates,
+                body: (*body).clone(),
+            }, size)?;
+        }
+        
+        Ok(())
+    }
+    
+    /// Find the first `k` matching documents in sort order, holding no more than `k` at a time
+    ///
+    /// Only documents after the cursor, if any, are considered. Returns
+    /// them in sort order, with the number of matching documents.
+    fn top_documents(
+        query: &Query,
+        hive: &Hive,
+        cancel: &CancelToken,
+        memory: &mut Reservation,
+        criteria: &[SortCriteria],
+        cursor: Option<&Cursor>,
+        k: usize,
+    ) -> Result<(Vec<Document>, usize), HiveError> {
+        let mut heap: BinaryHeap<Ranked> = BinaryHeap::new();
+        let mut total = 0;
+        
+        Self::scan_documents(query, hive, cancel, |document, size| {
+            if cursor.is_some_and(|cursor| !cursor.precedes(&document)) {
+                return Ok(());
+            }
+            total += 1;
+            let ranked = Ranked { document, size, criteria };
+            if heap.len() < k {
+                memory.grow(size)?;
+                heap.push(ranked);
+            } else if heap.peek().is_some_and(|last| ranked < *last) {
+                memory.grow(size)?;
+                if let Some(evicted) = heap.pop() {
+                    memory.shrink(evicted.size);
+                }
+                heap.push(ranked);
+            }
+            Ok(())
+        })?;
+        
+        let documents = heap.into_sorted_vec().into_iter().map(|ranked| ranked.document).collect();
+        Ok((documents, total))
+    }
+    
+    /// Find the distinct combinations of values of some fields among the matching documents
+    ///
+    /// Rows are in value order unless the query sorts them; a missing
+    /// field counts as null.
+    fn distinct_rows(
+        query: &Query,
+        hive: &Hive,
+        cancel: &CancelToken,
+        memory: &mut Reservation,
+        fields: &[String],
+    ) -> Result<Vec<serde_json::Value>, HiveError> {
+        let mut rows = BTreeSet::new();
+        Self::scan_documents(query, hive, cancel, |document, _| {
+            let body = with_id(document.body, &document.id);
+            let row = IndexKey(fields.iter()
+                .map(|field| field_value(&body, field).cloned().unwrap_or(serde_json::Value::Null))
+                .collect());
+            if !rows.contains(&row) {
+                memory.grow(row.0.iter().map(|value| value.to_string().len() * 2).sum())?;
+                rows.insert(row);
+            }
+            Ok(())
+        })?;
+        
+        let mut rows: Vec<serde_json::Value> = rows.into_iter()
+            .map(|row| fields.iter().cloned().zip(row.0).collect::<serde_json::Map<_, _>>().into())
+            .collect();
+        if let Some(criteria) = &query.sort {
+            rows.sort_by(|a, b| compare_documents(a, b, criteria));
+        }
+        Ok(rows)
+    }
+    
+    /// Run the subqueries in a query's filter, replacing each with the values it returns
+    ///
+    /// Subqueries on other hives are looked up in the manager, and fail
+    /// without one.
+    pub fn materialize(
+        query: &Query,
+        hive: &Hive,
+        manager: Option<&HiveManager>,
+        cancel: &CancelToken,
+    ) -> Result<Query, HiveError> {
+        let mut query = query.clone();
+        if let Some(filter) = &mut query.filter {
+            Self::materialize_filter(filter, hive, manager, cancel)?;
+        }
+        Ok(query)
+    }
+    
+    /// Replace the subqueries in a filter with their values
+    fn materialize_filter(
+        filter: &mut FilterExpression,
+        hive: &Hive,
+        manager: Option<&HiveManager>,
+        cancel: &CancelToken,
+    ) -> Result<(), HiveError> {
+        match filter {
+            FilterExpression::InQuery(field, subquery) => {
+                let values = Self::subquery_values(subquery, hive, manager, cancel)?;
+                *filter = FilterExpression::In(field.clone(), values);
+            }
+            FilterExpression::And(filters) | FilterExpression::Or(filters) => {
+                for filter in filters {
+                    Self::materialize_filter(filter, hive, manager, cancel)?;
+                }
+            }
+            FilterExpression::Not(filter) | FilterExpression::Array(ArrayFilter::ElemMatch { filter, .. }) => {
+                Self::materialize_filter(filter, hive, manager, cancel)?;
+            }
+            _ => {}
+        }
+        
+        Ok(())
+    }
+    
+    /// Run a subquery and collect the distinct values of its field
+    fn subquery_values(
+        subquery: &Subquery,
+        hive: &Hive,
+        manager: Option<&HiveManager>,
+        cancel: &CancelToken,
+    ) -> Result<Vec<serde_json::Value>, HiveError> {
+        if subquery.query.query_type != QueryType::Find {
+            return Err(HiveError::QueryError("Subqueries must be Find queries".to_string()));
+        }
+        
+        let result = match subquery.hive.as_deref() {
+            Some(name) if name != hive.name && name != hive.id => {
+                let manager = manager.ok_or_else(|| {
+                    HiveError::QueryError(format!("Subquery on hive '{}' cannot run here", name))
+                })?;
+                let other = manager.get_hive_by_name(name)
+                    .or_else(|| manager.get_hive(name))
+                    .ok_or(HiveError::HiveNotFound)?;
+                let other = other.read().map_err(|_| HiveError::LockError)?;
+                let query = Self::materialize(&subquery.query, &other, Some(manager), cancel)?;
+                Self::read(&query, &other, cancel)?
+            }
+            _ => {
+                let query = Self::materialize(&subquery.query, hive, manager, cancel)?;
+                Self::read(&query, hive, cancel)?
+            }
+        };
+        
+        let mut seen = HashSet::new();
+        Ok(result.results.iter()
+            .filter_map(|document| field_value(document, &subquery.field))
+            .filter(|value| seen.insert(value.to_string()))
+            .cloned()
+            .collect())
+    }
+    
+    /// Describe how a query would read its documents, without running it
+    pub fn explain(query: &Query, hive: &Hive) -> QueryPlan {
+        let stats = hive.statistics.collection(&query.target);
+        let filter = query.filter.as_ref().map(|filter| match stats {
+            Some(stats) => plan_filter(filter, stats),
+            None => filter.clone(),
+        });
+        let scan = Self::choose_scan(query, hive);
+        
+        let estimated_rows = stats.map(|stats| match &filter {
+            Some(filter) => stats.estimate_rows(filter),
+            None => stats.documents as f64,
+        });
+        
+        QueryPlan {
+            query_type: query.query_type.clone(),
+            target: query.target.clone(),
+            access: match &scan {
+                Some(scan) if scan.index_only => AccessPath::IndexOnlyScan,
+                Some(_) => AccessPath::IndexScan,
+                None => AccessPath::FullScan,
+            },
+            index: scan.as_ref().map(|scan| scan.index.definition().name.clone()),
+            index_condition: scan.as_ref().map(|scan| scan.condition.to_string()),
+            filter: filter.map(|filter| filter.to_string()),
+            estimated_rows,
+        }
+    }
+    
+    /// Choose the index, if any, that a query reads its documents through
+    fn choose_scan<'a>(query: &'a Query, hive: &'a Hive) -> Option<IndexScan<'a>> {
+        let filter = query.filter.as_ref()?;
+        let stats = hive.statistics.collection(&query.target);
+        
+        hive.indexes.plan(filter, stats, Self::read_columns(query).as_ref())
+    }
+    
+    /// Get every field a query reads, if it can be answered from those alone
+    ///
+    /// Only finds with a projection and counts qualify: other queries need
+    /// whole documents.
+    fn read_columns(query: &Query) -> Option<BTreeSet<String>> {
+        let mut columns = BTreeSet::new();
+        
+        match query.query_type {
+            QueryType::Find => {
+                columns.extend(query.projection.as_ref()?.iter().cloned());
+                for criterion in query.sort.iter().flatten() {
+                    columns.insert(criterion.field.clone());
+                }
+            }
+            QueryType::Count => {}
+            _ => return None,
+        }
+        
+        if let Some(filter) = &query.filter {
+            filter_fields(filter, &mut columns);
+        }
+        Some(columns)
+    }
+    
+    /// Score a document against the text searches of a query
+    ///
+    /// A full-text index on the searched field supplies the corpus
+    /// statistics BM25 needs; without one only term frequencies count.
+    fn score(searches: &[&TextSearch], hive: &Hive, document: &Document) -> f64 {
+        searches.iter()
+            .map(|search| match hive.indexes.text_index(&search.field) {
+                Some(index) => index.score(search, &document.id),
+                None => field_value(&document.body, &search.field)
+                    .and_then(serde_json::Value::as_str)
+                    .map_or(0.0, |text| search.score_text(text)),
+            })
+            .sum()
+    }
+    
+    /// Check a filter on just the top-level fields it reads, when the cell can decompress those alone
+    ///
+    /// Returns `false` only for documents that cannot match, which then need
+    /// not be decompressed whole. Cached documents are not checked, since
+    /// loading them costs nothing.
+    fn may_match(cell: &Cell, filter: &FilterExpression, fields: &BTreeSet<String>) -> Result<bool, HiveError> {
+        if cell.data.offsets.is_none() || cache::documents().contains(&cell.document_key()) {
+            return Ok(true);
+        }
+        let Some(texts) = cell.content_fields(fields)? else {
+            return Ok(true);
+        };
+        
+        let mut body = serde_json::Map::new();
+        for (name, text) in texts {
+            let value = serde_json::from_slice(&text)
+                .map_err(|e| HiveError::DeserializationError(e.to_string()))?;
+            body.insert(name, value);
+        }
+        Ok(filter.evaluate(&with_id(serde_json::Value::Object(body), &cell.id)))
+    }
+    
+    /// Parse the document in a cell, going through the document cache
+    ///
+    /// Returns the document with its approximate size in memory. Only
+    /// documents of cells that were not cold before this read are cached,
+    /// so a scan of rarely read cells does not push out the hot ones.
+    pub(crate) fn load_document(cell: &Cell) -> Result<(Arc<serde_json::Value>, usize), HiveError> {
+        let admit = cell.temperature() != Temperature::Cold;
+        cell.record_read();
+        let documents = cache::documents();
+        if let Some(cached) = documents.get(&cell.document_key()) {
+            return Ok(cached);
+        }
+        
+        let content = cell.get_content()?;
+        let _buffer = memory::global().reserve(MemoryCategory::Decompression, content.len())?;
+        let body: serde_json::Value = serde_json::from_slice(&content)
+            .map_err(|e| HiveError::DeserializationError(e.to_string()))?;
+        
+        // Parsed JSON takes roughly twice the space of its text
+        let size = content.len() * 2;
+        let body = Arc::new(body);
+        if admit {
+            documents.insert(&cell.document_key(), body.clone(), size);
+        }
+        
+        Ok((body, size))
+    }
+}
+
+impl QueryPlan {
+    /// Render the plan as lines of text, most important first
+    pub fn lines(&self) -> Vec<String> {
+        let rows = self.estimated_rows
+            .map(|rows| format!("  (rows={:.0})", rows))
+            .unwrap_or_default();
+        
+        let mut lines = vec![match (&self.access, &self.index) {
+            (AccessPath::FullScan, _) | (_, None) => format!("Full Scan on {}{}", self.target, rows),
+            (AccessPath::IndexScan, Some(index)) => format!("Index Scan using {} on {}{}", index, self.target, rows),
+            (AccessPath::IndexOnlyScan, Some(index)) => format!("Index Only Scan using {} on {}{}", index, self.target, rows),
+        }];
+        if let Some(condition) = &self.index_condition {
+            lines.push(format!("  Index Cond: {}", condition));
+        }
+        if let Some(filter) = &self.filter {
+            lines.push(format!("  Filter: {}", filter));
+        }
+        
+        lines
+    }
+}
+
+impl fmt::Display for FilterExpression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Nested AND and OR groups are parenthesized
+        let grouped = |filter: &FilterExpression| match filter {
+            FilterExpression::And(_) | FilterExpression::Or(_) => format!("({})", filter),
+            other => other.to_string(),
+        };
+        
+        match self {
+            FilterExpression::Comparison(op, field, value) => {
+                let op = match op {
+                    ComparisonOperator::Eq => "=",
+                    ComparisonOperator::Ne => "!=",
+                    ComparisonOperator::Gt => ">",
+                    ComparisonOperator::Gte => ">=",
+                    ComparisonOperator::Lt => "<",
+                    ComparisonOperator::Lte => "<=",
+                };
+                write!(f, "{} {} {}", field, op, sql_literal(value))
+            }
+            FilterExpression::And(filters) => {
+                write!(f, "{}", filters.iter().map(grouped).collect::<Vec<_>>().join(" AND "))
+            }
+            FilterExpression::Or(filters) => {
+                write!(f, "{}", filters.iter().map(grouped).collect::<Vec<_>>().join(" OR "))
+            }
+            FilterExpression::Not(filter) => write!(f, "NOT ({})", filter),
+            FilterExpression::Exists(field, true) => write!(f, "EXISTS({})", field),
+            FilterExpression::Exists(field, false) => write!(f, "NOT EXISTS({})", field),
+            FilterExpression::Pattern(field, pattern) => {
+                write!(f, "{} LIKE {}", field, sql_literal(&serde_json::Value::String(pattern.clone())))
+            }
+            FilterExpression::In(field, values) => {
+                let values: Vec<String> = values.iter().map(sql_literal).collect();
+                write!(f, "{} IN ({})", field, values.join(", "))
+            }
+            FilterExpression::Geo(geo) => write!(f, "{:?}", geo),
+            FilterExpression::Search(search) => {
+                let query = sql_literal(&serde_json::Value::String(search.query.clone()));
+                match search.fuzziness {
+                    0 => write!(f, "SEARCH({}, {})", search.field, query),
+                    fuzziness => write!(f, "SEARCH({}, {}, {})", search.field, query, fuzziness),
+                }
+            }
+            FilterExpression::Array(ArrayFilter::ElemMatch { field, filter }) => {
+                write!(f, "ANY_MATCH({}, {})", field, filter)
+            }
+            FilterExpression::Array(ArrayFilter::ContainsAll { field, values }) => {
+                let values: Vec<String> = values.iter().map(sql_literal).collect();
+                write!(f, "CONTAINS_ALL({}, {})", field, values.join(", "))
+            }
+            FilterExpression::Array(ArrayFilter::Size { field, size }) => {
+                write!(f, "ARRAY_LENGTH({}) = {}", field, size)
+            }
+            FilterExpression::InQuery(field, subquery) => {
+                let target = match &subquery.hive {
+                    Some(hive) => format!("{}.{}", hive, subquery.query.target),
+                    None => subquery.query.target.clone(),
+                };
+                write!(f, "{} IN (SELECT {} FROM {}", field, subquery.field, target)?;
+                if let Some(filter) = &subquery.query.filter {
+                    write!(f, " WHERE {}", filter)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+impl FilterExpression {
+    /// Check whether a document satisfies this filter
+    pub fn evaluate(&self, document: &serde_json::Value) -> bool {
+        match self {
+            FilterExpression::Comparison(op, field, expected) => {
+                match operand_value(document, field) {
+                    Some(actual) => match op {
+                        ComparisonOperator::Eq => values_equal(&actual, expected),
+                        ComparisonOperator::Ne => !values_equal(&actual, expected),
+                        ComparisonOperator::Gt => compare_values(&actual, expected) == Some(Ordering::Greater),
+                        ComparisonOperator::Gte => matches!(compare_values(&actual, expected), Some(Ordering::Greater | Ordering::Equal)),
+                        ComparisonOperator::Lt => compare_values(&actual, expected) == Some(Ordering::Less),
+                        ComparisonOperator::Lte => matches!(compare_values(&actual, expected), Some(Ordering::Less | Ordering::Equal)),
+                    },
+                    // A missing field is only "not equal" to a value
+                    None => *op == ComparisonOperator::Ne,
+                }
+            }
+            FilterExpression::And(expressions) => expressions.iter().all(|e| e.evaluate(document)),
+            FilterExpression::Or(expressions) => expressions.iter().any(|e| e.evaluate(document)),
+            FilterExpression::Not(expression) => !expression.evaluate(document),
+            FilterExpression::Exists(field, should_exist) => {
+                operand_value(document, field).is_some() == *should_exist
+            }
+            FilterExpression::Pattern(field, pattern) => {
+                match operand_value(document, field) {
+                    Some(value) => value.as_str().is_some_and(|text| like_match(pattern, text)),
+                    None => false,
+                }
+            }
+            FilterExpression::In(field, values) => {
+                match operand_value(document, field) {
+                    Some(actual) => values.iter().any(|v| values_equal(&actual, v)),
+                    None => false,
+                }
+            }
+            FilterExpression::Geo(geo) => geo.evaluate(document),
+            FilterExpression::Search(search) => search.evaluate(document),
+            FilterExpression::Array(array) => array.evaluate(document),
+            // Subqueries are replaced by their values before documents are read
+            FilterExpression::InQuery(..) => false,
+        }
+    }
+}
+
+impl ArrayFilter {
+    /// Get the array field the filter reads
+    pub fn field(&self) -> &str {
+        match self {
+            ArrayFilter::ElemMatch { field, .. }
+            | ArrayFilter::ContainsAll { field, .. }
+            | ArrayFilter::Size { field, .. } => field,
+        }
+    }
+    
+    /// Check whether a document's array satisfies this filter
+    ///
+    /// Documents where the field is missing or not an array never do.
+    pub fn evaluate(&self, document: &serde_json::Value) -> bool {
+        let Some(items) = field_value(document, self.field()).and_then(serde_json::Value::as_array) else {
+            return false;
+        };
+        
+        match self {
+            ArrayFilter::ElemMatch { filter, .. } => items.iter().any(|item| match item {
+                serde_json::Value::Object(_) => filter.evaluate(item),
+                other => filter.evaluate(&serde_json::json!({ ELEMENT_FIELD: other })),
+            }),
+            ArrayFilter::ContainsAll { values, .. } => {
+                values.iter().all(|value| items.iter().any(|item| values_equal(item, value)))
+            }
+            ArrayFilter::Size { size, .. } => items.len() == *size,
+        }
+    }
+}
+
+impl GeoFilter {
+    /// Check whether a document's point lies inside this area
+    ///
+    /// Points are `[x, y]` arrays or `{"lat": .., "lon": ..}` objects, and
+    /// distances are planar, in the same units as the coordinates.
+    pub fn evaluate(&self, document: &serde_json::Value) -> bool {
+        match self {
+            GeoFilter::Near { field, center, radius } => {
+                match field_value(document, field).and_then(geo_point) {
+                    Some((x, y)) => ((x - center.0).powi(2) + (y - center.1).powi(2)).sqrt() <= *radius,
+                    None => false,
+                }
+            }
+            GeoFilter::Within { field, min, max } => {
+                match field_value(document, field).and_then(geo_point) {
+                    Some((x, y)) => x >= min.0 && x <= max.0 && y >= min.1 && y <= max.1,
+                    None => false,
+                }
+            }
+        }
+    }
+}
+
+impl SortCriteria {
+    /// Sort by a field in a direction, with nulls in their default place
+    pub fn new(field: String, direction: SortDirection) -> Self {
+        Self {
+            field,
+            direction,
+            nulls: None,
+        }
+    }
+    
+    /// Put nulls first or last, whatever the direction
+    pub fn with_nulls(mut self, nulls: NullsOrder) -> Self {
+        self.nulls = Some(nulls);
+        self
+    }
+    
+    /// Check whether nulls come before every value
+    pub fn nulls_first(&self) -> bool {
+        match self.nulls {
+            Some(nulls) => nulls == NullsOrder::First,
+            None => self.direction == SortDirection::Descending,
+        }
+    }
+}
+
+/// A step of a field path
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum PathSegment {
+    /// Member of an object, or element of an array if the key is a number
+    Key(String),
+    
+    /// Element of an array
+    Index(usize),
+}
+
+/// Split a field path such as `items[0].sku` into its steps
+///
+/// Returns None if a bracket is unbalanced or holds something other than
+/// an array index.
+pub(crate) fn field_path(field: &str) -> Option<Vec<PathSegment>> {
+    let mut segments = Vec::new();
+    
+    for part in field.split('.') {
+        let (key, mut rest) = part.split_once('[')
+            .map_or((part, ""), |(key, rest)| (key, rest));
+        if !key.is_empty() {
+            segments.push(PathSegment::Key(key.to_string()));
+        }
+        while !rest.is_empty() {
+            let (index, after) = rest.split_once(']')?;
+            segments.push(PathSegment::Index(index.trim().parse().ok()?));
+            rest = match after {
+                "" => "",
+                after => after.strip_prefix('[')?,
+            };
+        }
+    }
+    
+    Some(segments)
+}
+
+/// Look up a field in a document, following the path or JSON Pointer if it names a nested one
+pub(crate) fn field_value<'a>(document: &'a serde_json::Value, field: &str) -> Option<&'a serde_json::Value> {
+    // A member whose name merely contains a dot takes precedence
+    if let Some(value) = document.get(field) {
+        return Some(value);
+    }
+    if field.starts_with('/') {
+        return document.pointer(field);
+    }
+    if !field.contains(['.', '[']) {
+        return None;
+    }
+    
+    field_path(field)?.iter().try_fold(document, |value, segment| match (segment, value) {
+        (PathSegment::Key(key), serde_json::Value::Object(object)) => object.get(key),
+        (PathSegment::Key(key), serde_json::Value::Array(items)) => items.get(key.parse::<usize>().ok()?),
+        (PathSegment::Index(index), serde_json::Value::Array(items)) => items.get(*index),
+        _ => None,
+    })
+}
+
+/// Look up a field, or compute a function of one such as `lower(email)`
+fn operand_value<'a>(document: &'a serde_json::Value, field: &str) -> Option<Cow<'a, serde_json::Value>> {
+    if !field.contains('(') {
+        return field_value(document, field).map(Cow::Borrowed);
+    }
+    
+    IndexExpression::parse(field).ok()?.evaluate(document).map(Cow::Owned)
+}
+
+/// Compare two JSON values for equality, treating 1 and 1.0 as equal
+pub(crate) fn values_equal(a: &serde_json::Value, b: &serde_json::Value) -> bool {
+    match (a.as_f64(), b.as_f64()) {
+        (Some(x), Some(y)) if a.is_number() && b.is_number() => x == y,
+        _ => a == b,
+    }
+}
+
+/// Order two JSON values of the same kind; values of different kinds are unordered
+pub(crate) fn compare_values(a: &serde_json::Value, b: &serde_json::Value) -> Option<Ordering> {
+    use serde_json::Value;
+    
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => x.as_f64()?.partial_cmp(&y.as_f64()?),
+        (Value::String(x), Value::String(y)) => Some(x.cmp(y)),
+        (Value::Bool(x), Value::Bool(y)) => Some(x.cmp(y)),
+        _ => None,
+    }
+}
+
+/// Order two documents by a list of sort criteria
+///
+/// Missing fields count as null. Values of different kinds are ordered
+/// by kind: booleans, numbers, strings, arrays, then objects.
+fn compare_documents(a: &serde_json::Value, b: &serde_json::Value, criteria: &[SortCriteria]) -> Ordering {
+    criteria.iter()
+        .map(|criterion| compare_sort_values(sort_value(a, &criterion.field), sort_value(b, &criterion.field), criterion))
+        .find(|ordering| *ordering != Ordering::Equal)
+        .unwrap_or(Ordering::Equal)
+}
+
+/// Get the value of a sort field in a document, or None if null or missing
+fn sort_value<'a>(document: &'a serde_json::Value, field: &str) -> Option<&'a serde_json::Value> {
+    field_value(document, field).filter(|value| !matches!(value, serde_json::Value::Null))
+}
+
+/// Order two values of a sort field, None standing for null
+fn compare_sort_values(x: Option<&serde_json::Value>, y: Option<&serde_json::Value>, criterion: &SortCriteria) -> Ordering {
+    match (x, y) {
+        (Some(x), Some(y)) => match criterion.direction {
+            SortDirection::Ascending => compare_key_values(x, y),
+            SortDirection::Descending => compare_key_values(x, y).reverse(),
+        },
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) if criterion.nulls_first() => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (Some(_), None) if criterion.nulls_first() => Ordering::Greater,
+        (Some(_), None) => Ordering::Less,
+    }
+}
+
+/// Keep only the listed fields of a document (plus its ID)
+///
+/// A nested field is kept under its path or pointer, as in `{"address.city": "Cairo"}`.
+fn project(document: &serde_json::Value, fields: &[String]) -> serde_json::Value {
+    let mut projected = serde_json::Map::new();
+    
+    if let Some(id) = document.get(ID_FIELD) {
+        projected.insert(ID_FIELD.to_string(), id.clone());
+    }
+    for field in fields {
+        if let Some(value) = field_value(document, field) {
+            projected.insert(field.clone(), value.clone());
+        }
+    }
+    
+    serde_json::Value::Object(projected)
+}
+
+/// Reorder the conditions of a filter so the cheapest to decide come first
+///
+/// AND conditions are evaluated most selective first and OR conditions
+/// least selective first, so evaluation stops as early as possible.
+fn plan_filter(filter: &FilterExpression, stats: &CollectionStats) -> FilterExpression {
+    let by_selectivity = |filters: &[FilterExpression]| {
+        let mut planned: Vec<(f64, FilterExpression)> = filters.iter()
+            .map(|filter| (stats.selectivity(filter), plan_filter(filter, stats)))
+            .collect();
+        planned.sort_by(|a, b| a.0.total_cmp(&b.0));
+        planned
+    };
+    
+    match filter {
+        FilterExpression::And(filters) => {
+            FilterExpression::And(by_selectivity(filters).into_iter().map(|(_, filter)| filter).collect())
+        }
+        FilterExpression::Or(filters) => {
+            FilterExpression::Or(by_selectivity(filters).into_iter().rev().map(|(_, filter)| filter).collect())
+        }
+        FilterExpression::Not(filter) => FilterExpression::Not(Box::new(plan_filter(filter, stats))),
+        other => other.clone(),
+    }
+}
+
+/// Collect the fields a filter reads (the field under a function such as `lower(email)`)
+fn filter_fields(filter: &FilterExpression, fields: &mut BTreeSet<String>) {
+    let mut add = |operand: &str| {
+        let field = match IndexExpression::parse(operand) {
+            Ok(expression) => expression.field().to_string(),
+            Err(_) => operand.to_string(),
+        };
+        fields.insert(field);
+    };
+    
+    match filter {
+        FilterExpression::Comparison(_, field, _)
+        | FilterExpression::Exists(field, _)
+        | FilterExpression::Pattern(field, _)
+        | FilterExpression::In(field, _) => add(field),
+        FilterExpression::Geo(GeoFilter::Near { field, .. } | GeoFilter::Within { field, .. }) => add(field),
+        FilterExpression::Search(search) => add(&search.field),
+        FilterExpression::Array(array) => add(array.field()),
+        FilterExpression::InQuery(field, _) => add(field),
+        FilterExpression::And(filters) | FilterExpression::Or(filters) => {
+            for filter in filters {
+                filter_fields(filter, fields);
+            }
+        }
+        FilterExpression::Not(filter) => filter_fields(filter, fields),
+    }
+}
+
+/// Get the top-level fields a filter reads, when few enough to check it on them alone
+///
+/// A path such as `address.city` reads `address`, and also a member
+/// named `address.city`, which takes precedence. The ID is not read from
+/// the document.
+fn prefilter_fields(filter: &FilterExpression) -> Option<BTreeSet<String>> {
+    let mut fields = BTreeSet::new();
+    filter_fields(filter, &mut fields);
+    
+    let mut names = BTreeSet::new();
+    for field in fields.into_iter().filter(|field| field != ID_FIELD) {
+        if let Some(pointer) = field.strip_prefix('/') {
+            let first = pointer.split('/').next().unwrap_or_default();
+            if first.contains('~') {
+                return None;
+            }
+            names.insert(first.to_string());
+            continue;
+        }
+        if let Some(PathSegment::Key(key)) = field_path(&field).and_then(|path| path.into_iter().next()) {
+            names.insert(key);
+        }
+        names.insert(field);
+    }
+    
+    (names.len() <= MAX_PREFILTER_FIELDS).then_some(names)
+}
+
+/// Check whether a filter holds subqueries that must run first
+fn has_subqueries(filter: &FilterExpression) -> bool {
+    match filter {
+        FilterExpression::InQuery(..) => true,
+        FilterExpression::And(filters) | FilterExpression::Or(filters) => filters.iter().any(has_subqueries),
+        FilterExpression::Not(filter) | FilterExpression::Array(ArrayFilter::ElemMatch { filter, .. }) => {
+            has_subqueries(filter)
+        }
+        _ => false,
+    }
+}
+
+/// Collect the text searches a document can match through (those not under a NOT)
+fn text_searches(filter: &FilterExpression) -> Vec<&TextSearch> {
+    match filter {
+        FilterExpression::Search(search) => vec![search],
+        FilterExpression::And(filters) | FilterExpression::Or(filters) => {
+            filters.iter().flat_map(text_searches).collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Write a JSON value as a SQL literal
+fn sql_literal(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(text) => format!("'{}'", text.replace('\'', "''")),
+        other => other.to_string(),
+    }
+}
+
+/// Add the cell ID to a document body
+pub(crate) fn with_id(mut body: serde_json::Value, id: &str) -> serde_json::Value {
+    if let Some(object) = body.as_object_mut() {
+        object.insert(ID_FIELD.to_string(), serde_json::Value::String(id.to_string()));
+    }
+    body
+}
+
+/// Read a point from a `[x, y]` array or a `{"lat", "lon"}` object
+fn geo_point(value: &serde_json::Value) -> Option<(f64, f64)> {
+    match value {
+        serde_json::Value::Array(items) if items.len() == 2 => {
+            Some((items[0].as_f64()?, items[1].as_f64()?))
+        }
+        serde_json::Value::Object(object) => {
+            Some((object.get("lat")?.as_f64()?, object.get("lon")?.as_f64()?))
+        }
+        _ => None,
+    }
+}
+
+/// Open the trace span covering the execution of a query
+fn execute_span(query: &Query, hive: &Hive) -> telemetry::SpanGuard {
+    let mut span = telemetry::span("query.execute");
+    span.set_attribute("db.system", "hivedb");
+    span.set_attribute("db.namespace", &hive.name);
+    span.set_attribute("db.operation.name", format!("{:?}", query.query_type));
+    span.set_attribute("db.collection.name", &query.target);
+    span
+}
+
+/// SQL LIKE matching: `%` matches any run of characters, `_` exactly one
+pub fn like_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    
+    // Iterative matcher with backtracking to the last `%`
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '_' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '%' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    
+    pattern[p..].iter().all(|c| *c == '%')
+}
+
+/// Generate a unique ID for an inserted document
+fn generate_document_id() -> String {
+    format!("doc-{}", hex::encode(determinism::random_bytes(12)))
+}
+
+/// Create a simple equality filter
+pub fn eq(field: &str, value: serde_json::Value) -> FilterExpression {
+    FilterExpression::Comparison(ComparisonOperator::Eq, field.to_string(), value)
+}
+
+/// Create a simple inequality filter
+pub fn ne(field: &str, value: serde_json::Value) -> FilterExpression {
+    FilterExpression::Comparison(ComparisonOperator::Ne, field.to_string(), value)
+}
+
+/// Create a greater than filter
+pub fn gt(field: &str, value: serde_json::Value) -> FilterExpression {
+    FilterExpression::Comparison(ComparisonOperator::Gt, field.to_string(), value)
+}
+
+/// Create a greater than or equal filter
+pub fn gte(field: &str, value: serde_json::Value) -> FilterExpression {
+    FilterExpression::Comparison(ComparisonOperator::Gte, field.to_string(), value)
+}
+
+/// Create a less than filter
+pub fn lt(field: &str, value: serde_json::Value) -> FilterExpression {
+    FilterExpression::Comparison(ComparisonOperator::Lt, field.to_string(), value)
+}
+
+/// Create a less than or equal filter
+pub fn lte(field: &str, value: serde_json::Value) -> FilterExpression {
+    FilterExpression::Comparison(ComparisonOperator::Lte, field.to_string(), value)
+}
+
+/// Create an AND filter
+pub fn and(expressions: Vec<FilterExpression>) -> FilterExpression {
+    FilterExpression::And(expressions)
+}
+
+/// Create an OR filter
+pub fn or(expressions: Vec<FilterExpression>) -> FilterExpression {
+    FilterExpression::Or(expressions)
+}
+
+/// Create a NOT filter
+pub fn not(expression: FilterExpression) -> FilterExpression {
+    FilterExpression::Not(Box::new(expression))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_query_builder() {
+        let query = Query::new(QueryType::Find, "users".to_string())
+            .with_filter(eq("age", serde_json::json!(30)))
+            .with_projection(vec!["name".to_string(), "email".to_string()])
+            .with_sort(vec![
+                SortCriteria::new("name".to_string(), SortDirection::Ascending)
+            ])
+            .with_limit(10)
+            .with_skip(0);
+        
+        assert_eq!(query.query_type, QueryType::Find);
+        assert_eq!(query.target, "users");
+        
+        if let Some(FilterExpression::Comparison(op, field, value)) = &query.filter {
+            assert_eq!(*op, ComparisonOperator::Eq);
+            assert_eq!(field, "age");
+            assert_eq!(value, &serde_json::json!(30));
+        } else {
+            panic!("Expected Comparison filter");
+        }
+        
+        assert_eq!(query.projection, Some(vec!["name".to_string(), "email".to_string()]));
+        assert_eq!(query.limit, Some(10));
+        assert_eq!(query.skip, Some(0));
+    }
+    
+    #[test]
+    fn test_complex_filter() {
+        let filter = and(vec![
+            eq("status", serde_json::json!("active")),
+            or(vec![
+                gt("age", serde_json::json!(18)),
+                eq("verified", serde_json::json!(true)),
+            ]),
+        ]);
+        
+        if let FilterExpression::And(conditions) = &filter {
+            assert_eq!(conditions.len(), 2);
+            
+            if let FilterExpression::Comparison(op, field, value) = &conditions[0] {
+                assert_eq!(*op, ComparisonOperator::Eq);
+                assert_eq!(field, "status");
+                assert_eq!(value, &serde_json::json!("active"));
+            } else {
+                panic!("Expected Comparison filter");
+            }
+            
+            if let FilterExpression::Or(or_conditions) = &conditions[1] {
+                assert_eq!(or_conditions.len(), 2);
+                
+                if let FilterExpression::Comparison(op, field, value) = &or_conditions[0] {
+                    assert_eq!(*op, ComparisonOperator::Gt);
+                    assert_eq!(field, "age");
+                    assert_eq!(value, &serde_json::json!(18));
+                } else {
+                    panic!("Expected Comparison filter");
+                }
+                
+                if let FilterExpression::Comparison(op, field, value) = &or_conditions[1] {
+                    assert_eq!(*op, ComparisonOperator::Eq);
+                    assert_eq!(field, "verified");
+                    assert_eq!(value, &serde_json::json!(true));
+                } else {
+                    panic!("Expected Comparison filter");
+                }
+            } else {
+                panic!("Expected Or filter");
+            }
+        } else {
+            panic!("Expected And filter");
+        }
+    }
+    
+    #[test]
+    fn test_filter_evaluation() {
+        let document = serde_json::json!({
+            "name": "Ahmed",
+            "age": 28,
+            "city": "Cairo",
+            "location": [30.0, 31.2],
+        });
+        
+        assert!(eq("age", serde_json::json!(28.0)).evaluate(&document));
+        assert!(gt("age", serde_json::json!(18)).evaluate(&document));
+        assert!(!lt("age", serde_json::json!(18)).evaluate(&document));
+        assert!(ne("missing", serde_json::json!(1)).evaluate(&document));
+        assert!(!eq("missing", serde_json::json!(1)).evaluate(&document));
+        assert!(and(vec![
+            FilterExpression::Exists("name".to_string(), true),
+            FilterExpression::Pattern("name".to_string(), "Ah%".to_string()),
+            FilterExpression::In("city".to_string(), vec![serde_json::json!("Cairo"), serde_json::json!("Giza")]),
+        ]).evaluate(&document));
+        assert!(FilterExpression::Geo(GeoFilter::Near {
+            field: "location".to_string(),
+            center: (30.0, 31.0),
+            radius: 0.5,
+        }).evaluate(&document));
+    }
+    
+    #[test]
+    fn test_subqueries() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut manager = HiveManager::new(temp_dir.path().to_path_buf()).unwrap();
+        let shop = manager.create_hive("shop".to_string(), String::new(), "test-user".to_string(), (16, 16)).unwrap();
+        let crm = manager.create_hive("crm".to_string(), String::new(), "test-user".to_string(), (16, 16)).unwrap();
+        
+        let insert = |hive: &str, target: &str, data: serde_json::Value| {
+            let hive = manager.get_hive(hive).unwrap();
+            let mut hive = hive.write().unwrap();
+            Query::new(QueryType::Insert, target.to_string()).with_data(data).execute(&mut hive).unwrap();
+        };
+        insert(&shop, "users", serde_json::json!([
+            { "_id": "u1", "name": "Ada" },
+            { "_id": "u2", "name": "Bob" },
+            { "_id": "u3", "name": "Cy" },
+        ]));
+        insert(&shop, "orders", serde_json::json!([
+            { "_id": "o1", "user": "u1", "week": 12 },
+            { "_id": "o2", "user": "u3", "week": 11 },
+            { "_id": "o3", "user": "u1", "week": 12 },
+        ]));
+        insert(&crm, "vips", serde_json::json!([{ "_id": "v1", "user": "u2" }]));
+        
+        let names = |result: QueryResult| -> Vec<String> {
+            result.results.iter().filter_map(|doc| doc["name"].as_str().map(String::from)).collect()
+        };
+        
+        let hive = manager.get_hive(&shop).unwrap();
+        let mut hive = hive.write().unwrap();
+        let this_week = crate::core::sql::parse(
+            "SELECT name FROM users WHERE _id IN (SELECT DISTINCT user FROM orders WHERE week = 12)"
+        ).unwrap();
+        assert_eq!(this_week.filter.as_ref().unwrap().to_string(), "_id IN (SELECT user FROM orders WHERE week = 12)");
+        assert_eq!(names(this_week.execute(&mut hive).unwrap()), vec!["Ada"]);
+        
+        // Another hive's subquery needs the manager to find that hive
+        let vips = crate::core::sql::parse("SELECT name FROM users WHERE _id NOT IN (SELECT user FROM crm.vips)").unwrap();
+        assert!(vips.execute(&mut hive).is_err());
+        let materialized = QueryExecutor::materialize(&vips, &hive, Some(&manager), &CancelToken::new()).unwrap();
+        assert!(!materialized.has_subqueries());
+        assert_eq!(names(materialized.execute(&mut hive).unwrap()), vec!["Ada", "Cy"]);
+    }
+    
+    #[test]
+    fn test_patch_update_and_pointer_projection() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut hive = Hive::new(
+            "patched".to_string(),
+            "Patch test".to_string(),
+            "test-user".to_string(),
+            temp_dir.path().to_path_buf(),
+            (16, 16),
+        ).unwrap();
+        
+        Query::new(QueryType::Insert, "users".to_string())
+            .with_data(serde_json::json!([
+                { "_id": "u1", "address": { "city": "Cairo" }, "tags": ["a"] },
+                { "_id": "u2", "address": { "city": "Giza" }, "tags": [] },
+            ]))
+            .execute(&mut hive)
+            .unwrap();
+        
+        let patched = Query::new(QueryType::Update, "users".to_string())
+            .with_data(serde_json::json!([
+                { "op": "add", "path": "/tags/-", "value": "new" },
+                { "op": "remove", "path": "/address/city" },
+            ]))
+            .execute(&mut hive)
+            .unwrap();
+        assert_eq!(patched.count, 2);
+        
+        let read = Query::new(QueryType::Find, "users".to_string())
+            .with_filter(eq("/tags/0", serde_json::json!("new")))
+            .with_projection(vec!["/tags/0".to_string(), "/address".to_string()])
+            .execute(&mut hive)
+            .unwrap();
+        assert_eq!(read.results, vec![serde_json::json!({ "_id": "u2", "/tags/0": "new", "/address": {} })]);
+        
+        // A failed test on one document leaves every document unchanged
+        let failed = Query::new(QueryType::Update, "users".to_string())
+            .with_data(serde_json::json!([
+                { "op": "replace", "path": "/tags/0", "value": "b" },
+                { "op": "test", "path": "/tags/1", "value": "new" },
+            ]))
+            .execute(&mut hive);
+        assert!(failed.is_err());
+        let unchanged = Query::new(QueryType::Count, "users".to_string())
+            .with_filter(eq("/tags/0", serde_json::json!("b")))
+            .execute(&mut hive)
+            .unwrap();
+        assert_eq!(unchanged.count, 0);
+    }
+    
+    #[test]
+    fn test_array_filters() {
+        let document = serde_json::json!({
+            "tags": ["gift", "rush", 3],
+            "items": [{ "sku": "A1", "qty": 1 }, { "sku": "B2", "qty": 4 }],
+            "name": "order",
+        });
+        
+        let elem_match = |field: &str, filter| FilterExpression::Array(ArrayFilter::ElemMatch {
+            field: field.to_string(),
+            filter: Box::new(filter),
+        });
+        assert!(elem_match("items", and(vec![
+            eq("sku", serde_json::json!("B2")),
+            gt("qty", serde_json::json!(2)),
+        ])).evaluate(&document));
+        assert!(!elem_match("items", and(vec![
+            eq("sku", serde_json::json!("A1")),
+            gt("qty", serde_json::json!(2)),
+        ])).evaluate(&document));
+        assert!(elem_match("tags", gt(ELEMENT_FIELD, serde_json::json!(2))).evaluate(&document));
+        assert!(!elem_match("name", eq(ELEMENT_FIELD, serde_json::json!("order"))).evaluate(&document));
+        
+        let contains_all = |values: serde_json::Value| FilterExpression::Array(ArrayFilter::ContainsAll {
+            field: "tags".to_string(),
+            values: values.as_array().unwrap().clone(),
+        });
+        assert!(contains_all(serde_json::json!(["rush", 3.0])).evaluate(&document));
+        assert!(!contains_all(serde_json::json!(["rush", "fragile"])).evaluate(&document));
+        
+        let size = |size| FilterExpression::Array(ArrayFilter::Size { field: "items".to_string(), size });
+        assert!(size(2).evaluate(&document));
+        assert!(!size(3).evaluate(&document));
+        assert!(!FilterExpression::Array(ArrayFilter::Size { field: "missing".to_string(), size: 0 }).evaluate(&document));
+    }
+    
+    #[test]
+    fn test_nested_field_paths() {
+        use crate::core::schema::{IndexType, Schema, SchemaIndex};
+        
+        assert_eq!(field_path("items[0][1].sku"), Some(vec![
+            PathSegment::Key("items".to_string()),
+            PathSegment::Index(0),
+            PathSegment::Index(1),
+            PathSegment::Key("sku".to_string()),
+        ]));
+        assert_eq!(field_path("items[x]"), None);
+        assert_eq!(field_path("items[0"), None);
+        
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut hive = Hive::new(
+            "nested".to_string(),
+            "Nested path test".to_string(),
+            "test-user".to_string(),
+            temp_dir.path().to_path_buf(),
+            (16, 16),
+        ).unwrap();
+        
+        let mut schema = Schema::new("users".to_string(), String::new(), "1".to_string());
+        schema.add_index(SchemaIndex::new("by_city".to_string(), vec!["address.city".to_string()], IndexType::BTree, false));
+        hive.set_schema(schema).unwrap();
+        
+        Query::new(QueryType::Insert, "users".to_string())
+            .with_data(serde_json::json!([
+                { "_id": "u1", "address": { "city": "Cairo" }, "tags": [{ "name": "admin" }], "a.b": 1 },
+                { "_id": "u2", "address": { "city": "Giza" }, "tags": [{ "name": "staff" }, { "name": "admin" }] },
+                { "_id": "u3", "address": { "city": "Cairo" }, "tags": [] },
+            ]))
+            .execute(&mut hive)
+            .unwrap();
+        
+        let by_city = crate::core::sql::parse("SELECT address.city, tags[0].name FROM users WHERE address.city = 'Cairo'").unwrap();
+        assert_eq!(QueryExecutor::explain(&by_city, &hive).access, AccessPath::IndexScan);
+        assert_eq!(by_city.execute(&mut hive).unwrap().results, vec![
+            serde_json::json!({ "_id": "u1", "address.city": "Cairo", "tags[0].name": "admin" }),
+            serde_json::json!({ "_id": "u3", "address.city": "Cairo" }),
+        ]);
+        
+        let sorted = crate::core::sql::parse("SELECT * FROM users WHERE tags.1.name = 'admin' OR a.b = 1 ORDER BY address.city DESC").unwrap();
+        let ids: Vec<String> = sorted.execute(&mut hive).unwrap().results.iter()
+            .filter_map(|doc| doc[ID_FIELD].as_str().map(String::from))
+            .collect();
+        assert_eq!(ids, vec!["u2", "u1"]);
+    }
+    
+    #[test]
+    fn test_like_match() {
+        assert!(like_match("%abc%", "xxabcxx"));
+        assert!(like_match("a_c", "abc"));
+        assert!(!like_match("a_c", "abbc"));
+        assert!(like_match("%", ""));
+        assert!(!like_match("abc", "abcd"));
+    }
+    
+    #[test]
+    fn test_sort_with_nulls_and_mixed_types() {
+        let documents = vec![
+            serde_json::json!({ "_id": "a", "city": "Cairo", "age": 30 }),
+            serde_json::json!({ "_id": "b", "city": "Cairo" }),
+            serde_json::json!({ "_id": "c", "city": "Alexandria", "age": "unknown" }),
+            serde_json::json!({ "_id": "d", "city": "Cairo", "age": null }),
+            serde_json::json!({ "_id": "e", "age": 25 }),
+            serde_json::json!({ "_id": "f", "city": "Alexandria", "age": 41 }),
+        ];
+        let sorted = |criteria: &[SortCriteria]| {
+            let mut sorted = documents.clone();
+            sorted.sort_by(|a, b| compare_documents(a, b, criteria));
+            sorted.iter().map(|document| document["_id"].as_str().unwrap().to_string()).collect::<Vec<_>>()
+        };
+        
+        // Nulls go last ascending and first descending, keeping their order;
+        // strings sort after numbers
+        let city = SortCriteria::new("city".to_string(), SortDirection::Ascending);
+        let age = SortCriteria::new("age".to_string(), SortDirection::Descending);
+        assert_eq!(sorted(&[city.clone(), age.clone()]), ["c", "f", "b", "d", "a", "e"]);
+        
+        let age = age.with_nulls(NullsOrder::Last);
+        assert_eq!(sorted(&[city.clone(), age.clone()]), ["c", "f", "a", "b", "d", "e"]);
+        
+        let city = city.with_nulls(NullsOrder::First);
+        assert_eq!(sorted(&[city, age]), ["e", "c", "f", "a", "b", "d"]);
+    }
+    
+    #[test]
+    fn test_cursor_pagination() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut hive = Hive::new(
+            "test-hive".to_string(),
+            "A test hive".to_string(),
+            "test-user".to_string(),
+            temp_dir.path().to_path_buf(),
+            (16, 16),
+        ).unwrap();
+        
+        let documents = (0..7)
+            .map(|i| serde_json::json!({ "_id": format!("u{}", i), "team": ["red", "blue"][i % 2] }))
+            .collect();
+        Query::new(QueryType::Insert, "users".to_string())
+            .with_data(serde_json::Value::Array(documents))
+            .execute(&mut hive)
+            .unwrap();
+        
+        // Pages of documents sorting alike continue in grid order
+        let sorted = || Query::new(QueryType::Find, "users".to_string())
+            .with_sort(vec![SortCriteria::new("team".to_string(), SortDirection::Ascending)])
+            .with_limit(3);
+        let mut ids = Vec::new();
+        let mut page = sorted().execute(&mut hive).unwrap();
+        loop {
+            ids.extend(page.results.iter().map(|result| result["_id"].as_str().unwrap().to_string()));
+            match page.next_cursor {
+                Some(cursor) => page = sorted().with_cursor(cursor).execute(&mut hive).unwrap(),
+                None => break,
+            }
+        }
+        assert_eq!(ids, ["u1", "u3", "u5", "u0", "u2", "u4", "u6"]);
+        assert!(!page.has_more);
+        
+        // Unsorted queries page in grid order
+        let first = Query::new(QueryType::Find, "users".to_string()).with_limit(4).execute(&mut hive).unwrap();
+        let rest = Query::new(QueryType::Find, "users".to_string())
+            .with_cursor(first.next_cursor.unwrap())
+            .execute(&mut hive)
+            .unwrap();
+        assert_eq!((rest.count, rest.next_cursor), (3, None));
+        
+        let cursor = sorted().execute(&mut hive).unwrap().next_cursor.unwrap();
+        let resorted = Query::new(QueryType::Find, "users".to_string()).with_cursor(cursor);
+        assert!(matches!(resorted.execute(&mut hive), Err(HiveError::QueryError(_))));
+        assert!(sorted().with_cursor("not a cursor".to_string()).execute(&mut hive).is_err());
+    }
+    
+    #[test]
+    fn test_execute_into_sink() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut hive = Hive::new(
+            "test-hive".to_string(),
+            "A test hive".to_string(),
+            "test-user".to_string(),
+            temp_dir.path().to_path_buf(),
+            (16, 16),
+        ).unwrap();
+        
+        let documents = (0..5).map(|i| serde_json::json!({ "_id": format!("p{}", i), "price": i * 10 })).collect();
+        Query::new(QueryType::Insert, "products".to_string())
+            .with_data(serde_json::Value::Array(documents))
+            .execute(&mut hive)
+            .unwrap();
+        
+        // Exports are streamed in read order
+        let mut lines = Vec::new();
+        let export = Query::new(QueryType::Find, "products".to_string())
+            .with_filter(gte("price", serde_json::json!(20)));
+        assert_eq!(export.execute_into(&hive, &mut lines, OutputFormat::JsonLines).unwrap(), 3);
+        let mut ids: Vec<String> = String::from_utf8(lines).unwrap().lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["_id"].as_str().unwrap().to_string())
+            .collect();
+        ids.sort();
+        assert_eq!(ids, ["p2", "p3", "p4"]);
+        
+        let mut csv = Vec::new();
+        let page = Query::new(QueryType::Find, "products".to_string())
+            .with_projection(vec!["price".to_string()])
+            .with_sort(vec![SortCriteria::new("price".to_string(), SortDirection::Descending)])
+            .with_limit(2);
+        assert_eq!(page.execute_into(&hive, &mut csv, OutputFormat::Csv).unwrap(), 2);
+        assert_eq!(String::from_utf8(csv).unwrap(), "_id,price\r\np4,40\r\np3,30\r\n");
+        
+        assert!(page.execute_into(&hive, Vec::new(), OutputFormat::Table).is_err());
+    }
+    
+    #[test]
+    fn test_query_execution() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut hive = Hive::new(
+            "test-hive".to_string(),
+            "A test hive".to_string(),
+            "test-user".to_string(),
+            temp_dir.path().to_path_buf(),
+            (16, 16),
+        ).unwrap();
+        
+        Query::new(QueryType::Insert, "users".to_string())
+            .with_data(serde_json::json!([
+                { "_id": "u1", "name": "Ahmed", "age": 28 },
+                { "_id": "u2", "name": "Sara", "age": 35 },
+                { "_id": "u3", "name": "Omar", "age": 17 },
+            ]))
+            .execute(&mut hive)
+            .unwrap();
+        
+        let adults = Query::new(QueryType::Find, "users".to_string())
+            .with_filter(gte("age", serde_json::json!(18)))
+            .with_sort(vec![SortCriteria::new("age".to_string(), SortDirection::Descending)])
+            .with_projection(vec!["name".to_string()])
+            .execute(&mut hive)
+            .unwrap();
+        
+        assert_eq!(adults.count, 2);
+        assert_eq!(adults.results[0], serde_json::json!({ "_id": "u2", "name": "Sara" }));
+        assert_eq!(adults.results[1], serde_json::json!({ "_id": "u1", "name": "Ahmed" }));
+        
+        let page = Query::new(QueryType::Find, "users".to_string())
+            .with_limit(2)
+            .execute(&mut hive)
+            .unwrap();
+        assert_eq!(page.count, 2);
+        assert!(page.has_more);
+        
+        let updated = Query::new(QueryType::Update, "users".to_string())
+            .with_filter(eq("_id", serde_json::json!("u3")))
+            .with_data(serde_json::json!({ "age": 18 }))
+            .execute(&mut hive)
+            .unwrap();
+        assert_eq!(updated.count, 1);
+        
+        let deleted = Query::new(QueryType::Delete, "users".to_string())
+            .with_filter(lt("age", serde_json::json!(30)))
+            .execute(&mut hive)
+            .unwrap();
+        assert_eq!(deleted.count, 2);
+        
+        let remaining = Query::new(QueryType::Count, "users".to_string())
+            .execute(&mut hive)
+            .unwrap();
+        assert_eq!(remaining.count, 1);
+    }
+    
+    #[test]
+    fn test_filters_planned_from_statistics() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut hive = Hive::new(
+            "planned".to_string(),
+            "Planner test".to_string(),
+            "test-user".to_string(),
+            temp_dir.path().to_path_buf(),
+            (16, 16),
+        ).unwrap();
+        
+        let users: Vec<serde_json::Value> = (0..100)
+            .map(|i| serde_json::json!({ "country": if i == 0 { "IS" } else { "US" }, "age": i }))
+            .collect();
+        Query::new(QueryType::Insert, "users".to_string())
+            .with_data(serde_json::Value::Array(users))
+            .execute(&mut hive)
+            .unwrap();
+        assert_eq!(hive.analyze(None).unwrap(), 2);
+        
+        let filter = FilterExpression::And(vec![
+            eq("country", serde_json::json!("US")),
+            eq("country", serde_json::json!("IS")),
+        ]);
+        let stats = hive.statistics.collection("users").unwrap();
+        match plan_filter(&filter, stats) {
+            FilterExpression::And(filters) => {
+                assert!(matches!(&filters[0], FilterExpression::Comparison(_, _, value) if value == "IS"));
+            }
+            other => panic!("Expected And filter, got {:?}", other),
+        }
+        
+        // Statistics are kept in the snapshot
+        hive.save().unwrap();
+        let loaded = Hive::load(temp_dir.path().to_path_buf()).unwrap();
+        assert_eq!(loaded.statistics, hive.statistics);
+    }
+    
+    #[test]
+    fn test_query_through_expression_index() {
+        use crate::core::schema::{IndexType, Schema, SchemaIndex};
+        
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut hive = Hive::new(
+            "indexed".to_string(),
+            "Index test".to_string(),
+            "test-user".to_string(),
+            temp_dir.path().to_path_buf(),
+            (16, 16),
+        ).unwrap();
+        
+        let mut schema = Schema::new("users".to_string(), String::new(), "1".to_string());
+        schema.add_index(
+            SchemaIndex::new("active_email".to_string(), vec!["lower(email)".to_string()], IndexType::BTree, false)
+                .with_predicate("active = true".to_string())
+        );
+        hive.set_schema(schema).unwrap();
+        
+        Query::new(QueryType::Insert, "users".to_string())
+            .with_data(serde_json::json!([
+                { "_id": "u1", "email": "Ada@Example.com", "active": true },
+                { "_id": "u2", "email": "ada@example.com", "active": false },
+                { "_id": "u3", "email": "bob@example.com", "active": true },
+            ]))
+            .execute(&mut hive)
+            .unwrap();
+        assert_eq!(hive.indexes.get("active_email").unwrap().len(), 2);
+        
+        let find = crate::core::sql::parse("SELECT * FROM users WHERE active = true AND lower(email) = 'ada@example.com'").unwrap();
+        let found = find.execute(&mut hive).unwrap();
+        assert_eq!(found.count, 1);
+        assert_eq!(found.results[0]["_id"], "u1");
+        
+        // Updates are reflected in the index
+        Query::new(QueryType::Update, "users".to_string())
+            .with_filter(eq("_id", serde_json::json!("u1")))
+            .with_data(serde_json::json!({ "active": false }))
+            .execute(&mut hive)
+            .unwrap();
+        assert_eq!(hive.indexes.get("active_email").unwrap().len(), 1);
+        assert_eq!(find.execute(&mut hive).unwrap().count, 0);
+    }
+    
+    #[test]
+    fn test_index_only_scan_and_explain() {
+        use crate::core::schema::{IndexType, Schema, SchemaIndex};
+        
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut hive = Hive::new(
+            "covered".to_string(),
+            "Covering index test".to_string(),
+            "test-user".to_string(),
+            temp_dir.path().to_path_buf(),
+            (16, 16),
+        ).unwrap();
+        
+        let mut schema = Schema::new("orders".to_string(), String::new(), "1".to_string());
+        schema.add_index(
+            SchemaIndex::new("by_total".to_string(), vec!["total".to_string()], IndexType::BTree, false)
+                .with_include(vec!["customer".to_string()])
+        );
+        hive.set_schema(schema).unwrap();
+        
+        Query::new(QueryType::Insert, "orders".to_string())
+            .with_data(serde_json::json!([
+                { "_id": "o1", "total": 120, "customer": "ada", "items": ["lamp"] },
+                { "_id": "o2", "total": 80, "customer": "bob", "items": ["pen"] },
+                { "_id": "o3", "total": 300, "customer": "cy", "items": ["desk"] },
+            ]))
+            .execute(&mut hive)
+            .unwrap();
+        
+        let covered = crate::core::sql::parse("SELECT customer FROM orders WHERE total >= 100 ORDER BY total DESC").unwrap();
+        let plan = QueryExecutor::explain(&covered, &hive);
+        assert_eq!(plan.access, AccessPath::IndexOnlyScan);
+        assert_eq!(plan.index.as_deref(), Some("by_total"));
+        assert_eq!(plan.lines()[0], "Index Only Scan using by_total on orders");
+        assert_eq!(plan.lines()[1], "  Index Cond: total >= 100");
+        
+        let result = covered.execute(&mut hive).unwrap();
+        assert_eq!(result.results, vec![
+            serde_json::json!({ "_id": "o3", "customer": "cy" }),
+            serde_json::json!({ "_id": "o1", "customer": "ada" }),
+        ]);
+        
+        // Reading a field the index does not store needs the cells
+        let uncovered = crate::core::sql::parse("SELECT items FROM orders WHERE total >= 100").unwrap();
+        assert_eq!(QueryExecutor::explain(&uncovered, &hive).access, AccessPath::IndexScan);
+        assert_eq!(uncovered.execute(&mut hive).unwrap().count, 2);
+        
+        let scan = crate::core::sql::parse("SELECT * FROM orders WHERE customer = 'bob'").unwrap();
+        assert_eq!(QueryExecutor::explain(&scan, &hive).lines(), vec![
+            "Full Scan on orders".to_string(),
+            "  Filter: customer = 'bob'".to_string(),
+        ]);
+    }
+    
+    #[test]
+    fn test_ranked_text_search() {
+        use crate::core::schema::{IndexType, Schema, SchemaIndex};
+        
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut hive = Hive::new(
+            "posts".to_string(),
+            "Search test".to_string(),
+            "test-user".to_string(),
+            temp_dir.path().to_path_buf(),
+            (16, 16),
+        ).unwrap();
+        
+        let mut schema = Schema::new("posts".to_string(), String::new(), "1".to_string());
+        schema.add_index(SchemaIndex::new("body_text".to_string(), vec!["body".to_string()], IndexType::FullText, false));
+        hive.set_schema(schema).unwrap();
+        
+        Query::new(QueryType::Insert, "posts".to_string())
+            .with_data(serde_json::json!([
+                { "_id": "p1", "title": "Bees", "body": "Bees build a hive from wax" },
+                { "_id": "p2", "title": "HiveDB", "body": "A hive database: the hive stores documents in hexagonal cells" },
+                { "_id": "p3", "title": "Honey", "body": "Honey keeps for years" },
+            ]))
+            .execute(&mut hive)
+            .unwrap();
+        
+        let search = crate::core::sql::parse("SELECT title, _score FROM posts WHERE SEARCH(body, 'hive')").unwrap();
+        assert_eq!(QueryExecutor::explain(&search, &hive).access, AccessPath::IndexScan);
+        let result = search.execute(&mut hive).unwrap();
+        let titles: Vec<&str> = result.results.iter().filter_map(|doc| doc["title"].as_str()).collect();
+        assert_eq!(titles, vec!["HiveDB", "Bees"]);
+        assert!(result.results[0][SCORE_FIELD].as_f64() > result.results[1][SCORE_FIELD].as_f64());
+        
+        // Fuzzy matching finds misspelled terms, and highlighting marks them
+        let fuzzy = Query::new(QueryType::Find, "posts".to_string())
+            .with_filter(FilterExpression::Search(
+                TextSearch::new("body".to_string(), "hony".to_string()).with_fuzziness(1)
+            ))
+            .with_highlight();
+        let result = fuzzy.execute(&mut hive).unwrap();
+        assert_eq!(result.count, 1);
+        assert_eq!(result.results[0][HIGHLIGHT_FIELD]["body"], "<em>Honey</em> keeps for years");
+        
+        // An explicit sort replaces ranking
+        let sorted = crate::core::sql::parse("SELECT title FROM posts WHERE SEARCH(body, 'hive') ORDER BY title").unwrap();
+        let result = sorted.execute(&mut hive).unwrap();
+        assert_eq!(result.results[0], serde_json::json!({ "_id": "p1", "title": "Bees" }));
+    }
+    
+    #[test]
+    fn test_distinct_and_top_k() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut hive = Hive::new(
+            "orders".to_string(),
+            "Top-k test".to_string(),
+            "test-user".to_string(),
+            temp_dir.path().to_path_buf(),
+            (16, 16),
+        ).unwrap();
+        
+        Query::new(QueryType::Insert, "orders".to_string())
+            .with_data(serde_json::json!([
+                { "_id": "o1", "city": "Cairo", "total": 40 },
+                { "_id": "o2", "city": "Giza", "total": 90 },
+                { "_id": "o3", "city": "Cairo", "total": 15 },
+                { "_id": "o4", "city": "Aswan", "total": 70 },
+                { "_id": "o5", "city": "Luxor", "total": 90 },
+            ]))
+            .execute(&mut hive)
+            .unwrap();
+        
+        let cities = crate::core::sql::parse("SELECT DISTINCT city FROM orders ORDER BY city DESC LIMIT 3").unwrap();
+        let result = cities.execute(&mut hive).unwrap();
+        assert_eq!(result.results, vec![
+            serde_json::json!({ "city": "Luxor" }),
+            serde_json::json!({ "city": "Giza" }),
+            serde_json::json!({ "city": "Cairo" }),
+        ]);
+        assert!(result.has_more);
+        
+        // Ties keep grid order, and skipped documents still count towards has_more
+        let top = Query::new(QueryType::Find, "orders".to_string())
+            .with_sort(vec![SortCriteria::new("total".to_string(), SortDirection::Descending)])
+            .with_skip(1)
+            .with_limit(2);
+        let result = top.execute(&mut hive).unwrap();
+        let ids: Vec<&str> = result.results.iter().filter_map(|doc| doc["_id"].as_str()).collect();
+        assert_eq!(ids, vec!["o5", "o4"]);
+        assert!(result.has_more);
     }
 }
@@ -0,0 +1,227 @@
+// HiveDB Remote Module
+//
+// This module keeps hive snapshots in an object store such as an S3 bucket,
+// so a server needs no state of its own: its data directory is only a
+// cache of the snapshots, filled from the store when the hives load and
+// written through to it on every save. A manifest object lists the hives
+// in the store. Like a data directory, a store (or a prefix of one) must be
+// used by a single server at a time.
+
+use std::path::Path;
+use std::sync::Arc;
+use crate::core::error::HiveError;
+use crate::core::hive::SNAPSHOT_FILE;
+use crate::core::snapshot;
+use crate::core::tiering::ObjectStore;
+use log::{debug, info};
+
+/// Key of the object listing the hives in a store
+pub const MANIFEST_KEY: &str = "hives.json";
+
+/// Hive snapshots kept in an object store
+#[derive(Clone)]
+pub struct SnapshotStore {
+    /// Where the snapshots are kept
+    store: Arc<dyn ObjectStore>,
+}
+
+impl std::fmt::Debug for SnapshotStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SnapshotStore").finish_non_exhaustive()
+    }
+}
+
+impl SnapshotStore {
+    /// Keep snapshots in an object store
+    pub fn new(store: impl ObjectStore + 'static) -> Self {
+        Self { store: Arc::new(store) }
+    }
+    
+    /// Get the storage directory names of the hives in the store
+    pub fn hives(&self) -> Result<Vec<String>, HiveError> {
+        match self.store.get(MANIFEST_KEY)? {
+            Some(data) => serde_json::from_slice(&data)
+                .map_err(|e| HiveError::DeserializationError(e.to_string())),
+            None => Ok(Vec::new()),
+        }
+    }
+    
+    /// Add hives to the manifest by their storage directories
+    pub fn add_hives(&self, dirs: &[&Path]) -> Result<(), HiveError> {
+        let mut hives = self.hives()?;
+        let count = hives.len();
+        for dir in dirs {
+            let name = dir_name(dir)?;
+            if !hives.contains(&name) {
+                hives.push(name);
+            }
+        }
+        
+        if hives.len() > count {
+            self.write_manifest(&hives)?;
+        }
+        Ok(())
+    }
+    
+    /// Remove a hive and its snapshot from the store
+    pub fn remove_hive(&self, dir: &Path) -> Result<(), HiveError> {
+        let name = dir_name(dir)?;
+        let mut hives = self.hives()?;
+        hives.retain(|hive| *hive != name);
+        self.write_manifest(&hives)?;
+        self.store.delete(&snapshot_key(&name))
+    }
+    
+    /// Store the snapshot of the hive kept in a storage directory
+    pub fn upload(&self, dir: &Path, data: &[u8]) -> Result<(), HiveError> {
+        let key = snapshot_key(&dir_name(dir)?);
+        self.store.put(&key, data)?;
+        debug!("Uploaded {} byte snapshot to '{}'", data.len(), key);
+        Ok(())
+    }
+    
+    /// Bring the snapshots in a data directory up to date with the store
+    ///
+    /// A cached snapshot newer than the stored one, left by a save that
+    /// could not upload, is uploaded instead. Returns the number of
+    /// snapshots downloaded.
+    pub fn pull(&self, base_path: &Path) -> Result<usize, HiveError> {
+        let mut downloaded = 0;
+        for name in self.hives()? {
+            let Some(remote) = self.store.get(&snapshot_key(&name))? else {
+                continue;
+            };
+            let dir = base_path.join(&name);
+            let path = dir.join(SNAPSHOT_FILE);
+            let local = std::fs::read(&path).ok();
+            
+            match local.as_deref().map(version) {
+                Some(Ok(local_version)) if local_version > version(&remote)? => {
+                    info!("Cached snapshot of hive '{}' is newer than the stored one; uploading it", name);
+                    self.upload(&dir, local.as_deref().unwrap_or_default())?;
+                }
+                _ if local.as_deref() == Some(&remote[..]) => {}
+                _ => {
+                    std::fs::create_dir_all(&dir)
+                        .and_then(|_| std::fs::write(&path, &remote))
+                        .map_err(|e| HiveError::IoError(e.to_string()))?;
+                    downloaded += 1;
+                }
+            }
+        }
+        
+        Ok(downloaded)
+    }
+    
+    /// Write the list of hives in the store
+    fn write_manifest(&self, hives: &[String]) -> Result<(), HiveError> {
+        let data = serde_json::to_vec(hives)
+            .map_err(|e| HiveError::SerializationError(e.to_string()))?;
+        self.store.put(MANIFEST_KEY, &data)
+    }
+}
+
+/// Get the key of the snapshot of a hive
+fn snapshot_key(name: &str) -> String {
+    format!("{}/{}", name, SNAPSHOT_FILE)
+}
+
+/// Get the name of a hive's storage directory, which names it in the store
+fn dir_name(dir: &Path) -> Result<String, HiveError> {
+    dir.file_name()
+        .and_then(|name| name.to_str())
+        .map(str::to_string)
+        .ok_or_else(|| HiveError::GenericError(format!("Hive directory {} has no name", dir.display())))
+}
+
+/// Get the hive version a snapshot was saved at
+fn version(data: &[u8]) -> Result<u64, HiveError> {
+    let (_, body) = snapshot::decode(data)?;
+    body.get("metadata")
+        .and_then(|metadata| metadata.get("version"))
+        .and_then(|version| version.as_u64())
+        .ok_or_else(|| HiveError::DeserializationError("Snapshot has no version".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use crate::core::cell::{Cell, CellDataType};
+    use crate::core::hive::HiveManager;
+    use tempfile::tempdir;
+    
+    #[derive(Clone, Default)]
+    struct MemoryStore(Arc<Mutex<HashMap<String, Vec<u8>>>>);
+    
+    impl ObjectStore for MemoryStore {
+        fn put(&self, key: &str, data: &[u8]) -> Result<(), HiveError> {
+            self.0.lock().unwrap().insert(key.to_string(), data.to_vec());
+            Ok(())
+        }
+        
+        fn get(&self, key: &str) -> Result<Option<Vec<u8>>, HiveError> {
+            Ok(self.0.lock().unwrap().get(key).cloned())
+        }
+        
+        fn delete(&self, key: &str) -> Result<(), HiveError> {
+            self.0.lock().unwrap().remove(key);
+            Ok(())
+        }
+    }
+    
+    #[test]
+    fn test_stateless_servers() {
+        let objects = MemoryStore::default();
+        let first_dir = tempdir().unwrap();
+        let mut first = HiveManager::new(first_dir.path().to_path_buf()).unwrap()
+            .with_snapshot_store(SnapshotStore::new(objects.clone()));
+        let id = first.create_hive("orders".to_string(), String::new(), "test-user".to_string(), (8, 8)).unwrap();
+        {
+            let hive_arc = first.get_hive(&id).unwrap();
+            let mut hive = hive_arc.write().unwrap();
+            hive.add_cell(Cell::new("o1".to_string(), (0, 0), CellDataType::Json, b"{}".to_vec(), false).unwrap()).unwrap();
+        }
+        first.save_all().unwrap();
+        drop(first);
+        
+        // A server with an empty data directory finds the hive in the store
+        let second_dir = tempdir().unwrap();
+        let mut second = HiveManager::new(second_dir.path().to_path_buf()).unwrap()
+            .with_snapshot_store(SnapshotStore::new(objects.clone()));
+        second.load_all().unwrap();
+        let hive_arc = second.get_hive(&id).unwrap();
+        assert_eq!(hive_arc.read().unwrap().cell_count(), 1);
+        assert!(second_dir.path().join("orders").join(SNAPSHOT_FILE).is_file());
+        
+        // Deleting the hive removes it from the store
+        drop(hive_arc);
+        second.delete_hive(&id).unwrap();
+        let store = SnapshotStore::new(objects.clone());
+        assert!(store.hives().unwrap().is_empty());
+        assert_eq!(objects.0.lock().unwrap().len(), 1);
+    }
+    
+    #[test]
+    fn test_pull_keeps_newer_cache() {
+        let objects = MemoryStore::default();
+        let store = SnapshotStore::new(objects.clone());
+        let dir = tempdir().unwrap();
+        let snapshot = |version: u64| snapshot::encode(
+            format!(r#"{{"metadata": {{"version": {}}}}}"#, version).as_bytes(),
+            snapshot::SnapshotCodec::Json,
+        ).unwrap();
+        
+        let hive_dir = dir.path().join("orders");
+        store.add_hives(&[&hive_dir]).unwrap();
+        store.upload(&hive_dir, &snapshot(3)).unwrap();
+        assert_eq!(store.pull(dir.path()).unwrap(), 1);
+        assert_eq!(store.pull(dir.path()).unwrap(), 0);
+        
+        // A save that wrote the cache but not the store is not undone
+        std::fs::write(hive_dir.join(SNAPSHOT_FILE), snapshot(4)).unwrap();
+        assert_eq!(store.pull(dir.path()).unwrap(), 0);
+        assert_eq!(objects.get("orders/hive.json").unwrap(), Some(snapshot(4)));
+    }
+}
@@ -0,0 +1,396 @@
+// HiveDB Retention Module
+//
+// This module applies retention rules, which move the cells a hive no
+// longer needs at hand out of it: cells not modified for some time, or
+// carrying a tag, are archived to another hive or to a honeycomb archive
+// file and then removed. A hive keeps its rules in a property, so they
+// travel with its snapshots. The server applies them periodically and
+// publishes an event for every archive; a dry run only reports what
+// would be archived.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use crate::core::cell::Cell;
+use crate::core::error::HiveError;
+use crate::core::events::EventKind;
+use crate::core::hive::{sanitize_name, Hive, HiveManager};
+use crate::core::worker;
+use crate::utils::determinism;
+use log::info;
+
+/// Property of a hive holding its retention rules as JSON
+pub const RETENTION_PROPERTY: &str = "retention";
+
+/// Where archived cells go
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArchiveTarget {
+    /// Another hive of the same manager, created when rules are applied
+    Hive(String),
+    
+    /// A directory, where each archive is a new honeycomb archive file
+    Directory(PathBuf),
+}
+
+impl fmt::Display for ArchiveTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Hive(name) => write!(f, "hive '{}'", name),
+            Self::Directory(path) => write!(f, "directory {}", path.display()),
+        }
+    }
+}
+
+/// Which cells of a hive are archived, and where to
+///
+/// A cell must meet every condition the rule sets.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetentionRule {
+    /// Time without writes after which a cell is archived
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub older_than: Option<Duration>,
+    
+    /// Tag of the cells to archive
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    
+    /// Where the cells go
+    pub archive: ArchiveTarget,
+}
+
+/// What applying the rules of a hive archived, or would archive in a dry run
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RetentionReport {
+    /// Name of the hive
+    pub hive: String,
+    
+    /// Whether the cells were left in place
+    pub dry_run: bool,
+    
+    /// One archive for each rule that matched cells
+    pub archives: Vec<ArchiveReport>,
+}
+
+/// Cells archived by one rule
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArchiveReport {
+    /// Where the cells went
+    pub target: String,
+    
+    /// IDs of the cells
+    pub cells: Vec<String>,
+    
+    /// Stored bytes of the cells
+    pub bytes: usize,
+}
+
+impl RetentionRule {
+    /// Archive cells not modified for some time
+    pub fn older_than(age: Duration, archive: ArchiveTarget) -> Self {
+        Self {
+            older_than: Some(age),
+            tag: None,
+            archive,
+        }
+    }
+    
+    /// Archive cells carrying a tag
+    pub fn tagged(tag: &str, archive: ArchiveTarget) -> Self {
+        Self {
+            older_than: None,
+            tag: Some(tag.to_string()),
+            archive,
+        }
+    }
+    
+    /// Also require cells to carry a tag
+    pub fn with_tag(mut self, tag: &str) -> Self {
+        self.tag = Some(tag.to_string());
+        self
+    }
+    
+    /// Check whether a cell is archived by this rule
+    pub fn matches(&self, cell: &Cell, now: u64) -> bool {
+        let old = self.older_than
+            .is_none_or(|age| now.saturating_sub(cell.metadata.modified_at) >= age.as_secs());
+        let tagged = self.tag.as_ref().is_none_or(|tag| cell.metadata.tags.contains(tag));
+        old && tagged && !cell.is_continuation()
+    }
+}
+
+/// Get the retention rules of a hive
+pub fn rules(hive: &Hive) -> Result<Vec<RetentionRule>, HiveError> {
+    match hive.get_property(RETENTION_PROPERTY) {
+        Some(rules) => serde_json::from_str(rules).map_err(|e| HiveError::DeserializationError(e.to_string())),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Replace the retention rules of a hive
+///
+/// Every rule needs an age or a tag, and cannot archive into the hive itself.
+pub fn set_rules(hive: &mut Hive, rules: &[RetentionRule]) -> Result<(), HiveError> {
+    for rule in rules {
+        if rule.older_than.is_none() && rule.tag.is_none() {
+            return Err(HiveError::GenericError("A retention rule needs an age or a tag".to_string()));
+        }
+        if rule.archive == ArchiveTarget::Hive(hive.name.clone()) {
+            return Err(HiveError::GenericError(format!("Hive '{}' cannot archive cells into itself", hive.name)));
+        }
+    }
+    
+    if rules.is_empty() {
+        return hive.remove_property(RETENTION_PROPERTY);
+    }
+    let rules = serde_json::to_string(rules).map_err(|e| HiveError::SerializationError(e.to_string()))?;
+    hive.set_property(RETENTION_PROPERTY.to_string(), rules)
+}
+
+/// Create the hives the rules of any hive archive into, returning their names
+///
+/// Applying rules only needs the manager for reading, so run this first
+/// with it locked for writing.
+pub fn create_archive_hives(manager: &mut HiveManager) -> Result<Vec<String>, HiveError> {
+    let mut missing = Vec::new();
+    for (id, _) in manager.list_hives() {
+        let Some(hive_arc) = manager.get_hive(&id) else {
+            continue;
+        };
+        let (rules, dimensions) = {
+            let hive = hive_arc.read().map_err(|_| HiveError::LockError)?;
+            (rules(&hive)?, hive.cells.dimensions())
+        };
+        for rule in rules {
+            if let ArchiveTarget::Hive(name) = rule.archive {
+                if manager.get_hive_by_name(&name).is_none() && !missing.iter().any(|(missing, _)| *missing == name) {
+                    missing.push((name, dimensions));
+                }
+            }
+        }
+    }
+    
+    let mut created = Vec::with_capacity(missing.len());
+    for (name, dimensions) in missing {
+        manager.create_hive(name.clone(), "Archived cells".to_string(), "hivedb".to_string(), dimensions)?;
+        created.push(name);
+    }
+    Ok(created)
+}
+
+/// Apply the retention rules of every hive
+///
+/// Between hives, archiving pauses to stay under the background rate limit.
+pub fn apply_all(manager: &HiveManager, dry_run: bool) -> Result<Vec<RetentionReport>, HiveError> {
+    let mut reports = Vec::new();
+    for (id, _) in manager.list_hives() {
+        let report = apply(manager, &id, dry_run)?;
+        if !dry_run {
+            worker::throttle(report.archives.iter().map(|archive| archive.bytes as u64).sum());
+        }
+        if !report.archives.is_empty() {
+            reports.push(report);
+        }
+    }
+    Ok(reports)
+}
+
+/// Apply the retention rules of a hive, given by ID
+///
+/// Cells are written to their archive before they are removed from the
+/// hive, so a failed archive leaves them in place. A cell matched by
+/// several rules goes to the first one's archive. Archive hives must
+/// exist, except for a dry run; see `create_archive_hives`.
+pub fn apply(manager: &HiveManager, hive_id: &str, dry_run: bool) -> Result<RetentionReport, HiveError> {
+    let hive_arc = manager.get_hive(hive_id).ok_or(HiveError::HiveNotFound)?;
+    let now = determinism::unix_time();
+    
+    // Archive hives are looked up first, as that locks every hive for reading
+    let rules = rules(&*hive_arc.read().map_err(|_| HiveError::LockError)?)?;
+    let mut targets = Vec::with_capacity(rules.len());
+    for rule in &rules {
+        let target = match &rule.archive {
+            ArchiveTarget::Hive(name) => manager.get_hive_by_name(name),
+            ArchiveTarget::Directory(_) => None,
+        };
+        if target.as_ref().is_some_and(|target| Arc::ptr_eq(target, &hive_arc)) {
+            return Err(HiveError::GenericError(format!("Hive cannot archive cells into itself ({})", rule.archive)));
+        }
+        targets.push(target);
+    }
+    
+    let mut hive = hive_arc.write().map_err(|_| HiveError::LockError)?;
+    let mut report = RetentionReport {
+        hive: hive.name.clone(),
+        dry_run,
+        archives: Vec::new(),
+    };
+    let mut taken = HashSet::new();
+    for (rule, target) in rules.iter().zip(targets) {
+        let mut matched = Vec::new();
+        let mut bytes = 0;
+        for cell_arc in hive.cells.all_cells() {
+            let cell = cell_arc.read().map_err(|_| HiveError::LockError)?;
+            if rule.matches(&cell, now) && taken.insert(cell.coordinates) {
+                matched.push((cell.coordinates, cell.id.clone()));
+                bytes += cell.metadata.size_bytes;
+            }
+        }
+        if matched.is_empty() {
+            continue;
+        }
+        matched.sort();
+        
+        if !dry_run {
+            let coordinates: Vec<_> = matched.iter().map(|(coordinates, _)| *coordinates).collect();
+            match &rule.archive {
+                ArchiveTarget::Hive(name) => {
+                    let target_arc = target
+                        .ok_or_else(|| HiveError::GenericError(format!("Archive hive '{}' does not exist", name)))?;
+                    let mut target = target_arc.write().map_err(|_| HiveError::LockError)?;
+                    archive_to_hive(&hive, &coordinates, &mut target)?;
+                    target.commit()?;
+                }
+                ArchiveTarget::Directory(path) => archive_to_file(&hive, &coordinates, path, now)?,
+            }
+            for coordinates in coordinates {
+                hive.remove_cell(coordinates)?;
+            }
+            hive.commit()?;
+            
+            info!("Archived {} cells ({} bytes) of hive '{}' to {}", matched.len(), bytes, hive.name, rule.archive);
+            manager.events().publish(Some(hive_id), EventKind::CellsArchived {
+                cells: matched.len(),
+                bytes,
+                target: rule.archive.to_string(),
+            });
+        }
+        report.archives.push(ArchiveReport {
+            target: rule.archive.to_string(),
+            cells: matched.into_iter().map(|(_, id)| id).collect(),
+            bytes,
+        });
+    }
+    Ok(report)
+}
+
+/// Copy a cell with its content and metadata, to new coordinates
+fn copy_cell(cell: &Cell, coordinates: (i32, i32)) -> Result<Cell, HiveError> {
+    let content = cell.get_content()?;
+    let mut copy = Cell::new(cell.id.clone(), coordinates, cell.data.data_type.clone(), content.to_vec(), cell.data.is_compressed)?;
+    copy.metadata.created_at = cell.metadata.created_at;
+    copy.metadata.modified_at = cell.metadata.modified_at;
+    copy.metadata.tags = cell.metadata.tags.clone();
+    Ok(copy)
+}
+
+/// Copy cells of a hive into the free positions of another, replacing cells with the same IDs
+fn archive_to_hive(hive: &Hive, coordinates: &[(i32, i32)], target: &mut Hive) -> Result<(), HiveError> {
+    for coordinates in coordinates {
+        let cell_arc = hive.get_cell(*coordinates).ok_or(HiveError::CellNotFound)?;
+        let cell = cell_arc.read().map_err(|_| HiveError::LockError)?;
+        
+        let existing = target.find_cell_by_id(&cell.id)
+            .map(|existing| existing.read().map(|existing| existing.coordinates).map_err(|_| HiveError::LockError))
+            .transpose()?;
+        if let Some(existing) = existing {
+            target.remove_cell(existing)?;
+        }
+        let free = target.free_coordinates().ok_or(HiveError::OutOfBoundsError)?;
+        target.add_cell(copy_cell(&cell, free)?)?;
+    }
+    Ok(())
+}
+
+/// Write cells of a hive to a new honeycomb archive in a directory
+///
+/// The archive holds a hive of the same name, schema and grid with only
+/// these cells, so `hivedb import` can bring them back.
+fn archive_to_file(hive: &Hive, coordinates: &[(i32, i32)], directory: &Path, now: u64) -> Result<(), HiveError> {
+    std::fs::create_dir_all(directory)
+        .map_err(|e| HiveError::IoError(format!("{}: {}", directory.display(), e)))?;
+    let (width, height) = hive.cells.dimensions();
+    let mut builder = Hive::builder()
+        .name(hive.name.clone())
+        .description(format!("Cells archived from hive '{}'", hive.name))
+        .owner(hive.metadata.owner.clone())
+        .storage_path(directory)
+        .dimensions(width, height);
+    if let Some(schema) = &hive.schema {
+        builder = builder.schema(schema.clone());
+    }
+    let mut archive = builder.build()?;
+    archive.cells.set_origin(hive.cells.origin())?;
+    
+    for coordinates in coordinates {
+        let cell_arc = hive.get_cell(*coordinates).ok_or(HiveError::CellNotFound)?;
+        let cell = cell_arc.read().map_err(|_| HiveError::LockError)?;
+        archive.add_cell(copy_cell(&cell, *coordinates)?)?;
+    }
+    
+    archive.export_comb(&directory.join(format!("{}-{}.comb", sanitize_name(&hive.name), now)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::cell::CellDataType;
+    use tempfile::tempdir;
+    
+    #[test]
+    fn test_apply_retention() {
+        let (_guard, clock) = determinism::deterministic(3);
+        let dir = tempdir().unwrap();
+        let mut manager = HiveManager::new(dir.path().join("hives")).unwrap();
+        let id = manager.create_hive("events".to_string(), String::new(), "test".to_string(), (8, 8)).unwrap();
+        let events = manager.events().subscribe().unwrap();
+        {
+            let hive_arc = manager.get_hive(&id).unwrap();
+            let mut hive = hive_arc.write().unwrap();
+            hive.add_cell(Cell::new("old".to_string(), (0, 0), CellDataType::Binary, vec![1; 8], false).unwrap()).unwrap();
+            clock.advance(Duration::from_secs(40 * 24 * 60 * 60));
+            hive.add_cell(Cell::new("new".to_string(), (1, 0), CellDataType::Binary, vec![2; 8], false).unwrap()).unwrap();
+            let mut tagged = Cell::new("tagged".to_string(), (2, 0), CellDataType::Binary, vec![3; 8], false).unwrap();
+            tagged.add_tag("temporary".to_string());
+            hive.add_cell(tagged).unwrap();
+            
+            let month = Duration::from_secs(30 * 24 * 60 * 60);
+            assert!(set_rules(&mut hive, &[RetentionRule::tagged("x", ArchiveTarget::Hive("events".to_string()))]).is_err());
+            set_rules(&mut hive, &[
+                RetentionRule::older_than(month, ArchiveTarget::Hive("cold".to_string())),
+                RetentionRule::tagged("temporary", ArchiveTarget::Directory(dir.path().join("archive"))),
+            ]).unwrap();
+            assert_eq!(rules(&hive).unwrap().len(), 2);
+        }
+        assert_eq!(create_archive_hives(&mut manager).unwrap(), vec!["cold".to_string()]);
+        
+        // A dry run changes nothing
+        let report = apply(&manager, &id, true).unwrap();
+        assert_eq!(report.archives.len(), 2);
+        assert_eq!(report.archives[0].cells, vec!["old".to_string()]);
+        assert_eq!(report.archives[1].cells, vec!["tagged".to_string()]);
+        assert_eq!(manager.get_hive(&id).unwrap().read().unwrap().cell_count(), 3);
+        
+        let reports = apply_all(&manager, false).unwrap();
+        assert_eq!(reports.len(), 1);
+        let hive_arc = manager.get_hive(&id).unwrap();
+        let hive = hive_arc.read().unwrap();
+        assert_eq!(hive.cell_count(), 1);
+        assert!(hive.find_cell_by_id("new").is_some());
+        
+        let cold_arc = manager.get_hive_by_name("cold").unwrap();
+        let cold = cold_arc.read().unwrap();
+        let archived = cold.find_cell_by_id("old").unwrap();
+        assert_eq!(archived.read().unwrap().get_content().unwrap().to_vec(), vec![1; 8]);
+        let files: Vec<_> = std::fs::read_dir(dir.path().join("archive")).unwrap().collect();
+        assert_eq!(files.len(), 1);
+        
+        let archived: Vec<_> = events.try_iter()
+            .filter(|event| matches!(event.kind, EventKind::CellsArchived { .. }))
+            .collect();
+        assert_eq!(archived.len(), 2);
+    }
+}
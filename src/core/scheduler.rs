@@ -0,0 +1,368 @@
+// HiveDB Scheduler Module
+//
+// This module shares query execution fairly between tenants, the users
+// queries run as. A server can cap the queries running at once and the
+// queries each tenant may run at once; when a slot frees up it goes to the
+// waiting tenant with the fewest running queries, so a tenant with many
+// queued scans cannot starve one with a single query. Queries also run in
+// time slices: at each cancellation checkpoint a query that used up its
+// slice while others wait gives up its slot and queues again.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use crate::core::error::HiveError;
+use crate::utils::determinism;
+use log::debug;
+
+/// Default time a query runs before it yields to waiting queries
+pub const DEFAULT_TIME_SLICE: Duration = Duration::from_millis(100);
+
+/// Default time a query waits for a slot before it is rejected
+pub const DEFAULT_QUEUE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Limits on the queries run at once
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SchedulerConfig {
+    /// Queries running at once on the server (None if unlimited)
+    pub max_running: Option<usize>,
+    
+    /// Queries running at once for each tenant (None if unlimited)
+    pub max_per_tenant: Option<usize>,
+    
+    /// Time a query runs before it yields to waiting queries
+    pub time_slice: Duration,
+    
+    /// Time a query waits for a slot before it is rejected
+    pub queue_timeout: Duration,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self {
+            max_running: None,
+            max_per_tenant: None,
+            time_slice: DEFAULT_TIME_SLICE,
+            queue_timeout: DEFAULT_QUEUE_TIMEOUT,
+        }
+    }
+}
+
+/// Queries of one tenant, as reported to operators
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TenantUsage {
+    /// Name of the tenant
+    pub tenant: String,
+    
+    /// Queries holding a slot
+    pub running: usize,
+    
+    /// Queries waiting for a slot
+    pub waiting: usize,
+    
+    /// Times a query of the tenant gave up its slot at the end of a slice
+    pub yields: u64,
+    
+    /// Queries of the tenant rejected after waiting too long
+    pub rejected: u64,
+}
+
+/// Hands out query slots fairly between tenants
+#[derive(Debug)]
+pub struct QueryScheduler {
+    /// Limits on the queries run at once
+    config: SchedulerConfig,
+    
+    /// Running and waiting queries
+    state: Mutex<SchedulerState>,
+    
+    /// Signalled whenever a slot is released
+    released: Condvar,
+}
+
+/// Running and waiting queries of a scheduler
+#[derive(Debug, Default)]
+struct SchedulerState {
+    /// Queries by tenant
+    tenants: HashMap<String, TenantUsage>,
+    
+    /// Queries holding a slot
+    running: usize,
+    
+    /// Waiting queries in arrival order, by ticket and tenant
+    queue: Vec<(u64, String)>,
+    
+    /// Last ticket handed to a waiting query
+    last_ticket: u64,
+}
+
+impl SchedulerState {
+    /// Get the usage of a tenant, creating it if needed
+    fn tenant(&mut self, tenant: &str) -> &mut TenantUsage {
+        self.tenants.entry(tenant.to_string()).or_insert_with(|| TenantUsage {
+            tenant: tenant.to_string(),
+            running: 0,
+            waiting: 0,
+            yields: 0,
+            rejected: 0,
+        })
+    }
+    
+    /// Check whether a waiting query may take a slot now
+    ///
+    /// It may if there is a free slot, its tenant is under its own limit,
+    /// and no other eligible tenant has fewer running queries or, with as
+    /// many, queued earlier.
+    fn may_run(&self, config: &SchedulerConfig, ticket: u64) -> bool {
+        if config.max_running.is_some_and(|max| self.running >= max) {
+            return false;
+        }
+        
+        let running = |tenant: &str| self.tenants.get(tenant).map_or(0, |usage| usage.running);
+        let next = self.queue.iter()
+            .filter(|(_, tenant)| config.max_per_tenant.map_or(true, |max| running(tenant) < max))
+            .min_by_key(|(ticket, tenant)| (running(tenant), *ticket));
+        next.is_some_and(|(next, _)| *next == ticket)
+    }
+    
+    /// Remove a ticket from the queue
+    fn dequeue(&mut self, ticket: u64, tenant: &str) {
+        self.queue.retain(|(queued, _)| *queued != ticket);
+        self.tenant(tenant).waiting -= 1;
+    }
+}
+
+impl QueryScheduler {
+    /// Create a scheduler with the given limits
+    pub fn new(config: SchedulerConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(SchedulerState::default()),
+            released: Condvar::new(),
+        }
+    }
+    
+    /// Get the limits of this scheduler
+    pub fn config(&self) -> &SchedulerConfig {
+        &self.config
+    }
+    
+    /// Wait for a slot to run a query of a tenant
+    ///
+    /// Fails with `TenantBusy` if no slot is given within the queue timeout.
+    pub fn admit(self: &Arc<Self>, tenant: &str) -> Result<QuerySlot, HiveError> {
+        if !self.acquire(tenant, self.config.queue_timeout)? {
+            self.state.lock().map_err(|_| HiveError::LockError)?
+                .tenant(tenant).rejected += 1;
+            return Err(HiveError::TenantBusy(tenant.to_string()));
+        }
+        
+        Ok(QuerySlot {
+            scheduler: self.clone(),
+            tenant: tenant.to_string(),
+            epoch: determinism::monotonic(),
+            slice_start: AtomicU64::new(0),
+        })
+    }
+    
+    /// Get the queries of every tenant that has run any, ordered by name
+    pub fn usage(&self) -> Result<Vec<TenantUsage>, HiveError> {
+        let state = self.state.lock().map_err(|_| HiveError::LockError)?;
+        
+        let mut usage: Vec<TenantUsage> = state.tenants.values().cloned().collect();
+        usage.sort_by(|a, b| a.tenant.cmp(&b.tenant));
+        Ok(usage)
+    }
+    
+    /// Queue for a slot and take it once it is this query's turn
+    ///
+    /// Returns false, leaving the queue, if the turn does not come in time.
+    fn acquire(&self, tenant: &str, timeout: Duration) -> Result<bool, HiveError> {
+        let mut state = self.state.lock().map_err(|_| HiveError::LockError)?;
+        state.last_ticket += 1;
+        let ticket = state.last_ticket;
+        state.queue.push((ticket, tenant.to_string()));
+        state.tenant(tenant).waiting += 1;
+        
+        let deadline = Instant::now() + timeout;
+        while !state.may_run(&self.config, ticket) {
+            let now = Instant::now();
+            if now >= deadline {
+                state.dequeue(ticket, tenant);
+                
+                // The queue changed, so another waiter may be next now
+                self.released.notify_all();
+                return Ok(false);
+            }
+            state = self.released.wait_timeout(state, deadline - now)
+                .map_err(|_| HiveError::LockError)?
+                .0;
+        }
+        
+        state.dequeue(ticket, tenant);
+        state.tenant(tenant).running += 1;
+        state.running += 1;
+        Ok(true)
+    }
+    
+    /// Give back the slot of a query of a tenant
+    fn release(&self, tenant: &str) {
+        if let Ok(mut state) = self.state.lock() {
+            state.tenant(tenant).running -= 1;
+            state.running -= 1;
+        }
+        self.released.notify_all();
+    }
+    
+    /// Check whether any query is waiting for a slot
+    fn has_waiters(&self) -> bool {
+        self.state.lock().is_ok_and(|state| !state.queue.is_empty())
+    }
+}
+
+/// A slot held by a running query; released when dropped
+#[derive(Debug)]
+pub struct QuerySlot {
+    /// Scheduler the slot belongs to
+    scheduler: Arc<QueryScheduler>,
+    
+    /// Tenant running the query
+    tenant: String,
+    
+    /// Reference point of `slice_start`, on the monotonic clock
+    epoch: Duration,
+    
+    /// When the current slice started, in milliseconds since `epoch`
+    slice_start: AtomicU64,
+}
+
+impl QuerySlot {
+    /// Get the tenant running the query
+    pub fn tenant(&self) -> &str {
+        &self.tenant
+    }
+    
+    /// Yield the slot to waiting queries if the current slice is used up
+    ///
+    /// Called at the query's cancellation checkpoints. The query waits at
+    /// most one more slice for its turn and then takes a slot anyway, since
+    /// the queries it yielded to may be waiting for locks it holds.
+    pub fn checkpoint(&self) {
+        let slice = self.scheduler.config.time_slice;
+        let start = Duration::from_millis(self.slice_start.load(Ordering::Relaxed));
+        if self.elapsed().saturating_sub(start) < slice || !self.scheduler.has_waiters() {
+            return;
+        }
+        
+        debug!("Query of tenant '{}' used up its time slice, yielding", self.tenant);
+        self.scheduler.release(&self.tenant);
+        let acquired = self.scheduler.acquire(&self.tenant, slice).unwrap_or(false);
+        if let Ok(mut state) = self.scheduler.state.lock() {
+            let usage = state.tenant(&self.tenant);
+            usage.yields += 1;
+            if !acquired {
+                usage.running += 1;
+                state.running += 1;
+            }
+        }
+        self.slice_start.store(self.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+    
+    /// Get the time since the slot was given
+    fn elapsed(&self) -> Duration {
+        determinism::monotonic().saturating_sub(self.epoch)
+    }
+}
+
+impl Drop for QuerySlot {
+    fn drop(&mut self) {
+        self.scheduler.release(&self.tenant);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    
+    fn scheduler(max_running: usize, max_per_tenant: Option<usize>) -> Arc<QueryScheduler> {
+        Arc::new(QueryScheduler::new(SchedulerConfig {
+            max_running: Some(max_running),
+            max_per_tenant,
+            time_slice: Duration::from_millis(20),
+            queue_timeout: Duration::from_millis(200),
+        }))
+    }
+    
+    #[test]
+    fn test_tenant_limit() {
+        let scheduler = scheduler(4, Some(1));
+        let _alice = scheduler.admit("alice").unwrap();
+        assert!(matches!(scheduler.admit("alice"), Err(HiveError::TenantBusy(_))));
+        let _bob = scheduler.admit("bob").unwrap();
+        
+        let usage = scheduler.usage().unwrap();
+        assert_eq!(usage[0].running, 1);
+        assert_eq!(usage[0].rejected, 1);
+        assert_eq!(usage[1].tenant, "bob");
+    }
+    
+    #[test]
+    fn test_fair_admission() {
+        let scheduler = scheduler(2, None);
+        let first = scheduler.admit("heavy").unwrap();
+        let second = scheduler.admit("heavy").unwrap();
+        
+        // Another heavy query queues before the light one
+        let queued = scheduler.clone();
+        let heavy = thread::spawn(move || queued.admit("heavy").map(|_| ()));
+        while scheduler.usage().unwrap()[0].waiting == 0 {
+            thread::yield_now();
+        }
+        let queued = scheduler.clone();
+        let light = thread::spawn(move || queued.admit("light"));
+        while scheduler.usage().unwrap().len() < 2 {
+            thread::yield_now();
+        }
+        
+        // The light tenant runs nothing, so it gets the first free slot
+        drop(first);
+        let _light = light.join().unwrap().unwrap();
+        let usage = scheduler.usage().unwrap();
+        assert_eq!((usage[0].running, usage[0].waiting), (1, 1));
+        
+        drop(second);
+        heavy.join().unwrap().unwrap();
+    }
+    
+    #[test]
+    fn test_time_slices() {
+        let scheduler = scheduler(1, None);
+        let slot = scheduler.admit("scan").unwrap();
+        
+        // Nobody waits, so the slot is kept
+        slot.checkpoint();
+        assert_eq!(scheduler.usage().unwrap()[0].yields, 0);
+        
+        // A waiting query gets a turn while the scan yields
+        let queued = scheduler.clone();
+        let other = thread::spawn(move || {
+            let _slot = queued.admit("point").unwrap();
+            thread::sleep(Duration::from_millis(10));
+        });
+        while scheduler.usage().unwrap().len() < 2 {
+            thread::yield_now();
+        }
+        thread::sleep(Duration::from_millis(20));
+        slot.checkpoint();
+        other.join().unwrap();
+        
+        let usage = scheduler.usage().unwrap();
+        assert_eq!(usage[1].tenant, "scan");
+        assert_eq!(usage[1].yields, 1);
+        assert_eq!(usage[1].running, 1);
+        assert_eq!(usage[0].running, 0);
+    }
+}
@@ -1,12 +1,24 @@
 // HiveDB Schema Module
 //
 // This module defines the schema system for HiveDB, which allows
-// for structured data validation and organization.
+// for structured data validation and organization. Custom validation
+// rules name functions registered with the process-wide validator
+// registry, which receive the field value and the whole document.
 
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::collections::HashMap;
+use std::sync::{Arc, OnceLock, RwLock};
 use crate::core::error::HiveError;
 
+/// A custom validator, given the field value and the whole document
+///
+/// It returns a message describing the problem when the value is invalid.
+pub type Validator = Arc<dyn Fn(&Value, &Value) -> Result<(), String> + Send + Sync>;
+
+/// The validators registered in this process, by name
+static VALIDATORS: OnceLock<RwLock<HashMap<String, Validator>>> = OnceLock::new();
+
 /// Represents a schema for data in HiveDB
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Schema {
@@ -109,7 +121,7 @@ pub enum ValidationRule {
     /// Enumeration of allowed values
     Enum(Vec<String>),
     
-    /// Custom validation rule
+    /// Custom validation rule, naming a registered validator
     Custom(String),
 }
 
@@ -119,7 +131,7 @@ pub struct SchemaIndex {
     /// Name of this index
     pub name: String,
     
-    /// Fields included in this index
+    /// Fields included in this index, or expressions over them such as `lower(email)`
     pub fields: Vec<String>,
     
     /// Type of this index
@@ -127,6 +139,14 @@ pub struct SchemaIndex {
     
     /// Whether this index is unique
     pub unique: bool,
+    
+    /// Condition a document must meet to be indexed, in SQL syntax (e.g. `active = true`)
+    #[serde(default)]
+    pub predicate: Option<String>,
+    
+    /// Fields whose values are stored in the index without being part of its key
+    #[serde(default)]
+    pub include: Vec<String>,
 }
 
 /// Types of indexes
@@ -143,6 +163,9 @@ pub enum IndexType {
     
     /// Full-text search index
     FullText,
+    
+    /// Trigram index for substring and prefix patterns
+    Ngram,
 }
 
 impl Schema {
@@ -181,10 +204,11 @@ impl Schema {
     }
     
     /// Validate data against this schema
-    pub fn validate(&self, data: &serde_json::Value) -> Result<(), HiveError> {
-        // TODO: Implement schema validation
-        
-        Ok(())
+    ///
+    /// Fields missing from the data are only an error when required and
+    /// without a default. Nested objects are validated field by field.
+    pub fn validate(&self, data: &Value) -> Result<(), HiveError> {
+        validate_fields(&self.fields, data, data, "")
     }
     
     /// Get a field by name
@@ -229,6 +253,149 @@ impl SchemaField {
     }
 }
 
+/// Register a validator under a name, replacing any of the same name
+///
+/// Fields use it with `ValidationRule::Custom(name)`.
+pub fn register_validator<F>(name: &str, validator: F) -> Result<(), HiveError>
+where
+    F: Fn(&Value, &Value) -> Result<(), String> + Send + Sync + 'static,
+{
+    registry().write().map_err(|_| HiveError::LockError)?
+        .insert(name.to_string(), Arc::new(validator));
+    Ok(())
+}
+
+/// Remove a validator, returning whether it was registered
+pub fn unregister_validator(name: &str) -> Result<bool, HiveError> {
+    Ok(registry().write().map_err(|_| HiveError::LockError)?.remove(name).is_some())
+}
+
+/// Get a registered validator by name
+pub fn validator(name: &str) -> Result<Validator, HiveError> {
+    registry().read().map_err(|_| HiveError::LockError)?
+        .get(name)
+        .cloned()
+        .ok_or_else(|| HiveError::SchemaValidationError(format!("Validator '{}' is not registered", name)))
+}
+
+/// Get the registry of validators
+fn registry() -> &'static RwLock<HashMap<String, Validator>> {
+    VALIDATORS.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+/// Validate the fields of an object, naming them after `prefix`
+fn validate_fields(fields: &[SchemaField], object: &Value, document: &Value, prefix: &str) -> Result<(), HiveError> {
+    let Value::Object(object) = object else {
+        return Err(invalid(prefix, "expected an object"));
+    };
+    
+    for field in fields {
+        let path = if prefix.is_empty() { field.name.clone() } else { format!("{}.{}", prefix, field.name) };
+        match object.get(&field.name) {
+            None | Some(Value::Null) => {
+                if field.required && field.default_value.is_none() {
+                    return Err(invalid(&path, "is required"));
+                }
+            }
+            Some(value) => {
+                check_type(&field.field_type, value, document, &path)?;
+                for rule in &field.validation {
+                    check_rule(rule, value, document, &path)?;
+                }
+            }
+        }
+    }
+    
+    Ok(())
+}
+
+/// Check that a value has a field type
+fn check_type(field_type: &FieldType, value: &Value, document: &Value, path: &str) -> Result<(), HiveError> {
+    let valid = match (field_type, value) {
+        (FieldType::String | FieldType::Reference, Value::String(_)) => true,
+        (FieldType::Integer, Value::Number(number)) => number.is_i64() || number.is_u64(),
+        (FieldType::Float, Value::Number(_)) => true,
+        (FieldType::Boolean, Value::Bool(_)) => true,
+        (FieldType::DateTime, Value::String(_) | Value::Number(_)) => true,
+        (FieldType::Binary, Value::String(_) | Value::Array(_)) => true,
+        (FieldType::GeoPoint, Value::Array(items)) => items.len() == 2 && items.iter().all(|item| matches!(item, Value::Number(_))),
+        (FieldType::GeoPoint, Value::Object(point)) => {
+            matches!(point.get("lat"), Some(Value::Number(_))) && matches!(point.get("lon"), Some(Value::Number(_)))
+        }
+        (FieldType::Array(item_type), Value::Array(items)) => {
+            for (i, item) in items.iter().enumerate() {
+                check_type(item_type, item, document, &format!("{}[{}]", path, i))?;
+            }
+            true
+        }
+        (FieldType::Object(fields), Value::Object(_)) => {
+            validate_fields(fields, value, document, path)?;
+            true
+        }
+        (FieldType::Custom(_), _) => true,
+        _ => false,
+    };
+    
+    if valid {
+        Ok(())
+    } else {
+        Err(invalid(path, &format!("expected {:?}", field_type)))
+    }
+}
+
+/// Check a validation rule against a value
+///
+/// Rules that do not apply to the type of the value pass. Patterns are
+/// not checked, as HiveDB links no regular expression engine.
+fn check_rule(rule: &ValidationRule, value: &Value, document: &Value, path: &str) -> Result<(), HiveError> {
+    let length = match value {
+        Value::String(text) => Some(text.chars().count()),
+        Value::Array(items) => Some(items.len()),
+        _ => None,
+    };
+    let number = match value {
+        Value::Number(number) => number.as_f64(),
+        _ => None,
+    };
+    
+    match rule {
+        ValidationRule::MinLength(min) if length.is_some_and(|length| length < *min) => {
+            Err(invalid(path, &format!("is shorter than {}", min)))
+        }
+        ValidationRule::MaxLength(max) if length.is_some_and(|length| length > *max) => {
+            Err(invalid(path, &format!("is longer than {}", max)))
+        }
+        ValidationRule::MinValue(min) if number.is_some_and(|number| number < *min) => {
+            Err(invalid(path, &format!("is less than {}", min)))
+        }
+        ValidationRule::MaxValue(max) if number.is_some_and(|number| number > *max) => {
+            Err(invalid(path, &format!("is greater than {}", max)))
+        }
+        ValidationRule::Enum(values) => {
+            let text = match value {
+                Value::String(text) => text.clone(),
+                other => other.to_string(),
+            };
+            if values.contains(&text) {
+                Ok(())
+            } else {
+                Err(invalid(path, &format!("must be one of {}", values.join(", "))))
+            }
+        }
+        ValidationRule::Custom(name) => validator(name)?(value, document).map_err(|message| invalid(path, &message)),
+        _ => Ok(()),
+    }
+}
+
+/// Build the error for an invalid field
+fn invalid(path: &str, message: &str) -> HiveError {
+    if path.is_empty() {
+        HiveError::SchemaValidationError(format!("Document {}", message))
+    } else {
+        HiveError::SchemaValidationError(format!("Field '{}' {}", path, message))
+    }
+}
+
 impl SchemaIndex {
     /// Create a new schema index
     pub fn new(
@@ -242,8 +409,22 @@ impl SchemaIndex {
             fields,
             index_type,
             unique,
+            predicate: None,
+            include: Vec::new(),
         }
     }
+    
+    /// Only index the documents that meet a condition
+    pub fn with_predicate(mut self, predicate: String) -> Self {
+        self.predicate = Some(predicate);
+        self
+    }
+    
+    /// Store the values of extra fields in the index, so it can answer queries reading them
+    pub fn with_include(mut self, fields: Vec<String>) -> Self {
+        self.include = fields;
+        self
+    }
 }
 
 #[cfg(test)]
@@ -321,4 +502,37 @@ mod tests {
         assert_eq!(id_index.name, "id_index");
         assert_eq!(id_index.unique, true);
     }
+    
+    #[test]
+    fn test_validate_with_custom_validator() {
+        register_validator("matches_billing_currency", |value, document| {
+            if Some(value) == document.get("billing").and_then(|billing| billing.get("currency")) {
+                Ok(())
+            } else {
+                Err("does not match the billing currency".to_string())
+            }
+        }).unwrap();
+        
+        let mut schema = Schema::new("orders".to_string(), String::new(), "1".to_string());
+        schema.add_field(SchemaField::new("id".to_string(), String::new(), FieldType::String, true)
+            .with_validation(ValidationRule::MinLength(3)));
+        schema.add_field(SchemaField::new("quantity".to_string(), String::new(), FieldType::Integer, false)
+            .with_validation(ValidationRule::MinValue(1.0)));
+        schema.add_field(SchemaField::new("currency".to_string(), String::new(), FieldType::String, true)
+            .with_validation(ValidationRule::Custom("matches_billing_currency".to_string())));
+        
+        let order = serde_json::json!({"id": "o-1", "quantity": 2, "currency": "EUR", "billing": {"currency": "EUR"}});
+        schema.validate(&order).unwrap();
+        
+        let mismatched = serde_json::json!({"id": "o-1", "currency": "USD", "billing": {"currency": "EUR"}});
+        assert!(matches!(schema.validate(&mismatched), Err(HiveError::SchemaValidationError(_))));
+        assert!(schema.validate(&serde_json::json!({"id": "o", "currency": "EUR"})).is_err());
+        assert!(schema.validate(&serde_json::json!({"id": "o-1", "quantity": 0, "currency": "EUR"})).is_err());
+        assert!(schema.validate(&serde_json::json!({"id": 1, "currency": "EUR"})).is_err());
+        assert!(schema.validate(&serde_json::json!({"currency": "EUR"})).is_err());
+        
+        // Rules naming unregistered validators fail rather than pass
+        assert!(unregister_validator("matches_billing_currency").unwrap());
+        assert!(schema.validate(&order).is_err());
+    }
 }
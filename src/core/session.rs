@@ -0,0 +1,470 @@
+// HiveDB Session Module
+//
+// This module tracks the client sessions connected to a server and the
+// queries they are running, so that operators can see who is connected
+// and cancel queries that misbehave. With a query scheduler, each query
+// runs in a slot given to the user of its session. The operations each
+// user ran against each hive are counted, for usage-based chargeback and
+// to spot abusive clients.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use crate::core::error::HiveError;
+use crate::core::scheduler::{QueryScheduler, QuerySlot};
+use crate::utils::determinism;
+
+/// A connected client session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionInfo {
+    /// Server-assigned session ID
+    pub id: u64,
+    
+    /// User the client connected as
+    pub user: String,
+    
+    /// Client address
+    pub client: String,
+    
+    /// Protocol the client speaks (e.g. "postgresql")
+    pub protocol: String,
+    
+    /// Name of the hive the session is bound to
+    pub hive: String,
+    
+    /// When the session was opened (seconds since the UNIX epoch)
+    pub connected_at: u64,
+}
+
+/// A query that is currently running
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryInfo {
+    /// Server-assigned query ID
+    pub id: u64,
+    
+    /// Session running the query
+    pub session_id: u64,
+    
+    /// Query text as sent by the client
+    pub text: String,
+    
+    /// When the query started (seconds since the UNIX epoch)
+    pub started_at: u64,
+}
+
+/// Kinds of operations counted in usage statistics
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    /// A query that only reads
+    Read,
+    
+    /// A statement that modifies the hive
+    Write,
+}
+
+/// Operations a user ran against a hive since the server started
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UsageStats {
+    /// User that ran the operations
+    pub user: String,
+    
+    /// Name of the hive they ran against
+    pub hive: String,
+    
+    /// Number of reads
+    pub reads: u64,
+    
+    /// Number of writes
+    pub writes: u64,
+    
+    /// Documents returned by reads
+    pub rows_read: u64,
+    
+    /// Documents changed by writes
+    pub rows_written: u64,
+    
+    /// Bytes of documents returned by reads
+    pub bytes_read: u64,
+    
+    /// Bytes of data sent by writes
+    pub bytes_written: u64,
+    
+    /// When the user last ran an operation (seconds since the UNIX epoch)
+    pub last_seen: u64,
+}
+
+/// Flag checked by running queries to find out they were killed
+///
+/// Checking it is also where a query holding a scheduler slot yields to
+/// waiting queries once its time slice is used up.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    /// Whether cancellation was requested
+    cancelled: Arc<AtomicBool>,
+    
+    /// Scheduler slot the query runs in, if any
+    slot: Option<Arc<QuerySlot>>,
+}
+
+impl CancelToken {
+    /// Create a token that is not cancelled
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Request cancellation
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+    
+    /// Check whether cancellation was requested
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+    
+    /// Return `QueryCancelled` if cancellation was requested
+    pub fn check(&self) -> Result<(), HiveError> {
+        if self.is_cancelled() {
+            return Err(HiveError::QueryCancelled);
+        }
+        
+        if let Some(slot) = &self.slot {
+            slot.checkpoint();
+        }
+        Ok(())
+    }
+}
+
+/// Registry of the sessions and running queries of a server
+#[derive(Default)]
+pub struct SessionRegistry {
+    /// Open sessions by ID
+    sessions: Mutex<HashMap<u64, SessionInfo>>,
+    
+    /// Running queries by ID, with their cancel tokens
+    queries: Mutex<HashMap<u64, (QueryInfo, CancelToken)>>,
+    
+    /// Last assigned session or query ID
+    last_id: AtomicU64,
+    
+    /// Usage statistics by user and hive
+    usage: Mutex<HashMap<(String, String), UsageStats>>,
+    
+    /// Scheduler giving queries their slots, if queries are scheduled
+    scheduler: Option<Arc<QueryScheduler>>,
+}
+
+impl SessionRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Run every query in a slot of a scheduler, as the user of its session
+    pub fn with_scheduler(mut self, scheduler: Arc<QueryScheduler>) -> Self {
+        self.scheduler = Some(scheduler);
+        self
+    }
+    
+    /// Get the scheduler giving queries their slots, if any
+    pub fn scheduler(&self) -> Option<&Arc<QueryScheduler>> {
+        self.scheduler.as_ref()
+    }
+    
+    /// Register a new session; it is closed when the handle is dropped
+    pub fn open_session(
+        self: &Arc<Self>,
+        user: &str,
+        client: &str,
+        protocol: &str,
+        hive: &str,
+    ) -> Result<SessionHandle, HiveError> {
+        let id = self.last_id.fetch_add(1, Ordering::SeqCst) + 1;
+        
+        let info = SessionInfo {
+            id,
+            user: user.to_string(),
+            client: client.to_string(),
+            protocol: protocol.to_string(),
+            hive: hive.to_string(),
+            connected_at: now()?,
+        };
+        
+        self.sessions.lock().map_err(|_| HiveError::LockError)?
+            .insert(id, info);
+        
+        Ok(SessionHandle {
+            registry: self.clone(),
+            id,
+        })
+    }
+    
+    /// Get all open sessions, ordered by ID
+    pub fn sessions(&self) -> Result<Vec<SessionInfo>, HiveError> {
+        let sessions = self.sessions.lock().map_err(|_| HiveError::LockError)?;
+        
+        let mut sessions: Vec<SessionInfo> = sessions.values().cloned().collect();
+        sessions.sort_by_key(|session| session.id);
+        Ok(sessions)
+    }
+    
+    /// Get all running queries, ordered by ID
+    pub fn queries(&self) -> Result<Vec<QueryInfo>, HiveError> {
+        let queries = self.queries.lock().map_err(|_| HiveError::LockError)?;
+        
+        let mut queries: Vec<QueryInfo> = queries.values().map(|(info, _)| info.clone()).collect();
+        queries.sort_by_key(|query| query.id);
+        Ok(queries)
+    }
+    
+    /// Get the usage statistics of every user and hive, ordered by user then hive
+    pub fn usage(&self) -> Result<Vec<UsageStats>, HiveError> {
+        let usage = self.usage.lock().map_err(|_| HiveError::LockError)?;
+        
+        let mut usage: Vec<UsageStats> = usage.values().cloned().collect();
+        usage.sort_by(|a, b| (&a.user, &a.hive).cmp(&(&b.user, &b.hive)));
+        Ok(usage)
+    }
+    
+    /// Count an operation a user ran against a hive
+    pub fn record_usage(&self, user: &str, hive: &str, operation: Operation, rows: u64, bytes: u64) -> Result<(), HiveError> {
+        let mut usage = self.usage.lock().map_err(|_| HiveError::LockError)?;
+        
+        let stats = usage.entry((user.to_string(), hive.to_string())).or_insert_with(|| UsageStats {
+            user: user.to_string(),
+            hive: hive.to_string(),
+            ..UsageStats::default()
+        });
+        match operation {
+            Operation::Read => {
+                stats.reads += 1;
+                stats.rows_read += rows;
+                stats.bytes_read += bytes;
+            }
+            Operation::Write => {
+                stats.writes += 1;
+                stats.rows_written += rows;
+                stats.bytes_written += bytes;
+            }
+        }
+        stats.last_seen = now()?;
+        Ok(())
+    }
+    
+    /// Request cancellation of a running query
+    pub fn kill_query(&self, id: u64) -> Result<(), HiveError> {
+        let queries = self.queries.lock().map_err(|_| HiveError::LockError)?;
+        
+        let (_, token) = queries.get(&id).ok_or(HiveError::QueryNotFound(id))?;
+        token.cancel();
+        Ok(())
+    }
+    
+    fn close_session(&self, id: u64) {
+        if let Ok(mut sessions) = self.sessions.lock() {
+            sessions.remove(&id);
+        }
+        
+        // Anything the session left running is cancelled
+        if let Ok(queries) = self.queries.lock() {
+            for (info, token) in queries.values() {
+                if info.session_id == id {
+                    token.cancel();
+                }
+            }
+        }
+    }
+    
+    fn finish_query(&self, id: u64) {
+        if let Ok(mut queries) = self.queries.lock() {
+            queries.remove(&id);
+        }
+    }
+}
+
+/// An open session; closes itself when dropped
+pub struct SessionHandle {
+    /// Registry the session belongs to
+    registry: Arc<SessionRegistry>,
+    
+    /// ID of the session
+    id: u64,
+}
+
+impl SessionHandle {
+    /// Get the session ID
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+    
+    /// Get the registry the session belongs to
+    pub fn registry(&self) -> &Arc<SessionRegistry> {
+        &self.registry
+    }
+    
+    /// Count an operation of this session against its user and hive
+    pub fn record_usage(&self, operation: Operation, rows: u64, bytes: u64) -> Result<(), HiveError> {
+        let (user, hive) = self.registry.sessions.lock().map_err(|_| HiveError::LockError)?
+            .get(&self.id)
+            .map(|session| (session.user.clone(), session.hive.clone()))
+            .unwrap_or_default();
+        self.registry.record_usage(&user, &hive, operation, rows, bytes)
+    }
+    
+    /// Register a running query; it is removed when the handle is dropped
+    ///
+    /// With a scheduler, first waits for a slot for the session's user.
+    pub fn begin_query(&self, text: &str) -> Result<QueryHandle, HiveError> {
+        let mut token = CancelToken::new();
+        if let Some(scheduler) = &self.registry.scheduler {
+            let user = self.registry.sessions.lock().map_err(|_| HiveError::LockError)?
+                .get(&self.id)
+                .map(|session| session.user.clone())
+                .unwrap_or_default();
+            token.slot = Some(Arc::new(scheduler.admit(&user)?));
+        }
+        let id = self.registry.last_id.fetch_add(1, Ordering::SeqCst) + 1;
+        
+        let info = QueryInfo {
+            id,
+            session_id: self.id,
+            text: text.to_string(),
+            started_at: now()?,
+        };
+        
+        self.registry.queries.lock().map_err(|_| HiveError::LockError)?
+            .insert(id, (info, token.clone()));
+        
+        Ok(QueryHandle {
+            registry: self.registry.clone(),
+            id,
+            token,
+        })
+    }
+}
+
+impl Drop for SessionHandle {
+    fn drop(&mut self) {
+        self.registry.close_session(self.id);
+    }
+}
+
+/// A running query; unregisters itself when dropped
+pub struct QueryHandle {
+    /// Registry the query belongs to
+    registry: Arc<SessionRegistry>,
+    
+    /// ID of the query
+    id: u64,
+    
+    /// Token the query must check while it runs
+    token: CancelToken,
+}
+
+impl QueryHandle {
+    /// Get the query ID
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+    
+    /// Get the cancel token of the query
+    pub fn token(&self) -> &CancelToken {
+        &self.token
+    }
+}
+
+impl Drop for QueryHandle {
+    fn drop(&mut self) {
+        self.registry.finish_query(self.id);
+    }
+}
+
+fn now() -> Result<u64, HiveError> {
+    Ok(determinism::unix_time())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::core::scheduler::SchedulerConfig;
+    
+    #[test]
+    fn test_session_lifecycle() {
+        let registry = Arc::new(SessionRegistry::new());
+        
+        let session = registry.open_session("alice", "127.0.0.1:5000", "postgresql", "shop").unwrap();
+        assert_eq!(registry.sessions().unwrap().len(), 1);
+        
+        let query = session.begin_query("SELECT * FROM orders").unwrap();
+        let queries = registry.queries().unwrap();
+        assert_eq!(queries.len(), 1);
+        assert_eq!(queries[0].session_id, session.id());
+        
+        registry.kill_query(query.id()).unwrap();
+        assert!(matches!(query.token().check(), Err(HiveError::QueryCancelled)));
+        
+        drop(query);
+        assert!(registry.queries().unwrap().is_empty());
+        assert!(matches!(registry.kill_query(42), Err(HiveError::QueryNotFound(42))));
+        
+        drop(session);
+        assert!(registry.sessions().unwrap().is_empty());
+    }
+    
+    #[test]
+    fn test_closing_session_cancels_queries() {
+        let registry = Arc::new(SessionRegistry::new());
+        
+        let session = registry.open_session("bob", "127.0.0.1:5001", "postgresql", "shop").unwrap();
+        let query = session.begin_query("SELECT * FROM orders").unwrap();
+        let token = query.token().clone();
+        
+        drop(session);
+        assert!(token.is_cancelled());
+    }
+    
+    #[test]
+    fn test_usage_per_user_and_hive() {
+        let registry = Arc::new(SessionRegistry::new());
+        
+        let alice = registry.open_session("alice", "127.0.0.1:5000", "postgresql", "shop").unwrap();
+        let bob = registry.open_session("bob", "127.0.0.1:5001", "postgresql", "shop").unwrap();
+        alice.record_usage(Operation::Read, 10, 500).unwrap();
+        alice.record_usage(Operation::Read, 2, 100).unwrap();
+        alice.record_usage(Operation::Write, 1, 40).unwrap();
+        bob.record_usage(Operation::Write, 3, 90).unwrap();
+        registry.record_usage("alice", "logs", Operation::Read, 1, 10).unwrap();
+        
+        // Usage outlives the sessions
+        drop(alice);
+        let usage = registry.usage().unwrap();
+        assert_eq!(usage.iter().map(|stats| (stats.user.as_str(), stats.hive.as_str())).collect::<Vec<_>>(),
+            [("alice", "logs"), ("alice", "shop"), ("bob", "shop")]);
+        assert_eq!((usage[1].reads, usage[1].rows_read, usage[1].bytes_read), (2, 12, 600));
+        assert_eq!((usage[1].writes, usage[1].rows_written, usage[1].bytes_written), (1, 1, 40));
+        assert_eq!((usage[2].reads, usage[2].writes), (0, 1));
+    }
+    
+    #[test]
+    fn test_scheduled_queries() {
+        let scheduler = Arc::new(QueryScheduler::new(SchedulerConfig {
+            max_per_tenant: Some(1),
+            queue_timeout: Duration::from_millis(50),
+            ..SchedulerConfig::default()
+        }));
+        let registry = Arc::new(SessionRegistry::new().with_scheduler(scheduler.clone()));
+        
+        let alice = registry.open_session("alice", "127.0.0.1:5000", "postgresql", "shop").unwrap();
+        let bob = registry.open_session("bob", "127.0.0.1:5001", "postgresql", "shop").unwrap();
+        let query = alice.begin_query("SELECT * FROM orders").unwrap();
+        assert!(matches!(alice.begin_query("SELECT * FROM orders"), Err(HiveError::TenantBusy(_))));
+        let _other = bob.begin_query("SELECT * FROM orders").unwrap();
+        
+        // Finishing the query frees the user's slot
+        drop(query);
+        assert!(alice.begin_query("SELECT * FROM orders").is_ok());
+        assert_eq!(scheduler.usage().unwrap()[0].rejected, 1);
+    }
+}
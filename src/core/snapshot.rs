@@ -0,0 +1,205 @@
+// HiveDB Snapshot Module
+//
+// This module defines the file format of hive snapshots. A snapshot starts
+// with a header holding a magic number, the format version and the codec
+// of the body, and ends with a checksum of everything before it, so a
+// damaged file is told apart from one written by a newer release. Files
+// written before the header existed are read as format version 0, and the
+// bodies of older versions are migrated up to the current layout on load.
+
+use serde_json::Value;
+use crate::core::codec::{self, DIGEST_LEN};
+use crate::core::error::HiveError;
+
+/// Bytes every snapshot file starts with
+pub const MAGIC: [u8; 4] = *b"HIVE";
+
+/// Version of the snapshot format written by this release
+pub const FORMAT_VERSION: u16 = 1;
+
+/// Length of the header: magic, format version, codec and a reserved byte
+const HEADER_LEN: usize = 8;
+
+/// Length of the SHA-256 checksum ending the file
+const FOOTER_LEN: usize = DIGEST_LEN;
+
+/// Compression level of zstd snapshot bodies
+const LEVEL: i32 = 3;
+
+/// A migration upgrading a snapshot body by one format version
+type Migration = fn(&mut Value) -> Result<(), HiveError>;
+
+/// Migrations by the version they upgrade from, so entry `n` turns version `n` into `n + 1`
+const MIGRATIONS: [Migration; FORMAT_VERSION as usize] = [migrate_v0];
+
+/// How the body of a snapshot is encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SnapshotCodec {
+    /// Plain JSON
+    Json,
+    
+    /// JSON compressed with zstd
+    #[default]
+    Zstd,
+}
+
+/// What the header of a snapshot file says about it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SnapshotHeader {
+    /// Format version the file was written in
+    pub version: u16,
+    
+    /// Encoding of the body
+    pub codec: SnapshotCodec,
+}
+
+impl SnapshotCodec {
+    /// Get the byte naming this codec in a header
+    fn id(self) -> u8 {
+        match self {
+            SnapshotCodec::Json => 0,
+            SnapshotCodec::Zstd => 1,
+        }
+    }
+    
+    /// Get the codec named by a header byte
+    fn from_id(id: u8) -> Result<Self, HiveError> {
+        match id {
+            0 => Ok(SnapshotCodec::Json),
+            1 => Ok(SnapshotCodec::Zstd),
+            _ => Err(HiveError::DeserializationError(format!("Unknown snapshot codec {}", id))),
+        }
+    }
+}
+
+/// Encode a JSON snapshot body as a snapshot file in the current format
+pub fn encode(body: &[u8], codec: SnapshotCodec) -> Result<Vec<u8>, HiveError> {
+    let body = match codec {
+        SnapshotCodec::Json => body.to_vec(),
+        SnapshotCodec::Zstd => zstd::bulk::compress(body, LEVEL)
+            .map_err(|e| HiveError::CompressionError(e.to_string()))?,
+    };
+    
+    let mut data = Vec::with_capacity(HEADER_LEN + body.len() + FOOTER_LEN);
+    data.extend_from_slice(&MAGIC);
+    data.extend_from_slice(&FORMAT_VERSION.to_be_bytes());
+    data.push(codec.id());
+    data.push(0);
+    data.extend_from_slice(&body);
+    
+    let checksum = codec::sha256(&data);
+    data.extend_from_slice(checksum.as_ref());
+    Ok(data)
+}
+
+/// Decode a snapshot file, migrating its body to the current format
+pub fn decode(data: &[u8]) -> Result<(SnapshotHeader, Value), HiveError> {
+    let (header, body) = if data.starts_with(&MAGIC) {
+        read_framed(data)?
+    } else {
+        (SnapshotHeader { version: 0, codec: SnapshotCodec::Json }, data.to_vec())
+    };
+    
+    let mut body: Value = serde_json::from_slice(&body)
+        .map_err(|e| HiveError::DeserializationError(e.to_string()))?;
+    for migration in &MIGRATIONS[header.version as usize..] {
+        migration(&mut body)?;
+    }
+    
+    Ok((header, body))
+}
+
+/// Check the header and checksum of a snapshot file and decode its body
+fn read_framed(data: &[u8]) -> Result<(SnapshotHeader, Vec<u8>), HiveError> {
+    if data.len() < HEADER_LEN + FOOTER_LEN {
+        return Err(HiveError::DeserializationError("Snapshot is truncated".to_string()));
+    }
+    
+    let (framed, checksum) = data.split_at(data.len() - FOOTER_LEN);
+    if codec::sha256(framed).as_ref() != checksum {
+        return Err(HiveError::DeserializationError("Snapshot checksum does not match its content".to_string()));
+    }
+    
+    let version = u16::from_be_bytes([framed[4], framed[5]]);
+    if version > FORMAT_VERSION {
+        return Err(HiveError::DeserializationError(format!(
+            "Snapshot format version {} is newer than the supported version {}",
+            version, FORMAT_VERSION
+        )));
+    }
+    let header = SnapshotHeader { version, codec: SnapshotCodec::from_id(framed[6])? };
+    
+    let body = &framed[HEADER_LEN..];
+    let body = match header.codec {
+        SnapshotCodec::Json => body.to_vec(),
+        SnapshotCodec::Zstd => zstd::stream::decode_all(body)
+            .map_err(|e| HiveError::DecompressionError(e.to_string()))?,
+    };
+    
+    Ok((header, body))
+}
+
+/// Upgrade a headerless snapshot, whose body has the same layout as version 1
+fn migrate_v0(_body: &mut Value) -> Result<(), HiveError> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    
+    #[test]
+    fn test_snapshot_format() {
+        let body = br#"{"id": "h1", "cells": [{"id": "c1"}, {"id": "c2"}]}"#;
+        let expected: Value = serde_json::from_slice(body).unwrap();
+        
+        for codec in [SnapshotCodec::Json, SnapshotCodec::Zstd] {
+            let data = encode(body, codec).unwrap();
+            assert!(data.starts_with(&MAGIC));
+            let (header, decoded) = decode(&data).unwrap();
+            assert_eq!(header, SnapshotHeader { version: FORMAT_VERSION, codec });
+            assert_eq!(decoded, expected);
+        }
+        
+        // Snapshots from before the header existed are migrated
+        let (header, decoded) = decode(body).unwrap();
+        assert_eq!(header.version, 0);
+        assert_eq!(decoded, expected);
+        
+        // Damage and newer versions are both refused
+        let mut data = encode(body, SnapshotCodec::Json).unwrap();
+        data[HEADER_LEN + 3] ^= 0xff;
+        assert!(decode(&data).is_err());
+        
+        let mut data = encode(body, SnapshotCodec::Json).unwrap();
+        data[5] = FORMAT_VERSION as u8 + 1;
+        let footer = data.len() - FOOTER_LEN;
+        let checksum = codec::sha256(&data[..footer]);
+        data[footer..].copy_from_slice(checksum.as_ref());
+        assert!(decode(&data).unwrap_err().to_string().contains("newer"));
+    }
+    
+    proptest! {
+        #[test]
+        fn prop_snapshot_round_trip(
+            items in prop::collection::vec(any::<String>(), 0..16),
+            compressed in any::<bool>(),
+            flip in any::<prop::sample::Index>(),
+            mask in 1u8..=255,
+        ) {
+            let body = serde_json::to_vec(&serde_json::json!({ "items": items })).unwrap();
+            let codec = if compressed { SnapshotCodec::Zstd } else { SnapshotCodec::Json };
+            let data = encode(&body, codec).unwrap();
+            
+            let (header, decoded) = decode(&data).unwrap();
+            prop_assert_eq!(header, SnapshotHeader { version: FORMAT_VERSION, codec });
+            prop_assert_eq!(decoded, serde_json::from_slice::<Value>(&body).unwrap());
+            
+            // Any damaged byte is caught
+            let mut damaged = data.clone();
+            damaged[flip.index(data.len())] ^= mask;
+            prop_assert!(decode(&damaged).is_err());
+        }
+    }
+}
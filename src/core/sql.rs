@@ -0,0 +1,1023 @@
+// HiveDB SQL Module
+//
+// This module translates a small subset of SQL into HiveDB queries, so
+// that SQL-speaking front-ends can reuse the query executor. Supported:
+//
+//   SELECT * | COUNT(*) | [DISTINCT] col, ... FROM name [WHERE ...]
+//       [ORDER BY col [ASC|DESC] [NULLS FIRST|LAST], ...] [LIMIT n] [OFFSET n]
+//   INSERT INTO name [(col, ...)] VALUES (value, ...), ...
+//
+// WHERE accepts comparisons, LIKE, IN, IS [NOT] NULL, AND, OR, NOT and
+// parentheses; IN may take a one-column SELECT, whose FROM may name another
+// hive as hive.name; the left-hand side may apply lower, upper, trim or length
+// to a column. SEARCH(col, 'terms' [, fuzziness]) is a full-text search;
+// its results are ranked and carry _score, and selecting _highlight adds
+// snippets with the matched terms highlighted. Array columns are matched
+// with ANY_MATCH(col, condition), whose condition reads the fields of each
+// element (or _element for an element that is not an object),
+// CONTAINS_ALL(col, value, ...) and ARRAY_LENGTH(col) = n. INSERT without a
+// column list takes one JSON object string per row. EXPLAIN SELECT ...
+// describes how a query would run, and the administrative statements SHOW
+// SESSIONS, SHOW QUERIES, KILL QUERY <id>, ANALYZE [name], REINDEX INDEX
+// <name> and DROP INDEX <name> are also recognized. MATCH statements find
+// graph patterns along the edges between cells; see the graph module.
+// EXECUTE name [(value, ...)] runs a statement of the query allowlist,
+// whose $1, $2... parameters are bound with `bind_parameters`.
+
+use crate::core::error::HiveError;
+use crate::core::graph::{EdgeDirection, EdgePattern, GraphQuery, NodePattern};
+use crate::core::index::IndexExpression;
+use crate::core::text::{TextSearch, MAX_FUZZINESS};
+use crate::core::query::{
+    ArrayFilter, ComparisonOperator, FilterExpression, NullsOrder, Query, QueryType, SortCriteria, SortDirection, Subquery,
+};
+use crate::utils::telemetry;
+
+/// A lexical token
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// Bare or double-quoted identifier (bare ones may be keywords)
+    Ident(String, bool),
+    
+    /// Single-quoted string literal
+    Str(String),
+    
+    /// Numeric literal
+    Number(serde_json::Value),
+    
+    /// Punctuation or operator
+    Symbol(&'static str),
+}
+
+/// A parsed SQL statement
+#[derive(Debug, Clone)]
+pub enum Statement {
+    /// A query over documents
+    Query(Query),
+    
+    /// List the open sessions
+    ShowSessions,
+    
+    /// List the running queries
+    ShowQueries,
+    
+    /// Cancel the running query with the given ID
+    KillQuery(u64),
+    
+    /// Refresh the statistics of a collection, or of all collections
+    Analyze(Option<String>),
+    
+    /// Describe how a query would run
+    Explain(Query),
+    
+    /// Rebuild the index with the given name
+    Reindex(String),
+    
+    /// Drop the index with the given name
+    DropIndex(String),
+    
+    /// Find the matches of a graph pattern
+    Match(GraphQuery),
+    
+    /// Run a registered statement with the given parameters
+    Execute { name: String, params: Vec<serde_json::Value> },
+}
+
+/// Parse a single SQL statement into a query
+pub fn parse(sql: &str) -> Result<Query, HiveError> {
+    match parse_statement(sql)? {
+        Statement::Query(query) => Ok(query),
+        _ => Err(syntax_error("expected SELECT or INSERT")),
+    }
+}
+
+/// Parse a single SQL statement, including administrative statements
+pub fn parse_statement(sql: &str) -> Result<Statement, HiveError> {
+    let mut span = telemetry::span("hql.parse");
+    span.record(parse_tokens(sql))
+}
+
+/// Tokenize and parse a single statement
+fn parse_tokens(sql: &str) -> Result<Statement, HiveError> {
+    let mut parser = Parser {
+        tokens: tokenize(sql)?,
+        position: 0,
+    };
+    
+    let statement = if parser.accept_keyword("SELECT") {
+        Statement::Query(parser.select()?)
+    } else if parser.accept_keyword("INSERT") {
+        Statement::Query(parser.insert()?)
+    } else if parser.accept_keyword("SHOW") {
+        if parser.accept_keyword("SESSIONS") {
+            Statement::ShowSessions
+        } else if parser.accept_keyword("QUERIES") {
+            Statement::ShowQueries
+        } else {
+            return Err(syntax_error("expected SESSIONS or QUERIES"));
+        }
+    } else if parser.accept_keyword("KILL") {
+        parser.expect_keyword("QUERY")?;
+        Statement::KillQuery(parser.unsigned_integer()? as u64)
+    } else if parser.accept_keyword("EXPLAIN") {
+        parser.expect_keyword("SELECT")?;
+        Statement::Explain(parser.select()?)
+    } else if parser.accept_keyword("ANALYZE") {
+        match parser.peek() {
+            Some(Token::Ident(..)) => Statement::Analyze(Some(parser.identifier()?)),
+            _ => Statement::Analyze(None),
+        }
+    } else if parser.accept_keyword("REINDEX") {
+        parser.expect_keyword("INDEX")?;
+        Statement::Reindex(parser.identifier()?)
+    } else if parser.accept_keyword("DROP") {
+        parser.expect_keyword("INDEX")?;
+        Statement::DropIndex(parser.identifier()?)
+    } else if parser.accept_keyword("MATCH") {
+        Statement::Match(parser.graph_match()?)
+    } else if parser.accept_keyword("EXECUTE") {
+        let name = parser.identifier()?;
+        let mut params = Vec::new();
+        if parser.accept_symbol("(") {
+            loop {
+                params.push(parser.literal()?);
+                if !parser.accept_symbol(",") {
+                    break;
+                }
+            }
+            parser.expect_symbol(")")?;
+        }
+        Statement::Execute { name, params }
+    } else {
+        return Err(syntax_error("expected SELECT, INSERT, EXPLAIN, SHOW, KILL, ANALYZE, REINDEX, DROP, MATCH or EXECUTE"));
+    };
+    
+    parser.accept_symbol(";");
+    if parser.position < parser.tokens.len() {
+        return Err(syntax_error(&format!("unexpected {:?}", parser.tokens[parser.position])));
+    }
+    
+    Ok(statement)
+}
+
+/// Parse a WHERE condition on its own, such as the predicate of a partial index
+pub fn parse_condition(sql: &str) -> Result<FilterExpression, HiveError> {
+    let mut parser = Parser {
+        tokens: tokenize(sql)?,
+        position: 0,
+    };
+    
+    let condition = parser.expression()?;
+    if parser.position < parser.tokens.len() {
+        return Err(syntax_error(&format!("unexpected {:?}", parser.tokens[parser.position])));
+    }
+    
+    Ok(condition)
+}
+
+/// Split a string holding several statements on top-level semicolons
+pub fn split_statements(sql: &str) -> Vec<String> {
+    let mut statements = Vec::new();
+    let mut current = String::new();
+    let mut quote: Option<char> = None;
+    
+    for c in sql.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None if c == ';' => {
+                statements.push(std::mem::take(&mut current));
+                continue;
+            }
+            None => {}
+        }
+        current.push(c);
+    }
+    statements.push(current);
+    
+    statements
+        .into_iter()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// Get the number of parameters of a statement: the highest `$n` it uses
+pub fn parameter_count(sql: &str) -> usize {
+    parameters(sql).into_iter().map(|(_, n)| n).max().unwrap_or(0)
+}
+
+/// Replace the `$1`, `$2`... parameters of a statement with literals
+///
+/// Each value becomes exactly one literal token, so parameters can never
+/// change the shape of the statement. Arrays and objects are refused.
+pub fn bind_parameters(sql: &str, params: &[serde_json::Value]) -> Result<String, HiveError> {
+    let mut bound = String::with_capacity(sql.len());
+    let mut end = 0;
+    
+    for (range, n) in parameters(sql) {
+        let value = n.checked_sub(1)
+            .and_then(|index| params.get(index))
+            .ok_or_else(|| HiveError::QueryError(format!("no value for parameter ${}", n)))?;
+        let literal = match value {
+            serde_json::Value::Null => "NULL".to_string(),
+            serde_json::Value::Bool(flag) => if *flag { "TRUE" } else { "FALSE" }.to_string(),
+            serde_json::Value::Number(number) => number.to_string(),
+            serde_json::Value::String(text) => format!("'{}'", text.replace('\'', "''")),
+            _ => return Err(HiveError::QueryError(format!("parameter ${} must be a scalar", n))),
+        };
+        bound.push_str(&sql[end..range.start]);
+        bound.push_str(&literal);
+        end = range.end;
+    }
+    bound.push_str(&sql[end..]);
+    
+    Ok(bound)
+}
+
+/// Find the `$n` parameters outside quotes, with their byte ranges
+fn parameters(sql: &str) -> Vec<(std::ops::Range<usize>, usize)> {
+    let mut found = Vec::new();
+    let mut quote: Option<char> = None;
+    let mut chars = sql.char_indices().peekable();
+    
+    while let Some((start, c)) = chars.next() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None if c == '$' => {
+                let mut end = start + 1;
+                while let Some((index, digit)) = chars.peek().copied().filter(|(_, d)| d.is_ascii_digit()) {
+                    end = index + digit.len_utf8();
+                    chars.next();
+                }
+                if let Ok(n) = sql[start + 1..end].parse() {
+                    found.push((start..end, n));
+                }
+            }
+            None => {}
+        }
+    }
+    
+    found
+}
+
+fn syntax_error(message: &str) -> HiveError {
+    HiveError::QueryError(format!("SQL syntax error: {}", message))
+}
+
+fn tokenize(sql: &str) -> Result<Vec<Token>, HiveError> {
+    let chars: Vec<char> = sql.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    
+    while i < chars.len() {
+        let c = chars[i];
+        
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '\'' || c == '"' {
+            // Quotes are escaped by doubling them
+            let mut text = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    Some(&q) if q == c && chars.get(i + 1) == Some(&c) => {
+                        text.push(c);
+                        i += 2;
+                    }
+                    Some(&q) if q == c => {
+                        i += 1;
+                        break;
+                    }
+                    Some(&other) => {
+                        text.push(other);
+                        i += 1;
+                    }
+                    None => return Err(syntax_error("unterminated quoted string")),
+                }
+            }
+            tokens.push(if c == '\'' { Token::Str(text) } else { Token::Ident(text, true) });
+        } else if c.is_ascii_digit() || (c == '-' && chars.get(i + 1).map_or(false, |d| d.is_ascii_digit())) {
+            let start = i;
+            i += 1;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            let number = if text.contains('.') {
+                text.parse::<f64>().map(serde_json::Value::from)
+                    .map_err(|_| syntax_error(&format!("invalid number '{}'", text)))?
+            } else {
+                text.parse::<i64>().map(serde_json::Value::from)
+                    .map_err(|_| syntax_error(&format!("invalid number '{}'", text)))?
+            };
+            tokens.push(Token::Number(number));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            // Column names may be paths into nested documents, like items[0].sku;
+            // an unmatched ']' ends the name, as in the edge pattern [:FRIEND]
+            let mut depth = 0;
+            while i < chars.len() {
+                match chars[i] {
+                    '[' => depth += 1,
+                    ']' if depth > 0 => depth -= 1,
+                    c if c.is_alphanumeric() || c == '_' || c == '.' => {}
+                    _ => break,
+                }
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect(), false));
+        } else {
+            let two: String = chars[i..(i + 2).min(chars.len())].iter().collect();
+            let symbol = match two.as_str() {
+                "<=" => "<=",
+                ">=" => ">=",
+                "<>" | "!=" => "!=",
+                _ => match c {
+                    '(' => "(",
+                    ')' => ")",
+                    ',' => ",",
+                    '*' => "*",
+                    '=' => "=",
+                    '<' => "<",
+                    '>' => ">",
+                    ';' => ";",
+                    '-' => "-",
+                    '[' => "[",
+                    ']' => "]",
+                    ':' => ":",
+                    _ => return Err(syntax_error(&format!("unexpected character '{}'", c))),
+                },
+            };
+            i += symbol.len();
+            tokens.push(Token::Symbol(symbol));
+        }
+    }
+    
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+    
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+    
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(word, false)) if word.eq_ignore_ascii_case(keyword))
+    }
+    
+    fn accept_keyword(&mut self, keyword: &str) -> bool {
+        if self.peek_keyword(keyword) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+    
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), HiveError> {
+        if self.accept_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(syntax_error(&format!("expected {}", keyword)))
+        }
+    }
+    
+    fn accept_symbol(&mut self, symbol: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+    
+    fn expect_symbol(&mut self, symbol: &str) -> Result<(), HiveError> {
+        if self.accept_symbol(symbol) {
+            Ok(())
+        } else {
+            Err(syntax_error(&format!("expected '{}'", symbol)))
+        }
+    }
+    
+    fn identifier(&mut self) -> Result<String, HiveError> {
+        match self.next() {
+            Some(Token::Ident(name, _)) => Ok(name),
+            other => Err(syntax_error(&format!("expected identifier, found {:?}", other))),
+        }
+    }
+    
+    fn literal(&mut self) -> Result<serde_json::Value, HiveError> {
+        match self.next() {
+            Some(Token::Str(text)) => Ok(serde_json::Value::String(text)),
+            Some(Token::Number(number)) => Ok(number),
+            Some(Token::Ident(word, false)) if word.eq_ignore_ascii_case("TRUE") => Ok(serde_json::Value::Bool(true)),
+            Some(Token::Ident(word, false)) if word.eq_ignore_ascii_case("FALSE") => Ok(serde_json::Value::Bool(false)),
+            Some(Token::Ident(word, false)) if word.eq_ignore_ascii_case("NULL") => Ok(serde_json::Value::Null),
+            other => Err(syntax_error(&format!("expected literal, found {:?}", other))),
+        }
+    }
+    
+    fn unsigned_integer(&mut self) -> Result<usize, HiveError> {
+        match self.next() {
+            Some(Token::Number(number)) => number.as_u64()
+                .map(|n| n as usize)
+                .ok_or_else(|| syntax_error("expected a non-negative integer")),
+            _ => Err(syntax_error("expected a non-negative integer")),
+        }
+    }
+    
+    fn select(&mut self) -> Result<Query, HiveError> {
+        let mut query_type = QueryType::Find;
+        let mut projection = None;
+        let mut distinct = None;
+        
+        if self.accept_keyword("DISTINCT") {
+            let mut fields = vec![self.identifier()?];
+            while self.accept_symbol(",") {
+                fields.push(self.identifier()?);
+            }
+            distinct = Some(fields);
+        } else if self.accept_symbol("*") {
+            // All fields
+        } else if self.peek_keyword("COUNT") {
+            self.position += 1;
+            self.expect_symbol("(")?;
+            self.expect_symbol("*")?;
+            self.expect_symbol(")")?;
+            query_type = QueryType::Count;
+        } else {
+            let mut fields = vec![self.identifier()?];
+            while self.accept_symbol(",") {
+                fields.push(self.identifier()?);
+            }
+            projection = Some(fields);
+        }
+        
+        self.expect_keyword("FROM")?;
+        let mut query = Query::new(query_type, self.identifier()?);
+        query.projection = projection;
+        query.distinct = distinct;
+        
+        if self.accept_keyword("WHERE") {
+            query.filter = Some(self.expression()?);
+        }
+        
+        if self.accept_keyword("ORDER") {
+            self.expect_keyword("BY")?;
+            let mut criteria = Vec::new();
+            loop {
+                let field = self.identifier()?;
+                let direction = if self.accept_keyword("DESC") {
+                    SortDirection::Descending
+                } else {
+                    self.accept_keyword("ASC");
+                    SortDirection::Ascending
+                };
+                let mut criterion = SortCriteria::new(field, direction);
+                if self.accept_keyword("NULLS") {
+                    if self.accept_keyword("FIRST") {
+                        criterion = criterion.with_nulls(NullsOrder::First);
+                    } else {
+                        self.expect_keyword("LAST")?;
+                        criterion = criterion.with_nulls(NullsOrder::Last);
+                    }
+                }
+                criteria.push(criterion);
+                
+                if !self.accept_symbol(",") {
+                    break;
+                }
+            }
+            query.sort = Some(criteria);
+        }
+        
+        if self.accept_keyword("LIMIT") {
+            query.limit = Some(self.unsigned_integer()?);
+        }
+        if self.accept_keyword("OFFSET") {
+            query.skip = Some(self.unsigned_integer()?);
+        }
+        
+        Ok(query)
+    }
+    
+    fn insert(&mut self) -> Result<Query, HiveError> {
+        self.expect_keyword("INTO")?;
+        let target = self.identifier()?;
+        
+        let mut columns = Vec::new();
+        if self.accept_symbol("(") {
+            loop {
+                columns.push(self.identifier()?);
+                if !self.accept_symbol(",") {
+                    break;
+                }
+            }
+            self.expect_symbol(")")?;
+        }
+        
+        self.expect_keyword("VALUES")?;
+        let mut documents = Vec::new();
+        loop {
+            self.expect_symbol("(")?;
+            let mut values = vec![self.literal()?];
+            while self.accept_symbol(",") {
+                values.push(self.literal()?);
+            }
+            self.expect_symbol(")")?;
+            
+            documents.push(row_to_document(&columns, values)?);
+            
+            if !self.accept_symbol(",") {
+                break;
+            }
+        }
+        
+        Ok(Query::new(QueryType::Insert, target).with_data(serde_json::Value::Array(documents)))
+    }
+    
+    /// Parse the rest of `MATCH pattern [WHERE ...] [RETURN var, ...] [LIMIT n]`
+    fn graph_match(&mut self) -> Result<GraphQuery, HiveError> {
+        let mut query = GraphQuery::new(self.node_pattern()?);
+        loop {
+            // Arrows are split into symbols, so -[...]-> is '-' ... '-' '>'
+            let direction = if self.accept_symbol("<") {
+                self.expect_symbol("-")?;
+                EdgeDirection::Incoming
+            } else if self.accept_symbol("-") {
+                EdgeDirection::Outgoing
+            } else {
+                break;
+            };
+            
+            let mut edge = EdgePattern { variable: None, label: None, direction };
+            if self.accept_symbol("[") {
+                if matches!(self.peek(), Some(Token::Ident(..))) {
+                    edge.variable = Some(self.identifier()?);
+                }
+                if self.accept_symbol(":") {
+                    edge.label = Some(self.identifier()?);
+                }
+                self.expect_symbol("]")?;
+            }
+            self.expect_symbol("-")?;
+            if direction == EdgeDirection::Outgoing {
+                self.expect_symbol(">")?;
+            }
+            
+            query = query.with_step(edge, self.node_pattern()?);
+        }
+        
+        if self.accept_keyword("WHERE") {
+            query = query.with_filter(self.expression()?);
+        }
+        if self.accept_keyword("RETURN") && !self.accept_symbol("*") {
+            let variables = query.variables();
+            let mut returns = Vec::new();
+            loop {
+                let name = self.identifier()?;
+                if !variables.contains(&name) {
+                    return Err(syntax_error(&format!("RETURN of unknown variable '{}'", name)));
+                }
+                returns.push(name);
+                if !self.accept_symbol(",") {
+                    break;
+                }
+            }
+            query = query.with_returns(returns);
+        }
+        if self.accept_keyword("LIMIT") {
+            query = query.with_limit(self.unsigned_integer()?);
+        }
+        
+        Ok(query)
+    }
+    
+    /// Parse a node of a graph pattern, `([variable][:collection])`
+    fn node_pattern(&mut self) -> Result<NodePattern, HiveError> {
+        self.expect_symbol("(")?;
+        let mut node = NodePattern { variable: None, collection: None };
+        if matches!(self.peek(), Some(Token::Ident(..))) {
+            node.variable = Some(self.identifier()?);
+        }
+        if self.accept_symbol(":") {
+            node.collection = Some(self.identifier()?);
+        }
+        self.expect_symbol(")")?;
+        
+        Ok(node)
+    }
+    
+    fn expression(&mut self) -> Result<FilterExpression, HiveError> {
+        let mut terms = vec![self.conjunction()?];
+        while self.accept_keyword("OR") {
+            terms.push(self.conjunction()?);
+        }
+        
+        Ok(if terms.len() == 1 { terms.remove(0) } else { FilterExpression::Or(terms) })
+    }
+    
+    fn conjunction(&mut self) -> Result<FilterExpression, HiveError> {
+        let mut terms = vec![self.unary()?];
+        while self.accept_keyword("AND") {
+            terms.push(self.unary()?);
+        }
+        
+        Ok(if terms.len() == 1 { terms.remove(0) } else { FilterExpression::And(terms) })
+    }
+    
+    fn unary(&mut self) -> Result<FilterExpression, HiveError> {
+        if self.accept_keyword("NOT") {
+            return Ok(FilterExpression::Not(Box::new(self.unary()?)));
+        }
+        
+        if self.accept_symbol("(") {
+            let expression = self.expression()?;
+            self.expect_symbol(")")?;
+            return Ok(expression);
+        }
+        
+        self.predicate()
+    }
+    
+    /// Parse a column, or a function applied to one such as `lower(email)`
+    fn operand(&mut self) -> Result<String, HiveError> {
+        let name = self.identifier()?;
+        if !self.accept_symbol("(") {
+            return Ok(name);
+        }
+        
+        let field = self.identifier()?;
+        self.expect_symbol(")")?;
+        IndexExpression::parse(&format!("{}({})", name, field))
+            .map(|expression| expression.to_string())
+            .map_err(|e| syntax_error(&e.to_string()))
+    }
+    
+    /// Parse the arguments of `SEARCH(column, 'terms' [, fuzziness])`
+    fn search(&mut self) -> Result<FilterExpression, HiveError> {
+        let field = self.identifier()?;
+        self.expect_symbol(",")?;
+        let query = match self.next() {
+            Some(Token::Str(query)) => query,
+            _ => return Err(syntax_error("SEARCH expects a string of terms")),
+        };
+        
+        let mut search = TextSearch::new(field, query);
+        if self.accept_symbol(",") {
+            let fuzziness = self.unsigned_integer()?;
+            search = search.with_fuzziness(fuzziness.min(MAX_FUZZINESS as usize) as u8);
+        }
+        self.expect_symbol(")")?;
+        
+        Ok(FilterExpression::Search(search))
+    }
+    
+    /// Parse the arguments of `ANY_MATCH(column, condition)`, `CONTAINS_ALL(column, value, ...)`
+    /// or `ARRAY_LENGTH(column) = n`
+    fn array(&mut self, function: &str) -> Result<FilterExpression, HiveError> {
+        let field = self.identifier()?;
+        
+        let filter = match function {
+            "ANY_MATCH" => {
+                self.expect_symbol(",")?;
+                ArrayFilter::ElemMatch { field, filter: Box::new(self.expression()?) }
+            }
+            "CONTAINS_ALL" => {
+                let mut values = Vec::new();
+                while self.accept_symbol(",") {
+                    values.push(self.literal()?);
+                }
+                ArrayFilter::ContainsAll { field, values }
+            }
+            _ => {
+                self.expect_symbol(")")?;
+                self.expect_symbol("=")?;
+                let size = self.unsigned_integer()?;
+                return Ok(FilterExpression::Array(ArrayFilter::Size { field, size }));
+            }
+        };
+        self.expect_symbol(")")?;
+        
+        Ok(FilterExpression::Array(filter))
+    }
+    
+    /// Parse the rest of `IN (SELECT col FROM [hive.]name ...)`
+    fn subquery(&mut self) -> Result<Subquery, HiveError> {
+        let mut query = self.select()?;
+        let field = match query.projection.as_deref().or(query.distinct.as_deref()) {
+            Some([field]) => field.clone(),
+            _ => return Err(syntax_error("a subquery must select exactly one column")),
+        };
+        
+        let hive = match query.target.split_once('.') {
+            Some((hive, target)) => {
+                let hive = hive.to_string();
+                query.target = target.to_string();
+                Some(hive)
+            }
+            None => None,
+        };
+        
+        Ok(Subquery { hive, field, query })
+    }
+    
+    fn predicate(&mut self) -> Result<FilterExpression, HiveError> {
+        if self.peek_keyword("SEARCH") && matches!(self.tokens.get(self.position + 1), Some(Token::Symbol("("))) {
+            self.position += 2;
+            return self.search();
+        }
+        for function in ["ANY_MATCH", "CONTAINS_ALL", "ARRAY_LENGTH"] {
+            if self.peek_keyword(function) && matches!(self.tokens.get(self.position + 1), Some(Token::Symbol("("))) {
+                self.position += 2;
+                return self.array(function);
+            }
+        }
+        
+        let field = self.operand()?;
+        
+        if self.accept_keyword("IS") {
+            let negated = self.accept_keyword("NOT");
+            self.expect_keyword("NULL")?;
+            
+            let is_null = FilterExpression::Or(vec![
+                FilterExpression::Exists(field.clone(), false),
+                FilterExpression::Comparison(ComparisonOperator::Eq, field, serde_json::Value::Null),
+            ]);
+            return Ok(if negated { FilterExpression::Not(Box::new(is_null)) } else { is_null });
+        }
+        
+        let negated = self.accept_keyword("NOT");
+        
+        let expression = if self.accept_keyword("LIKE") {
+            match self.next() {
+                Some(Token::Str(pattern)) => FilterExpression::Pattern(field, pattern),
+                _ => return Err(syntax_error("LIKE expects a string pattern")),
+            }
+        } else if self.accept_keyword("IN") {
+            self.expect_symbol("(")?;
+            if self.accept_keyword("SELECT") {
+                let subquery = self.subquery()?;
+                self.expect_symbol(")")?;
+                return Ok(if negated {
+                    FilterExpression::Not(Box::new(FilterExpression::InQuery(field, Box::new(subquery))))
+                } else {
+                    FilterExpression::InQuery(field, Box::new(subquery))
+                });
+            }
+            let mut values = vec![self.literal()?];
+            while self.accept_symbol(",") {
+                values.push(self.literal()?);
+            }
+            self.expect_symbol(")")?;
+            FilterExpression::In(field, values)
+        } else if negated {
+            return Err(syntax_error("expected LIKE or IN after NOT"));
+        } else {
+            let op = match self.next() {
+                Some(Token::Symbol("=")) => ComparisonOperator::Eq,
+                Some(Token::Symbol("!=")) => ComparisonOperator::Ne,
+                Some(Token::Symbol("<")) => ComparisonOperator::Lt,
+                Some(Token::Symbol("<=")) => ComparisonOperator::Lte,
+                Some(Token::Symbol(">")) => ComparisonOperator::Gt,
+                Some(Token::Symbol(">=")) => ComparisonOperator::Gte,
+                other => return Err(syntax_error(&format!("expected comparison operator, found {:?}", other))),
+            };
+            FilterExpression::Comparison(op, field, self.literal()?)
+        };
+        
+        Ok(if negated { FilterExpression::Not(Box::new(expression)) } else { expression })
+    }
+}
+
+/// Turn one VALUES row into a document
+fn row_to_document(columns: &[String], mut values: Vec<serde_json::Value>) -> Result<serde_json::Value, HiveError> {
+    if columns.is_empty() {
+        return match values.as_slice() {
+            [serde_json::Value::String(json)] => {
+                let document: serde_json::Value = serde_json::from_str(json)
+                    .map_err(|e| syntax_error(&format!("invalid JSON document: {}", e)))?;
+                if document.is_object() {
+                    Ok(document)
+                } else {
+                    Err(syntax_error("JSON documents must be objects"))
+                }
+            }
+            _ => Err(syntax_error("without a column list, each row must be a single JSON string")),
+        };
+    }
+    
+    if columns.len() != values.len() {
+        return Err(syntax_error("VALUES row does not match the column list"));
+    }
+    
+    let mut document = serde_json::Map::new();
+    for (column, value) in columns.iter().zip(values.drain(..)) {
+        document.insert(column.clone(), value);
+    }
+    
+    Ok(serde_json::Value::Object(document))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_parse_select() {
+        let query = parse(
+            "SELECT name, age FROM users WHERE age >= 18 AND (city = 'Cairo' OR city = 'Giza') \
+             ORDER BY age DESC, name NULLS FIRST LIMIT 10 OFFSET 5;"
+        ).unwrap();
+        
+        assert_eq!(query.query_type, QueryType::Find);
+        assert_eq!(query.target, "users");
+        assert_eq!(query.projection, Some(vec!["name".to_string(), "age".to_string()]));
+        assert_eq!(query.limit, Some(10));
+        assert_eq!(query.skip, Some(5));
+        
+        let sort = query.sort.unwrap();
+        assert_eq!(sort.len(), 2);
+        assert_eq!(sort[0].direction, SortDirection::Descending);
+        assert_eq!(sort[1].direction, SortDirection::Ascending);
+        assert_eq!((sort[0].nulls, sort[1].nulls), (None, Some(NullsOrder::First)));
+        
+        match query.filter {
+            Some(FilterExpression::And(terms)) => {
+                assert_eq!(terms.len(), 2);
+                assert!(matches!(&terms[0], FilterExpression::Comparison(ComparisonOperator::Gte, f, _) if f == "age"));
+                assert!(matches!(&terms[1], FilterExpression::Or(inner) if inner.len() == 2));
+            }
+            other => panic!("Expected And filter, got {:?}", other),
+        }
+        
+        let query = parse("SELECT DISTINCT city, country FROM users").unwrap();
+        assert_eq!(query.distinct, Some(vec!["city".to_string(), "country".to_string()]));
+        assert_eq!(query.projection, None);
+    }
+    
+    #[test]
+    fn test_parse_count_and_predicates() {
+        let query = parse("select count(*) from users where name like 'A%' and id not in (1, 2) and email is not null").unwrap();
+        
+        assert_eq!(query.query_type, QueryType::Count);
+        match query.filter {
+            Some(FilterExpression::And(terms)) => {
+                assert!(matches!(&terms[0], FilterExpression::Pattern(f, p) if f == "name" && p == "A%"));
+                assert!(matches!(&terms[1], FilterExpression::Not(inner) if matches!(**inner, FilterExpression::In(_, _))));
+                assert!(matches!(&terms[2], FilterExpression::Not(_)));
+            }
+            other => panic!("Expected And filter, got {:?}", other),
+        }
+    }
+    
+    #[test]
+    fn test_parse_array_predicates() {
+        let query = parse(
+            "SELECT * FROM orders WHERE ANY_MATCH(items, sku = 'A1' AND qty > 2) \
+             AND contains_all(tags, 'gift', 'rush') AND ARRAY_LENGTH(items) = 2"
+        ).unwrap();
+        match query.filter {
+            Some(FilterExpression::And(terms)) => {
+                assert!(matches!(&terms[0], FilterExpression::Array(ArrayFilter::ElemMatch { field, filter })
+                    if field == "items" && matches!(**filter, FilterExpression::And(_))));
+                assert!(matches!(&terms[1], FilterExpression::Array(ArrayFilter::ContainsAll { values, .. }) if values.len() == 2));
+                assert!(matches!(&terms[2], FilterExpression::Array(ArrayFilter::Size { size: 2, .. })));
+                assert_eq!(terms[0].to_string(), "ANY_MATCH(items, sku = 'A1' AND qty > 2)");
+            }
+            other => panic!("Expected And filter, got {:?}", other),
+        }
+        
+        assert!(parse("SELECT * FROM orders WHERE ARRAY_LENGTH(items) > 2").is_err());
+        assert!(parse("SELECT * FROM orders WHERE ANY_MATCH(items)").is_err());
+    }
+    
+    #[test]
+    fn test_parse_search() {
+        let query = parse("SELECT title, _highlight FROM posts WHERE search(body, 'hive databse', 1) AND published = true").unwrap();
+        match query.filter {
+            Some(FilterExpression::And(terms)) => {
+                let expected = TextSearch::new("body".to_string(), "hive databse".to_string()).with_fuzziness(1);
+                assert!(matches!(&terms[0], FilterExpression::Search(search) if *search == expected));
+                assert_eq!(terms[0].to_string(), "SEARCH(body, 'hive databse', 1)");
+            }
+            other => panic!("Expected And filter, got {:?}", other),
+        }
+        
+        assert!(parse("SELECT * FROM posts WHERE SEARCH(body, 3)").is_err());
+        assert!(parse("SELECT * FROM posts WHERE SEARCH(body, 'x'").is_err());
+    }
+    
+    #[test]
+    fn test_parse_insert() {
+        let query = parse("INSERT INTO users (name, age, active) VALUES ('O''Brien', 40, true), ('Sara', 35, false)").unwrap();
+        
+        assert_eq!(query.query_type, QueryType::Insert);
+        assert_eq!(query.target, "users");
+        assert_eq!(query.data.as_ref().and_then(|d| d.as_array()).map(|a| a.len()), Some(2));
+        
+        assert!(parse("INSERT INTO users VALUES ('{\"name\": \"Omar\"}')").is_ok());
+        assert!(parse("INSERT INTO users (name) VALUES ('a', 'b')").is_err());
+    }
+    
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse("DROP TABLE users").is_err());
+        assert!(parse("SELECT * users").is_err());
+        assert!(parse("SELECT DISTINCT * FROM users").is_err());
+        assert!(parse("SELECT * FROM users WHERE").is_err());
+        assert!(parse("SELECT * FROM users LIMIT -1").is_err());
+    }
+    
+    #[test]
+    fn test_parse_admin_statements() {
+        assert!(matches!(parse_statement("SHOW SESSIONS").unwrap(), Statement::ShowSessions));
+        assert!(matches!(parse_statement("show queries;").unwrap(), Statement::ShowQueries));
+        assert!(matches!(parse_statement("KILL QUERY 17").unwrap(), Statement::KillQuery(17)));
+        assert!(parse_statement("KILL QUERY").is_err());
+        assert!(matches!(parse_statement("ANALYZE").unwrap(), Statement::Analyze(None)));
+        assert!(matches!(parse_statement("analyze orders;").unwrap(), Statement::Analyze(Some(name)) if name == "orders"));
+        assert!(matches!(parse_statement("EXPLAIN SELECT name FROM users WHERE age > 3").unwrap(), Statement::Explain(_)));
+        assert!(parse_statement("EXPLAIN INSERT INTO users VALUES ('{}')").is_err());
+        assert!(matches!(parse_statement("REINDEX INDEX by_email").unwrap(), Statement::Reindex(name) if name == "by_email"));
+        assert!(matches!(parse_statement("drop index \"By Email\";").unwrap(), Statement::DropIndex(name) if name == "By Email"));
+        assert!(parse_statement("DROP TABLE users").is_err());
+        assert!(parse("SHOW SESSIONS").is_err());
+        
+        assert!(matches!(parse_statement("EXECUTE recent_orders").unwrap(), Statement::Execute { name, params } if name == "recent_orders" && params.is_empty()));
+        assert!(matches!(
+            parse_statement("execute by_user('amira', 10, NULL);").unwrap(),
+            Statement::Execute { params, .. } if params == vec![serde_json::Value::from("amira"), serde_json::Value::from(10), serde_json::Value::Null]
+        ));
+        assert!(parse_statement("EXECUTE by_user(age)").is_err());
+    }
+    
+    #[test]
+    fn test_bind_parameters() {
+        let sql = "SELECT * FROM users WHERE name = $1 AND age > $2 AND note != '$3'";
+        assert_eq!(parameter_count(sql), 2);
+        
+        let params = [serde_json::Value::from("o'hara"), serde_json::Value::from(30)];
+        let bound = bind_parameters(sql, &params).unwrap();
+        assert_eq!(bound, "SELECT * FROM users WHERE name = 'o''hara' AND age > 30 AND note != '$3'");
+        assert!(parse(&bound).is_ok());
+        
+        // A value is one literal, whatever it holds
+        let params = [serde_json::Value::from("x' OR '1' = '1"), serde_json::Value::from(0)];
+        let query = parse(&bind_parameters(sql, &params).unwrap()).unwrap();
+        assert!(matches!(query.filter, Some(FilterExpression::And(_))));
+        
+        assert!(bind_parameters(sql, &params[..1]).is_err());
+        assert!(bind_parameters("SELECT * FROM users WHERE tags = $1", &[serde_json::Value::Array(vec![])]).is_err());
+    }
+    
+    #[test]
+    fn test_parse_match() {
+        let query = match parse_statement("MATCH (a:users)-[:FRIEND]->(b)<-[r]-() WHERE a.age > 30 RETURN a, r LIMIT 3").unwrap() {
+            Statement::Match(query) => query,
+            other => panic!("Expected MATCH, got {:?}", other),
+        };
+        assert_eq!(query.start.collection.as_deref(), Some("users"));
+        assert_eq!(query.steps.len(), 2);
+        assert_eq!(query.steps[0].0.label.as_deref(), Some("FRIEND"));
+        assert_eq!(query.steps[0].0.direction, EdgeDirection::Outgoing);
+        assert_eq!(query.steps[1].0.variable.as_deref(), Some("r"));
+        assert_eq!(query.steps[1].0.direction, EdgeDirection::Incoming);
+        assert_eq!(query.steps[1].1.variable, None);
+        assert!(matches!(query.filter, Some(FilterExpression::Comparison(ComparisonOperator::Gt, ref f, _)) if f == "a.age"));
+        assert_eq!(query.returns, Some(vec!["a".to_string(), "r".to_string()]));
+        assert_eq!(query.limit, Some(3));
+        
+        assert!(matches!(parse_statement("match (a)-->(b) return *").unwrap(), Statement::Match(q) if q.returns.is_none()));
+        assert!(parse_statement("MATCH (a)-[:FRIEND]-(b)").is_err());
+        assert!(parse_statement("MATCH (a)-->(b) RETURN c").is_err());
+        assert!(parse_statement("MATCH a-->b").is_err());
+        
+        // Brackets in column paths still belong to the name
+        let query = parse("SELECT * FROM orders WHERE items[0].qty < -1").unwrap();
+        assert!(matches!(query.filter, Some(FilterExpression::Comparison(ComparisonOperator::Lt, ref f, _)) if f == "items[0].qty"));
+    }
+    
+    #[test]
+    fn test_split_statements() {
+        assert_eq!(
+            split_statements("SELECT 1; SELECT ';' ;; "),
+            vec!["SELECT 1".to_string(), "SELECT ';'".to_string()]
+        );
+    }
+}
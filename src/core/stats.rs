@@ -0,0 +1,352 @@
+// HiveDB Stats Module
+//
+// This module computes statistics about a hive: how many cells it holds,
+// how much space they take in memory and on disk, how large its indexes
+// are, and how the cells are spread over the grid. A manager report adds
+// up the hives and the state of the process-wide caches, for dashboards.
+
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::core::cache::{self, CacheStats};
+use crate::core::cell::CellDataType;
+use crate::core::error::HiveError;
+use crate::core::hive::Hive;
+use crate::core::memory::{self, MemoryUsage};
+use crate::core::pheromone::TemperatureCounts;
+use crate::core::query::field_value;
+use crate::core::tiering::{self, TierStats};
+
+/// Hive property recording when the hive was last compacted
+pub const LAST_COMPACTION_PROPERTY: &str = "last_compaction";
+
+/// Maximum number of heat-map regions along each axis
+pub const HEAT_MAP_SIZE: (usize, usize) = (16, 8);
+
+/// Statistics about a single index
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexStats {
+    /// Name of the index
+    pub name: String,
+    
+    /// Indexed fields
+    pub fields: Vec<String>,
+    
+    /// Number of documents with a value for every indexed field
+    pub entries: usize,
+    
+    /// Total size of the indexed values, serialized as JSON
+    pub key_bytes: usize,
+}
+
+/// Statistics about a hive
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HiveStats {
+    /// ID of the hive
+    pub id: String,
+    
+    /// Name of the hive
+    pub name: String,
+    
+    /// Version of the hive
+    pub version: u64,
+    
+    /// Number of cells
+    pub cell_count: usize,
+    
+    /// Grid dimensions (width, height)
+    pub dimensions: (usize, usize),
+    
+    /// Size of the cell contents as stored (after compression)
+    pub stored_bytes: u64,
+    
+    /// Size of the cell contents after decompression
+    pub raw_bytes: u64,
+    
+    /// Total size of the files in the hive's storage directory
+    pub bytes_on_disk: u64,
+    
+    /// Statistics for each index of the schema
+    pub indexes: Vec<IndexStats>,
+    
+    /// Fraction of occupied cells in each region of the grid, row by row
+    pub occupancy: Vec<Vec<f64>>,
+    
+    /// When the hive was last modified (seconds since the UNIX epoch)
+    pub modified_at: u64,
+    
+    /// When the hive was last compacted, if ever
+    pub last_compaction: Option<u64>,
+    
+    /// Versions the hive is ahead of its last saved snapshot
+    pub unsaved_versions: u64,
+    
+    /// Cells by how often and how lately they were read
+    pub temperatures: TemperatureCounts,
+}
+
+/// Statistics about all the hives of a manager and the caches they share
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManagerStats {
+    /// Statistics of each hive, ordered by name
+    pub hives: Vec<HiveStats>,
+    
+    /// Cells in all hives
+    pub cell_count: usize,
+    
+    /// Size of the cell contents of all hives as stored
+    pub stored_bytes: u64,
+    
+    /// Size of the storage directories of all hives
+    pub bytes_on_disk: u64,
+    
+    /// Hives with changes not saved yet
+    pub unsaved_hives: usize,
+    
+    /// State of the parsed document cache
+    pub document_cache: CacheStats,
+    
+    /// Where cell reads were served from
+    pub tiering: TierStats,
+    
+    /// Memory accounted against the memory budget
+    pub memory: MemoryUsage,
+}
+
+impl ManagerStats {
+    /// Add up the statistics of hives with those of the process-wide caches
+    pub fn collect(mut hives: Vec<HiveStats>) -> Self {
+        hives.sort_by(|a, b| a.name.cmp(&b.name));
+        
+        Self {
+            cell_count: hives.iter().map(|hive| hive.cell_count).sum(),
+            stored_bytes: hives.iter().map(|hive| hive.stored_bytes).sum(),
+            bytes_on_disk: hives.iter().map(|hive| hive.bytes_on_disk).sum(),
+            unsaved_hives: hives.iter().filter(|hive| hive.unsaved_versions > 0).count(),
+            hives,
+            document_cache: cache::documents().stats(),
+            tiering: tiering::stats(),
+            memory: memory::global().usage(),
+        }
+    }
+}
+
+impl HiveStats {
+    /// Collect the statistics of a hive
+    ///
+    /// This reads and decompresses every cell, so it is proportional to
+    /// the size of the hive.
+    pub fn collect(hive: &Hive) -> Result<Self, HiveError> {
+        let dimensions = hive.cells.dimensions();
+        let (min, _) = hive.cells.bounds();
+        let indexes = hive.schema.as_ref().map(|schema| schema.indexes.clone()).unwrap_or_default();
+        let mut index_stats: Vec<IndexStats> = indexes.iter()
+            .map(|index| IndexStats {
+                name: index.name.clone(),
+                fields: index.fields.clone(),
+                entries: 0,
+                key_bytes: 0,
+            })
+            .collect();
+        
+        let regions = (HEAT_MAP_SIZE.0.min(dimensions.0).max(1), HEAT_MAP_SIZE.1.min(dimensions.1).max(1));
+        let mut occupied = vec![vec![0usize; regions.0]; regions.1];
+        
+        let mut stored_bytes = 0;
+        let mut raw_bytes = 0;
+        let mut temperatures = TemperatureCounts::default();
+        
+        for cell_arc in hive.cells.all_cells() {
+            let cell = cell_arc.read().map_err(|_| HiveError::LockError)?;
+            let content = cell.get_content()?;
+            
+            stored_bytes += cell.data.content.len() as u64;
+            raw_bytes += content.len() as u64;
+            if !cell.is_continuation() {
+                temperatures.add(cell.temperature());
+            }
+            
+            let (x, y) = ((cell.coordinates.0 - min.0).max(0) as usize, (cell.coordinates.1 - min.1).max(0) as usize);
+            let column = (x * regions.0 / dimensions.0.max(1)).min(regions.0 - 1);
+            let row = (y * regions.1 / dimensions.1.max(1)).min(regions.1 - 1);
+            occupied[row][column] += 1;
+            
+            if index_stats.is_empty() || cell.data.data_type != CellDataType::Json {
+                continue;
+            }
+            
+            let document: serde_json::Value = match serde_json::from_slice(&content) {
+                Ok(document) => document,
+                Err(_) => continue,
+            };
+            for stats in &mut index_stats {
+                let values: Option<Vec<&serde_json::Value>> = stats.fields.iter()
+                    .map(|field| field_value(&document, field))
+                    .collect();
+                if let Some(values) = values {
+                    stats.entries += 1;
+                    stats.key_bytes += values.iter().map(|value| value.to_string().len()).sum::<usize>();
+                }
+            }
+        }
+        
+        let occupancy = occupied.iter()
+            .enumerate()
+            .map(|(row, counts)| {
+                counts.iter()
+                    .enumerate()
+                    .map(|(column, count)| {
+                        let width = region_span(column, regions.0, dimensions.0);
+                        let height = region_span(row, regions.1, dimensions.1);
+                        *count as f64 / (width * height).max(1) as f64
+                    })
+                    .collect()
+            })
+            .collect();
+        
+        Ok(Self {
+            id: hive.id.clone(),
+            name: hive.name.clone(),
+            version: hive.metadata.version,
+            cell_count: hive.cell_count(),
+            dimensions,
+            stored_bytes,
+            raw_bytes,
+            bytes_on_disk: directory_size(&hive.storage_path)?,
+            indexes: index_stats,
+            occupancy,
+            modified_at: hive.modified_at,
+            last_compaction: hive.get_property(LAST_COMPACTION_PROPERTY).and_then(|value| value.parse().ok()),
+            unsaved_versions: hive.metadata.version.saturating_sub(hive.group_commit().saved_version()),
+            temperatures,
+        })
+    }
+    
+    /// Ratio of raw to stored bytes (1.0 when nothing is compressed)
+    pub fn compression_ratio(&self) -> f64 {
+        if self.stored_bytes == 0 {
+            1.0
+        } else {
+            self.raw_bytes as f64 / self.stored_bytes as f64
+        }
+    }
+    
+    /// Fraction of the grid's positions holding a cell
+    pub fn fill_factor(&self) -> f64 {
+        let capacity = self.dimensions.0 * self.dimensions.1;
+        if capacity == 0 {
+            0.0
+        } else {
+            self.cell_count as f64 / capacity as f64
+        }
+    }
+}
+
+/// Number of grid positions covered by a heat-map region along one axis
+fn region_span(region: usize, regions: usize, size: usize) -> usize {
+    let start = (region * size).div_ceil(regions);
+    let end = ((region + 1) * size).div_ceil(regions);
+    end - start
+}
+
+/// Total size of the files under a directory (0 if it does not exist)
+fn directory_size(path: &Path) -> Result<u64, HiveError> {
+    if !path.exists() {
+        return Ok(0);
+    }
+    
+    let mut total = 0;
+    let entries = std::fs::read_dir(path)
+        .map_err(|e| HiveError::IoError(e.to_string()))?;
+    for entry in entries {
+        let entry = entry.map_err(|e| HiveError::IoError(e.to_string()))?;
+        let metadata = entry.metadata()
+            .map_err(|e| HiveError::IoError(e.to_string()))?;
+        
+        total += if metadata.is_dir() {
+            directory_size(&entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+    
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::cell::Cell;
+    use crate::core::hive::HiveManager;
+    use crate::core::schema::{IndexType, Schema, SchemaIndex};
+    use tempfile::tempdir;
+    
+    #[test]
+    fn test_hive_stats() {
+        let temp_dir = tempdir().unwrap();
+        let mut hive = Hive::new(
+            "stats".to_string(),
+            "Statistics test".to_string(),
+            "test-user".to_string(),
+            temp_dir.path().to_path_buf(),
+            (4, 4),
+        ).unwrap();
+        
+        let mut schema = Schema::new("stats".to_string(), String::new(), "1".to_string());
+        schema.add_index(SchemaIndex {
+            name: "by_sku".to_string(),
+            fields: vec!["sku".to_string()],
+            index_type: IndexType::Hash,
+            unique: true,
+            predicate: None,
+            include: Vec::new(),
+        });
+        hive.set_schema(schema).unwrap();
+        
+        let content = br#"{"sku": "A-1", "note": "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"}"#;
+        hive.add_cell(Cell::new("c1".to_string(), (0, 0), CellDataType::Json, content.to_vec(), true).unwrap()).unwrap();
+        hive.add_cell(Cell::new("c2".to_string(), (1, 0), CellDataType::Json, br#"{"n": 1}"#.to_vec(), false).unwrap()).unwrap();
+        hive.set_property(LAST_COMPACTION_PROPERTY.to_string(), "1700000000".to_string()).unwrap();
+        hive.save().unwrap();
+        
+        let stats = hive.stats().unwrap();
+        assert_eq!(stats.cell_count, 2);
+        assert_eq!(stats.raw_bytes, (content.len() + 8) as u64);
+        assert!(stats.bytes_on_disk > 0);
+        assert_eq!(stats.indexes[0].entries, 1);
+        assert_eq!(stats.indexes[0].key_bytes, "\"A-1\"".len());
+        assert_eq!(stats.last_compaction, Some(1_700_000_000));
+        assert_eq!(stats.unsaved_versions, 0);
+        assert_eq!(stats.fill_factor(), 2.0 / 16.0);
+        
+        // A 4x4 grid gets one region per position
+        assert_eq!(stats.occupancy.len(), 4);
+        assert_eq!(stats.occupancy[0], vec![1.0, 1.0, 0.0, 0.0]);
+    }
+    
+    #[test]
+    fn test_manager_stats() {
+        let temp_dir = tempdir().unwrap();
+        let mut manager = HiveManager::new(temp_dir.path().to_path_buf()).unwrap();
+        let orders = manager.create_hive("orders".to_string(), String::new(), "test-user".to_string(), (8, 8)).unwrap();
+        manager.create_hive("accounts".to_string(), String::new(), "test-user".to_string(), (8, 8)).unwrap();
+        manager.save_all().unwrap();
+        
+        let hive_arc = manager.get_hive(&orders).unwrap();
+        hive_arc.write().unwrap()
+            .add_cell(Cell::new("o1".to_string(), (0, 0), CellDataType::Json, b"{}".to_vec(), false).unwrap())
+            .unwrap();
+        
+        let stats = manager.stats().unwrap();
+        assert_eq!(stats.hives[0].name, "accounts");
+        assert_eq!(stats.cell_count, 1);
+        assert_eq!(stats.unsaved_hives, 1);
+        assert!(stats.hives[1].unsaved_versions > 0);
+        assert!(stats.bytes_on_disk > 0);
+    }
+    
+    #[test]
+    fn test_region_span_covers_axis() {
+        let total: usize = (0..3).map(|region| region_span(region, 3, 10)).sum();
+        assert_eq!(total, 10);
+    }
+}
@@ -0,0 +1,415 @@
+// HiveDB Text Module
+//
+// This module provides full-text search over string fields. Text is split
+// into lowercase terms, and a full-text index keeps an inverted list of
+// the documents holding each term so matches can be ranked with BM25.
+// Query terms may also match terms within a small edit distance, and the
+// matched terms can be highlighted in a snippet of the original text.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use serde::{Deserialize, Serialize};
+use crate::core::query::field_value;
+
+/// BM25 term frequency saturation
+pub const BM25_K1: f64 = 1.2;
+
+/// BM25 document length normalization
+pub const BM25_B: f64 = 0.75;
+
+/// Largest edit distance a search may allow
+pub const MAX_FUZZINESS: u8 = 2;
+
+/// Number of words in a highlighted snippet
+pub const SNIPPET_WORDS: usize = 12;
+
+/// Number of words shown before the first match in a snippet
+const SNIPPET_LEAD: usize = 3;
+
+/// Markers placed around matched terms in a snippet
+const HIGHLIGHT_START: &str = "<em>";
+const HIGHLIGHT_END: &str = "</em>";
+
+/// A full-text search on one field
+///
+/// A document matches if its field holds any of the query terms. With a
+/// fuzziness above zero, terms within that many edits also match; short
+/// terms always need fewer edits (none up to 2 characters, one up to 5).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TextSearch {
+    /// Field holding the text
+    pub field: String,
+    
+    /// Words to search for
+    pub query: String,
+    
+    /// Edits allowed between a query term and a matching term
+    pub fuzziness: u8,
+}
+
+impl TextSearch {
+    /// Create an exact search for the words of a query
+    pub fn new(field: String, query: String) -> Self {
+        Self {
+            field,
+            query,
+            fuzziness: 0,
+        }
+    }
+    
+    /// Allow terms within a number of edits to match (at most `MAX_FUZZINESS`)
+    pub fn with_fuzziness(mut self, fuzziness: u8) -> Self {
+        self.fuzziness = fuzziness.min(MAX_FUZZINESS);
+        self
+    }
+    
+    /// Get the distinct terms of the query
+    pub fn terms(&self) -> Vec<String> {
+        let mut terms = tokenize(&self.query);
+        terms.sort();
+        terms.dedup();
+        terms
+    }
+    
+    /// Get the edit distance between a query term and a term, if they match
+    pub fn distance(&self, query_term: &str, term: &str) -> Option<usize> {
+        if query_term == term {
+            return Some(0);
+        }
+        
+        let allowed = self.allowed_edits(query_term);
+        if allowed == 0 || query_term.chars().count().abs_diff(term.chars().count()) > allowed {
+            return None;
+        }
+        Some(edit_distance(query_term, term)).filter(|distance| *distance <= allowed)
+    }
+    
+    /// Check whether a document's field holds any of the query terms
+    pub fn evaluate(&self, document: &serde_json::Value) -> bool {
+        let Some(text) = field_value(document, &self.field).and_then(|value| value.as_str()) else {
+            return false;
+        };
+        
+        let query_terms = self.terms();
+        tokenize(text).iter()
+            .any(|term| query_terms.iter().any(|query_term| self.distance(query_term, term).is_some()))
+    }
+    
+    /// Score a document's field without corpus statistics
+    ///
+    /// Every term weighs the same and the document is taken to be of
+    /// average length, so only term frequencies count.
+    pub fn score_text(&self, text: &str) -> f64 {
+        let mut counts: HashMap<String, u32> = HashMap::new();
+        for term in tokenize(text) {
+            *counts.entry(term).or_default() += 1;
+        }
+        
+        self.terms().iter()
+            .flat_map(|query_term| counts.iter().filter_map(move |(term, tf)| {
+                self.distance(query_term, term).map(|distance| (*tf, distance))
+            }))
+            .map(|(tf, distance)| bm25(1.0, tf, 1.0) / (1 + distance) as f64)
+            .sum()
+    }
+    
+    /// Build a snippet of a document's field with the matched terms highlighted
+    ///
+    /// The snippet starts a few words before the first match; returns
+    /// None if the field holds no match.
+    pub fn highlight(&self, document: &serde_json::Value) -> Option<String> {
+        let text = field_value(document, &self.field)?.as_str()?;
+        let query_terms = self.terms();
+        let words = word_spans(text);
+        let matched: Vec<bool> = words.iter()
+            .map(|(start, end)| {
+                let term = text[*start..*end].to_lowercase();
+                query_terms.iter().any(|query_term| self.distance(query_term, &term).is_some())
+            })
+            .collect();
+        
+        let first = matched.iter().position(|matched| *matched)?;
+        let from = first.saturating_sub(SNIPPET_LEAD);
+        let to = (from + SNIPPET_WORDS).min(words.len());
+        
+        let mut snippet = String::new();
+        if from > 0 {
+            snippet.push('…');
+        }
+        let mut position = words[from].0;
+        for (&(start, end), &matched) in words[from..to].iter().zip(&matched[from..to]) {
+            snippet.push_str(&text[position..start]);
+            if matched {
+                snippet.push_str(HIGHLIGHT_START);
+                snippet.push_str(&text[start..end]);
+                snippet.push_str(HIGHLIGHT_END);
+            } else {
+                snippet.push_str(&text[start..end]);
+            }
+            position = end;
+        }
+        if to < words.len() {
+            snippet.push('…');
+        }
+        
+        Some(snippet)
+    }
+    
+    /// Get the edits allowed for a query term, given its length
+    fn allowed_edits(&self, query_term: &str) -> usize {
+        let by_length = match query_term.chars().count() {
+            0..=2 => 0,
+            3..=5 => 1,
+            _ => 2,
+        };
+        by_length.min(self.fuzziness as usize)
+    }
+}
+
+/// What a full-text index holds for one document
+#[derive(Debug, Clone)]
+struct IndexedText {
+    /// Number of terms in the text
+    length: usize,
+    
+    /// Distinct terms of the text
+    terms: Vec<String>,
+}
+
+/// Inverted index of the terms in a text field
+#[derive(Debug, Clone, Default)]
+pub struct TextIndex {
+    /// Cells holding each term, with the number of times it occurs
+    postings: BTreeMap<String, HashMap<String, u32>>,
+    
+    /// Indexed text of each cell
+    documents: HashMap<String, IndexedText>,
+    
+    /// Sum of the lengths of all indexed texts
+    total_length: usize,
+}
+
+impl TextIndex {
+    /// Create an empty index
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Index the text held by a cell, replacing what was indexed for it
+    pub fn insert(&mut self, cell_id: &str, text: &str) {
+        self.remove(cell_id);
+        
+        let mut counts: HashMap<String, u32> = HashMap::new();
+        let mut length = 0;
+        for term in tokenize(text) {
+            *counts.entry(term).or_default() += 1;
+            length += 1;
+        }
+        
+        let terms = counts.keys().cloned().collect();
+        for (term, tf) in counts {
+            self.postings.entry(term).or_default().insert(cell_id.to_string(), tf);
+        }
+        self.total_length += length;
+        self.documents.insert(cell_id.to_string(), IndexedText { length, terms });
+    }
+    
+    /// Remove a cell from the index
+    pub fn remove(&mut self, cell_id: &str) {
+        let Some(indexed) = self.documents.remove(cell_id) else {
+            return;
+        };
+        
+        self.total_length -= indexed.length;
+        for term in indexed.terms {
+            if let Some(cells) = self.postings.get_mut(&term) {
+                cells.remove(cell_id);
+                if cells.is_empty() {
+                    self.postings.remove(&term);
+                }
+            }
+        }
+    }
+    
+    /// Get the number of indexed documents
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+    
+    /// Check whether no document is indexed
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+    
+    /// Find the cells holding any term of a search
+    pub fn lookup(&self, search: &TextSearch) -> BTreeSet<String> {
+        search.terms().iter()
+            .flat_map(|query_term| self.expand(search, query_term))
+            .flat_map(|(term, _)| self.postings[term].keys().cloned())
+            .collect()
+    }
+    
+    /// Estimate the number of cells a search matches
+    pub fn estimate(&self, search: &TextSearch) -> usize {
+        let postings: usize = search.terms().iter()
+            .flat_map(|query_term| self.expand(search, query_term))
+            .map(|(term, _)| self.postings[term].len())
+            .sum();
+        postings.min(self.len())
+    }
+    
+    /// Score the text held by a cell against a search with BM25
+    ///
+    /// Terms matched through edits count for less the more edits they need.
+    pub fn score(&self, search: &TextSearch, cell_id: &str) -> f64 {
+        let Some(indexed) = self.documents.get(cell_id) else {
+            return 0.0;
+        };
+        let documents = self.len() as f64;
+        let average_length = self.total_length as f64 / documents;
+        let relative_length = if average_length > 0.0 { indexed.length as f64 / average_length } else { 1.0 };
+        
+        let mut score = 0.0;
+        for query_term in search.terms() {
+            for (term, distance) in self.expand(search, &query_term) {
+                let cells = &self.postings[term];
+                let Some(tf) = cells.get(cell_id) else {
+                    continue;
+                };
+                let df = cells.len() as f64;
+                let idf = (1.0 + (documents - df + 0.5) / (df + 0.5)).ln();
+                score += bm25(idf, *tf, relative_length) / (1 + distance) as f64;
+            }
+        }
+        score
+    }
+    
+    /// Get the indexed terms a query term matches, with their edit distance
+    fn expand<'a>(&'a self, search: &TextSearch, query_term: &str) -> Vec<(&'a str, usize)> {
+        if search.allowed_edits(query_term) == 0 {
+            return self.postings.get_key_value(query_term)
+                .map(|(term, _)| vec![(term.as_str(), 0)])
+                .unwrap_or_default();
+        }
+        
+        self.postings.keys()
+            .filter_map(|term| search.distance(query_term, term).map(|distance| (term.as_str(), distance)))
+            .collect()
+    }
+}
+
+/// Split text into lowercase terms, breaking on anything but letters and digits
+pub fn tokenize(text: &str) -> Vec<String> {
+    word_spans(text).into_iter()
+        .map(|(start, end)| text[start..end].to_lowercase())
+        .collect()
+}
+
+/// Count the single-character insertions, deletions and substitutions turning one term into another
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    
+    for (i, ca) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    
+    previous[b.len()]
+}
+
+/// Find the byte ranges of the words in a text
+fn word_spans(text: &str) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut start = None;
+    
+    for (i, c) in text.char_indices() {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                spans.push((s, i));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        spans.push((s, text.len()));
+    }
+    
+    spans
+}
+
+/// BM25 weight of a term occurring `tf` times in a document
+fn bm25(idf: f64, tf: u32, relative_length: f64) -> f64 {
+    let tf = tf as f64;
+    idf * tf * (BM25_K1 + 1.0) / (tf + BM25_K1 * (1.0 - BM25_B + BM25_B * relative_length))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    
+    #[test]
+    fn test_tokenize_and_edit_distance() {
+        assert_eq!(tokenize("Hello, World! It's 2024."), vec!["hello", "world", "it", "s", "2024"]);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("database", "databse"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+        
+        let search = TextSearch::new("body".to_string(), "databse of".to_string()).with_fuzziness(5);
+        assert_eq!(search.fuzziness, MAX_FUZZINESS);
+        assert_eq!(search.distance("databse", "database"), Some(1));
+        // Short terms must match exactly
+        assert_eq!(search.distance("of", "or"), None);
+    }
+    
+    #[test]
+    fn test_bm25_ranking() {
+        let mut index = TextIndex::new();
+        index.insert("a", "The hive stores honey");
+        index.insert("b", "Honey honey honey, the bees make honey");
+        index.insert("c", "A database shaped like a hive, storing documents in hexagonal cells");
+        index.insert("d", "Nothing relevant here");
+        
+        let search = TextSearch::new("body".to_string(), "honey".to_string());
+        assert_eq!(index.lookup(&search), BTreeSet::from(["a".to_string(), "b".to_string()]));
+        assert!(index.score(&search, "b") > index.score(&search, "a"));
+        assert_eq!(index.score(&search, "d"), 0.0);
+        
+        // A term in fewer documents weighs more
+        let search = TextSearch::new("body".to_string(), "hive database".to_string());
+        assert!(index.score(&search, "c") > index.score(&search, "a"));
+        
+        index.remove("b");
+        assert_eq!(index.len(), 3);
+        assert_eq!(index.estimate(&TextSearch::new("body".to_string(), "honey".to_string())), 1);
+    }
+    
+    #[test]
+    fn test_fuzzy_lookup_and_highlight() {
+        let mut index = TextIndex::new();
+        index.insert("a", "Distributed databases");
+        index.insert("b", "A database for bees");
+        
+        let exact = TextSearch::new("body".to_string(), "databse".to_string());
+        assert!(index.lookup(&exact).is_empty());
+        let fuzzy = exact.with_fuzziness(2);
+        assert_eq!(index.lookup(&fuzzy).len(), 2);
+        assert!(index.score(&fuzzy, "b") > index.score(&fuzzy, "a"));
+        
+        let document = json!({ "body": "HiveDB is a database shaped like a hive, with documents stored in hexagonal cells of the grid." });
+        let search = TextSearch::new("body".to_string(), "hive cells".to_string());
+        assert!(search.evaluate(&document));
+        assert_eq!(
+            search.highlight(&document).unwrap(),
+            "…shaped like a <em>hive</em>, with documents stored in hexagonal <em>cells</em> of the…"
+        );
+        assert!(search.highlight(&json!({ "body": "no match" })).is_none());
+    }
+}
@@ -0,0 +1,475 @@
+// HiveDB Tiering Module
+//
+// This module moves the content of rarely read cells to S3-compatible
+// object storage, keeping hot data on local disk. A cold cell keeps its
+// place in the grid, its checksum and its metadata, and names the object
+// holding its stored content; reads fetch that object transparently through
+// a small cache. Like the compression dictionaries, the cold tier is
+// process-wide so cells can be read without their hive, and it counts
+// where reads were served from so tier hit rates can be monitored.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::Duration;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use crate::core::error::HiveError;
+use crate::core::pheromone::{self, Evaporation, Trail};
+use crate::utils::determinism;
+use log::debug;
+
+/// Default time without reads or writes after which a cell is cold
+pub const DEFAULT_COLD_AFTER: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Default smallest stored content worth moving to the cold tier
+pub const DEFAULT_MIN_SIZE: usize = 4 * 1024;
+
+/// Default size of the cache of fetched cold content
+pub const DEFAULT_CACHE_SIZE: usize = 64 * 1024 * 1024;
+
+/// The cold tier of this process, once one is installed
+static COLD_TIER: OnceLock<RwLock<Option<Arc<ColdTier>>>> = OnceLock::new();
+
+/// Where the reads of this process were served from
+static COUNTERS: TierCounters = TierCounters::new();
+
+/// Storage for objects such as cold content or snapshots, like an S3 bucket
+pub trait ObjectStore: Send + Sync {
+    /// Store an object, replacing any object with the same key
+    fn put(&self, key: &str, data: &[u8]) -> Result<(), HiveError>;
+    
+    /// Get an object, or None if there is no object with the key
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, HiveError>;
+    
+    /// Delete an object, succeeding if it does not exist
+    fn delete(&self, key: &str) -> Result<(), HiveError>;
+}
+
+/// Which cells are moved to the cold tier
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TieringPolicy {
+    /// Time without reads or writes after which a cell is cold
+    pub cold_after: Duration,
+    
+    /// Smallest stored content worth moving
+    pub min_size: usize,
+}
+
+/// The object store holding cold content, with a cache of what was fetched
+pub struct ColdTier {
+    /// Where cold content is kept
+    store: Box<dyn ObjectStore>,
+    
+    /// Recently fetched content
+    cache: Mutex<FetchCache>,
+}
+
+/// Fetched content by object key, evicted oldest first
+#[derive(Default)]
+struct FetchCache {
+    /// Cached content by key
+    entries: HashMap<String, Bytes>,
+    
+    /// Keys in the order they were cached
+    order: VecDeque<String>,
+    
+    /// Total size of the cached content
+    size: usize,
+    
+    /// Largest total size to cache
+    capacity: usize,
+}
+
+/// Counters of where reads were served from
+struct TierCounters {
+    hot_reads: AtomicU64,
+    cold_reads: AtomicU64,
+    cache_hits: AtomicU64,
+    fetches: AtomicU64,
+    offloaded_cells: AtomicU64,
+    offloaded_bytes: AtomicU64,
+}
+
+/// Where the reads of this process were served from
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TierStats {
+    /// Reads of cells whose content is on local disk
+    pub hot_reads: u64,
+    
+    /// Reads of cells whose content is in the cold tier
+    pub cold_reads: u64,
+    
+    /// Fetches of cold content served from the cache
+    pub cache_hits: u64,
+    
+    /// Fetches of cold content from the object store
+    pub fetches: u64,
+    
+    /// Cells moved to the cold tier
+    pub offloaded_cells: u64,
+    
+    /// Stored bytes moved to the cold tier
+    pub offloaded_bytes: u64,
+}
+
+/// What a pass of the tiering policy moved
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OffloadReport {
+    /// Cells moved to the cold tier
+    pub cells: usize,
+    
+    /// Stored bytes moved to the cold tier
+    pub bytes: usize,
+}
+
+/// When a cell was last read, in seconds since the epoch (zero if not since it was loaded)
+///
+/// Also counts the reads since the cell was loaded, for heat maps, and
+/// lays the pheromone trail that classifies the cell as hot or cold.
+#[derive(Debug, Default)]
+pub struct AccessTime {
+    /// Time of the last read
+    last: AtomicU64,
+    
+    /// Number of reads
+    count: AtomicU64,
+    
+    /// Pheromone left by the reads
+    trail: Trail,
+}
+
+impl TieringPolicy {
+    /// Check whether a cell is cold, given its stored size and last activity
+    pub fn is_cold(&self, size: usize, last_active: u64, now: u64) -> bool {
+        size >= self.min_size && now.saturating_sub(last_active) >= self.cold_after.as_secs()
+    }
+}
+
+impl Default for TieringPolicy {
+    fn default() -> Self {
+        Self {
+            cold_after: DEFAULT_COLD_AFTER,
+            min_size: DEFAULT_MIN_SIZE,
+        }
+    }
+}
+
+impl ColdTier {
+    /// Keep cold content in an object store
+    pub fn new(store: impl ObjectStore + 'static) -> Self {
+        Self {
+            store: Box::new(store),
+            cache: Mutex::new(FetchCache { capacity: DEFAULT_CACHE_SIZE, ..FetchCache::default() }),
+        }
+    }
+    
+    /// Set the size of the cache of fetched content
+    pub fn with_cache_size(self, capacity: usize) -> Self {
+        if let Ok(mut cache) = self.cache.lock() {
+            cache.capacity = capacity;
+        }
+        self
+    }
+    
+    /// Store the content of a cell being moved to this tier
+    pub fn store(&self, key: &str, content: &[u8]) -> Result<(), HiveError> {
+        self.store.put(key, content)?;
+        COUNTERS.offloaded_cells.fetch_add(1, Ordering::Relaxed);
+        COUNTERS.offloaded_bytes.fetch_add(content.len() as u64, Ordering::Relaxed);
+        Ok(())
+    }
+    
+    /// Get cold content, from the cache if it was fetched lately
+    pub fn fetch(&self, key: &str) -> Result<Bytes, HiveError> {
+        if let Some(content) = self.cache.lock().map_err(|_| HiveError::LockError)?.entries.get(key) {
+            COUNTERS.cache_hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(content.clone());
+        }
+        
+        let content = self.store.get(key)?
+            .map(Bytes::from)
+            .ok_or_else(|| HiveError::IoError(format!("Cold content '{}' is missing from the object store", key)))?;
+        COUNTERS.fetches.fetch_add(1, Ordering::Relaxed);
+        debug!("Fetched {} bytes of cold content from '{}'", content.len(), key);
+        
+        self.cache.lock().map_err(|_| HiveError::LockError)?.insert(key, content.clone());
+        Ok(content)
+    }
+}
+
+impl FetchCache {
+    /// Cache content, evicting the oldest entries to make room
+    fn insert(&mut self, key: &str, content: Bytes) {
+        if content.len() > self.capacity || self.entries.contains_key(key) {
+            return;
+        }
+        
+        while self.size + content.len() > self.capacity {
+            let Some(oldest) = self.order.pop_front() else {
+                break;
+            };
+            if let Some(evicted) = self.entries.remove(&oldest) {
+                self.size -= evicted.len();
+            }
+        }
+        
+        self.size += content.len();
+        self.order.push_back(key.to_string());
+        self.entries.insert(key.to_string(), content);
+    }
+}
+
+impl TierCounters {
+    const fn new() -> Self {
+        Self {
+            hot_reads: AtomicU64::new(0),
+            cold_reads: AtomicU64::new(0),
+            cache_hits: AtomicU64::new(0),
+            fetches: AtomicU64::new(0),
+            offloaded_cells: AtomicU64::new(0),
+            offloaded_bytes: AtomicU64::new(0),
+        }
+    }
+}
+
+impl TierStats {
+    /// Get the share of reads served from local disk, if there were any reads
+    pub fn hot_hit_rate(&self) -> Option<f64> {
+        let reads = self.hot_reads + self.cold_reads;
+        (reads > 0).then(|| self.hot_reads as f64 / reads as f64)
+    }
+    
+    /// Get the share of cold content fetches served from the cache, if there were any
+    pub fn cache_hit_rate(&self) -> Option<f64> {
+        let fetches = self.cache_hits + self.fetches;
+        (fetches > 0).then(|| self.cache_hits as f64 / fetches as f64)
+    }
+}
+
+impl AccessTime {
+    /// Record a read now
+    pub fn touch(&self) {
+        let now = determinism::unix_time();
+        self.last.store(now, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.trail.deposit(&pheromone::evaporation(), now);
+    }
+    
+    /// Get the time of the last read
+    pub fn get(&self) -> u64 {
+        self.last.load(Ordering::Relaxed)
+    }
+    
+    /// Get the number of reads
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+    
+    /// Get the pheromone left by the reads at a time
+    pub fn pheromone(&self, model: &Evaporation, now: u64) -> f64 {
+        self.trail.level(model, now)
+    }
+}
+
+impl Clone for AccessTime {
+    fn clone(&self) -> Self {
+        Self {
+            last: AtomicU64::new(self.get()),
+            count: AtomicU64::new(self.count()),
+            trail: self.trail.clone(),
+        }
+    }
+}
+
+/// Make an object store the cold tier of this process
+pub fn install(tier: ColdTier) -> Result<Arc<ColdTier>, HiveError> {
+    let tier = Arc::new(tier);
+    *slot().write().map_err(|_| HiveError::LockError)? = Some(tier.clone());
+    Ok(tier)
+}
+
+/// Get the cold tier of this process, if one is installed
+pub fn cold_tier() -> Result<Option<Arc<ColdTier>>, HiveError> {
+    Ok(slot().read().map_err(|_| HiveError::LockError)?.clone())
+}
+
+/// Get cold content through the cold tier of this process
+pub fn fetch(key: &str) -> Result<Bytes, HiveError> {
+    cold_tier()?
+        .ok_or_else(|| HiveError::GenericError(format!("Content '{}' is in the cold tier, which is not configured", key)))?
+        .fetch(key)
+}
+
+/// Count a client read of a cell, by whether its content is cold
+pub fn record_read(cold: bool) {
+    let counter = if cold { &COUNTERS.cold_reads } else { &COUNTERS.hot_reads };
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Get where the reads of this process were served from
+pub fn stats() -> TierStats {
+    TierStats {
+        hot_reads: COUNTERS.hot_reads.load(Ordering::Relaxed),
+        cold_reads: COUNTERS.cold_reads.load(Ordering::Relaxed),
+        cache_hits: COUNTERS.cache_hits.load(Ordering::Relaxed),
+        fetches: COUNTERS.fetches.load(Ordering::Relaxed),
+        offloaded_cells: COUNTERS.offloaded_cells.load(Ordering::Relaxed),
+        offloaded_bytes: COUNTERS.offloaded_bytes.load(Ordering::Relaxed),
+    }
+}
+
+/// Parse a duration such as `90s`, `30m`, `12h` or `7d`
+pub fn parse_duration(s: &str) -> Result<Duration, HiveError> {
+    let s = s.trim();
+    let (number, unit) = s.split_at(s.len() - s.chars().last().map_or(0, char::len_utf8));
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => 0,
+    };
+    
+    number.parse::<u64>().ok()
+        .filter(|_| seconds > 0)
+        .map(|number| Duration::from_secs(number * seconds))
+        .ok_or_else(|| HiveError::GenericError(format!("Invalid duration '{}', expected a number of s, m, h or d", s)))
+}
+
+/// Get the slot holding the cold tier
+fn slot() -> &'static RwLock<Option<Arc<ColdTier>>> {
+    COLD_TIER.get_or_init(|| RwLock::new(None))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::cell::{Cell, CellDataType};
+    use crate::core::hive::Hive;
+    use crate::core::pheromone::{Temperature, TemperatureCounts};
+    use tempfile::tempdir;
+    
+    /// An object store in memory that counts its requests
+    #[derive(Clone, Default)]
+    struct MemoryStore {
+        objects: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+        gets: Arc<AtomicU64>,
+    }
+    
+    impl ObjectStore for MemoryStore {
+        fn put(&self, key: &str, data: &[u8]) -> Result<(), HiveError> {
+            self.objects.lock().unwrap().insert(key.to_string(), data.to_vec());
+            Ok(())
+        }
+        
+        fn get(&self, key: &str) -> Result<Option<Vec<u8>>, HiveError> {
+            self.gets.fetch_add(1, Ordering::Relaxed);
+            Ok(self.objects.lock().unwrap().get(key).cloned())
+        }
+        
+        fn delete(&self, key: &str) -> Result<(), HiveError> {
+            self.objects.lock().unwrap().remove(key);
+            Ok(())
+        }
+    }
+    
+    #[test]
+    fn test_fetch_cache() {
+        let store = MemoryStore::default();
+        let tier = ColdTier::new(store.clone()).with_cache_size(10);
+        tier.store("a", b"123456").unwrap();
+        tier.store("b", b"7890").unwrap();
+        tier.store("c", b"abcdef").unwrap();
+        
+        assert_eq!(&tier.fetch("a").unwrap()[..], b"123456");
+        assert_eq!(&tier.fetch("a").unwrap()[..], b"123456");
+        assert_eq!(store.gets.load(Ordering::Relaxed), 1);
+        
+        // Caching c evicts a, but b still fits beside it
+        tier.fetch("b").unwrap();
+        tier.fetch("c").unwrap();
+        tier.fetch("b").unwrap();
+        assert_eq!(store.gets.load(Ordering::Relaxed), 3);
+        tier.fetch("a").unwrap();
+        assert_eq!(store.gets.load(Ordering::Relaxed), 4);
+        assert!(tier.fetch("missing").is_err());
+    }
+    
+    #[test]
+    fn test_policy() {
+        let policy = TieringPolicy { cold_after: Duration::from_secs(100), min_size: 10 };
+        assert!(policy.is_cold(10, 1_000, 1_100));
+        assert!(!policy.is_cold(10, 1_000, 1_099));
+        assert!(!policy.is_cold(9, 0, 1_100));
+        
+        assert_eq!(parse_duration("7d").unwrap(), Duration::from_secs(7 * 24 * 60 * 60));
+        assert_eq!(parse_duration("90s").unwrap(), Duration::from_secs(90));
+        assert!(parse_duration("7").is_err());
+        assert!(parse_duration("d").is_err());
+        
+        let stats = TierStats { hot_reads: 3, cold_reads: 1, ..TierStats::default() };
+        assert_eq!(stats.hot_hit_rate(), Some(0.75));
+        assert_eq!(stats.cache_hit_rate(), None);
+    }
+    
+    #[test]
+    fn test_hive_offload() {
+        let temp_dir = tempdir().unwrap();
+        let store = MemoryStore::default();
+        let tier = install(ColdTier::new(store.clone())).unwrap();
+        
+        let mut hive = Hive::new("archive".to_string(), String::new(), "test-user".to_string(), temp_dir.path().to_path_buf(), (8, 8)).unwrap();
+        hive.cells.set_split_threshold(64);
+        let document = format!("{{\"log\": \"{}\"}}", "x".repeat(200)).into_bytes();
+        hive.add_cell(Cell::new("old".to_string(), (0, 0), CellDataType::Json, document.clone(), false).unwrap()).unwrap();
+        hive.add_cell(Cell::new("small".to_string(), (1, 0), CellDataType::Json, b"{}".to_vec(), false).unwrap()).unwrap();
+        
+        // Only the large cell is worth moving; its continuation cells go with it
+        let policy = TieringPolicy { cold_after: Duration::ZERO, min_size: 100 };
+        let report = hive.offload_cold(&policy, &tier).unwrap();
+        assert_eq!(report, OffloadReport { cells: 1, bytes: document.len() });
+        assert_eq!(hive.cells.stored_cells().len(), 2);
+        assert_eq!(store.objects.lock().unwrap().len(), 1);
+        
+        // Reads go through to the object store once, then to the cache
+        let cell_arc = hive.find_cell_by_id("old").unwrap();
+        assert!(cell_arc.read().unwrap().is_cold());
+        assert_eq!(cell_arc.read().unwrap().get_content().unwrap(), document);
+        assert_eq!(cell_arc.read().unwrap().get_content().unwrap(), document);
+        assert_eq!(store.gets.load(Ordering::Relaxed), 1);
+        assert!(hive.verify(false).unwrap().problems.is_empty());
+        
+        // Cold cells load as cold cells
+        hive.save().unwrap();
+        let loaded = Hive::load(temp_dir.path().to_path_buf()).unwrap();
+        let loaded_cell = loaded.find_cell_by_id("old").unwrap();
+        assert!(loaded_cell.read().unwrap().is_cold());
+        assert_eq!(loaded_cell.read().unwrap().get_content().unwrap(), document);
+        
+        // Writing a cold cell brings it back to local disk
+        hive.update_cell((0, 0), b"{\"log\": \"new\"}".to_vec(), false).unwrap();
+        assert!(!cell_arc.read().unwrap().is_cold());
+    }
+    
+    #[test]
+    fn test_hot_cells_stay() {
+        let temp_dir = tempdir().unwrap();
+        let tier = ColdTier::new(MemoryStore::default());
+        let mut hive = Hive::new("events".to_string(), String::new(), "test-user".to_string(), temp_dir.path().to_path_buf(), (8, 8)).unwrap();
+        hive.add_cell(Cell::new("busy".to_string(), (0, 0), CellDataType::Binary, vec![1; 200], false).unwrap()).unwrap();
+        hive.add_cell(Cell::new("idle".to_string(), (1, 0), CellDataType::Binary, vec![2; 200], false).unwrap()).unwrap();
+        
+        let busy = hive.find_cell_by_id("busy").unwrap();
+        for _ in 0..9 {
+            busy.read().unwrap().record_read();
+        }
+        assert_eq!(busy.read().unwrap().temperature(), Temperature::Hot);
+        assert_eq!(hive.stats().unwrap().temperatures, TemperatureCounts { hot: 1, warm: 0, cold: 1 });
+        
+        let policy = TieringPolicy { cold_after: Duration::ZERO, min_size: 100 };
+        assert_eq!(hive.offload_cold(&policy, &tier).unwrap().cells, 1);
+        assert!(!busy.read().unwrap().is_cold());
+        assert!(hive.find_cell_by_id("idle").unwrap().read().unwrap().is_cold());
+    }
+}
@@ -0,0 +1,483 @@
+// HiveDB Verify Module
+//
+// This module checks the integrity of a hive: cell checksums and
+// contents, the uniqueness guarantees of its indexes, its neighbor links
+// against the cells actually next to each other, and the coherence of its
+// on-disk snapshot. Problems that can be fixed
+// without losing data are repaired on request; corrupt cells are moved
+// to a quarantine directory rather than deleted.
+
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use crate::core::cell::CellDataType;
+use crate::core::codec::Integrity;
+use crate::core::error::HiveError;
+use crate::core::hive::{Hive, SNAPSHOT_FILE};
+use crate::core::index::{IndexExpression, SecondaryIndex};
+use crate::core::query::{field_path, with_id, PathSegment};
+use crate::core::snapshot;
+use log::warn;
+
+/// Directory, inside a hive's storage directory, that corrupt cells are moved to
+pub const QUARANTINE_DIR: &str = "quarantine";
+
+/// Kinds of integrity problems
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProblemKind {
+    /// The stored checksum does not match the cell content
+    ChecksumMismatch,
+    
+    /// The content cannot be decompressed or is not valid for its type
+    CorruptContent,
+    
+    /// Two cells share the same ID
+    DuplicateCellId,
+    
+    /// Documents share a key of a unique index
+    DuplicateIndexKey,
+    
+    /// An index covers a field the schema does not define
+    UndefinedIndexField,
+    
+    /// A neighbor link points to a cell that does not exist
+    DanglingNeighborLink,
+    
+    /// A neighbor link points to a cell that is not next to the linking cell
+    MisplacedNeighborLink,
+    
+    /// A cell is not linked to a cell next to it
+    MissingNeighborLink,
+    
+    /// An edge lies along a direction the cell has no neighbor link in
+    DanglingEdge,
+    
+    /// An interrupted save left a temporary snapshot behind
+    IncompleteSnapshot,
+    
+    /// The snapshot on disk does not match the hive in memory
+    StaleSnapshot,
+}
+
+/// An integrity problem found in a hive
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Problem {
+    /// What is wrong
+    pub kind: ProblemKind,
+    
+    /// ID of the affected cell, if any
+    pub cell_id: Option<String>,
+    
+    /// Human-readable description
+    pub detail: String,
+    
+    /// Whether the problem was repaired
+    pub repaired: bool,
+}
+
+/// Outcome of verifying a hive
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VerifyReport {
+    /// Name of the hive
+    pub hive: String,
+    
+    /// Number of cells checked
+    pub cells_checked: usize,
+    
+    /// Problems found, repaired or not
+    pub problems: Vec<Problem>,
+}
+
+impl VerifyReport {
+    /// Check whether no unrepaired problems remain
+    pub fn is_healthy(&self) -> bool {
+        self.problems.iter().all(|problem| problem.repaired)
+    }
+}
+
+/// Verify a hive, repairing what can be repaired if asked to
+///
+/// Repairs change the hive in memory and, when anything was repaired,
+/// save it.
+pub fn verify_hive(hive: &mut Hive, repair: bool) -> Result<VerifyReport, HiveError> {
+    let mut problems = Vec::new();
+    let cells = hive.cells.all_cells();
+    let cells_checked = cells.len();
+    
+    let mut corrupt = Vec::new();
+    let mut ids: HashMap<String, usize> = HashMap::new();
+    let mut documents = Vec::new();
+    
+    for cell_arc in &cells {
+        let cell = cell_arc.read().map_err(|_| HiveError::LockError)?;
+        *ids.entry(cell.id.clone()).or_default() += 1;
+        
+        let stored = match cell.stored_content() {
+            Ok(stored) => stored,
+            // An unreachable cold tier is not corruption, so the cell is not repaired
+            Err(e) if cell.is_cold() => {
+                problems.push(Problem {
+                    kind: ProblemKind::CorruptContent,
+                    cell_id: Some(cell.id.clone()),
+                    detail: format!("Cell at {:?} cannot be fetched from the cold tier: {}", cell.coordinates, e),
+                    repaired: false,
+                });
+                continue;
+            }
+            Err(_) => {
+                problems.push(Problem {
+                    kind: ProblemKind::CorruptContent,
+                    cell_id: Some(cell.id.clone()),
+                    detail: format!("Cell at {:?} is missing continuation cells", cell.coordinates),
+                    repaired: false,
+                });
+                corrupt.push((problems.len() - 1, cell.coordinates));
+                continue;
+            }
+        };
+        let checksum = Integrity::of_checksum(&cell.data.checksum).checksum(&stored);
+        if checksum != cell.data.checksum {
+            problems.push(Problem {
+                kind: ProblemKind::ChecksumMismatch,
+                cell_id: Some(cell.id.clone()),
+                detail: format!("Cell at {:?} has checksum {} but content hashes to {}", cell.coordinates, cell.data.checksum, checksum),
+                repaired: false,
+            });
+            corrupt.push((problems.len() - 1, cell.coordinates));
+            continue;
+        }
+        
+        let content = cell.get_content().and_then(|content| match cell.data.data_type {
+            CellDataType::Json => serde_json::from_slice::<serde_json::Value>(&content)
+                .map_err(|e| HiveError::DeserializationError(e.to_string())),
+            _ => Ok(serde_json::Value::Null),
+        });
+        match content {
+            Ok(serde_json::Value::Null) => {}
+            Ok(document) => documents.push((cell.id.clone(), document)),
+            Err(e) => {
+                problems.push(Problem {
+                    kind: ProblemKind::CorruptContent,
+                    cell_id: Some(cell.id.clone()),
+                    detail: format!("Cell at {:?} cannot be read: {}", cell.coordinates, e),
+                    repaired: false,
+                });
+                corrupt.push((problems.len() - 1, cell.coordinates));
+                continue;
+            }
+        }
+    }
+    
+    for (id, count) in &ids {
+        if *count > 1 {
+            problems.push(Problem {
+                kind: ProblemKind::DuplicateCellId,
+                cell_id: Some(id.clone()),
+                detail: format!("{} cells share the ID '{}'", count, id),
+                repaired: false,
+            });
+        }
+    }
+    
+    let unlinked = check_neighbor_links(hive, &mut problems)?;
+    check_indexes(hive, &documents, &mut problems);
+    check_snapshot(hive, cells_checked, &mut problems)?;
+    
+    if repair {
+        for (problem, coordinates) in unlinked {
+            hive.cells.relink(coordinates)?;
+            problems[problem].repaired = true;
+        }
+        
+        // Drop our references so the cells can be taken out of the grid
+        drop(cells);
+        for (problem, coordinates) in corrupt {
+            quarantine(hive, coordinates)?;
+            problems[problem].repaired = true;
+        }
+        
+        for problem in problems.iter_mut().filter(|p| p.kind == ProblemKind::IncompleteSnapshot) {
+            let _ = std::fs::remove_file(hive.storage_path.join(SNAPSHOT_FILE).with_extension("tmp"));
+            problem.repaired = true;
+        }
+        
+        let changed = problems.iter().any(|p| p.repaired && p.kind != ProblemKind::IncompleteSnapshot);
+        let stale = problems.iter().any(|p| p.kind == ProblemKind::StaleSnapshot);
+        if changed || stale {
+            hive.save()?;
+            for problem in problems.iter_mut().filter(|p| p.kind == ProblemKind::StaleSnapshot) {
+                problem.repaired = true;
+            }
+        }
+    }
+    
+    Ok(VerifyReport {
+        hive: hive.name.clone(),
+        cells_checked,
+        problems,
+    })
+}
+
+/// Check the neighbor links of every stored cell against the cells next to it
+///
+/// Returns the problems found with the coordinates of the cell to relink.
+fn check_neighbor_links(hive: &Hive, problems: &mut Vec<Problem>) -> Result<Vec<(usize, (i32, i32))>, HiveError> {
+    let mut unlinked = Vec::new();
+    
+    // Continuation cells sit in the grid too, so their links are checked as well
+    for cell_arc in hive.cells.stored_cells() {
+        let coordinates = cell_arc.read().map_err(|_| HiveError::LockError)?.coordinates;
+        let expected = hive.cells.neighbors_of(coordinates)?;
+        let cell = cell_arc.read().map_err(|_| HiveError::LockError)?;
+        
+        let mut found = Vec::new();
+        for (direction, neighbor_id) in &cell.neighbors {
+            if expected.get(direction) == Some(neighbor_id) {
+                continue;
+            }
+            if hive.cells.find_by_id(neighbor_id).is_none() {
+                found.push((ProblemKind::DanglingNeighborLink, format!("Link {:?} points to missing cell '{}'", direction, neighbor_id)));
+            } else {
+                found.push((ProblemKind::MisplacedNeighborLink, format!("Link {:?} points to '{}', which is not next to it", direction, neighbor_id)));
+            }
+        }
+        for (direction, neighbor_id) in &expected {
+            if !cell.neighbors.contains_key(direction) {
+                found.push((ProblemKind::MissingNeighborLink, format!("Cell '{}' lies {:?} but is not linked", neighbor_id, direction)));
+            }
+        }
+        for (direction, edge) in &cell.edges {
+            if !cell.neighbors.contains_key(direction) {
+                found.push((ProblemKind::DanglingEdge, format!("Edge '{}' lies {:?}, where there is no link", edge.label, direction)));
+            }
+        }
+        
+        for (kind, detail) in found {
+            problems.push(Problem {
+                kind,
+                cell_id: Some(cell.id.clone()),
+                detail,
+                repaired: false,
+            });
+            unlinked.push((problems.len() - 1, coordinates));
+        }
+    }
+    
+    Ok(unlinked)
+}
+
+/// Check the declared indexes against the schema and the documents
+fn check_indexes(hive: &Hive, documents: &[(String, serde_json::Value)], problems: &mut Vec<Problem>) {
+    let schema = match &hive.schema {
+        Some(schema) => schema,
+        None => return,
+    };
+    
+    for index in &schema.indexes {
+        if !schema.fields.is_empty() {
+            for field in &index.fields {
+                let field = IndexExpression::parse(field)
+                    .map(|expression| expression.field().to_string())
+                    .unwrap_or_else(|_| field.clone());
+                // A nested field is declared by its top-level field
+                let root = match field_path(&field).as_deref() {
+                    Some([PathSegment::Key(root), ..]) => root.clone(),
+                    _ => field.clone(),
+                };
+                if schema.get_field(&field).is_none() && schema.get_field(&root).is_none() {
+                    problems.push(Problem {
+                        kind: ProblemKind::UndefinedIndexField,
+                        cell_id: None,
+                        detail: format!("Index '{}' covers undefined field '{}'", index.name, field),
+                        repaired: false,
+                    });
+                }
+            }
+        }
+        
+        if !index.unique {
+            continue;
+        }
+        
+        // Keys are computed as the index computes them, predicate included
+        let secondary = match SecondaryIndex::new(index.clone()) {
+            Ok(secondary) => secondary,
+            Err(_) => continue,
+        };
+        
+        let mut seen: HashMap<String, &str> = HashMap::new();
+        for (id, document) in documents {
+            let key = match secondary.key_for(&with_id(document.clone(), id)) {
+                Some(key) => serde_json::to_string(&key.0).unwrap_or_default(),
+                None => continue,
+            };
+            
+            if let Some(first) = seen.insert(key.clone(), id) {
+                problems.push(Problem {
+                    kind: ProblemKind::DuplicateIndexKey,
+                    cell_id: Some(id.clone()),
+                    detail: format!("Unique index '{}' has key {} in both '{}' and '{}'", index.name, key, first, id),
+                    repaired: false,
+                });
+            }
+        }
+    }
+}
+
+/// Check the snapshot on disk against the hive in memory
+fn check_snapshot(hive: &Hive, cell_count: usize, problems: &mut Vec<Problem>) -> Result<(), HiveError> {
+    let path = hive.storage_path.join(SNAPSHOT_FILE);
+    
+    if path.with_extension("tmp").exists() {
+        problems.push(Problem {
+            kind: ProblemKind::IncompleteSnapshot,
+            cell_id: None,
+            detail: "A temporary snapshot was left behind by an interrupted save".to_string(),
+            repaired: false,
+        });
+    }
+    
+    if !path.exists() {
+        problems.push(Problem {
+            kind: ProblemKind::StaleSnapshot,
+            cell_id: None,
+            detail: "The hive has never been saved".to_string(),
+            repaired: false,
+        });
+        return Ok(());
+    }
+    
+    let data = std::fs::read(&path)
+        .map_err(|e| HiveError::IoError(e.to_string()))?;
+    let snapshot = match snapshot::decode(&data) {
+        Ok((_, snapshot)) => snapshot,
+        Err(e) => {
+            problems.push(Problem {
+                kind: ProblemKind::StaleSnapshot,
+                cell_id: None,
+                detail: format!("The snapshot cannot be read: {}", e),
+                repaired: false,
+            });
+            return Ok(());
+        }
+    };
+    
+    let snapshot_id = snapshot.get("id").and_then(|id| id.as_str()).unwrap_or_default();
+    let snapshot_cells = snapshot.get("cells").and_then(|cells| cells.as_array()).map_or(0, |cells| cells.len());
+    if snapshot_id != hive.id || snapshot_cells != cell_count {
+        problems.push(Problem {
+            kind: ProblemKind::StaleSnapshot,
+            cell_id: None,
+            detail: format!(
+                "The snapshot holds {} cells of hive '{}' but the hive has {} cells",
+                snapshot_cells, snapshot_id, cell_count
+            ),
+            repaired: false,
+        });
+    }
+    
+    Ok(())
+}
+
+/// Move a cell out of the hive into the quarantine directory
+fn quarantine(hive: &mut Hive, coordinates: (i32, i32)) -> Result<(), HiveError> {
+    let cell = hive.remove_cell(coordinates)?;
+    
+    let dir = hive.storage_path.join(QUARANTINE_DIR);
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| HiveError::IoError(e.to_string()))?;
+    let data = serde_json::to_vec(&cell)
+        .map_err(|e| HiveError::SerializationError(e.to_string()))?;
+    std::fs::write(dir.join(format!("{}.json", hex::encode(cell.id.as_bytes()))), data)
+        .map_err(|e| HiveError::IoError(e.to_string()))?;
+    
+    warn!("Quarantined corrupt cell '{}' of hive '{}'", cell.id, hive.name);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::cell::Cell;
+    use crate::core::schema::{IndexType, Schema, SchemaIndex};
+    use tempfile::tempdir;
+    
+    fn hive(path: std::path::PathBuf) -> Hive {
+        let mut hive = Hive::new(
+            "verify".to_string(),
+            "Integrity test".to_string(),
+            "test-user".to_string(),
+            path,
+            (8, 8),
+        ).unwrap();
+        
+        for (i, sku) in ["A", "B", "A"].iter().enumerate() {
+            let content = format!("{{\"sku\": \"{}\"}}", sku).into_bytes();
+            hive.add_cell(Cell::new(format!("c{}", i), (i as i32, 0), CellDataType::Json, content, true).unwrap()).unwrap();
+        }
+        hive
+    }
+    
+    #[test]
+    fn test_healthy_hive() {
+        let temp_dir = tempdir().unwrap();
+        let mut hive = hive(temp_dir.path().to_path_buf());
+        hive.save().unwrap();
+        
+        let report = verify_hive(&mut hive, false).unwrap();
+        assert_eq!(report.cells_checked, 3);
+        assert!(report.is_healthy(), "{:?}", report.problems);
+    }
+    
+    #[test]
+    fn test_detects_and_repairs() {
+        let temp_dir = tempdir().unwrap();
+        let mut hive = hive(temp_dir.path().to_path_buf());
+        
+        let mut schema = Schema::new("verify".to_string(), String::new(), "1".to_string());
+        schema.add_index(SchemaIndex {
+            name: "by_sku".to_string(),
+            fields: vec!["sku".to_string()],
+            index_type: IndexType::Hash,
+            unique: true,
+            predicate: None,
+            include: Vec::new(),
+        });
+        hive.set_schema(schema).unwrap();
+        
+        {
+            let cell_arc = hive.get_cell((1, 0)).unwrap();
+            let mut cell = cell_arc.write().unwrap();
+            let mut content = cell.data.content.to_vec();
+            content.push(0);
+            cell.data.content = content.into();
+        }
+        hive.get_cell((0, 0)).unwrap().write().unwrap().link_neighbor(hexgrid::Direction::South, "ghost".to_string());
+        hive.get_cell((2, 0)).unwrap().write().unwrap().link_neighbor(hexgrid::Direction::NorthWest, "c0".to_string());
+        
+        let report = verify_hive(&mut hive, false).unwrap();
+        let kinds: Vec<ProblemKind> = report.problems.iter().map(|p| p.kind).collect();
+        assert!(kinds.contains(&ProblemKind::ChecksumMismatch));
+        assert!(kinds.contains(&ProblemKind::DanglingNeighborLink));
+        assert!(kinds.contains(&ProblemKind::MisplacedNeighborLink));
+        assert!(kinds.contains(&ProblemKind::DuplicateIndexKey));
+        assert!(kinds.contains(&ProblemKind::StaleSnapshot));
+        assert!(!report.is_healthy());
+        
+        let report = verify_hive(&mut hive, true).unwrap();
+        let unrepaired: Vec<ProblemKind> = report.problems.iter().filter(|p| !p.repaired).map(|p| p.kind).collect();
+        assert_eq!(unrepaired, vec![ProblemKind::DuplicateIndexKey]);
+        
+        // The corrupt cell was quarantined and the repairs were saved
+        assert!(hive.find_cell_by_id("c1").is_none());
+        assert_eq!(std::fs::read_dir(temp_dir.path().join(QUARANTINE_DIR)).unwrap().count(), 1);
+        assert!(temp_dir.path().join(SNAPSHOT_FILE).exists());
+        
+        // Every link now matches the cells actually next to each other
+        for coordinates in [(0, 0), (2, 0)] {
+            let cell = hive.get_cell(coordinates).unwrap();
+            assert_eq!(cell.read().unwrap().neighbors, hive.cells.neighbors_of(coordinates).unwrap());
+        }
+        let report = verify_hive(&mut hive, false).unwrap();
+        assert!(report.problems.iter().all(|p| p.kind == ProblemKind::DuplicateIndexKey), "{:?}", report.problems);
+    }
+}
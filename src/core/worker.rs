@@ -0,0 +1,512 @@
+// HiveDB Worker Module
+//
+// This module runs the server's work on a fixed pool of worker bees.
+// Every task belongs to a priority class: foreground queries first, then
+// index maintenance, then compaction and other housekeeping. An idle
+// worker takes the oldest task of the highest class that is under its
+// concurrency limit, and background classes never take the last worker,
+// so a user query always has one to start on however much background
+// work is queued. Operators can also cap the background tasks running at
+// once and the data background jobs move per second, and change both
+// while the server runs.
+
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use crate::core::error::HiveError;
+use crate::utils::determinism;
+use log::{info, warn};
+
+/// Bytes in a megabyte, as the rate limit counts them
+const MB: f64 = 1024.0 * 1024.0;
+
+/// The worker pool shared by the server
+static POOL: OnceLock<WorkerPool> = OnceLock::new();
+
+/// Paces the data background jobs move
+static THROTTLE: RateLimiter = RateLimiter::new();
+
+/// A unit of work queued on a pool
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Classes of work, most urgent first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Priority {
+    /// Queries of clients, which someone is waiting for
+    Foreground,
+    
+    /// Building and rebuilding indexes
+    IndexMaintenance,
+    
+    /// Compaction and other housekeeping, such as moving cold cells
+    Compaction,
+}
+
+/// Tasks of one priority class, as reported to operators
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClassStats {
+    /// The priority class
+    pub priority: Priority,
+    
+    /// Tasks running at once at most (None if only the pool size limits them)
+    pub limit: Option<usize>,
+    
+    /// Tasks running
+    pub running: usize,
+    
+    /// Tasks waiting for a worker
+    pub queued: usize,
+    
+    /// Tasks finished since the pool started
+    pub completed: u64,
+}
+
+/// Limits on background work, so maintenance does not slow down queries
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackgroundLimits {
+    /// Megabytes background jobs may read or move per second (None if unlimited)
+    pub max_mb_per_sec: Option<f64>,
+    
+    /// Background tasks running at once (None if only the class limits apply)
+    pub max_tasks: Option<usize>,
+}
+
+/// Spreads the bytes moved over time so they stay under a rate
+#[derive(Debug)]
+pub struct RateLimiter {
+    /// Bytes per second (None if unlimited), and when the bytes moved so far are paid off
+    state: Mutex<(Option<f64>, Duration)>,
+}
+
+/// A fixed set of threads running tasks by priority
+pub struct WorkerPool {
+    /// State shared with the workers
+    shared: Arc<Shared>,
+    
+    /// Number of workers
+    workers: usize,
+}
+
+/// State shared by a pool and its workers
+struct Shared {
+    /// Queued and running tasks
+    state: Mutex<PoolState>,
+    
+    /// Signalled when a task is queued or finishes
+    changed: Condvar,
+}
+
+/// Queued and running tasks of a pool, by class
+struct PoolState {
+    /// Queued tasks, oldest first
+    queues: [VecDeque<Job>; 3],
+    
+    /// Running tasks
+    running: [usize; 3],
+    
+    /// Finished tasks
+    completed: [u64; 3],
+    
+    /// Largest number of running tasks
+    limits: [Option<usize>; 3],
+    
+    /// Largest number of running background tasks, of all classes
+    background_limit: Option<usize>,
+    
+    /// Set once the pool is dropped; workers leave when the queues are empty
+    shutdown: bool,
+}
+
+/// The result of a task, once it has run
+pub struct Task<T> {
+    /// Receives the result, or the panic the task raised
+    result: Receiver<thread::Result<T>>,
+    
+    /// Set when the task has run
+    finished: Arc<AtomicBool>,
+}
+
+impl Priority {
+    /// Every class, most urgent first
+    pub const ALL: [Priority; 3] = [Priority::Foreground, Priority::IndexMaintenance, Priority::Compaction];
+    
+    /// Get the position of this class in `ALL`
+    fn index(self) -> usize {
+        self as usize
+    }
+    
+    /// Check whether this class runs work nobody is waiting for
+    fn is_background(self) -> bool {
+        self != Priority::Foreground
+    }
+}
+
+impl PoolState {
+    /// Get the class whose oldest task should run next, if any may
+    fn next(&self, workers: usize) -> Option<Priority> {
+        let background: usize = Priority::ALL.iter()
+            .filter(|priority| priority.is_background())
+            .map(|priority| self.running[priority.index()])
+            .sum();
+        
+        Priority::ALL.into_iter().find(|priority| {
+            let class = priority.index();
+            !self.queues[class].is_empty()
+                && self.limits[class].is_none_or(|limit| self.running[class] < limit)
+                && (!priority.is_background() || self.background_may_start(background, workers))
+        })
+    }
+    
+    /// Check whether another background task may start beside those running
+    fn background_may_start(&self, background: usize, workers: usize) -> bool {
+        (workers == 1 || background + 1 < workers)
+            && self.background_limit.is_none_or(|limit| background < limit)
+    }
+}
+
+impl WorkerPool {
+    /// Start a pool of worker threads
+    ///
+    /// Index maintenance may use half the workers and compaction a
+    /// quarter, at least one each; foreground queries may use them all.
+    pub fn new(workers: usize) -> Self {
+        let workers = workers.max(1);
+        let shared = Arc::new(Shared {
+            state: Mutex::new(PoolState {
+                queues: Default::default(),
+                running: [0; 3],
+                completed: [0; 3],
+                limits: [None, Some((workers / 2).max(1)), Some((workers / 4).max(1))],
+                background_limit: None,
+                shutdown: false,
+            }),
+            changed: Condvar::new(),
+        });
+        
+        for number in 0..workers {
+            let shared = shared.clone();
+            let spawned = thread::Builder::new()
+                .name(format!("worker-bee-{}", number))
+                .spawn(move || work(&shared, workers));
+            if let Err(e) = spawned {
+                warn!("Failed to start worker bee {}: {}", number, e);
+            }
+        }
+        
+        Self { shared, workers }
+    }
+    
+    /// Set the largest number of tasks of a class running at once
+    pub fn with_limit(self, priority: Priority, limit: Option<usize>) -> Self {
+        self.set_limit(priority, limit);
+        self
+    }
+    
+    /// Change the largest number of tasks of a class running at once
+    pub fn set_limit(&self, priority: Priority, limit: Option<usize>) {
+        if let Ok(mut state) = self.shared.state.lock() {
+            state.limits[priority.index()] = limit.map(|limit| limit.max(1));
+        }
+        self.shared.changed.notify_all();
+    }
+    
+    /// Change the largest number of background tasks running at once, of all classes
+    pub fn set_background_limit(&self, limit: Option<usize>) {
+        if let Ok(mut state) = self.shared.state.lock() {
+            state.background_limit = limit.map(|limit| limit.max(1));
+        }
+        self.shared.changed.notify_all();
+    }
+    
+    /// Get the largest number of background tasks running at once, if limited
+    pub fn background_limit(&self) -> Option<usize> {
+        self.shared.state.lock().ok().and_then(|state| state.background_limit)
+    }
+    
+    /// Get the number of workers
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+    
+    /// Queue a task and return a handle to its result
+    pub fn submit<T, F>(&self, priority: Priority, task: F) -> Result<Task<T>, HiveError>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let (sender, result) = mpsc::sync_channel(1);
+        let finished = Arc::new(AtomicBool::new(false));
+        let done = finished.clone();
+        let job: Job = Box::new(move || {
+            let _ = sender.send(panic::catch_unwind(AssertUnwindSafe(task)));
+            done.store(true, Ordering::Release);
+        });
+        
+        self.shared.state.lock().map_err(|_| HiveError::LockError)?
+            .queues[priority.index()].push_back(job);
+        self.shared.changed.notify_all();
+        Ok(Task { result, finished })
+    }
+    
+    /// Run a task on a worker and wait for its result
+    pub fn run<T, F>(&self, priority: Priority, task: F) -> Result<T, HiveError>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        self.submit(priority, task)?.wait()
+    }
+    
+    /// Get the running and queued tasks of every class, most urgent first
+    pub fn stats(&self) -> Result<Vec<ClassStats>, HiveError> {
+        let state = self.shared.state.lock().map_err(|_| HiveError::LockError)?;
+        
+        Ok(Priority::ALL.into_iter()
+            .map(|priority| {
+                let class = priority.index();
+                ClassStats {
+                    priority,
+                    limit: state.limits[class],
+                    running: state.running[class],
+                    queued: state.queues[class].len(),
+                    completed: state.completed[class],
+                }
+            })
+            .collect())
+    }
+}
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.shared.state.lock() {
+            state.shutdown = true;
+        }
+        self.shared.changed.notify_all();
+    }
+}
+
+impl<T> Task<T> {
+    /// Check whether the task has run, successfully or not
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Acquire)
+    }
+    
+    /// Wait for the task to run and get its result
+    pub fn wait(self) -> Result<T, HiveError> {
+        match self.result.recv() {
+            Ok(Ok(value)) => Ok(value),
+            Ok(Err(_)) => Err(HiveError::GenericError("Task panicked".to_string())),
+            Err(_) => Err(HiveError::GenericError("Task was dropped before it ran".to_string())),
+        }
+    }
+}
+
+impl RateLimiter {
+    /// Create a limiter without a limit
+    pub const fn new() -> Self {
+        Self {
+            state: Mutex::new((None, Duration::ZERO)),
+        }
+    }
+    
+    /// Change the rate, in bytes per second (None lifts the limit)
+    pub fn set_rate(&self, bytes_per_sec: Option<f64>) {
+        if let Ok(mut state) = self.state.lock() {
+            *state = (bytes_per_sec.filter(|rate| *rate > 0.0), Duration::ZERO);
+        }
+    }
+    
+    /// Get the rate, in bytes per second, if limited
+    pub fn rate(&self) -> Option<f64> {
+        self.state.lock().ok().and_then(|state| state.0)
+    }
+    
+    /// Count bytes moved at a time, and get how long to pause to stay under the rate
+    ///
+    /// Time spent idle is not saved up for later bursts.
+    pub fn delay(&self, bytes: u64, now: Duration) -> Duration {
+        let Ok(mut state) = self.state.lock() else {
+            return Duration::ZERO;
+        };
+        let Some(rate) = state.0 else {
+            return Duration::ZERO;
+        };
+        
+        state.1 = state.1.max(now) + Duration::from_secs_f64(bytes as f64 / rate);
+        state.1 - now
+    }
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Run the tasks of a pool as they are given to this worker
+fn work(shared: &Shared, workers: usize) {
+    let Ok(mut state) = shared.state.lock() else {
+        return;
+    };
+    
+    loop {
+        let Some(priority) = state.next(workers) else {
+            if state.shutdown && state.queues.iter().all(VecDeque::is_empty) {
+                return;
+            }
+            state = match shared.changed.wait(state) {
+                Ok(state) => state,
+                Err(_) => return,
+            };
+            continue;
+        };
+        
+        let class = priority.index();
+        let Some(job) = state.queues[class].pop_front() else {
+            continue;
+        };
+        state.running[class] += 1;
+        drop(state);
+        
+        job();
+        
+        state = match shared.state.lock() {
+            Ok(state) => state,
+            Err(_) => return,
+        };
+        state.running[class] -= 1;
+        state.completed[class] += 1;
+        shared.changed.notify_all();
+    }
+}
+
+/// Make a pool the one shared by the server
+///
+/// Fails if the shared pool was already started.
+pub fn init(pool: WorkerPool) -> Result<&'static WorkerPool, HiveError> {
+    let workers = pool.workers();
+    POOL.set(pool)
+        .map_err(|_| HiveError::GenericError("The worker pool is already running".to_string()))?;
+    info!("Started {} worker bees", workers);
+    Ok(self::pool())
+}
+
+/// Limit the background work of this process
+pub fn set_background_limits(limits: BackgroundLimits) {
+    THROTTLE.set_rate(limits.max_mb_per_sec.map(|mb| mb * MB));
+    pool().set_background_limit(limits.max_tasks);
+    info!("Background work limited to {:?} MB/s and {:?} tasks at once", limits.max_mb_per_sec, limits.max_tasks);
+}
+
+/// Get the limits on the background work of this process
+pub fn background_limits() -> BackgroundLimits {
+    BackgroundLimits {
+        max_mb_per_sec: THROTTLE.rate().map(|rate| rate / MB),
+        max_tasks: POOL.get().and_then(WorkerPool::background_limit),
+    }
+}
+
+/// Pause a background job that moved some bytes, to keep it under the rate limit
+///
+/// Jobs call this between units of work, never while holding a hive lock.
+pub fn throttle(bytes: u64) {
+    let delay = THROTTLE.delay(bytes, determinism::monotonic());
+    if !delay.is_zero() {
+        thread::sleep(delay);
+    }
+}
+
+/// Get the worker pool shared by the server
+///
+/// Unless `init` set it up, it has a worker per available CPU, and at least two.
+pub fn pool() -> &'static WorkerPool {
+    POOL.get_or_init(|| {
+        let cpus = thread::available_parallelism().map_or(2, |cpus| cpus.get());
+        WorkerPool::new(cpus.max(2))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+    
+    #[test]
+    fn test_priorities_and_limits() {
+        let pool = WorkerPool::new(2).with_limit(Priority::Compaction, Some(1));
+        
+        // Hold both workers until the queue below is in place
+        let (release, gate) = channel::<()>();
+        let gate = Arc::new(Mutex::new(gate));
+        let blockers: Vec<_> = (0..2)
+            .map(|_| {
+                let gate = gate.clone();
+                pool.submit(Priority::Foreground, move || { gate.lock().unwrap().recv().unwrap(); }).unwrap()
+            })
+            .collect();
+        while pool.stats().unwrap()[0].running < 2 {
+            thread::sleep(Duration::from_millis(1));
+        }
+        
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let record = |priority: Priority, name: &'static str| {
+            let order = order.clone();
+            pool.submit(priority, move || order.lock().unwrap().push(name)).unwrap()
+        };
+        let tasks = vec![
+            record(Priority::Compaction, "compaction"),
+            record(Priority::IndexMaintenance, "index"),
+            record(Priority::Foreground, "query"),
+        ];
+        assert_eq!(pool.stats().unwrap().iter().map(|class| class.queued).sum::<usize>(), 3);
+        
+        // With one worker free, the query goes first and the rest follow by class
+        release.send(()).unwrap();
+        for task in tasks {
+            task.wait().unwrap();
+        }
+        assert_eq!(*order.lock().unwrap(), vec!["query", "index", "compaction"]);
+        
+        release.send(()).unwrap();
+        for task in blockers {
+            task.wait().unwrap();
+        }
+        let stats = pool.stats().unwrap();
+        assert_eq!((stats[0].completed, stats[1].completed, stats[2].completed), (3, 1, 1));
+        assert_eq!(stats[2].limit, Some(1));
+        
+        assert_eq!(pool.run(Priority::Foreground, || 6 * 7).unwrap(), 42);
+        assert!(pool.run(Priority::Compaction, || panic!("boom")).is_err());
+    }
+    
+    #[test]
+    fn test_background_limits() {
+        let pool = WorkerPool::new(4);
+        let mut state = pool.shared.state.lock().unwrap();
+        state.queues[Priority::Compaction.index()].push_back(Box::new(|| {}));
+        state.running[Priority::IndexMaintenance.index()] = 1;
+        assert_eq!(state.next(4), Some(Priority::Compaction));
+        
+        state.background_limit = Some(1);
+        assert_eq!(state.next(4), None);
+        state.queues[Priority::Foreground.index()].push_back(Box::new(|| {}));
+        assert_eq!(state.next(4), Some(Priority::Foreground));
+        state.queues.iter_mut().for_each(VecDeque::clear);
+        state.running = [0; 3];
+        drop(state);
+        
+        // 1 MB/s: each megabyte is paid off a second after the last one
+        let limiter = RateLimiter::new();
+        assert_eq!(limiter.delay(1 << 20, Duration::from_secs(10)), Duration::ZERO);
+        limiter.set_rate(Some(MB));
+        assert_eq!(limiter.delay(1 << 20, Duration::from_secs(10)), Duration::from_secs(1));
+        assert_eq!(limiter.delay(1 << 19, Duration::from_secs(10)), Duration::from_millis(1500));
+        assert_eq!(limiter.delay(1 << 20, Duration::from_secs(20)), Duration::from_secs(1));
+    }
+}
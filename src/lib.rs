@@ -4,7 +4,6 @@
 // for the HiveDB database system.
 
 pub mod core;
-pub mod storage;
 pub mod security;
 pub mod network;
 pub mod utils;
@@ -12,6 +11,9 @@ pub mod utils;
 #[cfg(feature = "wasm")]
 pub mod wasm;
 
+#[cfg(feature = "async")]
+pub mod r#async;
+
 use log::{info, LevelFilter};
 use std::error::Error;
 
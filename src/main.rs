@@ -1,7 +1,94 @@
+use hivedb::core::codec::Integrity;
+use hivedb::core::compression;
+use hivedb::core::coords::GridOrigin;
+use hivedb::core::disk::{self, DiskMonitor};
+use hivedb::core::durability::Durability;
+use hivedb::core::error::HiveError;
+use hivedb::core::events;
+use hivedb::core::hive::{Hive, HiveManager};
+use hivedb::core::history::{self, Trend};
+use hivedb::core::memory;
+use hivedb::core::mode::ModeControl;
+use hivedb::core::query::{FilterExpression, HqlParser, Query, QueryExecutor, QueryType};
+use hivedb::core::remote::SnapshotStore;
+use hivedb::core::retention::{self, ArchiveTarget, RetentionReport, RetentionRule};
+use hivedb::core::schema::Schema;
+use hivedb::core::scheduler::{QueryScheduler, SchedulerConfig};
+use hivedb::core::session::SessionRegistry;
+use hivedb::core::tiering::{self, ColdTier, TieringPolicy};
+use hivedb::core::worker::{self, BackgroundLimits, Priority, WorkerPool};
+use hivedb::network::admin::{self, AdminApi};
+use hivedb::network::cdc::{CdcConfig, CdcSink, KafkaSink, ManagerPublisher, NatsSink};
+use hivedb::network::http::{self, HttpRequest, HttpResponse};
+use hivedb::network::pgwire::PgServer;
+use hivedb::network::resp::{RespHandler, RespServer};
+use hivedb::network::s3::{S3Config, S3Store};
+use hivedb::network::webhooks::{self, WebhookRegistry};
+use hivedb::security::ldap::{LdapConfig, LdapProvider};
+use hivedb::security::oidc::OidcProvider;
+use hivedb::security::{Access, ApiKeyStore, Authenticator, JwtConfig, JwtValidator, QueryAllowlist, RoleMapping, UserStore};
+use hivedb::utils::backup::{self, BackupEntry, BackupKey, BackupOptions, KeySource};
+use hivedb::utils::bench::{self, BenchConfig, BenchTarget, EmbeddedTarget, RemoteTarget, Workload};
+use hivedb::utils::daemon::{self, PidFile, ServerStatus};
+use hivedb::utils::doctor::{self, Severity};
+use hivedb::utils::format::{self, OutputFormat};
+use hivedb::utils::seed::{self, SeedGenerator};
+use hivedb::utils::telemetry::{self, OtlpConfig};
 use hivedb::{core, init, name, version};
-use log::{error, info};
+use log::{error, info, warn};
+use serde_json::{json, Value};
 use std::env;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
 use std::process;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// Exit code for a query that failed to parse or run
+const EXIT_QUERY_ERROR: i32 = 1;
+
+/// Exit code for invalid command-line arguments
+const EXIT_USAGE: i32 = 2;
+
+/// Exit code when the hive cannot be found or loaded
+const EXIT_UNAVAILABLE: i32 = 3;
+
+/// Default grid dimensions for hives created from the CLI
+const DEFAULT_DIMENSIONS: (usize, usize) = (64, 64);
+
+/// Default address of the PostgreSQL protocol listener
+const DEFAULT_PG_ADDR: &str = "127.0.0.1:5432";
+
+/// Default address of the admin API
+const DEFAULT_ADMIN_ADDR: &str = "127.0.0.1:8090";
+
+/// Default address of the Redis protocol listener
+const DEFAULT_RESP_ADDR: &str = "127.0.0.1:6379";
+
+/// How often the server checks for a stop request, and `start`/`stop` for progress
+const SERVER_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Checks `start --daemon` makes before giving up on the server (10 seconds)
+const SERVER_STARTUP_POLLS: u32 = 50;
+
+/// Checks `stop` makes before giving up on the server (30 seconds)
+const SERVER_SHUTDOWN_POLLS: u32 = 150;
+
+/// How often the server checks the free space of the data directory
+const DISK_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often the server moves cold cells to the cold tier
+const TIERING_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// How often the server applies the retention rules of the hives
+const RETENTION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Number of documents `hivedb seed` inserts per query
+const SEED_BATCH_SIZE: usize = 1000;
+
+/// How often `hivedb watch` polls the server for new changes
+const WATCH_POLL_INTERVAL: Duration = Duration::from_millis(500);
 
 /// Main entry point for the HiveDB CLI
 fn main() {
@@ -21,11 +108,20 @@ fn main() {
         }
         "start" => {
             info!("Starting HiveDB server...");
-            if let Err(e) = start_server() {
+            if let Err(e) = start_server(&args[2..]) {
                 error!("Server error: {}", e);
                 process::exit(1);
             }
         }
+        "stop" => {
+            if let Err(e) = stop_server(&args[2..]) {
+                error!("Failed to stop server: {}", e);
+                process::exit(1);
+            }
+        }
+        "status" => {
+            process::exit(server_status());
+        }
         "create" => {
             if args.len() < 3 {
                 println!("Error: Missing hive name");
@@ -34,35 +130,1926 @@ fn main() {
             }
             let hive_name = &args[2];
             info!("Creating new hive: {}", hive_name);
-            if let Err(e) = create_hive(hive_name) {
+            if let Err(e) = create_hive(hive_name, &args[3..]) {
                 error!("Failed to create hive: {}", e);
                 process::exit(1);
             }
             println!("✅ Hive '{}' created successfully", hive_name);
         }
+        "query" => {
+            process::exit(query_command(&args[2..]));
+        }
+        "bench" => {
+            if let Err(e) = bench_command(&args[2..]) {
+                error!("Benchmark failed: {}", e);
+                process::exit(1);
+            }
+        }
+        "inspect" => {
+            if let Err(e) = inspect_command(&args[2..]) {
+                error!("Inspect failed: {}", e);
+                process::exit(1);
+            }
+        }
+        "verify" => {
+            match verify_command(&args[2..]) {
+                Ok(true) => {}
+                Ok(false) => process::exit(1),
+                Err(e) => {
+                    error!("Verify failed: {}", e);
+                    process::exit(2);
+                }
+            }
+        }
+        "doctor" => {
+            match doctor_command(&args[2..]) {
+                Ok(true) => {}
+                Ok(false) => process::exit(1),
+                Err(e) => {
+                    error!("Doctor failed: {}", e);
+                    process::exit(2);
+                }
+            }
+        }
+        "index" => {
+            if let Err(e) = index_command(&args[2..]) {
+                error!("Index command failed: {}", e);
+                process::exit(1);
+            }
+        }
+        "schema" => {
+            if let Err(e) = schema_command(&args[2..]) {
+                error!("Schema command failed: {}", e);
+                process::exit(1);
+            }
+        }
+        "retention" => {
+            if let Err(e) = retention_command(&args[2..]) {
+                error!("Retention command failed: {}", e);
+                process::exit(1);
+            }
+        }
+        "rebalance" => {
+            if let Err(e) = rebalance_command(&args[2..]) {
+                error!("Rebalance failed: {}", e);
+                process::exit(1);
+            }
+        }
+        "dictionary" => {
+            if let Err(e) = dictionary_command(&args[2..]) {
+                error!("Dictionary training failed: {}", e);
+                process::exit(1);
+            }
+        }
+        "backup" => {
+            if let Err(e) = backup_command(&args[2..]) {
+                error!("Backup failed: {}", e);
+                process::exit(1);
+            }
+        }
+        "restore" => {
+            if let Err(e) = restore_command(&args[2..]) {
+                error!("Restore failed: {}", e);
+                process::exit(1);
+            }
+        }
+        "seed" => {
+            if let Err(e) = seed_command(&args[2..]) {
+                error!("Seed failed: {}", e);
+                process::exit(1);
+            }
+        }
+        "watch" => {
+            if let Err(e) = watch_command(&args[2..]) {
+                error!("Watch failed: {}", e);
+                process::exit(1);
+            }
+        }
+        "policy" => {
+            if let Err(e) = policy_command(&args[2..]) {
+                error!("Policy command failed: {}", e);
+                process::exit(1);
+            }
+        }
+        "user" => {
+            if let Err(e) = user_command(&args[2..]) {
+                error!("User command failed: {}", e);
+                process::exit(1);
+            }
+        }
         "help" | _ => {
             print_usage();
         }
     }
 }
 
-/// Start the HiveDB server
-fn start_server() -> Result<(), Box<dyn std::error::Error>> {
-    println!("🐝 {} v{} server started", name(), version());
+/// Start the HiveDB server, in the foreground or with --daemon in the background
+///
+/// The PostgreSQL protocol listener binds HIVEDB_PG_ADDR. When
+/// HIVEDB_RESP_HIVE names a hive, created if needed, Redis clients can use
+/// its key-value cells through a listener on HIVEDB_RESP_ADDR. The admin API
+/// binds HIVEDB_ADMIN_ADDR and only starts when HIVEDB_ADMIN_TOKEN is set;
+/// API keys it creates are kept in the data directory and accepted in
+/// place of the token. When HIVEDB_JWT_ISSUER is set, JWTs from that
+/// issuer are accepted too, checked against the JWKS file named by
+/// HIVEDB_JWT_JWKS or the HIVEDB_JWT_SECRET shared secret, for
+/// HIVEDB_JWT_AUDIENCE if set; tokens with the HIVEDB_JWT_ADMIN_ROLE role
+/// (`admin` by default) are administrators, and HIVEDB_ADMIN_TOKEN may be
+/// left empty. Members of the HIVEDB_AUTH_ADMIN_GROUPS groups are
+/// administrators too when they sign in with a password checked by the
+/// LDAP directory at HIVEDB_LDAP_ADDR (binding as HIVEDB_LDAP_USER_DN,
+/// with groups under HIVEDB_LDAP_GROUP_BASE), or with an ID token from
+/// the OpenID Connect provider HIVEDB_OIDC_ISSUER, checked against the
+/// HIVEDB_OIDC_JWKS file for HIVEDB_OIDC_AUDIENCE if set.
+/// Traces are exported when OTEL_EXPORTER_OTLP_ENDPOINT is set, and
+/// HIVEDB_MEMORY_LIMIT (e.g. `512M` or `2G`) bounds the memory used by
+/// caches and queries. HIVEDB_DURABILITY (`always`, `buffered` or an
+/// interval such as `200ms`) sets when changed hives are saved. When
+/// HIVEDB_COLD_STORE names a bucket, cells unused for HIVEDB_COLD_AFTER
+/// (e.g. `7d`) are moved there. When HIVEDB_SNAPSHOT_STORE names one, the
+/// hive snapshots are kept there and the data directory only caches them.
+/// Writes are refused while the data directory has less free space than
+/// HIVEDB_MIN_FREE_SPACE (256M by default, 0 to turn off). Queries share
+/// the server fairly between users when HIVEDB_MAX_QUERIES or
+/// HIVEDB_TENANT_MAX_QUERIES limits the queries running at once. Admin API
+/// responses of HIVEDB_COMPRESSION_THRESHOLD or more (1K by default, 0 to
+/// turn off) are compressed for clients that accept it, and hives it
+/// creates may have at most HIVEDB_ADMIN_MAX_GRID_POSITIONS positions. When
+/// HIVEDB_QUERY_ALLOWLIST names a JSON file of named statements,
+/// PostgreSQL clients may only EXECUTE those. Hive statistics are sampled
+/// into a history every HIVEDB_STATS_INTERVAL (1h by default, 0 to turn
+/// off), for `hivedb inspect --history`. Retention rules set with
+/// `hivedb retention` are applied every hour. Queries, index builds and
+/// housekeeping run on HIVEDB_WORKER_THREADS worker bees (one per CPU by
+/// default), queries first; HIVEDB_BACKGROUND_MAX_TASKS caps the
+/// background tasks running at once and HIVEDB_BACKGROUND_MB_PER_SEC the
+/// data they move, and both can be changed through the admin API. When
+/// HIVEDB_CDC_KAFKA_BROKERS or HIVEDB_CDC_NATS_ADDR is set, the changes of
+/// every hive are published there, to HIVEDB_CDC_TOPIC (`hivedb.changes`
+/// by default) in HIVEDB_CDC_FORMAT (`json` or `debezium`). Webhooks
+/// registered through the admin API are kept in the data directory and
+/// notified of the changes made while the server runs.
+fn start_server(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let dir = data_dir();
+    
+    if args.iter().any(|arg| arg == "--daemon") {
+        if let ServerStatus::Running(pid) = daemon::status(&dir) {
+            return Err(HiveError::AlreadyRunning(pid).into());
+        }
+        
+        let mut child = daemon::spawn(&dir, &["start"])?;
+        let log = dir.join(daemon::LOG_FILE);
+        
+        // Wait until the server has claimed the data directory, or failed to
+        for _ in 0..SERVER_STARTUP_POLLS {
+            if let Some(status) = child.try_wait()? {
+                return Err(format!("server exited during startup ({}); see {}", status, log.display()).into());
+            }
+            if daemon::status(&dir) == ServerStatus::Running(child.id()) {
+                println!("🐝 HiveDB started in the background (PID {})", child.id());
+                println!("Logging to {}", log.display());
+                return Ok(());
+            }
+            std::thread::sleep(SERVER_POLL_INTERVAL);
+        }
+        
+        return Err(format!("server did not start in time; see {}", log.display()).into());
+    }
+    
+    let _pid_file = PidFile::acquire(&dir)?;
+    if let Some(config) = OtlpConfig::from_env() {
+        telemetry::init(config)?;
+    }
+    if let Ok(limit) = env::var("HIVEDB_MEMORY_LIMIT") {
+        let limit = memory::parse_size(&limit)?;
+        memory::global().set_limit(Some(limit));
+        info!("Memory budget set to {} bytes", limit);
+    }
+    if let Ok(workers) = env::var("HIVEDB_WORKER_THREADS") {
+        worker::init(WorkerPool::new(workers.parse()?))?;
+    }
+    let limits = background_limits()?;
+    if limits != BackgroundLimits::default() {
+        worker::set_background_limits(limits);
+    }
+    let manager = open_hives()?;
+    let audit = manager.events().subscribe()?;
+    std::thread::spawn(move || events::audit_log(audit));
+    let manager = Arc::new(RwLock::new(manager));
+    let durability = manager.read().map_err(|_| "hive manager lock poisoned")?.durability();
+    info!("Durability: {}", durability);
+    if let Durability::Interval(interval) = durability {
+        let manager = manager.clone();
+        std::thread::spawn(move || loop {
+            std::thread::sleep(interval);
+            let flushed = manager.read().map_err(|_| HiveError::LockError).and_then(|manager| manager.flush());
+            if let Err(e) = flushed {
+                error!("Failed to save changed hives: {}", e);
+            }
+        });
+    }
+    if let Some(tier) = tiering::cold_tier()? {
+        let policy = tiering_policy()?;
+        info!("Moving cells unused for {}s to the cold tier", policy.cold_after.as_secs());
+        let manager = manager.clone();
+        std::thread::spawn(move || loop {
+            std::thread::sleep(TIERING_INTERVAL);
+            let (manager, tier) = (manager.clone(), tier.clone());
+            let offloaded = worker::pool().run(Priority::Compaction, move || {
+                manager.read().map_err(|_| HiveError::LockError).and_then(|manager| manager.offload_cold(&policy, &tier))
+            });
+            if let Err(e) = offloaded.and_then(|offloaded| offloaded) {
+                error!("Failed to move cold cells: {}", e);
+            }
+        });
+    }
+    {
+        let manager = manager.clone();
+        std::thread::spawn(move || loop {
+            std::thread::sleep(RETENTION_INTERVAL);
+            let manager = manager.clone();
+            let applied = worker::pool().run(Priority::Compaction, move || {
+                let created = manager.write().map_err(|_| HiveError::LockError)
+                    .and_then(|mut manager| retention::create_archive_hives(&mut manager));
+                created.and_then(|_| {
+                    manager.read().map_err(|_| HiveError::LockError)
+                        .and_then(|manager| retention::apply_all(&manager, false))
+                })
+            });
+            if let Err(e) = applied.and_then(|applied| applied) {
+                error!("Failed to apply retention rules: {}", e);
+            }
+        });
+    }
+    if let Some(interval) = stats_interval()? {
+        let history = history::open(&mut *manager.write().map_err(|_| "hive manager lock poisoned")?)?;
+        info!("Sampling hive statistics every {}s", interval.as_secs());
+        let manager = manager.clone();
+        std::thread::spawn(move || loop {
+            std::thread::sleep(interval);
+            let recorded = manager.read().map_err(|_| HiveError::LockError)
+                .and_then(|manager| history::record(&manager, &history, history::DEFAULT_RETENTION));
+            if let Err(e) = recorded {
+                error!("Failed to record hive statistics: {}", e);
+            }
+        });
+    }
+    if let Some(mut sink) = cdc_sink()? {
+        let config = CdcConfig {
+            topic: env::var("HIVEDB_CDC_TOPIC").unwrap_or_else(|_| CdcConfig::default().topic),
+            format: match env::var("HIVEDB_CDC_FORMAT") {
+                Ok(format) => format.parse()?,
+                Err(_) => CdcConfig::default().format,
+            },
+            ..CdcConfig::default()
+        };
+        info!("Publishing change events to '{}' as {:?}", config.topic, config.format);
+        let manager = manager.clone();
+        std::thread::spawn(move || {
+            ManagerPublisher::new(config).tail(&manager, sink.as_mut(), &AtomicBool::new(false));
+        });
+    }
+    let webhooks = Arc::new(RwLock::new(WebhookRegistry::open(dir.join("webhooks.json"))?));
+    {
+        let (webhooks, manager) = (webhooks.clone(), manager.clone());
+        std::thread::spawn(move || webhooks::deliver_changes(&webhooks, &manager, &AtomicBool::new(false)));
+    }
+    let mut sessions = SessionRegistry::new();
+    if let Some(config) = scheduler_config()? {
+        info!(
+            "Scheduling queries: {:?} at once, {:?} per user",
+            config.max_running, config.max_per_tenant
+        );
+        sessions = sessions.with_scheduler(Arc::new(QueryScheduler::new(config)));
+    }
+    let sessions = Arc::new(sessions);
+    let mut mode = ModeControl::default();
+    let min_free = match env::var("HIVEDB_MIN_FREE_SPACE") {
+        Ok(min_free) => memory::parse_size(&min_free)? as u64,
+        Err(_) => disk::DEFAULT_MIN_FREE,
+    };
+    if min_free > 0 {
+        let monitor = Arc::new(DiskMonitor::new(dir.clone(), min_free));
+        match monitor.check() {
+            Ok(_) => {
+                mode = mode.with_disk_monitor(monitor.clone());
+                std::thread::spawn(move || loop {
+                    std::thread::sleep(DISK_CHECK_INTERVAL);
+                    if let Err(e) = monitor.check() {
+                        error!("Failed to check free disk space: {}", e);
+                    }
+                });
+            }
+            Err(e) => warn!("Free disk space is not monitored: {}", e),
+        }
+    }
+    let mode = Arc::new(mode);
+    
+    let pg_address = env::var("HIVEDB_PG_ADDR").unwrap_or_else(|_| DEFAULT_PG_ADDR.to_string());
+    let mut pg = PgServer::with_sessions(manager.clone(), sessions.clone()).with_mode(mode.clone());
+    if let Ok(path) = env::var("HIVEDB_QUERY_ALLOWLIST") {
+        let allowlist = QueryAllowlist::load(Path::new(&path))?;
+        info!("PostgreSQL clients are limited to {} allowlisted statements", allowlist.names().len());
+        pg = pg.with_allowlist(allowlist);
+    }
+    std::thread::spawn(move || {
+        if let Err(e) = pg.serve(&pg_address) {
+            error!("PostgreSQL protocol listener failed: {}", e);
+        }
+    });
+    if let Ok(name) = env::var("HIVEDB_RESP_HIVE") {
+        let resp_address = env::var("HIVEDB_RESP_ADDR").unwrap_or_else(|_| DEFAULT_RESP_ADDR.to_string());
+        let hive = {
+            let mut manager = manager.write().map_err(|_| "hive manager lock poisoned")?;
+            match manager.get_hive_by_name(&name) {
+                Some(hive) => hive,
+                None => {
+                    let id = manager.create_hive_from(Hive::builder().name(name.clone()))?;
+                    manager.get_hive(&id).ok_or(HiveError::HiveNotFound)?
+                }
+            }
+        };
+        info!("Serving the keys of hive '{}' to Redis clients", name);
+        let resp = RespServer::from_handler(RespHandler::new(hive).with_mode(mode.clone()));
+        std::thread::spawn(move || {
+            if let Err(e) = resp.serve(&resp_address) {
+                error!("Redis protocol listener failed: {}", e);
+            }
+        });
+    }
+    
+    if let Ok(token) = env::var("HIVEDB_ADMIN_TOKEN") {
+        let admin_address = env::var("HIVEDB_ADMIN_ADDR").unwrap_or_else(|_| DEFAULT_ADMIN_ADDR.to_string());
+        let threshold = match env::var("HIVEDB_COMPRESSION_THRESHOLD") {
+            Ok(threshold) => Some(memory::parse_size(&threshold)?).filter(|threshold| *threshold > 0),
+            Err(_) => Some(http::DEFAULT_COMPRESSION_THRESHOLD),
+        };
+        let max_positions = match env::var("HIVEDB_ADMIN_MAX_GRID_POSITIONS") {
+            Ok(max_positions) => max_positions.parse()?,
+            Err(_) => admin::DEFAULT_MAX_GRID_POSITIONS,
+        };
+        let api_keys = ApiKeyStore::open(dir.join("api_keys.json"))?;
+        let mut admin = AdminApi::new(manager.clone(), token);
+        if let Some(validator) = jwt_validator()? {
+            info!("Admin API accepts JWTs issued by {}", validator.config().issuer);
+            admin = admin.with_jwt(Arc::new(validator));
+        }
+        if let Some(authenticator) = authenticator()? {
+            admin = admin.with_authenticator(Arc::new(authenticator));
+        }
+        let admin = Arc::new(admin
+            .with_compression_threshold(threshold)
+            .with_max_grid_positions(max_positions)
+            .with_sessions(sessions)
+            .with_mode(mode)
+            .with_api_keys(Arc::new(RwLock::new(api_keys)))
+            .with_users(Arc::new(RwLock::new(open_users()?)))
+            .with_webhooks(webhooks));
+        std::thread::spawn(move || {
+            if let Err(e) = admin.serve(&admin_address) {
+                error!("Admin API failed: {}", e);
+            }
+        });
+    }
+    
+    println!("🐝 {} v{} server started (PID {})", name(), version(), process::id());
     println!("Listening for connections...");
     
-    // TODO: Implement actual server logic
+    while !daemon::stop_requested(&dir) {
+        std::thread::sleep(SERVER_POLL_INTERVAL);
+    }
+    
+    info!("Stop requested; saving hives");
+    manager.read().map_err(|_| "hive manager lock poisoned")?.save_all()?;
+    daemon::clear_stop_request(&dir);
+    info!("HiveDB server stopped");
+    
+    // The listener threads are blocked in accept, so exit rather than join them
+    process::exit(0);
+}
+
+/// Stop the server running on the data directory
+fn stop_server(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let dir = data_dir();
+    let force = args.iter().any(|arg| arg == "--force");
+    
+    let pid = match daemon::status(&dir) {
+        ServerStatus::Running(pid) => pid,
+        ServerStatus::Stale(pid) => {
+            std::fs::remove_file(dir.join(daemon::PID_FILE))?;
+            println!("HiveDB is not running (removed the stale PID file of process {})", pid);
+            return Ok(());
+        }
+        ServerStatus::Stopped => {
+            println!("HiveDB is not running");
+            return Ok(());
+        }
+    };
+    
+    daemon::request_stop(&dir)?;
+    for _ in 0..SERVER_SHUTDOWN_POLLS {
+        if !daemon::is_running(pid) {
+            println!("✅ HiveDB stopped (PID {})", pid);
+            return Ok(());
+        }
+        std::thread::sleep(SERVER_POLL_INTERVAL);
+    }
+    
+    if !force {
+        return Err(format!("server with PID {} did not stop in time; use --force to kill it", pid).into());
+    }
     
+    daemon::kill(pid)?;
+    daemon::clear_stop_request(&dir);
+    std::fs::remove_file(dir.join(daemon::PID_FILE))?;
+    println!("⚠️  HiveDB killed (PID {}); unsaved changes were lost", pid);
     Ok(())
 }
 
+/// Print whether a server is running and return the exit code
+fn server_status() -> i32 {
+    match daemon::status(&data_dir()) {
+        ServerStatus::Running(pid) => {
+            println!("HiveDB is running (PID {})", pid);
+            0
+        }
+        ServerStatus::Stale(pid) => {
+            println!("HiveDB is not running (stale PID file of process {})", pid);
+            EXIT_UNAVAILABLE
+        }
+        ServerStatus::Stopped => {
+            println!("HiveDB is not running");
+            EXIT_UNAVAILABLE
+        }
+    }
+}
+
 /// Create a new hive (database)
-fn create_hive(name: &str) -> Result<(), Box<dyn std::error::Error>> {
-    // TODO: Implement hive creation logic
+fn create_hive(name: &str, args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    const USAGE: &str = "usage: hivedb create <name> [--origin corner|center] [--integrity sha256|crc32c]";
+    let mut origin = GridOrigin::Corner;
+    let mut integrity = Integrity::default();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--origin" => origin = iter.next().ok_or(USAGE)?.parse()?,
+            "--integrity" => integrity = iter.next().ok_or(USAGE)?.parse()?,
+            _ => return Err(USAGE.into()),
+        }
+    }
+    
+    let mut manager = open_hives()?;
+    if manager.get_hive_by_name(name).is_some() {
+        return Err(format!("a hive named '{}' already exists", name).into());
+    }
+    
+    let owner = env::var("USER").unwrap_or_else(|_| "hivedb".to_string());
+    let id = manager.create_hive(name.to_string(), String::new(), owner, DEFAULT_DIMENSIONS)?;
+    
+    let hive = manager.get_hive(&id).ok_or("hive disappeared after creation")?;
+    let mut hive = hive.write().map_err(|_| "hive lock poisoned")?;
+    hive.cells.set_origin(origin)?;
+    hive.cells.set_integrity(integrity);
+    hive.save()?;
     
     Ok(())
 }
 
+/// Open the hives stored in the data directory
+///
+/// The cold tier named by HIVEDB_COLD_STORE is installed first, so cold
+/// cells can be read as the hives load. With HIVEDB_SNAPSHOT_STORE, the
+/// snapshots are fetched from that bucket before loading.
+fn open_hives() -> Result<HiveManager, Box<dyn std::error::Error>> {
+    let durability = match env::var("HIVEDB_DURABILITY") {
+        Ok(durability) => durability.parse()?,
+        Err(_) => Durability::default(),
+    };
+    if let Ok(url) = env::var("HIVEDB_COLD_STORE") {
+        tiering::install(ColdTier::new(S3Store::new(S3Config::from_env(&url)?)))?;
+        info!("Cold tier: {}", url);
+    }
+    let mut manager = HiveManager::builder()
+        .base_path(data_dir())
+        .durability(durability)
+        .load_existing(true);
+    if let Ok(url) = env::var("HIVEDB_SNAPSHOT_STORE") {
+        manager = manager.snapshot_store(SnapshotStore::new(S3Store::new(S3Config::from_env(&url)?)));
+        info!("Snapshot store: {}", url);
+    }
+    
+    Ok(manager.build()?)
+}
+
+/// Get the change event sink named by HIVEDB_CDC_KAFKA_BROKERS or HIVEDB_CDC_NATS_ADDR
+///
+/// Returns None when neither is set, and changes are not published.
+fn cdc_sink() -> Result<Option<Box<dyn CdcSink>>, Box<dyn std::error::Error>> {
+    match (env::var("HIVEDB_CDC_KAFKA_BROKERS"), env::var("HIVEDB_CDC_NATS_ADDR")) {
+        (Ok(_), Ok(_)) => Err("set only one of HIVEDB_CDC_KAFKA_BROKERS and HIVEDB_CDC_NATS_ADDR".into()),
+        (Ok(brokers), Err(_)) => Ok(Some(Box::new(KafkaSink::new(&brokers)?))),
+        (Err(_), Ok(address)) => Ok(Some(Box::new(NatsSink::connect(&address)?))),
+        (Err(_), Err(_)) => Ok(None),
+    }
+}
+
+/// Build the validator for admin JWTs from HIVEDB_JWT_ISSUER and related settings
+///
+/// Returns None when HIVEDB_JWT_ISSUER is not set.
+fn jwt_validator() -> Result<Option<JwtValidator>, Box<dyn std::error::Error>> {
+    let Ok(issuer) = env::var("HIVEDB_JWT_ISSUER") else {
+        return Ok(None);
+    };
+    
+    let admin_role = env::var("HIVEDB_JWT_ADMIN_ROLE").unwrap_or_else(|_| "admin".to_string());
+    let mut config = JwtConfig::new(&issuer).with_role(&admin_role, Access::Admin);
+    if let Ok(audience) = env::var("HIVEDB_JWT_AUDIENCE") {
+        config = config.with_audience(&audience);
+    }
+    
+    let (jwks, secret) = (env::var("HIVEDB_JWT_JWKS").ok(), env::var("HIVEDB_JWT_SECRET").ok());
+    if jwks.is_none() && secret.is_none() {
+        return Err("HIVEDB_JWT_ISSUER needs HIVEDB_JWT_JWKS or HIVEDB_JWT_SECRET".into());
+    }
+    
+    let mut validator = JwtValidator::new(config);
+    if let Some(path) = jwks {
+        validator.set_jwks(&std::fs::read_to_string(path)?)?;
+    }
+    if let Some(secret) = secret {
+        validator = validator.with_secret(secret.as_bytes());
+    }
+    
+    Ok(Some(validator))
+}
+
+/// Build the external authentication providers from the HIVEDB_LDAP_* and HIVEDB_OIDC_* settings
+///
+/// Returns None when neither HIVEDB_LDAP_ADDR nor HIVEDB_OIDC_ISSUER is
+/// set. Members of the HIVEDB_AUTH_ADMIN_GROUPS groups get admin access.
+fn authenticator() -> Result<Option<Authenticator>, Box<dyn std::error::Error>> {
+    let (ldap, oidc) = (env::var("HIVEDB_LDAP_ADDR").ok(), env::var("HIVEDB_OIDC_ISSUER").ok());
+    if ldap.is_none() && oidc.is_none() {
+        return Ok(None);
+    }
+    
+    let groups = env::var("HIVEDB_AUTH_ADMIN_GROUPS")
+        .map_err(|_| "HIVEDB_AUTH_ADMIN_GROUPS must name the groups of administrators")?;
+    let mapping = groups.split(',')
+        .map(str::trim)
+        .filter(|group| !group.is_empty())
+        .fold(RoleMapping::new().role("admin", Access::Admin), |mapping, group| mapping.map_group(group, "admin"));
+    let mut authenticator = Authenticator::new(mapping);
+    
+    if let Some(address) = ldap {
+        let template = env::var("HIVEDB_LDAP_USER_DN")
+            .map_err(|_| "HIVEDB_LDAP_ADDR needs HIVEDB_LDAP_USER_DN, such as uid={username},ou=people,dc=example,dc=com")?;
+        let mut config = LdapConfig::new(&address, &template);
+        if let Ok(base_dn) = env::var("HIVEDB_LDAP_GROUP_BASE") {
+            config = config.with_group_base(&base_dn);
+        }
+        info!("Admin API checks passwords against the LDAP directory at {}", address);
+        authenticator = authenticator.with_provider(Box::new(LdapProvider::new(config)));
+    }
+    
+    if let Some(issuer) = oidc {
+        let jwks = env::var("HIVEDB_OIDC_JWKS").map_err(|_| "HIVEDB_OIDC_ISSUER needs HIVEDB_OIDC_JWKS")?;
+        let mut config = JwtConfig::new(&issuer);
+        if let Ok(audience) = env::var("HIVEDB_OIDC_AUDIENCE") {
+            config = config.with_audience(&audience);
+        }
+        let mut validator = JwtValidator::new(config);
+        validator.set_jwks(&std::fs::read_to_string(jwks)?)?;
+        info!("Admin API accepts ID tokens issued by {}", issuer);
+        authenticator = authenticator.with_provider(Box::new(OidcProvider::new(validator)));
+    }
+    
+    Ok(Some(authenticator))
+}
+
+/// Get the query limits from HIVEDB_MAX_QUERIES and HIVEDB_TENANT_MAX_QUERIES
+///
+/// Returns None when neither is set, and queries are not scheduled.
+/// HIVEDB_QUERY_TIME_SLICE_MS sets how long a query runs before it yields.
+fn scheduler_config() -> Result<Option<SchedulerConfig>, Box<dyn std::error::Error>> {
+    let limit = |name: &str| env::var(name).ok().map(|value| value.parse::<usize>()).transpose();
+    let mut config = SchedulerConfig {
+        max_running: limit("HIVEDB_MAX_QUERIES")?,
+        max_per_tenant: limit("HIVEDB_TENANT_MAX_QUERIES")?,
+        ..SchedulerConfig::default()
+    };
+    if config.max_running.is_none() && config.max_per_tenant.is_none() {
+        return Ok(None);
+    }
+    if let Ok(slice) = env::var("HIVEDB_QUERY_TIME_SLICE_MS") {
+        config.time_slice = Duration::from_millis(slice.parse()?);
+    }
+    Ok(Some(config))
+}
+
+/// Get the tiering policy, with the idle time from HIVEDB_COLD_AFTER
+fn tiering_policy() -> Result<TieringPolicy, Box<dyn std::error::Error>> {
+    let mut policy = TieringPolicy::default();
+    if let Ok(cold_after) = env::var("HIVEDB_COLD_AFTER") {
+        policy.cold_after = tiering::parse_duration(&cold_after)?;
+    }
+    Ok(policy)
+}
+
+/// Get the limits on background work, from HIVEDB_BACKGROUND_MB_PER_SEC and HIVEDB_BACKGROUND_MAX_TASKS
+fn background_limits() -> Result<BackgroundLimits, Box<dyn std::error::Error>> {
+    Ok(BackgroundLimits {
+        max_mb_per_sec: env::var("HIVEDB_BACKGROUND_MB_PER_SEC").ok().map(|value| value.parse()).transpose()?,
+        max_tasks: env::var("HIVEDB_BACKGROUND_MAX_TASKS").ok().map(|value| value.parse()).transpose()?,
+    })
+}
+
+/// Get how often the server samples hive statistics, from HIVEDB_STATS_INTERVAL
+///
+/// Returns `None` when sampling is turned off with `0`.
+fn stats_interval() -> Result<Option<Duration>, Box<dyn std::error::Error>> {
+    match env::var("HIVEDB_STATS_INTERVAL") {
+        Ok(interval) if interval.trim() == "0" => Ok(None),
+        Ok(interval) => Ok(Some(tiering::parse_duration(&interval)?)),
+        Err(_) => Ok(Some(history::DEFAULT_SAMPLE_INTERVAL)),
+    }
+}
+
+/// Run one HQL statement against a hive and print the results
+///
+/// Returns the process exit code.
+fn query_command(args: &[String]) -> i32 {
+    const USAGE: &str = "usage: hivedb query <hive> \"<HQL>\" [--output json|jsonl|table|csv]";
+    
+    let mut positional = Vec::new();
+    let mut output = OutputFormat::Table;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--output" || arg == "-o" {
+            match iter.next().map(|name| OutputFormat::parse(name)) {
+                Some(Ok(format)) => output = format,
+                Some(Err(e)) => {
+                    eprintln!("{}", e);
+                    return EXIT_USAGE;
+                }
+                None => {
+                    eprintln!("{}", USAGE);
+                    return EXIT_USAGE;
+                }
+            }
+        } else {
+            positional.push(arg.as_str());
+        }
+    }
+    
+    let (hive_name, hql) = match positional.as_slice() {
+        [hive_name, hql] => (*hive_name, *hql),
+        _ => {
+            eprintln!("{}", USAGE);
+            return EXIT_USAGE;
+        }
+    };
+    
+    let query = match HqlParser::parse(hql) {
+        Ok(query) => query,
+        Err(e) => {
+            eprintln!("{}", e);
+            return EXIT_QUERY_ERROR;
+        }
+    };
+    
+    let manager = match open_hives() {
+        Ok(manager) => manager,
+        Err(e) => {
+            eprintln!("Failed to open the data directory: {}", e);
+            return EXIT_UNAVAILABLE;
+        }
+    };
+    let hive = match manager.get_hive_by_name(hive_name).or_else(|| manager.get_hive(hive_name)) {
+        Some(hive) => hive,
+        None => {
+            eprintln!("Hive '{}' not found in {}", hive_name, data_dir().display());
+            return EXIT_UNAVAILABLE;
+        }
+    };
+    
+    // JSON lines go through execute_into, which streams exports rather than holding them
+    if query.query_type == QueryType::Find && output == OutputFormat::JsonLines {
+        let hive = match hive.read() {
+            Ok(hive) => hive,
+            Err(_) => return EXIT_UNAVAILABLE,
+        };
+        return match query.execute_into(&hive, std::io::stdout().lock(), output) {
+            Ok(_) => 0,
+            Err(e) => {
+                eprintln!("{}", e);
+                EXIT_QUERY_ERROR
+            }
+        };
+    }
+    
+    let mut hive = match hive.write() {
+        Ok(hive) => hive,
+        Err(_) => return EXIT_UNAVAILABLE,
+    };
+    
+    let result = match QueryExecutor::execute(&query, &mut hive) {
+        Ok(result) => result,
+        Err(e) => {
+            eprintln!("{}", e);
+            return EXIT_QUERY_ERROR;
+        }
+    };
+    
+    let modified = matches!(result.query_type, QueryType::Insert | QueryType::Update | QueryType::Delete);
+    if modified {
+        if let Err(e) = hive.save() {
+            eprintln!("Failed to save hive: {}", e);
+            return EXIT_UNAVAILABLE;
+        }
+    }
+    
+    let documents = if result.results.is_empty() && modified {
+        vec![json!({ "count": result.count })]
+    } else {
+        result.results
+    };
+    print!("{}", format::format_documents(&documents, output));
+    if output == OutputFormat::Json {
+        println!();
+    }
+    
+    0
+}
+
+/// Print statistics about a hive, or with --history how they changed over time
+fn inspect_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let as_json = args.iter().any(|arg| arg == "--json");
+    let hive_name = args.iter()
+        .find(|arg| !arg.starts_with("--"))
+        .ok_or("usage: hivedb inspect <hive> [--history] [--json]")?;
+    
+    let manager = open_hives()?;
+    if args.iter().any(|arg| arg == "--history") {
+        return print_history(&manager, hive_name, as_json);
+    }
+    let hive = manager.get_hive_by_name(hive_name)
+        .or_else(|| manager.get_hive(hive_name))
+        .ok_or_else(|| format!("hive '{}' not found in {}", hive_name, data_dir().display()))?;
+    let stats = hive.read().map_err(|_| "hive lock poisoned")?.stats()?;
+    
+    if as_json {
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+        return Ok(());
+    }
+    
+    let time = |secs: u64| format!("{} (unix time)", secs);
+    println!("Hive:              {} ({})", stats.name, stats.id);
+    println!("Version:           {}", stats.version);
+    println!("Cells:             {} of {}x{} ({:.1}% full)", stats.cell_count, stats.dimensions.0, stats.dimensions.1, stats.fill_factor() * 100.0);
+    println!("Data size:         {} bytes stored, {} bytes raw", stats.stored_bytes, stats.raw_bytes);
+    println!("Compression ratio: {:.2}", stats.compression_ratio());
+    println!("Bytes on disk:     {}", stats.bytes_on_disk);
+    println!("Last modified:     {}", time(stats.modified_at));
+    println!("Last compaction:   {}", stats.last_compaction.map_or("never".to_string(), time));
+    println!("Temperature:       {} hot, {} warm, {} cold", stats.temperatures.hot, stats.temperatures.warm, stats.temperatures.cold);
+    
+    println!();
+    if stats.indexes.is_empty() {
+        println!("Indexes:           none");
+    } else {
+        println!("Indexes:");
+        for index in &stats.indexes {
+            println!("  {:<20} ({}) {} entries, {} key bytes", index.name, index.fields.join(", "), index.entries, index.key_bytes);
+        }
+    }
+    
+    // Darker characters mean a larger share of occupied positions
+    const SHADES: [char; 5] = [' ', '░', '▒', '▓', '█'];
+    println!();
+    println!("Occupancy:");
+    for row in &stats.occupancy {
+        let line: String = row.iter()
+            .map(|fraction| {
+                let shade = (fraction * (SHADES.len() - 1) as f64).ceil() as usize;
+                SHADES[shade.min(SHADES.len() - 1)]
+            })
+            .collect();
+        println!("  |{}|", line);
+    }
+    
+    Ok(())
+}
+
+/// Print the statistics history of a hive, with its rates between samples
+fn print_history(manager: &HiveManager, hive_name: &str, as_json: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let history = manager.get_hive_by_name(history::HISTORY_HIVE)
+        .ok_or("no statistics history yet; a running server samples it every HIVEDB_STATS_INTERVAL")?;
+    let samples = history::samples(&*history.read().map_err(|_| "hive lock poisoned")?, hive_name)?;
+    
+    if as_json {
+        println!("{}", serde_json::to_string_pretty(&samples)?);
+        return Ok(());
+    }
+    if samples.is_empty() {
+        return Err(format!("no statistics recorded for hive '{}'", hive_name).into());
+    }
+    
+    println!("{:<12} {:>10} {:>14} {:>14} {:>10} {:>10}", "Time (unix)", "Cells", "Stored bytes", "Bytes on disk", "Writes/h", "Reads/h");
+    let mut previous: Option<&history::StatsSample> = None;
+    for sample in &samples {
+        let rates = previous.map(|previous| {
+            let trend = Trend::between(previous, sample);
+            (trend.per_hour(trend.writes as f64), trend.per_hour(trend.reads as f64))
+        });
+        let rate = |rate: Option<f64>| rate.map_or("-".to_string(), |rate| format!("{:.1}", rate));
+        println!(
+            "{:<12} {:>10} {:>14} {:>14} {:>10} {:>10}",
+            sample.timestamp,
+            sample.cell_count,
+            sample.stored_bytes,
+            sample.bytes_on_disk,
+            rate(rates.map(|rates| rates.0)),
+            rate(rates.map(|rates| rates.1)),
+        );
+        previous = Some(sample);
+    }
+    
+    if let [first, .., last] = samples.as_slice() {
+        let trend = Trend::between(first, last);
+        let per_day = |change: i64| trend.per_hour(change as f64) * 24.0;
+        println!();
+        println!(
+            "Over {:.1} days: {:+.0} cells, {:+.0} stored bytes and {:+.0} bytes on disk per day",
+            trend.hours / 24.0,
+            per_day(trend.cells),
+            per_day(trend.stored_bytes),
+            per_day(trend.bytes_on_disk),
+        );
+    }
+    
+    Ok(())
+}
+
+/// Check the integrity of a hive and report whether it is healthy
+fn verify_command(args: &[String]) -> Result<bool, Box<dyn std::error::Error>> {
+    let as_json = args.iter().any(|arg| arg == "--json");
+    let repair = args.iter().any(|arg| arg == "--repair");
+    let hive_name = args.iter()
+        .find(|arg| !arg.starts_with("--"))
+        .ok_or("usage: hivedb verify <hive> [--repair] [--json]")?;
+    
+    let manager = open_hives()?;
+    let hive = manager.get_hive_by_name(hive_name)
+        .or_else(|| manager.get_hive(hive_name))
+        .ok_or_else(|| format!("hive '{}' not found in {}", hive_name, data_dir().display()))?;
+    let report = hive.write().map_err(|_| "hive lock poisoned")?.verify(repair)?;
+    
+    if as_json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(report.is_healthy());
+    }
+    
+    println!("Checked {} cells of hive '{}'", report.cells_checked, report.hive);
+    for problem in &report.problems {
+        let status = if problem.repaired { "repaired" } else { "found" };
+        let cell = problem.cell_id.as_deref().map_or(String::new(), |id| format!(" [{}]", id));
+        println!("  {:<8} {:?}{}: {}", status, problem.kind, cell, problem.detail);
+    }
+    
+    let remaining = report.problems.iter().filter(|problem| !problem.repaired).count();
+    if report.problems.is_empty() {
+        println!("✅ No problems found");
+    } else if remaining == 0 {
+        println!("✅ All {} problems repaired", report.problems.len());
+    } else {
+        println!("❌ {} of {} problems remain{}", remaining, report.problems.len(),
+            if repair { "" } else { " (run with --repair to fix what can be fixed)" });
+    }
+    
+    Ok(report.is_healthy())
+}
+
+/// Check the data directory and settings for problems and report whether none is serious
+fn doctor_command(args: &[String]) -> Result<bool, Box<dyn std::error::Error>> {
+    let as_json = args.iter().any(|arg| arg == "--json");
+    let settings = env::vars().filter(|(name, _)| name.starts_with("HIVEDB_")).collect();
+    let report = doctor::diagnose(&data_dir(), &settings);
+    
+    if as_json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(report.is_healthy());
+    }
+    
+    println!("Checking {}", report.data_dir);
+    for finding in &report.findings {
+        let mark = match finding.severity {
+            Severity::Ok => "✅",
+            Severity::Warning => "⚠️ ",
+            Severity::Error => "❌",
+        };
+        println!("{} {:<10} {}", mark, finding.check, finding.detail);
+        if let Some(fix) = &finding.fix {
+            println!("   {:<10} → {}", "", fix);
+        }
+    }
+    
+    println!();
+    match report.severity() {
+        Severity::Ok => println!("Your hives are ready to fly 🐝"),
+        Severity::Warning => println!("Some warnings, but HiveDB will work"),
+        Severity::Error => println!("Fix the problems marked ❌ before starting HiveDB"),
+    }
+    Ok(report.is_healthy())
+}
+
+const INDEX_USAGE: &str = "usage: hivedb index <hive> [list | drop <name>]
+       hivedb index <hive> rebuild <name> --server <host:port> [--token <token>]
+
+Indexes are kept in memory, so rebuild asks the running server to rebuild
+one. The admin token can also be given in HIVEDB_ADMIN_TOKEN.";
+
+/// List, rebuild or drop the indexes of a hive
+fn index_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let (hive_name, rest) = args.split_first().ok_or(INDEX_USAGE)?;
+    if rest.first().map(String::as_str) == Some("rebuild") {
+        return rebuild_index_remote(hive_name, &rest[1..]);
+    }
+    
+    let manager = open_hives()?;
+    let hive = manager.get_hive_by_name(hive_name)
+        .or_else(|| manager.get_hive(hive_name))
+        .ok_or_else(|| format!("hive '{}' not found in {}", hive_name, data_dir().display()))?;
+    let mut hive = hive.write().map_err(|_| "hive lock poisoned")?;
+    
+    match rest.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+        ["list"] | [] => {
+            if hive.indexes.is_empty() {
+                println!("Hive '{}' has no indexes", hive.name);
+            }
+            for index in hive.indexes.iter() {
+                let definition = index.definition();
+                let unique = if definition.unique { " unique" } else { "" };
+                let predicate = definition.predicate.as_deref().map_or(String::new(), |p| format!(" where {}", p));
+                println!("  {:<20} {:?}{} ({}){}  {} documents",
+                    definition.name, definition.index_type, unique, definition.fields.join(", "), predicate, index.len());
+            }
+        }
+        ["drop", name] => {
+            hive.drop_index(name)?;
+            hive.save()?;
+            println!("✅ Dropped index '{}'", name);
+        }
+        _ => return Err(INDEX_USAGE.into()),
+    }
+    
+    Ok(())
+}
+
+/// Rebuild an index of a hive on a running server
+fn rebuild_index_remote(hive_name: &str, args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut name = None;
+    let mut server = None;
+    let mut token = env::var("HIVEDB_ADMIN_TOKEN").ok();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--server" => server = Some(iter.next().ok_or(INDEX_USAGE)?),
+            "--token" => token = Some(iter.next().ok_or(INDEX_USAGE)?.clone()),
+            option if option.starts_with("--") || name.is_some() => return Err(INDEX_USAGE.into()),
+            index => name = Some(index),
+        }
+    }
+    let (name, server) = name.zip(server).ok_or(INDEX_USAGE)?;
+    let token = token.ok_or("an admin token is required (--token or HIVEDB_ADMIN_TOKEN)")?;
+    
+    let request = HttpRequest::new("POST", &format!("/hives/{}/indexes/{}/rebuild", hive_name, name), b"")
+        .with_header("Authorization", &format!("Bearer {}", token));
+    let response = http::send_request(server, &request, Duration::from_secs(300))?;
+    if response.status != 200 {
+        return Err(error_message(&response).into());
+    }
+    
+    let entries = response.json_body()?["entries"].as_u64().unwrap_or(0);
+    println!("✅ Rebuilt index '{}' ({} documents)", name, entries);
+    Ok(())
+}
+
+const SCHEMA_USAGE: &str = "usage: hivedb schema <hive> <file.json|file.avsc|file.proto> [--message <name>]";
+
+/// Replace the schema of a hive with one read from a file
+///
+/// The file holds a HiveDB schema as JSON, an Avro record schema (`.avsc`)
+/// or Protocol Buffers messages (`.proto`), of which --message names one.
+fn schema_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let (hive_name, path, message) = match args {
+        [hive_name, path] => (hive_name, Path::new(path), None),
+        [hive_name, path, flag, message] if flag == "--message" => (hive_name, Path::new(path), Some(message)),
+        _ => return Err(SCHEMA_USAGE.into()),
+    };
+    
+    let source = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let schema = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("proto") => Schema::from_proto(&source, message.ok_or("a .proto file needs --message <name>")?)?,
+        Some("avsc") => Schema::from_avro(&source)?,
+        _ => serde_json::from_str(&source)?,
+    };
+    
+    let manager = open_hives()?;
+    let hive = manager.get_hive_by_name(hive_name)
+        .or_else(|| manager.get_hive(hive_name))
+        .ok_or_else(|| format!("hive '{}' not found in {}", hive_name, data_dir().display()))?;
+    let mut hive = hive.write().map_err(|_| "hive lock poisoned")?;
+    
+    let (name, fields) = (schema.name.clone(), schema.fields.len());
+    hive.set_schema(schema)?;
+    hive.save()?;
+    println!("✅ Hive '{}' now has the schema '{}' ({} fields)", hive.name, name, fields);
+    Ok(())
+}
+
+const RETENTION_USAGE: &str = "usage: hivedb retention <hive> [list | clear | run [--dry-run] [--json]
+       | add [--older-than <age>] [--tag <tag>] (--to-hive <hive> | --to-dir <dir>)]";
+
+/// List, change or apply the retention rules of a hive
+fn retention_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let (hive_name, rest) = args.split_first().ok_or(RETENTION_USAGE)?;
+    let (action, options) = match rest.split_first() {
+        Some((action, options)) => (action.as_str(), options),
+        None => ("list", rest),
+    };
+    
+    let mut manager = open_hives()?;
+    let hive = manager.get_hive_by_name(hive_name)
+        .or_else(|| manager.get_hive(hive_name))
+        .ok_or_else(|| format!("hive '{}' not found in {}", hive_name, data_dir().display()))?;
+    
+    match action {
+        "list" => {
+            let hive = hive.read().map_err(|_| "hive lock poisoned")?;
+            let rules = retention::rules(&hive)?;
+            if rules.is_empty() {
+                println!("Hive '{}' has no retention rules", hive.name);
+            }
+            for (number, rule) in rules.iter().enumerate() {
+                println!("  {}. {}", number + 1, describe_rule(rule));
+            }
+        }
+        "add" => {
+            let mut older_than = None;
+            let mut tag = None;
+            let mut archive = None;
+            let mut iter = options.iter();
+            while let Some(option) = iter.next() {
+                let value = iter.next().ok_or(RETENTION_USAGE)?;
+                match option.as_str() {
+                    "--older-than" => older_than = Some(tiering::parse_duration(value)?),
+                    "--tag" => tag = Some(value.clone()),
+                    "--to-hive" => archive = Some(ArchiveTarget::Hive(value.clone())),
+                    "--to-dir" => archive = Some(ArchiveTarget::Directory(PathBuf::from(value))),
+                    _ => return Err(RETENTION_USAGE.into()),
+                }
+            }
+            let rule = RetentionRule { older_than, tag, archive: archive.ok_or(RETENTION_USAGE)? };
+            
+            let mut hive = hive.write().map_err(|_| "hive lock poisoned")?;
+            let mut rules = retention::rules(&hive)?;
+            rules.push(rule.clone());
+            retention::set_rules(&mut hive, &rules)?;
+            hive.save()?;
+            println!("✅ Hive '{}' now archives {}", hive.name, describe_rule(&rule));
+        }
+        "clear" => {
+            let mut hive = hive.write().map_err(|_| "hive lock poisoned")?;
+            retention::set_rules(&mut hive, &[])?;
+            hive.save()?;
+            println!("✅ Removed the retention rules of hive '{}'", hive.name);
+        }
+        "run" => {
+            let dry_run = options.iter().any(|option| option == "--dry-run");
+            let as_json = options.iter().any(|option| option == "--json");
+            let id = hive.read().map_err(|_| "hive lock poisoned")?.id.clone();
+            if !dry_run {
+                for name in retention::create_archive_hives(&mut manager)? {
+                    println!("Created archive hive '{}'", name);
+                }
+            }
+            let report = retention::apply(&manager, &id, dry_run)?;
+            if !dry_run {
+                manager.save_all()?;
+            }
+            print_retention_report(&report, as_json)?;
+        }
+        _ => return Err(RETENTION_USAGE.into()),
+    }
+    
+    Ok(())
+}
+
+/// Describe which cells a retention rule archives, and where to
+fn describe_rule(rule: &RetentionRule) -> String {
+    let mut conditions = Vec::new();
+    if let Some(age) = rule.older_than {
+        let secs = age.as_secs();
+        let age = match secs {
+            _ if secs > 0 && secs % (24 * 60 * 60) == 0 => format!("{}d", secs / (24 * 60 * 60)),
+            _ if secs > 0 && secs % (60 * 60) == 0 => format!("{}h", secs / (60 * 60)),
+            _ => format!("{}s", secs),
+        };
+        conditions.push(format!("unmodified for {}", age));
+    }
+    if let Some(tag) = &rule.tag {
+        conditions.push(format!("tagged '{}'", tag));
+    }
+    format!("cells {} to {}", conditions.join(" and "), rule.archive)
+}
+
+/// Print what applying retention rules archived, or would archive
+fn print_retention_report(report: &RetentionReport, as_json: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if as_json {
+        println!("{}", serde_json::to_string_pretty(report)?);
+        return Ok(());
+    }
+    
+    let verb = if report.dry_run { "Would archive" } else { "Archived" };
+    for archive in &report.archives {
+        println!("{} {} cells ({} bytes) to {}", verb, archive.cells.len(), archive.bytes, archive.target);
+        for id in &archive.cells {
+            println!("  {}", id);
+        }
+    }
+    if report.archives.is_empty() {
+        println!("✅ No cells of hive '{}' match its retention rules", report.hive);
+    }
+    Ok(())
+}
+
+/// Pack the cells of a hive toward the center of its grid and save it
+fn rebalance_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let hive_name = args.first().ok_or("usage: hivedb rebalance <hive>")?;
+    
+    let manager = open_hives()?;
+    let hive = manager.get_hive_by_name(hive_name)
+        .or_else(|| manager.get_hive(hive_name))
+        .ok_or_else(|| format!("hive '{}' not found in {}", hive_name, data_dir().display()))?;
+    let mut hive = hive.write().map_err(|_| "hive lock poisoned")?;
+    
+    let moved = hive.rebalance(|moved, total| eprint!("\rMoved {}/{} cells", moved, total))?;
+    if moved == 0 {
+        println!("✅ Hive '{}' is already packed", hive.name);
+        return Ok(());
+    }
+    
+    eprintln!();
+    hive.save()?;
+    println!("✅ Rebalanced hive '{}' ({} cells moved)", hive.name, moved);
+    Ok(())
+}
+
+const DICTIONARY_USAGE: &str = "usage: hivedb dictionary <hive> [--samples N]";
+
+/// Train a compression dictionary over the documents of a hive
+fn dictionary_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let (hive_name, samples) = match args {
+        [hive_name] => (hive_name, compression::DEFAULT_SAMPLE_COUNT),
+        [hive_name, flag, samples] if flag == "--samples" => (hive_name, samples.parse().map_err(|_| DICTIONARY_USAGE)?),
+        _ => return Err(DICTIONARY_USAGE.into()),
+    };
+    
+    let manager = open_hives()?;
+    let hive = manager.get_hive_by_name(hive_name)
+        .or_else(|| manager.get_hive(hive_name))
+        .ok_or_else(|| format!("hive '{}' not found in {}", hive_name, data_dir().display()))?;
+    let mut hive = hive.write().map_err(|_| "hive lock poisoned")?;
+    
+    let dictionary = hive.train_dictionary(samples)?;
+    hive.save()?;
+    println!("✅ Trained a {} byte dictionary over {} documents of hive '{}'; new compressed documents will use it",
+        dictionary.data.len(), dictionary.samples, hive.name);
+    Ok(())
+}
+
+const BACKUP_USAGE: &str = "usage: hivedb backup <file> [--hive <name>]... [--since <archive>] [--no-compress]
+                    [--passphrase | --key-file <path>] [--server <host:port> [--token <token>]]
+
+Archives are compressed with zstd unless --no-compress is given.
+--passphrase encrypts with a passphrase read from HIVEDB_BACKUP_PASSPHRASE
+or prompted for; --key-file encrypts with a 32 byte key, raw or hex.
+--since makes an incremental archive holding only the hives changed since
+an earlier archive, which must use the same key.
+Without --server the data directory is read directly, which needs the
+server to be stopped. With --server the hives are copied from the running
+server while it keeps taking writes. The admin token can also be given in
+HIVEDB_ADMIN_TOKEN.";
+
+/// Write the hives of the data directory to a backup archive
+fn backup_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut path = None;
+    let mut names = Vec::new();
+    let mut since = None;
+    let mut server = None;
+    let mut token = env::var("HIVEDB_ADMIN_TOKEN").ok();
+    let mut options = BackupOptions { compress: true, key: None };
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--hive" => names.push(iter.next().ok_or(BACKUP_USAGE)?.clone()),
+            "--since" => since = Some(iter.next().ok_or(BACKUP_USAGE)?),
+            "--server" => server = Some(iter.next().ok_or(BACKUP_USAGE)?),
+            "--token" => token = Some(iter.next().ok_or(BACKUP_USAGE)?.clone()),
+            "--no-compress" => options.compress = false,
+            "--passphrase" => options.key = Some(BackupKey::Passphrase(read_passphrase()?)),
+            "--key-file" => options.key = Some(BackupKey::from_file(iter.next().ok_or(BACKUP_USAGE)?.as_ref())?),
+            option if option.starts_with("--") || path.is_some() => return Err(BACKUP_USAGE.into()),
+            file => path = Some(PathBuf::from(file)),
+        }
+    }
+    let path = path.ok_or(BACKUP_USAGE)?;
+    
+    let mut entries = match server {
+        Some(server) => {
+            let token = token.ok_or("an admin token is required (--token or HIVEDB_ADMIN_TOKEN)")?;
+            backup_remote(server, &token, &names)?
+        }
+        None => backup_local(&names)?,
+    };
+    if let Some(since) = since {
+        let earlier = backup::read_archive(&std::fs::read(since)?, options.key.as_ref())?;
+        backup::skip_unchanged(&mut entries, &earlier);
+    }
+    
+    let data = backup::write_archive(&entries, &options)?;
+    std::fs::write(&path, &data)?;
+    let stored = entries.iter().filter(|entry| entry.snapshot.is_some()).count();
+    println!("✅ Backed up {} of {} hive(s) to {} ({} bytes{}{})",
+        stored, entries.len(), path.display(), data.len(),
+        if options.compress { ", compressed" } else { "" },
+        if options.key.is_some() { ", encrypted" } else { "" });
+    Ok(())
+}
+
+/// Read the snapshots of hives from the data directory
+fn backup_local(names: &[String]) -> Result<Vec<BackupEntry>, Box<dyn std::error::Error>> {
+    // Hives are named by their storage directories in the archive
+    let manager = open_hives()?;
+    let mut dirs = Vec::new();
+    for name in names {
+        let hive = manager.get_hive_by_name(name)
+            .ok_or_else(|| format!("hive '{}' not found in {}", name, data_dir().display()))?;
+        let hive = hive.read().map_err(|_| "hive lock poisoned")?;
+        dirs.extend(hive.storage_path.file_name().map(|dir| dir.to_string_lossy().into_owned()));
+    }
+    
+    Ok(backup::collect(&data_dir(), &dirs)?)
+}
+
+/// Copy snapshots of hives from a running server
+fn backup_remote(server: &str, token: &str, names: &[String]) -> Result<Vec<BackupEntry>, Box<dyn std::error::Error>> {
+    let fetch = |path: &str| -> Result<HttpResponse, Box<dyn std::error::Error>> {
+        let request = HttpRequest::new("GET", path, b"")
+            .with_header("Authorization", &format!("Bearer {}", token))
+            .with_header("Accept-Encoding", http::ACCEPT_ENCODING);
+        let response = http::send_request(server, &request, Duration::from_secs(300))?;
+        if !(200..300).contains(&response.status) {
+            return Err(error_message(&response).into());
+        }
+        Ok(response)
+    };
+    
+    let names = if names.is_empty() {
+        let body = fetch("/hives")?.json_body()?;
+        body["hives"].as_array()
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|hive| hive["name"].as_str().map(str::to_string))
+            .collect()
+    } else {
+        names.to_vec()
+    };
+    
+    let mut entries = Vec::with_capacity(names.len());
+    for name in names {
+        let response = fetch(&format!("/hives/{}/snapshot", name))?;
+        let directory = response.headers.iter()
+            .find(|(header, _)| header == "x-hivedb-directory")
+            .map(|(_, directory)| directory.clone())
+            .ok_or("server did not name the storage directory of the hive")?;
+        entries.push(BackupEntry::new(directory, response.body));
+    }
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    
+    Ok(entries)
+}
+
+const RESTORE_USAGE: &str = "usage: hivedb restore <file>... [--key-file <path>]
+
+Give a full archive followed by each incremental archive made since, in
+order. Archives encrypted with a passphrase read it from
+HIVEDB_BACKUP_PASSPHRASE or prompt for it, once for the whole chain.
+Hives that already exist are not overwritten.";
+
+/// Restore the hives of a chain of backup archives into the data directory
+fn restore_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut paths = Vec::new();
+    let mut key_file = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--key-file" => key_file = Some(iter.next().ok_or(RESTORE_USAGE)?),
+            option if option.starts_with("--") => return Err(RESTORE_USAGE.into()),
+            path => paths.push(path),
+        }
+    }
+    if paths.is_empty() {
+        return Err(RESTORE_USAGE.into());
+    }
+    
+    let mut key = None;
+    let mut chain = Vec::with_capacity(paths.len());
+    for path in &paths {
+        let data = std::fs::read(path)?;
+        if key.is_none() {
+            key = match (backup::key_source(&data)?, key_file) {
+                (None, _) => None,
+                (Some(KeySource::Passphrase), _) => Some(BackupKey::Passphrase(read_passphrase()?)),
+                (Some(KeySource::KeyFile), Some(key_file)) => Some(BackupKey::from_file(key_file.as_ref())?),
+                (Some(KeySource::KeyFile), None) => return Err("the backup is encrypted with a key file; pass --key-file".into()),
+            };
+        }
+        chain.push(backup::read_archive(&data, key.as_ref()).map_err(|e| format!("{}: {}", path, e))?);
+    }
+    let entries = backup::apply(&chain)?;
+    
+    // Holding the data directory keeps a server from loading half-restored hives
+    let dir = data_dir();
+    let _manager = HiveManager::new(dir.clone())?;
+    let restored = backup::restore(&dir, &entries)?;
+    println!("✅ Restored {} hive(s) from {} backup(s)", restored, paths.len());
+    Ok(())
+}
+
+/// Read the passphrase of a backup from HIVEDB_BACKUP_PASSPHRASE, or prompt for it
+fn read_passphrase() -> Result<String, Box<dyn std::error::Error>> {
+    if let Ok(passphrase) = env::var("HIVEDB_BACKUP_PASSPHRASE") {
+        return Ok(passphrase);
+    }
+    
+    let passphrase = read_secret("Backup passphrase: ")?;
+    if passphrase.is_empty() {
+        return Err("passphrase must not be empty".into());
+    }
+    
+    Ok(passphrase)
+}
+
+const SEED_USAGE: &str = "usage: hivedb seed <hive> [--schema user|order|product] [--count N] [--seed N]
+
+Without --schema the documents follow the schema the hive already has.
+A template is applied to hives without a schema.";
+
+/// Fill a hive with generated documents that follow its schema
+fn seed_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut positional = Vec::new();
+    let mut options = std::collections::HashMap::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.strip_prefix("--") {
+            Some(option) => {
+                let value = iter.next().ok_or(SEED_USAGE)?;
+                options.insert(option.to_string(), value.clone());
+            }
+            None => positional.push(arg.as_str()),
+        }
+    }
+    
+    let hive_name = match positional.as_slice() {
+        [hive] => *hive,
+        _ => return Err(SEED_USAGE.into()),
+    };
+    let number = |option: &str| options.get(option)
+        .map(|value| value.parse::<u64>().map_err(|_| format!("invalid value '{}' for --{}", value, option)))
+        .transpose();
+    let count = number("count")?.unwrap_or(1000) as usize;
+    let seed = number("seed")?.unwrap_or_else(rand::random);
+    
+    let manager = open_hives()?;
+    let hive = manager.get_hive_by_name(hive_name)
+        .or_else(|| manager.get_hive(hive_name))
+        .ok_or_else(|| format!("hive '{}' not found in {}", hive_name, data_dir().display()))?;
+    let mut hive = hive.write().map_err(|_| "hive lock poisoned")?;
+    
+    let schema = match (options.get("schema"), &hive.schema) {
+        (Some(name), existing) => {
+            let template = seed::template(name)
+                .ok_or_else(|| format!("unknown schema template '{}' (available: {})", name, seed::TEMPLATES.join(", ")))?;
+            match existing {
+                Some(existing) if existing.name != template.name => {
+                    return Err(format!("hive '{}' already has the schema '{}'", hive_name, existing.name).into());
+                }
+                Some(existing) => existing.clone(),
+                None => {
+                    hive.set_schema(template.clone())?;
+                    template
+                }
+            }
+        }
+        (None, Some(existing)) => existing.clone(),
+        (None, None) => return Err(format!("hive '{}' has no schema; pass --schema", hive_name).into()),
+    };
+    
+    let mut generator = SeedGenerator::new(seed);
+    let before = hive.cell_count();
+    let mut result = Ok(());
+    while hive.cell_count() - before < count {
+        let batch = (count - (hive.cell_count() - before)).min(SEED_BATCH_SIZE);
+        let documents: Vec<Value> = (0..batch).map(|_| generator.document(&schema)).collect();
+        let query = Query::new(QueryType::Insert, schema.name.clone()).with_data(Value::Array(documents));
+        
+        if let Err(e) = QueryExecutor::execute(&query, &mut hive) {
+            result = Err(e);
+            break;
+        }
+        eprint!("\rInserted {}/{}", hive.cell_count() - before, count);
+    }
+    eprintln!();
+    
+    // Keep what was inserted even if the hive filled up part way
+    hive.save()?;
+    let inserted = hive.cell_count() - before;
+    if let Err(e) = result {
+        return Err(format!("stopped after {} documents: {}", inserted, e).into());
+    }
+    
+    println!("✅ Inserted {} '{}' documents into '{}' (seed {})", inserted, schema.name, hive_name, seed);
+    Ok(())
+}
+
+const WATCH_USAGE: &str = "usage: hivedb watch <hive> [filter] --server <host:port> [--token <token>]
+                    [--from <sequence>] [--json]
+
+The filter is an HQL condition on the changed documents, e.g. \"status = 'open'\".
+Without --from only changes made after the command starts are shown.
+The admin token can also be given in HIVEDB_ADMIN_TOKEN.";
+
+/// Print the changes made to a hive on a running server as they happen
+fn watch_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut positional = Vec::new();
+    let mut options = std::collections::HashMap::new();
+    let mut as_json = false;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.strip_prefix("--") {
+            Some("json") => as_json = true,
+            Some(option) => {
+                let value = iter.next().ok_or(WATCH_USAGE)?;
+                options.insert(option.to_string(), value.clone());
+            }
+            None => positional.push(arg.as_str()),
+        }
+    }
+    
+    let (hive_name, filter) = match positional.as_slice() {
+        [hive] => (*hive, None),
+        [hive, filter] => (*hive, Some(parse_filter(filter)?)),
+        _ => return Err(WATCH_USAGE.into()),
+    };
+    let server = options.get("server").ok_or(WATCH_USAGE)?;
+    let token = options.get("token")
+        .cloned()
+        .or_else(|| env::var("HIVEDB_ADMIN_TOKEN").ok())
+        .ok_or("an admin token is required (--token or HIVEDB_ADMIN_TOKEN)")?;
+    
+    let fetch = |query: String| -> Result<HttpResponse, Box<dyn std::error::Error>> {
+        let request = HttpRequest::new("GET", &format!("/hives/{}/changes?{}", hive_name, query), b"")
+            .with_header("Authorization", &format!("Bearer {}", token))
+            .with_header("Accept-Encoding", http::ACCEPT_ENCODING);
+        Ok(http::send_request(server, &request, Duration::from_secs(30))?)
+    };
+    let latest = || -> Result<u64, Box<dyn std::error::Error>> {
+        let response = fetch("limit=0".to_string())?;
+        if response.status != 200 {
+            return Err(error_message(&response).into());
+        }
+        Ok(response.json_body()?["last_sequence"].as_u64().unwrap_or(0))
+    };
+    
+    let mut since = match options.get("from") {
+        Some(from) => from.parse::<u64>().map_err(|_| format!("invalid sequence number '{}'", from))?.saturating_sub(1),
+        None => latest()?,
+    };
+    if !as_json {
+        eprintln!("Watching hive '{}' on {} (Ctrl-C to stop)", hive_name, server);
+    }
+    
+    loop {
+        let response = fetch(format!("since={}", since))?;
+        if response.status == 410 {
+            let resumed = latest()?;
+            eprintln!("⚠️  Fell behind the server's change log; changes {}..{} were missed", since + 1, resumed);
+            since = resumed;
+            continue;
+        }
+        if response.status != 200 {
+            return Err(error_message(&response).into());
+        }
+        
+        let body = response.json_body()?;
+        let events = body["events"].as_array().cloned().unwrap_or_default();
+        for event in &events {
+            since = event["sequence"].as_u64().unwrap_or(since);
+            
+            if let Some(filter) = &filter {
+                if event["document"].is_null() || !filter.evaluate(&event["document"]) {
+                    continue;
+                }
+            }
+            
+            if as_json {
+                println!("{}", event);
+            } else {
+                let cell = event["cell_id"].as_str().unwrap_or("-");
+                let document = if event["document"].is_null() { String::new() } else { event["document"].to_string() };
+                println!("#{:<8} {} {:<14} {:<20} {}", since, event["timestamp"], event["kind"].as_str().unwrap_or("?"), cell, document);
+            }
+        }
+        std::io::stdout().flush()?;
+        
+        // Keep draining while the server still has a backlog
+        if events.len() < hivedb::network::admin::MAX_CHANGES_PER_REQUEST {
+            std::thread::sleep(WATCH_POLL_INTERVAL);
+        }
+    }
+}
+
+/// Parse an HQL condition into a filter expression
+fn parse_filter(condition: &str) -> Result<FilterExpression, Box<dyn std::error::Error>> {
+    let query = HqlParser::parse(&format!("SELECT * FROM changes WHERE {}", condition))
+        .map_err(|e| format!("invalid filter: {}", e))?;
+    query.filter.ok_or_else(|| "invalid filter: empty condition".into())
+}
+
+/// Run a benchmark against an embedded hive or a server
+fn bench_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    const USAGE: &str = "usage: hivedb bench [--workload insert|read|query|mixed] [--threads N]
+                    [--doc-size BYTES] [--duration SECS] [--preload N]
+                    [--server host:port [--hive NAME] [--user NAME]] [--json]";
+    
+    let mut config = BenchConfig::default();
+    let mut server = None;
+    let mut hive_name = "bench".to_string();
+    let mut user = env::var("USER").unwrap_or_else(|_| "hivedb".to_string());
+    let mut as_json = false;
+    
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--json" {
+            as_json = true;
+            continue;
+        }
+        
+        let value = iter.next().ok_or(USAGE)?;
+        let number = || value.parse::<u64>().map_err(|_| format!("invalid value '{}' for {}", value, arg));
+        match arg.as_str() {
+            "--workload" => config.workload = Workload::parse(value)?,
+            "--threads" => config.threads = number()? as usize,
+            "--doc-size" => config.document_size = number()? as usize,
+            "--duration" => config.duration = Duration::from_secs(number()?),
+            "--preload" => config.preload = number()?,
+            "--server" => server = Some(value.clone()),
+            "--hive" => hive_name = value.clone(),
+            "--user" => user = value.clone(),
+            _ => return Err(USAGE.into()),
+        }
+    }
+    
+    // Embedded runs use a scratch hive that is never saved
+    let scratch = env::temp_dir().join(format!("hivedb-bench-{}", process::id()));
+    let target: Box<dyn BenchTarget> = match &server {
+        Some(server) => Box::new(RemoteTarget::new(server, &user, &hive_name)),
+        None => {
+            let hive = core::Hive::new(hive_name.clone(), "Benchmark".to_string(), user.clone(), scratch.clone(), (1024, 1024))?;
+            Box::new(EmbeddedTarget::new(std::sync::Arc::new(std::sync::RwLock::new(hive))))
+        }
+    };
+    
+    eprintln!(
+        "Running {:?} workload: {} threads, {}-byte documents, {}s against {}",
+        config.workload,
+        config.threads,
+        config.document_size,
+        config.duration.as_secs(),
+        server.as_deref().unwrap_or("an embedded hive")
+    );
+    let report = bench::run(target.as_ref(), &config);
+    let _ = std::fs::remove_dir_all(&scratch);
+    let report = report?;
+    
+    if as_json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+    
+    println!("{:<8} {:>10} {:>8} {:>12} {:>10} {:>10} {:>10} {:>10}", "OP", "OPS", "ERRORS", "OPS/SEC", "P50 µs", "P90 µs", "P99 µs", "MAX µs");
+    for (name, summary) in &report.operations {
+        println!(
+            "{:<8} {:>10} {:>8} {:>12.1} {:>10} {:>10} {:>10} {:>10}",
+            name,
+            summary.operations,
+            summary.errors,
+            summary.throughput,
+            summary.p50_us,
+            summary.p90_us,
+            summary.p99_us,
+            summary.max_us
+        );
+    }
+    
+    Ok(())
+}
+
+/// Get the data directory (HIVEDB_DATA_DIR, or ./data)
+fn data_dir() -> PathBuf {
+    env::var("HIVEDB_DATA_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("data"))
+}
+
+/// Open the user store in the data directory
+fn open_users() -> Result<UserStore, Box<dyn std::error::Error>> {
+    let dir = data_dir();
+    std::fs::create_dir_all(&dir)?;
+    
+    Ok(UserStore::open(dir.join("users.json"))?)
+}
+
+/// Show or change the password and account policy
+fn policy_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut users = open_users()?;
+    
+    match args.first().map(String::as_str) {
+        Some("show") | None => {
+            let policy = users.policy();
+            let days = |secs: Option<u64>| secs.map_or("never".to_string(), |secs| format!("{} days", secs / 86_400));
+            println!("min-length           {}", policy.min_length);
+            println!("require-uppercase    {}", policy.require_uppercase);
+            println!("require-lowercase    {}", policy.require_lowercase);
+            println!("require-digit        {}", policy.require_digit);
+            println!("require-symbol       {}", policy.require_symbol);
+            println!("max-age              {}", days(policy.max_age));
+            println!("history              {}", policy.history);
+            println!("max-failed-attempts  {}", policy.max_failed_attempts.map_or("unlimited".to_string(), |n| n.to_string()));
+            println!("lockout              {} minutes", policy.lockout_duration / 60);
+        }
+        Some("set") if args.len() == 3 => {
+            let mut policy = users.policy().clone();
+            policy.set(&args[1], &args[2])?;
+            users.set_policy(policy)?;
+            println!("✅ Policy updated: {} = {}", args[1], args[2]);
+        }
+        _ => return Err("usage: hivedb policy [show | set <setting> <value>]".into()),
+    }
+    
+    Ok(())
+}
+
+/// A `hivedb user` subcommand
+enum UserCommand {
+    Create { name: String, access: Access },
+    Delete(String),
+    List,
+    Passwd(String),
+    Grant { name: String, access: Option<Access>, role: Option<String> },
+    Unlock(String),
+    Expire(String),
+}
+
+const USER_USAGE: &str = "usage: hivedb user <command> [--server <host:port> [--token <token>]]
+
+  create <name> [--access read|write|admin]
+  delete <name>
+  list
+  passwd <name>
+  grant <name> [read|write|admin] [--role <role>]
+  unlock <name>
+  expire <name>
+
+Without --server the user store in the data directory is changed directly.
+The admin token can also be given in HIVEDB_ADMIN_TOKEN.";
+
+/// Manage user accounts, locally or on a running server
+fn user_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut positional = Vec::new();
+    let mut options = std::collections::HashMap::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.strip_prefix("--") {
+            Some(option) => {
+                let value = iter.next().ok_or(USER_USAGE)?;
+                options.insert(option.to_string(), value.clone());
+            }
+            None => positional.push(arg.as_str()),
+        }
+    }
+    
+    let access = |value: Option<&str>| value.map(Access::parse).transpose();
+    let command = match positional.as_slice() {
+        ["create", name] => UserCommand::Create {
+            name: name.to_string(),
+            access: access(options.get("access").map(String::as_str))?.unwrap_or(Access::Read),
+        },
+        ["delete", name] => UserCommand::Delete(name.to_string()),
+        ["list"] => UserCommand::List,
+        ["passwd", name] => UserCommand::Passwd(name.to_string()),
+        ["grant", name, rest @ ..] if rest.len() <= 1 => {
+            let access = access(rest.first().copied())?;
+            let role = options.get("role").cloned();
+            if access.is_none() && role.is_none() {
+                return Err(USER_USAGE.into());
+            }
+            UserCommand::Grant { name: name.to_string(), access, role }
+        }
+        ["unlock", name] => UserCommand::Unlock(name.to_string()),
+        ["expire", name] => UserCommand::Expire(name.to_string()),
+        _ => return Err(USER_USAGE.into()),
+    };
+    
+    let password = match &command {
+        UserCommand::Create { name, .. } | UserCommand::Passwd(name) => Some(read_password(name)?),
+        _ => None,
+    };
+    
+    match options.get("server") {
+        Some(server) => {
+            let token = options.get("token")
+                .cloned()
+                .or_else(|| env::var("HIVEDB_ADMIN_TOKEN").ok())
+                .ok_or("an admin token is required (--token or HIVEDB_ADMIN_TOKEN)")?;
+            user_remote(server, &token, &command, password)
+        }
+        None => user_local(&command, password),
+    }
+}
+
+/// Run a user command against the user store in the data directory
+fn user_local(command: &UserCommand, password: Option<String>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut users = open_users()?;
+    let password = password.unwrap_or_default();
+    
+    match command {
+        UserCommand::Create { name, access } => {
+            users.create_user(name, &password, *access)?;
+        }
+        UserCommand::Delete(name) => users.delete_user(name)?,
+        UserCommand::List => {
+            let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs();
+            let rows: Vec<Value> = users.list()
+                .into_iter()
+                .map(|user| json!({
+                    "name": user.name,
+                    "access": user.access,
+                    "roles": user.roles,
+                    "locked": user.is_locked(now),
+                    "password_expired": user.must_change_password || users.is_expired(user, now),
+                }))
+                .collect();
+            print_users(&rows);
+            return Ok(());
+        }
+        UserCommand::Passwd(name) => users.set_password(name, &password)?,
+        UserCommand::Grant { name, access, role } => {
+            if let Some(access) = access {
+                users.set_access(name, *access)?;
+            }
+            if let Some(role) = role {
+                users.add_role(name, role)?;
+            }
+        }
+        UserCommand::Unlock(name) => users.unlock(name)?,
+        UserCommand::Expire(name) => users.expire_password(name)?,
+    }
+    
+    println!("✅ {}", describe_user_command(command));
+    Ok(())
+}
+
+/// Run a user command through the admin API of a running server
+fn user_remote(
+    server: &str,
+    token: &str,
+    command: &UserCommand,
+    password: Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (method, path, body) = match command {
+        UserCommand::Create { name, access } => (
+            "POST",
+            "/users".to_string(),
+            json!({ "name": name, "password": password, "access": access.as_str() }),
+        ),
+        UserCommand::Delete(name) => ("DELETE", format!("/users/{}", name), Value::Null),
+        UserCommand::List => ("GET", "/users".to_string(), Value::Null),
+        UserCommand::Passwd(name) => ("PUT", format!("/users/{}/password", name), json!({ "password": password })),
+        UserCommand::Grant { name, access, role } => (
+            "POST",
+            format!("/users/{}/grant", name),
+            json!({ "access": access.map(|access| access.as_str()), "role": role }),
+        ),
+        UserCommand::Unlock(name) => ("POST", format!("/users/{}/unlock", name), Value::Null),
+        UserCommand::Expire(name) => ("POST", format!("/users/{}/expire", name), Value::Null),
+    };
+    
+    let body = if body.is_null() { Vec::new() } else { body.to_string().into_bytes() };
+    let request = HttpRequest::new(method, &path, &body)
+        .with_header("Authorization", &format!("Bearer {}", token))
+        .with_header("Content-Type", "application/json");
+    let response = http::send_request(server, &request, Duration::from_secs(30))?;
+    
+    if !(200..300).contains(&response.status) {
+        return Err(error_message(&response).into());
+    }
+    
+    match command {
+        UserCommand::List => {
+            let body = response.json_body()?;
+            print_users(body["users"].as_array().map(Vec::as_slice).unwrap_or_default());
+        }
+        _ => println!("✅ {}", describe_user_command(command)),
+    }
+    
+    Ok(())
+}
+
+/// Extract the error message from a failed admin API response
+fn error_message(response: &HttpResponse) -> String {
+    response.json_body()
+        .ok()
+        .and_then(|body| body.get("error").and_then(Value::as_str).map(str::to_string))
+        .unwrap_or_else(|| format!("server returned status {}", response.status))
+}
+
+/// Describe a completed user command
+fn describe_user_command(command: &UserCommand) -> String {
+    match command {
+        UserCommand::Create { name, .. } => format!("User '{}' created", name),
+        UserCommand::Delete(name) => format!("User '{}' deleted", name),
+        UserCommand::List => String::new(),
+        UserCommand::Passwd(name) => format!("Password of user '{}' changed", name),
+        UserCommand::Grant { name, .. } => format!("Privileges of user '{}' updated", name),
+        UserCommand::Unlock(name) => format!("User '{}' unlocked", name),
+        UserCommand::Expire(name) => format!("User '{}' must change their password at next login", name),
+    }
+}
+
+/// Print a table of users
+fn print_users(users: &[Value]) {
+    println!("{:<24} {:<8} {:<24} STATUS", "NAME", "ACCESS", "ROLES");
+    for user in users {
+        let roles: Vec<&str> = user["roles"].as_array()
+            .map(|roles| roles.iter().filter_map(Value::as_str).collect())
+            .unwrap_or_default();
+        let status = if user["locked"].as_bool() == Some(true) {
+            "locked"
+        } else if user["password_expired"].as_bool() == Some(true) {
+            "password expired"
+        } else {
+            "active"
+        };
+        
+        println!(
+            "{:<24} {:<8} {:<24} {}",
+            user["name"].as_str().unwrap_or_default(),
+            user["access"].as_str().unwrap_or_default().to_lowercase(),
+            roles.join(","),
+            status
+        );
+    }
+}
+
+/// Read a new password for a user from standard input
+fn read_password(name: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let password = read_secret(&format!("New password for '{}': ", name))?;
+    if password.is_empty() {
+        return Err("password must not be empty".into());
+    }
+    
+    Ok(password)
+}
+
+/// Read a line from standard input, without echoing it when typed at a terminal
+fn read_secret(prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
+    if std::io::stdin().is_terminal() {
+        return Ok(rpassword::prompt_password(prompt)?);
+    }
+    
+    // Piped input, e.g. from a secrets manager
+    print!("{}", prompt);
+    std::io::stdout().flush()?;
+    let mut secret = String::new();
+    std::io::stdin().read_line(&mut secret)?;
+    
+    Ok(secret.trim_end_matches(&['\r', '\n'][..]).to_string())
+}
+
 /// Print usage information
 fn print_usage() {
     println!("🐝 {} v{}", name(), version());
@@ -72,8 +2059,26 @@ fn print_usage() {
     println!("  hivedb [COMMAND] [OPTIONS]");
     println!();
     println!("COMMANDS:");
-    println!("  start             Start the HiveDB server");
-    println!("  create <name>     Create a new hive (database)");
+    println!("  start             Start the HiveDB server (--daemon to run in the background)");
+    println!("  stop              Stop a background server (--force to kill it)");
+    println!("  status            Show whether a server is running");
+    println!("  create <name>     Create a new hive (database) (--origin corner|center, --integrity sha256|crc32c)");
+    println!("  query <hive> <q>  Run one HQL query (--output json|jsonl|table|csv)");
+    println!("  inspect <hive>    Show statistics about a hive (--history, --json)");
+    println!("  verify <hive>     Check a hive for corruption (--repair, --json)");
+    println!("  doctor            Check the data directory and settings for problems (--json)");
+    println!("  index <hive>      List, rebuild or drop the indexes of a hive");
+    println!("  schema <hive> <f> Set the schema of a hive from JSON, Avro or .proto (--message)");
+    println!("  retention <hive>  List, add or apply rules archiving old or tagged cells (run --dry-run)");
+    println!("  rebalance <hive>  Pack the cells of a hive toward the center of its grid");
+    println!("  dictionary <hive> Train a compression dictionary for small documents (--samples N)");
+    println!("  backup <file>     Back up hives to an archive (--hive, --since, --passphrase, --key-file, --server)");
+    println!("  restore <file>... Restore hives from a backup and its increments (--key-file)");
+    println!("  seed <hive>       Fill a hive with generated documents (--schema, --count)");
+    println!("  watch <hive> [f]  Print changes to a hive on a server as they happen");
+    println!("  bench             Run a benchmark workload (embedded or --server)");
+    println!("  policy            Show or change the password policy");
+    println!("  user              Manage user accounts (create, delete, list, passwd, grant)");
     println!("  version           Display version information");
     println!("  help              Display this help message");
     println!();
@@ -0,0 +1,1337 @@
+// HiveDB Admin Module
+//
+// This module exposes hive lifecycle operations over HTTP so that
+// operators can manage a running server without the embedded API.
+// Every request must carry the admin token, an API key with admin
+// access or a JWT granting admin access as a bearer token, or
+// credentials an external provider maps to admin access.
+
+use std::net::TcpListener;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use serde_json::{json, Value};
+use crate::core::codec::{self, Integrity};
+use crate::core::coords::GridOrigin;
+use crate::core::error::HiveError;
+use crate::core::hive::{Hive, HiveManager};
+use crate::core::index;
+use crate::core::memory;
+use crate::core::mode::{ModeControl, ServerMode};
+use crate::core::query::QueryExecutor;
+use crate::core::schema::{Schema, SchemaIndex};
+use crate::core::session::SessionRegistry;
+use crate::core::sql;
+use crate::core::tiering;
+use crate::core::worker::{self, BackgroundLimits};
+use crate::security::jwt::{self, JwtValidator};
+use crate::security::{Access, ApiKeyScope, ApiKeyStore, Authenticator, Credentials, Principal, UserStore};
+use crate::network::cdc::content_to_json;
+use crate::network::http::{self, HttpRequest, HttpResponse};
+use crate::network::webhooks::{WebhookFilter, WebhookRegistry};
+use crate::utils::determinism;
+use crate::utils::telemetry::{self, TraceContext};
+use log::{debug, info, warn};
+
+/// Default grid dimensions for hives created through the admin API
+const DEFAULT_DIMENSIONS: (usize, usize) = (64, 64);
+
+/// Default limit on the positions (width times height) of a hive created through the admin API
+pub const DEFAULT_MAX_GRID_POSITIONS: usize = 1 << 20;
+
+/// Most change events returned by one request to the change feed
+pub const MAX_CHANGES_PER_REQUEST: usize = 1000;
+
+/// Handles admin API requests against a hive manager
+pub struct AdminApi {
+    /// Hives being administered
+    manager: Arc<RwLock<HiveManager>>,
+    
+    /// Token that requests must present
+    token: String,
+    
+    /// Sessions of the server's client listeners
+    sessions: Arc<SessionRegistry>,
+    
+    /// Operating mode of the server
+    mode: Arc<ModeControl>,
+    
+    /// API keys accepted in place of the admin token, and managed here
+    api_keys: Option<Arc<RwLock<ApiKeyStore>>>,
+    
+    /// Validator for JWTs issued by an external identity provider
+    jwt: Option<Arc<JwtValidator>>,
+    
+    /// External providers checking passwords (Basic auth) and ID tokens
+    authenticator: Option<Arc<Authenticator>>,
+    
+    /// Local user accounts managed here
+    users: Option<Arc<RwLock<UserStore>>>,
+    
+    /// Webhooks notified of changes, managed here
+    webhooks: Option<Arc<RwLock<WebhookRegistry>>>,
+    
+    /// Smallest response body compressed for clients that accept it (None to never compress)
+    compression_threshold: Option<usize>,
+    
+    /// Most positions a hive created here may have
+    max_grid_positions: usize,
+}
+
+impl AdminApi {
+    /// Create an admin API protected by the given token
+    pub fn new(manager: Arc<RwLock<HiveManager>>, token: String) -> Self {
+        Self {
+            manager,
+            token,
+            sessions: Arc::new(SessionRegistry::new()),
+            mode: Arc::new(ModeControl::default()),
+            api_keys: None,
+            jwt: None,
+            authenticator: None,
+            users: None,
+            webhooks: None,
+            compression_threshold: Some(http::DEFAULT_COMPRESSION_THRESHOLD),
+            max_grid_positions: DEFAULT_MAX_GRID_POSITIONS,
+        }
+    }
+    
+    /// Refuse to create hives with more than the given number of positions
+    pub fn with_max_grid_positions(mut self, max_positions: usize) -> Self {
+        self.max_grid_positions = max_positions;
+        self
+    }
+    
+    /// Compress response bodies of at least the given size, or never with None
+    pub fn with_compression_threshold(mut self, threshold: Option<usize>) -> Self {
+        self.compression_threshold = threshold;
+        self
+    }
+    
+    /// Accept JWTs checked by the given validator
+    pub fn with_jwt(mut self, validator: Arc<JwtValidator>) -> Self {
+        self.jwt = Some(validator);
+        self
+    }
+    
+    /// Accept users authenticated by external providers
+    pub fn with_authenticator(mut self, authenticator: Arc<Authenticator>) -> Self {
+        self.authenticator = Some(authenticator);
+        self
+    }
+    
+    /// Accept and manage the API keys of a key store
+    pub fn with_api_keys(mut self, api_keys: Arc<RwLock<ApiKeyStore>>) -> Self {
+        self.api_keys = Some(api_keys);
+        self
+    }
+    
+    /// Manage the accounts of a user store
+    pub fn with_users(mut self, users: Arc<RwLock<UserStore>>) -> Self {
+        self.users = Some(users);
+        self
+    }
+    
+    /// Manage the webhooks of a registry
+    pub fn with_webhooks(mut self, webhooks: Arc<RwLock<WebhookRegistry>>) -> Self {
+        self.webhooks = Some(webhooks);
+        self
+    }
+    
+    /// Control the operating mode shared with the client listeners
+    pub fn with_mode(mut self, mode: Arc<ModeControl>) -> Self {
+        self.mode = mode;
+        self
+    }
+    
+    /// Use the session registry shared with the client listeners
+    pub fn with_sessions(mut self, sessions: Arc<SessionRegistry>) -> Self {
+        self.sessions = sessions;
+        self
+    }
+    
+    /// Accept connections on the given address until the listener fails
+    pub fn serve(self: Arc<Self>, address: &str) -> Result<(), HiveError> {
+        if self.token.is_empty() && self.api_keys.is_none() && self.jwt.is_none() && self.authenticator.is_none() {
+            return Err(HiveError::AuthenticationError("No admin credentials are configured".to_string()));
+        }
+        
+        let listener = TcpListener::bind(address)
+            .map_err(|e| HiveError::NetworkError(e.to_string()))?;
+        
+        info!("Admin API listening on {}", address);
+        
+        for stream in listener.incoming() {
+            let mut stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!("Failed to accept admin connection: {}", e);
+                    continue;
+                }
+            };
+            
+            let api = self.clone();
+            std::thread::spawn(move || {
+                let response = match http::read_request(&mut stream) {
+                    Ok(request) => match api.compression_threshold {
+                        Some(threshold) => api.handle(&request).compressed_for(&request, threshold),
+                        None => api.handle(&request),
+                    },
+                    Err(e) => HttpResponse::json(400, &json!({ "error": e.to_string() })),
+                };
+                
+                if let Err(e) = http::write_response(&mut stream, &response) {
+                    debug!("Failed to send admin response: {}", e);
+                }
+            });
+        }
+        
+        Ok(())
+    }
+    
+    /// Authenticate and dispatch a request
+    pub fn handle(&self, request: &HttpRequest) -> HttpResponse {
+        let parent = request.header("traceparent").and_then(TraceContext::from_traceparent);
+        let mut span = telemetry::server_span("admin.request", parent);
+        span.set_attribute("http.request.method", &request.method);
+        span.set_attribute("url.path", &request.path);
+        
+        let response = match self.authenticate(request).and_then(|_| {
+            debug!("Admin request: {} {}", request.method, request.path);
+            self.route(request)
+        }) {
+            Ok(response) => response,
+            Err(e) => {
+                span.set_error(&e);
+                error_response(&e)
+            }
+        };
+        
+        span.set_attribute("http.response.status_code", response.status);
+        response
+    }
+    
+    fn authenticate(&self, request: &HttpRequest) -> Result<(), HiveError> {
+        if let Some((username, password)) = request.basic_credentials() {
+            let authenticator = self.authenticator.as_ref()
+                .ok_or_else(|| HiveError::AuthenticationError("Password authentication is not configured".to_string()))?;
+            return require_admin(authenticator.authenticate(&Credentials::Password { username, password })?);
+        }
+        
+        let token = request.bearer_token()
+            .ok_or_else(|| HiveError::AuthenticationError("Missing bearer token".to_string()))?;
+        
+        if !self.token.is_empty()
+            && codec::constant_time_eq(token.as_bytes(), self.token.as_bytes())
+        {
+            return Ok(());
+        }
+        
+        if jwt::is_jwt(token) && (self.jwt.is_some() || self.authenticator.is_some()) {
+            let validated = match &self.jwt {
+                Some(validator) => validator.validate(token),
+                None => Err(HiveError::AuthenticationError("Admin JWTs are not configured".to_string())),
+            };
+            
+            // A token the admin JWT settings refuse may be an ID token of an external provider
+            let principal = match (validated, &self.authenticator) {
+                (Ok(principal), _) => principal,
+                (Err(_), Some(authenticator)) => authenticator.authenticate(&Credentials::Token(token.to_string()))?,
+                (Err(e), None) => return Err(e),
+            };
+            return require_admin(principal);
+        }
+        
+        match &self.api_keys {
+            Some(api_keys) => {
+                let api_keys = api_keys.read().map_err(|_| HiveError::LockError)?;
+                api_keys.authorize(token, None, Access::Admin).map(|_| ())
+            }
+            None => Err(HiveError::AuthenticationError("Invalid admin token".to_string())),
+        }
+    }
+    
+    fn route(&self, request: &HttpRequest) -> Result<HttpResponse, HiveError> {
+        let segments = request.segments();
+        
+        match (request.method.as_str(), segments.as_slice()) {
+            ("GET", ["hives"]) => self.list_hives(),
+            ("POST", ["hives"]) => self.create_hive(request),
+            ("GET", ["hives", hive]) => self.describe_hive(hive),
+            ("DELETE", ["hives", hive]) => self.delete_hive(hive),
+            ("GET", ["hives", hive, "changes"]) => self.list_changes(hive, request),
+            ("GET", ["hives", hive, "snapshot"]) => self.hive_snapshot(hive),
+            ("GET", ["hives", hive, "heatmap"]) => self.heat_map(hive, request),
+            ("POST", ["hives", hive, "query"]) => self.query_hive(hive, request),
+            ("PUT", ["hives", hive, "schema"]) => self.set_schema(hive, request),
+            ("GET", ["hives", hive, "indexes"]) => self.list_indexes(hive),
+            ("POST", ["hives", hive, "indexes"]) => self.build_index(hive, request),
+            ("POST", ["hives", hive, "indexes", name, "rebuild"]) => self.rebuild_index(hive, name),
+            ("DELETE", ["hives", hive, "indexes", name]) => self.drop_index(hive, name),
+            ("GET", ["mode"]) => Ok(HttpResponse::json(200, &json!({ "mode": self.mode.get().as_str() }))),
+            ("PUT", ["mode"]) => self.set_mode(request),
+            ("GET", ["stats"]) => self.server_stats(),
+            ("GET", ["events"]) => self.list_events(request),
+            ("GET", ["memory"]) => Ok(HttpResponse::json(200, &json!(memory::global().usage()))),
+            ("GET", ["tiering"]) => Ok(self.tiering_stats()),
+            ("GET", ["background"]) => self.background_limits(),
+            ("PUT", ["background"]) => self.set_background_limits(request),
+            ("GET", ["disk"]) => Ok(self.disk_status()),
+            ("GET", ["api-keys"]) => self.list_api_keys(),
+            ("POST", ["api-keys"]) => self.create_api_key(request),
+            ("POST", ["api-keys", id, "rotate"]) => self.rotate_api_key(id, request),
+            ("DELETE", ["api-keys", id]) => self.revoke_api_key(id),
+            ("GET", ["users"]) => self.list_users(),
+            ("POST", ["users"]) => self.create_user(request),
+            ("DELETE", ["users", name]) => self.update_user(|users| users.delete_user(name)),
+            ("PUT", ["users", name, "password"]) => self.set_password(name, request),
+            ("POST", ["users", name, "grant"]) => self.grant(name, request),
+            ("POST", ["users", name, "unlock"]) => self.update_user(|users| users.unlock(name)),
+            ("POST", ["users", name, "expire"]) => self.update_user(|users| users.expire_password(name)),
+            ("GET", ["webhooks"]) => self.list_webhooks(),
+            ("POST", ["webhooks"]) => self.register_webhook(request),
+            ("DELETE", ["webhooks", id]) => self.update_webhook(|webhooks| webhooks.unregister(id).map(|_| ())),
+            ("POST", ["webhooks", id, "enable"]) => self.update_webhook(|webhooks| webhooks.set_active(id, true)),
+            ("POST", ["webhooks", id, "disable"]) => self.update_webhook(|webhooks| webhooks.set_active(id, false)),
+            ("GET", ["sessions"]) => self.list_sessions(),
+            ("GET", ["queries"]) => self.list_queries(),
+            ("GET", ["usage"]) => self.list_usage(request),
+            ("GET", ["tenants"]) => self.list_tenants(),
+            ("DELETE", ["queries", id]) => self.kill_query(id),
+            _ => Ok(HttpResponse::json(404, &json!({ "error": "Unknown admin endpoint" }))),
+        }
+    }
+    
+    fn list_hives(&self) -> Result<HttpResponse, HiveError> {
+        let manager = self.manager.read().map_err(|_| HiveError::LockError)?;
+        
+        let mut hives = manager.list_hives();
+        hives.sort_by(|a, b| a.1.cmp(&b.1));
+        
+        let hives: Vec<Value> = hives.into_iter()
+            .map(|(id, name)| json!({ "id": id, "name": name }))
+            .collect();
+        
+        Ok(HttpResponse::json(200, &json!({ "hives": hives })))
+    }
+    
+    fn create_hive(&self, request: &HttpRequest) -> Result<HttpResponse, HiveError> {
+        let body = request.json()?;
+        
+        let name = body.get("name")
+            .and_then(Value::as_str)
+            .filter(|name| !name.is_empty())
+            .ok_or_else(|| HiveError::DeserializationError("'name' is required".to_string()))?;
+        let description = body.get("description").and_then(Value::as_str).unwrap_or("");
+        let owner = body.get("owner").and_then(Value::as_str).unwrap_or("admin");
+        let dimensions = match body.get("dimensions").and_then(Value::as_array) {
+            Some(dimensions) => match (dimensions.first().and_then(Value::as_u64), dimensions.get(1).and_then(Value::as_u64)) {
+                (Some(width), Some(height)) => grid_dimensions(width, height, self.max_grid_positions)?,
+                _ => return Err(HiveError::DeserializationError("'dimensions' must be [width, height]".to_string())),
+            },
+            None => DEFAULT_DIMENSIONS,
+        };
+        let origin: GridOrigin = match body.get("origin").and_then(Value::as_str) {
+            Some(origin) => origin.parse()
+                .map_err(|e: HiveError| HiveError::DeserializationError(e.to_string()))?,
+            None => GridOrigin::Corner,
+        };
+        let integrity: Integrity = match body.get("integrity").and_then(Value::as_str) {
+            Some(integrity) => integrity.parse()
+                .map_err(|e: HiveError| HiveError::DeserializationError(e.to_string()))?,
+            None => Integrity::default(),
+        };
+        
+        let mut manager = self.manager.write().map_err(|_| HiveError::LockError)?;
+        
+        if manager.get_hive_by_name(name).is_some() {
+            return Ok(HttpResponse::json(409, &json!({ "error": format!("Hive '{}' already exists", name) })));
+        }
+        
+        let id = manager.create_hive(name.to_string(), description.to_string(), owner.to_string(), dimensions)?;
+        if let Some(hive_arc) = manager.get_hive(&id) {
+            let mut hive = hive_arc.write().map_err(|_| HiveError::LockError)?;
+            hive.cells.set_origin(origin)?;
+            hive.cells.set_integrity(integrity);
+            hive.commit()?;
+        }
+        info!("Admin API created hive '{}'", name);
+        
+        Ok(HttpResponse::json(201, &json!({ "id": id, "name": name })))
+    }
+    
+    fn describe_hive(&self, key: &str) -> Result<HttpResponse, HiveError> {
+        let hive_arc = self.find_hive(key)?;
+        let hive = hive_arc.read().map_err(|_| HiveError::LockError)?;
+        
+        Ok(HttpResponse::json(200, &describe(&hive)))
+    }
+    
+    fn delete_hive(&self, key: &str) -> Result<HttpResponse, HiveError> {
+        let id = {
+            let hive_arc = self.find_hive(key)?;
+            let hive = hive_arc.read().map_err(|_| HiveError::LockError)?;
+            hive.id.clone()
+        };
+        
+        self.manager.write().map_err(|_| HiveError::LockError)?
+            .delete_hive(&id)?;
+        info!("Admin API deleted hive {}", id);
+        
+        Ok(HttpResponse::json(200, &json!({ "deleted": id })))
+    }
+    
+    /// List the change events recorded after `since`, oldest first
+    ///
+    /// The response always carries the hive's last sequence number, so
+    /// clients can start following the feed from the current position
+    /// by asking for `limit=0`.
+    fn list_changes(&self, key: &str, request: &HttpRequest) -> Result<HttpResponse, HiveError> {
+        let number = |name: &str| request.query_param(name)
+            .map(|value| value.parse::<u64>()
+                .map_err(|_| HiveError::QueryError(format!("Invalid value '{}' for '{}'", value, name))))
+            .transpose();
+        let since = number("since")?.unwrap_or(0);
+        let limit = number("limit")?.map_or(MAX_CHANGES_PER_REQUEST, |limit| (limit as usize).min(MAX_CHANGES_PER_REQUEST));
+        
+        let hive_arc = self.find_hive(key)?;
+        let hive = hive_arc.read().map_err(|_| HiveError::LockError)?;
+        let events: Vec<Value> = hive.changes.iter_since(since)?
+            .take(limit)
+            .map(|event| json!({
+                "sequence": event.sequence,
+                "kind": event.kind,
+                "cell_id": event.cell_id,
+                "coordinates": event.coordinates,
+                "timestamp": event.timestamp,
+                "document": event.content.as_deref().map(content_to_json),
+            }))
+            .collect();
+        
+        Ok(HttpResponse::json(200, &json!({
+            "hive_id": hive.id,
+            "last_sequence": hive.changes.last_sequence(),
+            "events": events,
+        })))
+    }
+    
+    /// Take a snapshot of a hive for a hot backup
+    ///
+    /// The hive is only locked while its state is copied, so writes go on
+    /// while the copy is encoded and sent. The storage directory and version
+    /// of the hive are returned as headers.
+    fn hive_snapshot(&self, key: &str) -> Result<HttpResponse, HiveError> {
+        let copy = {
+            let hive_arc = self.find_hive(key)?;
+            let hive = hive_arc.read().map_err(|_| HiveError::LockError)?;
+            hive.copy_snapshot()?
+        };
+        
+        let response = HttpResponse {
+            status: 200,
+            content_type: "application/octet-stream".to_string(),
+            headers: Vec::new(),
+            body: copy.encode()?,
+        };
+        Ok(response
+            .with_header("X-HiveDB-Directory", copy.directory())
+            .with_header("X-HiveDB-Version", &copy.version().to_string()))
+    }
+    
+    fn heat_map(&self, key: &str, request: &HttpRequest) -> Result<HttpResponse, HiveError> {
+        let map = {
+            let hive_arc = self.find_hive(key)?;
+            let hive = hive_arc.read().map_err(|_| HiveError::LockError)?;
+            hive.heat_map()?
+        };
+        
+        match request.query_param("format").unwrap_or("json") {
+            "json" => Ok(HttpResponse::json(200, &json!(map))),
+            "svg" => Ok(HttpResponse {
+                status: 200,
+                content_type: "image/svg+xml".to_string(),
+                headers: Vec::new(),
+                body: map.to_svg().into_bytes(),
+            }),
+            format => Err(HiveError::QueryError(format!("Unknown heat map format '{}'", format))),
+        }
+    }
+    
+    /// Run a read-only SQL query against a hive
+    ///
+    /// The body holds the `sql` statement and, to fetch the next page of
+    /// an earlier query, the `cursor` that query returned as `next_cursor`.
+    fn query_hive(&self, key: &str, request: &HttpRequest) -> Result<HttpResponse, HiveError> {
+        let body = request.json()?;
+        let mut query = sql::parse(required_str(&body, "sql")?)?;
+        query.cursor = body.get("cursor").and_then(Value::as_str).map(str::to_string);
+        
+        let hive_arc = self.find_hive(key)?;
+        let hive = hive_arc.read().map_err(|_| HiveError::LockError)?;
+        let result = QueryExecutor::execute_read(&query, &hive)?;
+        
+        Ok(HttpResponse::json(200, &json!({
+            "results": result.results,
+            "count": result.count,
+            "has_more": result.has_more,
+            "next_cursor": result.next_cursor,
+            "execution_time_ms": result.execution_time_ms,
+        })))
+    }
+    
+    fn set_schema(&self, key: &str, request: &HttpRequest) -> Result<HttpResponse, HiveError> {
+        let schema: Schema = serde_json::from_slice(&request.body)
+            .map_err(|e| HiveError::DeserializationError(e.to_string()))?;
+        
+        let hive_arc = self.find_hive(key)?;
+        let mut hive = hive_arc.write().map_err(|_| HiveError::LockError)?;
+        hive.set_schema(schema)?;
+        hive.commit()?;
+        info!("Admin API replaced the schema of hive '{}'", hive.name);
+        
+        Ok(HttpResponse::json(200, &describe(&hive)))
+    }
+    
+    /// Start building an index in the background
+    ///
+    /// The index joins the hive's schema once built, replacing any
+    /// existing index with the same name. Progress is reported by
+    /// `GET /hives/{hive}/indexes`.
+    fn build_index(&self, key: &str, request: &HttpRequest) -> Result<HttpResponse, HiveError> {
+        let index: SchemaIndex = serde_json::from_slice(&request.body)
+            .map_err(|e| HiveError::DeserializationError(e.to_string()))?;
+        
+        let hive_arc = self.find_hive(key)?;
+        if hive_arc.read().map_err(|_| HiveError::LockError)?.schema.is_none() {
+            return Err(HiveError::SchemaValidationError("Hive has no schema".to_string()));
+        }
+        
+        let build = index::build_in_background(&hive_arc, index)?;
+        info!("Admin API started building index '{}' in hive '{}'", build.name(), key);
+        
+        Ok(HttpResponse::json(202, &json!({ "index": build.name(), "state": "building" })))
+    }
+    
+    fn rebuild_index(&self, key: &str, name: &str) -> Result<HttpResponse, HiveError> {
+        let hive_arc = self.find_hive(key)?;
+        let mut hive = hive_arc.write().map_err(|_| HiveError::LockError)?;
+        let entries = hive.rebuild_index(name)?;
+        info!("Admin API rebuilt index '{}' of hive '{}'", name, hive.name);
+        
+        Ok(HttpResponse::json(200, &json!({ "index": name, "entries": entries })))
+    }
+    
+    fn drop_index(&self, key: &str, name: &str) -> Result<HttpResponse, HiveError> {
+        let hive_arc = self.find_hive(key)?;
+        let mut hive = hive_arc.write().map_err(|_| HiveError::LockError)?;
+        hive.drop_index(name)?;
+        hive.commit()?;
+        info!("Admin API dropped index '{}' of hive '{}'", name, hive.name);
+        
+        Ok(HttpResponse::json(200, &json!({ "dropped": name })))
+    }
+    
+    fn list_indexes(&self, key: &str) -> Result<HttpResponse, HiveError> {
+        let hive_arc = self.find_hive(key)?;
+        let hive = hive_arc.read().map_err(|_| HiveError::LockError)?;
+        
+        let mut indexes: Vec<Value> = hive.indexes.iter()
+            .map(|index| json!({
+                "name": index.definition().name,
+                "state": "ready",
+                "entries": index.len(),
+            }))
+            .collect();
+        indexes.extend(hive.indexes.building().map(|(name, progress)| json!({
+            "name": name,
+            "state": "building",
+            "scanned": progress.scanned(),
+            "total": progress.total(),
+        })));
+        
+        Ok(HttpResponse::json(200, &json!({ "indexes": indexes })))
+    }
+    
+    fn set_mode(&self, request: &HttpRequest) -> Result<HttpResponse, HiveError> {
+        let body = request.json()?;
+        let mode = body.get("mode")
+            .and_then(Value::as_str)
+            .ok_or_else(|| HiveError::DeserializationError("'mode' is required".to_string()))?;
+        let mode = ServerMode::parse(mode)?;
+        
+        let previous = self.mode.set(mode);
+        info!("Admin API switched server mode from {} to {}", previous.as_str(), mode.as_str());
+        
+        Ok(HttpResponse::json(200, &json!({ "mode": mode.as_str(), "previous": previous.as_str() })))
+    }
+    
+    fn background_limits(&self) -> Result<HttpResponse, HiveError> {
+        Ok(HttpResponse::json(200, &json!({
+            "limits": worker::background_limits(),
+            "workers": worker::pool().stats()?,
+        })))
+    }
+    
+    /// Change the limits on background work; a missing or null limit is lifted
+    fn set_background_limits(&self, request: &HttpRequest) -> Result<HttpResponse, HiveError> {
+        let limits: BackgroundLimits = serde_json::from_slice(&request.body)
+            .map_err(|e| HiveError::DeserializationError(e.to_string()))?;
+        if limits.max_mb_per_sec.is_some_and(|mb| mb.is_nan() || mb <= 0.0) || limits.max_tasks == Some(0) {
+            return Err(HiveError::DeserializationError("Limits must be positive".to_string()));
+        }
+        
+        worker::set_background_limits(limits);
+        
+        Ok(HttpResponse::json(200, &json!({ "limits": limits })))
+    }
+    
+    fn tiering_stats(&self) -> HttpResponse {
+        let stats = tiering::stats();
+        HttpResponse::json(200, &json!({
+            "configured": tiering::cold_tier().map_or(false, |tier| tier.is_some()),
+            "reads": stats,
+            "hot_hit_rate": stats.hot_hit_rate(),
+            "cache_hit_rate": stats.cache_hit_rate(),
+        }))
+    }
+    
+    fn server_stats(&self) -> Result<HttpResponse, HiveError> {
+        let stats = self.manager.read().map_err(|_| HiveError::LockError)?.stats()?;
+        
+        Ok(HttpResponse::json(200, &json!(stats)))
+    }
+    
+    fn list_events(&self, request: &HttpRequest) -> Result<HttpResponse, HiveError> {
+        let since = request.query_param("since")
+            .map(|value| value.parse::<u64>()
+                .map_err(|_| HiveError::QueryError(format!("Invalid value '{}' for 'since'", value))))
+            .transpose()?
+            .unwrap_or(0);
+        
+        let manager = self.manager.read().map_err(|_| HiveError::LockError)?;
+        Ok(HttpResponse::json(200, &json!({
+            "last_sequence": manager.events().last_sequence(),
+            "events": manager.events().since(since)?,
+        })))
+    }
+    
+    fn disk_status(&self) -> HttpResponse {
+        match self.mode.disk() {
+            Some(disk) => HttpResponse::json(200, &json!(disk.status())),
+            None => HttpResponse::json(200, &json!({ "monitored": false })),
+        }
+    }
+    
+    fn list_sessions(&self) -> Result<HttpResponse, HiveError> {
+        let sessions = self.sessions.sessions()?;
+        let queries = self.sessions.queries()?;
+        
+        Ok(HttpResponse::json(200, &json!({ "sessions": sessions, "queries": queries })))
+    }
+    
+    fn list_queries(&self) -> Result<HttpResponse, HiveError> {
+        let queries = self.sessions.queries()?;
+        
+        Ok(HttpResponse::json(200, &json!({ "queries": queries })))
+    }
+    
+    /// List the operations each user ran against each hive
+    ///
+    /// The `user` and `hive` query parameters narrow the list.
+    fn list_usage(&self, request: &HttpRequest) -> Result<HttpResponse, HiveError> {
+        let usage: Vec<_> = self.sessions.usage()?
+            .into_iter()
+            .filter(|stats| request.query_param("user").is_none_or(|user| stats.user == user))
+            .filter(|stats| request.query_param("hive").is_none_or(|hive| stats.hive == hive))
+            .collect();
+        
+        Ok(HttpResponse::json(200, &json!({ "usage": usage })))
+    }
+    
+    fn list_tenants(&self) -> Result<HttpResponse, HiveError> {
+        match self.sessions.scheduler() {
+            Some(scheduler) => Ok(HttpResponse::json(200, &json!({ "tenants": scheduler.usage()? }))),
+            None => Ok(HttpResponse::json(200, &json!({ "scheduled": false }))),
+        }
+    }
+    
+    fn kill_query(&self, id: &str) -> Result<HttpResponse, HiveError> {
+        let id: u64 = id.parse()
+            .map_err(|_| HiveError::DeserializationError(format!("Invalid query ID '{}'", id)))?;
+        
+        self.sessions.kill_query(id)?;
+        info!("Admin API killed query {}", id);
+        
+        Ok(HttpResponse::json(202, &json!({ "killed": id })))
+    }
+    
+    fn key_store(&self) -> Result<&Arc<RwLock<ApiKeyStore>>, HiveError> {
+        self.api_keys.as_ref().ok_or(HiveError::NotImplemented)
+    }
+    
+    fn list_api_keys(&self) -> Result<HttpResponse, HiveError> {
+        let store = self.key_store()?.read().map_err(|_| HiveError::LockError)?;
+        
+        Ok(HttpResponse::json(200, &json!({ "api_keys": store.list() })))
+    }
+    
+    fn create_api_key(&self, request: &HttpRequest) -> Result<HttpResponse, HiveError> {
+        let body = request.json()?;
+        
+        let name = body.get("name")
+            .and_then(Value::as_str)
+            .ok_or_else(|| HiveError::DeserializationError("'name' is required".to_string()))?;
+        let access = Access::parse(body.get("access").and_then(Value::as_str).unwrap_or("read"))?;
+        let hives = body.get("hives")
+            .and_then(Value::as_array)
+            .map(|hives| hives.iter().filter_map(Value::as_str).map(str::to_string).collect())
+            .unwrap_or_default();
+        let ttl = body.get("ttl_secs").and_then(Value::as_u64).map(Duration::from_secs);
+        
+        let mut store = self.key_store()?.write().map_err(|_| HiveError::LockError)?;
+        let (key, token) = store.create(name, ApiKeyScope::hives(hives, access), ttl)?;
+        
+        Ok(HttpResponse::json(201, &json!({ "api_key": key, "token": token })))
+    }
+    
+    fn rotate_api_key(&self, id: &str, request: &HttpRequest) -> Result<HttpResponse, HiveError> {
+        let body = request.json()?;
+        let grace = Duration::from_secs(body.get("grace_secs").and_then(Value::as_u64).unwrap_or(0));
+        
+        let mut store = self.key_store()?.write().map_err(|_| HiveError::LockError)?;
+        let token = store.rotate(id, grace)?;
+        
+        Ok(HttpResponse::json(200, &json!({ "id": id, "token": token })))
+    }
+    
+    fn revoke_api_key(&self, id: &str) -> Result<HttpResponse, HiveError> {
+        let mut store = self.key_store()?.write().map_err(|_| HiveError::LockError)?;
+        store.revoke(id)?;
+        
+        Ok(HttpResponse::json(200, &json!({ "revoked": id })))
+    }
+    
+    fn user_store(&self) -> Result<&Arc<RwLock<UserStore>>, HiveError> {
+        self.users.as_ref().ok_or(HiveError::NotImplemented)
+    }
+    
+    fn list_users(&self) -> Result<HttpResponse, HiveError> {
+        let users = self.user_store()?.read().map_err(|_| HiveError::LockError)?;
+        let now = crate::security::now()?;
+        
+        let list: Vec<Value> = users.list()
+            .into_iter()
+            .map(|user| json!({
+                "name": user.name,
+                "access": user.access,
+                "roles": user.roles,
+                "locked": user.is_locked(now),
+                "password_expired": user.must_change_password || users.is_expired(user, now),
+                "created_at": user.created_at,
+            }))
+            .collect();
+        
+        Ok(HttpResponse::json(200, &json!({ "users": list })))
+    }
+    
+    fn create_user(&self, request: &HttpRequest) -> Result<HttpResponse, HiveError> {
+        let body = request.json()?;
+        
+        let name = required_str(&body, "name")?;
+        let password = required_str(&body, "password")?;
+        let access = Access::parse(body.get("access").and_then(Value::as_str).unwrap_or("read"))?;
+        
+        let mut users = self.user_store()?.write().map_err(|_| HiveError::LockError)?;
+        let user = users.create_user(name, password, access)?;
+        
+        Ok(HttpResponse::json(201, &json!({ "name": user.name, "access": user.access })))
+    }
+    
+    fn set_password(&self, name: &str, request: &HttpRequest) -> Result<HttpResponse, HiveError> {
+        let body = request.json()?;
+        let password = required_str(&body, "password")?;
+        
+        self.update_user(|users| users.set_password(name, password))
+    }
+    
+    fn grant(&self, name: &str, request: &HttpRequest) -> Result<HttpResponse, HiveError> {
+        let body = request.json()?;
+        let access = body.get("access").and_then(Value::as_str).map(Access::parse).transpose()?;
+        let role = body.get("role").and_then(Value::as_str);
+        if access.is_none() && role.is_none() {
+            return Err(HiveError::DeserializationError("'access' or 'role' is required".to_string()));
+        }
+        
+        self.update_user(|users| {
+            if let Some(access) = access {
+                users.set_access(name, access)?;
+            }
+            if let Some(role) = role {
+                users.add_role(name, role)?;
+            }
+            Ok(())
+        })
+    }
+    
+    /// Apply a change to the user store and acknowledge it
+    fn update_user<F>(&self, change: F) -> Result<HttpResponse, HiveError>
+    where
+        F: FnOnce(&mut UserStore) -> Result<(), HiveError>,
+    {
+        let mut users = self.user_store()?.write().map_err(|_| HiveError::LockError)?;
+        change(&mut users)?;
+        
+        Ok(HttpResponse::json(200, &json!({ "ok": true })))
+    }
+    
+    fn webhook_registry(&self) -> Result<&Arc<RwLock<WebhookRegistry>>, HiveError> {
+        self.webhooks.as_ref().ok_or(HiveError::NotImplemented)
+    }
+    
+    /// List the webhooks, without their secrets
+    fn list_webhooks(&self) -> Result<HttpResponse, HiveError> {
+        let webhooks = self.webhook_registry()?.read().map_err(|_| HiveError::LockError)?;
+        
+        let mut list: Vec<Value> = webhooks.list()
+            .into_iter()
+            .map(|hook| json!({
+                "id": hook.id,
+                "url": hook.url,
+                "filter": hook.filter,
+                "active": hook.active,
+            }))
+            .collect();
+        list.sort_by(|a, b| a["id"].as_str().cmp(&b["id"].as_str()));
+        
+        Ok(HttpResponse::json(200, &json!({ "webhooks": list })))
+    }
+    
+    /// Register a webhook, generating its secret unless one is given
+    fn register_webhook(&self, request: &HttpRequest) -> Result<HttpResponse, HiveError> {
+        let body = request.json()?;
+        
+        let url = body.get("url")
+            .and_then(Value::as_str)
+            .ok_or_else(|| HiveError::DeserializationError("'url' is required".to_string()))?;
+        let filter: WebhookFilter = match body.get("filter") {
+            Some(filter) => serde_json::from_value(filter.clone())
+                .map_err(|e| HiveError::DeserializationError(e.to_string()))?,
+            None => WebhookFilter::default(),
+        };
+        let secret = match body.get("secret").and_then(Value::as_str) {
+            Some(secret) => secret.to_string(),
+            None => hex::encode(determinism::random_bytes(32)),
+        };
+        
+        let mut webhooks = self.webhook_registry()?.write().map_err(|_| HiveError::LockError)?;
+        let id = webhooks.register(url.to_string(), filter, secret.clone())
+            .map_err(|e| match e {
+                // The URL was refused
+                HiveError::NetworkError(message) => HiveError::DeserializationError(message),
+                e => e,
+            })?;
+        
+        Ok(HttpResponse::json(201, &json!({ "id": id, "secret": secret })))
+    }
+    
+    /// Apply a change to the webhook registry and acknowledge it
+    fn update_webhook<F>(&self, change: F) -> Result<HttpResponse, HiveError>
+    where
+        F: FnOnce(&mut WebhookRegistry) -> Result<(), HiveError>,
+    {
+        let mut webhooks = self.webhook_registry()?.write().map_err(|_| HiveError::LockError)?;
+        change(&mut webhooks)?;
+        
+        Ok(HttpResponse::json(200, &json!({ "ok": true })))
+    }
+    
+    /// Look up a hive by ID, falling back to its name
+    fn find_hive(&self, key: &str) -> Result<Arc<RwLock<Hive>>, HiveError> {
+        let manager = self.manager.read().map_err(|_| HiveError::LockError)?;
+        
+        manager.get_hive(key)
+            .or_else(|| manager.get_hive_by_name(key))
+            .ok_or(HiveError::HiveNotFound)
+    }
+}
+
+/// Get a required string field of a request body
+fn required_str<'a>(body: &'a Value, field: &str) -> Result<&'a str, HiveError> {
+    body.get(field)
+        .and_then(Value::as_str)
+        .ok_or_else(|| HiveError::DeserializationError(format!("'{}' is required", field)))
+}
+
+/// Summarize a hive for admin responses
+fn describe(hive: &Hive) -> Value {
+    json!({
+        "id": hive.id,
+        "name": hive.name,
+        "description": hive.description,
+        "owner": hive.metadata.owner,
+        "version": hive.metadata.version,
+        "tags": hive.metadata.tags,
+        "cells": hive.cell_count(),
+        "origin": hive.cells.origin().to_string(),
+        "integrity": hive.cells.integrity().to_string(),
+        "schema": hive.schema.as_ref().map(|schema| schema.name.clone()),
+        "created_at": hive.created_at,
+        "modified_at": hive.modified_at,
+    })
+}
+
+/// Refuse principals without admin access
+fn require_admin(principal: Principal) -> Result<(), HiveError> {
+    if principal.access < Access::Admin {
+        return Err(HiveError::AuthorizationError(format!("User '{}' is not an administrator", principal.name)));
+    }
+    
+    Ok(())
+}
+
+/// Check requested grid dimensions against the most positions allowed
+///
+/// Both sides must be at least one and fit grid coordinates.
+fn grid_dimensions(width: u64, height: u64, max_positions: usize) -> Result<(usize, usize), HiveError> {
+    let fits = |side: u64| i32::try_from(side).is_ok_and(|side| side > 0);
+    match width.checked_mul(height) {
+        Some(positions) if fits(width) && fits(height) && positions <= max_positions as u64 => {
+            Ok((width as usize, height as usize))
+        }
+        _ => Err(HiveError::DeserializationError(format!(
+            "'dimensions' must be positive and hold at most {} positions",
+            max_positions
+        ))),
+    }
+}
+
+/// Map an error to an HTTP response
+fn error_response(error: &HiveError) -> HttpResponse {
+    let status = match error {
+        HiveError::AuthenticationError(_) => 401,
+        HiveError::AuthorizationError(_) => 403,
+        HiveError::HiveNotFound
+        | HiveError::CellNotFound
+        | HiveError::QueryNotFound(_)
+        | HiveError::ApiKeyNotFound(_)
+        | HiveError::IndexNotFound(_)
+        | HiveError::UserNotFound(_)
+        | HiveError::WebhookNotFound(_) => 404,
+        // The hive is still referenced elsewhere (e.g. by a client session)
+        HiveError::ReferenceError | HiveError::UserAlreadyExists(_) => 409,
+        // The client fell behind the retained part of the change log
+        HiveError::ChangeLogTruncated(_) => 410,
+        HiveError::DeserializationError(_)
+        | HiveError::SchemaValidationError(_)
+        | HiveError::PasswordPolicyViolation(_)
+        | HiveError::QueryError(_) => 400,
+        HiveError::NotImplemented => 501,
+        // The client may retry once running queries have released memory or slots
+        HiveError::MemoryBudgetExceeded(_) | HiveError::TenantBusy(_) => 503,
+        HiveError::DiskSpaceLow(_) => 507,
+        _ => 500,
+    };
+    
+    HttpResponse::json(status, &json!({ "error": error.to_string() }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::cell::{Cell, CellDataType};
+    use crate::core::session::Operation;
+    use crate::security::auth::ExternalIdentity;
+    use crate::security::RoleMapping;
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use tempfile::tempdir;
+    
+    fn request(method: &str, path: &str, body: &str) -> HttpRequest {
+        HttpRequest::new(method, path, body.as_bytes())
+            .with_header("Authorization", "Bearer admin-secret")
+    }
+    
+    #[test]
+    fn test_admin_requires_token() {
+        let temp_dir = tempdir().unwrap();
+        let manager = Arc::new(RwLock::new(HiveManager::new(temp_dir.path().to_path_buf()).unwrap()));
+        let api = AdminApi::new(manager, "admin-secret".to_string());
+        
+        let response = api.handle(&HttpRequest::new("GET", "/hives", b""));
+        assert_eq!(response.status, 401);
+        
+        let response = api.handle(&HttpRequest::new("GET", "/hives", b"")
+            .with_header("Authorization", "Bearer wrong"));
+        assert_eq!(response.status, 401);
+    }
+    
+    /// Provider accepting the passwords of a fixed directory
+    struct DirectoryProvider;
+    
+    impl crate::security::AuthProvider for DirectoryProvider {
+        fn name(&self) -> &str {
+            "directory"
+        }
+        
+        fn authenticate(&self, credentials: &Credentials) -> Result<Option<ExternalIdentity>, HiveError> {
+            let Credentials::Password { username, password } = credentials else {
+                return Ok(None);
+            };
+            if password != "correct horse" {
+                return Err(HiveError::AuthenticationError("bad password".to_string()));
+            }
+            
+            let groups = if username == "dana" { vec!["dba".to_string()] } else { Vec::new() };
+            Ok(Some(ExternalIdentity { username: username.clone(), groups, provider: self.name().to_string() }))
+        }
+    }
+    
+    #[test]
+    fn test_admin_external_authentication() {
+        let temp_dir = tempdir().unwrap();
+        let manager = Arc::new(RwLock::new(HiveManager::new(temp_dir.path().to_path_buf()).unwrap()));
+        let mapping = RoleMapping::new().map_group("dba", "admin").role("admin", Access::Admin).role("member", Access::Read).default_role("member");
+        let authenticator = Authenticator::new(mapping).with_provider(Box::new(DirectoryProvider));
+        let api = AdminApi::new(manager.clone(), "admin-secret".to_string()).with_authenticator(Arc::new(authenticator));
+        
+        let basic = |credentials: &str| HttpRequest::new("GET", "/hives", b"")
+            .with_header("Authorization", &format!("Basic {}", STANDARD.encode(credentials)));
+        assert_eq!(api.handle(&basic("dana:correct horse")).status, 200);
+        assert_eq!(api.handle(&basic("dana:wrong")).status, 401);
+        assert_eq!(api.handle(&basic("omar:correct horse")).status, 403);
+        
+        // Without providers, passwords are refused
+        let api = AdminApi::new(manager, "admin-secret".to_string());
+        assert_eq!(api.handle(&basic("dana:correct horse")).status, 401);
+    }
+    
+    #[test]
+    fn test_admin_hive_lifecycle() {
+        let temp_dir = tempdir().unwrap();
+        let manager = Arc::new(RwLock::new(HiveManager::new(temp_dir.path().to_path_buf()).unwrap()));
+        let api = AdminApi::new(manager, "admin-secret".to_string());
+        
+        let response = api.handle(&request("POST", "/hives", r#"{"name": "orders", "dimensions": [8, 8], "origin": "center", "integrity": "crc32c"}"#));
+        assert_eq!(response.status, 201);
+        
+        let response = api.handle(&request("POST", "/hives", r#"{"name": "orders"}"#));
+        assert_eq!(response.status, 409);
+        
+        let response = api.handle(&request("GET", "/hives", ""));
+        let body = response.json_body().unwrap();
+        assert_eq!(body["hives"][0]["name"], "orders");
+        
+        let response = api.handle(&request("GET", "/stats", ""));
+        let body = response.json_body().unwrap();
+        assert_eq!(body["hives"][0]["name"], "orders");
+        assert_eq!(body["cell_count"], 0);
+        
+        let response = api.handle(&request("GET", "/hives/orders/heatmap?format=svg", ""));
+        assert_eq!(response.content_type, "image/svg+xml");
+        let response = api.handle(&request("GET", "/hives/orders/heatmap?format=png", ""));
+        assert_eq!(response.status, 400);
+        
+        let schema = r#"{"name": "order", "description": "", "version": "1", "fields": [], "indexes": [], "metadata": {}}"#;
+        let response = api.handle(&request("PUT", "/hives/orders/schema", schema));
+        assert_eq!(response.status, 200);
+        
+        let index = r#"{"name": "by_item", "fields": ["item"], "index_type": "Hash", "unique": false}"#;
+        let response = api.handle(&request("POST", "/hives/orders/indexes", index));
+        assert_eq!(response.status, 202);
+        
+        let response = api.handle(&request("GET", "/hives/orders/indexes", ""));
+        let body = response.json_body().unwrap();
+        assert_eq!(body["indexes"][0]["name"], "by_item");
+        
+        let response = api.handle(&request("DELETE", "/hives/orders/indexes/by_item", ""));
+        assert_eq!(response.status, 200);
+        let response = api.handle(&request("POST", "/hives/orders/indexes/by_item/rebuild", ""));
+        assert_eq!(response.status, 404);
+        
+        let response = api.handle(&request("GET", "/hives/orders", ""));
+        assert_eq!(response.json_body().unwrap()["origin"], "center");
+        assert_eq!(response.json_body().unwrap()["integrity"], "crc32c");
+        
+        let response = api.handle(&request("DELETE", "/hives/orders", ""));
+        assert_eq!(response.status, 200);
+        
+        let response = api.handle(&request("GET", "/hives/orders", ""));
+        assert_eq!(response.status, 404);
+    }
+    
+    #[test]
+    fn test_admin_grid_limits() {
+        assert_eq!(grid_dimensions(8, 16, 128).unwrap(), (8, 16));
+        assert!(grid_dimensions(8, 17, 128).is_err());
+        assert!(grid_dimensions(0, 16, 128).is_err());
+        assert!(grid_dimensions(u64::MAX, 2, usize::MAX).is_err());
+        assert!(grid_dimensions(1 << 32, 1, usize::MAX).is_err());
+        
+        let temp_dir = tempdir().unwrap();
+        let manager = Arc::new(RwLock::new(HiveManager::new(temp_dir.path().to_path_buf()).unwrap()));
+        let api = AdminApi::new(manager, "admin-secret".to_string()).with_max_grid_positions(64);
+        
+        let response = api.handle(&request("POST", "/hives", r#"{"name": "orders", "dimensions": [9, 9]}"#));
+        assert_eq!(response.status, 400);
+        let response = api.handle(&request("POST", "/hives/orders/compact", ""));
+        assert_eq!(response.status, 404);
+    }
+    
+    #[test]
+    fn test_admin_change_feed() {
+        let temp_dir = tempdir().unwrap();
+        let manager = Arc::new(RwLock::new(HiveManager::new(temp_dir.path().to_path_buf()).unwrap()));
+        let id = manager.write().unwrap()
+            .create_hive("orders".to_string(), String::new(), "admin".to_string(), (8, 8))
+            .unwrap();
+        let api = AdminApi::new(manager.clone(), "admin-secret".to_string());
+        
+        {
+            let hive_arc = manager.read().unwrap().get_hive(&id).unwrap();
+            let mut hive = hive_arc.write().unwrap();
+            for (i, item) in ["apple", "pear"].iter().enumerate() {
+                let content = format!("{{\"item\": \"{}\"}}", item).into_bytes();
+                hive.add_cell(Cell::new(format!("o{}", i), (i as i32, 0), CellDataType::Json, content, false).unwrap()).unwrap();
+            }
+        }
+        
+        let response = api.handle(&request("GET", "/hives/orders/changes?limit=0", ""));
+        let body = response.json_body().unwrap();
+        assert_eq!(body["last_sequence"], 2);
+        assert!(body["events"].as_array().unwrap().is_empty());
+        
+        let response = api.handle(&request("GET", "/hives/orders/changes?since=1", ""));
+        let body = response.json_body().unwrap();
+        assert_eq!(body["events"].as_array().unwrap().len(), 1);
+        assert_eq!(body["events"][0]["cell_id"], "o1");
+        assert_eq!(body["events"][0]["document"]["item"], "pear");
+        
+        let response = api.handle(&request("GET", "/hives/orders/changes?since=soon", ""));
+        assert_eq!(response.status, 400);
+    }
+    
+    #[test]
+    fn test_admin_query_pages() {
+        let temp_dir = tempdir().unwrap();
+        let manager = Arc::new(RwLock::new(HiveManager::new(temp_dir.path().to_path_buf()).unwrap()));
+        let id = manager.write().unwrap()
+            .create_hive("orders".to_string(), String::new(), "admin".to_string(), (8, 8))
+            .unwrap();
+        let api = AdminApi::new(manager.clone(), "admin-secret".to_string());
+        
+        {
+            let hive_arc = manager.read().unwrap().get_hive(&id).unwrap();
+            let mut hive = hive_arc.write().unwrap();
+            let documents = (1..=5).map(|total| json!({ "_id": format!("o{}", total), "total": total })).collect();
+            crate::core::query::Query::new(crate::core::query::QueryType::Insert, "orders".to_string())
+                .with_data(Value::Array(documents))
+                .execute(&mut hive)
+                .unwrap();
+        }
+        
+        let sql = "SELECT * FROM orders ORDER BY total DESC LIMIT 2";
+        let response = api.handle(&request("POST", "/hives/orders/query", &json!({ "sql": sql }).to_string()));
+        let body = response.json_body().unwrap();
+        assert_eq!(body["results"][1]["_id"], "o4");
+        assert_eq!(body["has_more"], true);
+        
+        let page = json!({ "sql": sql, "cursor": body["next_cursor"] }).to_string();
+        let body = api.handle(&request("POST", "/hives/orders/query", &page)).json_body().unwrap();
+        assert_eq!(body["results"][0]["_id"], "o3");
+        
+        let page = json!({ "sql": "SELECT * FROM orders ORDER BY total LIMIT 2", "cursor": "bogus" }).to_string();
+        assert_eq!(api.handle(&request("POST", "/hives/orders/query", &page)).status, 400);
+    }
+    
+    #[test]
+    fn test_admin_hot_snapshot() {
+        let temp_dir = tempdir().unwrap();
+        let manager = Arc::new(RwLock::new(HiveManager::new(temp_dir.path().to_path_buf()).unwrap()));
+        let id = manager.write().unwrap()
+            .create_hive("orders".to_string(), String::new(), "admin".to_string(), (32, 32))
+            .unwrap();
+        let api = AdminApi::new(manager.clone(), "admin-secret".to_string());
+        let hive_arc = manager.read().unwrap().get_hive(&id).unwrap();
+        
+        // Snapshots taken while a writer runs are each a consistent state of the hive
+        let writer = std::thread::spawn(move || {
+            for i in 0..200 {
+                let content = format!("{{\"n\": {}}}", i).into_bytes();
+                let cell = Cell::new(format!("o{}", i), (i % 32, i / 32), CellDataType::Json, content, false).unwrap();
+                hive_arc.write().unwrap().add_cell(cell).unwrap();
+            }
+        });
+        for _ in 0..20 {
+            let response = api.handle(&request("GET", "/hives/orders/snapshot", ""));
+            assert_eq!(response.status, 200);
+            let version = response.headers.iter()
+                .find(|(name, _)| name == "X-HiveDB-Version")
+                .map(|(_, value)| value.parse::<u64>().unwrap())
+                .unwrap();
+            
+            let (_, body) = crate::core::snapshot::decode(&response.body).unwrap();
+            assert_eq!(body["metadata"]["version"], version);
+            assert_eq!(body["cells"].as_array().unwrap().len() as u64, version - 1);
+        }
+        writer.join().unwrap();
+    }
+    
+    #[test]
+    fn test_admin_mode_switch() {
+        let temp_dir = tempdir().unwrap();
+        let manager = Arc::new(RwLock::new(HiveManager::new(temp_dir.path().to_path_buf()).unwrap()));
+        let mode = Arc::new(ModeControl::default());
+        let api = AdminApi::new(manager, "admin-secret".to_string()).with_mode(mode.clone());
+        
+        let response = api.handle(&request("PUT", "/mode", r#"{"mode": "maintenance"}"#));
+        assert_eq!(response.status, 200);
+        assert_eq!(mode.get(), ServerMode::Maintenance);
+        
+        // Admin traffic is still served in maintenance mode
+        let response = api.handle(&request("GET", "/mode", ""));
+        assert_eq!(response.json_body().unwrap()["mode"], "maintenance");
+        
+        let response = api.handle(&request("PUT", "/mode", r#"{"mode": "frozen"}"#));
+        assert_eq!(response.status, 400);
+    }
+    
+    #[test]
+    fn test_admin_background_limits() {
+        let temp_dir = tempdir().unwrap();
+        let manager = Arc::new(RwLock::new(HiveManager::new(temp_dir.path().to_path_buf()).unwrap()));
+        let api = AdminApi::new(manager, "admin-secret".to_string());
+        
+        let response = api.handle(&request("PUT", "/background", r#"{"max_mb_per_sec": 512, "max_tasks": 2}"#));
+        assert_eq!(response.status, 200);
+        assert_eq!(worker::background_limits(), BackgroundLimits { max_mb_per_sec: Some(512.0), max_tasks: Some(2) });
+        
+        let response = api.handle(&request("GET", "/background", ""));
+        let body = response.json_body().unwrap();
+        assert_eq!(body["limits"]["max_tasks"], 2);
+        assert_eq!(body["workers"].as_array().unwrap().len(), 3);
+        
+        let response = api.handle(&request("PUT", "/background", r#"{"max_tasks": 0}"#));
+        assert_eq!(response.status, 400);
+        
+        let response = api.handle(&request("PUT", "/background", "{}"));
+        assert_eq!(response.status, 200);
+        assert_eq!(worker::background_limits(), BackgroundLimits::default());
+    }
+    
+    #[test]
+    fn test_admin_api_keys() {
+        let temp_dir = tempdir().unwrap();
+        let manager = Arc::new(RwLock::new(HiveManager::new(temp_dir.path().to_path_buf()).unwrap()));
+        let api_keys = Arc::new(RwLock::new(ApiKeyStore::new()));
+        let api = AdminApi::new(manager, "admin-secret".to_string()).with_api_keys(api_keys);
+        
+        let response = api.handle(&request("POST", "/api-keys", r#"{"name": "ops", "access": "admin"}"#));
+        assert_eq!(response.status, 201);
+        let body = response.json_body().unwrap();
+        let token = body["token"].as_str().unwrap().to_string();
+        let id = body["api_key"]["id"].as_str().unwrap().to_string();
+        
+        // Admin keys can be used in place of the admin token
+        let response = api.handle(&HttpRequest::new("GET", "/hives", b"")
+            .with_header("Authorization", &format!("Bearer {}", token)));
+        assert_eq!(response.status, 200);
+        
+        let response = api.handle(&request("POST", "/api-keys", r#"{"name": "etl", "access": "read"}"#));
+        let reader = response.json_body().unwrap()["token"].as_str().unwrap().to_string();
+        let response = api.handle(&HttpRequest::new("GET", "/hives", b"")
+            .with_header("Authorization", &format!("Bearer {}", reader)));
+        assert_eq!(response.status, 403);
+        
+        let response = api.handle(&request("DELETE", &format!("/api-keys/{}", id), ""));
+        assert_eq!(response.status, 200);
+        let response = api.handle(&HttpRequest::new("GET", "/hives", b"")
+            .with_header("Authorization", &format!("Bearer {}", token)));
+        assert_eq!(response.status, 401);
+    }
+    
+    #[test]
+    fn test_admin_webhooks() {
+        let temp_dir = tempdir().unwrap();
+        let manager = Arc::new(RwLock::new(HiveManager::new(temp_dir.path().to_path_buf()).unwrap()));
+        let webhooks = Arc::new(RwLock::new(WebhookRegistry::new()));
+        let api = AdminApi::new(manager, "admin-secret".to_string()).with_webhooks(webhooks.clone());
+        
+        let response = api.handle(&request("POST", "/webhooks", r#"{"url": "https://example.com/hook"}"#));
+        assert_eq!(response.status, 400);
+        
+        let response = api.handle(&request("POST", "/webhooks", r#"{"url": "http://localhost:9000/hook", "filter": {"manager_events": true}}"#));
+        assert_eq!(response.status, 201);
+        let id = response.json_body().unwrap()["id"].as_str().unwrap().to_string();
+        assert!(webhooks.read().unwrap().get(&id).unwrap().filter.manager_events);
+        
+        let response = api.handle(&request("POST", &format!("/webhooks/{}/disable", id), ""));
+        assert_eq!(response.status, 200);
+        let body = api.handle(&request("GET", "/webhooks", "")).json_body().unwrap();
+        assert_eq!(body["webhooks"][0]["active"], false);
+        assert!(body["webhooks"][0].get("secret").is_none());
+        
+        assert_eq!(api.handle(&request("DELETE", &format!("/webhooks/{}", id), "")).status, 200);
+        assert_eq!(api.handle(&request("DELETE", &format!("/webhooks/{}", id), "")).status, 404);
+    }
+    
+    #[test]
+    fn test_admin_users() {
+        let temp_dir = tempdir().unwrap();
+        let manager = Arc::new(RwLock::new(HiveManager::new(temp_dir.path().to_path_buf()).unwrap()));
+        let users = Arc::new(RwLock::new(UserStore::new()));
+        let api = AdminApi::new(manager, "admin-secret".to_string()).with_users(users.clone());
+        
+        let response = api.handle(&request("POST", "/users", r#"{"name": "amira", "password": "short"}"#));
+        assert_eq!(response.status, 400);
+        
+        let response = api.handle(&request("POST", "/users", r#"{"name": "amira", "password": "long enough"}"#));
+        assert_eq!(response.status, 201);
+        let response = api.handle(&request("POST", "/users", r#"{"name": "amira", "password": "long enough"}"#));
+        assert_eq!(response.status, 409);
+        
+        let response = api.handle(&request("POST", "/users/amira/grant", r#"{"access": "write", "role": "analyst"}"#));
+        assert_eq!(response.status, 200);
+        let response = api.handle(&request("GET", "/users", ""));
+        let body = response.json_body().unwrap();
+        assert_eq!(body["users"][0]["access"], "Write");
+        assert_eq!(body["users"][0]["roles"][0], "analyst");
+        
+        let response = api.handle(&request("PUT", "/users/amira/password", r#"{"password": "another one"}"#));
+        assert_eq!(response.status, 200);
+        assert!(users.write().unwrap().authenticate("amira", "another one").is_ok());
+        
+        let response = api.handle(&request("DELETE", "/users/amira", ""));
+        assert_eq!(response.status, 200);
+        let response = api.handle(&request("DELETE", "/users/amira", ""));
+        assert_eq!(response.status, 404);
+    }
+    
+    #[test]
+    fn test_admin_sessions() {
+        let temp_dir = tempdir().unwrap();
+        let manager = Arc::new(RwLock::new(HiveManager::new(temp_dir.path().to_path_buf()).unwrap()));
+        let sessions = Arc::new(SessionRegistry::new());
+        let api = AdminApi::new(manager, "admin-secret".to_string()).with_sessions(sessions.clone());
+        
+        let session = sessions.open_session("alice", "127.0.0.1:5000", "postgresql", "shop").unwrap();
+        let query = session.begin_query("SELECT * FROM orders").unwrap();
+        
+        let response = api.handle(&request("GET", "/sessions", ""));
+        let body = response.json_body().unwrap();
+        assert_eq!(body["sessions"][0]["user"], "alice");
+        assert_eq!(body["queries"][0]["id"], query.id());
+        
+        let response = api.handle(&request("DELETE", &format!("/queries/{}", query.id()), ""));
+        assert_eq!(response.status, 202);
+        assert!(query.token().is_cancelled());
+        
+        let response = api.handle(&request("DELETE", "/queries/12345", ""));
+        assert_eq!(response.status, 404);
+        
+        session.record_usage(Operation::Read, 3, 120).unwrap();
+        sessions.record_usage("bob", "shop", Operation::Write, 1, 30).unwrap();
+        let response = api.handle(&request("GET", "/usage?user=alice", ""));
+        let body = response.json_body().unwrap();
+        assert_eq!(body["usage"].as_array().unwrap().len(), 1);
+        assert_eq!(body["usage"][0]["bytes_read"], 120);
+    }
+}
@@ -0,0 +1,598 @@
+// HiveDB CDC Module
+//
+// This module implements change-data-capture export. A publisher follows
+// a hive's change log and emits ordered change events to Kafka or NATS.
+// The server follows all of its hives this way when a sink is configured.
+
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use rdkafka::config::ClientConfig;
+use rdkafka::producer::{BaseProducer, BaseRecord, Producer};
+use serde_json::json;
+use crate::core::change::{ChangeEvent, ChangeKind};
+use crate::core::error::HiveError;
+use crate::core::hive::{Hive, HiveManager};
+use crate::security::masking::MaskingPolicy;
+use log::{debug, info, warn};
+
+/// Serialization formats for published change events
+#[derive(Debug, Clone, PartialEq)]
+pub enum CdcFormat {
+    /// The change event as HiveDB records it
+    Json,
+    
+    /// A Debezium-style envelope (`op`, `after`, `source`, `ts_ms`)
+    ///
+    /// The change log keeps only the content after a change, so `before`
+    /// is always null, as Debezium sends for sources without a before
+    /// image. Moves and schema changes carry no cell row and are skipped.
+    Debezium,
+}
+
+impl std::str::FromStr for CdcFormat {
+    type Err = HiveError;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(CdcFormat::Json),
+            "debezium" => Ok(CdcFormat::Debezium),
+            _ => Err(HiveError::GenericError(format!("Unknown CDC format '{}', expected json or debezium", s))),
+        }
+    }
+}
+
+/// Configuration for a CDC publisher
+#[derive(Debug, Clone)]
+pub struct CdcConfig {
+    /// Topic (Kafka) or subject (NATS) events are published to
+    pub topic: String,
+    
+    /// Serialization format for events
+    pub format: CdcFormat,
+    
+    /// Maximum number of events published per poll
+    pub batch_size: usize,
+    
+    /// Delay between polls when tailing a hive
+    pub poll_interval: Duration,
+}
+
+impl Default for CdcConfig {
+    fn default() -> Self {
+        Self {
+            topic: "hivedb.changes".to_string(),
+            format: CdcFormat::Json,
+            batch_size: 500,
+            poll_interval: Duration::from_millis(200),
+        }
+    }
+}
+
+/// A destination for change events
+pub trait CdcSink: Send {
+    /// Publish a single message; messages with the same key must stay ordered
+    fn publish(&mut self, topic: &str, key: &str, payload: &[u8]) -> Result<(), HiveError>;
+    
+    /// Wait until all published messages have been delivered
+    fn flush(&mut self) -> Result<(), HiveError> {
+        Ok(())
+    }
+}
+
+/// Publishes change events to a Kafka cluster
+pub struct KafkaSink {
+    /// The underlying Kafka producer
+    producer: BaseProducer,
+    
+    /// How long a flush may wait for outstanding deliveries
+    flush_timeout: Duration,
+}
+
+impl KafkaSink {
+    /// Create a sink connected to the given bootstrap servers
+    pub fn new(brokers: &str) -> Result<Self, HiveError> {
+        let producer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            // The idempotent producer keeps per-key ordering on retries,
+            // with up to five requests in flight per connection
+            .set("enable.idempotence", "true")
+            .create::<BaseProducer>()
+            .map_err(|e| HiveError::NetworkError(e.to_string()))?;
+        
+        Ok(Self {
+            producer,
+            flush_timeout: Duration::from_secs(10),
+        })
+    }
+}
+
+impl CdcSink for KafkaSink {
+    fn publish(&mut self, topic: &str, key: &str, payload: &[u8]) -> Result<(), HiveError> {
+        self.producer
+            .send(BaseRecord::to(topic).key(key).payload(payload))
+            .map_err(|(e, _)| HiveError::NetworkError(e.to_string()))?;
+        
+        // Serve delivery callbacks so the local queue does not fill up
+        self.producer.poll(Duration::from_millis(0));
+        Ok(())
+    }
+    
+    fn flush(&mut self) -> Result<(), HiveError> {
+        self.producer
+            .flush(self.flush_timeout)
+            .map_err(|e| HiveError::NetworkError(e.to_string()))
+    }
+}
+
+/// Publishes change events to a NATS server using the core text protocol
+pub struct NatsSink {
+    /// Connection to the NATS server
+    stream: TcpStream,
+}
+
+impl NatsSink {
+    /// Connect to a NATS server (e.g. "127.0.0.1:4222")
+    pub fn connect(address: &str) -> Result<Self, HiveError> {
+        let mut stream = TcpStream::connect(address)
+            .map_err(|e| HiveError::NetworkError(e.to_string()))?;
+        
+        stream
+            .write_all(b"CONNECT {\"verbose\":false,\"pedantic\":false,\"name\":\"hivedb-cdc\"}\r\n")
+            .map_err(|e| HiveError::NetworkError(e.to_string()))?;
+        
+        info!("Connected CDC sink to NATS server at {}", address);
+        
+        Ok(Self { stream })
+    }
+    
+    /// Answer server pings and surface protocol errors without blocking
+    fn drain_server_messages(&mut self) -> Result<(), HiveError> {
+        self.stream
+            .set_nonblocking(true)
+            .map_err(|e| HiveError::NetworkError(e.to_string()))?;
+        
+        let mut buffer = [0u8; 4096];
+        let result = loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => break Err(HiveError::NetworkError("NATS connection closed".to_string())),
+                Ok(n) => {
+                    let text = String::from_utf8_lossy(&buffer[..n]).into_owned();
+                    if let Some(line) = text.split("\r\n").find(|l| l.starts_with("-ERR")) {
+                        break Err(HiveError::NetworkError(line.to_string()));
+                    }
+                    
+                    let pongs = "PONG\r\n".repeat(text.matches("PING\r\n").count());
+                    if let Err(e) = self.stream.write_all(pongs.as_bytes()) {
+                        break Err(HiveError::NetworkError(e.to_string()));
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break Ok(()),
+                Err(e) => break Err(HiveError::NetworkError(e.to_string())),
+            }
+        };
+        
+        self.stream
+            .set_nonblocking(false)
+            .map_err(|e| HiveError::NetworkError(e.to_string()))?;
+        
+        result
+    }
+}
+
+impl CdcSink for NatsSink {
+    fn publish(&mut self, topic: &str, _key: &str, payload: &[u8]) -> Result<(), HiveError> {
+        let header = format!("PUB {} {}\r\n", topic, payload.len());
+        
+        self.stream.write_all(header.as_bytes())
+            .and_then(|_| self.stream.write_all(payload))
+            .and_then(|_| self.stream.write_all(b"\r\n"))
+            .map_err(|e| HiveError::NetworkError(e.to_string()))
+    }
+    
+    fn flush(&mut self) -> Result<(), HiveError> {
+        self.stream.flush()
+            .map_err(|e| HiveError::NetworkError(e.to_string()))?;
+        self.drain_server_messages()
+    }
+}
+
+/// Follows a hive's change log and publishes its events in order
+pub struct CdcPublisher {
+    /// Publisher configuration
+    config: CdcConfig,
+    
+    /// Sequence number of the last event successfully published
+    last_sequence: u64,
+    
+    /// Masking applied to documents before they leave HiveDB
+    masking: MaskingPolicy,
+}
+
+impl CdcPublisher {
+    /// Create a publisher that starts from the beginning of the change log
+    pub fn new(config: CdcConfig) -> Self {
+        Self::resume_from(config, 0)
+    }
+    
+    /// Create a publisher that resumes after the given sequence number
+    pub fn resume_from(config: CdcConfig, sequence: u64) -> Self {
+        Self {
+            config,
+            last_sequence: sequence,
+            masking: MaskingPolicy::default(),
+        }
+    }
+    
+    /// Mask sensitive fields of published documents
+    pub fn with_masking(mut self, masking: MaskingPolicy) -> Self {
+        self.masking = masking;
+        self
+    }
+    
+    /// Get the sequence number of the last event published
+    pub fn last_sequence(&self) -> u64 {
+        self.last_sequence
+    }
+    
+    /// Publish the next batch of pending events and return how many were sent
+    ///
+    /// Events the format skips count towards the batch but not the result.
+    pub fn poll(&mut self, hive: &Hive, sink: &mut dyn CdcSink) -> Result<usize, HiveError> {
+        let mut published = 0;
+        let mut cursor = self.last_sequence;
+        
+        for event in hive.changes.iter_since(self.last_sequence)?.take(self.config.batch_size) {
+            if let Some(payload) = self.encode(event)? {
+                sink.publish(&self.config.topic, &event_key(event), &payload)?;
+                published += 1;
+            }
+            cursor = event.sequence;
+        }
+        
+        // The position only moves once the batch is delivered, so a failed
+        // flush sends the batch again on the next poll
+        if published > 0 {
+            sink.flush()?;
+            debug!("Published {} change events up to sequence {}", published, cursor);
+        }
+        self.last_sequence = cursor;
+        
+        Ok(published)
+    }
+    
+    /// Tail a hive, publishing changes until `stop` is set
+    pub fn tail(
+        &mut self,
+        hive: Arc<RwLock<Hive>>,
+        sink: &mut dyn CdcSink,
+        stop: Arc<AtomicBool>,
+    ) -> Result<(), HiveError> {
+        while !stop.load(Ordering::Relaxed) {
+            let backlog = {
+                let hive = hive.read().map_err(|_| HiveError::LockError)?;
+                self.poll(&hive, sink)?;
+                self.last_sequence < hive.changes.last_sequence()
+            };
+            
+            // Keep draining while there is a backlog
+            if !backlog {
+                std::thread::sleep(self.config.poll_interval);
+            }
+        }
+        
+        info!("CDC publisher stopped at sequence {}", self.last_sequence);
+        Ok(())
+    }
+    
+    /// Serialize an event in the configured format, or None if the format skips it
+    pub fn encode(&self, event: &ChangeEvent) -> Result<Option<Vec<u8>>, HiveError> {
+        let document = event.content.as_deref().map(|content| {
+            let mut document = content_to_json(content);
+            self.masking.mask_document(&mut document, &[]);
+            document
+        });
+        
+        let value = match self.config.format {
+            CdcFormat::Json => json!({
+                "sequence": event.sequence,
+                "hive_id": event.hive_id,
+                "kind": event.kind,
+                "cell_id": event.cell_id,
+                "coordinates": event.coordinates,
+                "timestamp": event.timestamp,
+                "document": document,
+            }),
+            CdcFormat::Debezium => {
+                let op = match event.kind {
+                    ChangeKind::CellInserted => "c",
+                    ChangeKind::CellUpdated => "u",
+                    ChangeKind::CellRemoved => "d",
+                    ChangeKind::CellMoved | ChangeKind::SchemaChanged => return Ok(None),
+                };
+                
+                json!({
+                    "before": null,
+                    "after": document,
+                    "op": op,
+                    "ts_ms": event.timestamp * 1000,
+                    "source": {
+                        "connector": "hivedb",
+                        "hive": event.hive_id,
+                        "cell": event.cell_id,
+                        "sequence": event.sequence,
+                    },
+                })
+            }
+        };
+        
+        serde_json::to_vec(&value)
+            .map(Some)
+            .map_err(|e| HiveError::SerializationError(e.to_string()))
+    }
+}
+
+/// Follows the change logs of every hive of a manager
+///
+/// Each hive gets its own publisher when it is first seen, so hives
+/// created while running are followed too. Positions are kept in memory,
+/// so after a restart each hive is published from its oldest retained
+/// event.
+pub struct ManagerPublisher {
+    /// Configuration shared by the publishers
+    config: CdcConfig,
+    
+    /// Publisher of each hive, by hive ID
+    publishers: HashMap<String, CdcPublisher>,
+}
+
+impl ManagerPublisher {
+    /// Create a publisher for the hives of a manager
+    pub fn new(config: CdcConfig) -> Self {
+        Self {
+            config,
+            publishers: HashMap::new(),
+        }
+    }
+    
+    /// Publish the next batch of every hive, and return whether any has more pending
+    ///
+    /// The manager is only locked while its hives are listed. A hive whose
+    /// publisher fell behind its retained log is resumed from its latest
+    /// event, with a warning.
+    pub fn poll(&mut self, manager: &RwLock<HiveManager>, sink: &mut dyn CdcSink) -> Result<bool, HiveError> {
+        let hives: Vec<(String, Arc<RwLock<Hive>>)> = {
+            let manager = manager.read().map_err(|_| HiveError::LockError)?;
+            manager.list_hives().into_iter()
+                .filter_map(|(id, _)| manager.get_hive(&id).map(|hive| (id, hive)))
+                .collect()
+        };
+        self.publishers.retain(|id, _| hives.iter().any(|(hive_id, _)| hive_id == id));
+        
+        let mut backlog = false;
+        for (id, hive_arc) in hives {
+            let hive = hive_arc.read().map_err(|_| HiveError::LockError)?;
+            let publisher = self.publishers.entry(id)
+                .or_insert_with(|| CdcPublisher::new(self.config.clone()));
+            
+            match publisher.poll(&hive, sink) {
+                Ok(_) => {}
+                Err(HiveError::ChangeLogTruncated(sequence)) => {
+                    warn!(
+                        "CDC publisher for hive {} lost events after sequence {}; resuming from {}",
+                        hive.id, sequence, hive.changes.last_sequence()
+                    );
+                    *publisher = CdcPublisher::resume_from(self.config.clone(), hive.changes.last_sequence());
+                }
+                Err(e) => return Err(e),
+            }
+            backlog |= publisher.last_sequence() < hive.changes.last_sequence();
+        }
+        
+        Ok(backlog)
+    }
+    
+    /// Publish changes until `stop` is set, retrying after sink failures
+    pub fn tail(&mut self, manager: &RwLock<HiveManager>, sink: &mut dyn CdcSink, stop: &AtomicBool) {
+        while !stop.load(Ordering::Relaxed) {
+            let backlog = match self.poll(manager, sink) {
+                Ok(backlog) => backlog,
+                Err(e) => {
+                    warn!("Failed to publish change events: {}", e);
+                    false
+                }
+            };
+            
+            // Keep draining while there is a backlog
+            if !backlog {
+                std::thread::sleep(self.config.poll_interval);
+            }
+        }
+        
+        info!("CDC publisher stopped");
+    }
+}
+
+/// Build the message key for an event, so changes to one cell stay ordered
+fn event_key(event: &ChangeEvent) -> String {
+    match &event.cell_id {
+        Some(cell_id) => format!("{}/{}", event.hive_id, cell_id),
+        None => event.hive_id.clone(),
+    }
+}
+
+/// Embed cell content as JSON when possible, falling back to hex
+pub(crate) fn content_to_json(content: &[u8]) -> serde_json::Value {
+    serde_json::from_slice(content).unwrap_or_else(|_| {
+        warn!("Change event content is not JSON; publishing it hex-encoded");
+        serde_json::Value::String(hex::encode(content))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::cell::{Cell, CellDataType};
+    use crate::security::masking::{MaskingRule, MaskStrategy};
+    use tempfile::tempdir;
+    
+    /// Sink that keeps published messages in memory
+    #[derive(Default)]
+    struct MemorySink {
+        messages: Vec<(String, String, Vec<u8>)>,
+    }
+    
+    impl CdcSink for MemorySink {
+        fn publish(&mut self, topic: &str, key: &str, payload: &[u8]) -> Result<(), HiveError> {
+            self.messages.push((topic.to_string(), key.to_string(), payload.to_vec()));
+            Ok(())
+        }
+    }
+    
+    fn test_hive() -> Hive {
+        let temp_dir = tempdir().unwrap();
+        let mut hive = Hive::new(
+            "test-hive".to_string(),
+            "A test hive".to_string(),
+            "test-user".to_string(),
+            temp_dir.path().to_path_buf(),
+            (64, 64),
+        ).unwrap();
+        
+        for i in 0..3 {
+            let cell = Cell::new(
+                format!("cell-{}", i),
+                (i, 0),
+                CellDataType::Json,
+                format!("{{\"n\": {}}}", i).into_bytes(),
+                false,
+            ).unwrap();
+            hive.add_cell(cell).unwrap();
+        }
+        
+        hive
+    }
+    
+    #[test]
+    fn test_publisher_emits_in_order() {
+        let hive = test_hive();
+        let mut sink = MemorySink::default();
+        let mut publisher = CdcPublisher::new(CdcConfig::default());
+        
+        assert_eq!(publisher.poll(&hive, &mut sink).unwrap(), 3);
+        assert_eq!(publisher.last_sequence(), 3);
+        assert_eq!(sink.messages[0].1, format!("{}/cell-0", hive.id));
+        assert_eq!(sink.messages[2].1, format!("{}/cell-2", hive.id));
+        
+        // Nothing new to publish
+        assert_eq!(publisher.poll(&hive, &mut sink).unwrap(), 0);
+    }
+    
+    #[test]
+    fn test_publisher_respects_batch_size() {
+        let hive = test_hive();
+        let mut sink = MemorySink::default();
+        let config = CdcConfig {
+            batch_size: 2,
+            ..CdcConfig::default()
+        };
+        let mut publisher = CdcPublisher::new(config);
+        
+        assert_eq!(publisher.poll(&hive, &mut sink).unwrap(), 2);
+        assert_eq!(publisher.poll(&hive, &mut sink).unwrap(), 1);
+        assert_eq!(sink.messages.len(), 3);
+    }
+    
+    #[test]
+    fn test_failed_flush_keeps_position() {
+        struct FailingSink;
+        
+        impl CdcSink for FailingSink {
+            fn publish(&mut self, _topic: &str, _key: &str, _payload: &[u8]) -> Result<(), HiveError> {
+                Ok(())
+            }
+            
+            fn flush(&mut self) -> Result<(), HiveError> {
+                Err(HiveError::NetworkError("broker unavailable".to_string()))
+            }
+        }
+        
+        let hive = test_hive();
+        let mut publisher = CdcPublisher::new(CdcConfig::default());
+        assert!(publisher.poll(&hive, &mut FailingSink).is_err());
+        assert_eq!(publisher.last_sequence(), 0);
+        
+        // The undelivered batch is sent again
+        let mut sink = MemorySink::default();
+        assert_eq!(publisher.poll(&hive, &mut sink).unwrap(), 3);
+        assert_eq!(publisher.last_sequence(), 3);
+    }
+    
+    #[test]
+    fn test_publisher_masks_documents() {
+        let hive = test_hive();
+        let mut sink = MemorySink::default();
+        let masking = MaskingPolicy::new("salt")
+            .with_rule(MaskingRule::new("n", MaskStrategy::Redact));
+        let mut publisher = CdcPublisher::new(CdcConfig::default()).with_masking(masking);
+        
+        publisher.poll(&hive, &mut sink).unwrap();
+        let payload: serde_json::Value = serde_json::from_slice(&sink.messages[0].2).unwrap();
+        assert_eq!(payload["document"]["n"], crate::security::masking::REDACTED);
+    }
+    
+    #[test]
+    fn test_debezium_skips_non_cell_events() {
+        let mut hive = test_hive();
+        assert!(hive.rebalance(|_, _| {}).unwrap() > 0);
+        let mut sink = MemorySink::default();
+        let config = CdcConfig {
+            format: CdcFormat::Debezium,
+            ..CdcConfig::default()
+        };
+        let mut publisher = CdcPublisher::new(config);
+        
+        // The moves are consumed but not published
+        assert_eq!(publisher.poll(&hive, &mut sink).unwrap(), 3);
+        assert_eq!(publisher.last_sequence(), hive.changes.last_sequence());
+        assert_eq!(publisher.poll(&hive, &mut sink).unwrap(), 0);
+    }
+    
+    #[test]
+    fn test_manager_publisher_follows_new_hives() {
+        let temp_dir = tempdir().unwrap();
+        let manager = RwLock::new(HiveManager::new(temp_dir.path().to_path_buf()).unwrap());
+        let mut sink = MemorySink::default();
+        let mut publisher = ManagerPublisher::new(CdcConfig::default());
+        assert!(!publisher.poll(&manager, &mut sink).unwrap());
+        
+        for name in ["orders", "users"] {
+            let id = manager.write().unwrap()
+                .create_hive(name.to_string(), String::new(), "test-user".to_string(), (8, 8))
+                .unwrap();
+            let hive_arc = manager.read().unwrap().get_hive(&id).unwrap();
+            let cell = Cell::new(format!("{}-1", name), (0, 0), CellDataType::Json, b"{}".to_vec(), false).unwrap();
+            hive_arc.write().unwrap().add_cell(cell).unwrap();
+            
+            assert!(!publisher.poll(&manager, &mut sink).unwrap());
+        }
+        
+        assert_eq!(sink.messages.len(), 2);
+        assert!(sink.messages[1].1.ends_with("/users-1"));
+        assert_eq!("Debezium".parse::<CdcFormat>().unwrap(), CdcFormat::Debezium);
+        assert!("avro".parse::<CdcFormat>().is_err());
+    }
+    
+    #[test]
+    fn test_publisher_resumes_from_sequence() {
+        let hive = test_hive();
+        let mut sink = MemorySink::default();
+        let mut publisher = CdcPublisher::resume_from(CdcConfig::default(), 2);
+        
+        assert_eq!(publisher.poll(&hive, &mut sink).unwrap(), 1);
+        assert_eq!(sink.messages[0].1, format!("{}/cell-2", hive.id));
+    }
+}
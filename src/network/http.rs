@@ -0,0 +1,537 @@
+// HiveDB HTTP Module
+//
+// This module provides the minimal HTTP/1.1 plumbing shared by the
+// HTTP-based endpoints of HiveDB and the clients that call them. Only
+// what those need is implemented: one request per connection,
+// Content-Length bodies, no TLS. Large text and JSON bodies are compressed
+// with zstd or LZ4 when the client lists one in Accept-Encoding.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde_json::Value;
+use crate::core::codec;
+use crate::core::error::HiveError;
+use log::warn;
+
+/// Largest request body accepted
+pub const MAX_BODY_LEN: usize = 16 * 1024 * 1024;
+
+/// Smallest response body compressed for clients that accept it
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 1024;
+
+/// Accept-Encoding value of HiveDB's own clients, most preferred first
+pub const ACCEPT_ENCODING: &str = "zstd, lz4";
+
+/// Compression level of zstd response bodies
+const ZSTD_LEVEL: i32 = 3;
+
+/// A compression of HTTP bodies
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentEncoding {
+    /// Zstandard (RFC 8878)
+    Zstd,
+    
+    /// An LZ4 frame, understood by HiveDB's own clients
+    Lz4,
+}
+
+impl ContentEncoding {
+    /// Get the name used in Content-Encoding headers
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContentEncoding::Zstd => "zstd",
+            ContentEncoding::Lz4 => "lz4",
+        }
+    }
+    
+    /// Get an encoding by its name in headers
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "zstd" => Some(ContentEncoding::Zstd),
+            "lz4" => Some(ContentEncoding::Lz4),
+            _ => None,
+        }
+    }
+    
+    /// Compress a body
+    pub fn encode(&self, body: &[u8]) -> Result<Vec<u8>, HiveError> {
+        match self {
+            ContentEncoding::Zstd => zstd::bulk::compress(body, ZSTD_LEVEL)
+                .map_err(|e| HiveError::CompressionError(e.to_string())),
+            ContentEncoding::Lz4 => codec::lz4_compress(body),
+        }
+    }
+    
+    /// Decompress a body
+    pub fn decode(&self, body: &[u8]) -> Result<Vec<u8>, HiveError> {
+        match self {
+            ContentEncoding::Zstd => zstd::stream::decode_all(body)
+                .map_err(|e| HiveError::DecompressionError(e.to_string())),
+            ContentEncoding::Lz4 => codec::lz4_decompress(body),
+        }
+    }
+}
+
+/// A parsed HTTP request
+#[derive(Debug, Clone, Default)]
+pub struct HttpRequest {
+    /// Request method (upper case)
+    pub method: String,
+    
+    /// Request path, without the query string
+    pub path: String,
+    
+    /// Raw query string (empty if none)
+    pub query: String,
+    
+    /// Headers, with lower-cased names
+    pub headers: Vec<(String, String)>,
+    
+    /// Request body
+    pub body: Vec<u8>,
+}
+
+impl HttpRequest {
+    /// Create a request with the given method, path and body
+    pub fn new(method: &str, path: &str, body: &[u8]) -> Self {
+        let (path, query) = match path.split_once('?') {
+            Some((path, query)) => (path, query),
+            None => (path, ""),
+        };
+        
+        Self {
+            method: method.to_uppercase(),
+            path: path.to_string(),
+            query: query.to_string(),
+            headers: Vec::new(),
+            body: body.to_vec(),
+        }
+    }
+    
+    /// Add a header
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_lowercase(), value.to_string()));
+        self
+    }
+    
+    /// Get a header value by name (case-insensitive)
+    pub fn header(&self, name: &str) -> Option<&str> {
+        let name = name.to_lowercase();
+        self.headers.iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value.as_str())
+    }
+    
+    /// Get the bearer token from the Authorization header
+    pub fn bearer_token(&self) -> Option<&str> {
+        self.header("authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(|token| token.trim())
+    }
+    
+    /// Get the user name and password from a Basic Authorization header
+    pub fn basic_credentials(&self) -> Option<(String, String)> {
+        let encoded = self.header("authorization")?.strip_prefix("Basic ")?;
+        let decoded = String::from_utf8(STANDARD.decode(encoded.trim()).ok()?).ok()?;
+        let (username, password) = decoded.split_once(':')?;
+        
+        Some((username.to_string(), password.to_string()))
+    }
+    
+    /// Get a query string parameter
+    pub fn query_param(&self, name: &str) -> Option<&str> {
+        self.query.split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value)
+    }
+    
+    /// Get the encoding the client accepts that HiveDB prefers, if any
+    ///
+    /// zstd is preferred over LZ4 whatever the order in the header, and
+    /// encodings given a quality of 0 are refused.
+    pub fn accepted_encoding(&self) -> Option<ContentEncoding> {
+        let accepted: Vec<ContentEncoding> = self.header("accept-encoding")?
+            .split(',')
+            .filter_map(|item| {
+                let mut parts = item.split(';');
+                let encoding = ContentEncoding::from_name(parts.next()?)?;
+                let refused = parts.any(|param| {
+                    param.trim().strip_prefix("q=").and_then(|q| q.parse::<f32>().ok()) == Some(0.0)
+                });
+                (!refused).then_some(encoding)
+            })
+            .collect();
+        
+        [ContentEncoding::Zstd, ContentEncoding::Lz4].into_iter().find(|encoding| accepted.contains(encoding))
+    }
+    
+    /// Get the non-empty path segments
+    pub fn segments(&self) -> Vec<&str> {
+        self.path.split('/').filter(|s| !s.is_empty()).collect()
+    }
+    
+    /// Parse the body as JSON
+    pub fn json(&self) -> Result<Value, HiveError> {
+        if self.body.is_empty() {
+            return Ok(Value::Null);
+        }
+        
+        serde_json::from_slice(&self.body)
+            .map_err(|e| HiveError::DeserializationError(e.to_string()))
+    }
+}
+
+/// An HTTP response
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+    /// Status code
+    pub status: u16,
+    
+    /// Value of the Content-Type header
+    pub content_type: String,
+    
+    /// Additional headers
+    pub headers: Vec<(String, String)>,
+    
+    /// Response body
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    /// Create a JSON response
+    pub fn json(status: u16, body: &Value) -> Self {
+        Self {
+            status,
+            content_type: "application/json".to_string(),
+            headers: Vec::new(),
+            body: body.to_string().into_bytes(),
+        }
+    }
+    
+    /// Create a plain text response
+    pub fn text(status: u16, body: &str) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8".to_string(),
+            headers: Vec::new(),
+            body: body.as_bytes().to_vec(),
+        }
+    }
+    
+    /// Add a header
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+    
+    /// Get a header value by name (case-insensitive)
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+    
+    /// Compress the body for a request that accepts it
+    ///
+    /// Only text and JSON bodies of at least `threshold` bytes are
+    /// compressed; anything else, or a body that fails to compress, is
+    /// sent as it is.
+    pub fn compressed_for(mut self, request: &HttpRequest, threshold: usize) -> Self {
+        let compressible = self.content_type.starts_with("application/json") || self.content_type.starts_with("text/");
+        if !compressible || self.body.len() < threshold || self.header("content-encoding").is_some() {
+            return self;
+        }
+        let Some(encoding) = request.accepted_encoding() else {
+            return self;
+        };
+        
+        match encoding.encode(&self.body) {
+            Ok(body) => {
+                self.body = body;
+                self.with_header("Content-Encoding", encoding.as_str())
+                    .with_header("Vary", "Accept-Encoding")
+            }
+            Err(e) => {
+                warn!("Sending an uncompressed response: {}", e);
+                self
+            }
+        }
+    }
+    
+    /// Parse the body as JSON
+    pub fn json_body(&self) -> Result<Value, HiveError> {
+        serde_json::from_slice(&self.body)
+            .map_err(|e| HiveError::DeserializationError(e.to_string()))
+    }
+}
+
+/// Read a request from a stream
+pub fn read_request(stream: &mut TcpStream) -> Result<HttpRequest, HiveError> {
+    let mut reader = BufReader::new(stream);
+    
+    let mut line = String::new();
+    reader.read_line(&mut line)
+        .map_err(|e| HiveError::NetworkError(e.to_string()))?;
+    
+    let mut parts = line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target.to_string()),
+        _ => return Err(HiveError::NetworkError("malformed request line".to_string())),
+    };
+    
+    let mut request = HttpRequest::new(&method, &target, &[]);
+    request.headers = read_headers(&mut reader)?;
+    request.body = read_body(&mut reader, request.header("content-length"))?;
+    
+    Ok(request)
+}
+
+/// Send a request to a server and read its response
+pub fn send_request(address: &str, request: &HttpRequest, timeout: Duration) -> Result<HttpResponse, HiveError> {
+    let mut stream = TcpStream::connect(address)
+        .map_err(|e| HiveError::NetworkError(e.to_string()))?;
+    stream.set_read_timeout(Some(timeout))
+        .and_then(|_| stream.set_write_timeout(Some(timeout)))
+        .map_err(|e| HiveError::NetworkError(e.to_string()))?;
+    
+    let target = if request.query.is_empty() {
+        request.path.clone()
+    } else {
+        format!("{}?{}", request.path, request.query)
+    };
+    let mut head = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        request.method,
+        target,
+        address,
+        request.body.len()
+    );
+    for (name, value) in &request.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+    
+    stream.write_all(head.as_bytes())
+        .and_then(|_| stream.write_all(&request.body))
+        .map_err(|e| HiveError::NetworkError(e.to_string()))?;
+    
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)
+        .map_err(|e| HiveError::NetworkError(e.to_string()))?;
+    
+    let status = line.split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse::<u16>().ok())
+        .ok_or_else(|| HiveError::NetworkError("malformed status line".to_string()))?;
+    
+    let headers = read_headers(&mut reader)?;
+    let header = |name: &str| headers.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str());
+    let content_type = header("content-type").unwrap_or("application/octet-stream").to_string();
+    let mut body = read_body(&mut reader, header("content-length"))?;
+    
+    // Bodies are decompressed here, so callers that sent ACCEPT_ENCODING see them as usual
+    let mut headers = headers;
+    if let Some(index) = headers.iter().position(|(key, _)| key == "content-encoding") {
+        let (_, name) = headers.remove(index);
+        let encoding = ContentEncoding::from_name(&name)
+            .ok_or_else(|| HiveError::NetworkError(format!("unsupported Content-Encoding '{}'", name)))?;
+        body = encoding.decode(&body)?;
+    }
+    
+    Ok(HttpResponse {
+        status,
+        content_type,
+        headers,
+        body,
+    })
+}
+
+/// Split an http:// URL into host, port and path
+pub fn parse_http_url(url: &str) -> Result<(String, u16, String), HiveError> {
+    let rest = url.strip_prefix("http://")
+        .ok_or_else(|| HiveError::NetworkError(format!("Unsupported URL (only http:// is supported): {}", url)))?;
+    
+    let (authority, path) = match rest.find('/') {
+        Some(index) => (&rest[..index], &rest[index..]),
+        None => (rest, "/"),
+    };
+    
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => {
+            let port = port.parse::<u16>()
+                .map_err(|_| HiveError::NetworkError(format!("Invalid port in URL: {}", url)))?;
+            (host, port)
+        }
+        None => (authority, 80),
+    };
+    
+    if host.is_empty() {
+        return Err(HiveError::NetworkError(format!("Missing host in URL: {}", url)));
+    }
+    
+    Ok((host.to_string(), port, path.to_string()))
+}
+
+/// Read header lines up to the blank line, with lower-cased names
+fn read_headers<R: BufRead>(reader: &mut R) -> Result<Vec<(String, String)>, HiveError> {
+    let mut headers = Vec::new();
+    
+    loop {
+        let mut line = String::new();
+        let n = reader.read_line(&mut line)
+            .map_err(|e| HiveError::NetworkError(e.to_string()))?;
+        let line = line.trim_end();
+        if n == 0 || line.is_empty() {
+            break;
+        }
+        
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_lowercase(), value.trim().to_string()));
+        }
+    }
+    
+    Ok(headers)
+}
+
+/// Read a body of the length given by a Content-Length header
+fn read_body<R: Read>(reader: &mut R, content_length: Option<&str>) -> Result<Vec<u8>, HiveError> {
+    let length = content_length
+        .map(|value| value.parse::<usize>())
+        .transpose()
+        .map_err(|_| HiveError::NetworkError("invalid Content-Length".to_string()))?
+        .unwrap_or(0);
+    
+    if length > MAX_BODY_LEN {
+        return Err(HiveError::NetworkError("body too large".to_string()));
+    }
+    
+    let mut body = vec![0u8; length];
+    reader.read_exact(&mut body)
+        .map_err(|e| HiveError::NetworkError(e.to_string()))?;
+    
+    Ok(body)
+}
+
+/// Write a response to a stream and close the exchange
+pub fn write_response(stream: &mut TcpStream, response: &HttpResponse) -> Result<(), HiveError> {
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        reason_phrase(response.status),
+        response.content_type,
+        response.body.len()
+    );
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+    
+    stream.write_all(head.as_bytes())
+        .and_then(|_| stream.write_all(&response.body))
+        .map_err(|e| HiveError::NetworkError(e.to_string()))
+}
+
+/// Get the reason phrase for a status code
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        202 => "Accepted",
+        204 => "No Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        429 => "Too Many Requests",
+        501 => "Not Implemented",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_request_helpers() {
+        let request = HttpRequest::new("get", "/hives/abc/schema?verbose=1&x=2", b"")
+            .with_header("Authorization", "Bearer secret");
+        
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.segments(), vec!["hives", "abc", "schema"]);
+        assert_eq!(request.query_param("x"), Some("2"));
+        assert_eq!(request.query_param("y"), None);
+        assert_eq!(request.bearer_token(), Some("secret"));
+        assert_eq!(request.basic_credentials(), None);
+        assert_eq!(request.json().unwrap(), Value::Null);
+    }
+    
+    #[test]
+    fn test_send_request_round_trip() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let request = read_request(&mut stream).unwrap();
+            let response = HttpResponse::json(201, &serde_json::json!({
+                "path": request.path,
+                "token": request.bearer_token(),
+                "body": request.json().unwrap(),
+            }));
+            write_response(&mut stream, &response).unwrap();
+        });
+        
+        let request = HttpRequest::new("POST", "/users", br#"{"name":"amira"}"#)
+            .with_header("Authorization", "Bearer secret");
+        let response = send_request(&address, &request, Duration::from_secs(5)).unwrap();
+        
+        assert_eq!(response.status, 201);
+        let body = response.json_body().unwrap();
+        assert_eq!(body["path"], "/users");
+        assert_eq!(body["token"], "secret");
+        assert_eq!(body["body"]["name"], "amira");
+    }
+    
+    #[test]
+    fn test_response_compression() {
+        let accepted = |value: &str| HttpRequest::new("GET", "/", b"").with_header("Accept-Encoding", value).accepted_encoding();
+        assert_eq!(accepted("gzip, lz4, zstd"), Some(ContentEncoding::Zstd));
+        assert_eq!(accepted("lz4;q=0.5, zstd;q=0"), Some(ContentEncoding::Lz4));
+        assert_eq!(accepted("gzip"), None);
+        
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        
+        std::thread::spawn(move || {
+            for _ in 0..2 {
+                let (mut stream, _) = listener.accept().unwrap();
+                let request = read_request(&mut stream).unwrap();
+                let response = HttpResponse::text(200, &"hive ".repeat(1000)).compressed_for(&request, 4096);
+                write_response(&mut stream, &response).unwrap();
+            }
+        });
+        
+        for encoding in ["lz4", ACCEPT_ENCODING] {
+            let request = HttpRequest::new("GET", "/", b"").with_header("Accept-Encoding", encoding);
+            let response = send_request(&address, &request, Duration::from_secs(5)).unwrap();
+            assert_eq!(response.body, "hive ".repeat(1000).into_bytes());
+            assert_eq!(response.header("content-encoding"), None);
+        }
+        
+        // Small bodies and binary bodies are left alone
+        let request = HttpRequest::new("GET", "/", b"").with_header("Accept-Encoding", "zstd");
+        let small = HttpResponse::text(200, "hive").compressed_for(&request, 4096);
+        assert_eq!(small.header("content-encoding"), None);
+        let response = HttpResponse::text(200, &"hive ".repeat(1000)).compressed_for(&request, 4096);
+        assert_eq!(response.header("Content-Encoding"), Some("zstd"));
+    }
+}
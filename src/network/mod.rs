@@ -0,0 +1,12 @@
+// HiveDB Network Module
+//
+// This module contains the networking components of HiveDB, including
+// the integrations that move data between HiveDB and external systems.
+
+pub mod admin;
+pub mod cdc;
+pub mod http;
+pub mod pgwire;
+pub mod resp;
+pub mod s3;
+pub mod webhooks;
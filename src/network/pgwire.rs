@@ -0,0 +1,948 @@
+// HiveDB PostgreSQL Wire Protocol Module
+//
+// This module implements an optional listener speaking the PostgreSQL
+// frontend/backend protocol (v3), so psql and BI tools can connect to
+// HiveDB directly. Statements are translated by the SQL module, and only
+// the simple query protocol is supported. The startup `database`
+// parameter selects the hive. With a query allowlist, clients may only
+// EXECUTE the statements registered in it. Connections are not
+// authenticated, so SHOW SESSIONS, SHOW QUERIES and KILL QUERY are
+// refused; the admin API serves them. A matching minimal client is
+// provided for HiveDB's own tools.
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, RwLock};
+use crate::core::commit::CommitTicket;
+use crate::core::error::HiveError;
+use crate::core::hive::{Hive, HiveManager};
+use crate::core::mode::ModeControl;
+use crate::core::query::{QueryExecutor, QueryResult, QueryType};
+use crate::core::session::{Operation, SessionHandle, SessionRegistry};
+use crate::core::sql::{self, Statement};
+use crate::core::worker::{self, Priority};
+use crate::security::allowlist::QueryAllowlist;
+use crate::security::masking::MaskingPolicy;
+use crate::utils::telemetry::{self, TraceContext};
+use log::{debug, info, warn};
+
+/// Protocol version 3.0
+const PROTOCOL_VERSION: i32 = 196_608;
+
+/// Request code sent by clients that want TLS
+const SSL_REQUEST_CODE: i32 = 80_877_103;
+
+/// Request code sent by clients cancelling a running query
+const CANCEL_REQUEST_CODE: i32 = 80_877_102;
+
+/// Largest message accepted from a client
+const MAX_MESSAGE_LEN: usize = 64 * 1024 * 1024;
+
+/// OID of the `text` type, used for every column
+const TEXT_OID: i32 = 25;
+
+/// Serves the PostgreSQL protocol over TCP, one thread per connection
+#[derive(Clone)]
+pub struct PgServer {
+    /// Hives that clients can connect to
+    manager: Arc<RwLock<HiveManager>>,
+    
+    /// Registry the connections are tracked in
+    sessions: Arc<SessionRegistry>,
+    
+    /// Operating mode of the server
+    mode: Arc<ModeControl>,
+    
+    /// Masking policies by hive name
+    masking: HashMap<String, MaskingPolicy>,
+    
+    /// Statements clients are limited to (None to accept any SQL)
+    allowlist: Option<Arc<QueryAllowlist>>,
+}
+
+impl PgServer {
+    /// Create a server exposing the hives of a manager
+    pub fn new(manager: Arc<RwLock<HiveManager>>) -> Self {
+        Self::with_sessions(manager, Arc::new(SessionRegistry::new()))
+    }
+    
+    /// Create a server that tracks its connections in a shared registry
+    pub fn with_sessions(manager: Arc<RwLock<HiveManager>>, sessions: Arc<SessionRegistry>) -> Self {
+        Self {
+            manager,
+            sessions,
+            mode: Arc::new(ModeControl::default()),
+            masking: HashMap::new(),
+            allowlist: None,
+        }
+    }
+    
+    /// Mask the results of queries against a hive
+    ///
+    /// Connections are not authenticated, so every client counts as
+    /// low-privileged and the policy always applies.
+    pub fn with_masking(mut self, hive: &str, policy: MaskingPolicy) -> Self {
+        self.masking.insert(hive.to_string(), policy);
+        self
+    }
+    
+    /// Follow the operating mode shared with the rest of the server
+    pub fn with_mode(mut self, mode: Arc<ModeControl>) -> Self {
+        self.mode = mode;
+        self
+    }
+    
+    /// Only run the statements of an allowlist, through EXECUTE
+    ///
+    /// Connections are not authenticated, so no client holds a role the
+    /// allowlist could leave unrestricted.
+    pub fn with_allowlist(mut self, allowlist: QueryAllowlist) -> Self {
+        self.allowlist = Some(Arc::new(allowlist));
+        self
+    }
+    
+    /// Accept connections on the given address until the listener fails
+    pub fn serve(&self, address: &str) -> Result<(), HiveError> {
+        let listener = TcpListener::bind(address)
+            .map_err(|e| HiveError::NetworkError(e.to_string()))?;
+        
+        info!("PostgreSQL protocol listener started on {}", address);
+        
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!("Failed to accept PostgreSQL connection: {}", e);
+                    continue;
+                }
+            };
+            
+            let server = self.clone();
+            std::thread::spawn(move || {
+                if let Err(e) = handle_connection(&server, stream) {
+                    debug!("PostgreSQL connection closed: {}", e);
+                }
+            });
+        }
+        
+        Ok(())
+    }
+}
+
+/// What a statement produced
+enum Outcome {
+    /// A result set or row count
+    Rows(QueryResult),
+    
+    /// A bare command tag
+    Command(String),
+}
+
+/// A connected client bound to one hive
+#[derive(Clone)]
+pub struct PgSession {
+    /// The hive selected at startup
+    hive: Arc<RwLock<Hive>>,
+    
+    /// Registration of this session, shared with the statements running on worker bees
+    handle: Arc<SessionHandle>,
+    
+    /// Operating mode of the server
+    mode: Arc<ModeControl>,
+    
+    /// Masking applied to result sets
+    masking: MaskingPolicy,
+    
+    /// Trace the client asked statements to be recorded under
+    trace_parent: Option<TraceContext>,
+    
+    /// Hives that subqueries may read besides the selected one
+    manager: Option<Arc<RwLock<HiveManager>>>,
+    
+    /// Statements the client is limited to
+    allowlist: Option<Arc<QueryAllowlist>>,
+}
+
+impl PgSession {
+    /// Create a session for a hive
+    pub fn new(hive: Arc<RwLock<Hive>>, handle: SessionHandle) -> Self {
+        Self {
+            hive,
+            handle: Arc::new(handle),
+            mode: Arc::new(ModeControl::default()),
+            masking: MaskingPolicy::default(),
+            trace_parent: None,
+            manager: None,
+            allowlist: None,
+        }
+    }
+    
+    /// Record the spans of this session's statements in the caller's trace
+    pub fn with_trace_parent(mut self, parent: Option<TraceContext>) -> Self {
+        self.trace_parent = parent;
+        self
+    }
+    
+    /// Mask sensitive fields in result sets
+    pub fn with_masking(mut self, masking: MaskingPolicy) -> Self {
+        self.masking = masking;
+        self
+    }
+    
+    /// Let subqueries read the other hives of a manager
+    pub fn with_manager(mut self, manager: Arc<RwLock<HiveManager>>) -> Self {
+        self.manager = Some(manager);
+        self
+    }
+    
+    /// Follow the operating mode shared with the rest of the server
+    pub fn with_mode(mut self, mode: Arc<ModeControl>) -> Self {
+        self.mode = mode;
+        self
+    }
+    
+    /// Limit the client to the statements of an allowlist
+    pub fn with_allowlist(mut self, allowlist: Option<Arc<QueryAllowlist>>) -> Self {
+        self.allowlist = allowlist;
+        self
+    }
+    
+    /// Run a simple query message and append the response messages to `out`
+    pub fn simple_query(&self, sql_text: &str, out: &mut Vec<u8>) {
+        let statements = sql::split_statements(sql_text);
+        
+        if statements.is_empty() {
+            out.extend(message(b'I', &[]));
+        }
+        
+        for statement in statements {
+            let mut span = telemetry::server_span("pgwire.statement", self.trace_parent);
+            span.set_attribute("db.system", "hivedb");
+            span.set_attribute("network.protocol.name", "postgresql");
+            
+            match span.record(self.run(&statement)) {
+                Ok(Outcome::Rows(result)) => write_result(&result, out),
+                Ok(Outcome::Command(tag)) => out.extend(message(b'C', &cstrings(&[&tag]))),
+                Err(e) => {
+                    out.extend(error_response("ERROR", sql_state(&e), &e.to_string()));
+                    // The rest of the query string is skipped after an error
+                    break;
+                }
+            }
+        }
+        
+        out.extend(ready_for_query());
+    }
+    
+    fn run(&self, statement: &str) -> Result<Outcome, HiveError> {
+        self.mode.check_read()?;
+        
+        match (sql::parse_statement(statement)?, &self.allowlist) {
+            (Statement::Execute { name, params }, Some(allowlist)) => {
+                let bound = allowlist.bind(&name, &params)?;
+                self.dispatch(&bound, sql::parse_statement(&bound)?)
+            }
+            (Statement::Execute { .. }, None) => {
+                Err(HiveError::QueryError("no statements are registered for EXECUTE".to_string()))
+            }
+            (_, Some(allowlist)) if allowlist.is_restricted(&[]) => Err(HiveError::AuthorizationError(
+                "only EXECUTE of statements on the query allowlist is accepted".to_string(),
+            )),
+            (parsed, _) => self.dispatch(statement, parsed),
+        }
+    }
+    
+    /// Execute a statement, on a worker bee if it reads or writes documents
+    ///
+    /// Statements such as EXPLAIN run at once on the connection's thread,
+    /// so they never queue behind running queries.
+    fn dispatch(&self, statement: &str, parsed: Statement) -> Result<Outcome, HiveError> {
+        if !matches!(parsed, Statement::Query(_) | Statement::Match(_)) {
+            return self.execute(statement, parsed);
+        }
+        
+        let session = self.clone();
+        let statement = statement.to_string();
+        worker::pool().run(Priority::Foreground, move || session.execute(&statement, parsed))?
+    }
+    
+    fn execute(&self, statement: &str, parsed: Statement) -> Result<Outcome, HiveError> {
+        let query = match parsed {
+            Statement::Query(query) => query,
+            // Connections are not authenticated, so no client may see or
+            // kill the queries of others; the admin API offers these
+            Statement::ShowSessions | Statement::ShowQueries | Statement::KillQuery(_) => {
+                return Err(HiveError::AuthorizationError(
+                    "session administration needs an admin; use the admin API".to_string(),
+                ));
+            }
+            Statement::Explain(query) => {
+                let hive = self.hive.read().map_err(|_| HiveError::LockError)?;
+                let plan = QueryExecutor::explain(&query, &hive);
+                let lines: Vec<serde_json::Value> = plan.lines().into_iter()
+                    .map(|line| serde_json::json!({ "QUERY PLAN": line }))
+                    .collect();
+                return listing(&lines).map(Outcome::Rows);
+            }
+            Statement::Analyze(collection) => {
+                self.mode.check_write()?;
+                let mut hive = self.hive.write().map_err(|_| HiveError::LockError)?;
+                hive.analyze(collection.as_deref())?;
+                hive.commit()?;
+                return Ok(Outcome::Command("ANALYZE".to_string()));
+            }
+            Statement::Reindex(name) => {
+                self.mode.check_write()?;
+                let mut hive = self.hive.write().map_err(|_| HiveError::LockError)?;
+                hive.rebuild_index(&name)?;
+                return Ok(Outcome::Command("REINDEX".to_string()));
+            }
+            Statement::DropIndex(name) => {
+                self.mode.check_write()?;
+                let mut hive = self.hive.write().map_err(|_| HiveError::LockError)?;
+                hive.drop_index(&name)?;
+                hive.commit()?;
+                return Ok(Outcome::Command("DROP INDEX".to_string()));
+            }
+            Statement::Execute { .. } => {
+                return Err(HiveError::QueryError("EXECUTE cannot run another EXECUTE".to_string()));
+            }
+            Statement::Match(pattern) => {
+                let running = self.handle.begin_query(statement)?;
+                let hive = self.hive.read().map_err(|_| HiveError::LockError)?;
+                let mut result = listing(&pattern.execute(&hive, running.token())?)?;
+                self.handle.record_usage(Operation::Read, result.results.len() as u64, result_bytes(&result))?;
+                
+                // Each column holds a bound document, masked on its own
+                for row in &mut result.results {
+                    if let Some(bindings) = row.as_object_mut() {
+                        for document in bindings.values_mut() {
+                            self.masking.mask_document(document, &[]);
+                        }
+                    }
+                }
+                return Ok(Outcome::Rows(result));
+            }
+        };
+        
+        let running = self.handle.begin_query(statement)?;
+        
+        // Subqueries run first, while the other hives can be locked in order
+        let query = match &self.manager {
+            Some(manager) if query.has_subqueries() => {
+                let manager = manager.read().map_err(|_| HiveError::LockError)?;
+                let hive = self.hive.read().map_err(|_| HiveError::LockError)?;
+                QueryExecutor::materialize(&query, &hive, Some(&manager), running.token())?
+            }
+            _ => query,
+        };
+        
+        let mut result = match query.query_type {
+            QueryType::Find | QueryType::Count => {
+                let hive = self.hive.read().map_err(|_| HiveError::LockError)?;
+                QueryExecutor::execute_read_cancellable(&query, &hive, running.token())?
+            }
+            _ => {
+                self.mode.check_write()?;
+                let (result, ticket) = {
+                    let mut hive = self.hive.write().map_err(|_| HiveError::LockError)?;
+                    let result = QueryExecutor::execute_cancellable(&query, &mut hive, running.token())?;
+                    (result, CommitTicket::new(&hive))
+                };
+                
+                // Wait for the save outside the lock, so concurrent writers share it
+                ticket.wait(&self.hive)?;
+                result
+            }
+        };
+        
+        match query.query_type {
+            QueryType::Find | QueryType::Count => {
+                self.handle.record_usage(Operation::Read, result.results.len() as u64, result_bytes(&result))?;
+            }
+            _ => {
+                let bytes = query.data.as_ref().map_or(0, |data| data.to_string().len() as u64);
+                self.handle.record_usage(Operation::Write, result.count as u64, bytes)?;
+            }
+        }
+        
+        self.masking.mask_result(&mut result, &[]);
+        Ok(Outcome::Rows(result))
+    }
+}
+
+/// Get the size of the documents a query returned, as JSON
+fn result_bytes(result: &QueryResult) -> u64 {
+    result.results.iter().map(|document| document.to_string().len() as u64).sum()
+}
+
+fn handle_connection(server: &PgServer, mut stream: TcpStream) -> Result<(), HiveError> {
+    let parameters = match startup(&mut stream)? {
+        Some(parameters) => parameters,
+        None => return Ok(()),
+    };
+    
+    if let Err(e) = server.mode.check_read() {
+        let _ = stream.write_all(&error_response("FATAL", sql_state(&e), &e.to_string()));
+        return Err(e);
+    }
+    
+    let database = parameters.iter()
+        .find(|(key, _)| key == "database")
+        .or_else(|| parameters.iter().find(|(key, _)| key == "user"))
+        .map(|(_, value)| value.clone())
+        .unwrap_or_default();
+    
+    let hive = server.manager.read().map_err(|_| HiveError::LockError)?
+        .get_hive_by_name(&database);
+    
+    let hive = match hive {
+        Some(hive) => hive,
+        None => {
+            let reply = error_response("FATAL", "3D000", &format!("hive \"{}\" does not exist", database));
+            let _ = stream.write_all(&reply);
+            return Err(HiveError::HiveNotFound);
+        }
+    };
+    
+    let mut greeting = Vec::new();
+    // AuthenticationOk
+    greeting.extend(message(b'R', &0i32.to_be_bytes()));
+    for (name, value) in [
+        ("server_version", "14.0 (HiveDB)"),
+        ("server_encoding", "UTF8"),
+        ("client_encoding", "UTF8"),
+        ("DateStyle", "ISO, MDY"),
+        ("integer_datetimes", "on"),
+        ("standard_conforming_strings", "on"),
+    ] {
+        greeting.extend(message(b'S', &cstrings(&[name, value])));
+    }
+    // BackendKeyData; cancellation is not supported, so the key is unused
+    let mut key_data = (std::process::id() as i32).to_be_bytes().to_vec();
+    key_data.extend(0i32.to_be_bytes());
+    greeting.extend(message(b'K', &key_data));
+    greeting.extend(ready_for_query());
+    write(&mut stream, &greeting)?;
+    
+    info!("PostgreSQL client connected to hive '{}'", database);
+    
+    let user = parameters.iter()
+        .find(|(key, _)| key == "user")
+        .map(|(_, value)| value.as_str())
+        .unwrap_or("");
+    let client = stream.peer_addr().map(|a| a.to_string()).unwrap_or_default();
+    let handle = server.sessions.open_session(user, &client, "postgresql", &database)?;
+    // Clients can join HiveDB's spans to their trace with a `traceparent` startup parameter
+    let trace_parent = parameters.iter()
+        .find(|(key, _)| key == "traceparent")
+        .and_then(|(_, value)| TraceContext::from_traceparent(value));
+    let session = PgSession::new(hive, handle)
+        .with_trace_parent(trace_parent)
+        .with_mode(server.mode.clone())
+        .with_manager(server.manager.clone())
+        .with_masking(server.masking.get(&database).cloned().unwrap_or_default())
+        .with_allowlist(server.allowlist.clone());
+    let mut in_failed_extended_query = false;
+    
+    loop {
+        let (tag, body) = read_message(&mut stream)?;
+        let mut out = Vec::new();
+        
+        match tag {
+            b'Q' => {
+                let text = String::from_utf8_lossy(body.strip_suffix(&[0]).unwrap_or(&body)).into_owned();
+                session.simple_query(&text, &mut out);
+            }
+            b'X' => return Ok(()),
+            b'S' => {
+                in_failed_extended_query = false;
+                out.extend(ready_for_query());
+            }
+            b'H' => {}
+            // Parse/Bind/Describe/Execute/Close: report once, then discard until Sync
+            _ => {
+                if !in_failed_extended_query {
+                    in_failed_extended_query = true;
+                    out.extend(error_response("ERROR", "0A000", "the extended query protocol is not supported"));
+                }
+            }
+        }
+        
+        if !out.is_empty() {
+            write(&mut stream, &out)?;
+        }
+    }
+}
+
+/// Handle the startup phase and return the client's parameters
+///
+/// Returns None if the client only sent a cancel request.
+fn startup(stream: &mut TcpStream) -> Result<Option<Vec<(String, String)>>, HiveError> {
+    loop {
+        let mut length = [0u8; 4];
+        read_exact(stream, &mut length)?;
+        let length = i32::from_be_bytes(length) as usize;
+        if length < 8 || length > MAX_MESSAGE_LEN {
+            return Err(HiveError::NetworkError("invalid startup message".to_string()));
+        }
+        
+        let mut body = vec![0u8; length - 4];
+        read_exact(stream, &mut body)?;
+        let code = i32::from_be_bytes([body[0], body[1], body[2], body[3]]);
+        
+        match code {
+            SSL_REQUEST_CODE => write(stream, b"N")?,
+            CANCEL_REQUEST_CODE => return Ok(None),
+            PROTOCOL_VERSION => {
+                let fields: Vec<String> = body[4..]
+                    .split(|b| *b == 0)
+                    .map(|s| String::from_utf8_lossy(s).into_owned())
+                    .collect();
+                
+                return Ok(Some(fields
+                    .chunks(2)
+                    .filter(|pair| pair.len() == 2 && !pair[0].is_empty())
+                    .map(|pair| (pair[0].clone(), pair[1].clone()))
+                    .collect()));
+            }
+            _ => {
+                let reply = error_response("FATAL", "0A000", "unsupported frontend protocol");
+                let _ = stream.write_all(&reply);
+                return Err(HiveError::NetworkError(format!("unsupported protocol {}", code)));
+            }
+        }
+    }
+}
+
+/// A minimal client for the simple query protocol
+///
+/// Only trust authentication is supported, as offered by `PgServer`.
+pub struct PgClient {
+    /// Connection to the server
+    stream: TcpStream,
+}
+
+impl PgClient {
+    /// Connect to a server and select a hive
+    pub fn connect(address: &str, user: &str, hive: &str) -> Result<Self, HiveError> {
+        let mut stream = TcpStream::connect(address)
+            .map_err(|e| HiveError::NetworkError(e.to_string()))?;
+        let _ = stream.set_nodelay(true);
+        
+        let mut body = PROTOCOL_VERSION.to_be_bytes().to_vec();
+        body.extend(cstrings(&["user", user, "database", hive, ""]));
+        let mut startup = ((body.len() + 4) as i32).to_be_bytes().to_vec();
+        startup.extend(body);
+        write(&mut stream, &startup)?;
+        
+        let mut client = Self { stream };
+        loop {
+            let (tag, body) = read_message(&mut client.stream)?;
+            match tag {
+                b'R' if body.get(..4) == Some(&[0, 0, 0, 0][..]) => {}
+                b'R' => {
+                    return Err(HiveError::AuthenticationError("Unsupported authentication method".to_string()));
+                }
+                b'E' => return Err(HiveError::NetworkError(error_text(&body))),
+                b'Z' => return Ok(client),
+                _ => {}
+            }
+        }
+    }
+    
+    /// Run one or more statements and return the rows of the result sets
+    ///
+    /// Each row holds the text of its columns (None for NULL).
+    pub fn simple_query(&mut self, sql_text: &str) -> Result<Vec<Vec<Option<String>>>, HiveError> {
+        write(&mut self.stream, &message(b'Q', &cstrings(&[sql_text])))?;
+        
+        let mut rows = Vec::new();
+        let mut error = None;
+        loop {
+            let (tag, body) = read_message(&mut self.stream)?;
+            match tag {
+                b'D' => rows.push(parse_data_row(&body)?),
+                b'E' => error = Some(error_text(&body)),
+                b'Z' => break,
+                _ => {}
+            }
+        }
+        
+        match error {
+            Some(error) => Err(HiveError::QueryError(error)),
+            None => Ok(rows),
+        }
+    }
+    
+    /// Close the connection
+    pub fn close(mut self) {
+        let _ = self.stream.write_all(&message(b'X', &[]));
+    }
+}
+
+/// Get the message field of an ErrorResponse
+fn error_text(body: &[u8]) -> String {
+    body.split(|b| *b == 0)
+        .find_map(|field| field.strip_prefix(b"M"))
+        .map(|text| String::from_utf8_lossy(text).into_owned())
+        .unwrap_or_else(|| "unknown server error".to_string())
+}
+
+/// Parse the columns of a DataRow
+fn parse_data_row(body: &[u8]) -> Result<Vec<Option<String>>, HiveError> {
+    let malformed = || HiveError::NetworkError("malformed data row".to_string());
+    
+    let count = i16::from_be_bytes([*body.first().ok_or_else(malformed)?, *body.get(1).ok_or_else(malformed)?]);
+    let mut rest = &body[2..];
+    let mut columns = Vec::with_capacity(count.max(0) as usize);
+    
+    for _ in 0..count {
+        let length = i32::from_be_bytes(rest.get(..4).ok_or_else(malformed)?.try_into().map_err(|_| malformed())?);
+        rest = &rest[4..];
+        if length < 0 {
+            columns.push(None);
+            continue;
+        }
+        
+        let value = rest.get(..length as usize).ok_or_else(malformed)?;
+        columns.push(Some(String::from_utf8_lossy(value).into_owned()));
+        rest = &rest[length as usize..];
+    }
+    
+    Ok(columns)
+}
+
+/// Append the messages describing a query result
+fn write_result(result: &QueryResult, out: &mut Vec<u8>) {
+    match result.query_type {
+        QueryType::Find | QueryType::Count => {
+            let columns = result_columns(&result.results);
+            
+            let mut description = (columns.len() as i16).to_be_bytes().to_vec();
+            for column in &columns {
+                description.extend(cstrings(&[column]));
+                description.extend(0i32.to_be_bytes()); // table OID
+                description.extend(0i16.to_be_bytes()); // column number
+                description.extend(TEXT_OID.to_be_bytes());
+                description.extend((-1i16).to_be_bytes()); // variable length
+                description.extend((-1i32).to_be_bytes()); // type modifier
+                description.extend(0i16.to_be_bytes()); // text format
+            }
+            out.extend(message(b'T', &description));
+            
+            for document in &result.results {
+                let mut row = (columns.len() as i16).to_be_bytes().to_vec();
+                for column in &columns {
+                    match document.get(column.as_str()) {
+                        None | Some(serde_json::Value::Null) => row.extend((-1i32).to_be_bytes()),
+                        Some(value) => {
+                            let text = match value {
+                                serde_json::Value::String(s) => s.clone(),
+                                other => other.to_string(),
+                            };
+                            row.extend((text.len() as i32).to_be_bytes());
+                            row.extend(text.as_bytes());
+                        }
+                    }
+                }
+                out.extend(message(b'D', &row));
+            }
+            
+            out.extend(message(b'C', &cstrings(&[&format!("SELECT {}", result.results.len())])));
+        }
+        QueryType::Insert => out.extend(message(b'C', &cstrings(&[&format!("INSERT 0 {}", result.count)]))),
+        QueryType::Update => out.extend(message(b'C', &cstrings(&[&format!("UPDATE {}", result.count)]))),
+        QueryType::Delete => out.extend(message(b'C', &cstrings(&[&format!("DELETE {}", result.count)]))),
+        QueryType::Aggregate => out.extend(message(b'C', &cstrings(&["SELECT 0"]))),
+    }
+}
+
+/// Turn a list of records into a result set
+fn listing<T: serde::Serialize>(records: &[T]) -> Result<QueryResult, HiveError> {
+    let results = records.iter()
+        .map(|record| serde_json::to_value(record).map_err(|e| HiveError::SerializationError(e.to_string())))
+        .collect::<Result<Vec<_>, _>>()?;
+    
+    Ok(QueryResult {
+        query_type: QueryType::Find,
+        count: results.len(),
+        results,
+        has_more: false,
+        next_cursor: None,
+        execution_time_ms: 0,
+    })
+}
+
+/// Column names for a result: every field, in order of first appearance
+fn result_columns(results: &[serde_json::Value]) -> Vec<String> {
+    let mut columns: Vec<String> = Vec::new();
+    
+    for document in results {
+        if let Some(object) = document.as_object() {
+            for key in object.keys() {
+                if !columns.contains(key) {
+                    columns.push(key.clone());
+                }
+            }
+        }
+    }
+    
+    columns
+}
+
+/// Map an error to a SQLSTATE code
+fn sql_state(error: &HiveError) -> &'static str {
+    match error {
+        HiveError::QueryError(message) if message.starts_with("SQL syntax error") => "42601",
+        HiveError::QueryError(_) => "22000",
+        HiveError::QueryCancelled => "57014",
+        HiveError::ReadOnlyMode => "25006",
+        HiveError::MaintenanceMode => "57P03",
+        HiveError::MemoryBudgetExceeded(_) => "53200",
+        HiveError::TenantBusy(_) => "53000",
+        HiveError::DiskSpaceLow(_) => "53100",
+        HiveError::QueryNotFound(_) | HiveError::IndexNotFound(_) => "42704",
+        HiveError::NotImplemented => "0A000",
+        HiveError::AuthorizationError(_) => "42501",
+        _ => "XX000",
+    }
+}
+
+fn message(tag: u8, body: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(body.len() + 5);
+    out.push(tag);
+    out.extend(((body.len() + 4) as i32).to_be_bytes());
+    out.extend(body);
+    out
+}
+
+fn cstrings(values: &[&str]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for value in values {
+        out.extend(value.as_bytes());
+        out.push(0);
+    }
+    out
+}
+
+fn error_response(severity: &str, code: &str, text: &str) -> Vec<u8> {
+    let mut body = Vec::new();
+    for (field, value) in [(b'S', severity), (b'V', severity), (b'C', code), (b'M', text)] {
+        body.push(field);
+        body.extend(cstrings(&[value]));
+    }
+    body.push(0);
+    message(b'E', &body)
+}
+
+fn ready_for_query() -> Vec<u8> {
+    message(b'Z', b"I")
+}
+
+fn read_message(stream: &mut TcpStream) -> Result<(u8, Vec<u8>), HiveError> {
+    let mut header = [0u8; 5];
+    read_exact(stream, &mut header)?;
+    
+    let length = i32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+    if length < 4 || length > MAX_MESSAGE_LEN {
+        return Err(HiveError::NetworkError("invalid message length".to_string()));
+    }
+    
+    let mut body = vec![0u8; length - 4];
+    read_exact(stream, &mut body)?;
+    Ok((header[0], body))
+}
+
+fn read_exact(stream: &mut TcpStream, buffer: &mut [u8]) -> Result<(), HiveError> {
+    stream.read_exact(buffer)
+        .map_err(|e| HiveError::NetworkError(e.to_string()))
+}
+
+fn write(stream: &mut TcpStream, data: &[u8]) -> Result<(), HiveError> {
+    stream.write_all(data)
+        .map_err(|e| HiveError::NetworkError(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::mode::ServerMode;
+    use crate::security::masking::{MaskingRule, MaskStrategy};
+    use tempfile::tempdir;
+    
+    fn session() -> PgSession {
+        let temp_dir = tempdir().unwrap();
+        let hive = Hive::new(
+            "shop".to_string(),
+            "A test hive".to_string(),
+            "test-user".to_string(),
+            temp_dir.path().to_path_buf(),
+            (16, 16),
+        ).unwrap();
+        
+        let handle = Arc::new(SessionRegistry::new())
+            .open_session("test-user", "127.0.0.1:5432", "postgresql", "shop")
+            .unwrap();
+        
+        PgSession::new(Arc::new(RwLock::new(hive)), handle)
+    }
+    
+    /// Split a response buffer into (tag, body) messages
+    fn messages(mut data: &[u8]) -> Vec<(u8, Vec<u8>)> {
+        let mut messages = Vec::new();
+        while !data.is_empty() {
+            let length = i32::from_be_bytes([data[1], data[2], data[3], data[4]]) as usize;
+            messages.push((data[0], data[5..1 + length].to_vec()));
+            data = &data[1 + length..];
+        }
+        messages
+    }
+    
+    #[test]
+    fn test_insert_and_select() {
+        let session = session();
+        
+        let mut out = Vec::new();
+        session.simple_query("INSERT INTO orders (item, price) VALUES ('honey', 12), ('wax', 3)", &mut out);
+        let replies = messages(&out);
+        assert_eq!(replies[0], (b'C', b"INSERT 0 2\0".to_vec()));
+        assert_eq!(replies[1], (b'Z', b"I".to_vec()));
+        
+        let mut out = Vec::new();
+        session.simple_query("SELECT item FROM orders WHERE price > 5", &mut out);
+        let tags: Vec<u8> = messages(&out).iter().map(|(tag, _)| *tag).collect();
+        assert_eq!(tags, vec![b'T', b'D', b'C', b'Z']);
+    }
+    
+    #[test]
+    fn test_errors_stop_the_batch() {
+        let session = session();
+        
+        let mut out = Vec::new();
+        session.simple_query("SELEC * FROM orders; SELECT * FROM orders", &mut out);
+        let replies = messages(&out);
+        
+        assert_eq!(replies.len(), 2);
+        assert_eq!(replies[0].0, b'E');
+        assert_eq!(replies[1].0, b'Z');
+    }
+    
+    #[test]
+    fn test_session_administration_refused() {
+        let session = session();
+        
+        // Unauthenticated clients cannot list or kill other sessions' queries
+        for sql_text in ["SHOW SESSIONS", "SHOW QUERIES", "KILL QUERY 999"] {
+            let mut out = Vec::new();
+            session.simple_query(sql_text, &mut out);
+            let replies = messages(&out);
+            assert_eq!(replies[0].0, b'E');
+            assert!(String::from_utf8_lossy(&replies[0].1).contains("42501"));
+        }
+    }
+    
+    #[test]
+    fn test_server_modes() {
+        let mode = Arc::new(ModeControl::default());
+        let session = session().with_mode(mode.clone());
+        
+        mode.set(ServerMode::ReadOnly);
+        let mut out = Vec::new();
+        session.simple_query("INSERT INTO orders (item) VALUES ('honey')", &mut out);
+        let replies = messages(&out);
+        assert_eq!(replies[0].0, b'E');
+        assert!(String::from_utf8_lossy(&replies[0].1).contains("25006"));
+        
+        let mut out = Vec::new();
+        session.simple_query("SELECT * FROM orders", &mut out);
+        assert_eq!(messages(&out)[0].0, b'T');
+        
+        mode.set(ServerMode::Maintenance);
+        let mut out = Vec::new();
+        session.simple_query("SELECT * FROM orders", &mut out);
+        assert!(String::from_utf8_lossy(&messages(&out)[0].1).contains("57P03"));
+    }
+    
+    #[test]
+    fn test_masked_results() {
+        let masking = MaskingPolicy::new("salt")
+            .with_rule(MaskingRule::new("email", MaskStrategy::Redact));
+        let session = session().with_masking(masking);
+        
+        let mut out = Vec::new();
+        session.simple_query("INSERT INTO users (email) VALUES ('a@example.com')", &mut out);
+        
+        let mut out = Vec::new();
+        session.simple_query("SELECT email FROM users", &mut out);
+        let row = &messages(&out)[1];
+        assert_eq!(row.0, b'D');
+        assert!(!String::from_utf8_lossy(&row.1).contains("a@example.com"));
+    }
+    
+    #[test]
+    fn test_allowlisted_statements() {
+        let mut allowlist = QueryAllowlist::new();
+        allowlist.register("add_order", "INSERT INTO orders (item, price) VALUES ($1, $2)").unwrap();
+        allowlist.register("orders_over", "SELECT item FROM orders WHERE price > $1").unwrap();
+        let session = session().with_allowlist(Some(Arc::new(allowlist)));
+        
+        let mut out = Vec::new();
+        session.simple_query("EXECUTE add_order('honey', 12); EXECUTE add_order('wax', 3)", &mut out);
+        let replies = messages(&out);
+        assert_eq!(replies[0], (b'C', b"INSERT 0 1\0".to_vec()));
+        assert_eq!(replies[1], (b'C', b"INSERT 0 1\0".to_vec()));
+        
+        let mut out = Vec::new();
+        session.simple_query("EXECUTE orders_over(5)", &mut out);
+        let tags: Vec<u8> = messages(&out).iter().map(|(tag, _)| *tag).collect();
+        assert_eq!(tags, vec![b'T', b'D', b'C', b'Z']);
+        
+        // Raw SQL and unknown statements are refused as insufficient privilege
+        for sql_text in ["SELECT * FROM orders", "EXECUTE drop_all", "SHOW SESSIONS"] {
+            let mut out = Vec::new();
+            session.simple_query(sql_text, &mut out);
+            let replies = messages(&out);
+            assert_eq!(replies[0].0, b'E');
+            assert!(String::from_utf8_lossy(&replies[0].1).contains("42501"));
+        }
+    }
+    
+    #[test]
+    fn test_client_round_trip() {
+        let temp_dir = tempdir().unwrap();
+        let mut manager = HiveManager::new(temp_dir.path().to_path_buf()).unwrap();
+        manager.create_hive("shop".to_string(), String::new(), "test-user".to_string(), (16, 16)).unwrap();
+        let server = PgServer::new(Arc::new(RwLock::new(manager)));
+        
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().take(2) {
+                let _ = handle_connection(&server, stream.unwrap());
+            }
+        });
+        
+        let mut client = PgClient::connect(&address, "test-user", "shop").unwrap();
+        client.simple_query("INSERT INTO orders (item, qty) VALUES ('honey', 2)").unwrap();
+        let rows = client.simple_query("SELECT item, qty FROM orders").unwrap();
+        assert_eq!(rows, vec![vec![Some("honey".to_string()), Some("2".to_string())]]);
+        
+        assert!(matches!(client.simple_query("SELEC 1"), Err(HiveError::QueryError(_))));
+        client.close();
+        
+        assert!(PgClient::connect(&address, "test-user", "missing").is_err());
+    }
+    
+    #[test]
+    fn test_empty_query() {
+        let session = session();
+        
+        let mut out = Vec::new();
+        session.simple_query("  ; ", &mut out);
+        let tags: Vec<u8> = messages(&out).iter().map(|(tag, _)| *tag).collect();
+        
+        assert_eq!(tags, vec![b'I', b'Z']);
+    }
+}
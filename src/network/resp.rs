@@ -0,0 +1,968 @@
+// HiveDB RESP Module
+//
+// This module implements a Redis protocol (RESP2) compatibility mode.
+// GET/SET/DEL/SCAN and friends are mapped onto key-value cells of a hive,
+// so existing Redis clients can use HiveDB as a persistent key-value store.
+// Pipelined commands are answered with one write per batch read from the
+// client, and MSET and DEL change all of their keys or none of them.
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, RwLock};
+use crate::core::cell::{Cell, CellDataType};
+use crate::core::commit::CommitTicket;
+use crate::core::error::HiveError;
+use crate::core::hive::Hive;
+use crate::core::mode::ModeControl;
+use log::{debug, info, warn};
+
+/// Largest bulk string accepted from a client (same limit as Redis)
+const MAX_BULK_LEN: i64 = 512 * 1024 * 1024;
+
+/// Longest inline command or protocol line accepted from a client (same limit as Redis)
+const MAX_INLINE_LEN: usize = 64 * 1024;
+
+/// Most arguments accepted in one command
+const MAX_ARRAY_LEN: i64 = 1024 * 1024;
+
+/// Most unparsed bytes held for a client before it is disconnected
+const MAX_QUERY_BUFFER: usize = 1024 * 1024 * 1024;
+
+/// Default number of keys returned by one SCAN call
+const DEFAULT_SCAN_COUNT: usize = 10;
+
+/// A value in the Redis serialization protocol
+#[derive(Debug, Clone, PartialEq)]
+pub enum RespValue {
+    /// Simple string (`+OK`)
+    SimpleString(String),
+    
+    /// Error reply (`-ERR ...`)
+    Error(String),
+    
+    /// Integer reply (`:1`)
+    Integer(i64),
+    
+    /// Bulk string; None is the null bulk string
+    BulkString(Option<Vec<u8>>),
+    
+    /// Array; None is the null array
+    Array(Option<Vec<RespValue>>),
+}
+
+impl RespValue {
+    /// The `+OK` reply
+    pub fn ok() -> Self {
+        RespValue::SimpleString("OK".to_string())
+    }
+    
+    /// A bulk string reply
+    pub fn bulk(data: impl Into<Vec<u8>>) -> Self {
+        RespValue::BulkString(Some(data.into()))
+    }
+    
+    /// Serialize this value onto the end of a buffer
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            RespValue::SimpleString(s) => {
+                out.push(b'+');
+                out.extend_from_slice(s.as_bytes());
+            }
+            RespValue::Error(e) => {
+                out.push(b'-');
+                out.extend_from_slice(e.as_bytes());
+            }
+            RespValue::Integer(i) => {
+                out.extend_from_slice(format!(":{}", i).as_bytes());
+            }
+            RespValue::BulkString(None) => out.extend_from_slice(b"$-1"),
+            RespValue::BulkString(Some(data)) => {
+                out.extend_from_slice(format!("${}\r\n", data.len()).as_bytes());
+                out.extend_from_slice(data);
+            }
+            RespValue::Array(None) => out.extend_from_slice(b"*-1"),
+            RespValue::Array(Some(items)) => {
+                out.extend_from_slice(format!("*{}\r\n", items.len()).as_bytes());
+                for item in items {
+                    item.encode(out);
+                }
+                // Nested values already end with CRLF
+                return;
+            }
+        }
+        out.extend_from_slice(b"\r\n");
+    }
+    
+    /// Serialize this value into a new buffer
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode(&mut out);
+        out
+    }
+}
+
+/// Parse one command from the start of a buffer
+///
+/// Returns the command and the number of bytes consumed, or None if the
+/// buffer does not yet hold a complete command. A command is an array of
+/// bulk strings; arrays holding anything else are rejected. Inline
+/// commands (`PING\r\n`) are accepted and returned as arrays of bulk
+/// strings.
+pub fn parse(buffer: &[u8]) -> Result<Option<(RespValue, usize)>, HiveError> {
+    match buffer.first() {
+        None => Ok(None),
+        Some(b'*') => parse_command(buffer),
+        Some(b'+' | b'-' | b':' | b'$') => parse_value(buffer, 0),
+        Some(_) => {
+            let (line, next) = match read_line(buffer, 0)? {
+                Some(found) => found,
+                None => return Ok(None),
+            };
+            let words = line
+                .split(|b| b.is_ascii_whitespace())
+                .filter(|word| !word.is_empty())
+                .map(|word| RespValue::bulk(word.to_vec()))
+                .collect();
+            Ok(Some((RespValue::Array(Some(words)), next)))
+        }
+    }
+}
+
+/// Parse an array of bulk strings from the start of a buffer
+fn parse_command(buffer: &[u8]) -> Result<Option<(RespValue, usize)>, HiveError> {
+    let (line, next) = match read_line(buffer, 1)? {
+        Some(found) => found,
+        None => return Ok(None),
+    };
+    let count = parse_integer(&String::from_utf8_lossy(line))?;
+    if count < 0 {
+        return Ok(Some((RespValue::Array(None), next)));
+    }
+    if count > MAX_ARRAY_LEN {
+        return Err(HiveError::NetworkError("invalid multibulk length".to_string()));
+    }
+    
+    let mut items = Vec::new();
+    let mut cursor = next;
+    for _ in 0..count {
+        // Only bulk strings can follow, so a nested array is never parsed
+        if buffer.get(cursor).is_some_and(|prefix| *prefix != b'$') {
+            return Err(HiveError::NetworkError("expected '$' in a command".to_string()));
+        }
+        match parse_value(buffer, cursor)? {
+            Some((item, after)) => {
+                items.push(item);
+                cursor = after;
+            }
+            None => return Ok(None),
+        }
+    }
+    
+    Ok(Some((RespValue::Array(Some(items)), cursor)))
+}
+
+/// Parse a bulk string or a simple value starting at a position
+fn parse_value(buffer: &[u8], position: usize) -> Result<Option<(RespValue, usize)>, HiveError> {
+    let prefix = match buffer.get(position) {
+        Some(prefix) => *prefix,
+        None => return Ok(None),
+    };
+    let (line, next) = match read_line(buffer, position + 1)? {
+        Some(found) => found,
+        None => return Ok(None),
+    };
+    let text = String::from_utf8_lossy(line).into_owned();
+    
+    match prefix {
+        b'+' => Ok(Some((RespValue::SimpleString(text), next))),
+        b'-' => Ok(Some((RespValue::Error(text), next))),
+        b':' => Ok(Some((RespValue::Integer(parse_integer(&text)?), next))),
+        b'$' => {
+            let len = parse_integer(&text)?;
+            if len < 0 {
+                return Ok(Some((RespValue::BulkString(None), next)));
+            }
+            if len > MAX_BULK_LEN {
+                return Err(HiveError::NetworkError("invalid bulk length".to_string()));
+            }
+            
+            let end = next + len as usize;
+            if buffer.len() < end + 2 {
+                return Ok(None);
+            }
+            if &buffer[end..end + 2] != b"\r\n" {
+                return Err(HiveError::NetworkError("expected CRLF after bulk string".to_string()));
+            }
+            
+            Ok(Some((RespValue::bulk(buffer[next..end].to_vec()), end + 2)))
+        }
+        _ => Err(HiveError::NetworkError(format!("unexpected '{}'", prefix as char))),
+    }
+}
+
+/// Find the CRLF-terminated line starting at `start`
+///
+/// A line longer than `MAX_INLINE_LEN` is an error rather than something
+/// to wait for, so an unterminated line is never scanned past the limit.
+fn read_line(buffer: &[u8], start: usize) -> Result<Option<(&[u8], usize)>, HiveError> {
+    let rest = match buffer.get(start..) {
+        Some(rest) => rest,
+        None => return Ok(None),
+    };
+    let window = &rest[..rest.len().min(MAX_INLINE_LEN + 2)];
+    match window.windows(2).position(|w| w == b"\r\n") {
+        Some(end) => Ok(Some((&rest[..end], start + end + 2))),
+        None if window.len() == MAX_INLINE_LEN + 2 => {
+            Err(HiveError::NetworkError("too big inline request".to_string()))
+        }
+        None => Ok(None),
+    }
+}
+
+fn parse_integer(text: &str) -> Result<i64, HiveError> {
+    text.parse::<i64>()
+        .map_err(|_| HiveError::NetworkError(format!("invalid integer '{}'", text)))
+}
+
+/// Executes Redis commands against the key-value cells of a hive
+pub struct RespHandler {
+    /// The hive that stores the keys
+    hive: Arc<RwLock<Hive>>,
+    
+    /// Operating mode of the server
+    mode: Arc<ModeControl>,
+}
+
+impl RespHandler {
+    /// Create a handler backed by the given hive
+    pub fn new(hive: Arc<RwLock<Hive>>) -> Self {
+        Self {
+            hive,
+            mode: Arc::new(ModeControl::default()),
+        }
+    }
+    
+    /// Follow the operating mode shared with the rest of the server
+    pub fn with_mode(mut self, mode: Arc<ModeControl>) -> Self {
+        self.mode = mode;
+        self
+    }
+    
+    /// Execute a command and return the reply to send
+    pub fn handle(&self, command: RespValue) -> RespValue {
+        let args = match command {
+            RespValue::Array(Some(items)) => items
+                .into_iter()
+                .map(|item| match item {
+                    RespValue::BulkString(Some(data)) => Some(data),
+                    RespValue::SimpleString(s) => Some(s.into_bytes()),
+                    _ => None,
+                })
+                .collect::<Option<Vec<_>>>(),
+            _ => None,
+        };
+        
+        let args = match args {
+            Some(args) if !args.is_empty() => args,
+            _ => return RespValue::Error("ERR Protocol error: expected an array of bulk strings".to_string()),
+        };
+        
+        match self.execute(&args) {
+            Ok(reply) => reply,
+            // Error prefixes that Redis clients already understand
+            Err(HiveError::ReadOnlyMode) => RespValue::Error("READONLY You can't write against a read only server.".to_string()),
+            Err(e @ HiveError::MaintenanceMode) => RespValue::Error(format!("LOADING {}", e)),
+            Err(e @ HiveError::MemoryBudgetExceeded(_)) => RespValue::Error(format!("OOM {}", e)),
+            Err(e @ HiveError::DiskSpaceLow(_)) => RespValue::Error(format!("MISCONF {}", e)),
+            Err(e) => RespValue::Error(format!("ERR {}", e)),
+        }
+    }
+    
+    fn execute(&self, args: &[Vec<u8>]) -> Result<RespValue, HiveError> {
+        let name = String::from_utf8_lossy(&args[0]).to_ascii_uppercase();
+        let params = &args[1..];
+        
+        match name.as_str() {
+            "PING" | "COMMAND" => {}
+            "SET" | "MSET" | "DEL" => self.mode.check_write()?,
+            _ => self.mode.check_read()?,
+        }
+        
+        match name.as_str() {
+            "PING" => Ok(match params.first() {
+                Some(message) => RespValue::bulk(message.clone()),
+                None => RespValue::SimpleString("PONG".to_string()),
+            }),
+            "ECHO" => {
+                expect_args(&name, params, 1, 1)?;
+                Ok(RespValue::bulk(params[0].clone()))
+            }
+            "GET" => {
+                expect_args(&name, params, 1, 1)?;
+                self.get(&key(&params[0])?)
+            }
+            "SET" => {
+                expect_args(&name, params, 2, usize::MAX)?;
+                self.set(&key(&params[0])?, &params[1], &params[2..])
+            }
+            "MGET" => {
+                expect_args(&name, params, 1, usize::MAX)?;
+                let values = params.iter()
+                    .map(|param| self.get(&key(param)?))
+                    .collect::<Result<Vec<_>, HiveError>>()?;
+                Ok(RespValue::Array(Some(values)))
+            }
+            "MSET" => {
+                if params.is_empty() || !params.len().is_multiple_of(2) {
+                    return Err(HiveError::NetworkError("wrong number of arguments for 'mset' command".to_string()));
+                }
+                self.mset(params)
+            }
+            "DEL" => {
+                expect_args(&name, params, 1, usize::MAX)?;
+                let keys = params.iter()
+                    .map(|param| key(param))
+                    .collect::<Result<Vec<_>, HiveError>>()?;
+                self.del(&keys)
+            }
+            "EXISTS" => {
+                expect_args(&name, params, 1, usize::MAX)?;
+                let hive = self.hive.read().map_err(|_| HiveError::LockError)?;
+                let mut found = 0;
+                for param in params {
+                    if kv_coordinates(&hive, &key(param)?)?.is_some() {
+                        found += 1;
+                    }
+                }
+                Ok(RespValue::Integer(found))
+            }
+            "DBSIZE" => {
+                expect_args(&name, params, 0, 0)?;
+                Ok(RespValue::Integer(self.keys()?.len() as i64))
+            }
+            "KEYS" => {
+                expect_args(&name, params, 1, 1)?;
+                let keys = self.keys()?
+                    .into_iter()
+                    .filter(|k| glob_match(&params[0], k.as_bytes()))
+                    .map(RespValue::bulk)
+                    .collect();
+                Ok(RespValue::Array(Some(keys)))
+            }
+            "SCAN" => {
+                expect_args(&name, params, 1, usize::MAX)?;
+                self.scan(params)
+            }
+            // redis-cli asks for command metadata on connect
+            "COMMAND" => Ok(RespValue::Array(Some(Vec::new()))),
+            _ => Err(HiveError::NetworkError(format!("unknown command '{}'", name))),
+        }
+    }
+    
+    fn get(&self, key: &str) -> Result<RespValue, HiveError> {
+        let hive = self.hive.read().map_err(|_| HiveError::LockError)?;
+        
+        match kv_cell(&hive, key)? {
+            Some(cell) => {
+                let cell = cell.read().map_err(|_| HiveError::LockError)?;
+                cell.record_read();
+                Ok(RespValue::bulk(cell.get_content()?))
+            }
+            None => Ok(RespValue::BulkString(None)),
+        }
+    }
+    
+    fn set(&self, key: &str, value: &[u8], options: &[Vec<u8>]) -> Result<RespValue, HiveError> {
+        let mut only_if_missing = false;
+        let mut only_if_present = false;
+        
+        for option in options {
+            match String::from_utf8_lossy(option).to_ascii_uppercase().as_str() {
+                "NX" => only_if_missing = true,
+                "XX" => only_if_present = true,
+                "EX" | "PX" | "EXAT" | "PXAT" | "KEEPTTL" => {
+                    return Err(HiveError::NetworkError("key expiry is not supported".to_string()));
+                }
+                _ => return Err(HiveError::NetworkError("syntax error".to_string())),
+            }
+        }
+        if only_if_missing && only_if_present {
+            return Err(HiveError::NetworkError("syntax error".to_string()));
+        }
+        
+        let ticket = {
+            let mut hive = self.hive.write().map_err(|_| HiveError::LockError)?;
+            
+            match kv_coordinates(&hive, key)? {
+                Some(_) if only_if_missing => return Ok(RespValue::BulkString(None)),
+                None if only_if_present => return Ok(RespValue::BulkString(None)),
+                _ => put(&mut hive, key, value)?,
+            };
+            CommitTicket::new(&hive)
+        };
+        
+        // The lock is released first so writers arriving meanwhile share the save
+        ticket.wait(&self.hive)?;
+        Ok(RespValue::ok())
+    }
+    
+    /// Set several keys at once, undoing the ones already set if one fails
+    fn mset(&self, params: &[Vec<u8>]) -> Result<RespValue, HiveError> {
+        let pairs = params.chunks(2)
+            .map(|pair| Ok((key(&pair[0])?, &pair[1])))
+            .collect::<Result<Vec<_>, HiveError>>()?;
+        
+        let ticket = {
+            let mut hive = self.hive.write().map_err(|_| HiveError::LockError)?;
+            let mut undo = Vec::with_capacity(pairs.len());
+            
+            for (key, value) in &pairs {
+                match put(&mut hive, key, value) {
+                    Ok(step) => undo.push(step),
+                    Err(e) => {
+                        rollback(&mut hive, undo);
+                        return Err(e);
+                    }
+                }
+            }
+            CommitTicket::new(&hive)
+        };
+        
+        ticket.wait(&self.hive)?;
+        Ok(RespValue::ok())
+    }
+    
+    /// Delete several keys at once, putting back the ones already deleted if one fails
+    fn del(&self, keys: &[String]) -> Result<RespValue, HiveError> {
+        let (ticket, removed) = {
+            let mut hive = self.hive.write().map_err(|_| HiveError::LockError)?;
+            let mut undo = Vec::with_capacity(keys.len());
+            
+            for key in keys {
+                let removed = kv_coordinates(&hive, key).and_then(|coordinates| match coordinates {
+                    Some(coordinates) => hive.remove_cell(coordinates).map(Some),
+                    None => Ok(None),
+                });
+                match removed {
+                    Ok(Some(cell)) => undo.push(Undo::Insert(Box::new(cell))),
+                    Ok(None) => {}
+                    Err(e) => {
+                        rollback(&mut hive, undo);
+                        return Err(e);
+                    }
+                }
+            }
+            (CommitTicket::new(&hive), undo.len())
+        };
+        
+        ticket.wait(&self.hive)?;
+        Ok(RespValue::Integer(removed as i64))
+    }
+    
+    fn scan(&self, params: &[Vec<u8>]) -> Result<RespValue, HiveError> {
+        let cursor = String::from_utf8_lossy(&params[0])
+            .parse::<usize>()
+            .map_err(|_| HiveError::NetworkError("invalid cursor".to_string()))?;
+        
+        let mut pattern: Option<&[u8]> = None;
+        let mut count = DEFAULT_SCAN_COUNT;
+        
+        let mut options = params[1..].iter();
+        while let Some(option) = options.next() {
+            let value = options.next()
+                .ok_or_else(|| HiveError::NetworkError("syntax error".to_string()))?;
+            match String::from_utf8_lossy(option).to_ascii_uppercase().as_str() {
+                "MATCH" => pattern = Some(value),
+                "COUNT" => {
+                    count = String::from_utf8_lossy(value)
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|c| *c > 0)
+                        .ok_or_else(|| HiveError::NetworkError("value is not an integer or out of range".to_string()))?;
+                }
+                _ => return Err(HiveError::NetworkError("syntax error".to_string())),
+            }
+        }
+        
+        // The cursor is a position in the sorted key list
+        let keys = self.keys()?;
+        let end = cursor.saturating_add(count).min(keys.len());
+        let page = keys.get(cursor..end).unwrap_or(&[]);
+        let next_cursor = if end < keys.len() { end } else { 0 };
+        
+        let matched = page
+            .iter()
+            .filter(|k| pattern.map_or(true, |p| glob_match(p, k.as_bytes())))
+            .map(|k| RespValue::bulk(k.clone()))
+            .collect();
+        
+        Ok(RespValue::Array(Some(vec![
+            RespValue::bulk(next_cursor.to_string()),
+            RespValue::Array(Some(matched)),
+        ])))
+    }
+    
+    /// All keys in the hive, sorted
+    fn keys(&self) -> Result<Vec<String>, HiveError> {
+        let hive = self.hive.read().map_err(|_| HiveError::LockError)?;
+        
+        let mut keys: Vec<String> = hive.cells.all_cells()
+            .iter()
+            .filter_map(|cell_arc| {
+                let cell = cell_arc.read().ok()?;
+                if cell.data.data_type == CellDataType::KeyValue {
+                    Some(cell.id.clone())
+                } else {
+                    None
+                }
+            })
+            .collect();
+        keys.sort();
+        
+        Ok(keys)
+    }
+}
+
+/// How to take back one change to a key
+enum Undo {
+    /// Put back the previous value of a key
+    Restore((i32, i32), Vec<u8>),
+    
+    /// Remove a key that did not exist
+    Remove((i32, i32)),
+    
+    /// Put back a deleted key
+    Insert(Box<Cell>),
+}
+
+impl Undo {
+    fn apply(self, hive: &mut Hive) {
+        let result = match self {
+            Undo::Restore(coordinates, value) => hive.update_cell(coordinates, value, false),
+            Undo::Remove(coordinates) => hive.remove_cell(coordinates).map(|_| ()),
+            Undo::Insert(cell) => hive.add_cell(*cell),
+        };
+        if let Err(e) = result {
+            warn!("Failed to undo a partial write: {}", e);
+        }
+    }
+}
+
+/// Take back the changes of a command that failed partway, latest first
+fn rollback(hive: &mut Hive, undo: Vec<Undo>) {
+    for step in undo.into_iter().rev() {
+        step.apply(hive);
+    }
+}
+
+/// Write the value of a key, creating its cell if needed
+fn put(hive: &mut Hive, key: &str, value: &[u8]) -> Result<Undo, HiveError> {
+    if let Some(cell_arc) = kv_cell(hive, key)? {
+        let (coordinates, previous) = {
+            let cell = cell_arc.read().map_err(|_| HiveError::LockError)?;
+            (cell.coordinates, cell.get_content()?)
+        };
+        hive.update_cell(coordinates, value.to_vec(), false)?;
+        return Ok(Undo::Restore(coordinates, previous.to_vec()));
+    }
+    
+    if hive.find_cell_by_id(key).is_some() {
+        return Err(HiveError::NetworkError(
+            "WRONGTYPE a non key-value cell already uses this ID".to_string(),
+        ));
+    }
+    
+    let coordinates = hive.free_coordinates()
+        .ok_or_else(|| HiveError::NetworkError("hive is full".to_string()))?;
+    let cell = Cell::new_with_integrity(
+        key.to_string(),
+        coordinates,
+        CellDataType::KeyValue,
+        value.to_vec(),
+        false,
+        hive.cells.integrity(),
+    )?;
+    hive.add_cell(cell)?;
+    Ok(Undo::Remove(coordinates))
+}
+
+/// Serves the Redis protocol over TCP, one thread per connection
+pub struct RespServer {
+    /// Shared command handler
+    handler: Arc<RespHandler>,
+}
+
+impl RespServer {
+    /// Create a server backed by the given hive
+    pub fn new(hive: Arc<RwLock<Hive>>) -> Self {
+        Self::from_handler(RespHandler::new(hive))
+    }
+    
+    /// Create a server around a configured handler
+    pub fn from_handler(handler: RespHandler) -> Self {
+        Self {
+            handler: Arc::new(handler),
+        }
+    }
+    
+    /// Accept connections on the given address until the listener fails
+    pub fn serve(&self, address: &str) -> Result<(), HiveError> {
+        let listener = TcpListener::bind(address)
+            .map_err(|e| HiveError::NetworkError(e.to_string()))?;
+        
+        info!("RESP compatibility listener started on {}", address);
+        
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!("Failed to accept RESP connection: {}", e);
+                    continue;
+                }
+            };
+            
+            let handler = self.handler.clone();
+            std::thread::spawn(move || {
+                let peer = stream.peer_addr().map(|a| a.to_string()).unwrap_or_default();
+                if let Err(e) = handle_connection(&handler, stream) {
+                    debug!("RESP connection {} closed: {}", peer, e);
+                }
+            });
+        }
+        
+        Ok(())
+    }
+}
+
+/// Read commands from a client and write replies until it disconnects
+fn handle_connection(handler: &RespHandler, mut stream: TcpStream) -> Result<(), HiveError> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 16 * 1024];
+    
+    loop {
+        let n = stream.read(&mut chunk)
+            .map_err(|e| HiveError::NetworkError(e.to_string()))?;
+        if n == 0 {
+            return Ok(());
+        }
+        buffer.extend_from_slice(&chunk[..n]);
+        
+        // Replies to every complete command received are sent in one write
+        let mut replies = Vec::new();
+        loop {
+            let (command, used) = match parse(&buffer) {
+                Ok(Some(parsed)) => parsed,
+                Ok(None) => break,
+                Err(e) => {
+                    RespValue::Error(format!("ERR Protocol error: {}", e)).encode(&mut replies);
+                    let _ = stream.write_all(&replies);
+                    return Err(e);
+                }
+            };
+            buffer.drain(..used);
+            
+            if is_quit(&command) {
+                RespValue::ok().encode(&mut replies);
+                let _ = stream.write_all(&replies);
+                return Ok(());
+            }
+            
+            handler.handle(command).encode(&mut replies);
+        }
+        
+        // A client that never completes a command is cut off
+        if buffer.len() > MAX_QUERY_BUFFER {
+            let e = HiveError::NetworkError("query buffer limit exceeded".to_string());
+            RespValue::Error(format!("ERR Protocol error: {}", e)).encode(&mut replies);
+            let _ = stream.write_all(&replies);
+            return Err(e);
+        }
+        
+        if !replies.is_empty() {
+            stream.write_all(&replies)
+                .map_err(|e| HiveError::NetworkError(e.to_string()))?;
+        }
+    }
+}
+
+fn is_quit(command: &RespValue) -> bool {
+    match command {
+        RespValue::Array(Some(items)) => matches!(
+            items.first(),
+            Some(RespValue::BulkString(Some(name))) if name.eq_ignore_ascii_case(b"QUIT")
+        ),
+        _ => false,
+    }
+}
+
+fn expect_args(name: &str, params: &[Vec<u8>], min: usize, max: usize) -> Result<(), HiveError> {
+    if params.len() < min || params.len() > max {
+        return Err(HiveError::NetworkError(format!(
+            "wrong number of arguments for '{}' command",
+            name.to_ascii_lowercase()
+        )));
+    }
+    Ok(())
+}
+
+fn key(raw: &[u8]) -> Result<String, HiveError> {
+    String::from_utf8(raw.to_vec())
+        .map_err(|_| HiveError::NetworkError("keys must be valid UTF-8".to_string()))
+}
+
+/// Find the key-value cell holding a key
+fn kv_cell(hive: &Hive, key: &str) -> Result<Option<Arc<RwLock<Cell>>>, HiveError> {
+    match hive.find_cell_by_id(key) {
+        Some(cell_arc) => {
+            let is_kv = cell_arc.read().map_err(|_| HiveError::LockError)?
+                .data.data_type == CellDataType::KeyValue;
+            Ok(if is_kv { Some(cell_arc) } else { None })
+        }
+        None => Ok(None),
+    }
+}
+
+fn kv_coordinates(hive: &Hive, key: &str) -> Result<Option<(i32, i32)>, HiveError> {
+    match kv_cell(hive, key)? {
+        Some(cell_arc) => {
+            let cell = cell_arc.read().map_err(|_| HiveError::LockError)?;
+            Ok(Some(cell.coordinates))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Redis-style glob matching supporting `*`, `?` and `\` escapes
+///
+/// Matching walks both strings once, going back only to the most recent
+/// `*`, so it takes at most pattern length times text length steps.
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // Pattern position after the last `*`, and the text position it matched up to
+    let mut star: Option<(usize, usize)> = None;
+    
+    while t < text.len() {
+        let width = match pattern.get(p) {
+            Some(b'*') => {
+                p += 1;
+                star = Some((p, t));
+                continue;
+            }
+            Some(b'?') => Some(1),
+            Some(b'\\') if p + 1 < pattern.len() => (pattern[p + 1] == text[t]).then_some(2),
+            Some(c) => (*c == text[t]).then_some(1),
+            None => None,
+        };
+        
+        match (width, star) {
+            (Some(width), _) => {
+                p += width;
+                t += 1;
+            }
+            (None, Some((after_star, matched))) => {
+                // Let the last `*` swallow one more byte and retry
+                p = after_star;
+                t = matched + 1;
+                star = Some((after_star, t));
+            }
+            (None, None) => return false,
+        }
+    }
+    
+    pattern[p..].iter().all(|c| *c == b'*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::mode::ServerMode;
+    use tempfile::tempdir;
+    
+    fn handler() -> RespHandler {
+        let temp_dir = tempdir().unwrap();
+        let hive = Hive::new(
+            "kv".to_string(),
+            "Key-value hive".to_string(),
+            "test-user".to_string(),
+            temp_dir.path().to_path_buf(),
+            (8, 8),
+        ).unwrap();
+        
+        RespHandler::new(Arc::new(RwLock::new(hive)))
+    }
+    
+    fn command(words: &[&str]) -> RespValue {
+        RespValue::Array(Some(words.iter().map(|w| RespValue::bulk(w.as_bytes().to_vec())).collect()))
+    }
+    
+    #[test]
+    fn test_parse_and_encode() {
+        let encoded = command(&["SET", "key", "value"]).to_bytes();
+        assert_eq!(encoded, b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n".to_vec());
+        
+        let (parsed, used) = parse(&encoded).unwrap().unwrap();
+        assert_eq!(parsed, command(&["SET", "key", "value"]));
+        assert_eq!(used, encoded.len());
+        
+        // Incomplete input waits for more data
+        assert!(parse(&encoded[..encoded.len() - 3]).unwrap().is_none());
+        
+        // Inline commands
+        let (inline, _) = parse(b"PING hello\r\n").unwrap().unwrap();
+        assert_eq!(inline, command(&["PING", "hello"]));
+    }
+    
+    #[test]
+    fn test_parse_limits() {
+        // Commands hold only bulk strings, so nested arrays are refused
+        assert!(parse(b"*1\r\n*1\r\n$1\r\na\r\n").is_err());
+        assert!(parse(b"*1\r\n:1\r\n").is_err());
+        assert!(parse(b"*2147483647\r\n").is_err());
+        
+        // Unterminated lines wait up to the inline limit, then fail
+        let line = vec![b'a'; MAX_INLINE_LEN + 1];
+        assert!(parse(&line).unwrap().is_none());
+        let line = vec![b'a'; MAX_INLINE_LEN + 2];
+        assert!(parse(&line).is_err());
+        let mut header = b"*".to_vec();
+        header.extend(vec![b'1'; MAX_INLINE_LEN + 2]);
+        assert!(parse(&header).is_err());
+    }
+    
+    #[test]
+    fn test_get_set_del() {
+        let handler = handler();
+        
+        assert_eq!(handler.handle(command(&["GET", "a"])), RespValue::BulkString(None));
+        assert_eq!(handler.handle(command(&["SET", "a", "1"])), RespValue::ok());
+        assert_eq!(handler.handle(command(&["GET", "a"])), RespValue::bulk(b"1".to_vec()));
+        assert_eq!(handler.handle(command(&["SET", "a", "2"])), RespValue::ok());
+        assert_eq!(handler.handle(command(&["GET", "a"])), RespValue::bulk(b"2".to_vec()));
+        assert_eq!(handler.handle(command(&["SET", "a", "3", "NX"])), RespValue::BulkString(None));
+        assert!(matches!(handler.handle(command(&["SET", "a", "3", "NX", "XX"])), RespValue::Error(_)));
+        assert_eq!(handler.handle(command(&["EXISTS", "a", "b"])), RespValue::Integer(1));
+        assert_eq!(handler.handle(command(&["DEL", "a", "b"])), RespValue::Integer(1));
+        assert_eq!(handler.handle(command(&["GET", "a"])), RespValue::BulkString(None));
+        
+        // Every key is checked before any is deleted
+        handler.handle(command(&["SET", "b", "1"]));
+        let del = RespValue::Array(Some(vec![RespValue::bulk(b"DEL".to_vec()), RespValue::bulk(b"b".to_vec()), RespValue::bulk(vec![0xff])]));
+        assert!(matches!(handler.handle(del), RespValue::Error(_)));
+        assert_eq!(handler.handle(command(&["EXISTS", "b"])), RespValue::Integer(1));
+    }
+    
+    #[test]
+    fn test_mset_and_mget() {
+        let handler = handler();
+        handler.handle(command(&["SET", "a", "1"]));
+        
+        assert_eq!(handler.handle(command(&["MSET", "a", "2", "b", "3"])), RespValue::ok());
+        assert_eq!(handler.handle(command(&["MGET", "a", "b", "c"])), RespValue::Array(Some(vec![
+            RespValue::bulk(b"2".to_vec()),
+            RespValue::bulk(b"3".to_vec()),
+            RespValue::BulkString(None),
+        ])));
+        assert!(matches!(handler.handle(command(&["MSET", "a", "4", "b"])), RespValue::Error(_)));
+        
+        // A key that cannot be written undoes the ones before it
+        {
+            let mut hive = handler.hive.write().unwrap();
+            let coordinates = hive.free_coordinates().unwrap();
+            hive.add_cell(Cell::new("doc".to_string(), coordinates, CellDataType::Binary, b"\x00".to_vec(), false).unwrap()).unwrap();
+        }
+        assert!(matches!(handler.handle(command(&["MSET", "a", "5", "c", "6", "doc", "7"])), RespValue::Error(_)));
+        assert_eq!(handler.handle(command(&["MGET", "a", "c"])), RespValue::Array(Some(vec![
+            RespValue::bulk(b"2".to_vec()),
+            RespValue::BulkString(None),
+        ])));
+    }
+    
+    #[test]
+    fn test_pipelined_commands() {
+        use std::net::TcpListener;
+        
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let handler = handler();
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let _ = handle_connection(&handler, stream);
+        });
+        
+        let mut client = TcpStream::connect(address).unwrap();
+        let mut pipeline = Vec::new();
+        for words in [&["SET", "a", "1"][..], &["GET", "a"], &["QUIT"]] {
+            command(words).encode(&mut pipeline);
+        }
+        client.write_all(&pipeline).unwrap();
+        
+        let mut replies = Vec::new();
+        client.read_to_end(&mut replies).unwrap();
+        assert_eq!(replies, b"+OK\r\n$1\r\n1\r\n+OK\r\n".to_vec());
+    }
+    
+    #[test]
+    fn test_scan() {
+        let handler = handler();
+        for key in ["user:1", "user:2", "order:1"] {
+            handler.handle(command(&["SET", key, "x"]));
+        }
+        
+        let reply = handler.handle(command(&["SCAN", "0", "COUNT", "2"]));
+        assert_eq!(reply, RespValue::Array(Some(vec![
+            RespValue::bulk(b"2".to_vec()),
+            RespValue::Array(Some(vec![
+                RespValue::bulk(b"order:1".to_vec()),
+                RespValue::bulk(b"user:1".to_vec()),
+            ])),
+        ])));
+        
+        let reply = handler.handle(command(&["SCAN", "0", "MATCH", "user:*", "COUNT", "10"]));
+        assert_eq!(reply, RespValue::Array(Some(vec![
+            RespValue::bulk(b"0".to_vec()),
+            RespValue::Array(Some(vec![
+                RespValue::bulk(b"user:1".to_vec()),
+                RespValue::bulk(b"user:2".to_vec()),
+            ])),
+        ])));
+    }
+    
+    #[test]
+    fn test_server_modes() {
+        let mode = Arc::new(ModeControl::default());
+        let handler = handler().with_mode(mode.clone());
+        handler.handle(command(&["SET", "a", "1"]));
+        
+        mode.set(ServerMode::ReadOnly);
+        assert!(matches!(handler.handle(command(&["SET", "a", "2"])), RespValue::Error(e) if e.starts_with("READONLY")));
+        assert_eq!(handler.handle(command(&["GET", "a"])), RespValue::bulk(b"1".to_vec()));
+        
+        mode.set(ServerMode::Maintenance);
+        assert!(matches!(handler.handle(command(&["GET", "a"])), RespValue::Error(e) if e.starts_with("LOADING")));
+        assert_eq!(handler.handle(command(&["PING"])), RespValue::SimpleString("PONG".to_string()));
+    }
+    
+    #[test]
+    fn test_unknown_command() {
+        let handler = handler();
+        
+        assert!(matches!(handler.handle(command(&["FLUSHALL"])), RespValue::Error(_)));
+        assert!(matches!(handler.handle(command(&["GET"])), RespValue::Error(_)));
+    }
+    
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match(b"user:*", b"user:42"));
+        assert!(glob_match(b"h?llo", b"hello"));
+        assert!(!glob_match(b"h?llo", b"hllo"));
+        assert!(glob_match(b"a\\*", b"a*"));
+        assert!(!glob_match(b"a\\*", b"ab"));
+        assert!(glob_match(b"*a*b*", b"xxaxxbxx"));
+        assert!(!glob_match(b"*a*b", b"xxaxxbxx"));
+        assert!(glob_match(b"**", b""));
+        
+        // Many stars against a long miss finish quickly
+        let pattern = [b"*a".repeat(32), b"b".to_vec()].concat();
+        assert!(!glob_match(&pattern, &[b'a'; 4096]));
+    }
+}
@@ -0,0 +1,909 @@
+// HiveDB Webhooks Module
+//
+// This module lets users register HTTP endpoints that are notified when
+// data changes, and optionally of hive-level events from the manager's
+// event bus. Matching events are queued and POSTed as signed JSON.
+// Failed deliveries are retried with exponential backoff at scheduled
+// times, so collecting events never waits on a slow endpoint. Webhooks
+// are saved to a file so registrations survive restarts.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use crate::core::change::{ChangeEvent, ChangeKind};
+use crate::core::codec::HmacSha256;
+use crate::core::error::HiveError;
+use crate::core::events::{EventBus, ManagerEvent};
+use crate::core::hive::{Hive, HiveManager};
+use crate::network::cdc::content_to_json;
+use crate::network::http::parse_http_url;
+use crate::utils::determinism;
+use log::{debug, info, warn};
+
+/// Header carrying the HMAC-SHA256 signature of a delivery
+pub const SIGNATURE_HEADER: &str = "X-HiveDB-Signature";
+
+/// Header carrying the timestamp that was signed along with the body
+pub const TIMESTAMP_HEADER: &str = "X-HiveDB-Timestamp";
+
+/// Most deliveries queued for one webhook before the oldest are dropped
+pub const DEFAULT_MAX_PENDING: usize = 1000;
+
+/// How often the delivery loop looks for new changes
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Selects which change events a webhook receives
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WebhookFilter {
+    /// Only events from this hive (all hives if None)
+    pub hive_id: Option<String>,
+    
+    /// Only these kinds of change (all kinds if empty)
+    pub kinds: Vec<ChangeKind>,
+    
+    /// Only cells whose ID starts with this prefix
+    pub cell_id_prefix: Option<String>,
+    
+    /// Also receive the manager's hive-level events
+    #[serde(default)]
+    pub manager_events: bool,
+}
+
+impl WebhookFilter {
+    /// Check whether an event passes this filter
+    pub fn matches(&self, event: &ChangeEvent) -> bool {
+        if let Some(hive_id) = &self.hive_id {
+            if &event.hive_id != hive_id {
+                return false;
+            }
+        }
+        
+        if !self.kinds.is_empty() && !self.kinds.contains(&event.kind) {
+            return false;
+        }
+        
+        if let Some(prefix) = &self.cell_id_prefix {
+            match &event.cell_id {
+                Some(cell_id) if cell_id.starts_with(prefix.as_str()) => {}
+                _ => return false,
+            }
+        }
+        
+        true
+    }
+    
+    /// Check whether a manager event passes this filter
+    ///
+    /// Only the hive filter applies; the others select change events.
+    pub fn matches_event(&self, event: &ManagerEvent) -> bool {
+        self.manager_events
+            && self.hive_id.as_ref().map_or(true, |hive_id| event.hive_id.as_ref() == Some(hive_id))
+    }
+}
+
+/// A registered webhook
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Webhook {
+    /// Unique identifier for this webhook
+    pub id: String,
+    
+    /// Endpoint that receives the POST requests
+    pub url: String,
+    
+    /// Which events are delivered
+    pub filter: WebhookFilter,
+    
+    /// Shared secret used to sign deliveries
+    pub secret: String,
+    
+    /// Whether deliveries are currently enabled
+    pub active: bool,
+}
+
+/// Retry behaviour for failed deliveries
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Total number of attempts per delivery
+    pub max_attempts: u32,
+    
+    /// Delay before the first retry
+    pub initial_backoff: Duration,
+    
+    /// Upper bound for the delay between retries
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before the given retry (1-based), with up to 10% jitter
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let exponent = retry.saturating_sub(1).min(16);
+        let base = self.initial_backoff
+            .saturating_mul(1 << exponent)
+            .min(self.max_backoff);
+        
+        let jitter_ms = (base.as_millis() as u64) / 10;
+        if jitter_ms == 0 {
+            return base;
+        }
+        
+        base + Duration::from_millis(rand::thread_rng().gen_range(0..=jitter_ms))
+    }
+}
+
+/// Outcome of delivering one event to one webhook
+#[derive(Debug, Clone)]
+pub struct DeliveryReport {
+    /// ID of the webhook the event was sent to
+    pub webhook_id: String,
+    
+    /// Sequence number of the delivered event
+    pub sequence: u64,
+    
+    /// Number of attempts made
+    pub attempts: u32,
+    
+    /// HTTP status of the last attempt, if a response was received
+    pub status: Option<u16>,
+    
+    /// Whether the endpoint accepted the event
+    pub delivered: bool,
+}
+
+/// Sends webhook requests over the network
+pub trait WebhookTransport: Send + Sync {
+    /// POST a body to a URL and return the HTTP status code
+    fn post(&self, url: &str, headers: &[(String, String)], body: &[u8]) -> Result<u16, HiveError>;
+}
+
+/// Minimal HTTP/1.1 transport for `http://` endpoints
+///
+/// TLS is not supported, so `https://` webhooks are refused when they are
+/// registered; use a local relay for them.
+pub struct HttpTransport {
+    /// Timeout for connecting, sending and receiving
+    timeout: Duration,
+}
+
+impl HttpTransport {
+    /// Create a transport with the given timeout
+    pub fn new(timeout: Duration) -> Self {
+        Self { timeout }
+    }
+}
+
+impl Default for HttpTransport {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(10))
+    }
+}
+
+impl WebhookTransport for HttpTransport {
+    fn post(&self, url: &str, headers: &[(String, String)], body: &[u8]) -> Result<u16, HiveError> {
+        let (host, port, path) = parse_http_url(url)?;
+        
+        let address = (host.as_str(), port)
+            .to_socket_addrs()
+            .map_err(|e| HiveError::NetworkError(e.to_string()))?
+            .next()
+            .ok_or_else(|| HiveError::NetworkError(format!("Could not resolve {}", host)))?;
+        
+        let mut stream = TcpStream::connect_timeout(&address, self.timeout)
+            .map_err(|e| HiveError::NetworkError(e.to_string()))?;
+        stream.set_read_timeout(Some(self.timeout))
+            .and_then(|_| stream.set_write_timeout(Some(self.timeout)))
+            .map_err(|e| HiveError::NetworkError(e.to_string()))?;
+        
+        let mut request = format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+            path, host, body.len()
+        );
+        for (name, value) in headers {
+            request.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        request.push_str("\r\n");
+        
+        stream.write_all(request.as_bytes())
+            .and_then(|_| stream.write_all(body))
+            .map_err(|e| HiveError::NetworkError(e.to_string()))?;
+        
+        // Only the status line is needed
+        let mut response = [0u8; 64];
+        let n = stream.read(&mut response)
+            .map_err(|e| HiveError::NetworkError(e.to_string()))?;
+        let status_line = String::from_utf8_lossy(&response[..n]);
+        
+        status_line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|code| code.parse::<u16>().ok())
+            .ok_or_else(|| HiveError::NetworkError(format!("Malformed HTTP response from {}", url)))
+    }
+}
+
+/// A delivery waiting for its next attempt
+struct PendingDelivery {
+    /// Attempts made so far
+    report: DeliveryReport,
+    
+    /// Body to POST, shared by the deliveries of one event
+    body: Arc<[u8]>,
+    
+    /// When the next attempt may be made
+    due: Instant,
+}
+
+/// Manages registered webhooks and delivers change events to them
+///
+/// `poll` and `poll_events` only queue deliveries; `deliver_due` sends
+/// them, so the network is never used while a hive is locked.
+pub struct WebhookRegistry {
+    /// Registered webhooks by ID
+    hooks: HashMap<String, Webhook>,
+    
+    /// Retry behaviour for failed deliveries
+    retry: RetryPolicy,
+    
+    /// Transport used to send requests
+    transport: Box<dyn WebhookTransport>,
+    
+    /// Last change sequence dispatched, per hive
+    positions: HashMap<String, u64>,
+    
+    /// Last manager event sequence dispatched
+    event_position: u64,
+    
+    /// Deliveries not yet finished, in the order their events were queued
+    pending: VecDeque<PendingDelivery>,
+    
+    /// Most deliveries queued per webhook
+    max_pending: usize,
+    
+    /// Deliveries dropped from a full queue, until they are reported
+    dropped: Vec<DeliveryReport>,
+    
+    /// File the webhooks are saved to after every change
+    path: Option<PathBuf>,
+}
+
+impl WebhookRegistry {
+    /// Create a registry that delivers over plain HTTP
+    pub fn new() -> Self {
+        Self::with_transport(Box::new(HttpTransport::default()), RetryPolicy::default())
+    }
+    
+    /// Create a registry with a custom transport and retry policy
+    pub fn with_transport(transport: Box<dyn WebhookTransport>, retry: RetryPolicy) -> Self {
+        Self {
+            hooks: HashMap::new(),
+            retry,
+            transport,
+            positions: HashMap::new(),
+            event_position: 0,
+            pending: VecDeque::new(),
+            max_pending: DEFAULT_MAX_PENDING,
+            dropped: Vec::new(),
+            path: None,
+        }
+    }
+    
+    /// Open a registry persisted at the given path, creating it if needed
+    pub fn open(path: PathBuf) -> Result<Self, HiveError> {
+        let mut registry = Self::new();
+        if path.exists() {
+            let data = std::fs::read(&path)
+                .map_err(|e| HiveError::IoError(e.to_string()))?;
+            let hooks: Vec<Webhook> = serde_json::from_slice(&data)
+                .map_err(|e| HiveError::DeserializationError(e.to_string()))?;
+            registry.hooks = hooks.into_iter().map(|hook| (hook.id.clone(), hook)).collect();
+        }
+        
+        registry.path = Some(path);
+        Ok(registry)
+    }
+    
+    /// Queue at most the given number of deliveries per webhook
+    pub fn with_max_pending(mut self, max_pending: usize) -> Self {
+        self.max_pending = max_pending.max(1);
+        self
+    }
+    
+    /// Register a new webhook and return its ID
+    pub fn register(
+        &mut self,
+        url: String,
+        filter: WebhookFilter,
+        secret: String,
+    ) -> Result<String, HiveError> {
+        if url.starts_with("https://") {
+            return Err(HiveError::NetworkError(format!(
+                "https:// webhooks are not supported; deliver through a local TLS relay: {}",
+                url
+            )));
+        }
+        parse_http_url(&url)?;
+        
+        let id = generate_webhook_id();
+        self.hooks.insert(id.clone(), Webhook {
+            id: id.clone(),
+            url: url.clone(),
+            filter,
+            secret,
+            active: true,
+        });
+        
+        self.persist()?;
+        info!("Registered webhook {} for {}", id, url);
+        
+        Ok(id)
+    }
+    
+    /// Remove a webhook
+    pub fn unregister(&mut self, id: &str) -> Result<Webhook, HiveError> {
+        let hook = self.hooks.remove(id)
+            .ok_or_else(|| HiveError::WebhookNotFound(id.to_string()))?;
+        self.persist()?;
+        Ok(hook)
+    }
+    
+    /// Enable or disable deliveries to a webhook
+    pub fn set_active(&mut self, id: &str, active: bool) -> Result<(), HiveError> {
+        let hook = self.hooks.get_mut(id)
+            .ok_or_else(|| HiveError::WebhookNotFound(id.to_string()))?;
+        hook.active = active;
+        self.persist()
+    }
+    
+    /// Get a webhook by ID
+    pub fn get(&self, id: &str) -> Option<&Webhook> {
+        self.hooks.get(id)
+    }
+    
+    /// List all registered webhooks
+    pub fn list(&self) -> Vec<&Webhook> {
+        self.hooks.values().collect()
+    }
+    
+    /// Queue one event for every active webhook whose filter matches it
+    ///
+    /// Returns the number of deliveries queued.
+    pub fn dispatch(&mut self, event: &ChangeEvent) -> Result<usize, HiveError> {
+        let body = serde_json::to_vec(&event_payload(event))
+            .map_err(|e| HiveError::SerializationError(e.to_string()))?;
+        
+        Ok(self.enqueue(event.sequence, body, |filter| filter.matches(event)))
+    }
+    
+    /// Queue all changes recorded in a hive since the last call
+    ///
+    /// Nothing is sent, so this is cheap to call while holding the hive's
+    /// read lock; `deliver_due` sends the deliveries once it is released.
+    /// Returns the number of deliveries queued.
+    pub fn poll(&mut self, hive: &Hive) -> Result<usize, HiveError> {
+        let position = self.positions.get(&hive.id).copied().unwrap_or(0);
+        let mut queued = 0;
+        
+        for event in hive.changes.iter_since(position)? {
+            queued += self.dispatch(event)?;
+            self.positions.insert(hive.id.clone(), event.sequence);
+        }
+        
+        Ok(queued)
+    }
+    
+    /// Queue one manager event for every active webhook that asked for them
+    ///
+    /// Returns the number of deliveries queued.
+    pub fn dispatch_event(&mut self, event: &ManagerEvent) -> Result<usize, HiveError> {
+        let body = serde_json::to_vec(event)
+            .map_err(|e| HiveError::SerializationError(e.to_string()))?;
+        
+        Ok(self.enqueue(event.sequence, body, |filter| filter.matches_event(event)))
+    }
+    
+    /// Queue all manager events published since the last call
+    ///
+    /// Returns the number of deliveries queued.
+    pub fn poll_events(&mut self, events: &EventBus) -> Result<usize, HiveError> {
+        let mut queued = 0;
+        for event in events.since(self.event_position)? {
+            queued += self.dispatch_event(&event)?;
+            self.event_position = event.sequence;
+        }
+        
+        Ok(queued)
+    }
+    
+    /// Queue the changes of every hive of a manager, and its hive-level events
+    ///
+    /// A hive whose change log was truncated past the last change queued is
+    /// resumed from its latest change, with a warning.
+    pub fn poll_manager(&mut self, manager: &HiveManager) -> Result<usize, HiveError> {
+        let mut queued = self.poll_events(manager.events())?;
+        let hives = manager.list_hives();
+        self.positions.retain(|id, _| hives.iter().any(|(hive_id, _)| hive_id == id));
+        
+        for (id, _) in hives {
+            let Some(hive_arc) = manager.get_hive(&id) else { continue };
+            let hive = hive_arc.read().map_err(|_| HiveError::LockError)?;
+            match self.poll(&hive) {
+                Ok(count) => queued += count,
+                Err(HiveError::ChangeLogTruncated(sequence)) => {
+                    warn!(
+                        "Webhooks missed changes of hive {} after sequence {}; resuming from {}",
+                        hive.id, sequence, hive.changes.last_sequence()
+                    );
+                    self.positions.insert(id, hive.changes.last_sequence());
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        
+        Ok(queued)
+    }
+    
+    /// Skip the changes and hive-level events a manager has already recorded
+    pub fn skip_recorded(&mut self, manager: &HiveManager) -> Result<(), HiveError> {
+        self.event_position = manager.events().last_sequence();
+        for (id, _) in manager.list_hives() {
+            if let Some(hive_arc) = manager.get_hive(&id) {
+                let hive = hive_arc.read().map_err(|_| HiveError::LockError)?;
+                self.positions.insert(id, hive.changes.last_sequence());
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Make the attempts that are due, and report the deliveries that finished
+    ///
+    /// Each due delivery gets one attempt. One that failed in a way worth
+    /// retrying is scheduled again after the retry policy's backoff, rather
+    /// than waited for, so this never sleeps. Deliveries to a webhook are
+    /// made in the order of their events: later ones wait behind a retry.
+    /// Deliveries dropped because their webhook's queue was full are
+    /// reported as undelivered.
+    pub fn deliver_due(&mut self) -> Vec<DeliveryReport> {
+        let now = Instant::now();
+        let mut waiting: HashSet<String> = HashSet::new();
+        let mut reports = std::mem::take(&mut self.dropped);
+        let mut index = 0;
+        
+        while index < self.pending.len() {
+            let delivery = &mut self.pending[index];
+            if delivery.due > now || waiting.contains(&delivery.report.webhook_id) {
+                waiting.insert(delivery.report.webhook_id.clone());
+                index += 1;
+                continue;
+            }
+            
+            let Some(hook) = self.hooks.get(&delivery.report.webhook_id).filter(|hook| hook.active) else {
+                // The webhook was removed or disabled after the event was queued
+                self.pending.remove(index);
+                continue;
+            };
+            
+            let finished = attempt(self.transport.as_ref(), hook, &mut delivery.report, &delivery.body);
+            if finished || delivery.report.attempts >= self.retry.max_attempts {
+                if let Some(delivery) = self.pending.remove(index) {
+                    if !delivery.report.delivered {
+                        warn!(
+                            "Giving up on event {} for webhook {} after {} attempts",
+                            delivery.report.sequence, delivery.report.webhook_id, delivery.report.attempts
+                        );
+                    }
+                    reports.push(delivery.report);
+                }
+                continue;
+            }
+            
+            delivery.due = now + self.retry.backoff(delivery.report.attempts);
+            waiting.insert(delivery.report.webhook_id.clone());
+            index += 1;
+        }
+        
+        reports
+    }
+    
+    /// Get when the next queued delivery may be attempted, or None if none are queued
+    pub fn next_due(&self) -> Option<Instant> {
+        let mut seen = HashSet::new();
+        self.pending.iter()
+            // Only the oldest delivery to each webhook can be attempted
+            .filter(|delivery| seen.insert(delivery.report.webhook_id.as_str()))
+            .map(|delivery| delivery.due)
+            .min()
+    }
+    
+    /// Queue a body for the active webhooks whose filters accept it
+    ///
+    /// When a webhook already has the most deliveries queued, its oldest
+    /// delivery is dropped to make room.
+    fn enqueue(&mut self, sequence: u64, body: Vec<u8>, accepts: impl Fn(&WebhookFilter) -> bool) -> usize {
+        let body: Arc<[u8]> = body.into();
+        let now = Instant::now();
+        
+        let deliveries: Vec<PendingDelivery> = self.hooks.values()
+            .filter(|hook| hook.active && accepts(&hook.filter))
+            .map(|hook| PendingDelivery {
+                report: DeliveryReport {
+                    webhook_id: hook.id.clone(),
+                    sequence,
+                    attempts: 0,
+                    status: None,
+                    delivered: false,
+                },
+                body: body.clone(),
+                due: now,
+            })
+            .collect();
+        
+        let queued = deliveries.len();
+        for delivery in deliveries {
+            let webhook_id = delivery.report.webhook_id.as_str();
+            let mut queued_for_hook = self.pending.iter()
+                .enumerate()
+                .filter(|(_, pending)| pending.report.webhook_id == webhook_id);
+            if let Some((oldest, _)) = queued_for_hook.next() {
+                if queued_for_hook.count() + 1 >= self.max_pending {
+                    if let Some(dropped) = self.pending.remove(oldest) {
+                        warn!(
+                            "Delivery queue of webhook {} is full; dropping event {}",
+                            webhook_id, dropped.report.sequence
+                        );
+                        self.dropped.push(dropped.report);
+                    }
+                }
+            }
+            self.pending.push_back(delivery);
+        }
+        queued
+    }
+    
+    /// Save the webhooks to the registry's file, if it has one
+    fn persist(&self) -> Result<(), HiveError> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        
+        let data = serde_json::to_vec_pretty(&self.list())
+            .map_err(|e| HiveError::SerializationError(e.to_string()))?;
+        
+        // Write to a temporary file first so a crash never leaves a partial registry
+        let temp_path = path.with_extension("tmp");
+        std::fs::write(&temp_path, data)
+            .and_then(|_| std::fs::rename(&temp_path, path))
+            .map_err(|e| HiveError::IoError(e.to_string()))
+    }
+}
+
+/// Queue the changes of a manager's hives and deliver them until `stop` is set
+///
+/// Changes recorded before the loop starts are skipped, so a restart does
+/// not deliver the retained change logs again. The registry is locked for
+/// one pass at a time, so webhooks can be managed while this runs.
+pub fn deliver_changes(registry: &RwLock<WebhookRegistry>, manager: &RwLock<HiveManager>, stop: &AtomicBool) {
+    let skipped = manager.read().map_err(|_| HiveError::LockError).and_then(|manager| {
+        registry.write().map_err(|_| HiveError::LockError)?.skip_recorded(&manager)
+    });
+    if let Err(e) = skipped {
+        warn!("Failed to skip recorded changes: {}", e);
+    }
+    
+    while !stop.load(Ordering::Relaxed) {
+        let next_due = manager.read().map_err(|_| HiveError::LockError).and_then(|manager| {
+            let mut registry = registry.write().map_err(|_| HiveError::LockError)?;
+            registry.poll_manager(&manager)?;
+            drop(manager);
+            
+            registry.deliver_due();
+            Ok(registry.next_due())
+        });
+        
+        let pause = match next_due {
+            Ok(next_due) => next_due.map_or(POLL_INTERVAL, |due| {
+                due.saturating_duration_since(Instant::now()).min(POLL_INTERVAL)
+            }),
+            Err(e) => {
+                warn!("Failed to deliver webhooks: {}", e);
+                POLL_INTERVAL
+            }
+        };
+        std::thread::sleep(pause);
+    }
+    
+    info!("Webhook delivery stopped");
+}
+
+/// Make one attempt at a delivery, and return whether it is finished
+///
+/// A delivery is finished once the endpoint accepts it, or answers with a
+/// client error that retrying will not fix.
+fn attempt(transport: &dyn WebhookTransport, hook: &Webhook, report: &mut DeliveryReport, body: &[u8]) -> bool {
+    report.attempts += 1;
+    
+    let headers = signature_headers(&hook.secret, body);
+    match transport.post(&hook.url, &headers, body) {
+        Ok(status) => {
+            report.status = Some(status);
+            if (200..300).contains(&status) {
+                report.delivered = true;
+                debug!("Delivered event {} to webhook {}", report.sequence, hook.id);
+                return true;
+            }
+            // Client errors will not succeed on retry, except timeouts and throttling
+            (400..500).contains(&status) && status != 408 && status != 429
+        }
+        Err(e) => {
+            debug!("Webhook {} attempt {} failed: {}", hook.id, report.attempts, e);
+            false
+        }
+    }
+}
+
+impl Default for WebhookRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Compute the signature headers for a delivery
+///
+/// The signature is HMAC-SHA256 over "<timestamp>.<body>", hex-encoded.
+pub fn signature_headers(secret: &str, body: &[u8]) -> Vec<(String, String)> {
+    let timestamp = determinism::unix_time().to_string();
+    
+    vec![
+        (TIMESTAMP_HEADER.to_string(), timestamp.clone()),
+        (SIGNATURE_HEADER.to_string(), format!("sha256={}", sign_payload(secret, &timestamp, body))),
+    ]
+}
+
+/// Sign a payload with the webhook secret
+pub fn sign_payload(secret: &str, timestamp: &str, body: &[u8]) -> String {
+    let mut context = HmacSha256::new(secret.as_bytes());
+    context.update(timestamp.as_bytes());
+    context.update(b".");
+    context.update(body);
+    
+    hex::encode(context.finish())
+}
+
+/// Build the JSON body delivered for an event
+fn event_payload(event: &ChangeEvent) -> serde_json::Value {
+    json!({
+        "sequence": event.sequence,
+        "hive_id": event.hive_id,
+        "kind": event.kind,
+        "cell_id": event.cell_id,
+        "coordinates": event.coordinates,
+        "timestamp": event.timestamp,
+        "document": event.content.as_deref().map(content_to_json),
+    })
+}
+
+/// Generate a unique ID for a webhook
+fn generate_webhook_id() -> String {
+    let mut rng = rand::thread_rng();
+    let random_bytes: Vec<u8> = (0..8).map(|_| rng.gen::<u8>()).collect();
+    
+    format!("hook-{}", hex::encode(random_bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::events::EventKind;
+    use std::sync::{Arc, Mutex};
+    
+    /// Transport that replays canned status codes and records requests
+    struct MockTransport {
+        statuses: Mutex<Vec<u16>>,
+        requests: Arc<Mutex<Vec<(String, Vec<(String, String)>)>>>,
+    }
+    
+    impl WebhookTransport for MockTransport {
+        fn post(&self, url: &str, headers: &[(String, String)], _body: &[u8]) -> Result<u16, HiveError> {
+            self.requests.lock().unwrap().push((url.to_string(), headers.to_vec()));
+            let mut statuses = self.statuses.lock().unwrap();
+            Ok(if statuses.is_empty() { 200 } else { statuses.remove(0) })
+        }
+    }
+    
+    fn registry(statuses: Vec<u16>) -> (WebhookRegistry, Arc<Mutex<Vec<(String, Vec<(String, String)>)>>>) {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let transport = MockTransport {
+            statuses: Mutex::new(statuses),
+            requests: requests.clone(),
+        };
+        let retry = RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(0),
+            max_backoff: Duration::from_millis(0),
+        };
+        
+        (WebhookRegistry::with_transport(Box::new(transport), retry), requests)
+    }
+    
+    /// Make attempts until no deliveries are queued
+    fn deliver_all(registry: &mut WebhookRegistry) -> Vec<DeliveryReport> {
+        let mut reports = Vec::new();
+        while registry.next_due().is_some() {
+            reports.extend(registry.deliver_due());
+        }
+        reports
+    }
+    
+    fn event(kind: ChangeKind, cell_id: &str) -> ChangeEvent {
+        ChangeEvent {
+            sequence: 1,
+            hive_id: "hive-1".to_string(),
+            kind,
+            cell_id: Some(cell_id.to_string()),
+            coordinates: Some((0, 0)),
+            content: Some(b"{\"n\": 1}".to_vec()),
+            timestamp: 0,
+        }
+    }
+    
+    #[test]
+    fn test_filter_matching() {
+        let filter = WebhookFilter {
+            hive_id: Some("hive-1".to_string()),
+            kinds: vec![ChangeKind::CellInserted],
+            cell_id_prefix: Some("user-".to_string()),
+            manager_events: false,
+        };
+        
+        assert!(filter.matches(&event(ChangeKind::CellInserted, "user-1")));
+        assert!(!filter.matches(&event(ChangeKind::CellRemoved, "user-1")));
+        assert!(!filter.matches(&event(ChangeKind::CellInserted, "order-1")));
+        assert!(WebhookFilter::default().matches(&event(ChangeKind::CellRemoved, "order-1")));
+    }
+    
+    #[test]
+    fn test_dispatch_retries_server_errors() {
+        let (mut registry, requests) = registry(vec![500, 503]);
+        registry.register("http://localhost:9000/hook".to_string(), WebhookFilter::default(), "secret".to_string()).unwrap();
+        
+        assert_eq!(registry.dispatch(&event(ChangeKind::CellInserted, "a")).unwrap(), 1);
+        let reports = deliver_all(&mut registry);
+        
+        assert_eq!(reports.len(), 1);
+        assert!(reports[0].delivered);
+        assert_eq!(reports[0].attempts, 3);
+        assert_eq!(requests.lock().unwrap().len(), 3);
+    }
+    
+    #[test]
+    fn test_dispatch_does_not_retry_client_errors() {
+        let (mut registry, requests) = registry(vec![404]);
+        registry.register("http://localhost:9000/hook".to_string(), WebhookFilter::default(), "secret".to_string()).unwrap();
+        
+        registry.dispatch(&event(ChangeKind::CellInserted, "a")).unwrap();
+        let reports = deliver_all(&mut registry);
+        
+        assert!(!reports[0].delivered);
+        assert_eq!(reports[0].status, Some(404));
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+    
+    #[test]
+    fn test_deliveries_are_signed() {
+        let (mut registry, requests) = registry(vec![]);
+        registry.register("http://localhost:9000/hook".to_string(), WebhookFilter::default(), "secret".to_string()).unwrap();
+        registry.dispatch(&event(ChangeKind::CellInserted, "a")).unwrap();
+        deliver_all(&mut registry);
+        
+        let requests = requests.lock().unwrap();
+        let headers = &requests[0].1;
+        let timestamp = &headers.iter().find(|(name, _)| name == TIMESTAMP_HEADER).unwrap().1;
+        let signature = &headers.iter().find(|(name, _)| name == SIGNATURE_HEADER).unwrap().1;
+        
+        let body = serde_json::to_vec(&event_payload(&event(ChangeKind::CellInserted, "a"))).unwrap();
+        assert_eq!(signature, &format!("sha256={}", sign_payload("secret", timestamp, &body)));
+    }
+    
+    #[test]
+    fn test_manager_events() {
+        let (mut registry, requests) = registry(vec![]);
+        let filter = WebhookFilter {
+            hive_id: Some("hive-1".to_string()),
+            manager_events: true,
+            ..WebhookFilter::default()
+        };
+        registry.register("http://localhost:9000/events".to_string(), filter, "secret".to_string()).unwrap();
+        registry.register("http://localhost:9000/changes".to_string(), WebhookFilter::default(), "secret".to_string()).unwrap();
+        
+        let bus = EventBus::default();
+        bus.publish(Some("hive-1"), EventKind::SchemaChanged { version: 2 });
+        bus.publish(Some("hive-2"), EventKind::SchemaChanged { version: 5 });
+        
+        assert_eq!(registry.poll_events(&bus).unwrap(), 1);
+        assert_eq!(registry.poll_events(&bus).unwrap(), 0);
+        assert_eq!(deliver_all(&mut registry).len(), 1);
+        
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].0, "http://localhost:9000/events");
+    }
+    
+    #[test]
+    fn test_retries_are_scheduled() {
+        let (mut registry, requests) = registry(vec![503]);
+        registry.retry.initial_backoff = Duration::from_secs(60);
+        registry.retry.max_backoff = Duration::from_secs(60);
+        registry.register("http://localhost:9000/hook".to_string(), WebhookFilter::default(), "secret".to_string()).unwrap();
+        registry.dispatch(&event(ChangeKind::CellInserted, "a")).unwrap();
+        registry.dispatch(&event(ChangeKind::CellUpdated, "a")).unwrap();
+        
+        // The failed delivery is retried in a minute, without waiting for
+        // it here, and the later event stays queued behind it
+        let started = Instant::now();
+        assert!(registry.deliver_due().is_empty());
+        assert!(started.elapsed() < Duration::from_secs(30));
+        assert!(registry.next_due().unwrap() > Instant::now() + Duration::from_secs(30));
+        assert!(registry.deliver_due().is_empty());
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+    
+    #[test]
+    fn test_full_queue_drops_oldest() {
+        let (registry, requests) = registry(vec![]);
+        let mut registry = registry.with_max_pending(2);
+        registry.register("http://localhost:9000/hook".to_string(), WebhookFilter::default(), "secret".to_string()).unwrap();
+        
+        for sequence in 1..=3 {
+            let event = ChangeEvent { sequence, ..event(ChangeKind::CellInserted, "a") };
+            registry.dispatch(&event).unwrap();
+        }
+        
+        let reports = deliver_all(&mut registry);
+        assert_eq!(reports.len(), 3);
+        assert_eq!((reports[0].sequence, reports[0].delivered), (1, false));
+        assert!(reports[1..].iter().all(|report| report.delivered));
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+    
+    #[test]
+    fn test_webhooks_are_persisted() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("webhooks.json");
+        
+        let mut registry = WebhookRegistry::open(path.clone()).unwrap();
+        let id = registry.register("http://localhost:9000/hook".to_string(), WebhookFilter::default(), "secret".to_string()).unwrap();
+        registry.set_active(&id, false).unwrap();
+        
+        let registry = WebhookRegistry::open(path).unwrap();
+        assert_eq!(registry.get(&id).unwrap().url, "http://localhost:9000/hook");
+        assert!(!registry.get(&id).unwrap().active);
+        assert!(matches!(WebhookRegistry::new().unregister(&id), Err(HiveError::WebhookNotFound(_))));
+    }
+    
+    #[test]
+    fn test_register_rejects_unsupported_urls() {
+        let (mut registry, _) = registry(vec![]);
+        
+        assert!(registry.register("https://example.com/hook".to_string(), WebhookFilter::default(), "s".to_string()).is_err());
+        assert!(registry.register("ftp://example.com".to_string(), WebhookFilter::default(), "s".to_string()).is_err());
+        assert_eq!(parse_http_url("http://example.com:8080/a/b").unwrap(), ("example.com".to_string(), 8080, "/a/b".to_string()));
+        assert_eq!(parse_http_url("http://example.com").unwrap(), ("example.com".to_string(), 80, "/".to_string()));
+    }
+}
@@ -0,0 +1,126 @@
+// HiveDB Allowlist Module
+//
+// This module keeps the statements a server accepts in prepared-only mode.
+// Each statement is registered under a name, with $1, $2... standing for
+// its parameters; clients then send `EXECUTE name(value, ...)` instead of
+// SQL text, which is useful when HiveDB is exposed to edge clients that
+// should only ever run a known set of queries.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::core::error::HiveError;
+use crate::core::sql;
+
+/// Named statements that restricted clients may run
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct QueryAllowlist {
+    /// SQL of each statement by name
+    statements: BTreeMap<String, String>,
+    
+    /// Roles that may still send any SQL
+    #[serde(default)]
+    unrestricted_roles: Vec<String>,
+}
+
+impl QueryAllowlist {
+    /// Create an allowlist without statements
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Read an allowlist from a JSON file
+    ///
+    /// The file holds a `statements` object mapping names to SQL, and
+    /// optionally the `unrestricted_roles` array.
+    pub fn load(path: &Path) -> Result<Self, HiveError> {
+        let data = std::fs::read(path)
+            .map_err(|e| HiveError::IoError(format!("{}: {}", path.display(), e)))?;
+        let loaded: Self = serde_json::from_slice(&data)
+            .map_err(|e| HiveError::DeserializationError(e.to_string()))?;
+        
+        let mut allowlist = Self {
+            statements: BTreeMap::new(),
+            unrestricted_roles: loaded.unrestricted_roles,
+        };
+        for (name, statement) in loaded.statements {
+            allowlist.register(&name, &statement)?;
+        }
+        Ok(allowlist)
+    }
+    
+    /// Register a statement under a name, replacing any statement of that name
+    ///
+    /// Statements without parameters are parsed here, so their syntax
+    /// errors show up before a client runs them.
+    pub fn register(&mut self, name: &str, statement: &str) -> Result<(), HiveError> {
+        let first_word = statement.split_whitespace().next().unwrap_or_default();
+        if first_word.eq_ignore_ascii_case("EXECUTE") {
+            return Err(HiveError::QueryError(format!("statement '{}' cannot itself be an EXECUTE", name)));
+        }
+        if sql::parameter_count(statement) == 0 {
+            sql::parse_statement(statement)?;
+        }
+        
+        self.statements.insert(name.to_string(), statement.trim().to_string());
+        Ok(())
+    }
+    
+    /// Let a role send any SQL
+    pub fn unrestricted_for(mut self, role: &str) -> Self {
+        self.unrestricted_roles.push(role.to_string());
+        self
+    }
+    
+    /// Get the names of the registered statements
+    pub fn names(&self) -> Vec<&str> {
+        self.statements.keys().map(String::as_str).collect()
+    }
+    
+    /// Check whether a client holding the given roles may only run registered statements
+    pub fn is_restricted(&self, roles: &[String]) -> bool {
+        !self.unrestricted_roles.iter().any(|role| roles.contains(role))
+    }
+    
+    /// Get the SQL of a registered statement with its parameters bound
+    pub fn bind(&self, name: &str, params: &[Value]) -> Result<String, HiveError> {
+        let statement = self.statements.get(name)
+            .ok_or_else(|| HiveError::AuthorizationError(format!("statement '{}' is not on the query allowlist", name)))?;
+        
+        let expected = sql::parameter_count(statement);
+        if params.len() != expected {
+            return Err(HiveError::QueryError(format!(
+                "statement '{}' takes {} parameters, {} given",
+                name,
+                expected,
+                params.len()
+            )));
+        }
+        sql::bind_parameters(statement, params)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_allowlist() {
+        let mut allowlist = QueryAllowlist::new().unrestricted_for("operator");
+        allowlist.register("by_user", "SELECT * FROM orders WHERE user = $1 LIMIT $2").unwrap();
+        allowlist.register("count", "SELECT COUNT(*) FROM orders").unwrap();
+        assert!(allowlist.register("broken", "SELECT FROM").is_err());
+        assert!(allowlist.register("nested", "EXECUTE count").is_err());
+        assert_eq!(allowlist.names(), vec!["by_user", "count"]);
+        
+        let bound = allowlist.bind("by_user", &[Value::from("amira"), Value::from(5)]).unwrap();
+        assert_eq!(bound, "SELECT * FROM orders WHERE user = 'amira' LIMIT 5");
+        assert!(allowlist.bind("by_user", &[Value::from("amira")]).is_err());
+        assert!(matches!(allowlist.bind("drop_all", &[]), Err(HiveError::AuthorizationError(_))));
+        
+        assert!(allowlist.is_restricted(&[]));
+        assert!(allowlist.is_restricted(&["analyst".to_string()]));
+        assert!(!allowlist.is_restricted(&["operator".to_string()]));
+    }
+}
@@ -0,0 +1,376 @@
+// HiveDB API Keys Module
+//
+// This module manages long-lived API keys for non-interactive services.
+// A key is shown in full only once, when it is created or rotated; the
+// store keeps a SHA-256 hash of its secret.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use crate::core::codec;
+use crate::core::error::HiveError;
+use crate::security::{now, Access};
+use log::info;
+
+/// Prefix of every API key
+const KEY_PREFIX: &str = "hdb_";
+
+/// Number of random bytes in a key secret
+const SECRET_LEN: usize = 32;
+
+/// What an API key may access
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApiKeyScope {
+    /// Names of the hives the key may access (empty for all hives)
+    pub hives: Vec<String>,
+    
+    /// Highest access level granted
+    pub access: Access,
+}
+
+impl ApiKeyScope {
+    /// Create a scope covering every hive
+    pub fn all_hives(access: Access) -> Self {
+        Self {
+            hives: Vec::new(),
+            access,
+        }
+    }
+    
+    /// Create a scope limited to the given hives
+    pub fn hives(hives: Vec<String>, access: Access) -> Self {
+        Self { hives, access }
+    }
+    
+    /// Check whether the scope allows an access level, optionally on a hive
+    pub fn allows(&self, hive: Option<&str>, access: Access) -> bool {
+        if access > self.access {
+            return false;
+        }
+        
+        match hive {
+            Some(hive) => self.hives.is_empty() || self.hives.iter().any(|h| h == hive),
+            None => true,
+        }
+    }
+}
+
+/// A stored API key (without its secret)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKey {
+    /// Public key ID, embedded in the key itself
+    pub id: String,
+    
+    /// Human-readable name (e.g. the service using the key)
+    pub name: String,
+    
+    /// What the key may access
+    pub scope: ApiKeyScope,
+    
+    /// Hex-encoded SHA-256 hash of the current secret
+    secret_hash: String,
+    
+    /// Hash of the secret replaced by the last rotation
+    previous_secret_hash: Option<String>,
+    
+    /// When the previous secret stops being accepted
+    previous_valid_until: Option<u64>,
+    
+    /// When the key was created (seconds since the UNIX epoch)
+    pub created_at: u64,
+    
+    /// When the key expires, if ever
+    pub expires_at: Option<u64>,
+    
+    /// When the key was last rotated
+    pub rotated_at: Option<u64>,
+    
+    /// Whether the key was revoked
+    pub revoked: bool,
+}
+
+/// Store of API keys, optionally persisted to a JSON file
+#[derive(Debug, Default)]
+pub struct ApiKeyStore {
+    /// Keys by ID
+    keys: HashMap<String, ApiKey>,
+    
+    /// File the store is saved to after every change
+    path: Option<PathBuf>,
+}
+
+impl ApiKeyStore {
+    /// Create an empty in-memory store
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Open a store persisted at the given path, creating it if needed
+    pub fn open(path: PathBuf) -> Result<Self, HiveError> {
+        let keys = if path.exists() {
+            let data = std::fs::read(&path)
+                .map_err(|e| HiveError::IoError(e.to_string()))?;
+            let keys: Vec<ApiKey> = serde_json::from_slice(&data)
+                .map_err(|e| HiveError::DeserializationError(e.to_string()))?;
+            keys.into_iter().map(|key| (key.id.clone(), key)).collect()
+        } else {
+            HashMap::new()
+        };
+        
+        Ok(Self {
+            keys,
+            path: Some(path),
+        })
+    }
+    
+    /// Create a key and return it with its full token
+    ///
+    /// The token cannot be recovered later.
+    pub fn create(
+        &mut self,
+        name: &str,
+        scope: ApiKeyScope,
+        ttl: Option<Duration>,
+    ) -> Result<(ApiKey, String), HiveError> {
+        let now = now()?;
+        let id = generate_key_id();
+        let secret = generate_secret();
+        
+        let key = ApiKey {
+            id: id.clone(),
+            name: name.to_string(),
+            scope,
+            secret_hash: hash_secret(&secret),
+            previous_secret_hash: None,
+            previous_valid_until: None,
+            created_at: now,
+            expires_at: ttl.map(|ttl| now + ttl.as_secs()),
+            rotated_at: None,
+            revoked: false,
+        };
+        
+        self.keys.insert(id.clone(), key.clone());
+        self.persist()?;
+        info!("Created API key '{}' ({})", name, id);
+        
+        Ok((key, format_token(&id, &secret)))
+    }
+    
+    /// Check a token and return the key it belongs to
+    pub fn authenticate(&self, token: &str) -> Result<&ApiKey, HiveError> {
+        let invalid = || HiveError::AuthenticationError("Invalid API key".to_string());
+        
+        let (id, secret) = parse_token(token).ok_or_else(invalid)?;
+        let key = self.keys.get(id).ok_or_else(invalid)?;
+        let now = now()?;
+        
+        let hash = hash_secret(secret);
+        let current = codec::constant_time_eq(hash.as_bytes(), key.secret_hash.as_bytes());
+        let previous = match (&key.previous_secret_hash, key.previous_valid_until) {
+            (Some(previous), Some(until)) if now < until => {
+                codec::constant_time_eq(hash.as_bytes(), previous.as_bytes())
+            }
+            _ => false,
+        };
+        
+        if !current && !previous {
+            return Err(invalid());
+        }
+        if key.revoked {
+            return Err(HiveError::AuthenticationError("API key has been revoked".to_string()));
+        }
+        if key.expires_at.map_or(false, |expires_at| now >= expires_at) {
+            return Err(HiveError::AuthenticationError("API key has expired".to_string()));
+        }
+        
+        Ok(key)
+    }
+    
+    /// Check a token and that its scope allows the requested access
+    pub fn authorize(&self, token: &str, hive: Option<&str>, access: Access) -> Result<&ApiKey, HiveError> {
+        let key = self.authenticate(token)?;
+        
+        if !key.scope.allows(hive, access) {
+            return Err(HiveError::AuthorizationError(format!(
+                "API key '{}' does not grant {} access{}",
+                key.name,
+                access.as_str(),
+                hive.map(|hive| format!(" to hive '{}'", hive)).unwrap_or_default()
+            )));
+        }
+        
+        Ok(key)
+    }
+    
+    /// Replace the secret of a key and return the new token
+    ///
+    /// The old secret keeps working for the grace period so that clients
+    /// can be updated without downtime.
+    pub fn rotate(&mut self, id: &str, grace: Duration) -> Result<String, HiveError> {
+        let now = now()?;
+        let secret = generate_secret();
+        
+        let key = self.keys.get_mut(id)
+            .ok_or_else(|| HiveError::ApiKeyNotFound(id.to_string()))?;
+        if key.revoked {
+            return Err(HiveError::AuthenticationError("API key has been revoked".to_string()));
+        }
+        
+        let previous = std::mem::replace(&mut key.secret_hash, hash_secret(&secret));
+        if grace.as_secs() > 0 {
+            key.previous_secret_hash = Some(previous);
+            key.previous_valid_until = Some(now + grace.as_secs());
+        } else {
+            key.previous_secret_hash = None;
+            key.previous_valid_until = None;
+        }
+        key.rotated_at = Some(now);
+        
+        self.persist()?;
+        info!("Rotated API key {}", id);
+        
+        Ok(format_token(id, &secret))
+    }
+    
+    /// Revoke a key; it can no longer be used or rotated
+    pub fn revoke(&mut self, id: &str) -> Result<(), HiveError> {
+        let key = self.keys.get_mut(id)
+            .ok_or_else(|| HiveError::ApiKeyNotFound(id.to_string()))?;
+        key.revoked = true;
+        key.previous_secret_hash = None;
+        key.previous_valid_until = None;
+        
+        self.persist()?;
+        info!("Revoked API key {}", id);
+        
+        Ok(())
+    }
+    
+    /// Get a key by ID
+    pub fn get(&self, id: &str) -> Option<&ApiKey> {
+        self.keys.get(id)
+    }
+    
+    /// List all keys, oldest first
+    pub fn list(&self) -> Vec<&ApiKey> {
+        let mut keys: Vec<&ApiKey> = self.keys.values().collect();
+        keys.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.id.cmp(&b.id)));
+        keys
+    }
+    
+    /// Save the store to its file, if it has one
+    fn persist(&self) -> Result<(), HiveError> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        
+        let data = serde_json::to_vec_pretty(&self.list())
+            .map_err(|e| HiveError::SerializationError(e.to_string()))?;
+        
+        // Write to a temporary file first so a crash never leaves a partial store
+        let temp_path = path.with_extension("tmp");
+        std::fs::write(&temp_path, data)
+            .and_then(|_| std::fs::rename(&temp_path, path))
+            .map_err(|e| HiveError::IoError(e.to_string()))
+    }
+}
+
+fn format_token(id: &str, secret: &str) -> String {
+    format!("{}{}_{}", KEY_PREFIX, id, secret)
+}
+
+fn parse_token(token: &str) -> Option<(&str, &str)> {
+    token.strip_prefix(KEY_PREFIX)?.split_once('_')
+}
+
+fn hash_secret(secret: &str) -> String {
+    hex::encode(codec::sha256(secret.as_bytes()))
+}
+
+/// Generate a unique ID for an API key
+fn generate_key_id() -> String {
+    let mut rng = rand::thread_rng();
+    let random_bytes: Vec<u8> = (0..8).map(|_| rng.gen::<u8>()).collect();
+    
+    hex::encode(random_bytes)
+}
+
+fn generate_secret() -> String {
+    let mut rng = rand::thread_rng();
+    let random_bytes: Vec<u8> = (0..SECRET_LEN).map(|_| rng.gen::<u8>()).collect();
+    
+    hex::encode(random_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+    
+    #[test]
+    fn test_scope() {
+        let scope = ApiKeyScope::hives(vec!["orders".to_string()], Access::Write);
+        
+        assert!(scope.allows(Some("orders"), Access::Read));
+        assert!(scope.allows(Some("orders"), Access::Write));
+        assert!(!scope.allows(Some("orders"), Access::Admin));
+        assert!(!scope.allows(Some("users"), Access::Read));
+        assert!(ApiKeyScope::all_hives(Access::Read).allows(Some("users"), Access::Read));
+    }
+    
+    #[test]
+    fn test_create_authenticate_revoke() {
+        let mut store = ApiKeyStore::new();
+        let (key, token) = store.create("etl", ApiKeyScope::all_hives(Access::Read), None).unwrap();
+        
+        assert!(token.starts_with("hdb_"));
+        assert_eq!(store.authenticate(&token).unwrap().id, key.id);
+        assert!(store.authorize(&token, Some("orders"), Access::Read).is_ok());
+        assert!(matches!(
+            store.authorize(&token, Some("orders"), Access::Write),
+            Err(HiveError::AuthorizationError(_))
+        ));
+        assert!(store.authenticate("hdb_unknown_secret").is_err());
+        assert!(store.authenticate("not-a-key").is_err());
+        
+        store.revoke(&key.id).unwrap();
+        assert!(store.authenticate(&token).is_err());
+        assert!(store.rotate(&key.id, Duration::from_secs(0)).is_err());
+    }
+    
+    #[test]
+    fn test_rotation_grace_period() {
+        let mut store = ApiKeyStore::new();
+        let (key, old_token) = store.create("etl", ApiKeyScope::all_hives(Access::Write), None).unwrap();
+        
+        let new_token = store.rotate(&key.id, Duration::from_secs(3600)).unwrap();
+        assert!(store.authenticate(&new_token).is_ok());
+        assert!(store.authenticate(&old_token).is_ok());
+        
+        let newest_token = store.rotate(&key.id, Duration::from_secs(0)).unwrap();
+        assert!(store.authenticate(&newest_token).is_ok());
+        assert!(store.authenticate(&new_token).is_err());
+    }
+    
+    #[test]
+    fn test_persistence() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("api_keys.json");
+        
+        let token = {
+            let mut store = ApiKeyStore::open(path.clone()).unwrap();
+            let (_, token) = store.create("etl", ApiKeyScope::all_hives(Access::Read), None).unwrap();
+            token
+        };
+        
+        // Only the hash of the secret is stored
+        let stored = std::fs::read_to_string(&path).unwrap();
+        assert!(!stored.contains(token.rsplit('_').next().unwrap()));
+        
+        let store = ApiKeyStore::open(path).unwrap();
+        assert!(store.authenticate(&token).is_ok());
+    }
+}
@@ -0,0 +1,230 @@
+// HiveDB Authentication Module
+//
+// This module defines the pluggable authentication backends of HiveDB.
+// A provider only establishes who a user is and which external groups
+// they belong to; mapping those groups to HiveDB roles and access levels
+// stays in HiveDB.
+
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use crate::core::error::HiveError;
+use crate::security::{Access, Principal};
+use log::debug;
+
+/// Credentials presented by a client
+#[derive(Clone)]
+pub enum Credentials {
+    /// User name and password
+    Password {
+        /// User name
+        username: String,
+        
+        /// Password
+        password: String,
+    },
+    
+    /// Bearer token (e.g. an OIDC ID token)
+    Token(String),
+}
+
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        // Never print secrets
+        match self {
+            Credentials::Password { username, .. } => write!(f, "Password({})", username),
+            Credentials::Token(_) => write!(f, "Token"),
+        }
+    }
+}
+
+/// A user as established by an authentication provider
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExternalIdentity {
+    /// User name
+    pub username: String,
+    
+    /// Groups the provider reports for the user
+    pub groups: Vec<String>,
+    
+    /// Name of the provider that authenticated the user
+    pub provider: String,
+}
+
+/// An authentication backend
+pub trait AuthProvider: Send + Sync {
+    /// Name of the provider, used in logs and identities
+    fn name(&self) -> &str;
+    
+    /// Authenticate credentials
+    ///
+    /// Returns `Ok(None)` when the provider does not handle this kind of
+    /// credentials, and an error when it handles them but rejects them.
+    fn authenticate(&self, credentials: &Credentials) -> Result<Option<ExternalIdentity>, HiveError>;
+}
+
+/// Maps external groups to HiveDB roles and roles to access levels
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RoleMapping {
+    /// HiveDB roles granted by each external group
+    pub group_roles: HashMap<String, Vec<String>>,
+    
+    /// Access level granted by each HiveDB role
+    pub role_access: HashMap<String, Access>,
+    
+    /// Roles granted to every authenticated user
+    pub default_roles: Vec<String>,
+}
+
+impl RoleMapping {
+    /// Create an empty mapping
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Grant a role to the members of an external group
+    pub fn map_group(mut self, group: &str, role: &str) -> Self {
+        self.group_roles.entry(group.to_string()).or_default().push(role.to_string());
+        self
+    }
+    
+    /// Set the access level of a role
+    pub fn role(mut self, role: &str, access: Access) -> Self {
+        self.role_access.insert(role.to_string(), access);
+        self
+    }
+    
+    /// Grant a role to every authenticated user
+    pub fn default_role(mut self, role: &str) -> Self {
+        self.default_roles.push(role.to_string());
+        self
+    }
+    
+    /// Turn an external identity into a principal
+    pub fn principal(&self, identity: &ExternalIdentity) -> Result<Principal, HiveError> {
+        let mut roles = self.default_roles.clone();
+        for group in &identity.groups {
+            for role in self.group_roles.get(group).into_iter().flatten() {
+                if !roles.contains(role) {
+                    roles.push(role.clone());
+                }
+            }
+        }
+        
+        let access = roles.iter()
+            .filter_map(|role| self.role_access.get(role).copied())
+            .max()
+            .ok_or_else(|| HiveError::AuthorizationError(format!(
+                "No HiveDB role is mapped for user '{}'",
+                identity.username
+            )))?;
+        
+        Ok(Principal {
+            name: identity.username.clone(),
+            roles,
+            access,
+        })
+    }
+}
+
+/// Authenticates users against a chain of providers
+pub struct Authenticator {
+    /// Providers, tried in order
+    providers: Vec<Box<dyn AuthProvider>>,
+    
+    /// Mapping from external groups to HiveDB roles
+    mapping: RoleMapping,
+}
+
+impl Authenticator {
+    /// Create an authenticator with no providers
+    pub fn new(mapping: RoleMapping) -> Self {
+        Self {
+            providers: Vec::new(),
+            mapping,
+        }
+    }
+    
+    /// Add a provider after the existing ones
+    pub fn with_provider(mut self, provider: Box<dyn AuthProvider>) -> Self {
+        self.providers.push(provider);
+        self
+    }
+    
+    /// Authenticate credentials with the first provider that handles them
+    pub fn authenticate(&self, credentials: &Credentials) -> Result<Principal, HiveError> {
+        for provider in &self.providers {
+            if let Some(identity) = provider.authenticate(credentials)? {
+                debug!("User '{}' authenticated by {}", identity.username, provider.name());
+                return self.mapping.principal(&identity);
+            }
+        }
+        
+        Err(HiveError::AuthenticationError("No authentication provider accepts these credentials".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// Provider accepting a single user
+    struct StaticProvider;
+    
+    impl AuthProvider for StaticProvider {
+        fn name(&self) -> &str {
+            "static"
+        }
+        
+        fn authenticate(&self, credentials: &Credentials) -> Result<Option<ExternalIdentity>, HiveError> {
+            match credentials {
+                Credentials::Password { username, password } if username == "nour" => {
+                    if password != "hunter2" {
+                        return Err(HiveError::AuthenticationError("bad password".to_string()));
+                    }
+                    Ok(Some(ExternalIdentity {
+                        username: username.clone(),
+                        groups: vec!["data-team".to_string()],
+                        provider: self.name().to_string(),
+                    }))
+                }
+                _ => Ok(None),
+            }
+        }
+    }
+    
+    #[test]
+    fn test_authenticator() {
+        let mapping = RoleMapping::new()
+            .map_group("data-team", "analyst")
+            .role("analyst", Access::Read)
+            .role("member", Access::Read);
+        let authenticator = Authenticator::new(mapping).with_provider(Box::new(StaticProvider));
+        
+        let principal = authenticator.authenticate(&Credentials::Password {
+            username: "nour".to_string(),
+            password: "hunter2".to_string(),
+        }).unwrap();
+        assert_eq!(principal.roles, vec!["analyst".to_string()]);
+        assert_eq!(principal.access, Access::Read);
+        
+        assert!(authenticator.authenticate(&Credentials::Password {
+            username: "nour".to_string(),
+            password: "wrong".to_string(),
+        }).is_err());
+        assert!(authenticator.authenticate(&Credentials::Token("abc".to_string())).is_err());
+    }
+    
+    #[test]
+    fn test_unmapped_identity_is_rejected() {
+        let identity = ExternalIdentity {
+            username: "guest".to_string(),
+            groups: vec!["visitors".to_string()],
+            provider: "static".to_string(),
+        };
+        
+        assert!(RoleMapping::new().principal(&identity).is_err());
+        
+        let mapping = RoleMapping::new().default_role("member").role("member", Access::Read);
+        assert_eq!(mapping.principal(&identity).unwrap().roles, vec!["member".to_string()]);
+    }
+}
@@ -0,0 +1,365 @@
+// HiveDB JWT Module
+//
+// This module validates JSON Web Tokens issued by an external identity
+// provider, so HiveDB can sit behind an existing SSO setup. HS256 tokens
+// are checked against a shared secret and RS256/ES256 tokens against the
+// provider's JWKS. Claims are mapped to a HiveDB principal. RS256/ES256
+// signatures are checked with ring, so builds without the `standard`
+// feature accept HS256 tokens only.
+
+use std::collections::HashMap;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+#[cfg(feature = "ring")]
+use ring::signature;
+use serde_json::Value;
+use crate::core::codec;
+use crate::core::error::HiveError;
+use crate::security::{now, Access, Principal};
+
+/// JWT validation settings
+#[derive(Debug, Clone)]
+pub struct JwtConfig {
+    /// Required `iss` claim
+    pub issuer: String,
+    
+    /// Required `aud` claim, if any
+    pub audience: Option<String>,
+    
+    /// Claim holding the user name
+    pub user_claim: String,
+    
+    /// Claim holding the roles (an array, or a space-separated string)
+    pub roles_claim: String,
+    
+    /// Access level granted by each role
+    pub role_access: HashMap<String, Access>,
+    
+    /// Access level for tokens whose roles map to nothing (None rejects them)
+    pub default_access: Option<Access>,
+    
+    /// Allowed clock skew in seconds for `exp` and `nbf`
+    pub leeway: u64,
+}
+
+impl JwtConfig {
+    /// Create a configuration for an issuer with the usual claim names
+    pub fn new(issuer: &str) -> Self {
+        Self {
+            issuer: issuer.to_string(),
+            audience: None,
+            user_claim: "sub".to_string(),
+            roles_claim: "roles".to_string(),
+            role_access: HashMap::new(),
+            default_access: None,
+            leeway: 60,
+        }
+    }
+    
+    /// Require an audience
+    pub fn with_audience(mut self, audience: &str) -> Self {
+        self.audience = Some(audience.to_string());
+        self
+    }
+    
+    /// Map a role to an access level
+    pub fn with_role(mut self, role: &str, access: Access) -> Self {
+        self.role_access.insert(role.to_string(), access);
+        self
+    }
+    
+    /// Grant an access level to tokens without a mapped role
+    pub fn with_default_access(mut self, access: Access) -> Self {
+        self.default_access = Some(access);
+        self
+    }
+}
+
+/// A public key from a JWKS document
+#[derive(Debug, Clone)]
+#[cfg_attr(not(feature = "ring"), allow(dead_code))]
+enum PublicKey {
+    /// RSA modulus and exponent
+    Rsa { n: Vec<u8>, e: Vec<u8> },
+    
+    /// Uncompressed P-256 point
+    EcP256(Vec<u8>),
+}
+
+/// Validates JWTs and maps them to principals
+pub struct JwtValidator {
+    /// Validation settings
+    config: JwtConfig,
+    
+    /// Shared secret for HS256 tokens
+    secret: Option<Vec<u8>>,
+    
+    /// JWKS keys by key ID
+    keys: HashMap<String, PublicKey>,
+}
+
+impl JwtValidator {
+    /// Create a validator that accepts no keys yet
+    pub fn new(config: JwtConfig) -> Self {
+        Self {
+            config,
+            secret: None,
+            keys: HashMap::new(),
+        }
+    }
+    
+    /// Accept HS256 tokens signed with a shared secret
+    pub fn with_secret(mut self, secret: &[u8]) -> Self {
+        self.secret = Some(secret.to_vec());
+        self
+    }
+    
+    /// Replace the RS256/ES256 keys with those of a JWKS document
+    pub fn set_jwks(&mut self, jwks: &str) -> Result<(), HiveError> {
+        let jwks: Value = serde_json::from_str(jwks)
+            .map_err(|e| HiveError::DeserializationError(e.to_string()))?;
+        
+        let mut keys = HashMap::new();
+        for jwk in jwks.get("keys").and_then(Value::as_array).into_iter().flatten() {
+            let kid = jwk.get("kid").and_then(Value::as_str).unwrap_or_default().to_string();
+            let field = |name: &str| -> Result<Vec<u8>, HiveError> {
+                jwk.get(name)
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| HiveError::DeserializationError(format!("JWK '{}' is missing '{}'", kid, name)))
+                    .and_then(decode)
+            };
+            
+            let key = match (jwk.get("kty").and_then(Value::as_str), jwk.get("crv").and_then(Value::as_str)) {
+                (Some("RSA"), _) => PublicKey::Rsa { n: field("n")?, e: field("e")? },
+                (Some("EC"), Some("P-256")) => {
+                    let mut point = vec![0x04];
+                    point.extend(field("x")?);
+                    point.extend(field("y")?);
+                    PublicKey::EcP256(point)
+                }
+                // Keys of other types are not usable for the algorithms we accept
+                _ => continue,
+            };
+            keys.insert(kid, key);
+        }
+        
+        self.keys = keys;
+        Ok(())
+    }
+    
+    /// Validate a token and map it to a principal
+    pub fn validate(&self, token: &str) -> Result<Principal, HiveError> {
+        let claims = self.claims(token)?;
+        self.principal(&claims)
+    }
+    
+    /// Validate a token and return its claims without mapping roles
+    pub fn claims(&self, token: &str) -> Result<Value, HiveError> {
+        let claims = self.verify(token)?;
+        self.check_claims(&claims)?;
+        Ok(claims)
+    }
+    
+    /// Get the validation settings
+    pub fn config(&self) -> &JwtConfig {
+        &self.config
+    }
+    
+    /// Check the signature of a token and return its claims
+    fn verify(&self, token: &str) -> Result<Value, HiveError> {
+        let parts: Vec<&str> = token.split('.').collect();
+        if parts.len() != 3 {
+            return Err(invalid("malformed token"));
+        }
+        
+        let header = parse_json(&decode(parts[0])?)?;
+        let claims = parse_json(&decode(parts[1])?)?;
+        let signature = decode(parts[2])?;
+        let signed = &token[..parts[0].len() + 1 + parts[1].len()];
+        
+        let kid = header.get("kid").and_then(Value::as_str).unwrap_or_default();
+        let key = self.keys.get(kid);
+        
+        let verified = match (header.get("alg").and_then(Value::as_str), key) {
+            (Some("HS256"), _) => match &self.secret {
+                Some(secret) => codec::verify_hmac_sha256(secret, signed.as_bytes(), &signature),
+                None => return Err(invalid("HS256 tokens are not accepted")),
+            },
+            #[cfg(feature = "ring")]
+            (Some("RS256"), Some(PublicKey::Rsa { n, e })) => signature::RsaPublicKeyComponents { n, e }
+                .verify(&signature::RSA_PKCS1_2048_8192_SHA256, signed.as_bytes(), &signature)
+                .is_ok(),
+            #[cfg(feature = "ring")]
+            (Some("ES256"), Some(PublicKey::EcP256(point))) => {
+                signature::UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_FIXED, point)
+                    .verify(signed.as_bytes(), &signature)
+                    .is_ok()
+            }
+            #[cfg(feature = "ring")]
+            (Some("RS256"), _) | (Some("ES256"), _) => return Err(invalid(&format!("unknown key ID '{}'", kid))),
+            #[cfg(not(feature = "ring"))]
+            (Some("RS256"), _) | (Some("ES256"), _) => return Err(invalid("RS256 and ES256 tokens need the standard feature")),
+            (alg, _) => return Err(invalid(&format!("unsupported algorithm {:?}", alg))),
+        };
+        
+        if !verified {
+            return Err(invalid("bad signature"));
+        }
+        
+        Ok(claims)
+    }
+    
+    fn check_claims(&self, claims: &Value) -> Result<(), HiveError> {
+        let now = now()?;
+        
+        let exp = claims.get("exp").and_then(Value::as_u64)
+            .ok_or_else(|| invalid("missing exp claim"))?;
+        if now > exp.saturating_add(self.config.leeway) {
+            return Err(invalid("token has expired"));
+        }
+        
+        if let Some(nbf) = claims.get("nbf").and_then(Value::as_u64) {
+            if now + self.config.leeway < nbf {
+                return Err(invalid("token is not valid yet"));
+            }
+        }
+        
+        if claims.get("iss").and_then(Value::as_str) != Some(self.config.issuer.as_str()) {
+            return Err(invalid("unexpected issuer"));
+        }
+        
+        if let Some(audience) = &self.config.audience {
+            let matches = match claims.get("aud") {
+                Some(Value::String(aud)) => aud == audience,
+                Some(Value::Array(auds)) => auds.iter().any(|aud| aud.as_str() == Some(audience)),
+                _ => false,
+            };
+            if !matches {
+                return Err(invalid("unexpected audience"));
+            }
+        }
+        
+        Ok(())
+    }
+    
+    fn principal(&self, claims: &Value) -> Result<Principal, HiveError> {
+        let name = claims.get(&self.config.user_claim)
+            .and_then(Value::as_str)
+            .ok_or_else(|| invalid(&format!("missing {} claim", self.config.user_claim)))?
+            .to_string();
+        
+        let roles: Vec<String> = match claims.get(&self.config.roles_claim) {
+            Some(Value::Array(roles)) => roles.iter().filter_map(Value::as_str).map(str::to_string).collect(),
+            Some(Value::String(roles)) => roles.split_whitespace().map(str::to_string).collect(),
+            _ => Vec::new(),
+        };
+        
+        let access = roles.iter()
+            .filter_map(|role| self.config.role_access.get(role).copied())
+            .max()
+            .or(self.config.default_access)
+            .ok_or_else(|| HiveError::AuthorizationError(format!("No HiveDB role is mapped for user '{}'", name)))?;
+        
+        Ok(Principal { name, roles, access })
+    }
+}
+
+/// Check whether a bearer token looks like a JWT rather than an opaque token
+pub fn is_jwt(token: &str) -> bool {
+    token.split('.').count() == 3
+}
+
+fn invalid(reason: &str) -> HiveError {
+    HiveError::AuthenticationError(format!("Invalid JWT: {}", reason))
+}
+
+fn decode(part: &str) -> Result<Vec<u8>, HiveError> {
+    URL_SAFE_NO_PAD.decode(part.trim_end_matches('='))
+        .map_err(|_| invalid("bad base64url encoding"))
+}
+
+fn parse_json(data: &[u8]) -> Result<Value, HiveError> {
+    serde_json::from_slice(data).map_err(|_| invalid("bad JSON"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    const SECRET: &[u8] = b"test-secret";
+    
+    fn token(claims: Value) -> String {
+        let header = URL_SAFE_NO_PAD.encode(br#"{"alg":"HS256","typ":"JWT"}"#);
+        let payload = URL_SAFE_NO_PAD.encode(claims.to_string());
+        let signed = format!("{}.{}", header, payload);
+        let tag = codec::hmac_sha256(SECRET, signed.as_bytes());
+        
+        format!("{}.{}", signed, URL_SAFE_NO_PAD.encode(tag.as_ref()))
+    }
+    
+    fn validator() -> JwtValidator {
+        let config = JwtConfig::new("https://sso.example.com")
+            .with_audience("hivedb")
+            .with_role("analyst", Access::Read)
+            .with_role("dba", Access::Admin);
+        
+        JwtValidator::new(config).with_secret(SECRET)
+    }
+    
+    #[test]
+    fn test_valid_token() {
+        let exp = now().unwrap() + 600;
+        let principal = validator().validate(&token(serde_json::json!({
+            "iss": "https://sso.example.com",
+            "aud": ["hivedb", "other"],
+            "sub": "amira",
+            "roles": ["analyst", "dba"],
+            "exp": exp,
+        }))).unwrap();
+        
+        assert_eq!(principal.name, "amira");
+        assert_eq!(principal.access, Access::Admin);
+    }
+    
+    #[test]
+    fn test_rejected_tokens() {
+        let validator = validator();
+        let exp = now().unwrap() + 600;
+        
+        let expired = token(serde_json::json!({
+            "iss": "https://sso.example.com", "aud": "hivedb", "sub": "a", "roles": ["dba"], "exp": 1,
+        }));
+        assert!(validator.validate(&expired).is_err());
+        
+        let wrong_issuer = token(serde_json::json!({
+            "iss": "https://evil.example.com", "aud": "hivedb", "sub": "a", "roles": ["dba"], "exp": exp,
+        }));
+        assert!(validator.validate(&wrong_issuer).is_err());
+        
+        let unmapped = token(serde_json::json!({
+            "iss": "https://sso.example.com", "aud": "hivedb", "sub": "a", "roles": ["guest"], "exp": exp,
+        }));
+        assert!(matches!(validator.validate(&unmapped), Err(HiveError::AuthorizationError(_))));
+        
+        let mut tampered = token(serde_json::json!({
+            "iss": "https://sso.example.com", "aud": "hivedb", "sub": "a", "roles": ["analyst"], "exp": exp,
+        }));
+        tampered.push('A');
+        assert!(validator.validate(&tampered).is_err());
+        
+        assert!(validator.validate("not-a-jwt").is_err());
+    }
+    
+    #[test]
+    fn test_jwks_parsing() {
+        let mut validator = validator();
+        validator.set_jwks(r#"{"keys": [
+            {"kty": "RSA", "kid": "rsa-1", "n": "sXch", "e": "AQAB"},
+            {"kty": "EC", "crv": "P-256", "kid": "ec-1", "x": "f83O", "y": "x_FE"},
+            {"kty": "oct", "kid": "ignored", "k": "AAAA"}
+        ]}"#).unwrap();
+        
+        assert_eq!(validator.keys.len(), 2);
+        assert!(validator.set_jwks(r#"{"keys": [{"kty": "RSA", "kid": "broken"}]}"#).is_err());
+    }
+}
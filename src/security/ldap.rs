@@ -0,0 +1,476 @@
+// HiveDB LDAP Module
+//
+// This module authenticates users against an LDAP directory with a
+// simple bind as the user, then looks up the groups the user is a member
+// of. Only the few LDAPv3 messages needed for that are implemented, over
+// plain `ldap://` connections.
+
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+use crate::core::error::HiveError;
+use crate::security::auth::{AuthProvider, Credentials, ExternalIdentity};
+
+/// LDAP result code for success
+const RESULT_SUCCESS: u8 = 0;
+
+/// LDAP result code for invalid credentials
+const RESULT_INVALID_CREDENTIALS: u8 = 49;
+
+/// Largest response message accepted
+const MAX_MESSAGE_LEN: usize = 4 * 1024 * 1024;
+
+/// BER tags used by the messages we send and receive
+mod tag {
+    pub const BOOLEAN: u8 = 0x01;
+    pub const INTEGER: u8 = 0x02;
+    pub const OCTET_STRING: u8 = 0x04;
+    pub const ENUMERATED: u8 = 0x0a;
+    pub const SEQUENCE: u8 = 0x30;
+    pub const SET: u8 = 0x31;
+    pub const BIND_REQUEST: u8 = 0x60;
+    pub const BIND_RESPONSE: u8 = 0x61;
+    pub const UNBIND_REQUEST: u8 = 0x42;
+    pub const SEARCH_REQUEST: u8 = 0x63;
+    pub const SEARCH_RESULT_ENTRY: u8 = 0x64;
+    pub const SEARCH_RESULT_DONE: u8 = 0x65;
+    pub const SIMPLE_AUTH: u8 = 0x80;
+    pub const EQUALITY_MATCH: u8 = 0xa3;
+}
+
+/// Settings for an LDAP directory
+#[derive(Debug, Clone)]
+pub struct LdapConfig {
+    /// Directory address as host:port
+    pub address: String,
+    
+    /// DN to bind as, with `{username}` replaced by the escaped user name
+    /// (e.g. "uid={username},ou=people,dc=example,dc=com")
+    pub user_dn_template: String,
+    
+    /// Base DN to search for groups (no group lookup if None)
+    pub group_base_dn: Option<String>,
+    
+    /// Group attribute listing member DNs
+    pub member_attribute: String,
+    
+    /// Group attribute holding the group name
+    pub group_name_attribute: String,
+    
+    /// Timeout for connecting and for each response
+    pub timeout: Duration,
+}
+
+impl LdapConfig {
+    /// Create a configuration with the usual attribute names
+    pub fn new(address: &str, user_dn_template: &str) -> Self {
+        Self {
+            address: address.to_string(),
+            user_dn_template: user_dn_template.to_string(),
+            group_base_dn: None,
+            member_attribute: "member".to_string(),
+            group_name_attribute: "cn".to_string(),
+            timeout: Duration::from_secs(5),
+        }
+    }
+    
+    /// Look up groups under the given base DN
+    pub fn with_group_base(mut self, base_dn: &str) -> Self {
+        self.group_base_dn = Some(base_dn.to_string());
+        self
+    }
+}
+
+/// Authentication provider backed by an LDAP directory
+pub struct LdapProvider {
+    /// Directory settings
+    config: LdapConfig,
+}
+
+impl LdapProvider {
+    /// Create a provider for a directory
+    pub fn new(config: LdapConfig) -> Self {
+        Self { config }
+    }
+    
+    fn connect(&self) -> Result<TcpStream, HiveError> {
+        let address = self.config.address
+            .to_socket_addrs()
+            .map_err(|e| HiveError::NetworkError(e.to_string()))?
+            .next()
+            .ok_or_else(|| HiveError::NetworkError(format!("Could not resolve {}", self.config.address)))?;
+        
+        let stream = TcpStream::connect_timeout(&address, self.config.timeout)
+            .map_err(|e| HiveError::NetworkError(e.to_string()))?;
+        stream.set_read_timeout(Some(self.config.timeout))
+            .and_then(|_| stream.set_write_timeout(Some(self.config.timeout)))
+            .map_err(|e| HiveError::NetworkError(e.to_string()))?;
+        
+        Ok(stream)
+    }
+    
+    /// Find the names of the groups listing the user as a member
+    fn groups(&self, stream: &mut TcpStream, base_dn: &str, user_dn: &str) -> Result<Vec<String>, HiveError> {
+        let request = search_request(2, base_dn, &self.config.member_attribute, user_dn, &self.config.group_name_attribute);
+        write(stream, &request)?;
+        
+        let mut groups = Vec::new();
+        loop {
+            let (_, op_tag, op) = read_message(stream)?;
+            match op_tag {
+                tag::SEARCH_RESULT_ENTRY => {
+                    for (attribute, values) in parse_search_entry(&op)? {
+                        if attribute.eq_ignore_ascii_case(&self.config.group_name_attribute) {
+                            groups.extend(values);
+                        }
+                    }
+                }
+                tag::SEARCH_RESULT_DONE => {
+                    let (code, message) = parse_result(&op)?;
+                    if code != RESULT_SUCCESS {
+                        return Err(HiveError::AuthenticationError(format!("LDAP group search failed ({}): {}", code, message)));
+                    }
+                    return Ok(groups);
+                }
+                // Referrals and other messages are ignored
+                _ => {}
+            }
+        }
+    }
+}
+
+impl AuthProvider for LdapProvider {
+    fn name(&self) -> &str {
+        "ldap"
+    }
+    
+    fn authenticate(&self, credentials: &Credentials) -> Result<Option<ExternalIdentity>, HiveError> {
+        let (username, password) = match credentials {
+            Credentials::Password { username, password } => (username, password),
+            _ => return Ok(None),
+        };
+        
+        // An empty password would be an unauthenticated bind, which servers accept
+        if username.is_empty() || password.is_empty() {
+            return Err(HiveError::AuthenticationError("User name and password are required".to_string()));
+        }
+        
+        let user_dn = self.config.user_dn_template.replace("{username}", &escape_dn_value(username));
+        let mut stream = self.connect()?;
+        
+        write(&mut stream, &bind_request(1, &user_dn, password))?;
+        let (_, op_tag, op) = read_message(&mut stream)?;
+        if op_tag != tag::BIND_RESPONSE {
+            return Err(HiveError::NetworkError("Unexpected LDAP response to bind".to_string()));
+        }
+        
+        match parse_result(&op)? {
+            (RESULT_SUCCESS, _) => {}
+            (RESULT_INVALID_CREDENTIALS, _) => {
+                return Err(HiveError::AuthenticationError("Invalid user name or password".to_string()));
+            }
+            (code, message) => {
+                return Err(HiveError::AuthenticationError(format!("LDAP bind failed ({}): {}", code, message)));
+            }
+        }
+        
+        let groups = match &self.config.group_base_dn {
+            Some(base_dn) => self.groups(&mut stream, base_dn, &user_dn)?,
+            None => Vec::new(),
+        };
+        
+        let _ = stream.write_all(&message(3, &tlv(tag::UNBIND_REQUEST, &[])));
+        
+        Ok(Some(ExternalIdentity {
+            username: username.clone(),
+            groups,
+            provider: self.name().to_string(),
+        }))
+    }
+}
+
+/// Escape a value for use in a DN (RFC 4514)
+pub fn escape_dn_value(value: &str) -> String {
+    let mut escaped = String::new();
+    let last = value.chars().count().saturating_sub(1);
+    
+    for (i, c) in value.chars().enumerate() {
+        match c {
+            ',' | '+' | '"' | '\\' | '<' | '>' | ';' | '=' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '#' if i == 0 => escaped.push_str("\\#"),
+            ' ' if i == 0 || i == last => escaped.push_str("\\ "),
+            '\0' => escaped.push_str("\\00"),
+            _ => escaped.push(c),
+        }
+    }
+    
+    escaped
+}
+
+fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    let len = content.len();
+    
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes: Vec<u8> = (len as u32).to_be_bytes().iter().copied().skip_while(|b| *b == 0).collect();
+        out.push(0x80 | bytes.len() as u8);
+        out.extend(bytes);
+    }
+    
+    out.extend_from_slice(content);
+    out
+}
+
+fn integer(tag: u8, value: i32) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    // Drop leading bytes that only repeat the sign
+    let mut start = 0;
+    while start < 3 && ((bytes[start] == 0 && bytes[start + 1] & 0x80 == 0) || (bytes[start] == 0xff && bytes[start + 1] & 0x80 != 0)) {
+        start += 1;
+    }
+    tlv(tag, &bytes[start..])
+}
+
+fn message(id: i32, op: &[u8]) -> Vec<u8> {
+    let mut content = integer(tag::INTEGER, id);
+    content.extend_from_slice(op);
+    tlv(tag::SEQUENCE, &content)
+}
+
+fn bind_request(id: i32, dn: &str, password: &str) -> Vec<u8> {
+    let mut content = integer(tag::INTEGER, 3);
+    content.extend(tlv(tag::OCTET_STRING, dn.as_bytes()));
+    content.extend(tlv(tag::SIMPLE_AUTH, password.as_bytes()));
+    message(id, &tlv(tag::BIND_REQUEST, &content))
+}
+
+fn search_request(id: i32, base_dn: &str, attribute: &str, value: &str, wanted: &str) -> Vec<u8> {
+    let mut filter = tlv(tag::OCTET_STRING, attribute.as_bytes());
+    filter.extend(tlv(tag::OCTET_STRING, value.as_bytes()));
+    
+    let mut content = tlv(tag::OCTET_STRING, base_dn.as_bytes());
+    content.extend(integer(tag::ENUMERATED, 2)); // wholeSubtree
+    content.extend(integer(tag::ENUMERATED, 0)); // neverDerefAliases
+    content.extend(integer(tag::INTEGER, 0)); // no size limit
+    content.extend(integer(tag::INTEGER, 0)); // no time limit
+    content.extend(tlv(tag::BOOLEAN, &[0])); // typesOnly = false
+    content.extend(tlv(tag::EQUALITY_MATCH, &filter));
+    content.extend(tlv(tag::SEQUENCE, &tlv(tag::OCTET_STRING, wanted.as_bytes())));
+    
+    message(id, &tlv(tag::SEARCH_REQUEST, &content))
+}
+
+/// Split BER content into (tag, value) elements
+fn elements(mut data: &[u8]) -> Result<Vec<(u8, &[u8])>, HiveError> {
+    let malformed = || HiveError::DeserializationError("Malformed LDAP message".to_string());
+    let mut out = Vec::new();
+    
+    while !data.is_empty() {
+        let tag = data[0];
+        let (len, header) = match data.get(1).copied().ok_or_else(malformed)? {
+            short if short < 0x80 => (short as usize, 2),
+            long => {
+                let count = (long & 0x7f) as usize;
+                if count == 0 || count > 4 || data.len() < 2 + count {
+                    return Err(malformed());
+                }
+                let len = data[2..2 + count].iter().fold(0usize, |acc, b| (acc << 8) | *b as usize);
+                (len, 2 + count)
+            }
+        };
+        
+        if data.len() < header + len {
+            return Err(malformed());
+        }
+        out.push((tag, &data[header..header + len]));
+        data = &data[header + len..];
+    }
+    
+    Ok(out)
+}
+
+/// Read one LDAP message and return (message ID, operation tag, operation content)
+fn read_message(stream: &mut TcpStream) -> Result<(i64, u8, Vec<u8>), HiveError> {
+    let mut header = [0u8; 2];
+    read_exact(stream, &mut header)?;
+    
+    let len = if header[1] < 0x80 {
+        header[1] as usize
+    } else {
+        let count = (header[1] & 0x7f) as usize;
+        if count == 0 || count > 4 {
+            return Err(HiveError::DeserializationError("Unsupported LDAP length encoding".to_string()));
+        }
+        let mut bytes = vec![0u8; count];
+        read_exact(stream, &mut bytes)?;
+        bytes.iter().fold(0usize, |acc, b| (acc << 8) | *b as usize)
+    };
+    
+    if header[0] != tag::SEQUENCE || len > MAX_MESSAGE_LEN {
+        return Err(HiveError::DeserializationError("Malformed LDAP message".to_string()));
+    }
+    
+    let mut body = vec![0u8; len];
+    read_exact(stream, &mut body)?;
+    
+    let parts = elements(&body)?;
+    match parts.as_slice() {
+        [(tag::INTEGER, id), (op_tag, op), ..] => {
+            let id = id.iter().fold(0i64, |acc, b| (acc << 8) | *b as i64);
+            Ok((id, *op_tag, op.to_vec()))
+        }
+        _ => Err(HiveError::DeserializationError("Malformed LDAP message".to_string())),
+    }
+}
+
+/// Parse an LDAPResult into (result code, diagnostic message)
+fn parse_result(op: &[u8]) -> Result<(u8, String), HiveError> {
+    let parts = elements(op)?;
+    match parts.as_slice() {
+        [(tag::ENUMERATED, code), _, (tag::OCTET_STRING, message), ..] => Ok((
+            code.last().copied().unwrap_or(0),
+            String::from_utf8_lossy(message).into_owned(),
+        )),
+        _ => Err(HiveError::DeserializationError("Malformed LDAP result".to_string())),
+    }
+}
+
+/// Parse a SearchResultEntry into (attribute, values) pairs
+fn parse_search_entry(op: &[u8]) -> Result<Vec<(String, Vec<String>)>, HiveError> {
+    let parts = elements(op)?;
+    let attributes = match parts.as_slice() {
+        [(tag::OCTET_STRING, _), (tag::SEQUENCE, attributes)] => elements(attributes)?,
+        _ => return Err(HiveError::DeserializationError("Malformed LDAP search entry".to_string())),
+    };
+    
+    let mut out = Vec::new();
+    for (_, attribute) in attributes {
+        if let [(tag::OCTET_STRING, name), (tag::SET, values)] = elements(attribute)?.as_slice() {
+            let values = elements(values)?
+                .into_iter()
+                .map(|(_, value)| String::from_utf8_lossy(value).into_owned())
+                .collect();
+            out.push((String::from_utf8_lossy(name).into_owned(), values));
+        }
+    }
+    
+    Ok(out)
+}
+
+fn read_exact(stream: &mut TcpStream, buffer: &mut [u8]) -> Result<(), HiveError> {
+    stream.read_exact(buffer)
+        .map_err(|e| HiveError::NetworkError(e.to_string()))
+}
+
+fn write(stream: &mut TcpStream, data: &[u8]) -> Result<(), HiveError> {
+    stream.write_all(data)
+        .map_err(|e| HiveError::NetworkError(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    
+    fn ldap_result(op_tag: u8, code: u8) -> Vec<u8> {
+        let mut content = integer(tag::ENUMERATED, code as i32);
+        content.extend(tlv(tag::OCTET_STRING, b""));
+        content.extend(tlv(tag::OCTET_STRING, b""));
+        tlv(op_tag, &content)
+    }
+    
+    fn search_entry(dn: &str, group: &str) -> Vec<u8> {
+        let values = tlv(tag::SET, &tlv(tag::OCTET_STRING, group.as_bytes()));
+        let mut attribute = tlv(tag::OCTET_STRING, b"cn");
+        attribute.extend(values);
+        
+        let mut content = tlv(tag::OCTET_STRING, dn.as_bytes());
+        content.extend(tlv(tag::SEQUENCE, &tlv(tag::SEQUENCE, &attribute)));
+        tlv(tag::SEARCH_RESULT_ENTRY, &content)
+    }
+    
+    /// Serve one connection: accept the bind if the password matches, then answer a group search
+    fn mock_directory(password: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            
+            let (id, op_tag, op) = read_message(&mut stream).unwrap();
+            assert_eq!(op_tag, tag::BIND_REQUEST);
+            let presented = elements(&op).unwrap()[2].1.to_vec();
+            let code = if presented == password.as_bytes() { RESULT_SUCCESS } else { RESULT_INVALID_CREDENTIALS };
+            stream.write_all(&message(id as i32, &ldap_result(tag::BIND_RESPONSE, code))).unwrap();
+            if code != RESULT_SUCCESS {
+                return;
+            }
+            
+            let (id, op_tag, _) = read_message(&mut stream).unwrap();
+            assert_eq!(op_tag, tag::SEARCH_REQUEST);
+            stream.write_all(&message(id as i32, &search_entry("cn=analysts,ou=groups", "analysts"))).unwrap();
+            stream.write_all(&message(id as i32, &ldap_result(tag::SEARCH_RESULT_DONE, RESULT_SUCCESS))).unwrap();
+        });
+        
+        address
+    }
+    
+    fn provider(address: &str) -> LdapProvider {
+        LdapProvider::new(
+            LdapConfig::new(address, "uid={username},ou=people,dc=example,dc=com")
+                .with_group_base("ou=groups,dc=example,dc=com"),
+        )
+    }
+    
+    fn password(username: &str, password: &str) -> Credentials {
+        Credentials::Password {
+            username: username.to_string(),
+            password: password.to_string(),
+        }
+    }
+    
+    #[test]
+    fn test_bind_and_group_lookup() {
+        let address = mock_directory("s3cret");
+        
+        let identity = provider(&address).authenticate(&password("hana", "s3cret")).unwrap().unwrap();
+        assert_eq!(identity.username, "hana");
+        assert_eq!(identity.groups, vec!["analysts".to_string()]);
+    }
+    
+    #[test]
+    fn test_invalid_credentials() {
+        let address = mock_directory("s3cret");
+        
+        assert!(matches!(
+            provider(&address).authenticate(&password("hana", "wrong")),
+            Err(HiveError::AuthenticationError(_))
+        ));
+    }
+    
+    #[test]
+    fn test_empty_password_is_rejected_locally() {
+        // No directory is listening; the request must fail before connecting
+        let provider = provider("127.0.0.1:1");
+        
+        assert!(matches!(
+            provider.authenticate(&password("hana", "")),
+            Err(HiveError::AuthenticationError(_))
+        ));
+        assert!(provider.authenticate(&Credentials::Token("t".to_string())).unwrap().is_none());
+    }
+    
+    #[test]
+    fn test_ber_encoding() {
+        assert_eq!(integer(tag::INTEGER, 3), vec![0x02, 0x01, 0x03]);
+        assert_eq!(integer(tag::INTEGER, 128), vec![0x02, 0x02, 0x00, 0x80]);
+        assert_eq!(tlv(tag::OCTET_STRING, &[0u8; 200])[..3], [0x04, 0x81, 200]);
+        assert_eq!(escape_dn_value("a,b=c"), "a\\,b\\=c");
+        assert_eq!(escape_dn_value(" #x "), "\\ #x\\ ");
+    }
+}
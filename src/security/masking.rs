@@ -0,0 +1,203 @@
+// HiveDB Masking Module
+//
+// This module hides sensitive fields from readers that should not see
+// them. Masking rules are applied to query results for low-privileged
+// roles and to documents leaving HiveDB through exports.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::core::codec::Sha256;
+use crate::core::query::QueryResult;
+
+/// Replacement for redacted values
+pub const REDACTED: &str = "[REDACTED]";
+
+/// How a field is masked
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum MaskStrategy {
+    /// Replace the value with a salted SHA-256 hash (equal values stay equal)
+    Hash,
+    
+    /// Replace the value with a fixed marker
+    Redact,
+    
+    /// Keep the first and last characters and mask the rest with `*`
+    Partial {
+        /// Characters kept at the start
+        keep_start: usize,
+        
+        /// Characters kept at the end
+        keep_end: usize,
+    },
+}
+
+/// A masking rule for one field
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MaskingRule {
+    /// Field to mask; nested fields use dotted paths (e.g. "address.zip")
+    pub field: String,
+    
+    /// How to mask the field
+    pub strategy: MaskStrategy,
+    
+    /// Roles that see the raw value
+    pub unmasked_roles: Vec<String>,
+}
+
+impl MaskingRule {
+    /// Create a rule that applies to every role
+    pub fn new(field: &str, strategy: MaskStrategy) -> Self {
+        Self {
+            field: field.to_string(),
+            strategy,
+            unmasked_roles: Vec::new(),
+        }
+    }
+    
+    /// Let a role see the raw value
+    pub fn unmasked_for(mut self, role: &str) -> Self {
+        self.unmasked_roles.push(role.to_string());
+        self
+    }
+}
+
+/// A set of masking rules
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MaskingPolicy {
+    /// Rules, applied in order
+    pub rules: Vec<MaskingRule>,
+    
+    /// Salt mixed into hashed values to prevent dictionary lookups
+    pub salt: String,
+}
+
+impl MaskingPolicy {
+    /// Create an empty policy with the given hash salt
+    pub fn new(salt: &str) -> Self {
+        Self {
+            rules: Vec::new(),
+            salt: salt.to_string(),
+        }
+    }
+    
+    /// Add a rule
+    pub fn with_rule(mut self, rule: MaskingRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+    
+    /// Check whether the policy has no rules
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+    
+    /// Mask a document for a reader holding the given roles
+    ///
+    /// Exports pass no roles, so every rule applies.
+    pub fn mask_document(&self, document: &mut Value, roles: &[String]) {
+        for rule in &self.rules {
+            if rule.unmasked_roles.iter().any(|role| roles.contains(role)) {
+                continue;
+            }
+            
+            if let Some(value) = field_mut(document, &rule.field) {
+                if !value.is_null() {
+                    *value = self.mask_value(value, &rule.strategy);
+                }
+            }
+        }
+    }
+    
+    /// Mask every document of a query result
+    pub fn mask_result(&self, result: &mut QueryResult, roles: &[String]) {
+        for document in &mut result.results {
+            self.mask_document(document, roles);
+        }
+    }
+    
+    fn mask_value(&self, value: &Value, strategy: &MaskStrategy) -> Value {
+        let text = match value {
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        };
+        
+        match strategy {
+            MaskStrategy::Hash => {
+                let mut context = Sha256::new();
+                context.update(self.salt.as_bytes());
+                context.update(text.as_bytes());
+                Value::String(hex::encode(context.finish().as_ref()))
+            }
+            MaskStrategy::Redact => Value::String(REDACTED.to_string()),
+            MaskStrategy::Partial { keep_start, keep_end } => {
+                Value::String(mask_partial(&text, *keep_start, *keep_end))
+            }
+        }
+    }
+}
+
+/// Keep the ends of a string and replace the middle with `*`
+///
+/// Strings too short to hide anything are masked entirely.
+fn mask_partial(text: &str, keep_start: usize, keep_end: usize) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    
+    if keep_start + keep_end >= chars.len() {
+        return "*".repeat(chars.len());
+    }
+    
+    chars.iter()
+        .enumerate()
+        .map(|(i, c)| if i < keep_start || i >= chars.len() - keep_end { *c } else { '*' })
+        .collect()
+}
+
+/// Find a field by dotted path
+fn field_mut<'a>(document: &'a mut Value, path: &str) -> Option<&'a mut Value> {
+    path.split('.').try_fold(document, |value, part| value.get_mut(part))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    
+    fn policy() -> MaskingPolicy {
+        MaskingPolicy::new("pepper")
+            .with_rule(MaskingRule::new("email", MaskStrategy::Hash))
+            .with_rule(MaskingRule::new("ssn", MaskStrategy::Redact).unmasked_for("compliance"))
+            .with_rule(MaskingRule::new("card.number", MaskStrategy::Partial { keep_start: 0, keep_end: 4 }))
+    }
+    
+    #[test]
+    fn test_mask_document() {
+        let mut document = json!({
+            "name": "Layla",
+            "email": "layla@example.com",
+            "ssn": "123-45-6789",
+            "card": { "number": "4111111111111111" },
+        });
+        policy().mask_document(&mut document, &[]);
+        
+        assert_eq!(document["name"], "Layla");
+        assert_ne!(document["email"], "layla@example.com");
+        assert_eq!(document["ssn"], REDACTED);
+        assert_eq!(document["card"]["number"], "************1111");
+    }
+    
+    #[test]
+    fn test_unmasked_roles() {
+        let mut document = json!({ "ssn": "123-45-6789", "email": null });
+        policy().mask_document(&mut document, &["compliance".to_string()]);
+        
+        assert_eq!(document["ssn"], "123-45-6789");
+        assert_eq!(document["email"], Value::Null);
+    }
+    
+    #[test]
+    fn test_mask_partial() {
+        assert_eq!(mask_partial("secret", 1, 1), "s****t");
+        assert_eq!(mask_partial("abc", 2, 2), "***");
+        assert_eq!(mask_partial("", 1, 1), "");
+    }
+}
@@ -0,0 +1,77 @@
+// HiveDB Security Module
+//
+// This module contains the security components of HiveDB, including
+// the credentials that clients use to authenticate and the permissions
+// that decide what they may do.
+
+pub mod allowlist;
+pub mod api_keys;
+pub mod auth;
+pub mod jwt;
+pub mod ldap;
+pub mod masking;
+pub mod oidc;
+pub mod users;
+
+pub use allowlist::QueryAllowlist;
+pub use api_keys::{ApiKey, ApiKeyScope, ApiKeyStore};
+pub use auth::{AuthProvider, Authenticator, Credentials, RoleMapping};
+pub use jwt::{JwtConfig, JwtValidator};
+pub use masking::{MaskingPolicy, MaskingRule, MaskStrategy};
+pub use users::{PasswordPolicy, User, UserStore};
+
+use serde::{Deserialize, Serialize};
+use crate::core::error::HiveError;
+use crate::utils::determinism;
+
+/// Levels of access to a hive; each level includes the ones below it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Access {
+    /// Read documents and metadata
+    Read,
+    
+    /// Read and modify documents
+    Write,
+    
+    /// Manage hives, schemas and the server itself
+    Admin,
+}
+
+impl Access {
+    /// Get the name of the access level
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Access::Read => "read",
+            Access::Write => "write",
+            Access::Admin => "admin",
+        }
+    }
+    
+    /// Parse an access level name
+    pub fn parse(name: &str) -> Result<Self, HiveError> {
+        match name.to_ascii_lowercase().as_str() {
+            "read" => Ok(Access::Read),
+            "write" => Ok(Access::Write),
+            "admin" => Ok(Access::Admin),
+            _ => Err(HiveError::DeserializationError(format!("Unknown access level '{}'", name))),
+        }
+    }
+}
+
+/// An authenticated identity and what it may do
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Principal {
+    /// User name
+    pub name: String,
+    
+    /// Roles held by the user
+    pub roles: Vec<String>,
+    
+    /// Highest access level granted by the roles
+    pub access: Access,
+}
+
+/// Get the current time in seconds since the UNIX epoch
+pub(crate) fn now() -> Result<u64, HiveError> {
+    Ok(determinism::unix_time())
+}
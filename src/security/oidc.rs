@@ -0,0 +1,103 @@
+// HiveDB OIDC Module
+//
+// This module authenticates users presenting an ID token from an OpenID
+// Connect provider. The token is validated with the provider's keys and
+// its groups claim is handed to HiveDB's role mapping.
+
+use serde_json::Value;
+use crate::core::error::HiveError;
+use crate::security::auth::{AuthProvider, Credentials, ExternalIdentity};
+use crate::security::jwt::{self, JwtValidator};
+
+/// Authentication provider accepting OIDC ID tokens
+pub struct OidcProvider {
+    /// Validator configured with the provider's issuer and keys
+    validator: JwtValidator,
+    
+    /// Claim listing the user's groups
+    groups_claim: String,
+}
+
+impl OidcProvider {
+    /// Create a provider; the user name comes from the validator's user claim
+    pub fn new(validator: JwtValidator) -> Self {
+        Self {
+            validator,
+            groups_claim: "groups".to_string(),
+        }
+    }
+    
+    /// Read groups from another claim
+    pub fn with_groups_claim(mut self, claim: &str) -> Self {
+        self.groups_claim = claim.to_string();
+        self
+    }
+}
+
+impl AuthProvider for OidcProvider {
+    fn name(&self) -> &str {
+        "oidc"
+    }
+    
+    fn authenticate(&self, credentials: &Credentials) -> Result<Option<ExternalIdentity>, HiveError> {
+        let token = match credentials {
+            Credentials::Token(token) if jwt::is_jwt(token) => token,
+            _ => return Ok(None),
+        };
+        
+        let claims = self.validator.claims(token)?;
+        let user_claim = &self.validator.config().user_claim;
+        
+        let username = claims.get(user_claim)
+            .and_then(Value::as_str)
+            .ok_or_else(|| HiveError::AuthenticationError(format!("ID token has no {} claim", user_claim)))?
+            .to_string();
+        
+        let groups = match claims.get(&self.groups_claim) {
+            Some(Value::Array(groups)) => groups.iter().filter_map(Value::as_str).map(str::to_string).collect(),
+            Some(Value::String(groups)) => groups.split_whitespace().map(str::to_string).collect(),
+            _ => Vec::new(),
+        };
+        
+        Ok(Some(ExternalIdentity {
+            username,
+            groups,
+            provider: self.name().to_string(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    use base64::Engine;
+    use crate::core::codec;
+    use crate::security::auth::{Authenticator, RoleMapping};
+    use crate::security::{now, Access, JwtConfig};
+    
+    fn id_token(claims: Value) -> String {
+        let header = URL_SAFE_NO_PAD.encode(br#"{"alg":"HS256"}"#);
+        let signed = format!("{}.{}", header, URL_SAFE_NO_PAD.encode(claims.to_string()));
+        let tag = codec::hmac_sha256(b"oidc-secret", signed.as_bytes());
+        format!("{}.{}", signed, URL_SAFE_NO_PAD.encode(tag.as_ref()))
+    }
+    
+    #[test]
+    fn test_oidc_groups_are_mapped() {
+        let validator = JwtValidator::new(JwtConfig::new("https://idp.example.com")).with_secret(b"oidc-secret");
+        let mapping = RoleMapping::new().map_group("engineering", "writer").role("writer", Access::Write);
+        let authenticator = Authenticator::new(mapping).with_provider(Box::new(OidcProvider::new(validator)));
+        
+        let token = id_token(serde_json::json!({
+            "iss": "https://idp.example.com",
+            "sub": "yusuf",
+            "groups": ["engineering"],
+            "exp": now().unwrap() + 300,
+        }));
+        
+        let principal = authenticator.authenticate(&Credentials::Token(token)).unwrap();
+        assert_eq!(principal.name, "yusuf");
+        assert_eq!(principal.access, Access::Write);
+    }
+}
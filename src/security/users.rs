@@ -0,0 +1,591 @@
+// HiveDB Users Module
+//
+// This module manages the local user accounts of HiveDB and the password
+// and account policies that apply to them: password complexity, expiry,
+// reuse, and lockout after repeated failed logins. Passwords are stored
+// as Argon2 hashes.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use argon2::password_hash::SaltString;
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use crate::core::error::HiveError;
+use crate::security::{now, Access, Principal};
+use log::{info, warn};
+
+/// Number of random bytes in a password salt
+const SALT_LEN: usize = 16;
+
+/// Rules that passwords and accounts must follow
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PasswordPolicy {
+    /// Minimum number of characters
+    pub min_length: usize,
+    
+    /// Require at least one uppercase letter
+    pub require_uppercase: bool,
+    
+    /// Require at least one lowercase letter
+    pub require_lowercase: bool,
+    
+    /// Require at least one digit
+    pub require_digit: bool,
+    
+    /// Require at least one character that is not a letter or digit
+    pub require_symbol: bool,
+    
+    /// Seconds after which a password must be changed (never if None)
+    pub max_age: Option<u64>,
+    
+    /// Number of previous passwords that may not be reused
+    pub history: usize,
+    
+    /// Failed logins in a row after which the account is locked (never if None)
+    pub max_failed_attempts: Option<u32>,
+    
+    /// Seconds an account stays locked
+    pub lockout_duration: u64,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            min_length: 8,
+            require_uppercase: false,
+            require_lowercase: false,
+            require_digit: false,
+            require_symbol: false,
+            max_age: None,
+            history: 0,
+            max_failed_attempts: Some(5),
+            lockout_duration: 15 * 60,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    /// Check a password against the complexity rules
+    pub fn check(&self, password: &str) -> Result<(), HiveError> {
+        let mut problems = Vec::new();
+        
+        if password.chars().count() < self.min_length {
+            problems.push(format!("at least {} characters", self.min_length));
+        }
+        if self.require_uppercase && !password.chars().any(char::is_uppercase) {
+            problems.push("an uppercase letter".to_string());
+        }
+        if self.require_lowercase && !password.chars().any(char::is_lowercase) {
+            problems.push("a lowercase letter".to_string());
+        }
+        if self.require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+            problems.push("a digit".to_string());
+        }
+        if self.require_symbol && !password.chars().any(|c| !c.is_alphanumeric()) {
+            problems.push("a symbol".to_string());
+        }
+        
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(HiveError::PasswordPolicyViolation(format!("Password must contain {}", problems.join(", "))))
+        }
+    }
+    
+    /// Set a policy setting by name, as used by the admin CLI
+    ///
+    /// Durations are given in days (`max-age`) and minutes (`lockout`);
+    /// `max-age` and `max-failed-attempts` accept 0 to disable them.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), HiveError> {
+        let invalid = || HiveError::DeserializationError(format!("Invalid value '{}' for {}", value, name));
+        let number = || value.parse::<u64>().map_err(|_| invalid());
+        let flag = || value.parse::<bool>().map_err(|_| invalid());
+        
+        match name {
+            "min-length" => self.min_length = number()? as usize,
+            "require-uppercase" => self.require_uppercase = flag()?,
+            "require-lowercase" => self.require_lowercase = flag()?,
+            "require-digit" => self.require_digit = flag()?,
+            "require-symbol" => self.require_symbol = flag()?,
+            "max-age" => self.max_age = Some(number()? * 24 * 60 * 60).filter(|age| *age > 0),
+            "history" => self.history = number()? as usize,
+            "max-failed-attempts" => self.max_failed_attempts = Some(number()? as u32).filter(|n| *n > 0),
+            "lockout" => self.lockout_duration = number()? * 60,
+            _ => return Err(HiveError::DeserializationError(format!("Unknown policy setting '{}'", name))),
+        }
+        
+        Ok(())
+    }
+}
+
+/// A local user account
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct User {
+    /// User name
+    pub name: String,
+    
+    /// Roles held by the user
+    pub roles: Vec<String>,
+    
+    /// Access level granted to the user
+    pub access: Access,
+    
+    /// Argon2 hash of the current password (PHC string format)
+    password_hash: String,
+    
+    /// Hashes of earlier passwords, most recent first
+    previous_hashes: Vec<String>,
+    
+    /// When the password was last changed (seconds since the UNIX epoch)
+    pub password_changed_at: u64,
+    
+    /// Whether the user must change their password before logging in
+    pub must_change_password: bool,
+    
+    /// Failed logins since the last successful one
+    pub failed_attempts: u32,
+    
+    /// When the account lock ends, if it is locked
+    pub locked_until: Option<u64>,
+    
+    /// When the account was created
+    pub created_at: u64,
+}
+
+impl User {
+    /// Get the principal for this user
+    pub fn principal(&self) -> Principal {
+        Principal {
+            name: self.name.clone(),
+            roles: self.roles.clone(),
+            access: self.access,
+        }
+    }
+    
+    /// Check whether the account is locked at the given time
+    pub fn is_locked(&self, now: u64) -> bool {
+        self.locked_until.map_or(false, |until| now < until)
+    }
+}
+
+/// Contents of the users file
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct UserFile {
+    /// Policy applied to all accounts
+    policy: PasswordPolicy,
+    
+    /// Accounts, sorted by name
+    users: Vec<User>,
+}
+
+/// Store of local user accounts, optionally persisted to a JSON file
+#[derive(Debug, Default)]
+pub struct UserStore {
+    /// Accounts by name
+    users: HashMap<String, User>,
+    
+    /// Policy applied to all accounts
+    policy: PasswordPolicy,
+    
+    /// File the store is saved to after every change
+    path: Option<PathBuf>,
+}
+
+impl UserStore {
+    /// Create an empty in-memory store with the default policy
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Open a store persisted at the given path, creating it if needed
+    pub fn open(path: PathBuf) -> Result<Self, HiveError> {
+        let file = if path.exists() {
+            let data = std::fs::read(&path)
+                .map_err(|e| HiveError::IoError(e.to_string()))?;
+            serde_json::from_slice(&data)
+                .map_err(|e| HiveError::DeserializationError(e.to_string()))?
+        } else {
+            UserFile::default()
+        };
+        
+        Ok(Self {
+            users: file.users.into_iter().map(|user| (user.name.clone(), user)).collect(),
+            policy: file.policy,
+            path: Some(path),
+        })
+    }
+    
+    /// Get the current policy
+    pub fn policy(&self) -> &PasswordPolicy {
+        &self.policy
+    }
+    
+    /// Replace the policy; existing passwords are checked again when they next change
+    pub fn set_policy(&mut self, policy: PasswordPolicy) -> Result<(), HiveError> {
+        self.policy = policy;
+        self.persist()
+    }
+    
+    /// Create an account
+    pub fn create_user(&mut self, name: &str, password: &str, access: Access) -> Result<&User, HiveError> {
+        if name.is_empty() {
+            return Err(HiveError::DeserializationError("User name must not be empty".to_string()));
+        }
+        if self.users.contains_key(name) {
+            return Err(HiveError::UserAlreadyExists(name.to_string()));
+        }
+        self.policy.check(password)?;
+        
+        let now = now()?;
+        let user = User {
+            name: name.to_string(),
+            roles: Vec::new(),
+            access,
+            password_hash: hash_password(password)?,
+            previous_hashes: Vec::new(),
+            password_changed_at: now,
+            must_change_password: false,
+            failed_attempts: 0,
+            locked_until: None,
+            created_at: now,
+        };
+        
+        self.users.insert(name.to_string(), user);
+        self.persist()?;
+        info!("Created user '{}'", name);
+        
+        self.get(name).ok_or_else(|| HiveError::UserNotFound(name.to_string()))
+    }
+    
+    /// Delete an account
+    pub fn delete_user(&mut self, name: &str) -> Result<(), HiveError> {
+        self.users.remove(name)
+            .ok_or_else(|| HiveError::UserNotFound(name.to_string()))?;
+        
+        self.persist()?;
+        info!("Deleted user '{}'", name);
+        
+        Ok(())
+    }
+    
+    /// Check a user's password and return the account
+    ///
+    /// Failed attempts are counted and lock the account once the policy's
+    /// limit is reached. A correct password that has expired or must be
+    /// rotated yields `PasswordExpired`; use `change_password` then.
+    pub fn authenticate(&mut self, name: &str, password: &str) -> Result<&User, HiveError> {
+        self.verify(name, password)?;
+        
+        let user = self.get(name).ok_or_else(|| HiveError::UserNotFound(name.to_string()))?;
+        if user.must_change_password || self.is_expired(user, now()?) {
+            return Err(HiveError::PasswordExpired(name.to_string()));
+        }
+        
+        Ok(user)
+    }
+    
+    /// Change a user's own password, which requires the current one
+    pub fn change_password(&mut self, name: &str, current: &str, new: &str) -> Result<(), HiveError> {
+        self.verify(name, current)?;
+        self.set_password(name, new)
+    }
+    
+    /// Set a user's password as an administrator
+    pub fn set_password(&mut self, name: &str, password: &str) -> Result<(), HiveError> {
+        self.policy.check(password)?;
+        
+        let history = self.policy.history;
+        let user = self.users.get_mut(name)
+            .ok_or_else(|| HiveError::UserNotFound(name.to_string()))?;
+        
+        let reused = std::iter::once(&user.password_hash)
+            .chain(user.previous_hashes.iter())
+            .take(history)
+            .any(|hash| verify_password(password, hash));
+        if reused {
+            return Err(HiveError::PasswordPolicyViolation(format!(
+                "Password must differ from the last {} passwords",
+                history
+            )));
+        }
+        
+        let previous = std::mem::replace(&mut user.password_hash, hash_password(password)?);
+        user.previous_hashes.insert(0, previous);
+        user.previous_hashes.truncate(history.saturating_sub(1));
+        user.password_changed_at = now()?;
+        user.must_change_password = false;
+        
+        self.persist()?;
+        info!("Changed password of user '{}'", name);
+        
+        Ok(())
+    }
+    
+    /// Require a user to choose a new password at their next login
+    pub fn expire_password(&mut self, name: &str) -> Result<(), HiveError> {
+        let user = self.users.get_mut(name)
+            .ok_or_else(|| HiveError::UserNotFound(name.to_string()))?;
+        user.must_change_password = true;
+        
+        self.persist()
+    }
+    
+    /// Lift a lockout and reset the failed login count
+    pub fn unlock(&mut self, name: &str) -> Result<(), HiveError> {
+        let user = self.users.get_mut(name)
+            .ok_or_else(|| HiveError::UserNotFound(name.to_string()))?;
+        user.locked_until = None;
+        user.failed_attempts = 0;
+        
+        self.persist()?;
+        info!("Unlocked user '{}'", name);
+        
+        Ok(())
+    }
+    
+    /// Set the access level of a user
+    pub fn set_access(&mut self, name: &str, access: Access) -> Result<(), HiveError> {
+        let user = self.users.get_mut(name)
+            .ok_or_else(|| HiveError::UserNotFound(name.to_string()))?;
+        user.access = access;
+        
+        self.persist()?;
+        info!("Granted {} access to user '{}'", access.as_str(), name);
+        
+        Ok(())
+    }
+    
+    /// Grant a role to a user
+    pub fn add_role(&mut self, name: &str, role: &str) -> Result<(), HiveError> {
+        let user = self.users.get_mut(name)
+            .ok_or_else(|| HiveError::UserNotFound(name.to_string()))?;
+        if !user.roles.iter().any(|r| r == role) {
+            user.roles.push(role.to_string());
+        }
+        
+        self.persist()?;
+        info!("Granted role '{}' to user '{}'", role, name);
+        
+        Ok(())
+    }
+    
+    /// Get an account by name
+    pub fn get(&self, name: &str) -> Option<&User> {
+        self.users.get(name)
+    }
+    
+    /// List all accounts, sorted by name
+    pub fn list(&self) -> Vec<&User> {
+        let mut users: Vec<&User> = self.users.values().collect();
+        users.sort_by(|a, b| a.name.cmp(&b.name));
+        users
+    }
+    
+    /// Check whether a user's password is older than the policy allows
+    pub fn is_expired(&self, user: &User, now: u64) -> bool {
+        self.policy.max_age.map_or(false, |max_age| now >= user.password_changed_at.saturating_add(max_age))
+    }
+    
+    /// Check a password, counting failures towards a lockout
+    fn verify(&mut self, name: &str, password: &str) -> Result<(), HiveError> {
+        let now = now()?;
+        let invalid = || HiveError::AuthenticationError("Invalid user name or password".to_string());
+        
+        let max_failed_attempts = self.policy.max_failed_attempts;
+        let lockout_duration = self.policy.lockout_duration;
+        let user = self.users.get_mut(name).ok_or_else(invalid)?;
+        
+        // Refuse locked accounts before looking at the password so a lockout
+        // cannot be used to keep guessing
+        if user.is_locked(now) {
+            return Err(HiveError::AccountLocked(name.to_string()));
+        }
+        
+        if verify_password(password, &user.password_hash) {
+            if user.failed_attempts > 0 || user.locked_until.is_some() {
+                user.failed_attempts = 0;
+                user.locked_until = None;
+                self.persist()?;
+            }
+            return Ok(());
+        }
+        
+        user.failed_attempts += 1;
+        if max_failed_attempts.map_or(false, |max| user.failed_attempts >= max) {
+            user.locked_until = Some(now + lockout_duration);
+            user.failed_attempts = 0;
+            warn!("Locked user '{}' after repeated failed logins", name);
+        }
+        self.persist()?;
+        
+        Err(invalid())
+    }
+    
+    /// Save the store to its file, if it has one
+    fn persist(&self) -> Result<(), HiveError> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        
+        let file = UserFile {
+            policy: self.policy.clone(),
+            users: self.list().into_iter().cloned().collect(),
+        };
+        let data = serde_json::to_vec_pretty(&file)
+            .map_err(|e| HiveError::SerializationError(e.to_string()))?;
+        
+        // Write to a temporary file first so a crash never leaves a partial store
+        let temp_path = path.with_extension("tmp");
+        std::fs::write(&temp_path, data)
+            .and_then(|_| std::fs::rename(&temp_path, path))
+            .map_err(|e| HiveError::IoError(e.to_string()))
+    }
+}
+
+fn hash_password(password: &str) -> Result<String, HiveError> {
+    let mut rng = rand::thread_rng();
+    let salt_bytes: Vec<u8> = (0..SALT_LEN).map(|_| rng.gen::<u8>()).collect();
+    let salt = SaltString::encode_b64(&salt_bytes)
+        .map_err(|e| HiveError::GenericError(e.to_string()))?;
+    
+    Ok(Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| HiveError::GenericError(e.to_string()))?
+        .to_string())
+}
+
+fn verify_password(password: &str, hash: &str) -> bool {
+    match PasswordHash::new(hash) {
+        Ok(hash) => Argon2::default().verify_password(password.as_bytes(), &hash).is_ok(),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+    
+    #[test]
+    fn test_password_complexity() {
+        let policy = PasswordPolicy {
+            min_length: 10,
+            require_uppercase: true,
+            require_digit: true,
+            require_symbol: true,
+            ..PasswordPolicy::default()
+        };
+        
+        assert!(matches!(policy.check("short"), Err(HiveError::PasswordPolicyViolation(_))));
+        assert!(policy.check("longenoughbutplain").is_err());
+        assert!(policy.check("Longenough1!").is_ok());
+    }
+    
+    #[test]
+    fn test_policy_settings() {
+        let mut policy = PasswordPolicy::default();
+        
+        policy.set("max-age", "90").unwrap();
+        policy.set("lockout", "30").unwrap();
+        policy.set("max-failed-attempts", "0").unwrap();
+        
+        assert_eq!(policy.max_age, Some(90 * 24 * 60 * 60));
+        assert_eq!(policy.lockout_duration, 30 * 60);
+        assert_eq!(policy.max_failed_attempts, None);
+        assert!(policy.set("min-length", "many").is_err());
+        assert!(policy.set("colour", "blue").is_err());
+    }
+    
+    #[test]
+    fn test_lockout_after_failed_attempts() {
+        let mut store = UserStore::new();
+        store.set_policy(PasswordPolicy {
+            max_failed_attempts: Some(3),
+            ..PasswordPolicy::default()
+        }).unwrap();
+        store.create_user("amira", "correct horse", Access::Read).unwrap();
+        
+        for _ in 0..3 {
+            assert!(matches!(
+                store.authenticate("amira", "wrong password"),
+                Err(HiveError::AuthenticationError(_))
+            ));
+        }
+        
+        // Even the right password is refused while locked
+        assert!(matches!(
+            store.authenticate("amira", "correct horse"),
+            Err(HiveError::AccountLocked(_))
+        ));
+        
+        store.unlock("amira").unwrap();
+        assert_eq!(store.authenticate("amira", "correct horse").unwrap().name, "amira");
+    }
+    
+    #[test]
+    fn test_forced_rotation_and_history() {
+        let mut store = UserStore::new();
+        store.set_policy(PasswordPolicy {
+            history: 2,
+            ..PasswordPolicy::default()
+        }).unwrap();
+        store.create_user("omar", "first password", Access::Write).unwrap();
+        
+        store.expire_password("omar").unwrap();
+        assert!(matches!(
+            store.authenticate("omar", "first password"),
+            Err(HiveError::PasswordExpired(_))
+        ));
+        
+        assert!(matches!(
+            store.change_password("omar", "first password", "first password"),
+            Err(HiveError::PasswordPolicyViolation(_))
+        ));
+        store.change_password("omar", "first password", "second password").unwrap();
+        assert!(store.authenticate("omar", "second password").is_ok());
+        
+        // The previous password is still remembered; the one before it is not
+        assert!(store.set_password("omar", "first password").is_err());
+        store.set_password("omar", "third password").unwrap();
+        store.set_password("omar", "first password").unwrap();
+    }
+    
+    #[test]
+    fn test_password_expiry() {
+        let mut store = UserStore::new();
+        store.create_user("lina", "initial password", Access::Read).unwrap();
+        
+        let user = store.get("lina").unwrap().clone();
+        assert!(!store.is_expired(&user, user.password_changed_at + 1_000_000));
+        
+        store.set_policy(PasswordPolicy {
+            max_age: Some(60),
+            ..PasswordPolicy::default()
+        }).unwrap();
+        assert!(!store.is_expired(&user, user.password_changed_at + 59));
+        assert!(store.is_expired(&user, user.password_changed_at + 60));
+    }
+    
+    #[test]
+    fn test_persistence() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("users.json");
+        
+        {
+            let mut store = UserStore::open(path.clone()).unwrap();
+            store.create_user("amira", "correct horse", Access::Admin).unwrap();
+            let mut policy = store.policy().clone();
+            policy.set("min-length", "12").unwrap();
+            store.set_policy(policy).unwrap();
+        }
+        
+        let stored = std::fs::read_to_string(&path).unwrap();
+        assert!(!stored.contains("correct horse"));
+        
+        let mut store = UserStore::open(path).unwrap();
+        assert_eq!(store.policy().min_length, 12);
+        assert_eq!(store.authenticate("amira", "correct horse").unwrap().access, Access::Admin);
+    }
+}
@@ -0,0 +1,569 @@
+// HiveDB Backup Module
+//
+// This module writes and reads the archives of `hivedb backup`. An archive
+// holds the snapshots of one or more hives, optionally compressed with zstd
+// and encrypted with AES-256-GCM under a key read from a key file or
+// derived from a passphrase with Argon2. The header records how the body
+// was written, so restoring needs no options beyond the key. Encrypted
+// archives are authenticated by the cipher and the others end with a
+// SHA-256 checksum, so a damaged archive or a wrong key is reported before
+// anything is restored.
+//
+// An incremental archive lists every hive but only holds the snapshots
+// that changed since an earlier archive; the others are recorded by the
+// digest of their snapshot. Restoring applies a full archive and then each
+// increment in turn, and the digests check that the chain is complete and
+// in order.
+
+use std::path::Path;
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use argon2::Argon2;
+use rand::Rng;
+use crate::core::codec;
+use crate::core::error::HiveError;
+use crate::core::hive::SNAPSHOT_FILE;
+use crate::core::snapshot;
+use log::info;
+
+/// Bytes every backup archive starts with
+pub const MAGIC: [u8; 4] = *b"HVBK";
+
+/// Version of the archive format written by this release
+pub const FORMAT_VERSION: u8 = 2;
+
+/// Length of an encryption key
+pub const KEY_LEN: usize = 32;
+
+/// Length of the header: magic, format version, flags, key source and a reserved byte
+const HEADER_LEN: usize = 8;
+
+/// Header flag of archives whose body is compressed with zstd
+const FLAG_COMPRESSED: u8 = 1;
+
+/// Header flag of encrypted archives
+const FLAG_ENCRYPTED: u8 = 2;
+
+/// Length of the salt a passphrase is stretched with
+const SALT_LEN: usize = 16;
+
+/// Length of an AES-GCM nonce
+const NONCE_LEN: usize = 12;
+
+/// Length of the SHA-256 checksum ending unencrypted archives and of snapshot digests
+const CHECKSUM_LEN: usize = 32;
+
+/// Compression level of archive bodies
+const LEVEL: i32 = 19;
+
+/// The key a backup archive is encrypted with
+#[derive(Clone, PartialEq, Eq)]
+pub enum BackupKey {
+    /// A passphrase, stretched into a key with Argon2
+    Passphrase(String),
+    
+    /// A key read from a key file
+    Key([u8; KEY_LEN]),
+}
+
+/// How a backup archive is written
+#[derive(Clone, Default)]
+pub struct BackupOptions {
+    /// Compress the archive with zstd
+    pub compress: bool,
+    
+    /// Encrypt the archive with this key
+    pub key: Option<BackupKey>,
+}
+
+/// One hive in a backup archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupEntry {
+    /// Name of the hive's storage directory
+    pub name: String,
+    
+    /// SHA-256 digest of the hive's snapshot file
+    pub digest: [u8; CHECKSUM_LEN],
+    
+    /// The hive's snapshot file, or None if it is unchanged since an earlier archive
+    pub snapshot: Option<Vec<u8>>,
+}
+
+/// Where the key of an encrypted archive comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeySource {
+    /// A passphrase
+    Passphrase,
+    
+    /// A key file
+    KeyFile,
+}
+
+impl std::fmt::Debug for BackupKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BackupKey::Passphrase(_) => f.write_str("Passphrase(..)"),
+            BackupKey::Key(_) => f.write_str("Key(..)"),
+        }
+    }
+}
+
+impl BackupEntry {
+    /// Record the snapshot of a hive
+    pub fn new(name: String, snapshot: Vec<u8>) -> Self {
+        Self { name, digest: digest(&snapshot), snapshot: Some(snapshot) }
+    }
+}
+
+impl BackupKey {
+    /// Read a key file holding 32 bytes, either raw or as 64 hex digits
+    pub fn from_file(path: &Path) -> Result<Self, HiveError> {
+        let data = std::fs::read(path)
+            .map_err(|e| HiveError::IoError(format!("Cannot read key file {}: {}", path.display(), e)))?;
+        let key = match hex::decode(String::from_utf8_lossy(&data).trim()) {
+            Ok(key) if key.len() == KEY_LEN => key,
+            _ if data.len() == KEY_LEN => data,
+            _ => {
+                return Err(HiveError::EncryptionError(format!(
+                    "Key file {} must hold {} bytes or {} hex digits",
+                    path.display(), KEY_LEN, KEY_LEN * 2
+                )));
+            }
+        };
+        
+        let mut bytes = [0; KEY_LEN];
+        bytes.copy_from_slice(&key);
+        Ok(BackupKey::Key(bytes))
+    }
+    
+    /// Get where this key comes from
+    pub fn source(&self) -> KeySource {
+        match self {
+            BackupKey::Passphrase(_) => KeySource::Passphrase,
+            BackupKey::Key(_) => KeySource::KeyFile,
+        }
+    }
+    
+    /// Get the AES key, stretching a passphrase with a salt
+    fn derive(&self, salt: &[u8]) -> Result<[u8; KEY_LEN], HiveError> {
+        match self {
+            BackupKey::Passphrase(passphrase) => {
+                let mut key = [0; KEY_LEN];
+                Argon2::default()
+                    .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+                    .map_err(|e| HiveError::EncryptionError(e.to_string()))?;
+                Ok(key)
+            }
+            BackupKey::Key(key) => Ok(*key),
+        }
+    }
+}
+
+impl KeySource {
+    /// Get the byte naming this source in a header
+    fn id(self) -> u8 {
+        match self {
+            KeySource::Passphrase => 1,
+            KeySource::KeyFile => 2,
+        }
+    }
+    
+    /// Get the source named by a header byte
+    fn from_id(id: u8) -> Result<Self, HiveError> {
+        match id {
+            1 => Ok(KeySource::Passphrase),
+            2 => Ok(KeySource::KeyFile),
+            _ => Err(HiveError::DeserializationError(format!("Unknown backup key source {}", id))),
+        }
+    }
+}
+
+/// Read the snapshots of the hives in a data directory
+///
+/// Only the hives named are read, or every hive if none are.
+pub fn collect(base_path: &Path, names: &[String]) -> Result<Vec<BackupEntry>, HiveError> {
+    let mut entries = Vec::new();
+    let dirs = std::fs::read_dir(base_path)
+        .map_err(|e| HiveError::IoError(e.to_string()))?;
+    for dir in dirs {
+        let path = dir.map_err(|e| HiveError::IoError(e.to_string()))?.path();
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        if !path.join(SNAPSHOT_FILE).is_file() || (!names.is_empty() && !names.iter().any(|n| n == name)) {
+            continue;
+        }
+        
+        let snapshot = std::fs::read(path.join(SNAPSHOT_FILE))
+            .map_err(|e| HiveError::IoError(e.to_string()))?;
+        entries.push(BackupEntry::new(name.to_string(), snapshot));
+    }
+    
+    if let Some(missing) = names.iter().find(|name| !entries.iter().any(|entry| entry.name == **name)) {
+        return Err(HiveError::GenericError(format!("No hive '{}' to back up", missing)));
+    }
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(entries)
+}
+
+/// Leave out the snapshots an earlier archive already holds, making an incremental archive
+pub fn skip_unchanged(entries: &mut [BackupEntry], earlier: &[BackupEntry]) {
+    for entry in entries {
+        if earlier.iter().any(|earlier| earlier.name == entry.name && earlier.digest == entry.digest) {
+            entry.snapshot = None;
+        }
+    }
+}
+
+/// Apply a chain of archives, a full one followed by its increments in order
+///
+/// Returns the hives as of the last archive, each with its snapshot.
+pub fn apply(chain: &[Vec<BackupEntry>]) -> Result<Vec<BackupEntry>, HiveError> {
+    let mut hives: Vec<BackupEntry> = Vec::new();
+    for (position, entries) in chain.iter().enumerate() {
+        let mut next = Vec::with_capacity(entries.len());
+        for entry in entries {
+            if entry.snapshot.is_some() {
+                next.push(entry.clone());
+                continue;
+            }
+            
+            let earlier = hives.iter()
+                .find(|earlier| earlier.name == entry.name && earlier.digest == entry.digest)
+                .ok_or_else(|| HiveError::GenericError(format!(
+                    "Backup {} of the chain is an increment whose snapshot of hive '{}' is not in the earlier \
+                     backups; give the full backup and every increment since, in order",
+                    position + 1, entry.name
+                )))?;
+            next.push(earlier.clone());
+        }
+        
+        // Hives missing from an archive were deleted before it was made
+        hives = next;
+    }
+    
+    Ok(hives)
+}
+
+/// Write the hives of a backup archive into a data directory
+///
+/// Every snapshot is checked before any is written, and hives that already
+/// exist are refused rather than overwritten. An incremental archive must
+/// first be applied to its full archive. Returns the number of hives restored.
+pub fn restore(base_path: &Path, entries: &[BackupEntry]) -> Result<usize, HiveError> {
+    for entry in entries {
+        let Some(snapshot) = &entry.snapshot else {
+            return Err(HiveError::GenericError(format!(
+                "Backup holds no snapshot of hive '{}'; restore the full backup it is an increment of first",
+                entry.name
+            )));
+        };
+        if entry.name.is_empty() || entry.name.contains(['/', '\\']) || entry.name.starts_with('.') {
+            return Err(HiveError::DeserializationError(format!("Invalid hive name '{}' in backup", entry.name)));
+        }
+        snapshot::decode(snapshot)
+            .map_err(|e| HiveError::DeserializationError(format!("Snapshot of hive '{}' cannot be read: {}", entry.name, e)))?;
+        if base_path.join(&entry.name).exists() {
+            return Err(HiveError::GenericError(format!("Hive '{}' already exists; delete it before restoring", entry.name)));
+        }
+    }
+    
+    for entry in entries {
+        let dir = base_path.join(&entry.name);
+        let snapshot = entry.snapshot.as_deref().unwrap_or_default();
+        std::fs::create_dir_all(&dir)
+            .and_then(|_| std::fs::write(dir.join(SNAPSHOT_FILE), snapshot))
+            .map_err(|e| HiveError::IoError(e.to_string()))?;
+        info!("Restored hive '{}'", entry.name);
+    }
+    
+    Ok(entries.len())
+}
+
+/// Write a backup archive of hive snapshots
+pub fn write_archive(entries: &[BackupEntry], options: &BackupOptions) -> Result<Vec<u8>, HiveError> {
+    let mut body = Vec::new();
+    for entry in entries {
+        let name_len = u16::try_from(entry.name.len())
+            .map_err(|_| HiveError::SerializationError(format!("Hive name '{}' is too long", entry.name)))?;
+        body.extend_from_slice(&name_len.to_be_bytes());
+        body.extend_from_slice(entry.name.as_bytes());
+        body.extend_from_slice(&entry.digest);
+        match &entry.snapshot {
+            Some(snapshot) => {
+                body.push(1);
+                body.extend_from_slice(&(snapshot.len() as u64).to_be_bytes());
+                body.extend_from_slice(snapshot);
+            }
+            None => body.push(0),
+        }
+    }
+    if options.compress {
+        body = zstd::bulk::compress(&body, LEVEL)
+            .map_err(|e| HiveError::CompressionError(e.to_string()))?;
+    }
+    
+    let mut flags = 0;
+    if options.compress {
+        flags |= FLAG_COMPRESSED;
+    }
+    if options.key.is_some() {
+        flags |= FLAG_ENCRYPTED;
+    }
+    let source = options.key.as_ref().map_or(0, |key| key.source().id());
+    let mut data = Vec::with_capacity(HEADER_LEN + SALT_LEN + NONCE_LEN + body.len() + CHECKSUM_LEN);
+    data.extend_from_slice(&MAGIC);
+    data.extend_from_slice(&[FORMAT_VERSION, flags, source, 0]);
+    
+    match &options.key {
+        Some(key) => {
+            let mut rng = rand::thread_rng();
+            let salt: [u8; SALT_LEN] = rng.gen();
+            let nonce: [u8; NONCE_LEN] = rng.gen();
+            data.extend_from_slice(&salt);
+            data.extend_from_slice(&nonce);
+            
+            // The header, salt and nonce are authenticated along with the body
+            let ciphertext = cipher(key, &salt)?
+                .encrypt(Nonce::from_slice(&nonce), Payload { msg: &body, aad: &data })
+                .map_err(|_| HiveError::EncryptionError("Cannot encrypt backup".to_string()))?;
+            data.extend_from_slice(&ciphertext);
+        }
+        None => {
+            data.extend_from_slice(&body);
+            let checksum = codec::sha256(&data);
+            data.extend_from_slice(checksum.as_ref());
+        }
+    }
+    
+    Ok(data)
+}
+
+/// Get the key source of an archive, or None if it is not encrypted
+pub fn key_source(data: &[u8]) -> Result<Option<KeySource>, HiveError> {
+    let header = read_header(data)?;
+    if header[5] & FLAG_ENCRYPTED == 0 {
+        return Ok(None);
+    }
+    KeySource::from_id(header[6]).map(Some)
+}
+
+/// Read and verify a backup archive
+pub fn read_archive(data: &[u8], key: Option<&BackupKey>) -> Result<Vec<BackupEntry>, HiveError> {
+    let header = read_header(data)?;
+    let flags = header[5];
+    
+    let body = if flags & FLAG_ENCRYPTED != 0 {
+        let key = key.ok_or_else(|| HiveError::EncryptionError("Backup is encrypted; a key is required".to_string()))?;
+        if data.len() < HEADER_LEN + SALT_LEN + NONCE_LEN {
+            return Err(HiveError::DeserializationError("Backup is truncated".to_string()));
+        }
+        let (aad, ciphertext) = data.split_at(HEADER_LEN + SALT_LEN + NONCE_LEN);
+        let salt = &aad[HEADER_LEN..HEADER_LEN + SALT_LEN];
+        let nonce = &aad[HEADER_LEN + SALT_LEN..];
+        cipher(key, salt)?
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad })
+            .map_err(|_| HiveError::EncryptionError("Backup cannot be decrypted: wrong key or damaged archive".to_string()))?
+    } else {
+        if data.len() < HEADER_LEN + CHECKSUM_LEN {
+            return Err(HiveError::DeserializationError("Backup is truncated".to_string()));
+        }
+        let (framed, checksum) = data.split_at(data.len() - CHECKSUM_LEN);
+        if codec::sha256(framed).as_ref() != checksum {
+            return Err(HiveError::DeserializationError("Backup checksum does not match its content".to_string()));
+        }
+        framed[HEADER_LEN..].to_vec()
+    };
+    
+    let body = if flags & FLAG_COMPRESSED != 0 {
+        zstd::stream::decode_all(&body[..])
+            .map_err(|e| HiveError::DecompressionError(e.to_string()))?
+    } else {
+        body
+    };
+    
+    read_entries(&body, header[4])
+}
+
+/// Check the magic and format version of an archive and return its header
+fn read_header(data: &[u8]) -> Result<&[u8], HiveError> {
+    if data.len() < HEADER_LEN || !data.starts_with(&MAGIC) {
+        return Err(HiveError::DeserializationError("Not a HiveDB backup".to_string()));
+    }
+    if data[4] > FORMAT_VERSION {
+        return Err(HiveError::DeserializationError(format!(
+            "Backup format version {} is newer than the supported version {}",
+            data[4], FORMAT_VERSION
+        )));
+    }
+    Ok(&data[..HEADER_LEN])
+}
+
+/// Split the body of an archive into its entries
+///
+/// Format version 1 archives are full and hold no digests.
+fn read_entries(mut body: &[u8], version: u8) -> Result<Vec<BackupEntry>, HiveError> {
+    let truncated = || HiveError::DeserializationError("Backup body is truncated".to_string());
+    let mut entries = Vec::new();
+    while !body.is_empty() {
+        let (len, rest) = body.split_first_chunk::<2>().ok_or_else(truncated)?;
+        let len = u16::from_be_bytes(*len) as usize;
+        let name = rest.get(..len).ok_or_else(truncated)?;
+        let name = String::from_utf8(name.to_vec())
+            .map_err(|e| HiveError::DeserializationError(e.to_string()))?;
+        
+        let mut rest = &rest[len..];
+        
+        let mut stored = None;
+        if version >= 2 {
+            let (digest, tail) = rest.split_first_chunk::<CHECKSUM_LEN>().ok_or_else(truncated)?;
+            let (present, tail) = tail.split_first().ok_or_else(truncated)?;
+            stored = Some(*digest);
+            rest = tail;
+            if *present == 0 {
+                entries.push(BackupEntry { name, digest: *digest, snapshot: None });
+                body = rest;
+                continue;
+            }
+        }
+        
+        let (len, rest) = rest.split_first_chunk::<8>().ok_or_else(truncated)?;
+        let len = usize::try_from(u64::from_be_bytes(*len)).map_err(|_| truncated())?;
+        let entry = BackupEntry::new(name, rest.get(..len).ok_or_else(truncated)?.to_vec());
+        if stored.is_some_and(|stored| stored != entry.digest) {
+            return Err(HiveError::DeserializationError(format!("Snapshot of hive '{}' does not match its digest", entry.name)));
+        }
+        entries.push(entry);
+        body = &rest[len..];
+    }
+    
+    Ok(entries)
+}
+
+/// Get the SHA-256 digest of a snapshot
+fn digest(snapshot: &[u8]) -> [u8; CHECKSUM_LEN] {
+    let mut digest = [0; CHECKSUM_LEN];
+    digest.copy_from_slice(codec::sha256(snapshot).as_ref());
+    digest
+}
+
+/// Build the cipher for a key and salt
+fn cipher(key: &BackupKey, salt: &[u8]) -> Result<Aes256Gcm, HiveError> {
+    Aes256Gcm::new_from_slice(&key.derive(salt)?)
+        .map_err(|e| HiveError::EncryptionError(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+    
+    fn entries() -> Vec<BackupEntry> {
+        let snapshot = snapshot::encode(br#"{"metadata": {"version": 1}}"#, snapshot::SnapshotCodec::Json).unwrap();
+        vec![
+            BackupEntry::new("orders".to_string(), snapshot.clone()),
+            BackupEntry::new("users".to_string(), snapshot),
+        ]
+    }
+    
+    #[test]
+    fn test_archive_options() {
+        let keys = [
+            None,
+            Some(BackupKey::Passphrase("correct horse battery staple".to_string())),
+            Some(BackupKey::Key([7; KEY_LEN])),
+        ];
+        for key in keys {
+            for compress in [false, true] {
+                let options = BackupOptions { compress, key: key.clone() };
+                let data = write_archive(&entries(), &options).unwrap();
+                assert_eq!(key_source(&data).unwrap(), key.as_ref().map(BackupKey::source));
+                assert_eq!(read_archive(&data, key.as_ref()).unwrap(), entries());
+                
+                // Any damage is caught, whether by the checksum or the cipher
+                let mut damaged = data.clone();
+                let last = damaged.len() - 1;
+                damaged[last] ^= 1;
+                assert!(read_archive(&damaged, key.as_ref()).is_err());
+            }
+        }
+    }
+    
+    #[test]
+    fn test_wrong_key() {
+        let options = BackupOptions { compress: true, key: Some(BackupKey::Passphrase("secret".to_string())) };
+        let data = write_archive(&entries(), &options).unwrap();
+        assert!(read_archive(&data, None).is_err());
+        let wrong = BackupKey::Passphrase("guess".to_string());
+        assert!(matches!(read_archive(&data, Some(&wrong)), Err(HiveError::EncryptionError(_))));
+    }
+    
+    #[test]
+    fn test_key_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("backup.key");
+        std::fs::write(&path, format!("{}\n", hex::encode([9; KEY_LEN]))).unwrap();
+        assert_eq!(BackupKey::from_file(&path).unwrap(), BackupKey::Key([9; KEY_LEN]));
+        std::fs::write(&path, [3; KEY_LEN]).unwrap();
+        assert_eq!(BackupKey::from_file(&path).unwrap(), BackupKey::Key([3; KEY_LEN]));
+        std::fs::write(&path, "short").unwrap();
+        assert!(BackupKey::from_file(&path).is_err());
+    }
+    
+    #[test]
+    fn test_collect_and_restore() {
+        let source = tempdir().unwrap();
+        restore(source.path(), &entries()).unwrap();
+        assert_eq!(collect(source.path(), &[]).unwrap(), entries());
+        assert_eq!(collect(source.path(), &["users".to_string()]).unwrap().len(), 1);
+        assert!(collect(source.path(), &["missing".to_string()]).is_err());
+        
+        // Existing hives are never overwritten
+        assert!(restore(source.path(), &entries()).is_err());
+        
+        let target = tempdir().unwrap();
+        let mut bad = entries();
+        bad[1].snapshot.as_mut().unwrap().truncate(10);
+        assert!(restore(target.path(), &bad).is_err());
+        assert!(!target.path().join("orders").exists());
+    }
+    
+    #[test]
+    fn test_incremental_chain() {
+        let dir = tempdir().unwrap();
+        let write = |name: &str, version: u64| {
+            let body = format!(r#"{{"metadata": {{"version": {}}}}}"#, version);
+            let snapshot = snapshot::encode(body.as_bytes(), snapshot::SnapshotCodec::Json).unwrap();
+            std::fs::create_dir_all(dir.path().join(name)).unwrap();
+            std::fs::write(dir.path().join(name).join(SNAPSHOT_FILE), snapshot).unwrap();
+        };
+        let options = BackupOptions { compress: true, key: None };
+        let archive = |since: Option<&[BackupEntry]>| {
+            let mut entries = collect(dir.path(), &[]).unwrap();
+            if let Some(since) = since {
+                skip_unchanged(&mut entries, since);
+            }
+            read_archive(&write_archive(&entries, &options).unwrap(), None).unwrap()
+        };
+        
+        write("orders", 1);
+        write("users", 1);
+        let full = archive(None);
+        
+        // Only the changed hive is stored; deleted hives drop out
+        write("orders", 2);
+        let first = archive(Some(&full));
+        assert_eq!(first.iter().filter(|entry| entry.snapshot.is_some()).count(), 1);
+        std::fs::remove_dir_all(dir.path().join("users")).unwrap();
+        write("products", 1);
+        let second = archive(Some(&first));
+        
+        let restored = apply(&[full.clone(), first.clone(), second.clone()]).unwrap();
+        assert_eq!(restored, collect(dir.path(), &[]).unwrap());
+        
+        // Increments need their base, in order
+        assert!(restore(tempdir().unwrap().path(), &first).is_err());
+        assert!(apply(&[full.clone(), second]).is_err());
+        assert!(apply(&[first, full]).is_err());
+    }
+}
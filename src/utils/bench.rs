@@ -0,0 +1,377 @@
+// HiveDB Bench Module
+//
+// This module runs simple benchmark workloads against a hive, either
+// embedded in the process or on a server reached over the PostgreSQL
+// protocol, and reports throughput and latency percentiles. Every
+// operation is an HQL statement, so both targets do the same work.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use crate::core::error::HiveError;
+use crate::core::hive::Hive;
+use crate::core::query::{QueryExecutor, QueryType};
+use crate::core::sql;
+use crate::network::pgwire::PgClient;
+
+/// Collection the benchmark documents are written to
+pub const BENCH_COLLECTION: &str = "bench";
+
+/// Number of distinct `bucket` values, which query workloads filter on
+const BUCKETS: u64 = 100;
+
+/// Operations a benchmark can run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Workload {
+    /// Insert new documents
+    Insert,
+    
+    /// Look up single documents by key
+    Read,
+    
+    /// Run filtered queries returning a few documents
+    Query,
+    
+    /// 80% reads, 10% inserts and 10% queries
+    Mixed,
+}
+
+impl Workload {
+    /// Parse a workload name
+    pub fn parse(name: &str) -> Result<Self, HiveError> {
+        match name.to_ascii_lowercase().as_str() {
+            "insert" => Ok(Workload::Insert),
+            "read" => Ok(Workload::Read),
+            "query" => Ok(Workload::Query),
+            "mixed" => Ok(Workload::Mixed),
+            _ => Err(HiveError::DeserializationError(format!("Unknown workload '{}'", name))),
+        }
+    }
+}
+
+/// Settings of a benchmark run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchConfig {
+    /// What to run
+    pub workload: Workload,
+    
+    /// Number of concurrent clients
+    pub threads: usize,
+    
+    /// Approximate size of each document in bytes
+    pub document_size: usize,
+    
+    /// How long to run
+    pub duration: Duration,
+    
+    /// Documents inserted before the run, for read and query workloads
+    pub preload: u64,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            workload: Workload::Mixed,
+            threads: 4,
+            document_size: 256,
+            duration: Duration::from_secs(10),
+            preload: 1_000,
+        }
+    }
+}
+
+/// Something a benchmark client can send statements to
+pub trait BenchConnection {
+    /// Run one statement
+    fn execute(&mut self, statement: &str) -> Result<(), HiveError>;
+}
+
+/// Where a benchmark runs
+pub trait BenchTarget: Send + Sync {
+    /// Open a connection for one client thread
+    fn connect(&self) -> Result<Box<dyn BenchConnection>, HiveError>;
+}
+
+/// A hive in this process
+pub struct EmbeddedTarget {
+    /// The hive under test
+    hive: Arc<RwLock<Hive>>,
+}
+
+impl EmbeddedTarget {
+    /// Benchmark the given hive
+    pub fn new(hive: Arc<RwLock<Hive>>) -> Self {
+        Self { hive }
+    }
+}
+
+impl BenchTarget for EmbeddedTarget {
+    fn connect(&self) -> Result<Box<dyn BenchConnection>, HiveError> {
+        Ok(Box::new(EmbeddedConnection { hive: self.hive.clone() }))
+    }
+}
+
+struct EmbeddedConnection {
+    hive: Arc<RwLock<Hive>>,
+}
+
+impl BenchConnection for EmbeddedConnection {
+    fn execute(&mut self, statement: &str) -> Result<(), HiveError> {
+        let query = sql::parse(statement)?;
+        
+        match query.query_type {
+            QueryType::Find | QueryType::Count => {
+                let hive = self.hive.read().map_err(|_| HiveError::LockError)?;
+                QueryExecutor::execute_read(&query, &hive)?;
+            }
+            _ => {
+                let mut hive = self.hive.write().map_err(|_| HiveError::LockError)?;
+                QueryExecutor::execute(&query, &mut hive)?;
+            }
+        }
+        
+        Ok(())
+    }
+}
+
+/// A hive on a server speaking the PostgreSQL protocol
+pub struct RemoteTarget {
+    /// Server address (host:port)
+    address: String,
+    
+    /// User name sent at startup
+    user: String,
+    
+    /// Name of the hive under test
+    hive: String,
+}
+
+impl RemoteTarget {
+    /// Benchmark a hive on a server
+    pub fn new(address: &str, user: &str, hive: &str) -> Self {
+        Self {
+            address: address.to_string(),
+            user: user.to_string(),
+            hive: hive.to_string(),
+        }
+    }
+}
+
+impl BenchTarget for RemoteTarget {
+    fn connect(&self) -> Result<Box<dyn BenchConnection>, HiveError> {
+        Ok(Box::new(PgClient::connect(&self.address, &self.user, &self.hive)?))
+    }
+}
+
+impl BenchConnection for PgClient {
+    fn execute(&mut self, statement: &str) -> Result<(), HiveError> {
+        self.simple_query(statement).map(|_| ())
+    }
+}
+
+/// Latency distribution of one kind of operation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LatencySummary {
+    /// Completed operations
+    pub operations: u64,
+    
+    /// Failed operations
+    pub errors: u64,
+    
+    /// Completed operations per second
+    pub throughput: f64,
+    
+    /// Median latency in microseconds
+    pub p50_us: u64,
+    
+    /// 90th percentile latency in microseconds
+    pub p90_us: u64,
+    
+    /// 99th percentile latency in microseconds
+    pub p99_us: u64,
+    
+    /// Highest latency in microseconds
+    pub max_us: u64,
+}
+
+/// Results of a benchmark run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchReport {
+    /// Settings of the run
+    pub config: BenchConfig,
+    
+    /// How long the run actually took
+    pub elapsed: Duration,
+    
+    /// Results by operation (insert, read, query)
+    pub operations: Vec<(String, LatencySummary)>,
+}
+
+/// Latencies recorded by one thread, by operation
+#[derive(Default)]
+struct Samples {
+    latencies: [Vec<u64>; 3],
+    errors: [u64; 3],
+}
+
+const OPERATIONS: [&str; 3] = ["insert", "read", "query"];
+
+/// Run a benchmark
+pub fn run(target: &dyn BenchTarget, config: &BenchConfig) -> Result<BenchReport, HiveError> {
+    let threads = config.threads.max(1);
+    let payload = "x".repeat(config.document_size.saturating_sub(40));
+    
+    // Documents that read and query workloads can expect to find
+    let preload = if config.workload == Workload::Insert { 0 } else { config.preload.max(1) };
+    let mut connection = target.connect()?;
+    for key in 0..preload {
+        connection.execute(&insert_statement(key, &payload))?;
+    }
+    
+    let next_key = AtomicU64::new(preload);
+    let started = Instant::now();
+    let deadline = started + config.duration;
+    
+    let samples: Vec<Samples> = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads)
+            .map(|_| scope.spawn(|| client_loop(target, config, &payload, preload, &next_key, deadline)))
+            .collect();
+        
+        handles.into_iter()
+            .map(|handle| handle.join().map_err(|_| HiveError::GenericError("benchmark thread panicked".to_string()))?)
+            .collect::<Result<Vec<Samples>, HiveError>>()
+    })?;
+    
+    let elapsed = started.elapsed();
+    let mut operations = Vec::new();
+    for (i, name) in OPERATIONS.iter().enumerate() {
+        let mut latencies: Vec<u64> = samples.iter().flat_map(|s| s.latencies[i].iter().copied()).collect();
+        let errors: u64 = samples.iter().map(|s| s.errors[i]).sum();
+        if latencies.is_empty() && errors == 0 {
+            continue;
+        }
+        
+        latencies.sort_unstable();
+        operations.push((name.to_string(), LatencySummary {
+            operations: latencies.len() as u64,
+            errors,
+            throughput: latencies.len() as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+            p50_us: percentile(&latencies, 50.0),
+            p90_us: percentile(&latencies, 90.0),
+            p99_us: percentile(&latencies, 99.0),
+            max_us: latencies.last().copied().unwrap_or(0),
+        }));
+    }
+    
+    Ok(BenchReport {
+        config: config.clone(),
+        elapsed,
+        operations,
+    })
+}
+
+/// Issue operations from one thread until the deadline
+fn client_loop(
+    target: &dyn BenchTarget,
+    config: &BenchConfig,
+    payload: &str,
+    preload: u64,
+    next_key: &AtomicU64,
+    deadline: Instant,
+) -> Result<Samples, HiveError> {
+    let mut connection = target.connect()?;
+    let mut rng = rand::thread_rng();
+    let mut samples = Samples::default();
+    let mut counter: u64 = 0;
+    
+    while Instant::now() < deadline {
+        let operation = match config.workload {
+            Workload::Insert => 0,
+            Workload::Read => 1,
+            Workload::Query => 2,
+            Workload::Mixed => match counter % 10 {
+                0 => 0,
+                1 => 2,
+                _ => 1,
+            },
+        };
+        counter += 1;
+        
+        let statement = match operation {
+            0 => insert_statement(next_key.fetch_add(1, Ordering::Relaxed), payload),
+            1 => format!("SELECT * FROM {} WHERE key = {}", BENCH_COLLECTION, rng.gen_range(0..preload.max(1))),
+            _ => format!("SELECT * FROM {} WHERE bucket = {} LIMIT 10", BENCH_COLLECTION, rng.gen_range(0..BUCKETS)),
+        };
+        
+        let started = Instant::now();
+        match connection.execute(&statement) {
+            Ok(()) => samples.latencies[operation].push(started.elapsed().as_micros() as u64),
+            Err(_) => samples.errors[operation] += 1,
+        }
+    }
+    
+    Ok(samples)
+}
+
+fn insert_statement(key: u64, payload: &str) -> String {
+    let document = json!({ "key": key, "bucket": key % BUCKETS, "payload": payload });
+    format!("INSERT INTO {} VALUES ('{}')", BENCH_COLLECTION, document.to_string().replace('\'', "''"))
+}
+
+/// Get a percentile of sorted values (nearest rank)
+fn percentile(sorted: &[u64], percent: f64) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    
+    let rank = ((percent / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+    
+    #[test]
+    fn test_percentile() {
+        let values: Vec<u64> = (1..=100).collect();
+        
+        assert_eq!(percentile(&values, 50.0), 50);
+        assert_eq!(percentile(&values, 99.0), 99);
+        assert_eq!(percentile(&values, 100.0), 100);
+        assert_eq!(percentile(&[], 50.0), 0);
+    }
+    
+    #[test]
+    fn test_embedded_mixed_run() {
+        let temp_dir = tempdir().unwrap();
+        let hive = Hive::new(
+            "bench".to_string(),
+            "Benchmark".to_string(),
+            "test-user".to_string(),
+            temp_dir.path().to_path_buf(),
+            (64, 64),
+        ).unwrap();
+        let target = EmbeddedTarget::new(Arc::new(RwLock::new(hive)));
+        
+        let config = BenchConfig {
+            workload: Workload::Mixed,
+            threads: 2,
+            document_size: 64,
+            duration: Duration::from_millis(200),
+            preload: 50,
+        };
+        let report = run(&target, &config).unwrap();
+        
+        let names: Vec<&str> = report.operations.iter().map(|(name, _)| name.as_str()).collect();
+        assert!(names.contains(&"read"));
+        for (_, summary) in &report.operations {
+            assert!(summary.p50_us <= summary.p99_us);
+            assert!(summary.p99_us <= summary.max_us);
+        }
+    }
+}
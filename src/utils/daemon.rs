@@ -0,0 +1,245 @@
+// HiveDB Daemon Module
+//
+// This module manages a HiveDB server running in the background: the PID
+// file that records it and keeps a second server off the same data
+// directory, the request file used to stop it cleanly, and launching the
+// server as a detached process with its output sent to a log file.
+
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use crate::core::error::HiveError;
+use log::{info, warn};
+
+/// Name of the PID file in the data directory
+pub const PID_FILE: &str = "hivedb.pid";
+
+/// Name of the file that asks a running server to shut down
+pub const STOP_FILE: &str = "hivedb.stop";
+
+/// Name of the log file written by a daemonized server
+pub const LOG_FILE: &str = "hivedb.log";
+
+/// State of the server for a data directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServerStatus {
+    /// A server is running with this PID
+    Running(u32),
+    
+    /// The PID file names a process that is no longer running
+    Stale(u32),
+    
+    /// No server has the data directory
+    Stopped,
+}
+
+/// The PID file of a running server, removed again when dropped
+#[derive(Debug)]
+pub struct PidFile {
+    /// Path of the PID file
+    path: PathBuf,
+    
+    /// PID recorded in the file
+    pid: u32,
+}
+
+impl PidFile {
+    /// Record this process as the server for a data directory
+    ///
+    /// Fails with `AlreadyRunning` if another live process holds the PID
+    /// file. A PID file left behind by a process that died is replaced.
+    pub fn acquire(data_dir: &Path) -> Result<Self, HiveError> {
+        fs::create_dir_all(data_dir)
+            .map_err(|e| HiveError::IoError(e.to_string()))?;
+        
+        let path = data_dir.join(PID_FILE);
+        let pid = std::process::id();
+        
+        for _ in 0..2 {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    writeln!(file, "{}", pid).map_err(|e| HiveError::IoError(e.to_string()))?;
+                    
+                    // A stop request addressed to a previous server does not apply to us
+                    let _ = fs::remove_file(data_dir.join(STOP_FILE));
+                    return Ok(Self { path, pid });
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => match status(data_dir) {
+                    ServerStatus::Running(other) => return Err(HiveError::AlreadyRunning(other)),
+                    ServerStatus::Stale(other) => {
+                        warn!("Removing stale PID file of process {}", other);
+                        fs::remove_file(&path).map_err(|e| HiveError::IoError(e.to_string()))?;
+                    }
+                    // The other server exited in the meantime
+                    ServerStatus::Stopped => {}
+                },
+                Err(e) => return Err(HiveError::IoError(e.to_string())),
+            }
+        }
+        
+        Err(HiveError::IoError(format!("Could not create {}", path.display())))
+    }
+    
+    /// Get the PID recorded in the file
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        // Leave the file alone if another process has taken it over
+        if read_pid(&self.path) == Some(self.pid) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Get the state of the server for a data directory
+pub fn status(data_dir: &Path) -> ServerStatus {
+    match read_pid(&data_dir.join(PID_FILE)) {
+        Some(pid) if is_running(pid) => ServerStatus::Running(pid),
+        Some(pid) => ServerStatus::Stale(pid),
+        None => ServerStatus::Stopped,
+    }
+}
+
+/// Ask the server of a data directory to save its hives and exit
+pub fn request_stop(data_dir: &Path) -> Result<(), HiveError> {
+    fs::write(data_dir.join(STOP_FILE), b"")
+        .map_err(|e| HiveError::IoError(e.to_string()))
+}
+
+/// Check whether the server has been asked to stop
+pub fn stop_requested(data_dir: &Path) -> bool {
+    data_dir.join(STOP_FILE).exists()
+}
+
+/// Remove the stop request once it has been handled
+pub fn clear_stop_request(data_dir: &Path) {
+    let _ = fs::remove_file(data_dir.join(STOP_FILE));
+}
+
+/// Start this executable in the background with the given arguments
+///
+/// The child gets no terminal input, and its output is appended to the
+/// log file in the data directory. On Unix it runs in its own process
+/// group, so signals sent to the terminal do not reach it.
+pub fn spawn(data_dir: &Path, args: &[&str]) -> Result<Child, HiveError> {
+    fs::create_dir_all(data_dir)
+        .map_err(|e| HiveError::IoError(e.to_string()))?;
+    
+    let log_path = data_dir.join(LOG_FILE);
+    let log = OpenOptions::new().create(true).append(true).open(&log_path)
+        .map_err(|e| HiveError::IoError(e.to_string()))?;
+    let log_err = log.try_clone()
+        .map_err(|e| HiveError::IoError(e.to_string()))?;
+    
+    let exe = std::env::current_exe()
+        .map_err(|e| HiveError::IoError(e.to_string()))?;
+    let mut command = Command::new(exe);
+    command.args(args)
+        .env("HIVEDB_DATA_DIR", data_dir)
+        .stdin(Stdio::null())
+        .stdout(log)
+        .stderr(log_err);
+    
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+    
+    let child = command.spawn()
+        .map_err(|e| HiveError::IoError(e.to_string()))?;
+    info!("Started background server with PID {}, logging to {}", child.id(), log_path.display());
+    
+    Ok(child)
+}
+
+/// Forcefully terminate a process
+pub fn kill(pid: u32) -> Result<(), HiveError> {
+    #[cfg(unix)]
+    let mut command = {
+        let mut command = Command::new("kill");
+        command.args(["-KILL", &pid.to_string()]);
+        command
+    };
+    #[cfg(not(unix))]
+    let mut command = {
+        let mut command = Command::new("taskkill");
+        command.args(["/F", "/PID", &pid.to_string()]);
+        command
+    };
+    
+    let status = command.stdout(Stdio::null()).stderr(Stdio::null()).status()
+        .map_err(|e| HiveError::IoError(e.to_string()))?;
+    if !status.success() {
+        return Err(HiveError::GenericError(format!("Could not kill process {}", pid)));
+    }
+    
+    Ok(())
+}
+
+/// Check whether a process is running
+pub fn is_running(pid: u32) -> bool {
+    #[cfg(unix)]
+    let running = Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false);
+    #[cfg(not(unix))]
+    let running = Command::new("tasklist")
+        .args(["/FI", &format!("PID eq {}", pid), "/NH"])
+        .output()
+        .map(|output| String::from_utf8_lossy(&output.stdout).split_whitespace().any(|word| word == pid.to_string()))
+        .unwrap_or(false);
+    
+    running
+}
+
+/// Read the PID stored in a PID file
+fn read_pid(path: &Path) -> Option<u32> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+    
+    #[test]
+    fn test_pid_file_excludes_second_server() {
+        let temp_dir = tempdir().unwrap();
+        
+        let pid_file = PidFile::acquire(temp_dir.path()).unwrap();
+        assert_eq!(pid_file.pid(), std::process::id());
+        assert_eq!(status(temp_dir.path()), ServerStatus::Running(std::process::id()));
+        assert!(matches!(PidFile::acquire(temp_dir.path()), Err(HiveError::AlreadyRunning(_))));
+        
+        drop(pid_file);
+        assert_eq!(status(temp_dir.path()), ServerStatus::Stopped);
+    }
+    
+    #[test]
+    fn test_stale_pid_file_and_stop_request() {
+        let temp_dir = tempdir().unwrap();
+        
+        // PIDs are far below this on every supported platform
+        fs::write(temp_dir.path().join(PID_FILE), "4294967290\n").unwrap();
+        assert_eq!(status(temp_dir.path()), ServerStatus::Stale(4_294_967_290));
+        
+        request_stop(temp_dir.path()).unwrap();
+        let _pid_file = PidFile::acquire(temp_dir.path()).unwrap();
+        assert!(!stop_requested(temp_dir.path()));
+        
+        request_stop(temp_dir.path()).unwrap();
+        assert!(stop_requested(temp_dir.path()));
+        clear_stop_request(temp_dir.path());
+        assert!(!stop_requested(temp_dir.path()));
+    }
+}
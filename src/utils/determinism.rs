@@ -0,0 +1,226 @@
+// HiveDB Determinism Module
+//
+// This module is where hive code reads the time and draws random IDs, so
+// tests and fuzzers can replace both and get the same hive state on every
+// run. By default the system clock and the thread's random number
+// generator are used; `install` swaps in other sources for the current
+// thread until the returned guard is dropped, and `deterministic` sets up
+// a manual clock and a seeded generator in one call. Secrets such as API
+// keys, password salts and backup nonces never come from here.
+
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use rand::{RngCore, SeedableRng};
+use rand::rngs::StdRng;
+
+/// Time a deterministic clock starts at: 2024-01-01T00:00:00Z
+pub const DETERMINISTIC_START: Duration = Duration::from_secs(1_704_067_200);
+
+/// A source of time
+pub trait Clock: Send + Sync {
+    /// Get the wall-clock time since the UNIX epoch
+    fn now(&self) -> Duration;
+    
+    /// Get the time since some fixed point, which never goes backwards
+    fn monotonic(&self) -> Duration;
+}
+
+/// A source of random bytes
+pub trait Rng: Send + Sync {
+    /// Fill a buffer with random bytes
+    fn fill_bytes(&self, dest: &mut [u8]);
+}
+
+/// The system clock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default()
+    }
+    
+    fn monotonic(&self) -> Duration {
+        static START: OnceLock<Instant> = OnceLock::new();
+        START.get_or_init(Instant::now).elapsed()
+    }
+}
+
+/// The random number generator of the calling thread
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ThreadRng;
+
+impl Rng for ThreadRng {
+    fn fill_bytes(&self, dest: &mut [u8]) {
+        rand::thread_rng().fill_bytes(dest);
+    }
+}
+
+/// A clock that only moves when told to
+#[derive(Debug)]
+pub struct ManualClock {
+    /// Current time since the UNIX epoch, in milliseconds
+    millis: AtomicU64,
+}
+
+impl ManualClock {
+    /// Create a clock stopped at a time since the UNIX epoch
+    pub fn new(start: Duration) -> Self {
+        Self {
+            millis: AtomicU64::new(start.as_millis() as u64),
+        }
+    }
+    
+    /// Move the clock forward
+    pub fn advance(&self, by: Duration) {
+        self.millis.fetch_add(by.as_millis() as u64, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        Duration::from_millis(self.millis.load(Ordering::SeqCst))
+    }
+    
+    fn monotonic(&self) -> Duration {
+        self.now()
+    }
+}
+
+/// A generator giving the same bytes for the same seed
+#[derive(Debug)]
+pub struct SeededRng {
+    /// The generator
+    rng: Mutex<StdRng>,
+}
+
+impl SeededRng {
+    /// Create a generator from a seed
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: Mutex::new(StdRng::seed_from_u64(seed)),
+        }
+    }
+}
+
+impl Rng for SeededRng {
+    fn fill_bytes(&self, dest: &mut [u8]) {
+        // A poisoned generator is still a valid generator
+        let mut rng = self.rng.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        rng.fill_bytes(dest);
+    }
+}
+
+/// Sources installed on a thread
+#[derive(Clone)]
+struct Sources {
+    clock: Arc<dyn Clock>,
+    rng: Arc<dyn Rng>,
+}
+
+thread_local! {
+    /// Sources installed on this thread (None for the system ones)
+    static INSTALLED: RefCell<Option<Sources>> = const { RefCell::new(None) };
+}
+
+/// Puts back the sources a thread used before `install` when dropped
+#[must_use = "the sources are uninstalled when the guard is dropped"]
+pub struct Installed {
+    /// Sources to put back
+    previous: Option<Sources>,
+    
+    /// Keeps the guard on the thread it was made on
+    _thread: std::marker::PhantomData<*const ()>,
+}
+
+impl Drop for Installed {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        INSTALLED.with(|installed| *installed.borrow_mut() = previous);
+    }
+}
+
+/// Use a clock and random number source on this thread until the guard is dropped
+pub fn install(clock: Arc<dyn Clock>, rng: Arc<dyn Rng>) -> Installed {
+    let previous = INSTALLED.with(|installed| installed.borrow_mut().replace(Sources { clock, rng }));
+    Installed {
+        previous,
+        _thread: std::marker::PhantomData,
+    }
+}
+
+/// Use a manual clock at `DETERMINISTIC_START` and a seeded generator on this thread
+///
+/// Returns the guard and the clock, so a test can move time forward.
+pub fn deterministic(seed: u64) -> (Installed, Arc<ManualClock>) {
+    let clock = Arc::new(ManualClock::new(DETERMINISTIC_START));
+    let guard = install(clock.clone(), Arc::new(SeededRng::new(seed)));
+    (guard, clock)
+}
+
+/// Get the wall-clock time since the UNIX epoch
+pub fn now() -> Duration {
+    INSTALLED.with(|installed| match &*installed.borrow() {
+        Some(sources) => sources.clock.now(),
+        None => SystemClock.now(),
+    })
+}
+
+/// Get the wall-clock time in seconds since the UNIX epoch
+pub fn unix_time() -> u64 {
+    now().as_secs()
+}
+
+/// Get the time since some fixed point, which never goes backwards
+pub fn monotonic() -> Duration {
+    INSTALLED.with(|installed| match &*installed.borrow() {
+        Some(sources) => sources.clock.monotonic(),
+        None => SystemClock.monotonic(),
+    })
+}
+
+/// Get random bytes
+pub fn random_bytes(len: usize) -> Vec<u8> {
+    let mut bytes = vec![0; len];
+    INSTALLED.with(|installed| match &*installed.borrow() {
+        Some(sources) => sources.rng.fill_bytes(&mut bytes),
+        None => ThreadRng.fill_bytes(&mut bytes),
+    });
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_deterministic_sources() {
+        let draw = || {
+            let (_guard, clock) = deterministic(42);
+            let first = (unix_time(), random_bytes(16));
+            clock.advance(Duration::from_secs(90));
+            (first, unix_time(), random_bytes(16))
+        };
+        
+        let (first, later, bytes) = draw();
+        assert_eq!(first.0, DETERMINISTIC_START.as_secs());
+        assert_eq!(later, first.0 + 90);
+        assert_ne!(first.1, bytes);
+        assert_eq!(draw(), (first, later, bytes));
+        
+        // The system sources are back once the guard is dropped
+        assert!(unix_time() > DETERMINISTIC_START.as_secs());
+    }
+    
+    #[test]
+    fn test_nested_install() {
+        let (_outer, _) = deterministic(1);
+        {
+            let _inner = install(Arc::new(ManualClock::new(Duration::from_secs(5))), Arc::new(SeededRng::new(2)));
+            assert_eq!(unix_time(), 5);
+        }
+        assert_eq!(unix_time(), DETERMINISTIC_START.as_secs());
+    }
+}
@@ -0,0 +1,441 @@
+// HiveDB Doctor Module
+//
+// This module checks a data directory and the settings of the server for
+// problems, without opening the hives through a manager, so it works when
+// the server would not start. Each finding says what is wrong and, when
+// something can be done about it, what to do. The checks cover the
+// HIVEDB_* settings, access to the data directory, leftover lock and stop
+// files, the format of each hive snapshot, files left behind by
+// interrupted saves, and the free disk space.
+
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions, TryLockError};
+use std::net::ToSocketAddrs;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::core::disk;
+use crate::core::durability::Durability;
+use crate::core::error::HiveError;
+use crate::core::hive::{LOCK_FILE, SNAPSHOT_FILE};
+use crate::core::memory;
+use crate::core::snapshot::{self, FORMAT_VERSION};
+use crate::core::tiering;
+use crate::network::cdc::CdcFormat;
+use crate::security::allowlist::QueryAllowlist;
+use crate::security::jwt::{JwtConfig, JwtValidator};
+use crate::utils::daemon::{self, ServerStatus};
+
+/// Shortest admin token that is not reported as weak
+const MIN_TOKEN_LEN: usize = 16;
+
+/// Settings HiveDB reads, with what each expects
+const SETTINGS: [(&str, &str); 38] = [
+    ("HIVEDB_DATA_DIR", "a directory"),
+    ("HIVEDB_DURABILITY", "always, buffered or an interval such as 200ms"),
+    ("HIVEDB_MEMORY_LIMIT", "a size such as 512M or 2G"),
+    ("HIVEDB_MIN_FREE_SPACE", "a size such as 256M, or 0"),
+    ("HIVEDB_MAX_QUERIES", "a number of queries"),
+    ("HIVEDB_TENANT_MAX_QUERIES", "a number of queries"),
+    ("HIVEDB_QUERY_TIME_SLICE_MS", "a number of milliseconds"),
+    ("HIVEDB_WORKER_THREADS", "a number of threads"),
+    ("HIVEDB_BACKGROUND_MAX_TASKS", "a number of tasks"),
+    ("HIVEDB_BACKGROUND_MB_PER_SEC", "a number of megabytes per second"),
+    ("HIVEDB_COLD_STORE", "a bucket URL"),
+    ("HIVEDB_COLD_AFTER", "a duration such as 12h or 7d"),
+    ("HIVEDB_SNAPSHOT_STORE", "a bucket URL"),
+    ("HIVEDB_PG_ADDR", "an address such as 127.0.0.1:5432"),
+    ("HIVEDB_RESP_HIVE", "a hive name"),
+    ("HIVEDB_RESP_ADDR", "an address such as 127.0.0.1:6379"),
+    ("HIVEDB_ADMIN_ADDR", "an address such as 127.0.0.1:8090"),
+    ("HIVEDB_ADMIN_TOKEN", "a secret token"),
+    ("HIVEDB_JWT_ISSUER", "the issuer of admin JWTs"),
+    ("HIVEDB_JWT_AUDIENCE", "the audience of admin JWTs"),
+    ("HIVEDB_JWT_JWKS", "a JWKS file"),
+    ("HIVEDB_JWT_SECRET", "a shared secret for HS256 JWTs"),
+    ("HIVEDB_JWT_ADMIN_ROLE", "a JWT role"),
+    ("HIVEDB_AUTH_ADMIN_GROUPS", "a list of groups such as dba,platform"),
+    ("HIVEDB_LDAP_ADDR", "an address such as ldap.example.com:389"),
+    ("HIVEDB_LDAP_USER_DN", "a DN template such as uid={username},ou=people,dc=example,dc=com"),
+    ("HIVEDB_LDAP_GROUP_BASE", "a base DN such as ou=groups,dc=example,dc=com"),
+    ("HIVEDB_OIDC_ISSUER", "the issuer of ID tokens"),
+    ("HIVEDB_OIDC_AUDIENCE", "the audience of ID tokens"),
+    ("HIVEDB_OIDC_JWKS", "a JWKS file"),
+    ("HIVEDB_COMPRESSION_THRESHOLD", "a size such as 1K, or 0"),
+    ("HIVEDB_ADMIN_MAX_GRID_POSITIONS", "a number of positions"),
+    ("HIVEDB_QUERY_ALLOWLIST", "a JSON file of named statements"),
+    ("HIVEDB_STATS_INTERVAL", "a duration such as 1h, or 0"),
+    ("HIVEDB_CDC_KAFKA_BROKERS", "a list of brokers such as kafka1:9092,kafka2:9092"),
+    ("HIVEDB_CDC_NATS_ADDR", "an address such as 127.0.0.1:4222"),
+    ("HIVEDB_CDC_TOPIC", "a topic or subject name"),
+    ("HIVEDB_CDC_FORMAT", "json or debezium"),
+];
+
+/// Settings read by the backup commands rather than the server
+const CLIENT_SETTINGS: [&str; 1] = ["HIVEDB_BACKUP_PASSPHRASE"];
+
+/// How serious a finding is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// Nothing wrong
+    Ok,
+    
+    /// Worth fixing, but HiveDB works
+    Warning,
+    
+    /// HiveDB will not start, or will lose or refuse data
+    Error,
+}
+
+/// The outcome of a check
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Finding {
+    /// What was checked: settings, directory, locks, hives or disk
+    pub check: String,
+    
+    /// How serious it is
+    pub severity: Severity,
+    
+    /// What was found
+    pub detail: String,
+    
+    /// What to do about it
+    pub fix: Option<String>,
+}
+
+/// Findings about a data directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DoctorReport {
+    /// The data directory checked
+    pub data_dir: String,
+    
+    /// Findings, in the order they were made
+    pub findings: Vec<Finding>,
+}
+
+impl DoctorReport {
+    /// Get the most serious finding's severity
+    pub fn severity(&self) -> Severity {
+        self.findings.iter().map(|finding| finding.severity).max().unwrap_or(Severity::Ok)
+    }
+    
+    /// Check whether nothing would stop HiveDB from working
+    pub fn is_healthy(&self) -> bool {
+        self.severity() != Severity::Error
+    }
+    
+    /// Record a finding
+    fn push(&mut self, check: &str, severity: Severity, detail: String, fix: Option<String>) {
+        self.findings.push(Finding {
+            check: check.to_string(),
+            severity,
+            detail,
+            fix,
+        });
+    }
+}
+
+/// Check a data directory and the HIVEDB_* settings the server would use
+pub fn diagnose(data_dir: &Path, settings: &BTreeMap<String, String>) -> DoctorReport {
+    let mut report = DoctorReport {
+        data_dir: data_dir.display().to_string(),
+        findings: Vec::new(),
+    };
+    
+    check_settings(settings, &mut report);
+    if check_directory(data_dir, &mut report) {
+        check_locks(data_dir, &mut report);
+        check_hives(data_dir, &mut report);
+        check_disk(data_dir, settings, &mut report);
+    }
+    report
+}
+
+/// Check that every setting is known and has a valid value
+fn check_settings(settings: &BTreeMap<String, String>, report: &mut DoctorReport) {
+    let before = report.findings.len();
+    
+    for (name, value) in settings {
+        let Some((_, expected)) = SETTINGS.iter().find(|(setting, _)| setting == name) else {
+            if !CLIENT_SETTINGS.contains(&name.as_str()) {
+                report.push("settings", Severity::Warning, format!("{} is not a HiveDB setting", name),
+                    Some("Check its spelling, or unset it".to_string()));
+            }
+            continue;
+        };
+        
+        let parsed = match name.as_str() {
+            "HIVEDB_DURABILITY" => value.parse::<Durability>().map(drop),
+            "HIVEDB_MEMORY_LIMIT" | "HIVEDB_MIN_FREE_SPACE" | "HIVEDB_COMPRESSION_THRESHOLD" => memory::parse_size(value).map(drop),
+            "HIVEDB_MAX_QUERIES" | "HIVEDB_TENANT_MAX_QUERIES" | "HIVEDB_QUERY_TIME_SLICE_MS"
+            | "HIVEDB_WORKER_THREADS" | "HIVEDB_BACKGROUND_MAX_TASKS"
+            | "HIVEDB_ADMIN_MAX_GRID_POSITIONS" => value.parse::<u64>()
+                .map(drop)
+                .map_err(|e| HiveError::GenericError(e.to_string())),
+            "HIVEDB_BACKGROUND_MB_PER_SEC" => value.parse::<f64>()
+                .map(drop)
+                .map_err(|e| HiveError::GenericError(e.to_string())),
+            "HIVEDB_COLD_AFTER" => tiering::parse_duration(value).map(drop),
+            "HIVEDB_STATS_INTERVAL" if value.trim() == "0" => Ok(()),
+            "HIVEDB_STATS_INTERVAL" => tiering::parse_duration(value).map(drop),
+            "HIVEDB_QUERY_ALLOWLIST" => QueryAllowlist::load(Path::new(value)).map(drop),
+            "HIVEDB_CDC_FORMAT" => value.parse::<CdcFormat>().map(drop),
+            "HIVEDB_JWT_JWKS" | "HIVEDB_OIDC_JWKS" => fs::read_to_string(value)
+                .map_err(|e| HiveError::IoError(e.to_string()))
+                .and_then(|jwks| JwtValidator::new(JwtConfig::new("")).set_jwks(&jwks)),
+            "HIVEDB_PG_ADDR" | "HIVEDB_RESP_ADDR" | "HIVEDB_ADMIN_ADDR"
+            | "HIVEDB_CDC_NATS_ADDR" | "HIVEDB_LDAP_ADDR" => value.to_socket_addrs()
+                .map(drop)
+                .map_err(|e| HiveError::GenericError(e.to_string())),
+            _ => Ok(()),
+        };
+        if let Err(e) = parsed {
+            report.push("settings", Severity::Error, format!("{}='{}' is invalid: {}", name, value, e),
+                Some(format!("Set {} to {}", name, expected)));
+        }
+    }
+    
+    if settings.get("HIVEDB_ADMIN_TOKEN").is_some_and(|token| token.len() < MIN_TOKEN_LEN) {
+        report.push("settings", Severity::Warning, "HIVEDB_ADMIN_TOKEN is short enough to guess".to_string(),
+            Some(format!("Use a random token of at least {} characters", MIN_TOKEN_LEN)));
+    }
+    for (setting, needs) in [
+        ("HIVEDB_COLD_AFTER", "HIVEDB_COLD_STORE"),
+        ("HIVEDB_RESP_ADDR", "HIVEDB_RESP_HIVE"),
+        ("HIVEDB_ADMIN_ADDR", "HIVEDB_ADMIN_TOKEN"),
+        ("HIVEDB_JWT_ISSUER", "HIVEDB_ADMIN_TOKEN"),
+        ("HIVEDB_JWT_AUDIENCE", "HIVEDB_JWT_ISSUER"),
+        ("HIVEDB_JWT_JWKS", "HIVEDB_JWT_ISSUER"),
+        ("HIVEDB_JWT_SECRET", "HIVEDB_JWT_ISSUER"),
+        ("HIVEDB_JWT_ADMIN_ROLE", "HIVEDB_JWT_ISSUER"),
+        ("HIVEDB_LDAP_ADDR", "HIVEDB_ADMIN_TOKEN"),
+        ("HIVEDB_LDAP_ADDR", "HIVEDB_LDAP_USER_DN"),
+        ("HIVEDB_LDAP_ADDR", "HIVEDB_AUTH_ADMIN_GROUPS"),
+        ("HIVEDB_LDAP_USER_DN", "HIVEDB_LDAP_ADDR"),
+        ("HIVEDB_LDAP_GROUP_BASE", "HIVEDB_LDAP_ADDR"),
+        ("HIVEDB_OIDC_ISSUER", "HIVEDB_ADMIN_TOKEN"),
+        ("HIVEDB_OIDC_ISSUER", "HIVEDB_OIDC_JWKS"),
+        ("HIVEDB_OIDC_ISSUER", "HIVEDB_AUTH_ADMIN_GROUPS"),
+        ("HIVEDB_OIDC_AUDIENCE", "HIVEDB_OIDC_ISSUER"),
+        ("HIVEDB_OIDC_JWKS", "HIVEDB_OIDC_ISSUER"),
+    ] {
+        if settings.contains_key(setting) && !settings.contains_key(needs) {
+            report.push("settings", Severity::Warning, format!("{} has no effect without {}", setting, needs),
+                Some(format!("Set {} too, or unset {}", needs, setting)));
+        }
+    }
+    
+    if report.findings.len() == before {
+        report.push("settings", Severity::Ok, format!("{} settings are valid", settings.len()), None);
+    }
+}
+
+/// Check that the data directory can be read and written
+///
+/// Returns whether the other checks of the directory can run.
+fn check_directory(data_dir: &Path, report: &mut DoctorReport) -> bool {
+    if !data_dir.exists() {
+        report.push("directory", Severity::Warning, format!("{} does not exist yet", data_dir.display()),
+            Some("It is created by `hivedb start` or `hivedb create`; set HIVEDB_DATA_DIR if the hives are elsewhere".to_string()));
+        return false;
+    }
+    if !data_dir.is_dir() {
+        report.push("directory", Severity::Error, format!("{} is not a directory", data_dir.display()),
+            Some("Move the file away, or set HIVEDB_DATA_DIR to a directory".to_string()));
+        return false;
+    }
+    
+    let access = Some(format!("Give the user running HiveDB read and write access to {}", data_dir.display()));
+    if let Err(e) = fs::read_dir(data_dir) {
+        report.push("directory", Severity::Error, format!("{} cannot be listed: {}", data_dir.display(), e), access);
+        return false;
+    }
+    let probe = data_dir.join(".doctor");
+    match fs::write(&probe, b"").and_then(|()| fs::remove_file(&probe)) {
+        Ok(()) => report.push("directory", Severity::Ok, format!("{} is readable and writable", data_dir.display()), None),
+        Err(e) => report.push("directory", Severity::Error, format!("{} is not writable: {}", data_dir.display(), e), access),
+    }
+    true
+}
+
+/// Check for PID, stop and lock files left behind by servers that died
+fn check_locks(data_dir: &Path, report: &mut DoctorReport) {
+    let status = daemon::status(data_dir);
+    match status {
+        ServerStatus::Running(pid) => {
+            report.push("locks", Severity::Ok, format!("A server is running (PID {})", pid), None);
+        }
+        ServerStatus::Stale(pid) => {
+            report.push("locks", Severity::Warning, format!("{} names process {}, which is not running", daemon::PID_FILE, pid),
+                Some("Run `hivedb stop` to remove it".to_string()));
+        }
+        ServerStatus::Stopped => {}
+    }
+    
+    let stop_file = data_dir.join(daemon::STOP_FILE);
+    if !matches!(status, ServerStatus::Running(_)) && stop_file.exists() {
+        report.push("locks", Severity::Warning, "A stop request was left behind; the next server would stop as soon as it starts".to_string(),
+            Some(format!("Remove {}", stop_file.display())));
+    }
+    
+    // A manager holds the lock while it has the hives open
+    let lock_path = data_dir.join(LOCK_FILE);
+    if !matches!(status, ServerStatus::Running(_)) && lock_path.exists() {
+        let held = OpenOptions::new().write(true).open(&lock_path)
+            .map(|lock| matches!(lock.try_lock(), Err(TryLockError::WouldBlock)));
+        match held {
+            Ok(true) => report.push("locks", Severity::Warning, "Another process has the hives open".to_string(),
+                Some("Stop it before starting a server on this directory".to_string())),
+            Ok(false) => {}
+            Err(e) => report.push("locks", Severity::Error, format!("{} cannot be opened: {}", lock_path.display(), e),
+                Some(format!("Give the user running HiveDB write access to {}", lock_path.display()))),
+        }
+    }
+}
+
+/// Check the snapshot of every hive, and look for files interrupted saves left behind
+fn check_hives(data_dir: &Path, report: &mut DoctorReport) {
+    let Ok(entries) = fs::read_dir(data_dir) else {
+        return;
+    };
+    let mut directories: Vec<_> = entries.flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .collect();
+    directories.sort();
+    
+    let mut current = 0;
+    for path in directories {
+        let directory = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        let snapshot_path = path.join(SNAPSHOT_FILE);
+        let temp_path = snapshot_path.with_extension("tmp");
+        
+        if !snapshot_path.is_file() {
+            let detail = if temp_path.exists() {
+                format!("{} holds only the snapshot of a first save that was interrupted", path.display())
+            } else {
+                format!("{} holds no hive", path.display())
+            };
+            report.push("hives", Severity::Warning, detail,
+                Some(format!("Remove {} if nothing in it is needed", path.display())));
+            continue;
+        }
+        
+        let decoded = fs::read(&snapshot_path)
+            .map_err(|e| HiveError::IoError(e.to_string()))
+            .and_then(|data| snapshot::decode(&data));
+        let (header, body) = match decoded {
+            Ok(decoded) => decoded,
+            Err(e) => {
+                report.push("hives", Severity::Error, format!("The snapshot in {} cannot be read: {}", path.display(), e),
+                    Some("Restore the hive with `hivedb restore` from a backup".to_string()));
+                continue;
+            }
+        };
+        let name = body.get("name").and_then(|name| name.as_str()).unwrap_or(&directory).to_string();
+        
+        if header.version < FORMAT_VERSION {
+            report.push("hives", Severity::Warning,
+                format!("Hive '{}' is in format version {}, the current one is {}", name, header.version, FORMAT_VERSION),
+                Some("It is rewritten in the current format the next time it is saved".to_string()));
+        } else {
+            current += 1;
+        }
+        if temp_path.exists() {
+            report.push("hives", Severity::Warning, format!("An interrupted save of hive '{}' left {} behind", name, temp_path.display()),
+                Some(format!("Run `hivedb verify {} --repair`", name)));
+        }
+    }
+    
+    if current > 0 {
+        report.push("hives", Severity::Ok, format!("{} hives are in format version {}", current, FORMAT_VERSION), None);
+    }
+}
+
+/// Check the free space against the minimum the server keeps
+fn check_disk(data_dir: &Path, settings: &BTreeMap<String, String>, report: &mut DoctorReport) {
+    let min_free = settings.get("HIVEDB_MIN_FREE_SPACE")
+        .and_then(|size| memory::parse_size(size).ok())
+        .map_or(disk::DEFAULT_MIN_FREE, |size| size as u64);
+    let free = match disk::free_space(data_dir) {
+        Ok(free) => free,
+        Err(e) => {
+            report.push("disk", Severity::Warning, format!("The free space cannot be measured: {}", e), None);
+            return;
+        }
+    };
+    
+    let fix = Some("Free space on the volume, or move the data directory to a larger one".to_string());
+    if free < min_free {
+        report.push("disk", Severity::Error,
+            format!("{} bytes free, below the {} bytes under which writes are refused", free, min_free), fix);
+    } else if free < min_free.saturating_mul(2) {
+        report.push("disk", Severity::Warning,
+            format!("{} bytes free, close to the {} bytes under which writes are refused", free, min_free), fix);
+    } else {
+        report.push("disk", Severity::Ok, format!("{} bytes free", free), None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::hive::Hive;
+    use tempfile::tempdir;
+    
+    #[test]
+    fn test_check_settings() {
+        let settings: BTreeMap<String, String> = [
+            ("HIVEDB_DURABILITY", "sometimes"),
+            ("HIVEDB_MEMORY_LIMIT", "2G"),
+            ("HIVEDB_COLD_AFTER", "7d"),
+            ("HIVEDB_ADMIN_TOKEN", "secret"),
+            ("HIVEDB_JWT_AUDIENCE", "hivedb"),
+            ("HIVEDB_DURABILTY", "always"),
+        ].into_iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+        
+        let mut report = DoctorReport { data_dir: String::new(), findings: Vec::new() };
+        check_settings(&settings, &mut report);
+        let details: Vec<&str> = report.findings.iter().map(|finding| finding.detail.as_str()).collect();
+        
+        assert_eq!(report.findings.len(), 5, "{:?}", details);
+        assert_eq!(report.findings[0].severity, Severity::Error);
+        assert!(report.findings[0].fix.as_deref().unwrap().contains("always, buffered"));
+        assert!(details.contains(&"HIVEDB_DURABILTY is not a HiveDB setting"));
+        assert!(details.contains(&"HIVEDB_COLD_AFTER has no effect without HIVEDB_COLD_STORE"));
+        assert!(details.contains(&"HIVEDB_JWT_AUDIENCE has no effect without HIVEDB_JWT_ISSUER"));
+        assert!(!report.is_healthy());
+    }
+    
+    #[test]
+    fn test_diagnose_directory() {
+        let temp_dir = tempdir().unwrap();
+        let hive = Hive::new(
+            "orders".to_string(),
+            String::new(),
+            "test-user".to_string(),
+            temp_dir.path().join("orders"),
+            (8, 8),
+        ).unwrap();
+        hive.save().unwrap();
+        
+        fs::write(temp_dir.path().join("orders").join(SNAPSHOT_FILE).with_extension("tmp"), b"partial").unwrap();
+        fs::create_dir(temp_dir.path().join("empty")).unwrap();
+        fs::create_dir(temp_dir.path().join("broken")).unwrap();
+        fs::write(temp_dir.path().join("broken").join(SNAPSHOT_FILE), b"HIVE\0\x01garbage").unwrap();
+        fs::write(temp_dir.path().join(daemon::STOP_FILE), b"").unwrap();
+        
+        let report = diagnose(temp_dir.path(), &BTreeMap::new());
+        let found = |check: &str, severity: Severity| report.findings.iter()
+            .filter(|finding| finding.check == check && finding.severity == severity)
+            .count();
+        
+        assert_eq!(found("directory", Severity::Ok), 1);
+        assert_eq!(found("locks", Severity::Warning), 1);
+        assert_eq!(found("hives", Severity::Error), 1);
+        assert_eq!(found("hives", Severity::Warning), 2);
+        assert_eq!(found("hives", Severity::Ok), 1);
+        assert!(report.findings.iter().any(|finding| finding.fix.as_deref() == Some("Run `hivedb verify orders --repair`")));
+        assert_eq!(report.severity(), Severity::Error);
+    }
+}
@@ -0,0 +1,308 @@
+// HiveDB Format Module
+//
+// This module renders query results for people and scripts: as JSON, as
+// JSON lines, as an aligned text table, or as CSV. Columns are the
+// top-level fields of the result documents, in order of first appearance.
+// Results too large to hold can be streamed to a writer with
+// `DocumentWriter`, one document at a time.
+
+use std::io::Write;
+use serde_json::Value;
+use crate::core::error::HiveError;
+
+/// Ways to render query results
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// A JSON array of documents
+    Json,
+    
+    /// One JSON document per line
+    JsonLines,
+    
+    /// An aligned text table
+    Table,
+    
+    /// Comma-separated values with a header row
+    Csv,
+}
+
+impl OutputFormat {
+    /// Parse a format name
+    pub fn parse(name: &str) -> Result<Self, HiveError> {
+        match name.to_ascii_lowercase().as_str() {
+            "json" => Ok(OutputFormat::Json),
+            "jsonl" | "ndjson" => Ok(OutputFormat::JsonLines),
+            "table" => Ok(OutputFormat::Table),
+            "csv" => Ok(OutputFormat::Csv),
+            _ => Err(HiveError::DeserializationError(format!("Unknown output format '{}'", name))),
+        }
+    }
+}
+
+/// Render documents in the given format
+pub fn format_documents(documents: &[Value], format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Json => serde_json::to_string_pretty(documents).unwrap_or_default(),
+        OutputFormat::JsonLines => documents.iter().map(|document| format!("{}\n", document)).collect(),
+        OutputFormat::Table => format_table(documents),
+        OutputFormat::Csv => format_csv(documents),
+    }
+}
+
+/// Writes documents to a sink as they come, without holding them
+///
+/// JSON is written as an array with one document per line. CSV columns
+/// are given up front or taken from the first document, and fields
+/// outside them are left out. Tables cannot be streamed, as aligning
+/// their columns needs every row.
+pub struct DocumentWriter<W: Write> {
+    /// Where the documents go
+    sink: W,
+    
+    /// Format of the output
+    format: OutputFormat,
+    
+    /// CSV columns, once known
+    columns: Option<Vec<String>>,
+    
+    /// Number of documents written
+    count: usize,
+}
+
+impl<W: Write> DocumentWriter<W> {
+    /// Create a writer of documents in a format
+    pub fn new(sink: W, format: OutputFormat) -> Result<Self, HiveError> {
+        if format == OutputFormat::Table {
+            return Err(HiveError::QueryError("Tables cannot be streamed; use json, jsonl or csv".to_string()));
+        }
+        
+        Ok(Self {
+            sink,
+            format,
+            columns: None,
+            count: 0,
+        })
+    }
+    
+    /// Set the CSV columns instead of taking them from the first document
+    pub fn with_columns(mut self, columns: Vec<String>) -> Self {
+        self.columns = Some(columns);
+        self
+    }
+    
+    /// Write one document
+    pub fn write(&mut self, document: &Value) -> Result<(), HiveError> {
+        let mut out = String::new();
+        match self.format {
+            OutputFormat::Json => {
+                out.push_str(if self.count == 0 { "[\n" } else { ",\n" });
+                out.push_str(&document.to_string());
+            }
+            OutputFormat::JsonLines => {
+                out.push_str(&document.to_string());
+                out.push('\n');
+            }
+            OutputFormat::Csv => {
+                if self.count == 0 {
+                    let columns = self.columns.get_or_insert_with(|| columns(std::slice::from_ref(document)));
+                    out.push_str(&csv_line(columns.iter().map(|column| csv_field(column))));
+                }
+                let columns = self.columns.as_deref().unwrap_or_default();
+                out.push_str(&csv_line(columns.iter().map(|column| csv_field(&cell_text(document, column)))));
+            }
+            OutputFormat::Table => unreachable!("tables are rejected when the writer is created"),
+        }
+        
+        self.sink.write_all(out.as_bytes()).map_err(|e| HiveError::IoError(e.to_string()))?;
+        self.count += 1;
+        Ok(())
+    }
+    
+    /// Get the number of documents written
+    pub fn count(&self) -> usize {
+        self.count
+    }
+    
+    /// Finish the output and flush it, returning the sink
+    pub fn finish(mut self) -> Result<W, HiveError> {
+        let end = match (self.format, self.count) {
+            (OutputFormat::Json, 0) => "[]\n",
+            (OutputFormat::Json, _) => "\n]\n",
+            _ => "",
+        };
+        self.sink.write_all(end.as_bytes())
+            .and_then(|_| self.sink.flush())
+            .map_err(|e| HiveError::IoError(e.to_string()))?;
+        Ok(self.sink)
+    }
+}
+
+/// Get the column names of a set of documents
+pub fn columns(documents: &[Value]) -> Vec<String> {
+    let mut columns: Vec<String> = Vec::new();
+    
+    for document in documents {
+        match document {
+            Value::Object(object) => {
+                for key in object.keys() {
+                    if !columns.contains(key) {
+                        columns.push(key.clone());
+                    }
+                }
+            }
+            _ => {
+                if !columns.iter().any(|c| c == "value") {
+                    columns.push("value".to_string());
+                }
+            }
+        }
+    }
+    
+    columns
+}
+
+/// Get the text of one cell: strings as-is, null as empty, anything else as JSON
+fn cell_text(document: &Value, column: &str) -> String {
+    let value = match document {
+        Value::Object(object) => object.get(column),
+        other if column == "value" => Some(other),
+        _ => None,
+    };
+    
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(text)) => text.clone(),
+        Some(other) => other.to_string(),
+    }
+}
+
+fn format_table(documents: &[Value]) -> String {
+    let columns = columns(documents);
+    let rows: Vec<Vec<String>> = documents.iter()
+        .map(|document| columns.iter().map(|column| cell_text(document, column).replace('\n', " ")).collect())
+        .collect();
+    
+    let widths: Vec<usize> = columns.iter()
+        .enumerate()
+        .map(|(i, column)| {
+            rows.iter()
+                .map(|row| row[i].chars().count())
+                .chain(std::iter::once(column.chars().count()))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    
+    let line = |cells: &[String]| {
+        cells.iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = *width))
+            .collect::<Vec<_>>()
+            .join(" | ")
+            .trim_end()
+            .to_string()
+    };
+    
+    let mut out = String::new();
+    out.push_str(&line(&columns));
+    out.push('\n');
+    out.push_str(&widths.iter().map(|width| "-".repeat(*width)).collect::<Vec<_>>().join("-+-"));
+    out.push('\n');
+    for row in &rows {
+        out.push_str(&line(row));
+        out.push('\n');
+    }
+    out.push_str(&format!("({} row{})\n", rows.len(), if rows.len() == 1 { "" } else { "s" }));
+    
+    out
+}
+
+fn format_csv(documents: &[Value]) -> String {
+    let columns = columns(documents);
+    
+    let mut out = String::new();
+    out.push_str(&csv_line(columns.iter().map(|column| csv_field(column))));
+    for document in documents {
+        out.push_str(&csv_line(columns.iter().map(|column| csv_field(&cell_text(document, column)))));
+    }
+    
+    out
+}
+
+/// Join CSV fields into a line
+fn csv_line(fields: impl Iterator<Item = String>) -> String {
+    let mut line = fields.collect::<Vec<_>>().join(",");
+    line.push_str("\r\n");
+    line
+}
+
+/// Quote a CSV field if needed (RFC 4180)
+fn csv_field(text: &str) -> String {
+    if text.contains(|c| matches!(c, ',' | '"' | '\r' | '\n')) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    
+    fn documents() -> Vec<Value> {
+        vec![
+            json!({ "_id": "a", "name": "Honey, raw", "qty": 3 }),
+            json!({ "_id": "b", "name": "Wax", "tags": ["x"] }),
+        ]
+    }
+    
+    #[test]
+    fn test_columns_in_order_of_appearance() {
+        assert_eq!(columns(&documents()), vec!["_id", "name", "qty", "tags"]);
+    }
+    
+    #[test]
+    fn test_csv_quoting() {
+        let csv = format_documents(&documents(), OutputFormat::Csv);
+        let lines: Vec<&str> = csv.split("\r\n").collect();
+        
+        assert_eq!(lines[0], "_id,name,qty,tags");
+        assert_eq!(lines[1], "a,\"Honey, raw\",3,");
+        assert_eq!(lines[2], "b,Wax,,\"[\"\"x\"\"]\"");
+    }
+    
+    #[test]
+    fn test_table_alignment() {
+        let table = format_documents(&documents(), OutputFormat::Table);
+        let lines: Vec<&str> = table.lines().collect();
+        
+        assert_eq!(lines[0], "_id | name       | qty | tags");
+        assert_eq!(lines[2], "a   | Honey, raw | 3   |");
+        assert_eq!(lines[4], "(2 rows)");
+        assert!(OutputFormat::parse("yaml").is_err());
+    }
+    
+    #[test]
+    fn test_document_writer() {
+        let mut writer = DocumentWriter::new(Vec::new(), OutputFormat::Csv).unwrap();
+        for document in documents() {
+            writer.write(&document).unwrap();
+        }
+        assert_eq!(writer.count(), 2);
+        let csv = String::from_utf8(writer.finish().unwrap()).unwrap();
+        assert_eq!(csv, "_id,name,qty\r\na,\"Honey, raw\",3\r\nb,Wax,\r\n");
+        
+        let mut writer = DocumentWriter::new(Vec::new(), OutputFormat::Json).unwrap();
+        for document in documents() {
+            writer.write(&document).unwrap();
+        }
+        let json = writer.finish().unwrap();
+        assert_eq!(serde_json::from_slice::<Value>(&json).unwrap(), Value::Array(documents()));
+        
+        let lines = String::from_utf8(DocumentWriter::new(Vec::new(), OutputFormat::JsonLines).unwrap().finish().unwrap()).unwrap();
+        assert!(lines.is_empty());
+        assert_eq!(format_documents(&documents(), OutputFormat::JsonLines).lines().count(), 2);
+        assert!(DocumentWriter::new(Vec::new(), OutputFormat::Table).is_err());
+    }
+}
@@ -0,0 +1,13 @@
+// HiveDB Utilities Module
+//
+// This module contains helpers shared by the HiveDB library and its
+// command-line tools.
+
+pub mod backup;
+pub mod bench;
+pub mod daemon;
+pub mod determinism;
+pub mod doctor;
+pub mod format;
+pub mod seed;
+pub mod telemetry;
@@ -0,0 +1,433 @@
+// HiveDB Seed Module
+//
+// This module generates realistic fake documents that conform to a hive
+// schema, for load testing and demos. Values are chosen from the field
+// type, the validation rules and, for common field names such as
+// `email` or `city`, from small word lists so the data reads naturally.
+
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use serde_json::{json, Map, Value};
+use crate::core::schema::{FieldType, IndexType, Schema, SchemaField, SchemaIndex, ValidationRule};
+use crate::utils::determinism;
+
+/// Names of the built-in schema templates
+pub const TEMPLATES: [&str; 3] = ["user", "order", "product"];
+
+/// Chance that an optional field is present in a generated document
+const OPTIONAL_FIELD_PROBABILITY: f64 = 0.8;
+
+/// Timestamps are spread over this many seconds before now
+const DATETIME_SPAN: u64 = 3 * 365 * 24 * 3600;
+
+const FIRST_NAMES: [&str; 16] = [
+    "Amira", "Ben", "Chloe", "Diego", "Elif", "Farid", "Grace", "Hiro",
+    "Ines", "Jonas", "Karim", "Lena", "Maya", "Noah", "Omar", "Priya",
+];
+
+const LAST_NAMES: [&str; 16] = [
+    "Haddad", "Smith", "Tanaka", "Garcia", "Yilmaz", "Nasser", "Muller", "Rossi",
+    "Kowalski", "Dubois", "Silva", "Khan", "Novak", "Larsen", "Okafor", "Chen",
+];
+
+const CITIES: [(&str, &str); 12] = [
+    ("Cairo", "Egypt"), ("Berlin", "Germany"), ("Tokyo", "Japan"), ("Madrid", "Spain"),
+    ("Istanbul", "Turkey"), ("Amman", "Jordan"), ("Lyon", "France"), ("Toronto", "Canada"),
+    ("Lagos", "Nigeria"), ("Austin", "United States"), ("Oslo", "Norway"), ("Pune", "India"),
+];
+
+const STREETS: [&str; 8] = [
+    "Main Street", "Oak Avenue", "Station Road", "Harbor Lane",
+    "Market Square", "Hill Road", "Garden Way", "River Street",
+];
+
+const DOMAINS: [&str; 4] = ["example.com", "example.org", "mail.example", "hive.example"];
+
+const COMPANIES: [&str; 8] = [
+    "Acme", "Globex", "Initech", "Umbrella", "Hooli", "Stark Industries", "Wonka", "Soylent",
+];
+
+const ADJECTIVES: [&str; 10] = [
+    "Classic", "Compact", "Deluxe", "Eco", "Ultra", "Smart", "Rustic", "Modern", "Portable", "Premium",
+];
+
+const NOUNS: [&str; 10] = [
+    "Honey Jar", "Lamp", "Backpack", "Kettle", "Notebook", "Chair", "Headphones", "Blanket", "Mug", "Speaker",
+];
+
+const WORDS: [&str; 16] = [
+    "hive", "comb", "nectar", "pollen", "queen", "worker", "drone", "swarm",
+    "flower", "meadow", "amber", "wax", "garden", "summer", "bloom", "field",
+];
+
+/// Generates fake documents from a schema
+pub struct SeedGenerator {
+    /// Source of randomness (seeded, so runs can be reproduced)
+    rng: StdRng,
+    
+    /// Number of documents generated so far, used to keep unique fields unique
+    sequence: u64,
+    
+    /// Reference time for generated timestamps (seconds since the UNIX epoch)
+    now: u64,
+}
+
+impl SeedGenerator {
+    /// Create a generator from a seed
+    pub fn new(seed: u64) -> Self {
+        let now = determinism::unix_time();
+        
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+            sequence: 0,
+            now,
+        }
+    }
+    
+    /// Generate one document conforming to a schema
+    pub fn document(&mut self, schema: &Schema) -> Value {
+        self.sequence += 1;
+        
+        let unique: Vec<&str> = schema.indexes.iter()
+            .filter(|index| index.unique && index.fields.len() == 1)
+            .map(|index| index.fields[0].as_str())
+            .collect();
+        
+        let mut document = Map::new();
+        for field in &schema.fields {
+            if !field.required && !self.rng.gen_bool(OPTIONAL_FIELD_PROBABILITY) {
+                continue;
+            }
+            
+            let value = self.field_value(field);
+            let value = if unique.contains(&field.name.as_str()) { self.make_unique(value) } else { value };
+            document.insert(field.name.clone(), value);
+        }
+        
+        Value::Object(document)
+    }
+    
+    /// Generate a value for a field, honouring its validation rules
+    fn field_value(&mut self, field: &SchemaField) -> Value {
+        for rule in &field.validation {
+            if let ValidationRule::Enum(values) = rule {
+                if let Some(value) = values.choose(&mut self.rng) {
+                    return Value::String(value.clone());
+                }
+            }
+        }
+        
+        let (min, max) = field.validation.iter().fold((None, None), |(min, max), rule| match rule {
+            ValidationRule::MinValue(value) => (Some(*value), max),
+            ValidationRule::MaxValue(value) => (min, Some(*value)),
+            _ => (min, max),
+        });
+        
+        let value = self.typed_value(&field.name.to_lowercase(), &field.field_type, min, max);
+        fit_length(value, &field.validation)
+    }
+    
+    /// Generate a value of a type, picking realistic data from the field name
+    fn typed_value(&mut self, name: &str, field_type: &FieldType, min: Option<f64>, max: Option<f64>) -> Value {
+        match field_type {
+            FieldType::String | FieldType::Custom(_) => Value::String(self.text(name)),
+            FieldType::Integer => {
+                let (low, high) = match name {
+                    _ if name.contains("age") => (18.0, 90.0),
+                    _ if name.contains("quantity") || name.contains("count") => (1.0, 20.0),
+                    _ if name.contains("year") => (1990.0, 2025.0),
+                    _ => (0.0, 1000.0),
+                };
+                let low = min.unwrap_or(low).ceil() as i64;
+                let high = max.unwrap_or(high).floor() as i64;
+                json!(if high > low { self.rng.gen_range(low..=high) } else { low })
+            }
+            FieldType::Float => {
+                let (low, high) = match name {
+                    _ if name.contains("rating") => (1.0, 5.0),
+                    _ if name.contains("price") || name.contains("amount") || name.contains("total") => (1.0, 500.0),
+                    _ => (0.0, 1000.0),
+                };
+                let low = min.unwrap_or(low);
+                let high = max.unwrap_or(high);
+                let value = if high > low { self.rng.gen_range(low..high) } else { low };
+                json!((value * 100.0).round() / 100.0)
+            }
+            FieldType::Boolean => json!(self.rng.gen_bool(0.5)),
+            FieldType::DateTime => {
+                let secs = self.now.saturating_sub(self.rng.gen_range(0..DATETIME_SPAN));
+                Value::String(format_datetime(secs))
+            }
+            FieldType::Binary => {
+                let bytes: [u8; 16] = self.rng.gen();
+                Value::String(hex::encode(bytes))
+            }
+            FieldType::Array(item_type) => {
+                let length = self.rng.gen_range(1..=4);
+                // Array items are named after the array, so `tags` yields words
+                let singular = name.strip_suffix('s').unwrap_or(name);
+                Value::Array((0..length).map(|_| self.typed_value(singular, item_type, min, max)).collect())
+            }
+            FieldType::Object(fields) => {
+                let mut object = Map::new();
+                for field in fields {
+                    if field.required || self.rng.gen_bool(OPTIONAL_FIELD_PROBABILITY) {
+                        let value = self.field_value(field);
+                        object.insert(field.name.clone(), value);
+                    }
+                }
+                Value::Object(object)
+            }
+            FieldType::Reference => {
+                let bytes: [u8; 8] = self.rng.gen();
+                Value::String(hex::encode(bytes))
+            }
+            FieldType::GeoPoint => {
+                let lat: f64 = self.rng.gen_range(-90.0..90.0);
+                let lon: f64 = self.rng.gen_range(-180.0..180.0);
+                json!({ "lat": (lat * 1e5).round() / 1e5, "lon": (lon * 1e5).round() / 1e5 })
+            }
+        }
+    }
+    
+    /// Generate a string that suits the field name
+    fn text(&mut self, name: &str) -> String {
+        let first = *FIRST_NAMES.choose(&mut self.rng).unwrap_or(&"Alex");
+        let last = *LAST_NAMES.choose(&mut self.rng).unwrap_or(&"Doe");
+        let (city, country) = *CITIES.choose(&mut self.rng).unwrap_or(&("Cairo", "Egypt"));
+        
+        match name {
+            _ if name.contains("email") => format!(
+                "{}.{}@{}",
+                first.to_lowercase(),
+                last.to_lowercase(),
+                DOMAINS.choose(&mut self.rng).unwrap_or(&"example.com")
+            ),
+            _ if name.contains("username") || name.contains("login") => {
+                format!("{}{}", first.to_lowercase(), &last[..1].to_lowercase())
+            }
+            _ if name.contains("first") => first.to_string(),
+            _ if name.contains("last") || name.contains("surname") => last.to_string(),
+            _ if name.contains("phone") => format!(
+                "+1-555-{:03}-{:04}",
+                self.rng.gen_range(100..1000),
+                self.rng.gen_range(0..10000)
+            ),
+            _ if name.contains("street") || name.contains("address") => {
+                format!("{} {}", self.rng.gen_range(1..300), STREETS.choose(&mut self.rng).unwrap_or(&"Main Street"))
+            }
+            _ if name.contains("city") => city.to_string(),
+            _ if name.contains("country") => country.to_string(),
+            _ if name.contains("zip") || name.contains("postal") => format!("{:05}", self.rng.gen_range(1000..100000)),
+            _ if name.contains("company") => COMPANIES.choose(&mut self.rng).unwrap_or(&"Acme").to_string(),
+            _ if name.contains("url") || name.contains("website") => {
+                format!("https://{}/{}", DOMAINS.choose(&mut self.rng).unwrap_or(&"example.com"), self.word())
+            }
+            _ if name.contains("sku") || name.contains("code") || name.contains("number") => {
+                let prefix: String = name.chars().take(3).collect();
+                format!("{}-{:06}", prefix.to_uppercase(), self.rng.gen_range(0..1_000_000))
+            }
+            _ if name.contains("title") || name.contains("product") => format!(
+                "{} {}",
+                ADJECTIVES.choose(&mut self.rng).unwrap_or(&"Classic"),
+                NOUNS.choose(&mut self.rng).unwrap_or(&"Lamp")
+            ),
+            _ if name.contains("name") => format!("{} {}", first, last),
+            _ if name.contains("description") || name.contains("bio") || name.contains("comment") => {
+                let length = self.rng.gen_range(6..14);
+                let mut sentence: Vec<&str> = (0..length).map(|_| self.word()).collect();
+                let capitalized = capitalize(sentence[0]);
+                sentence[0] = &capitalized;
+                format!("{}.", sentence.join(" "))
+            }
+            _ => self.word().to_string(),
+        }
+    }
+    
+    /// Pick a random word
+    fn word(&mut self) -> &'static str {
+        WORDS.choose(&mut self.rng).unwrap_or(&"hive")
+    }
+    
+    /// Make a value unique by folding in the document sequence number
+    fn make_unique(&self, value: Value) -> Value {
+        match value {
+            Value::String(text) => match text.split_once('@') {
+                Some((local, domain)) => Value::String(format!("{}{}@{}", local, self.sequence, domain)),
+                None => Value::String(format!("{}{}", text, self.sequence)),
+            },
+            Value::Number(_) => json!(self.sequence),
+            other => other,
+        }
+    }
+}
+
+/// Get a built-in schema template by name
+pub fn template(name: &str) -> Option<Schema> {
+    let field = |name: &str, field_type: FieldType, required: bool| {
+        SchemaField::new(name.to_string(), String::new(), field_type, required)
+    };
+    let choice = |values: &[&str]| ValidationRule::Enum(values.iter().map(|value| value.to_string()).collect());
+    let unique = |name: &str, field: &str| {
+        SchemaIndex::new(name.to_string(), vec![field.to_string()], IndexType::Hash, true)
+    };
+    
+    let mut schema = Schema::new(name.to_string(), format!("Generated {} documents", name), "1".to_string());
+    match name {
+        "user" => {
+            schema.add_field(field("username", FieldType::String, true).with_validation(ValidationRule::MaxLength(32)));
+            schema.add_field(field("email", FieldType::String, true));
+            schema.add_field(field("first_name", FieldType::String, true));
+            schema.add_field(field("last_name", FieldType::String, true));
+            schema.add_field(field("age", FieldType::Integer, false)
+                .with_validation(ValidationRule::MinValue(18.0))
+                .with_validation(ValidationRule::MaxValue(90.0)));
+            schema.add_field(field("phone", FieldType::String, false));
+            schema.add_field(field("city", FieldType::String, true));
+            schema.add_field(field("country", FieldType::String, true));
+            schema.add_field(field("status", FieldType::String, true).with_validation(choice(&["active", "inactive", "suspended"])));
+            schema.add_field(field("created_at", FieldType::DateTime, true));
+            schema.add_field(field("tags", FieldType::Array(Box::new(FieldType::String)), false));
+            schema.add_index(unique("by_username", "username"));
+            schema.add_index(unique("by_email", "email"));
+        }
+        "order" => {
+            schema.add_field(field("order_number", FieldType::String, true));
+            schema.add_field(field("customer", FieldType::Reference, true));
+            schema.add_field(field("status", FieldType::String, true)
+                .with_validation(choice(&["pending", "paid", "shipped", "delivered", "cancelled"])));
+            schema.add_field(field("items", FieldType::Array(Box::new(FieldType::Object(vec![
+                field("product", FieldType::String, true),
+                field("quantity", FieldType::Integer, true),
+                field("price", FieldType::Float, true),
+            ]))), true));
+            schema.add_field(field("total", FieldType::Float, true));
+            schema.add_field(field("currency", FieldType::String, true).with_validation(choice(&["USD", "EUR", "GBP", "JPY"])));
+            schema.add_field(field("shipping_address", FieldType::Object(vec![
+                field("street", FieldType::String, true),
+                field("city", FieldType::String, true),
+                field("zip", FieldType::String, true),
+                field("country", FieldType::String, true),
+            ]), true));
+            schema.add_field(field("created_at", FieldType::DateTime, true));
+            schema.add_index(unique("by_order_number", "order_number"));
+        }
+        "product" => {
+            schema.add_field(field("sku", FieldType::String, true));
+            schema.add_field(field("title", FieldType::String, true));
+            schema.add_field(field("description", FieldType::String, false).with_validation(ValidationRule::MaxLength(200)));
+            schema.add_field(field("category", FieldType::String, true)
+                .with_validation(choice(&["home", "garden", "electronics", "kitchen", "outdoors"])));
+            schema.add_field(field("price", FieldType::Float, true).with_validation(ValidationRule::MinValue(0.5)));
+            schema.add_field(field("in_stock", FieldType::Boolean, true));
+            schema.add_field(field("rating", FieldType::Float, false));
+            schema.add_field(field("tags", FieldType::Array(Box::new(FieldType::String)), false));
+            schema.add_index(unique("by_sku", "sku"));
+        }
+        _ => return None,
+    }
+    
+    Some(schema)
+}
+
+/// Pad or truncate strings and arrays to the lengths allowed by the rules
+fn fit_length(value: Value, rules: &[ValidationRule]) -> Value {
+    let (min, max) = rules.iter().fold((0, usize::MAX), |(min, max), rule| match rule {
+        ValidationRule::MinLength(length) => (*length, max),
+        ValidationRule::MaxLength(length) => (min, *length),
+        _ => (min, max),
+    });
+    
+    match value {
+        Value::String(mut text) => {
+            if text.chars().count() > max {
+                text = text.chars().take(max).collect();
+            }
+            while text.chars().count() < min {
+                text.push('x');
+            }
+            Value::String(text)
+        }
+        Value::Array(mut items) => {
+            items.truncate(max);
+            while items.len() < min {
+                let item = items.last().cloned().unwrap_or(Value::Null);
+                items.push(item);
+            }
+            Value::Array(items)
+        }
+        other => other,
+    }
+}
+
+/// Capitalize the first letter of a word
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Format a UNIX timestamp as an ISO 8601 UTC date-time
+fn format_datetime(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let time = secs % 86_400;
+    
+    // Civil date from days since 1970-01-01 (proleptic Gregorian calendar)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, time / 3600, time % 3600 / 60, time % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_documents_follow_template() {
+        let schema = template("user").unwrap();
+        let mut generator = SeedGenerator::new(42);
+        
+        let documents: Vec<Value> = (0..50).map(|_| generator.document(&schema)).collect();
+        for document in &documents {
+            for field in schema.fields.iter().filter(|field| field.required) {
+                assert!(document.get(&field.name).is_some(), "missing {} in {}", field.name, document);
+            }
+            
+            let status = document["status"].as_str().unwrap();
+            assert!(["active", "inactive", "suspended"].contains(&status));
+            if let Some(age) = document.get("age") {
+                assert!((18..=90).contains(&age.as_i64().unwrap()));
+            }
+        }
+        
+        let mut emails: Vec<&str> = documents.iter().map(|document| document["email"].as_str().unwrap()).collect();
+        emails.sort();
+        emails.dedup();
+        assert_eq!(emails.len(), documents.len());
+        
+        assert!(template("invoice").is_none());
+    }
+    
+    #[test]
+    fn test_length_rules_and_dates() {
+        let rules = [ValidationRule::MinLength(4), ValidationRule::MaxLength(6)];
+        assert_eq!(fit_length(json!("ab"), &rules), json!("abxx"));
+        assert_eq!(fit_length(json!("abcdefgh"), &rules), json!("abcdef"));
+        
+        assert_eq!(format_datetime(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_datetime(951_782_400 + 3661), "2000-02-29T01:01:01Z");
+    }
+}
@@ -0,0 +1,407 @@
+// HiveDB Telemetry Module
+//
+// This module records trace spans around query parsing and execution,
+// storage I/O and network request handling, and exports them to an
+// OpenTelemetry collector with OTLP over HTTP (JSON encoding). Tracing is
+// off until `init` is called; spans are then batched on a background
+// thread and dropped rather than blocking when the exporter falls behind.
+// Incoming W3C `traceparent` headers make HiveDB spans children of the
+// caller's trace.
+
+use std::cell::RefCell;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use rand::Rng;
+use serde_json::{json, Value};
+use crate::core::error::HiveError;
+use crate::network::http::{self, HttpRequest};
+use log::{debug, info, warn};
+
+/// Spans waiting to be exported before new ones are dropped
+const QUEUE_CAPACITY: usize = 4096;
+
+/// Path of the OTLP/HTTP trace endpoint
+const TRACES_PATH: &str = "/v1/traces";
+
+/// Sender feeding the exporter thread, set once by `init`
+static EXPORTER: OnceLock<SyncSender<SpanData>> = OnceLock::new();
+
+thread_local! {
+    /// Spans open on this thread, innermost last
+    static ACTIVE: RefCell<Vec<TraceContext>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Exporter configuration
+#[derive(Debug, Clone)]
+pub struct OtlpConfig {
+    /// Collector base URL, e.g. `http://localhost:4318`
+    pub endpoint: String,
+    
+    /// Value of the `service.name` resource attribute
+    pub service_name: String,
+    
+    /// Most spans sent in one export request
+    pub batch_size: usize,
+    
+    /// Longest time a span waits before it is exported
+    pub flush_interval: Duration,
+}
+
+impl OtlpConfig {
+    /// Create a configuration for a collector
+    pub fn new(endpoint: &str) -> Self {
+        Self {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            service_name: "hivedb".to_string(),
+            batch_size: 512,
+            flush_interval: Duration::from_secs(5),
+        }
+    }
+    
+    /// Build a configuration from the standard OTEL_* environment variables
+    ///
+    /// Returns None when OTEL_EXPORTER_OTLP_ENDPOINT is not set.
+    pub fn from_env() -> Option<Self> {
+        let endpoint = std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT").ok()?;
+        let mut config = Self::new(&endpoint);
+        if let Ok(service_name) = std::env::var("OTEL_SERVICE_NAME") {
+            config.service_name = service_name;
+        }
+        
+        Some(config)
+    }
+}
+
+/// Identifies a span within a trace
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceContext {
+    /// ID of the trace
+    pub trace_id: [u8; 16],
+    
+    /// ID of the span
+    pub span_id: [u8; 8],
+}
+
+impl TraceContext {
+    /// Parse a W3C `traceparent` header value
+    pub fn from_traceparent(value: &str) -> Option<Self> {
+        let parts: Vec<&str> = value.trim().split('-').collect();
+        if parts.len() < 4 || parts[0].len() != 2 || parts[0] == "ff" {
+            return None;
+        }
+        
+        let trace_id: [u8; 16] = hex::decode(parts[1]).ok()?.try_into().ok()?;
+        let span_id: [u8; 8] = hex::decode(parts[2]).ok()?.try_into().ok()?;
+        if trace_id == [0; 16] || span_id == [0; 8] {
+            return None;
+        }
+        
+        Some(Self { trace_id, span_id })
+    }
+    
+    /// Format this context as a W3C `traceparent` header value
+    pub fn to_traceparent(&self) -> String {
+        format!("00-{}-{}-01", hex::encode(self.trace_id), hex::encode(self.span_id))
+    }
+}
+
+/// Role of a span in a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpanKind {
+    /// Work inside HiveDB
+    Internal,
+    
+    /// Handling of a request from a client
+    Server,
+}
+
+/// A finished span, as handed to the exporter
+#[derive(Debug, Clone)]
+pub struct SpanData {
+    /// Identity of the span
+    pub context: TraceContext,
+    
+    /// Span that caused this one, if any
+    pub parent_span_id: Option<[u8; 8]>,
+    
+    /// Operation name
+    pub name: &'static str,
+    
+    /// Role of the span
+    pub kind: SpanKind,
+    
+    /// Start time (nanoseconds since the UNIX epoch)
+    pub start_nanos: u128,
+    
+    /// End time (nanoseconds since the UNIX epoch)
+    pub end_nanos: u128,
+    
+    /// Attributes describing the operation
+    pub attributes: Vec<(&'static str, String)>,
+    
+    /// Error message, if the operation failed
+    pub error: Option<String>,
+}
+
+/// An open span, recorded when dropped
+///
+/// Spans opened while another is open on the same thread become its
+/// children. When tracing is off the guard is inert and costs nothing.
+#[must_use]
+pub struct SpanGuard {
+    /// The span being recorded (None when tracing is off)
+    span: Option<SpanData>,
+}
+
+impl SpanGuard {
+    /// Add an attribute to the span
+    pub fn set_attribute(&mut self, key: &'static str, value: impl ToString) {
+        if let Some(span) = &mut self.span {
+            span.attributes.push((key, value.to_string()));
+        }
+    }
+    
+    /// Mark the span as failed
+    pub fn set_error(&mut self, error: &HiveError) {
+        if let Some(span) = &mut self.span {
+            span.error = Some(error.to_string());
+        }
+    }
+    
+    /// Record the outcome of the operation the span covers and pass it on
+    pub fn record<T>(&mut self, result: Result<T, HiveError>) -> Result<T, HiveError> {
+        if let Err(e) = &result {
+            self.set_error(e);
+        }
+        result
+    }
+    
+    /// Get the identity of the span (None when tracing is off)
+    pub fn context(&self) -> Option<TraceContext> {
+        self.span.as_ref().map(|span| span.context)
+    }
+}
+
+impl Drop for SpanGuard {
+    fn drop(&mut self) {
+        if let Some(mut span) = self.span.take() {
+            ACTIVE.with(|active| {
+                let mut active = active.borrow_mut();
+                if let Some(position) = active.iter().rposition(|context| *context == span.context) {
+                    active.remove(position);
+                }
+            });
+            
+            span.end_nanos = now_nanos();
+            if let Some(exporter) = EXPORTER.get() {
+                // Drop the span rather than slow down the operation it describes
+                let _ = exporter.try_send(span);
+            }
+        }
+    }
+}
+
+/// Open an internal span, a child of the span open on this thread
+pub fn span(name: &'static str) -> SpanGuard {
+    let parent = ACTIVE.with(|active| active.borrow().last().copied());
+    start(name, SpanKind::Internal, parent)
+}
+
+/// Open a span for a client request, continuing the caller's trace if known
+pub fn server_span(name: &'static str, parent: Option<TraceContext>) -> SpanGuard {
+    start(name, SpanKind::Server, parent)
+}
+
+/// Check whether spans are being exported
+pub fn enabled() -> bool {
+    EXPORTER.get().is_some()
+}
+
+/// Start exporting spans to an OTLP collector
+///
+/// Can only be called once per process.
+pub fn init(config: OtlpConfig) -> Result<(), HiveError> {
+    let url = format!("{}{}", config.endpoint, TRACES_PATH);
+    let (host, port, path) = http::parse_http_url(&url)?;
+    
+    let (sender, receiver) = mpsc::sync_channel(QUEUE_CAPACITY);
+    EXPORTER.set(sender)
+        .map_err(|_| HiveError::GenericError("Trace export is already initialized".to_string()))?;
+    
+    info!("Exporting traces to {}", url);
+    std::thread::spawn(move || export_loop(receiver, &format!("{}:{}", host, port), &path, &config));
+    
+    Ok(())
+}
+
+/// Open a span with the given parent
+fn start(name: &'static str, kind: SpanKind, parent: Option<TraceContext>) -> SpanGuard {
+    if !enabled() {
+        return SpanGuard { span: None };
+    }
+    
+    let mut rng = rand::thread_rng();
+    let context = TraceContext {
+        trace_id: parent.map_or_else(|| rng.gen(), |parent| parent.trace_id),
+        span_id: rng.gen(),
+    };
+    ACTIVE.with(|active| active.borrow_mut().push(context));
+    
+    SpanGuard {
+        span: Some(SpanData {
+            context,
+            parent_span_id: parent.map(|parent| parent.span_id),
+            name,
+            kind,
+            start_nanos: now_nanos(),
+            end_nanos: 0,
+            attributes: Vec::new(),
+            error: None,
+        }),
+    }
+}
+
+/// Collect spans into batches and send them until the process exits
+fn export_loop(receiver: Receiver<SpanData>, address: &str, path: &str, config: &OtlpConfig) {
+    let mut batch = Vec::with_capacity(config.batch_size);
+    
+    loop {
+        let disconnected = match receiver.recv_timeout(config.flush_interval) {
+            Ok(span) => {
+                batch.push(span);
+                if batch.len() < config.batch_size {
+                    continue;
+                }
+                false
+            }
+            Err(RecvTimeoutError::Timeout) => false,
+            Err(RecvTimeoutError::Disconnected) => true,
+        };
+        
+        if !batch.is_empty() {
+            let body = encode(&batch, &config.service_name).to_string();
+            let request = HttpRequest::new("POST", path, body.as_bytes())
+                .with_header("Content-Type", "application/json");
+            match http::send_request(address, &request, Duration::from_secs(10)) {
+                Ok(response) if (200..300).contains(&response.status) => {
+                    debug!("Exported {} spans", batch.len());
+                }
+                Ok(response) => warn!("Trace collector rejected {} spans with status {}", batch.len(), response.status),
+                Err(e) => warn!("Failed to export {} spans: {}", batch.len(), e),
+            }
+            batch.clear();
+        }
+        
+        if disconnected {
+            return;
+        }
+    }
+}
+
+/// Encode spans as an OTLP/JSON `ExportTraceServiceRequest`
+pub fn encode(spans: &[SpanData], service_name: &str) -> Value {
+    let attribute = |key: &str, value: &str| json!({ "key": key, "value": { "stringValue": value } });
+    
+    let spans: Vec<Value> = spans.iter()
+        .map(|span| {
+            let mut value = json!({
+                "traceId": hex::encode(span.context.trace_id),
+                "spanId": hex::encode(span.context.span_id),
+                "name": span.name,
+                // SPAN_KIND_INTERNAL = 1, SPAN_KIND_SERVER = 2
+                "kind": match span.kind { SpanKind::Internal => 1, SpanKind::Server => 2 },
+                "startTimeUnixNano": span.start_nanos.to_string(),
+                "endTimeUnixNano": span.end_nanos.to_string(),
+                "attributes": span.attributes.iter().map(|(key, value)| attribute(key, value)).collect::<Vec<_>>(),
+                // STATUS_CODE_UNSET = 0, STATUS_CODE_ERROR = 2
+                "status": match &span.error {
+                    Some(message) => json!({ "code": 2, "message": message }),
+                    None => json!({ "code": 0 }),
+                },
+            });
+            if let Some(parent) = span.parent_span_id {
+                value["parentSpanId"] = json!(hex::encode(parent));
+            }
+            value
+        })
+        .collect();
+    
+    json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [
+                    attribute("service.name", service_name),
+                    attribute("service.version", crate::version()),
+                ],
+            },
+            "scopeSpans": [{
+                "scope": { "name": "hivedb", "version": crate::version() },
+                "spans": spans,
+            }],
+        }],
+    })
+}
+
+/// Get the current time in nanoseconds since the UNIX epoch
+fn now_nanos() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    
+    #[test]
+    fn test_traceparent() {
+        let header = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let context = TraceContext::from_traceparent(header).unwrap();
+        assert_eq!(hex::encode(context.trace_id), "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(context.to_traceparent(), header);
+        
+        assert!(TraceContext::from_traceparent("00-00000000000000000000000000000000-00f067aa0ba902b7-01").is_none());
+        assert!(TraceContext::from_traceparent("00-4bf92f35-00f067aa0ba902b7-01").is_none());
+        assert!(TraceContext::from_traceparent("garbage").is_none());
+    }
+    
+    #[test]
+    fn test_spans_are_exported() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut config = OtlpConfig::new(&format!("http://{}", listener.local_addr().unwrap()));
+        config.flush_interval = Duration::from_millis(50);
+        init(config).unwrap();
+        
+        let caller = TraceContext::from_traceparent("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01").unwrap();
+        {
+            let mut request = server_span("test.request", Some(caller));
+            request.set_attribute("test.attribute", 42);
+            let child = span("test.child");
+            assert_eq!(child.context().unwrap().trace_id, caller.trace_id);
+        }
+        
+        // Other tests may export spans too, so read requests until ours arrive
+        let mut names = Vec::new();
+        while !names.contains(&"test.request".to_string()) {
+            let (mut stream, _) = listener.accept().unwrap();
+            let request = http::read_request(&mut stream).unwrap();
+            assert_eq!(request.path, TRACES_PATH);
+            http::write_response(&mut stream, &http::HttpResponse::json(200, &json!({}))).unwrap();
+            
+            let body = request.json().unwrap();
+            for span in body["resourceSpans"][0]["scopeSpans"][0]["spans"].as_array().unwrap() {
+                if span["name"] == "test.request" {
+                    assert_eq!(span["traceId"], "4bf92f3577b34da6a3ce929d0e0e4736");
+                    assert_eq!(span["parentSpanId"], "00f067aa0ba902b7");
+                    assert_eq!(span["kind"], 2);
+                    assert_eq!(span["attributes"][0]["value"]["stringValue"], "42");
+                }
+                names.push(span["name"].as_str().unwrap().to_string());
+            }
+        }
+        assert!(names.contains(&"test.child".to_string()));
+    }
+}
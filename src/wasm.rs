@@ -0,0 +1,33 @@
+// HiveDB WASM Module
+//
+// This module exposes HiveDB to JavaScript when the crate is built with
+// the `wasm` feature, which also selects the pure-Rust checksum and LZ4
+// providers. Only the functions that need no storage engine or network
+// are bound so far.
+
+use wasm_bindgen::prelude::*;
+use crate::core::codec;
+
+/// Get the version of HiveDB
+#[wasm_bindgen(js_name = version)]
+pub fn wasm_version() -> String {
+    crate::version().to_string()
+}
+
+/// Get the SHA-256 digest of some bytes, as lower-case hex
+#[wasm_bindgen]
+pub fn sha256_hex(data: &[u8]) -> String {
+    codec::sha256(data).to_hex()
+}
+
+/// Compress bytes into an LZ4 frame, as compressed cells store them
+#[wasm_bindgen]
+pub fn lz4_compress(data: &[u8]) -> Result<Vec<u8>, JsValue> {
+    codec::lz4_compress(data).map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+/// Decompress an LZ4 frame
+#[wasm_bindgen]
+pub fn lz4_decompress(data: &[u8]) -> Result<Vec<u8>, JsValue> {
+    codec::lz4_decompress(data).map_err(|e| JsValue::from_str(&e.to_string()))
+}